@@ -0,0 +1,33 @@
+#!/usr/bin/env bash
+
+# Forward (and NAT) the traffic routed through this host by the clients.
+#
+# Run by the russula router worker before the netbench scenario and undone
+# afterwards. The instance's source/dest check is disabled by the orchestrator
+# so that it can forward traffic which isn't addressed to it.
+#
+# usage: router.sh <enable|disable>
+
+set -e
+
+iface=$(ip route show default | awk '{print $5; exit}')
+
+case "$1" in
+    enable)
+        sysctl -w net.ipv4.ip_forward=1
+        # otherwise the clients are redirected to the servers, which share the subnet
+        sysctl -w net.ipv4.conf.all.send_redirects=0
+        sysctl -w "net.ipv4.conf.$iface.send_redirects=0"
+        # the servers reply to the router rather than directly to the clients
+        iptables -t nat -C POSTROUTING -o "$iface" -j MASQUERADE 2>/dev/null \
+            || iptables -t nat -A POSTROUTING -o "$iface" -j MASQUERADE
+        ;;
+    disable)
+        iptables -t nat -D POSTROUTING -o "$iface" -j MASQUERADE 2>/dev/null || true
+        sysctl -w net.ipv4.ip_forward=0
+        ;;
+    *)
+        echo "Please specify 'enable' or 'disable'"
+        exit 1
+        ;;
+esac
@@ -0,0 +1,48 @@
+#!/usr/bin/env bash
+
+# Upload the bytes appended to the files since the last upload, as numbered
+# chunks, every interval until killed.
+#
+# Started by the russula worker alongside netbench so that the output of a long
+# run survives a failure of the host. Only the new bytes are read and they are
+# piped to s3 without a local copy. A chunk of each file is named after its
+# sequence number: <s3_prefix>/<file>.00000, <s3_prefix>/<file>.00001, ...
+#
+# usage: stream_results.sh <s3_prefix> <interval_secs> <file>...
+
+[[ -z "$1" ]] && { echo "Please specify an 's3_prefix'" ; exit 1; }
+[[ -z "$2" ]] && { echo "Please specify an 'interval_secs'" ; exit 1; }
+
+prefix=${1%/}
+interval=$2
+shift 2
+files=("$@")
+
+declare -A offsets
+declare -A seqs
+
+upload_chunks() {
+    for file in "${files[@]}"; do
+        [[ -f "$file" ]] || continue
+        size=$(stat -c %s "$file")
+        off=${offsets[$file]:-0}
+        (( size > off )) || continue
+        seq=${seqs[$file]:-0}
+        chunk=$(printf "%s/%s.%05d" "$prefix" "$(basename "$file")" "$seq")
+        if tail -c +$((off + 1)) "$file" | head -c $((size - off)) | aws s3 cp - "$chunk"; then
+            offsets[$file]=$size
+            seqs[$file]=$((seq + 1))
+        fi
+    done
+}
+
+# upload the remainder once the run is stopped
+trap 'upload_chunks; exit 0' TERM INT
+
+while true
+do
+    # wait in the background so that the trap runs as soon as it's received
+    sleep "$interval" &
+    wait $!
+    upload_chunks
+done
@@ -0,0 +1,28 @@
+#!/usr/bin/env bash
+
+# Sample system metrics (cpu, memory, tcp, network) until killed.
+#
+# Started by the russula worker alongside netbench so that the samples cover
+# the netbench run.
+#
+# usage: sys_metrics.sh <output_file> [interval_secs]
+
+[[ -z "$1" ]] && { echo "Please specify an 'output_file'" ; exit 1; }
+
+out=$1
+interval=${2:-1}
+
+echo "unix_millis,cpu_user,cpu_system,cpu_idle,cpu_iowait,mem_free_kb,tcp_estab,tcp_retrans_segs,rx_kbps,tx_kbps" > "$out"
+
+while true
+do
+    ts=$(date +%s%3N)
+    # the second vmstat sample covers the interval
+    vm=$(vmstat -n "$interval" 2 | tail -1 | awk '{print $13","$14","$15","$16","$4}')
+    estab=$(ss -tan state established | tail -n +2 | wc -l)
+    # cumulative count. RetransSegs is the 13th field of the Tcp values
+    retrans=$(awk '/^Tcp:/ { getline; print $13 }' /proc/net/snmp)
+    # sum of all interfaces other than loopback (sysstat)
+    net=$(sar -n DEV 1 1 2>/dev/null | awk '/^Average:/ && $2 != "IFACE" && $2 != "lo" { rx += $5; tx += $6 } END { printf "%.1f,%.1f", rx, tx }')
+    echo "$ts,$vm,$estab,$retrans,$net" >> "$out"
+done
@@ -0,0 +1,443 @@
+// Copyright Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+//! The `orchestrator` command line: runs scenarios on new or pooled hosts,
+//! and the subcommands which manage runs, reports and infra.
+
+use crate::*;
+use aws_types::region::Region;
+use clap::{Parser, Subcommand};
+use config::OrchestratorConfig;
+use error::{OrchError, OrchResult};
+use std::{path::PathBuf, process::Command};
+
+#[derive(Parser, Debug)]
+pub struct Args {
+    /// Path to the scenario file
+    ///
+    /// Can be specified multiple times or point to a directory of scenario files, in
+    /// which case the scenarios are run sequentially on the same hosts.
+    #[arg(long, default_value = "scripts/request_response.json")]
+    pub(crate) scenario_file: Vec<PathBuf>,
+
+    /// Path to a run spec (json) declaring a matrix of drivers, scenarios, instance
+    /// types and impairments to run. Overrides `--scenario-file`.
+    #[arg(long)]
+    pub(crate) run_spec: Option<PathBuf>,
+
+    /// Path to the orchestrator config (json)
+    #[arg(long)]
+    pub(crate) config: Option<PathBuf>,
+
+    /// How long the presigned urls for the report and results are valid. Max 7 days.
+    #[arg(long, value_parser = duration::parse_duration, default_value = "7days")]
+    pub(crate) presign_expiry: core::time::Duration,
+
+    /// The transport used by russula to coordinate the hosts: tcp or udp
+    #[arg(long, default_value_t = russula::Transport::Tcp)]
+    pub(crate) russula_transport: russula::Transport,
+
+    /// How often system metrics (cpu, memory, tcp, network) are sampled on the hosts
+    /// while netbench is running
+    #[arg(long, value_parser = duration::parse_duration, default_value = "1s")]
+    pub(crate) sys_metrics_interval: core::time::Duration,
+
+    /// Profile the netbench drivers while running and upload a flamegraph for each
+    /// host. ex: perf
+    #[arg(long)]
+    pub(crate) profile: Option<russula::netbench::Profiler>,
+
+    /// The format of the log written to `<workspace_dir>/<unique_id>/orchestrator.log`,
+    /// which is uploaded with the run's artifacts: text or json
+    #[arg(long, default_value_t = logging::LogFormat::Text)]
+    pub(crate) log_format: logging::LogFormat,
+
+    /// Capture the netbench traffic on the hosts with tcpdump: all, server or client.
+    ///
+    /// The captures are rotated and capped at 1GB per host, and uploaded compressed.
+    #[arg(long)]
+    pub(crate) capture_pcap: Option<ssm_utils::PcapHosts>,
+
+    /// The number of netbench drivers to run concurrently on each host, each on its
+    /// own netbench port. Client driver N connects to server driver N.
+    #[arg(
+        long,
+        default_value_t = 1,
+        value_parser = clap::value_parser!(u16).range(1..=russula::netbench::MAX_DRIVER_INSTANCES as i64)
+    )]
+    pub(crate) driver_instances: u16,
+
+    /// How the russula coordinators handle hosts which are unreachable or fail:
+    /// fail-fast, quorum:<n> (continue while n hosts of a group are healthy) or
+    /// best-effort (continue while any host of a group is healthy).
+    ///
+    /// Hosts excluded from a run are listed in the report.
+    #[arg(long, default_value_t = russula::FailurePolicy::FailFast)]
+    pub(crate) failure_policy: russula::FailurePolicy,
+
+    /// The number of times unhealthy hosts are replaced before giving up.
+    ///
+    /// The hosts are checked (ssm agent online, free disk space, clock sync and a
+    /// reachable russula port) after they're launched.
+    #[arg(long, default_value_t = 2)]
+    pub(crate) health_check_retries: u32,
+
+    /// Launch the hosts without asking for confirmation of the plan
+    #[arg(long, short)]
+    pub(crate) yes: bool,
+
+    /// The max clock offset of a host from NTP time. The run is aborted if a host's
+    /// clock isn't synchronized within the offset after configuring the hosts.
+    #[arg(long, value_parser = duration::parse_duration, default_value = "1ms")]
+    pub(crate) max_clock_offset: core::time::Duration,
+
+    /// Label the run, to find it later with `list-runs --label`. May be repeated.
+    /// ex: --label branch=my-feature
+    #[arg(long = "label", value_parser = index::parse_label)]
+    pub(crate) labels: Vec<(String, String)>,
+
+    /// Comment a comparison of the run against a baseline on this GitHub PR.
+    /// Requires `github` in the orchestrator config.
+    #[arg(long)]
+    pub(crate) github_pr: Option<u64>,
+
+    /// Repeat the jobs N times on the same hosts. Each iteration's results are
+    /// stored separately and the report summarizes the spread of the system
+    /// metrics across the iterations (mean, median, stddev and 95% confidence
+    /// interval), flagging noisy results.
+    #[arg(long, default_value_t = 1, value_parser = clap::value_parser!(u32).range(1..=100))]
+    pub(crate) iterations: u32,
+
+    /// Run each job once before the measured run and discard its results, so
+    /// that first-connection costs (handshakes, cold caches) aren't measured.
+    #[arg(long)]
+    pub(crate) warmup: bool,
+
+    /// Skip running each driver for a few seconds on every host before the
+    /// jobs, which fails the run early if a driver crashes or writes no results.
+    #[arg(long)]
+    pub(crate) skip_smoke: bool,
+
+    /// Run each job for a fixed duration (ex: 2h) rather than until the scenario
+    /// completes, for soak tests. The scenario should run at least this long.
+    ///
+    /// The results are streamed to `streams/` every 10 minutes (see
+    /// `--stream-interval`) and the hosts' shutdown timer is extended to cover
+    /// the run.
+    #[arg(long, value_parser = duration::parse_duration)]
+    pub(crate) duration: Option<core::time::Duration>,
+
+    /// Upload the output appended by the hosts to `streams/` in chunks at this
+    /// interval while each job runs, ex: 5m, so that a failed host doesn't lose
+    /// the results. Defaults to 10m with `--duration` and disabled otherwise.
+    #[arg(long, value_parser = duration::parse_duration)]
+    pub(crate) stream_interval: Option<core::time::Duration>,
+
+    /// How long the hosts live before shutting themselves down, ex: 3h.
+    ///
+    /// Defaults to an estimate based on the number of jobs and `--duration`.
+    /// The shutdown is rescheduled if the run overruns it.
+    #[arg(long, value_parser = duration::parse_duration)]
+    pub(crate) host_lifetime: Option<core::time::Duration>,
+
+    /// Build the s2n-quic and tcp drivers from this repository, ex: a fork
+    #[arg(long, default_value_t = STATE.netbench_repo.to_string())]
+    pub(crate) driver_repo: String,
+
+    /// Build the drivers from this branch of `--driver-repo`
+    #[arg(long, default_value_t = STATE.netbench_branch.to_string())]
+    pub(crate) driver_branch: String,
+
+    /// Fetch and build this commit or ref of `--driver-repo`, ex: pull/123/head
+    #[arg(long)]
+    pub(crate) driver_rev: Option<String>,
+
+    /// Run on the hosts of an infra pool, created with `infra create <name>`,
+    /// instead of launching new hosts. Only the drivers are rebuilt and the
+    /// hosts are kept alive after the run.
+    #[arg(long)]
+    pub(crate) use_infra: Option<String>,
+
+    /// The number of result files downloaded from S3 concurrently to generate
+    /// the report. Failed or corrupted downloads are retried.
+    #[arg(long, default_value_t = 16, value_parser = clap::value_parser!(u16).range(1..=256))]
+    pub(crate) download_parallelism: u16,
+
+    #[command(subcommand)]
+    command: Option<OrchCommand>,
+}
+
+#[derive(Subcommand, Debug)]
+enum OrchCommand {
+    /// Manage netbench scenario files
+    Scenario {
+        #[command(subcommand)]
+        command: scenario::ScenarioCommand,
+    },
+    /// Delete old run artifacts from the s3 log bucket
+    CleanupArtifacts(prune::PruneArgs),
+    /// Follow the progress of a run started on another machine
+    Attach(dashboard::attach::AttachArgs),
+    /// Print the hosts, steps and artifacts of a run, ex: to tell if it's stuck
+    Status(dashboard::status::StatusArgs),
+    /// Download all the artifacts of a run to a local directory
+    Fetch(fetch::FetchArgs),
+    /// List the latest runs, optionally with the given labels
+    ListRuns(index::ListRunsArgs),
+    /// Check the AWS credentials, permissions and resources a run relies on
+    Doctor(doctor::DoctorArgs),
+    /// Manage named baseline runs
+    Baseline {
+        #[command(subcommand)]
+        command: baseline::BaselineCommand,
+    },
+    /// Compare the system metrics of a run against a baseline
+    Compare(report::compare::CompareArgs),
+    /// Inspect the report of a run
+    Report {
+        #[command(subcommand)]
+        command: report::ReportCommand,
+    },
+    /// Manage pools of hosts which are kept alive across runs
+    Infra {
+        #[command(subcommand)]
+        command: pool::InfraCommand,
+    },
+    /// Execute the run specs received from an SQS queue
+    Serve(serve::ServeArgs),
+    /// Export the run's plan as an AWS Step Functions state machine
+    ExportAsl(plan::asl::ExportAslArgs),
+    /// Stop a run started on another machine, cleaning up its hosts
+    Cancel(shutdown::CancelArgs),
+}
+
+/// Parse the command line and execute the command, or the run.
+pub async fn run() -> OrchResult<()> {
+    let unique_id = format!(
+        "{}-{}",
+        humantime::format_rfc3339_seconds(std::time::SystemTime::now()),
+        STATE.version
+    );
+
+    let args = Args::parse();
+    let log_guard = logging::init(&unique_id, args.log_format);
+
+    // the logs and scenarios are in STATE.region wherever the orchestrator runs
+    control_plane::init(control_plane::Deployment::detect().await);
+    let region = Region::new(STATE.region);
+    let aws_config = aws_config::from_env().region(region).load().await;
+
+    if let Some(command) = &args.command {
+        return match command {
+            OrchCommand::Scenario { command } => command.run(),
+            OrchCommand::CleanupArtifacts(prune_args) => prune_args.run(&aws_config).await,
+            OrchCommand::Attach(attach_args) => attach_args.run(&aws_config).await,
+            OrchCommand::Status(status_args) => status_args.run(&aws_config).await,
+            OrchCommand::ListRuns(list_args) => list_args.run(&aws_config).await,
+            OrchCommand::Fetch(fetch_args) => {
+                fetch_args.run(args.download_options(), &aws_config).await
+            }
+            OrchCommand::Doctor(doctor_args) => doctor_args.run(&args, &aws_config).await,
+            OrchCommand::Baseline { command } => command.run(&aws_config).await,
+            OrchCommand::Compare(compare_args) => compare_args.run(&aws_config).await,
+            OrchCommand::Report { command } => command.run(&aws_config).await,
+            OrchCommand::Infra { command } => command.run(&unique_id, &args, &aws_config).await,
+            OrchCommand::Serve(serve_args) => serve_args.run(&args, &aws_config).await,
+            OrchCommand::ExportAsl(export_args) => export_args.run(&args),
+            OrchCommand::Cancel(cancel_args) => cancel_args.run(&aws_config).await,
+        };
+    }
+    let config = OrchestratorConfig::load(args.config.as_deref())?;
+    poll::configure(config.polling);
+    let run_spec = args
+        .run_spec
+        .as_deref()
+        .map(run_spec::RunSpec::load)
+        .transpose()?;
+    let groups = check_requirements(&args, run_spec.as_ref(), &config, &aws_config).await?;
+    // an infra pool's hosts are already running
+    if args.use_infra.is_none() {
+        for group in groups.iter() {
+            plan::RunPlan::new(&group.scenarios, &group.instance_type, &config.host_groups)?
+                .confirm(args.yes)?;
+        }
+    }
+
+    // stop the workers and delete the hosts on Ctrl-C rather than leaking them
+    shutdown::install();
+
+    let start = std::time::Instant::now();
+    let matrix = run_spec.is_some();
+    // stop the run as if by Ctrl-C once it's cancelled from another terminal
+    let cancel_ids = std::iter::once(unique_id.clone())
+        .chain(
+            groups
+                .iter()
+                .filter(|_| matrix)
+                .map(|group| group.unique_id(&unique_id)),
+        )
+        .collect();
+    let cancel = tokio::spawn(shutdown::watch_cancel(
+        aws_sdk_s3::Client::new(&aws_config),
+        cancel_ids,
+    ));
+    let result = execute(&unique_id, &args, matrix, &config, &groups, &aws_config).await;
+    cancel.abort();
+    let status = run_status(&unique_id, matrix, &result, start.elapsed());
+    notify::notify(&config.notifications, &status, &aws_config).await;
+
+    // flush the log before uploading it
+    drop(log_guard);
+    let s3_client = aws_sdk_s3::Client::new(&aws_config);
+    if let Err(err) = logging::upload(&s3_client, &unique_id).await {
+        eprintln!("{}", err);
+    }
+    if shutdown::is_interrupted() {
+        if let Err(err) = result {
+            eprintln!("{}", err);
+        }
+        std::process::exit(shutdown::EXIT_CODE);
+    }
+    result
+}
+
+/// Run each group on its own hosts, one after the other.
+pub(crate) async fn execute(
+    unique_id: &str,
+    args: &Args,
+    matrix: bool,
+    config: &OrchestratorConfig,
+    groups: &[run_spec::RunGroup],
+    aws_config: &aws_types::SdkConfig,
+) -> OrchResult<()> {
+    let mut outcomes = Vec::new();
+    for group in groups.iter() {
+        if shutdown::is_interrupted() {
+            break;
+        }
+        let group_id = match matrix {
+            true => group.unique_id(unique_id),
+            false => unique_id.to_string(),
+        };
+        let result = orchestrator::run(group_id, args, config, group, aws_config).await;
+        outcomes.push(result);
+    }
+
+    let s3_client = aws_sdk_s3::Client::new(aws_config);
+    if matrix {
+        let errors: Vec<Option<String>> = outcomes
+            .iter()
+            .map(|outcome| outcome.as_ref().err().map(|err| err.to_string()))
+            .collect();
+        if let Err(err) =
+            run_spec::upload_matrix_report(&s3_client, unique_id, groups, &errors).await
+        {
+            eprintln!("{}", err);
+        }
+    }
+    outcomes
+        .into_iter()
+        .collect::<OrchResult<Vec<()>>>()
+        .map(|_| ())
+}
+
+pub(crate) fn run_status(
+    unique_id: &str,
+    matrix: bool,
+    result: &OrchResult<()>,
+    duration: std::time::Duration,
+) -> notify::RunStatus {
+    notify::RunStatus {
+        unique_id: unique_id.to_string(),
+        outcome: match result {
+            _ if shutdown::is_interrupted() => notify::Outcome::Interrupted,
+            Ok(()) => notify::Outcome::Succeeded,
+            Err(err) => notify::Outcome::Failed(err.to_string()),
+        },
+        duration,
+        report_url: match matrix {
+            true => format!("{}/matrix.html", STATE.cf_url(unique_id)),
+            false => format!("{}/report/index.html", STATE.cf_url(unique_id)),
+        },
+    }
+}
+
+impl Args {
+    pub(crate) fn driver_source(&self) -> DriverSource {
+        DriverSource {
+            repo: self.driver_repo.clone(),
+            branch: self.driver_branch.clone(),
+            rev: self.driver_rev.clone(),
+        }
+    }
+
+    pub(crate) fn download_options(&self) -> s3_utils::DownloadOptions {
+        s3_utils::DownloadOptions {
+            parallelism: self.download_parallelism.into(),
+            ..Default::default()
+        }
+    }
+}
+
+pub(crate) async fn check_requirements(
+    args: &Args,
+    run_spec: Option<&run_spec::RunSpec>,
+    config: &OrchestratorConfig,
+    aws_config: &aws_types::SdkConfig,
+) -> OrchResult<Vec<run_spec::RunGroup>> {
+    let groups = resolve_groups(args, run_spec, config)?;
+    args.driver_source().validate()?;
+    for group in groups.iter() {
+        let plan = plan::RunPlan::new(&group.scenarios, &group.instance_type, &config.host_groups)?;
+        for instance_type in plan.instance_types() {
+            config.budget.check(plan.instances(), instance_type)?;
+        }
+    }
+
+    // export PATH="/home/toidiu/projects/s2n-quic/netbench/target/release/:$PATH"
+    Command::new("s2n-netbench")
+        .output()
+        .map_err(|_err| OrchError::Init {
+            dbg: "Missing `s2n-netbench` cli. Please the Getting started section in the Readme"
+                .to_string(),
+        })?;
+
+    // report folder
+    std::fs::create_dir_all(STATE.workspace_dir).map_err(|_err| OrchError::Init {
+        dbg: "Failed to create local workspace".to_string(),
+    })?;
+
+    let iam_client = aws_sdk_iam::Client::new(aws_config);
+    iam_client
+        .list_roles()
+        .send()
+        .await
+        .map_err(|_err| OrchError::Init {
+            dbg: "Missing AWS credentials.".to_string(),
+        })?;
+
+    Ok(groups)
+}
+
+/// The groups of jobs of the run, from the run spec or the scenario files.
+pub(crate) fn resolve_groups(
+    args: &Args,
+    run_spec: Option<&run_spec::RunSpec>,
+    config: &OrchestratorConfig,
+) -> OrchResult<Vec<run_spec::RunGroup>> {
+    let scenario_files = match &run_spec {
+        Some(run_spec) => &run_spec.scenarios,
+        None => &args.scenario_file,
+    };
+    let mut scenarios = Vec::new();
+    for path in scenario_paths(scenario_files)? {
+        scenarios.push(load_scenario(&path)?);
+    }
+    Ok(match &run_spec {
+        Some(run_spec) => run_spec.groups(&scenarios, config),
+        None => vec![run_spec::RunGroup::new(scenarios, config)],
+    }
+    .into_iter()
+    .map(|group| group.with_iterations(args.iterations))
+    .collect())
+}
@@ -0,0 +1,1714 @@
+// Copyright Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::{
+    ec2_utils::{EndpointType, HostOs, LinuxDistro},
+    error::{OrchError, OrchResult},
+    Scenario, STATE,
+};
+use core::time::Duration;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::{
+    collections::BTreeMap,
+    fs::File,
+    net::Ipv4Addr,
+    path::{Path, PathBuf},
+};
+
+/// Orchestrator configuration, loaded from a json file with `--config`.
+///
+/// ```json
+/// {
+///   "impairment": { "delay": "20ms", "jitter": "5ms", "loss_pct": 0.1 },
+///   "scenario_impairments": {
+///     "request_response.json": { "rate": "100mbit" }
+///   }
+/// }
+/// ```
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct OrchestratorConfig {
+    // Network impairment applied to every scenario
+    pub impairment: Option<Impairment>,
+    // Scenario name -> network impairment. Overrides `impairment`
+    pub scenario_impairments: BTreeMap<String, Impairment>,
+    // CIDRs allowed to reach the hosts' ssh and russula ports. Defaults to the
+    // orchestrator's public ip. ex: 203.0.113.0/24
+    pub ingress_cidrs: Vec<String>,
+    // Launch the hosts in a private subnet without public ips
+    pub private_network: Option<PrivateNetwork>,
+    // Launch the hosts in a VPC created for the run rather than the tagged subnet
+    pub dedicated_vpc: Option<DedicatedVpc>,
+    // Set the MTU of the hosts' primary interface, and verify it before the
+    // scenarios run. ex: 9001 (jumbo frames) or 1500
+    pub mtu: Option<u16>,
+    // Tune the hosts before running the scenarios to reduce run to run variance
+    pub tuning: Option<Tuning>,
+    // Kernel tunables applied to the Linux hosts before the scenarios and
+    // reverted after the run. ex: { "net.core.rmem_max": "26214400" }
+    pub sysctl: BTreeMap<String, String>,
+    // Limits on the hosts launched by a run
+    pub budget: Budget,
+    // Comment a comparison against a baseline on a GitHub PR
+    pub github: Option<Github>,
+    // Options for the netbench collector which launches the drivers
+    pub collector: Collector,
+    // How s2n-netbench's own report commands render the results, next to the
+    // orchestrator's report
+    pub netbench_report: NetbenchReport,
+    // Where to send the status of the run once it finishes or is aborted
+    pub notifications: Vec<Notification>,
+    // Launch the hosts from this existing launch template (its default
+    // version) rather than one created for the run
+    pub launch_template: Option<String>,
+    // The operating system of the client hosts. Windows clients are compared
+    // against Linux servers
+    pub client_os: HostOs,
+    // The distribution of the Linux hosts. A run spec can compare several
+    pub distro: LinuxDistro,
+    // Record the summary metrics of each run in a DynamoDB table
+    pub trend: Option<Trend>,
+    // Measure the network ceiling of the instances with iperf3 before the
+    // scenarios run
+    pub iperf3: Option<Iperf3>,
+    // Probe the throughput of the hosts before the scenarios and compare it to
+    // the previous runs on the same instance type
+    pub calibration: Option<Calibration>,
+    // How often SSM commands, EC2 instances and russula Workers are polled
+    pub polling: Polling,
+    // How long each russula phase of a scenario may take before it's aborted
+    pub russula_timeouts: RussulaTimeouts,
+    // The groups of hosts launched for the run. A group named after each of
+    // the server, client and router roles without a configured group is sized
+    // for the largest scenario
+    pub host_groups: Vec<HostGroup>,
+    // Share the compiled artifacts of the driver and russula builds between
+    // the hosts with sccache
+    pub build_cache: Option<BuildCache>,
+    // Certificates or tokens needed by the drivers, delivered encrypted to the
+    // Linux hosts for the run
+    pub secrets: Vec<Secret>,
+    // Replace the certificates of the scenarios with certificates generated
+    // for the run
+    pub tls: Option<Tls>,
+    // The root volume of the Linux hosts and whether their instance store is
+    // used for the captures
+    pub storage: Storage,
+    // How the orchestrator reaches the hosts when it runs within AWS
+    pub control_plane: ControlPlane,
+}
+
+impl OrchestratorConfig {
+    /// Load the config, or use the default config if a path is not specified.
+    pub fn load(path: Option<&Path>) -> OrchResult<Self> {
+        let Some(path) = path else {
+            return Ok(OrchestratorConfig::default());
+        };
+        let file = File::open(path).map_err(|err| OrchError::Init {
+            dbg: format!("Failed to open config {:?}: {}", path, err),
+        })?;
+        let config: OrchestratorConfig =
+            serde_json::from_reader(file).map_err(|err| OrchError::Init {
+                dbg: format!("Failed to parse config {:?}: {}", path, err),
+            })?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    fn validate(&self) -> OrchResult<()> {
+        for impairment in self
+            .impairment
+            .iter()
+            .chain(self.scenario_impairments.values())
+        {
+            impairment.validate()?;
+        }
+        for cidr in self.ingress_cidrs.iter() {
+            if !is_ipv4_cidr(cidr) {
+                return Err(OrchError::Init {
+                    dbg: format!("Invalid ingress cidr (ex: 203.0.113.0/24): {}", cidr),
+                });
+            }
+        }
+        if self.private_network.is_some() && !self.ingress_cidrs.is_empty() {
+            return Err(OrchError::Init {
+                dbg: "ingress_cidrs are unused with a private_network. russula is relayed over ssm"
+                    .to_string(),
+            });
+        }
+        if let Some(name) = &self.launch_template {
+            let valid = |c: char| c.is_ascii_alphanumeric() || "()./_-".contains(c);
+            if name.len() < 3 || !name.chars().all(valid) {
+                return Err(OrchError::Init {
+                    dbg: format!("Invalid launch template name: {:?}", name),
+                });
+            }
+        }
+        if let Some(dedicated_vpc) = &self.dedicated_vpc {
+            if self.private_network.is_some() {
+                return Err(OrchError::Init {
+                    dbg: "dedicated_vpc and private_network can't be used together".to_string(),
+                });
+            }
+            let valid_prefix = dedicated_vpc
+                .cidr
+                .split_once('/')
+                .and_then(|(_ip, prefix)| prefix.parse::<u8>().ok())
+                .is_some_and(|prefix| (16..=28).contains(&prefix));
+            if !is_ipv4_cidr(&dedicated_vpc.cidr) || !valid_prefix {
+                return Err(OrchError::Init {
+                    dbg: format!(
+                        "Invalid dedicated_vpc cidr (a /16 to /28 ex: 10.0.0.0/16): {}",
+                        dedicated_vpc.cidr
+                    ),
+                });
+            }
+        }
+        if let Some(mtu) = self.mtu {
+            if !(1280..=9001).contains(&mtu) {
+                return Err(OrchError::Init {
+                    dbg: format!("mtu must be between 1280 and 9001: {}", mtu),
+                });
+            }
+        }
+        if let Some(tuning) = &self.tuning {
+            tuning.validate()?;
+        }
+        for (key, value) in self.sysctl.iter() {
+            if !is_sysctl_key(key) {
+                return Err(OrchError::Init {
+                    dbg: format!("Invalid sysctl key (ex: net.core.rmem_max): {:?}", key),
+                });
+            }
+            // interpolated in the commands run on the hosts
+            let valid = |c: char| c.is_ascii_alphanumeric() || " _-".contains(c);
+            if value.trim().is_empty() || !value.chars().all(valid) {
+                return Err(OrchError::Init {
+                    dbg: format!("Invalid sysctl value for {}: {:?}", key, value),
+                });
+            }
+        }
+        self.collector.validate()?;
+        if self.client_os.is_windows() {
+            // these configure the hosts with Linux tools
+            let linux_only = [
+                ("impairment", self.impairment.is_some()),
+                (
+                    "scenario_impairments",
+                    !self.scenario_impairments.is_empty(),
+                ),
+                ("mtu", self.mtu.is_some()),
+                ("tuning", self.tuning.is_some()),
+                ("launch_template", self.launch_template.is_some()),
+            ];
+            if let Some((option, _)) = linux_only.iter().find(|(_, set)| *set) {
+                return Err(OrchError::Init {
+                    dbg: format!("{} isn't supported with windows clients", option),
+                });
+            }
+        }
+        for notification in self.notifications.iter() {
+            notification.validate()?;
+        }
+        if let Some(github) = &self.github {
+            if github
+                .repo
+                .split('/')
+                .filter(|part| !part.is_empty())
+                .count()
+                != 2
+            {
+                return Err(OrchError::Init {
+                    dbg: format!("github repo must be <owner>/<repo>: {}", github.repo),
+                });
+            }
+        }
+        if self
+            .trend
+            .as_ref()
+            .is_some_and(|trend| trend.table.is_empty())
+        {
+            return Err(OrchError::Init {
+                dbg: "trend table must not be empty".to_string(),
+            });
+        }
+        if let Some(iperf3) = &self.iperf3 {
+            iperf3.validate()?;
+        }
+        if let Some(calibration) = &self.calibration {
+            calibration.validate()?;
+        }
+        self.polling.validate()?;
+        self.russula_timeouts.validate()?;
+        if let Some(build_cache) = &self.build_cache {
+            build_cache.validate()?;
+        }
+        if let Some(tls) = &self.tls {
+            tls.validate()?;
+        }
+        self.storage.validate()?;
+        let mut names = std::collections::BTreeSet::new();
+        for secret in self.secrets.iter() {
+            secret.validate()?;
+            if !names.insert(&secret.name) {
+                return Err(OrchError::Init {
+                    dbg: format!("Duplicate secret {}", secret.name),
+                });
+            }
+        }
+        let mut names = std::collections::BTreeSet::new();
+        for group in self.host_groups.iter() {
+            group.validate()?;
+            if !names.insert(group.name.as_str()) {
+                return Err(OrchError::Init {
+                    dbg: format!("duplicate host group name: {}", group.name),
+                });
+            }
+        }
+        Ok(())
+    }
+
+    pub fn impairment(&self, scenario: &Scenario) -> Option<&Impairment> {
+        self.scenario_impairments
+            .get(&scenario.name)
+            .or(self.impairment.as_ref())
+    }
+}
+
+/// Launch the hosts in a private subnet without public ips.
+///
+/// The orchestrator reaches russula on the hosts through SSM port forwarding
+/// sessions. The subnet needs VPC endpoints for ssm, ssmmessages, ec2messages
+/// and s3, or a NAT gateway.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PrivateNetwork {
+    // Filter used to find the private subnet. ex: ["tag:aws-cdk:subnet-type", "Private"]
+    pub subnet_tag: (String, String),
+}
+
+/// Launch the hosts in a VPC, subnet, internet gateway and route table created
+/// for the run and deleted with its hosts, so that no network needs to exist in
+/// the account beforehand.
+///
+/// ```json
+/// { "dedicated_vpc": { "cidr": "10.0.0.0/16" } }
+/// ```
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DedicatedVpc {
+    // The ip range of the VPC and of its single subnet
+    pub cidr: String,
+}
+
+impl Default for DedicatedVpc {
+    fn default() -> Self {
+        DedicatedVpc {
+            cidr: "10.0.0.0/16".to_string(),
+        }
+    }
+}
+
+/// The tuning profile applied to every host: irqbalance is disabled, the NIC's
+/// interrupts are pinned to the first `irq_cpus` cpus, the cpu governor is set
+/// and, optionally, the hyperthread siblings of the other cpus are disabled so
+/// that each driver thread has a core to itself.
+///
+/// ```json
+/// { "tuning": { "irq_cpus": 2, "governor": "performance", "disable_smt_siblings": true } }
+/// ```
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Tuning {
+    pub irq_cpus: u16,
+    pub governor: String,
+    pub disable_smt_siblings: bool,
+}
+
+impl Default for Tuning {
+    fn default() -> Self {
+        Tuning {
+            irq_cpus: 2,
+            governor: "performance".to_string(),
+            disable_smt_siblings: true,
+        }
+    }
+}
+
+impl Tuning {
+    fn validate(&self) -> OrchResult<()> {
+        if !(1..=16).contains(&self.irq_cpus) {
+            return Err(OrchError::Init {
+                dbg: format!(
+                    "tuning irq_cpus must be between 1 and 16: {}",
+                    self.irq_cpus
+                ),
+            });
+        }
+        if self.governor.is_empty() || !self.governor.chars().all(|c| c.is_ascii_lowercase()) {
+            return Err(OrchError::Init {
+                dbg: format!("Invalid tuning governor: {:?}", self.governor),
+            });
+        }
+        Ok(())
+    }
+}
+
+/// Post a comparison of the run against a baseline run as a comment on the PR
+/// passed with `--github-pr`.
+///
+/// ```json
+/// { "github": { "repo": "aws/s2n-quic", "baseline": "main" } }
+/// ```
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Github {
+    // The repo of the PR. ex: aws/s2n-quic
+    pub repo: String,
+    // Name of the baseline run to compare against. see `baselines/<name>` in the log bucket
+    #[serde(default = "default_github_baseline")]
+    pub baseline: String,
+    // Env var holding the token used to post the comment
+    #[serde(default = "default_github_token_env")]
+    pub token_env: String,
+}
+
+/// Record the throughput, latency and connections of each job and driver in a
+/// DynamoDB table after the run, for long-horizon regression tracking. Chart a
+/// metric with `report trend`.
+///
+/// The table needs a `series` (string) partition key and a `run` (string) sort
+/// key.
+///
+/// ```json
+/// { "trend": { "table": "netbench-trend" } }
+/// ```
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Trend {
+    pub table: String,
+}
+
+/// Run an iperf3 baseline between the client and server hosts before the
+/// scenarios, for context on the network ceiling of the instances. Each client
+/// runs against its own iperf3 server, for each protocol in turn.
+///
+/// ```json
+/// { "iperf3": { "protocols": ["tcp", "udp"], "duration": "10s", "parallel": 4, "udp_bitrate": "10G" } }
+/// ```
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Iperf3 {
+    pub protocols: Vec<Iperf3Protocol>,
+    // How long each test runs. Defaults to iperf3's default (10s)
+    #[serde(with = "humantime_opt")]
+    pub duration: Option<Duration>,
+    // Parallel streams of each client
+    pub parallel: u16,
+    // The target bitrate of the udp tests in iperf3 units, since iperf3 sends
+    // 1Mbit/s of udp by default. ex: 10G, or 0 for unlimited
+    pub udp_bitrate: String,
+}
+
+impl Default for Iperf3 {
+    fn default() -> Self {
+        Iperf3 {
+            protocols: vec![Iperf3Protocol::Tcp],
+            duration: None,
+            parallel: 1,
+            udp_bitrate: "10G".to_string(),
+        }
+    }
+}
+
+impl Iperf3 {
+    fn validate(&self) -> OrchResult<()> {
+        if self.protocols.is_empty() {
+            return Err(OrchError::Init {
+                dbg: "iperf3 protocols must not be empty".to_string(),
+            });
+        }
+        if !(1..=128).contains(&self.parallel) {
+            return Err(OrchError::Init {
+                dbg: format!(
+                    "iperf3 parallel must be between 1 and 128: {}",
+                    self.parallel
+                ),
+            });
+        }
+        if self
+            .duration
+            .is_some_and(|duration| duration.as_secs() == 0)
+        {
+            return Err(OrchError::Init {
+                dbg: "iperf3 duration must be at least 1s".to_string(),
+            });
+        }
+        let valid_bitrate = self
+            .udp_bitrate
+            .trim_end_matches(['K', 'M', 'G', 'T'])
+            .parse::<u64>()
+            .is_ok();
+        if !valid_bitrate {
+            return Err(OrchError::Init {
+                dbg: format!(
+                    "iperf3 udp_bitrate must be an iperf3 bitrate (ex: 10G): {}",
+                    self.udp_bitrate
+                ),
+            });
+        }
+        Ok(())
+    }
+}
+
+/// Probe the loopback and cross-host throughput of the hosts with iperf3 before
+/// the scenarios, and warn in the report when a host deviates from the norm of
+/// the previous runs on the same instance type, ex: because of a noisy
+/// neighbor.
+///
+/// The norms are the medians of the last `history` runs within the norms,
+/// stored in the log bucket under `calibration/<instance_type>.json`.
+///
+/// ```json
+/// { "calibration": { "duration": "3s", "max_deviation_pct": 20, "history": 20 } }
+/// ```
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Calibration {
+    // How long each probe runs
+    #[serde(with = "humantime_opt")]
+    pub duration: Option<Duration>,
+    // A host whose throughput deviates more than this from the norm is flagged
+    pub max_deviation_pct: f64,
+    // The number of previous runs the norms are computed from
+    pub history: usize,
+}
+
+impl Default for Calibration {
+    fn default() -> Self {
+        Calibration {
+            duration: Some(Duration::from_secs(3)),
+            max_deviation_pct: 20.0,
+            history: 20,
+        }
+    }
+}
+
+impl Calibration {
+    fn validate(&self) -> OrchResult<()> {
+        if self
+            .duration
+            .is_some_and(|duration| duration.as_secs() == 0)
+        {
+            return Err(OrchError::Init {
+                dbg: "calibration duration must be at least 1s".to_string(),
+            });
+        }
+        if !(self.max_deviation_pct > 0.0 && self.max_deviation_pct <= 100.0) {
+            return Err(OrchError::Init {
+                dbg: format!(
+                    "calibration max_deviation_pct must be 0-100: {}",
+                    self.max_deviation_pct
+                ),
+            });
+        }
+        if self.history == 0 {
+            return Err(OrchError::Init {
+                dbg: "calibration history must be at least 1".to_string(),
+            });
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Iperf3Protocol {
+    Tcp,
+    Udp,
+}
+
+impl Iperf3Protocol {
+    pub fn as_str(&self) -> &str {
+        match self {
+            Iperf3Protocol::Tcp => "tcp",
+            Iperf3Protocol::Udp => "udp",
+        }
+    }
+}
+
+/// Where to send the status, duration and report url of a run once it finishes
+/// or is aborted.
+///
+/// ```json
+/// { "notifications": [
+///   { "type": "slack" },
+///   { "type": "sns", "topic_arn": "arn:aws:sns:us-west-2:123456789012:netbench" },
+///   { "type": "webhook", "url": "https://example.com/netbench" }
+/// ] }
+/// ```
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+pub enum Notification {
+    // Post a message to a Slack incoming webhook
+    Slack {
+        // Env var holding the webhook url, which is a secret
+        #[serde(default = "default_slack_webhook_env")]
+        webhook_env: String,
+    },
+    // Publish a message to an SNS topic
+    Sns {
+        topic_arn: String,
+    },
+    // Post the status as json
+    Webhook {
+        url: String,
+    },
+}
+
+impl Notification {
+    fn validate(&self) -> OrchResult<()> {
+        let valid = match self {
+            Notification::Slack { webhook_env } => !webhook_env.is_empty(),
+            Notification::Sns { topic_arn } => topic_arn.starts_with("arn:aws:sns:"),
+            Notification::Webhook { url } => {
+                url.starts_with("https://") || url.starts_with("http://")
+            }
+        };
+        if !valid {
+            return Err(OrchError::Init {
+                dbg: format!("Invalid notification: {:?}", self),
+            });
+        }
+        Ok(())
+    }
+}
+
+impl std::fmt::Display for Notification {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Notification::Slack { webhook_env } => write!(f, "slack (${webhook_env})"),
+            Notification::Sns { topic_arn } => write!(f, "sns {topic_arn}"),
+            Notification::Webhook { url } => write!(f, "webhook {url}"),
+        }
+    }
+}
+
+fn default_slack_webhook_env() -> String {
+    "SLACK_WEBHOOK_URL".to_string()
+}
+
+fn default_github_baseline() -> String {
+    "main".to_string()
+}
+
+fn default_github_token_env() -> String {
+    "GITHUB_TOKEN".to_string()
+}
+
+/// Limits on the hosts launched by a run, which protect against accidentally
+/// launching a large (expensive) fleet.
+///
+/// ```json
+/// { "budget": { "max_instances": 10, "max_instance_hours": 20, "allowed_instance_types": ["c5.4xlarge"] } }
+/// ```
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Budget {
+    // Max hosts launched by a run, including replacements of unhealthy hosts
+    pub max_instances: Option<usize>,
+    // Max instance-hours, assuming the hosts run until their scheduled shutdown
+    pub max_instance_hours: Option<f64>,
+    // Instance types which can be launched. Any type if empty
+    pub allowed_instance_types: Vec<String>,
+}
+
+impl Budget {
+    /// Error if launching `instances` hosts of `instance_type` exceeds the budget.
+    pub fn check(&self, instances: usize, instance_type: &str) -> OrchResult<()> {
+        self.check_lifetime(instances, instance_type, STATE.shutdown_min)
+    }
+
+    /// Error if launching `instances` hosts of `instance_type`, which shutdown
+    /// after `shutdown_min`, exceeds the budget.
+    pub fn check_lifetime(
+        &self,
+        instances: usize,
+        instance_type: &str,
+        shutdown_min: u16,
+    ) -> OrchResult<()> {
+        if !self.allowed_instance_types.is_empty()
+            && !self
+                .allowed_instance_types
+                .iter()
+                .any(|allowed| allowed == instance_type)
+        {
+            return Err(OrchError::Init {
+                dbg: format!(
+                    "Budget: instance type {} is not allowed: {:?}",
+                    instance_type, self.allowed_instance_types
+                ),
+            });
+        }
+        if let Some(max_instances) = self.max_instances {
+            if instances > max_instances {
+                return Err(OrchError::Init {
+                    dbg: format!(
+                        "Budget: {} instances exceeds max_instances {}",
+                        instances, max_instances
+                    ),
+                });
+            }
+        }
+        if let Some(max_instance_hours) = self.max_instance_hours {
+            let instance_hours = instances as f64 * shutdown_min as f64 / 60.0;
+            if instance_hours > max_instance_hours {
+                return Err(OrchError::Init {
+                    dbg: format!(
+                        "Budget: {} instance-hours ({} instances until shutdown after {}min) exceeds max_instance_hours {}",
+                        instance_hours, instances, shutdown_min, max_instance_hours
+                    ),
+                });
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A group of hosts launched for the run, with the `role` of its hosts.
+///
+/// The hosts of all the groups with a server, client or router role run the
+/// scenarios' drivers. Observer hosts are launched and configured, but don't
+/// run a russula Worker. ex: a host capturing traffic or a bastion.
+///
+/// ```json
+/// { "host_groups": [
+///     { "name": "client", "role": "client", "count": 2, "instance_type": "c5n.18xlarge" },
+///     { "name": "observer", "role": "observer", "count": 1 }
+/// ] }
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct HostGroup {
+    pub name: String,
+    pub role: EndpointType,
+    pub count: usize,
+    // Defaults to the run's instance type
+    #[serde(default)]
+    pub instance_type: Option<String>,
+}
+
+impl HostGroup {
+    fn validate(&self) -> OrchResult<()> {
+        let valid = |c: char| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-';
+        if self.name.is_empty() || !self.name.chars().all(valid) {
+            return Err(OrchError::Init {
+                dbg: format!(
+                    "host group names must be lowercase alphanumeric or '-': {:?}",
+                    self.name
+                ),
+            });
+        }
+        if self.count == 0 {
+            return Err(OrchError::Init {
+                dbg: format!("host group {} must have at least 1 host", self.name),
+            });
+        }
+        Ok(())
+    }
+}
+
+/// Install sccache on the Linux hosts while they are configured and use it as
+/// the rustc wrapper of their builds, with an S3 bucket as the cache. The hosts
+/// build identical code so only the first host to compile a crate compiles it.
+///
+/// The instance profile of the hosts needs write access to the bucket.
+///
+/// ```json
+/// { "build_cache": { "bucket": "netbench-sccache", "prefix": "sccache", "region": "us-west-1" } }
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct BuildCache {
+    pub bucket: String,
+    // The key prefix of the cache in the bucket
+    pub prefix: String,
+    pub region: String,
+    // The sccache release installed on the hosts
+    pub version: String,
+}
+
+impl Default for BuildCache {
+    fn default() -> Self {
+        BuildCache {
+            bucket: STATE.s3_private_log_bucket.to_string(),
+            prefix: "sccache".to_string(),
+            region: STATE.region.to_string(),
+            version: "0.8.1".to_string(),
+        }
+    }
+}
+
+impl BuildCache {
+    /// The values are interpolated into the configure commands so only allow
+    /// characters found in bucket names, prefixes, regions and versions.
+    fn validate(&self) -> OrchResult<()> {
+        for (option, value, extra) in [
+            ("bucket", &self.bucket, ".-"),
+            ("prefix", &self.prefix, "._/-"),
+            ("region", &self.region, "-"),
+            ("version", &self.version, "."),
+        ] {
+            let valid = |c: char| c.is_ascii_alphanumeric() || extra.contains(c);
+            if value.is_empty() || !value.chars().all(valid) {
+                return Err(OrchError::Init {
+                    dbg: format!("Invalid build_cache {}: {:?}", option, value),
+                });
+            }
+        }
+        Ok(())
+    }
+}
+
+/// How the orchestrator reaches the hosts when it runs within AWS, ex: as an
+/// ECS task or on an EC2 instance.
+///
+/// With `private_ips` the orchestrator connects to the hosts' private ips and
+/// only its own private ip is allowed in, so it needs to run in the hosts' VPC
+/// (or a peered VPC) and region.
+///
+/// ```json
+/// { "control_plane": { "private_ips": true } }
+/// ```
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ControlPlane {
+    pub private_ips: bool,
+    // Have the russula Workers dial the orchestrator and register, rather than
+    // the orchestrator connecting to each Worker's russula port
+    pub russula_registration: Option<RussulaRegistration>,
+}
+
+/// The russula Workers dial the Coordinators run by the orchestrator, on the
+/// russula port of their role, and register as their host's private ip. Only
+/// the orchestrator needs to be reachable, so the hosts can be in a private
+/// subnet, without SSM port forwarding, or behind a NAT.
+///
+/// ```json
+/// { "control_plane": { "russula_registration": { "coordinator_ip": "10.0.1.5" } } }
+/// ```
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RussulaRegistration {
+    // The ip the Workers dial. Defaults to the orchestrator's private ip when
+    // it runs within AWS
+    pub coordinator_ip: Option<Ipv4Addr>,
+}
+
+/// The root volume of the Linux hosts, and the NVMe instance store disks which
+/// are formatted and mounted while the hosts are configured.
+///
+/// The pcaps and profiles are written to the russula checkout, which is on the
+/// instance store if `instance_store` is set and the instance type has one.
+/// Set by the launch template with `launch_template`.
+///
+/// ```json
+/// { "storage": { "size_gb": 200, "volume_type": "gp3", "iops": 6000, "throughput": 500, "instance_store": true } }
+/// ```
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Storage {
+    // Defaults to 50GB, or 200GB with --capture-pcap or --profile
+    pub size_gb: Option<u32>,
+    pub volume_type: VolumeType,
+    // Provisioned IOPS of gp3, io1 and io2 volumes
+    pub iops: Option<u32>,
+    // Provisioned throughput of gp3 volumes, in MiB/s
+    pub throughput: Option<u32>,
+    // Mount the instance store disks, striped if there are several, at the
+    // russula checkout
+    pub instance_store: bool,
+}
+
+const ROOT_VOLUME_GB: u32 = 50;
+// Room for the pcaps and perf profiles
+const CAPTURE_ROOT_VOLUME_GB: u32 = 200;
+
+impl Storage {
+    pub fn root_volume_gb(&self) -> u32 {
+        self.size_gb.unwrap_or(ROOT_VOLUME_GB)
+    }
+
+    /// Grow the default root volume for runs which capture pcaps or profiles.
+    pub fn for_captures(&mut self) {
+        self.size_gb.get_or_insert(CAPTURE_ROOT_VOLUME_GB);
+    }
+
+    fn validate(&self) -> OrchResult<()> {
+        let invalid = |dbg: String| Err(OrchError::Init { dbg });
+        if !(8..=16384).contains(&self.root_volume_gb()) {
+            return invalid(format!(
+                "storage size_gb must be between 8 and 16384: {}",
+                self.root_volume_gb()
+            ));
+        }
+        let iops_range = match self.volume_type {
+            VolumeType::Gp2 => None,
+            VolumeType::Gp3 => Some(3000..=16000),
+            VolumeType::Io1 | VolumeType::Io2 => Some(100..=64000),
+        };
+        match (iops_range, self.iops) {
+            (None, Some(_)) => {
+                return invalid(format!(
+                    "storage iops can't be provisioned for {} volumes",
+                    self.volume_type.as_str()
+                ))
+            }
+            (Some(range), Some(iops)) if !range.contains(&iops) => {
+                return invalid(format!(
+                    "storage iops of {} volumes must be in {:?}: {}",
+                    self.volume_type.as_str(),
+                    range,
+                    iops
+                ))
+            }
+            (Some(_), None) if self.volume_type != VolumeType::Gp3 => {
+                return invalid(format!(
+                    "storage iops are required for {} volumes",
+                    self.volume_type.as_str()
+                ))
+            }
+            _ => {}
+        }
+        match (self.volume_type, self.throughput) {
+            (VolumeType::Gp3, Some(throughput)) if !(125..=1000).contains(&throughput) => {
+                invalid(format!(
+                    "storage throughput must be in 125..=1000 MiB/s: {}",
+                    throughput
+                ))
+            }
+            (VolumeType::Gp3, _) | (_, None) => Ok(()),
+            (volume_type, Some(_)) => invalid(format!(
+                "storage throughput can only be provisioned for gp3 volumes, not {}",
+                volume_type.as_str()
+            )),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum VolumeType {
+    #[default]
+    Gp2,
+    Gp3,
+    Io1,
+    Io2,
+}
+
+impl VolumeType {
+    pub fn as_str(&self) -> &str {
+        match self {
+            VolumeType::Gp2 => "gp2",
+            VolumeType::Gp3 => "gp3",
+            VolumeType::Io1 => "io1",
+            VolumeType::Io2 => "io2",
+        }
+    }
+}
+
+/// A secret delivered to `/run/netbench/secrets/<name>` on the Linux hosts,
+/// either the string value of an AWS Secrets Manager `secret_id` or a local
+/// `file`.
+///
+/// ```json
+/// { "secrets": [{ "name": "tls-key", "secret_id": "netbench/tls-key" }, { "name": "token", "file": "token.txt" }] }
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Secret {
+    pub name: String,
+    // The name or arn of the secret
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub secret_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub file: Option<PathBuf>,
+}
+
+impl Secret {
+    fn validate(&self) -> OrchResult<()> {
+        let valid = |c: char| c.is_ascii_alphanumeric() || "._-".contains(c);
+        if self.name.is_empty() || self.name.starts_with('.') || !self.name.chars().all(valid) {
+            return Err(OrchError::Init {
+                dbg: format!(
+                    "secret names must be alphanumeric, '.', '_' or '-': {:?}",
+                    self.name
+                ),
+            });
+        }
+        match (&self.secret_id, &self.file) {
+            (Some(secret_id), None) => {
+                let valid = |c: char| c.is_ascii_alphanumeric() || "/_+=.@:-".contains(c);
+                if secret_id.is_empty() || !secret_id.chars().all(valid) {
+                    return Err(OrchError::Init {
+                        dbg: format!("Invalid secret_id of secret {}", self.name),
+                    });
+                }
+                Ok(())
+            }
+            (None, Some(_)) => Ok(()),
+            _ => Err(OrchError::Init {
+                dbg: format!(
+                    "secret {} must have either a secret_id or a file",
+                    self.name
+                ),
+            }),
+        }
+    }
+}
+
+/// Generate a CA and a server certificate for the run, which replace the
+/// certificates of the scenarios and are installed in `/home/ec2-user/tls` on
+/// the Linux hosts.
+///
+/// ```json
+/// { "tls": { "subject_alt_names": ["localhost"] } }
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Tls {
+    // The names the server certificate is valid for, which the clients verify
+    pub subject_alt_names: Vec<String>,
+}
+
+impl Default for Tls {
+    fn default() -> Self {
+        Tls {
+            subject_alt_names: vec!["localhost".to_string()],
+        }
+    }
+}
+
+impl Tls {
+    fn validate(&self) -> OrchResult<()> {
+        if self.subject_alt_names.is_empty() {
+            return Err(OrchError::Init {
+                dbg: "tls subject_alt_names must not be empty".to_string(),
+            });
+        }
+        let valid = |c: char| c.is_ascii_alphanumeric() || ".-*:".contains(c);
+        for name in self.subject_alt_names.iter() {
+            if name.is_empty() || !name.chars().all(valid) {
+                return Err(OrchError::Init {
+                    dbg: format!("Invalid tls subject alt name: {:?}", name),
+                });
+            }
+        }
+        Ok(())
+    }
+}
+
+/// How often the orchestrator polls the state of SSM commands, EC2 instances
+/// and russula Workers.
+///
+/// ```json
+/// { "polling": { "ssm": { "min": "1s", "max": "30s" }, "russula": { "max": "5s" } } }
+/// ```
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Polling {
+    pub ssm: PollBackoff,
+    pub ec2: PollBackoff,
+    pub russula: PollBackoff,
+}
+
+impl Polling {
+    /// The (min, max) delays between polls of SSM commands.
+    pub fn ssm(&self) -> (Duration, Duration) {
+        self.ssm.delays(Duration::from_secs(30))
+    }
+
+    /// The (min, max) delays between polls of EC2 instances.
+    pub fn ec2(&self) -> (Duration, Duration) {
+        self.ec2.delays(Duration::from_secs(30))
+    }
+
+    /// The (min, max) delays between polls of russula Workers.
+    ///
+    /// The drivers are only stopped once the Coordinator notices the Workers
+    /// are done, so the delay is capped lower.
+    pub fn russula(&self) -> (Duration, Duration) {
+        self.russula.delays(Duration::from_secs(5))
+    }
+
+    fn validate(&self) -> OrchResult<()> {
+        for (name, (min, max)) in [
+            ("ssm", self.ssm()),
+            ("ec2", self.ec2()),
+            ("russula", self.russula()),
+        ] {
+            if min.is_zero() || min > max {
+                return Err(OrchError::Init {
+                    dbg: format!(
+                        "polling {} min must be greater than 0 and at most max: {:?} {:?}",
+                        name, min, max
+                    ),
+                });
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A phase of a scenario coordinated with russula.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RussulaPhase {
+    // The Workers start listening (or register)
+    Start,
+    // The Workers are configured with the run parameters
+    Ready,
+    // The drivers run, until the clients complete
+    Run,
+    // The servers and routers are stopped
+    Stop,
+}
+
+impl std::fmt::Display for RussulaPhase {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let phase = match self {
+            RussulaPhase::Start => "start",
+            RussulaPhase::Ready => "ready",
+            RussulaPhase::Run => "run",
+            RussulaPhase::Stop => "stop",
+        };
+        write!(f, "{phase}")
+    }
+}
+
+/// How long each russula phase of a scenario may take. A Coordinator which
+/// exceeds the budget of a phase aborts the scenario: the Workers are killed,
+/// the partial results and the hosts' logs collected, and the run fails.
+///
+/// The run budget defaults to an hour, or `--duration` and 10m for a soak.
+///
+/// ```json
+/// { "russula_timeouts": { "start": "2m", "ready": "5m", "run": "1h", "stop": "5m" } }
+/// ```
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RussulaTimeouts {
+    #[serde(with = "humantime_opt")]
+    pub start: Option<Duration>,
+    #[serde(with = "humantime_opt")]
+    pub ready: Option<Duration>,
+    #[serde(with = "humantime_opt")]
+    pub run: Option<Duration>,
+    #[serde(with = "humantime_opt")]
+    pub stop: Option<Duration>,
+}
+
+impl RussulaTimeouts {
+    // Time for the drivers to stop once a soak's duration elapses
+    const DURATION_MARGIN: Duration = Duration::from_secs(600);
+
+    /// The budget of `phase`.
+    pub fn budget(&self, phase: RussulaPhase) -> Duration {
+        let minutes = |minutes: u64| Duration::from_secs(60 * minutes);
+        match phase {
+            RussulaPhase::Start => self.start.unwrap_or(minutes(2)),
+            RussulaPhase::Ready => self.ready.unwrap_or(minutes(5)),
+            RussulaPhase::Run => self.run.unwrap_or(minutes(60)),
+            RussulaPhase::Stop => self.stop.unwrap_or(minutes(5)),
+        }
+    }
+
+    /// The budgets of the scenarios run for `duration`, if bounded.
+    pub fn with_duration(mut self, duration: Option<Duration>) -> Self {
+        if let (None, Some(duration)) = (self.run, duration) {
+            let run = duration + Self::DURATION_MARGIN;
+            self.run = Some(run.max(self.budget(RussulaPhase::Run)));
+        }
+        self
+    }
+
+    fn validate(&self) -> OrchResult<()> {
+        for phase in [
+            RussulaPhase::Start,
+            RussulaPhase::Ready,
+            RussulaPhase::Run,
+            RussulaPhase::Stop,
+        ] {
+            if self.budget(phase).is_zero() {
+                return Err(OrchError::Init {
+                    dbg: format!("russula_timeouts {} must be greater than 0", phase),
+                });
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The delay between polls starts at `min` and doubles while the polled state
+/// doesn't change, up to `max`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PollBackoff {
+    // Defaults to 1s
+    #[serde(with = "humantime_opt")]
+    pub min: Option<Duration>,
+    #[serde(with = "humantime_opt")]
+    pub max: Option<Duration>,
+}
+
+impl PollBackoff {
+    fn delays(&self, default_max: Duration) -> (Duration, Duration) {
+        (
+            self.min.unwrap_or(Duration::from_secs(1)),
+            self.max.unwrap_or(default_max),
+        )
+    }
+}
+
+/// Options passed through the russula workers to the `s2n-netbench-collector`
+/// which launches each driver.
+///
+/// ```json
+/// { "collector": { "interval": "100ms", "disable_bpf": true } }
+/// ```
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Collector {
+    // How often the driver is sampled. Defaults to the collector's default (1s)
+    #[serde(with = "humantime_opt")]
+    pub interval: Option<Duration>,
+    // Skip the bpf probes, which add detailed histograms to every sample, to keep
+    // the output of long runs small
+    pub disable_bpf: bool,
+}
+
+impl Collector {
+    fn validate(&self) -> OrchResult<()> {
+        if self.interval.is_some_and(|interval| interval.is_zero()) {
+            return Err(OrchError::Init {
+                dbg: "Collector interval must be greater than 0".to_string(),
+            });
+        }
+        Ok(())
+    }
+}
+
+/// The reports rendered by `s2n-netbench`, where the orchestrator runs, over
+/// the downloaded results. `report-tree` renders `report/index.html` unless
+/// disabled; if it's disabled or fails, the report links the orchestrator's
+/// summary instead.
+///
+/// ```json
+/// { "netbench_report": { "per_job": true } }
+/// ```
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct NetbenchReport {
+    // Skip s2n-netbench's reports, ex: when it isn't installed
+    pub disable: bool,
+    // Also run `s2n-netbench report` over the results of each job and driver,
+    // writing `report/netbench/<job>/<driver>.json`
+    pub per_job: bool,
+}
+
+/// Network conditions emulated on the hosts with `tc netem` while netbench is
+/// running.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Impairment {
+    // Added to each packet. ex: 20ms
+    #[serde(with = "humantime_opt")]
+    pub delay: Option<Duration>,
+    // Random variation of the delay. Requires `delay`
+    #[serde(with = "humantime_opt")]
+    pub jitter: Option<Duration>,
+    // Percentage of packets dropped. ex: 0.1
+    pub loss_pct: Option<f64>,
+    // Bandwidth cap in tc units. ex: 100mbit
+    pub rate: Option<String>,
+}
+
+impl Impairment {
+    fn validate(&self) -> OrchResult<()> {
+        if self.jitter.is_some() && self.delay.is_none() {
+            return Err(OrchError::Init {
+                dbg: "Impairment jitter requires a delay".to_string(),
+            });
+        }
+        if let Some(loss_pct) = self.loss_pct {
+            if !(0.0..=100.0).contains(&loss_pct) {
+                return Err(OrchError::Init {
+                    dbg: format!("Impairment loss_pct must be 0-100: {}", loss_pct),
+                });
+            }
+        }
+        if let Some(rate) = &self.rate {
+            let valid = rate
+                .trim_end_matches(char::is_alphabetic)
+                .parse::<u64>()
+                .is_ok();
+            if !valid {
+                return Err(OrchError::Init {
+                    dbg: format!("Impairment rate must be a tc rate (ex: 100mbit): {}", rate),
+                });
+            }
+        }
+        Ok(())
+    }
+
+    /// The `tc netem` arguments. ex: delay 20ms 5ms loss 0.1% rate 100mbit
+    pub fn netem_args(&self) -> String {
+        let mut args = Vec::new();
+        if let Some(delay) = self.delay {
+            args.push(format!("delay {}ms", delay.as_millis()));
+            if let Some(jitter) = self.jitter {
+                args.push(format!("{}ms", jitter.as_millis()));
+            }
+        }
+        if let Some(loss_pct) = self.loss_pct {
+            args.push(format!("loss {}%", loss_pct));
+        }
+        if let Some(rate) = &self.rate {
+            args.push(format!("rate {}", rate));
+        }
+        args.join(" ")
+    }
+}
+
+// A dotted path under /proc/sys. ex: net.ipv4.tcp_congestion_control
+fn is_sysctl_key(key: &str) -> bool {
+    let parts: Vec<&str> = key.split('.').collect();
+    parts.len() > 1
+        && parts.iter().all(|part| {
+            !part.is_empty()
+                && part
+                    .chars()
+                    .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_')
+        })
+}
+
+fn is_ipv4_cidr(cidr: &str) -> bool {
+    match cidr.split_once('/') {
+        Some((ip, prefix)) => {
+            ip.parse::<Ipv4Addr>().is_ok() && prefix.parse::<u8>().is_ok_and(|prefix| prefix <= 32)
+        }
+        None => false,
+    }
+}
+
+// (De)serialize an optional duration in the humantime format. ex: 20ms
+mod humantime_opt {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(
+        duration: &Option<Duration>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        match duration {
+            Some(duration) => {
+                serializer.serialize_some(&humantime::format_duration(*duration).to_string())
+            }
+            None => serializer.serialize_none(),
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Option<Duration>, D::Error> {
+        Option::<String>::deserialize(deserializer)?
+            .map(|duration| humantime::parse_duration(&duration))
+            .transpose()
+            .map_err(serde::de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn impairment_netem_args() {
+        let config: OrchestratorConfig = serde_json::from_str(
+            r#"{
+                "impairment": { "delay": "20ms", "jitter": "5ms", "loss_pct": 0.1 },
+                "scenario_impairments": { "incast.json": { "rate": "100mbit" } }
+            }"#,
+        )
+        .unwrap();
+        config.validate().unwrap();
+        assert_eq!(
+            config.impairment.unwrap().netem_args(),
+            "delay 20ms 5ms loss 0.1%"
+        );
+        assert_eq!(
+            config.scenario_impairments["incast.json"].netem_args(),
+            "rate 100mbit"
+        );
+
+        let jitter_only = Impairment {
+            jitter: Some(Duration::from_millis(5)),
+            ..Default::default()
+        };
+        assert!(jitter_only.validate().is_err());
+    }
+
+    #[test]
+    fn ingress_cidrs() {
+        assert!(is_ipv4_cidr("203.0.113.7/32"));
+        assert!(!is_ipv4_cidr("203.0.113.7"));
+        assert!(!is_ipv4_cidr("203.0.113.7/33"));
+        assert!(!is_ipv4_cidr("::1/128"));
+    }
+
+    #[test]
+    fn budget() {
+        let config: OrchestratorConfig = serde_json::from_str(
+            r#"{ "budget": { "max_instances": 4, "allowed_instance_types": ["c5.4xlarge"] } }"#,
+        )
+        .unwrap();
+        config.budget.check(4, "c5.4xlarge").unwrap();
+        assert!(config.budget.check(5, "c5.4xlarge").is_err());
+        assert!(config.budget.check(1, "c5n.18xlarge").is_err());
+
+        // the hosts run until they're shutdown after STATE.shutdown_min
+        let budget = Budget {
+            max_instance_hours: Some(2.0 * STATE.shutdown_min as f64 / 60.0),
+            ..Default::default()
+        };
+        budget.check(2, "c5.4xlarge").unwrap();
+        assert!(budget.check(3, "c5.4xlarge").is_err());
+        assert!(Budget::default().check(100, "c5n.18xlarge").is_ok());
+    }
+
+    #[test]
+    fn private_network() {
+        let config: OrchestratorConfig = serde_json::from_str(
+            r#"{ "private_network": { "subnet_tag": ["tag:aws-cdk:subnet-type", "Private"] } }"#,
+        )
+        .unwrap();
+        config.validate().unwrap();
+        assert_eq!(
+            config.private_network.unwrap().subnet_tag,
+            ("tag:aws-cdk:subnet-type".to_string(), "Private".to_string())
+        );
+    }
+
+    #[test]
+    fn dedicated_vpc() {
+        let config: OrchestratorConfig =
+            serde_json::from_str(r#"{ "dedicated_vpc": {} }"#).unwrap();
+        config.validate().unwrap();
+        assert_eq!(config.dedicated_vpc.unwrap().cidr, "10.0.0.0/16");
+
+        let config: OrchestratorConfig =
+            serde_json::from_str(r#"{ "dedicated_vpc": { "cidr": "10.0.0.0/8" } }"#).unwrap();
+        assert!(config.validate().is_err());
+
+        let config: OrchestratorConfig = serde_json::from_str(
+            r#"{
+                "dedicated_vpc": { "cidr": "172.16.0.0/24" },
+                "private_network": { "subnet_tag": ["tag:aws-cdk:subnet-type", "Private"] }
+            }"#,
+        )
+        .unwrap();
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn windows_clients() {
+        let config: OrchestratorConfig =
+            serde_json::from_str(r#"{ "client_os": "windows" }"#).unwrap();
+        config.validate().unwrap();
+        assert_eq!(config.client_os, HostOs::Windows);
+        assert_eq!(OrchestratorConfig::default().client_os, HostOs::Linux);
+
+        let config: OrchestratorConfig =
+            serde_json::from_str(r#"{ "client_os": "windows", "mtu": 9001 }"#).unwrap();
+        assert!(config.validate().is_err());
+        assert!(serde_json::from_str::<OrchestratorConfig>(r#"{ "client_os": "macos" }"#).is_err());
+    }
+
+    #[test]
+    fn linux_distro() {
+        let config: OrchestratorConfig = serde_json::from_str(r#"{ "distro": "ubuntu" }"#).unwrap();
+        assert_eq!(config.distro, LinuxDistro::Ubuntu);
+        assert_eq!(OrchestratorConfig::default().distro, LinuxDistro::Al2023);
+        assert!(serde_json::from_str::<OrchestratorConfig>(r#"{ "distro": "centos" }"#).is_err());
+    }
+
+    #[test]
+    fn sysctl() {
+        let config: OrchestratorConfig = serde_json::from_str(
+            r#"{ "sysctl": {
+                "net.core.rmem_max": "26214400",
+                "net.ipv4.tcp_rmem": "4096 131072 26214400",
+                "net.ipv4.tcp_congestion_control": "bbr"
+            } }"#,
+        )
+        .unwrap();
+        config.validate().unwrap();
+        assert_eq!(config.sysctl["net.ipv4.tcp_congestion_control"], "bbr");
+        assert!(OrchestratorConfig::default().sysctl.is_empty());
+
+        for sysctl in [
+            r#"{ "rmem_max": "1" }"#,
+            r#"{ "net..rmem_max": "1" }"#,
+            r#"{ "net/core/rmem_max": "1" }"#,
+            r#"{ "net.core.rmem_max": "" }"#,
+            r#"{ "net.core.rmem_max": "1; reboot" }"#,
+            r#"{ "net.core.rmem_max": "'1'" }"#,
+        ] {
+            let config: OrchestratorConfig =
+                serde_json::from_str(&format!(r#"{{ "sysctl": {sysctl} }}"#)).unwrap();
+            assert!(config.validate().is_err(), "{}", sysctl);
+        }
+    }
+
+    #[test]
+    fn notifications() {
+        let config: OrchestratorConfig = serde_json::from_str(
+            r#"{ "notifications": [
+                { "type": "slack" },
+                { "type": "sns", "topic_arn": "arn:aws:sns:us-west-2:123456789012:netbench" },
+                { "type": "webhook", "url": "https://example.com/netbench" }
+            ] }"#,
+        )
+        .unwrap();
+        config.validate().unwrap();
+        assert_eq!(
+            config.notifications[0],
+            Notification::Slack {
+                webhook_env: "SLACK_WEBHOOK_URL".to_string()
+            }
+        );
+        assert_eq!(
+            config.notifications[2].to_string(),
+            "webhook https://example.com/netbench"
+        );
+
+        let sns = Notification::Sns {
+            topic_arn: "netbench".to_string(),
+        };
+        assert!(sns.validate().is_err());
+        assert!(serde_json::from_str::<Notification>(r#"{ "type": "email" }"#).is_err());
+    }
+
+    #[test]
+    fn iperf3() {
+        let config: OrchestratorConfig = serde_json::from_str(r#"{ "iperf3": {} }"#).unwrap();
+        config.validate().unwrap();
+        assert_eq!(config.iperf3, Some(Iperf3::default()));
+
+        let iperf3: Iperf3 = serde_json::from_str(
+            r#"{ "protocols": ["tcp", "udp"], "duration": "30s", "parallel": 4, "udp_bitrate": "0" }"#,
+        )
+        .unwrap();
+        iperf3.validate().unwrap();
+        assert_eq!(
+            iperf3.protocols,
+            vec![Iperf3Protocol::Tcp, Iperf3Protocol::Udp]
+        );
+        assert_eq!(iperf3.duration, Some(Duration::from_secs(30)));
+
+        for invalid in [
+            r#"{ "protocols": [] }"#,
+            r#"{ "parallel": 0 }"#,
+            r#"{ "duration": "100ms" }"#,
+            r#"{ "udp_bitrate": "10Gbps; reboot" }"#,
+        ] {
+            let iperf3: Iperf3 = serde_json::from_str(invalid).unwrap();
+            assert!(iperf3.validate().is_err(), "{}", invalid);
+        }
+        assert!(serde_json::from_str::<Iperf3>(r#"{ "protocols": ["sctp"] }"#).is_err());
+    }
+
+    #[test]
+    fn calibration() {
+        let config: OrchestratorConfig =
+            serde_json::from_str(r#"{ "calibration": { "max_deviation_pct": 10 } }"#).unwrap();
+        config.validate().unwrap();
+        let calibration = config.calibration.unwrap();
+        assert_eq!(calibration.duration, Some(Duration::from_secs(3)));
+        assert_eq!(calibration.max_deviation_pct, 10.0);
+
+        for invalid in [
+            r#"{ "max_deviation_pct": 0 }"#,
+            r#"{ "history": 0 }"#,
+            r#"{ "duration": "10ms" }"#,
+        ] {
+            let calibration: Calibration = serde_json::from_str(invalid).unwrap();
+            assert!(calibration.validate().is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn polling() {
+        let config: OrchestratorConfig = serde_json::from_str(
+            r#"{ "polling": { "ssm": { "max": "1m" }, "russula": { "min": "500ms" } } }"#,
+        )
+        .unwrap();
+        config.validate().unwrap();
+        let secs = Duration::from_secs;
+        assert_eq!(config.polling.ssm(), (secs(1), secs(60)));
+        assert_eq!(config.polling.ec2(), (secs(1), secs(30)));
+        assert_eq!(
+            config.polling.russula(),
+            (Duration::from_millis(500), secs(5))
+        );
+
+        for invalid in [
+            r#"{ "ec2": { "min": "0s" } }"#,
+            r#"{ "ssm": { "min": "1m", "max": "30s" } }"#,
+        ] {
+            let polling: Polling = serde_json::from_str(invalid).unwrap();
+            assert!(polling.validate().is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn russula_timeouts() {
+        let config: OrchestratorConfig =
+            serde_json::from_str(r#"{ "russula_timeouts": { "start": "30s", "run": "2h" } }"#)
+                .unwrap();
+        config.validate().unwrap();
+        let timeouts = config.russula_timeouts;
+        let secs = Duration::from_secs;
+        assert_eq!(timeouts.budget(RussulaPhase::Start), secs(30));
+        assert_eq!(timeouts.budget(RussulaPhase::Ready), secs(300));
+        assert_eq!(timeouts.budget(RussulaPhase::Run), secs(7200));
+        // a configured run budget isn't extended
+        assert_eq!(
+            timeouts
+                .with_duration(Some(secs(3 * 3600)))
+                .budget(RussulaPhase::Run),
+            secs(7200)
+        );
+
+        let soak = RussulaTimeouts::default().with_duration(Some(secs(3 * 3600)));
+        assert_eq!(soak.budget(RussulaPhase::Run), secs(3 * 3600 + 600));
+        let short = RussulaTimeouts::default().with_duration(Some(secs(60)));
+        assert_eq!(short.budget(RussulaPhase::Run), secs(3600));
+
+        let invalid: RussulaTimeouts = serde_json::from_str(r#"{ "stop": "0s" }"#).unwrap();
+        assert!(invalid.validate().is_err());
+        assert!(serde_json::from_str::<RussulaTimeouts>(r#"{ "kill": "1m" }"#).is_err());
+    }
+
+    #[test]
+    fn host_groups() {
+        let config: OrchestratorConfig = serde_json::from_str(
+            r#"{ "host_groups": [
+                { "name": "client-large", "role": "client", "count": 2, "instance_type": "c5n.18xlarge" },
+                { "name": "observer", "role": "observer", "count": 1 }
+            ] }"#,
+        )
+        .unwrap();
+        config.validate().unwrap();
+        assert_eq!(config.host_groups[0].role, EndpointType::Client);
+        assert_eq!(config.host_groups[1].role, EndpointType::Observer);
+        assert_eq!(config.host_groups[1].instance_type, None);
+
+        for invalid in [
+            r#"{ "host_groups": [{ "name": "Observer", "role": "observer", "count": 1 }] }"#,
+            r#"{ "host_groups": [{ "name": "observer", "role": "observer", "count": 0 }] }"#,
+            r#"{ "host_groups": [
+                { "name": "observer", "role": "observer", "count": 1 },
+                { "name": "observer", "role": "server", "count": 1 }
+            ] }"#,
+        ] {
+            let config: OrchestratorConfig = serde_json::from_str(invalid).unwrap();
+            assert!(config.validate().is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn build_cache() {
+        let config: OrchestratorConfig =
+            serde_json::from_str(r#"{ "build_cache": { "bucket": "netbench-sccache" } }"#).unwrap();
+        config.validate().unwrap();
+        let build_cache = config.build_cache.unwrap();
+        assert_eq!(build_cache.bucket, "netbench-sccache");
+        assert_eq!(build_cache.prefix, "sccache");
+        assert_eq!(build_cache.region, STATE.region);
+
+        let config: OrchestratorConfig =
+            serde_json::from_str(r#"{ "build_cache": { "prefix": "cache; rm -rf /" } }"#).unwrap();
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn secrets() {
+        let config: OrchestratorConfig = serde_json::from_str(
+            r#"{ "secrets": [
+                { "name": "tls-key", "secret_id": "arn:aws:secretsmanager:us-west-1:123456789012:secret:netbench/tls-key-AbCdEf" },
+                { "name": "token", "file": "token.txt" }
+            ] }"#,
+        )
+        .unwrap();
+        config.validate().unwrap();
+        assert_eq!(config.secrets[1].file, Some(PathBuf::from("token.txt")));
+
+        for invalid in [
+            r#"{ "secrets": [{ "name": "token" }] }"#,
+            r#"{ "secrets": [{ "name": "token", "secret_id": "token", "file": "token.txt" }] }"#,
+            r#"{ "secrets": [{ "name": "../token", "file": "token.txt" }] }"#,
+            r#"{ "secrets": [{ "name": "token", "secret_id": "token $(id)" }] }"#,
+            r#"{ "secrets": [
+                { "name": "token", "file": "token.txt" },
+                { "name": "token", "secret_id": "token" }
+            ] }"#,
+        ] {
+            let config: OrchestratorConfig = serde_json::from_str(invalid).unwrap();
+            assert!(config.validate().is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn storage() {
+        let config: OrchestratorConfig = serde_json::from_str(
+            r#"{ "storage": { "volume_type": "gp3", "iops": 6000, "throughput": 500, "instance_store": true } }"#,
+        )
+        .unwrap();
+        config.validate().unwrap();
+        let mut storage = config.storage;
+        assert_eq!(storage.root_volume_gb(), 50);
+        storage.for_captures();
+        assert_eq!(storage.root_volume_gb(), 200);
+        let mut sized = Storage {
+            size_gb: Some(100),
+            ..Default::default()
+        };
+        sized.for_captures();
+        assert_eq!(sized.root_volume_gb(), 100);
+
+        for invalid in [
+            r#"{ "storage": { "size_gb": 4 } }"#,
+            r#"{ "storage": { "iops": 3000 } }"#,
+            r#"{ "storage": { "volume_type": "io2" } }"#,
+            r#"{ "storage": { "volume_type": "gp3", "iops": 20000 } }"#,
+            r#"{ "storage": { "volume_type": "io1", "iops": 3000, "throughput": 500 } }"#,
+        ] {
+            let config: OrchestratorConfig = serde_json::from_str(invalid).unwrap();
+            assert!(config.validate().is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn tls() {
+        let config: OrchestratorConfig = serde_json::from_str(r#"{ "tls": {} }"#).unwrap();
+        config.validate().unwrap();
+        assert_eq!(config.tls.unwrap().subject_alt_names, vec!["localhost"]);
+
+        for invalid in [
+            r#"{ "tls": { "subject_alt_names": [] } }"#,
+            r#"{ "tls": { "subject_alt_names": ["local host"] } }"#,
+        ] {
+            let config: OrchestratorConfig = serde_json::from_str(invalid).unwrap();
+            assert!(config.validate().is_err(), "{}", invalid);
+        }
+    }
+}
@@ -0,0 +1,217 @@
+// Copyright Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::{
+    config::RussulaRegistration,
+    error::{OrchError, OrchResult},
+};
+use serde_json::Value;
+use std::{net::Ipv4Addr, sync::OnceLock, time::Duration};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpStream,
+};
+use tracing::{debug, info};
+
+// Set by the ECS agent in the containers of a task
+const ECS_METADATA_ENV: &str = "ECS_CONTAINER_METADATA_URI_V4";
+const METADATA_TIMEOUT: Duration = Duration::from_secs(1);
+
+static DEPLOYMENT: OnceLock<Deployment> = OnceLock::new();
+
+/// Where the orchestrator itself runs.
+///
+/// Within AWS the SDK picks up the task or instance role from the default
+/// credential chain, so only the region and address of the orchestrator need
+/// to be detected.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Deployment {
+    pub platform: Platform,
+    pub region: Option<String>,
+    pub private_ip: Option<Ipv4Addr>,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Platform {
+    // ex: a developer machine or a CI runner outside of AWS
+    #[default]
+    Local,
+    Ecs,
+    Ec2,
+}
+
+impl Platform {
+    pub fn as_str(&self) -> &str {
+        match self {
+            Platform::Local => "local",
+            Platform::Ecs => "ecs",
+            Platform::Ec2 => "ec2",
+        }
+    }
+}
+
+impl Deployment {
+    /// Detect the deployment from the ECS task metadata, or else from the EC2
+    /// instance metadata (IMDS). Falls back to a local deployment.
+    pub async fn detect() -> Self {
+        let env_region = ["AWS_REGION", "AWS_DEFAULT_REGION"]
+            .into_iter()
+            .find_map(|var| std::env::var(var).ok().filter(|region| !region.is_empty()));
+        let mut deployment = match std::env::var(ECS_METADATA_ENV) {
+            Ok(uri) => ecs_task_metadata(&uri)
+                .await
+                .map(|task| Deployment::from_ecs_task(&task))
+                .unwrap_or(Deployment {
+                    platform: Platform::Ecs,
+                    ..Default::default()
+                }),
+            Err(_) => Deployment::from_imds().await.unwrap_or_default(),
+        };
+        if env_region.is_some() {
+            deployment.region = env_region;
+        }
+        deployment
+    }
+
+    fn from_ecs_task(task: &Value) -> Self {
+        // us-east-1a -> us-east-1
+        let region = task["AvailabilityZone"]
+            .as_str()
+            .map(|zone| zone.trim_end_matches(|c: char| c.is_ascii_lowercase()))
+            .filter(|region| !region.is_empty())
+            .map(String::from);
+        let private_ip = task["Containers"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .flat_map(|container| container["Networks"].as_array().into_iter().flatten())
+            .flat_map(|network| network["IPv4Addresses"].as_array().into_iter().flatten())
+            .find_map(|ip| ip.as_str()?.parse().ok());
+        Deployment {
+            platform: Platform::Ecs,
+            region,
+            private_ip,
+        }
+    }
+
+    async fn from_imds() -> Option<Self> {
+        let client = aws_config::imds::Client::builder()
+            .connect_timeout(METADATA_TIMEOUT)
+            .read_timeout(METADATA_TIMEOUT)
+            .max_attempts(1)
+            .build()
+            .await
+            .ok()?;
+        let region = client
+            .get("/latest/meta-data/placement/region")
+            .await
+            .map_err(|err| debug!("Not running on EC2: {}", err))
+            .ok()?;
+        let private_ip = client
+            .get("/latest/meta-data/local-ipv4")
+            .await
+            .ok()
+            .and_then(|ip| ip.trim().parse().ok());
+        Some(Deployment {
+            platform: Platform::Ec2,
+            region: Some(region.trim().to_string()),
+            private_ip,
+        })
+    }
+}
+
+/// Record the deployment of the orchestrator and export its region to the
+/// local `aws` cli, which unlike the SDK doesn't read it from the instance
+/// metadata.
+pub fn init(deployment: Deployment) {
+    info!(
+        "Orchestrator deployment: {} region: {:?} private ip: {:?}",
+        deployment.platform.as_str(),
+        deployment.region,
+        deployment.private_ip
+    );
+    if let Some(region) = &deployment.region {
+        if std::env::var_os("AWS_DEFAULT_REGION").is_none() {
+            std::env::set_var("AWS_DEFAULT_REGION", region);
+        }
+    }
+    DEPLOYMENT.get_or_init(|| deployment);
+}
+
+/// The deployment recorded by `init`, or a local deployment.
+pub fn deployment() -> &'static Deployment {
+    DEPLOYMENT.get_or_init(Deployment::default)
+}
+
+/// The ip the russula Workers dial to register with the Coordinators, if they
+/// register. Defaults to the orchestrator's private ip.
+pub fn coordinator_ip(registration: Option<&RussulaRegistration>) -> OrchResult<Option<Ipv4Addr>> {
+    let Some(registration) = registration else {
+        return Ok(None);
+    };
+    registration
+        .coordinator_ip
+        .or(deployment().private_ip)
+        .map(Some)
+        .ok_or(OrchError::Init {
+            dbg: "russula_registration needs a coordinator_ip when the orchestrator runs outside of AWS"
+                .to_string(),
+        })
+}
+
+async fn ecs_task_metadata(uri: &str) -> Option<Value> {
+    // ex: http://169.254.170.2/v4/<id>
+    let (host, path) = uri.strip_prefix("http://")?.split_once('/')?;
+    let get = async {
+        let mut stream = TcpStream::connect(host).await?;
+        stream
+            .write_all(
+                format!("GET /{path}/task HTTP/1.0\r\nHost: {host}\r\nConnection: close\r\n\r\n")
+                    .as_bytes(),
+            )
+            .await?;
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await?;
+        Ok::<String, std::io::Error>(response)
+    };
+    let response = tokio::time::timeout(METADATA_TIMEOUT, get)
+        .await
+        .ok()?
+        .map_err(|err| debug!("Failed to get the ECS task metadata: {}", err))
+        .ok()?;
+    let (_headers, body) = response.split_once("\r\n\r\n")?;
+    serde_json::from_str(body).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn ecs_deployment() {
+        let task = json!({
+            "Cluster": "netbench",
+            "AvailabilityZone": "us-east-1a",
+            "Containers": [{
+                "Name": "orchestrator",
+                "Networks": [{ "NetworkMode": "awsvpc", "IPv4Addresses": ["10.0.2.106"] }]
+            }]
+        });
+        assert_eq!(
+            Deployment::from_ecs_task(&task),
+            Deployment {
+                platform: Platform::Ecs,
+                region: Some("us-east-1".to_string()),
+                private_ip: Some(Ipv4Addr::new(10, 0, 2, 106)),
+            }
+        );
+        assert_eq!(
+            Deployment::from_ecs_task(&json!({})),
+            Deployment {
+                platform: Platform::Ecs,
+                ..Default::default()
+            }
+        );
+    }
+}
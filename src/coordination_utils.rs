@@ -2,207 +2,828 @@
 // SPDX-License-Identifier: Apache-2.0
 
 use crate::{
-    ec2_utils::InfraDetail,
+    config::{RussulaPhase, RussulaTimeouts},
+    dashboard::{live, timeline},
+    ec2_utils::{EndpointType, HostOs, InfraDetail, InstanceDetail},
+    error::{OrchError, OrchResult},
+    poll::{self, Backoff},
     poll_ssm_results,
     russula::{
         self,
-        netbench::{client, server},
-        RussulaBuilder,
+        netbench::{client, driver_short_name, router, server, ProcessExit, RunParams},
+        Protocol, RussulaBuilder, RussulaError, RussulaEvent, RussulaEvents, RussulaResult,
     },
-    ssm_utils, NetbenchDriver, Scenario, STATE,
+    ssm_utils::{
+        self, command_output, list_invocation_status, port_forward::PortForward, InvocationStatus,
+        WorkerOptions,
+    },
+    NetbenchDriver, Scenario, STATE,
 };
 use aws_sdk_ssm::operation::send_command::SendCommandOutput;
-use core::time::Duration;
+use core::{future::Future, task::Poll, time::Duration};
+use futures::{FutureExt, StreamExt};
+use serde::{Deserialize, Serialize};
 use std::{
-    collections::BTreeSet,
-    net::{IpAddr, SocketAddr},
+    collections::{BTreeMap, BTreeSet},
+    net::{IpAddr, Ipv4Addr, SocketAddr},
+    str::FromStr,
 };
-use tracing::{debug, info};
+use tracing::{debug, info, warn};
+
+// The lines of the Workers' SSM output included in the error if they don't start
+const WORKER_OUTPUT_LINES: usize = 10;
+
+/// Record that a coordinator reached a state on the run's timeline.
+fn transition(coordinator: &str, state: &str) {
+    timeline::record(timeline::Event::Transition {
+        coordinator: coordinator.to_string(),
+        state: state.to_string(),
+    });
+}
+
+/// Record the progress of a coordinator's peers as it happens.
+fn record_event(host_group: &str, event: RussulaEvent) {
+    match event {
+        RussulaEvent::PeerConnected { peer } => debug!(%peer, "{} worker connected", host_group),
+        RussulaEvent::PeerTransitioned { peer, from, to } => {
+            debug!(%peer, "{} coordinator {} -> {}", host_group, from, to);
+            transition(&format!("{host_group} {peer}"), &to);
+        }
+        RussulaEvent::PeerProgress { peer, progress } => {
+            info!(%peer, "{} worker progress. {}", host_group, progress);
+            live::record(host_group, peer, progress);
+        }
+        RussulaEvent::PeerError {
+            peer,
+            error,
+            excluded,
+        } => warn!(%peer, excluded, "{} worker failed: {}", host_group, error),
+    }
+}
+
+/// The error of a Coordinator which exceeded the budget of `phase`, naming the
+/// hosts of its `pending` peers.
+fn timed_out(
+    host_group: &str,
+    phase: RussulaPhase,
+    timeout: Duration,
+    pending: &[SocketAddr],
+    hosts: &BTreeMap<SocketAddr, String>,
+) -> OrchError {
+    let hosts = pending
+        .iter()
+        .map(|peer| match hosts.get(peer) {
+            Some(instance_id) => instance_id.clone(),
+            None => peer.to_string(),
+        })
+        .collect();
+    OrchError::RussulaTimeout {
+        endpoint: format!("{host_group} coordinator"),
+        phase: phase.to_string(),
+        timeout,
+        hosts,
+        dbg: String::new(),
+    }
+}
+
+/// Drive a coordinator with `progress`, one of its `run_till_*`, recording its
+/// events as they are emitted. Pending if `timeout` elapses first.
+///
+/// The Workers' SSM command is checked concurrently so a Worker which fails
+/// outside of russula (ex: its build failed) fails the scenario.
+async fn drive_coord(
+    host_group: &str,
+    ssm_client: &aws_sdk_ssm::Client,
+    worker: &SendCommandOutput,
+    events: &mut RussulaEvents,
+    timeout: Duration,
+    progress: impl Future<Output = RussulaResult<()>>,
+) -> OrchResult<Poll<()>> {
+    let command_id = ssm_utils::command_id(worker)?;
+    let mut backoff = Backoff::ssm();
+    let poll_worker = tokio::time::sleep(Duration::ZERO);
+    let deadline = tokio::time::sleep(timeout);
+    tokio::pin!(progress, poll_worker, deadline);
+    loop {
+        tokio::select! {
+            result = &mut progress => {
+                // the events of the last poll
+                while let Some(Some(event)) = events.next().now_or_never() {
+                    record_event(host_group, event);
+                }
+                return result
+                    .map(|()| Poll::Ready(()))
+                    .map_err(|err| OrchError::russula(&format!("{host_group} coordinator"), err));
+            }
+            _ = &mut deadline => {
+                warn!("{} coordinator timed out after {:?}", host_group, timeout);
+                return Ok(Poll::Pending);
+            }
+            Some(event) = events.next() => record_event(host_group, event),
+            _ = &mut poll_worker => {
+                let poll = poll_ssm_results(host_group, ssm_client, command_id).await?;
+                debug!("{} Russula!: Worker {:?}", host_group, poll);
+                poll_worker
+                    .as_mut()
+                    .reset(tokio::time::Instant::now() + backoff.next_delay());
+            }
+        }
+    }
+}
+
+/// A netbench driver which exited with an error before it was stopped by its
+/// Worker.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct DriverFailure {
+    pub host_group: String,
+    pub endpoint: SocketAddr,
+    pub scenario: String,
+    pub exit: ProcessExit,
+}
+
+impl std::fmt::Display for DriverFailure {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} driver on {} failed for scenario {}. {}",
+            self.host_group, self.endpoint, self.scenario, self.exit
+        )
+    }
+}
+
+impl DriverFailure {
+    fn from_exits(
+        host_group: &str,
+        scenario: &str,
+        exits: Vec<(SocketAddr, ProcessExit)>,
+    ) -> Vec<Self> {
+        exits
+            .into_iter()
+            .filter(|(_endpoint, exit)| exit.is_failure())
+            .map(|(endpoint, exit)| {
+                let failure = DriverFailure {
+                    host_group: host_group.to_string(),
+                    endpoint,
+                    scenario: scenario.to_string(),
+                    exit,
+                };
+                warn!("{}", failure);
+                failure
+            })
+            .collect()
+    }
+
+    /// Fail the run rather than producing empty results.
+    pub fn to_error(failures: &[DriverFailure]) -> OrchError {
+        let endpoints: Vec<String> = failures
+            .iter()
+            .map(|failure| failure.endpoint.to_string())
+            .collect();
+        OrchError::Russula {
+            endpoint: endpoints.join(","),
+            dbg: failures
+                .iter()
+                .map(|failure| failure.to_string())
+                .collect::<Vec<String>>()
+                .join("\n"),
+        }
+    }
+}
+
+/// A host which was unreachable or failed, and was excluded from a scenario by
+/// the [`FailurePolicy`](russula::FailurePolicy).
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct DegradedPeer {
+    pub host_group: String,
+    pub endpoint: SocketAddr,
+    pub scenario: String,
+    pub dbg: String,
+}
+
+impl std::fmt::Display for DegradedPeer {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} host {} was excluded from scenario {}. {}",
+            self.host_group, self.endpoint, self.scenario, self.dbg
+        )
+    }
+}
+
+impl DegradedPeer {
+    fn from_failed_peers(
+        host_group: &str,
+        scenario: &str,
+        failed_peers: &BTreeMap<SocketAddr, String>,
+    ) -> Vec<Self> {
+        failed_peers
+            .iter()
+            .map(|(endpoint, dbg)| DegradedPeer {
+                host_group: host_group.to_string(),
+                endpoint: *endpoint,
+                scenario: scenario.to_string(),
+                dbg: dbg.clone(),
+            })
+            .collect()
+    }
+}
+
+/// The addresses used by the Coordinators to reach the russula Workers.
+///
+/// Hosts in a private network don't have public ips, so russula is relayed
+/// through an SSM port forwarding session to each host. Workers which register
+/// with the Coordinators are known by their private ip instead.
+pub struct RussulaAddrs {
+    // instance id -> session
+    port_forwards: BTreeMap<String, PortForward>,
+    registration: bool,
+}
+
+impl RussulaAddrs {
+    pub async fn new(
+        infra: &InfraDetail,
+        private_network: bool,
+        registration: bool,
+    ) -> OrchResult<Self> {
+        let mut port_forwards = BTreeMap::new();
+        if private_network && !registration {
+            for instance in infra
+                .servers
+                .iter()
+                .chain(infra.clients.iter())
+                .chain(infra.routers.iter())
+            {
+                let port_forward = PortForward::start(
+                    &instance.instance_id,
+                    instance.endpoint_type.russula_port(),
+                )
+                .await?;
+                port_forwards.insert(instance.instance_id.clone(), port_forward);
+            }
+        }
+        Ok(RussulaAddrs {
+            port_forwards,
+            registration,
+        })
+    }
+
+    fn addrs(&self, instances: &[InstanceDetail]) -> Vec<SocketAddr> {
+        instances
+            .iter()
+            .map(
+                |instance| match self.port_forwards.get(&instance.instance_id) {
+                    Some(port_forward) => port_forward.local_addr,
+                    None => SocketAddr::new(
+                        IpAddr::from_str(match self.registration {
+                            true => &instance.private_ip,
+                            false => &instance.ip,
+                        })
+                        .unwrap(),
+                        instance.endpoint_type.russula_port(),
+                    ),
+                },
+            )
+            .collect()
+    }
+
+    /// The instance id of the host behind each addr.
+    fn hosts(&self, instances: &[InstanceDetail]) -> BTreeMap<SocketAddr, String> {
+        self.addrs(instances)
+            .into_iter()
+            .zip(instances.iter().map(|host| host.instance_id.clone()))
+            .collect()
+    }
+
+    /// The addr a Coordinator listens on for the Workers of `endpoint_type` to
+    /// register, if they register.
+    fn registration_addr(&self, endpoint_type: EndpointType) -> Option<SocketAddr> {
+        self.registration.then(|| {
+            SocketAddr::new(
+                IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+                endpoint_type.russula_port(),
+            )
+        })
+    }
+}
 
 pub struct ServerNetbenchRussula {
     worker: SendCommandOutput,
+    events: RussulaEvents,
     coord: russula::Russula<server::CoordProtocol>,
+    scenario: String,
+    hosts: BTreeMap<SocketAddr, String>,
+    timeouts: RussulaTimeouts,
 }
 
 impl ServerNetbenchRussula {
     pub async fn new(
         ssm_client: &aws_sdk_ssm::Client,
         infra: &InfraDetail,
+        russula_addrs: &RussulaAddrs,
         instance_ids: Vec<String>,
         scenario: &Scenario,
         driver: &NetbenchDriver,
-    ) -> Self {
+        worker_opts: &WorkerOptions,
+    ) -> OrchResult<Self> {
         // server run commands
         debug!("starting server worker");
 
         let worker =
-            ssm_utils::server::run_russula_worker(ssm_client, instance_ids, driver, scenario).await;
+            ssm_utils::server::run_russula_worker(ssm_client, instance_ids, worker_opts).await?;
 
-        // wait for worker to start
-        tokio::time::sleep(Duration::from_secs(5)).await;
-
-        // server coord
-        debug!("starting server coordinator");
-        let coord = server_coord(infra.server_ips()).await;
-        ServerNetbenchRussula { worker, coord }
+        let params = RunParams {
+            driver: Some(driver.driver_name.clone()),
+            scenario: Some(scenario.name.clone()),
+            netbench_port: Some(STATE.netbench_port),
+            driver_instances: Some(worker_opts.driver_instances),
+            warmup: Some(worker_opts.warmup),
+            stream_to: stream_prefix(worker_opts, driver),
+            stream_interval: worker_opts.stream_interval,
+            ..Default::default()
+        };
+        let hosts = russula_addrs.hosts(&infra.servers);
+        let (coord, events) = start_coord(
+            "server",
+            ssm_client,
+            &worker,
+            &hosts,
+            russula_addrs.registration_addr(EndpointType::Server),
+            server::CoordProtocol::new().with_params(params),
+            worker_opts,
+        )
+        .await?;
+        Ok(ServerNetbenchRussula {
+            worker,
+            events,
+            coord,
+            scenario: scenario.name.clone(),
+            hosts,
+            timeouts: worker_opts.russula_timeouts,
+        })
     }
 
-    pub async fn wait_workers_running(&mut self, ssm_client: &aws_sdk_ssm::Client) {
-        loop {
-            let poll_worker = poll_ssm_results(
-                "server",
-                ssm_client,
-                self.worker.command().unwrap().command_id().unwrap(),
-            )
-            .await
-            .unwrap();
-
-            let poll_coord_worker_running = self.coord.poll_worker_running().await.unwrap();
+    /// The server drivers which exited with an error. Only populated once the
+    /// Workers are done.
+    pub fn driver_failures(&self) -> Vec<DriverFailure> {
+        DriverFailure::from_exits("server", &self.scenario, self.coord.worker_exits())
+    }
 
-            debug!(
-                "Server Russula!: poll worker_running. Coordinator: {:?} Worker {:?}",
-                poll_coord_worker_running, poll_worker
-            );
+    /// The server hosts excluded from the scenario by the failure policy.
+    pub fn degraded_peers(&self) -> Vec<DegradedPeer> {
+        DegradedPeer::from_failed_peers("server", &self.scenario, self.coord.failed_peers())
+    }
 
-            if poll_coord_worker_running.is_ready() {
-                break;
-            }
-            tokio::time::sleep(Duration::from_secs(5)).await;
+    pub async fn wait_workers_running(
+        &mut self,
+        ssm_client: &aws_sdk_ssm::Client,
+    ) -> OrchResult<()> {
+        let timeout = self.timeouts.budget(RussulaPhase::Run);
+        let poll = drive_coord(
+            "server",
+            ssm_client,
+            &self.worker,
+            &mut self.events,
+            timeout,
+            self.coord.run_till_worker_running(),
+        )
+        .await?;
+        if poll.is_pending() {
+            let pending = self.coord.pending_worker_running_peers();
+            return Err(timed_out(
+                "server",
+                RussulaPhase::Run,
+                timeout,
+                &pending,
+                &self.hosts,
+            ));
         }
+        transition("server", "workers_running");
+        Ok(())
     }
 
-    pub async fn wait_done(&mut self, ssm_client: &aws_sdk_ssm::Client) {
-        // poll server russula workers/coord
-        loop {
-            let poll_worker = poll_ssm_results(
+    pub async fn wait_done(&mut self, ssm_client: &aws_sdk_ssm::Client) -> OrchResult<()> {
+        // The worker kills the collector's process group (collector and driver) on
+        // the Kill transition and reports the exit status to the coordinator, so
+        // the coordinator reaching Done is sufficient.
+        let timeout = self.timeouts.budget(RussulaPhase::Stop);
+        let poll = drive_coord(
+            "server",
+            ssm_client,
+            &self.worker,
+            &mut self.events,
+            timeout,
+            self.coord.run_till_done(),
+        )
+        .await?;
+        if poll.is_pending() {
+            let pending = self.coord.pending_done_peers();
+            return Err(timed_out(
                 "server",
-                ssm_client,
-                self.worker.command().unwrap().command_id().unwrap(),
-            )
-            .await
-            .unwrap();
-
-            let poll_coord_done = self.coord.poll_done().await.unwrap();
-
-            debug!(
-                "Server Russula!: Coordinator: {:?} Worker {:?}",
-                poll_coord_done, poll_worker
-            );
-
-            // FIXME the worker doesnt complete but its not necessary to wait so continue.
-            //
-            // maybe try sudo
-            //
-            // The collector launches the driver process, which doesnt get killed when the
-            // collector is killed. However its not necessary to wait for its completing
-            // for the purpose of a single run.
-            // ```
-            //  55320  ./target/debug/russula_cli
-            //  55646  /home/ec2-user/bin/netbench-collector
-            //  55647  /home/ec2-user/bin/netbench-driver-s2n-quic-server
-            // ```
-            if poll_coord_done.is_ready() {
-                break;
-            }
-            tokio::time::sleep(Duration::from_secs(5)).await;
+                RussulaPhase::Stop,
+                timeout,
+                &pending,
+                &self.hosts,
+            ));
         }
 
         info!("Server Russula!: Successful");
+        transition("server", "done");
+        Ok(())
     }
 }
 
 pub struct ClientNetbenchRussula {
     worker: SendCommandOutput,
+    events: RussulaEvents,
     coord: russula::Russula<client::CoordProtocol>,
+    scenario: String,
+    hosts: BTreeMap<SocketAddr, String>,
+    timeouts: RussulaTimeouts,
 }
 
 impl ClientNetbenchRussula {
     pub async fn new(
         ssm_client: &aws_sdk_ssm::Client,
         infra: &InfraDetail,
+        russula_addrs: &RussulaAddrs,
         instance_ids: Vec<String>,
         scenario: &Scenario,
         driver: &NetbenchDriver,
-    ) -> Self {
+        worker_opts: &WorkerOptions,
+    ) -> OrchResult<Self> {
         // client run commands
         debug!("starting client worker");
-        let worker = ssm_utils::client::run_russula_worker(
+        let worker =
+            ssm_utils::client::run_russula_worker(ssm_client, instance_ids, worker_opts).await?;
+
+        let netbench_servers = infra
+            .server_ips()
+            .iter()
+            .map(|ip| SocketAddr::new(*ip, STATE.netbench_port))
+            .collect();
+        let params = RunParams {
+            driver: Some(driver.driver_name.clone()),
+            scenario: Some(scenario.name.clone()),
+            netbench_servers: Some(netbench_servers),
+            driver_instances: Some(worker_opts.driver_instances),
+            warmup: Some(worker_opts.warmup),
+            duration: worker_opts.duration,
+            ..Default::default()
+        };
+        // the output is streamed by a bash script
+        let params = match worker_opts.client_os {
+            HostOs::Linux => RunParams {
+                stream_to: stream_prefix(worker_opts, driver),
+                stream_interval: worker_opts.stream_interval,
+                ..params
+            },
+            HostOs::Windows => params,
+        };
+        let hosts = russula_addrs.hosts(&infra.clients);
+        let (coord, events) = start_coord(
+            "client",
             ssm_client,
-            instance_ids,
-            &infra.server_ips(),
-            driver,
-            scenario,
+            &worker,
+            &hosts,
+            russula_addrs.registration_addr(EndpointType::Client),
+            client::CoordProtocol::new().with_params(params),
+            worker_opts,
         )
-        .await;
+        .await?;
+        Ok(ClientNetbenchRussula {
+            worker,
+            events,
+            coord,
+            scenario: scenario.name.clone(),
+            hosts,
+            timeouts: worker_opts.russula_timeouts,
+        })
+    }
 
-        // wait for worker to start
-        tokio::time::sleep(Duration::from_secs(5)).await;
+    /// The client drivers which exited with an error. Only populated once the
+    /// Workers are done.
+    pub fn driver_failures(&self) -> Vec<DriverFailure> {
+        DriverFailure::from_exits("client", &self.scenario, self.coord.worker_exits())
+    }
 
-        // client coord
-        debug!("starting client coordinator");
-        let coord = client_coord(infra.client_ips()).await;
-        ClientNetbenchRussula { worker, coord }
+    /// The client hosts excluded from the scenario by the failure policy.
+    pub fn degraded_peers(&self) -> Vec<DegradedPeer> {
+        DegradedPeer::from_failed_peers("client", &self.scenario, self.coord.failed_peers())
     }
 
-    pub async fn wait_done(&mut self, ssm_client: &aws_sdk_ssm::Client) {
-        // poll client russula workers/coord
-        loop {
-            let poll_worker = poll_ssm_results(
+    pub async fn wait_done(&mut self, ssm_client: &aws_sdk_ssm::Client) -> OrchResult<()> {
+        let timeout = self.timeouts.budget(RussulaPhase::Run);
+        let poll = drive_coord(
+            "client",
+            ssm_client,
+            &self.worker,
+            &mut self.events,
+            timeout,
+            self.coord.run_till_done(),
+        )
+        .await?;
+        if poll.is_pending() {
+            let pending = self.coord.pending_done_peers();
+            return Err(timed_out(
                 "client",
-                ssm_client,
-                self.worker.command().unwrap().command_id().unwrap(),
-            )
-            .await
-            .unwrap();
+                RussulaPhase::Run,
+                timeout,
+                &pending,
+                &self.hosts,
+            ));
+        }
 
-            let poll_coord_done = self.coord.poll_done().await.unwrap();
+        info!("Client Russula!: Successful");
+        transition("client", "done");
+        Ok(())
+    }
+}
 
-            debug!(
-                "Client Russula!: Coordinator: {:?} Worker {:?}",
-                poll_coord_done, poll_worker
-            );
+/// Routes the client traffic through the router hosts for the duration of a
+/// scenario.
+pub struct RouterNetbenchRussula {
+    worker: SendCommandOutput,
+    events: RussulaEvents,
+    coord: russula::Russula<router::CoordProtocol>,
+    hosts: BTreeMap<SocketAddr, String>,
+    timeouts: RussulaTimeouts,
+}
 
-            if poll_coord_done.is_ready() {
-                // if poll_coord_done.is_ready() && poll_worker.is_ready() {
-                break;
+impl RouterNetbenchRussula {
+    pub async fn new(
+        ssm_client: &aws_sdk_ssm::Client,
+        infra: &InfraDetail,
+        russula_addrs: &RussulaAddrs,
+        worker_opts: &WorkerOptions,
+    ) -> OrchResult<Self> {
+        debug!("starting router worker");
+        let worker =
+            ssm_utils::router::run_russula_worker(ssm_client, infra.router_ids(), worker_opts)
+                .await?;
+
+        let hosts = russula_addrs.hosts(&infra.routers);
+        let (coord, events) = start_coord(
+            "router",
+            ssm_client,
+            &worker,
+            &hosts,
+            russula_addrs.registration_addr(EndpointType::Router),
+            router::CoordProtocol::new(),
+            worker_opts,
+        )
+        .await?;
+        Ok(RouterNetbenchRussula {
+            worker,
+            events,
+            coord,
+            hosts,
+            timeouts: worker_opts.russula_timeouts,
+        })
+    }
+
+    /// The router hosts excluded from the scenario by the failure policy.
+    pub fn degraded_peers(&self, scenario: &str) -> Vec<DegradedPeer> {
+        DegradedPeer::from_failed_peers("router", scenario, self.coord.failed_peers())
+    }
+
+    /// Wait for the routers to forward traffic.
+    pub async fn wait_workers_routing(
+        &mut self,
+        ssm_client: &aws_sdk_ssm::Client,
+    ) -> OrchResult<()> {
+        let timeout = self.timeouts.budget(RussulaPhase::Run);
+        let poll = drive_coord(
+            "router",
+            ssm_client,
+            &self.worker,
+            &mut self.events,
+            timeout,
+            self.coord.run_till_worker_running(),
+        )
+        .await?;
+        if poll.is_pending() {
+            let pending = self.coord.pending_worker_running_peers();
+            return Err(timed_out(
+                "router",
+                RussulaPhase::Run,
+                timeout,
+                &pending,
+                &self.hosts,
+            ));
+        }
+        transition("router", "workers_routing");
+        Ok(())
+    }
+
+    /// Disable forwarding on the routers.
+    pub async fn wait_done(&mut self, ssm_client: &aws_sdk_ssm::Client) -> OrchResult<()> {
+        let timeout = self.timeouts.budget(RussulaPhase::Stop);
+        let poll = drive_coord(
+            "router",
+            ssm_client,
+            &self.worker,
+            &mut self.events,
+            timeout,
+            self.coord.run_till_done(),
+        )
+        .await?;
+        if poll.is_pending() {
+            let pending = self.coord.pending_done_peers();
+            return Err(timed_out(
+                "router",
+                RussulaPhase::Stop,
+                timeout,
+                &pending,
+                &self.hosts,
+            ));
+        }
+
+        info!("Router Russula!: Successful");
+        transition("router", "done");
+        Ok(())
+    }
+}
+
+/// The s3 prefix which the Workers of a driver stream their output to, which
+/// mirrors the driver's folder under results/.
+///
+/// ex: s3://<bucket>/<unique_id>/streams/request_response/s2n-quic
+fn stream_prefix(worker_opts: &WorkerOptions, driver: &NetbenchDriver) -> Option<String> {
+    worker_opts.stream_interval?;
+    let stream_to = worker_opts.stream_to.as_deref()?;
+    Some(format!(
+        "{stream_to}/{}",
+        driver_short_name(&driver.driver_name)
+    ))
+}
+
+/// Connect a Coordinator to the Workers started by the `worker` SSM command,
+/// or have them register on `registration_addr`, and wait for it to be Ready.
+///
+/// Rather than waiting a fixed time for the Workers to start, the Coordinator
+/// retries connecting while their SSM command is running, up to the start
+/// budget. If the Workers don't start listening the error includes the status
+/// and output of the command.
+async fn start_coord<P: Protocol + Send>(
+    host_group: &str,
+    ssm_client: &aws_sdk_ssm::Client,
+    worker: &SendCommandOutput,
+    hosts: &BTreeMap<SocketAddr, String>,
+    registration_addr: Option<SocketAddr>,
+    protocol: P,
+    worker_opts: &WorkerOptions,
+) -> OrchResult<(russula::Russula<P>, RussulaEvents)> {
+    debug!("starting {} coordinator", host_group);
+    live::clear(host_group);
+    let coordinator = format!("{host_group} coordinator");
+    let command_id = ssm_utils::command_id(worker)?;
+    let (poll_delay, max_poll_delay) = poll::russula_delays();
+    let timeouts = worker_opts.russula_timeouts;
+    let start_timeout = timeouts.budget(RussulaPhase::Start);
+    let addrs = BTreeSet::from_iter(hosts.keys().copied());
+    let mut build = RussulaBuilder::new(addrs, protocol, poll_delay)
+        .max_poll_delay(max_poll_delay)
+        .connect_timeout(start_timeout)
+        .transport(worker_opts.transport)
+        .failure_policy(worker_opts.failure_policy);
+    if let Some(registration_addr) = registration_addr {
+        build = build.accept_registrations(registration_addr);
+    }
+    let build = build.build();
+    tokio::pin!(build);
+
+    let mut backoff = Backoff::ssm();
+    let connected = loop {
+        tokio::select! {
+            coord = &mut build => break coord.map_err(|err| match err {
+                RussulaError::PeerTimeout { peer, .. } => {
+                    timed_out(host_group, RussulaPhase::Start, start_timeout, &[peer], hosts)
+                }
+                err => OrchError::russula(&coordinator, err),
+            }),
+            _ = backoff.wait() => {
+                // Workers whose command failed won't ever listen
+                if let Err(err) = poll_ssm_results(host_group, ssm_client, command_id).await {
+                    break Err(err);
+                }
             }
-            tokio::time::sleep(Duration::from_secs(5)).await;
         }
+    };
+    let mut coord = match connected {
+        Ok(coord) => coord,
+        Err(err) => {
+            let diagnostics = worker_diagnostics(host_group, ssm_client, command_id).await;
+            return Err(match err {
+                OrchError::RussulaTimeout {
+                    endpoint,
+                    phase,
+                    timeout,
+                    hosts,
+                    dbg: _,
+                } => OrchError::RussulaTimeout {
+                    endpoint,
+                    phase,
+                    timeout,
+                    hosts,
+                    dbg: format!("Workers didn't start.\n{}", diagnostics),
+                },
+                err => OrchError::Russula {
+                    endpoint: coordinator,
+                    dbg: format!("Workers didn't start. {}\n{}", err, diagnostics),
+                },
+            });
+        }
+    };
 
-        info!("Client Russula!: Successful");
+    let events = coord.events();
+    let ready_timeout = timeouts.budget(RussulaPhase::Ready);
+    match tokio::time::timeout(ready_timeout, coord.run_till_ready()).await {
+        Ok(ready) => ready.map_err(|err| OrchError::russula(&coordinator, err))?,
+        Err(_elapsed) => {
+            let pending = coord.pending_ready_peers();
+            return Err(timed_out(
+                host_group,
+                RussulaPhase::Ready,
+                ready_timeout,
+                &pending,
+                hosts,
+            ));
+        }
     }
+    info!("{} coord Ready", host_group);
+    transition(host_group, "ready");
+    Ok((coord, events))
 }
 
-async fn server_coord(server_ips: Vec<IpAddr>) -> russula::Russula<server::CoordProtocol> {
-    let protocol = server::CoordProtocol::new();
-    let server_addr: Vec<SocketAddr> = server_ips
-        .iter()
-        .map(|ip| SocketAddr::new(*ip, STATE.russula_port))
-        .collect();
-    let server_coord = RussulaBuilder::new(
-        BTreeSet::from_iter(server_addr),
-        protocol,
-        STATE.poll_delay_russula,
-    );
-    let mut server_coord = server_coord.build().await.unwrap();
-    server_coord.run_till_ready().await.unwrap();
-    info!("server coord Ready");
-    server_coord
-}
-
-async fn client_coord(client_ips: Vec<IpAddr>) -> russula::Russula<client::CoordProtocol> {
-    let protocol = client::CoordProtocol::new();
-    let client_addr: Vec<SocketAddr> = client_ips
-        .iter()
-        .map(|ip| SocketAddr::new(*ip, STATE.russula_port))
-        .collect();
-    let client_coord = RussulaBuilder::new(
-        BTreeSet::from_iter(client_addr),
-        protocol,
-        STATE.poll_delay_russula,
-    );
-    let mut client_coord = client_coord.build().await.unwrap();
-    client_coord.run_till_ready().await.unwrap();
-    info!("client coord Ready");
-    client_coord
+/// The status and the last lines of the output of the Workers' SSM command on
+/// each host, or why they couldn't be listed.
+async fn worker_diagnostics(
+    host_group: &str,
+    ssm_client: &aws_sdk_ssm::Client,
+    command_id: &str,
+) -> String {
+    let invocations = match list_invocation_status(host_group, ssm_client, command_id).await {
+        Ok(invocations) => invocations,
+        Err(err) => return format!("Failed to list the Workers' SSM invocations: {}", err),
+    };
+    let mut diagnostics = Vec::new();
+    for invocation in invocations {
+        let output = command_output(ssm_client, host_group, command_id, &invocation.instance_id)
+            .await
+            .unwrap_or_else(|err| err.to_string());
+        diagnostics.push(format_diagnostic(&invocation, &output));
+    }
+    diagnostics.join("\n")
+}
+
+fn format_diagnostic(invocation: &InvocationStatus, output: &str) -> String {
+    let lines: Vec<&str> = output.lines().collect();
+    let tail = &lines[lines.len().saturating_sub(WORKER_OUTPUT_LINES)..];
+    let mut diagnostic = format!("{}: {:?}", invocation.instance_id, invocation.status);
+    for line in tail {
+        diagnostic.push_str("\n    ");
+        diagnostic.push_str(line);
+    }
+    diagnostic
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use aws_sdk_ssm::types::CommandInvocationStatus;
+
+    #[test]
+    fn worker_diagnostic() {
+        let invocation = InvocationStatus {
+            instance_id: "i-1".to_string(),
+            comment: "run_server_russula".to_string(),
+            status: CommandInvocationStatus::Failed,
+        };
+        let output: String = (0..20).map(|line| format!("line {line}\n")).collect();
+        let diagnostic = format_diagnostic(&invocation, &output);
+        assert!(diagnostic.starts_with("i-1: Failed\n    line 10\n"));
+        assert!(diagnostic.ends_with("    line 19"));
+        assert_eq!(format_diagnostic(&invocation, ""), "i-1: Failed");
+    }
+
+    #[test]
+    fn russula_timeout() {
+        let peer = |port| SocketAddr::from(([10, 0, 0, 1], port));
+        let hosts = BTreeMap::from([(peer(7000), "i-1".to_string())]);
+        let err = timed_out(
+            "server",
+            RussulaPhase::Stop,
+            Duration::from_secs(300),
+            &[peer(7000), peer(7001)],
+            &hosts,
+        );
+        assert!(matches!(
+            &err,
+            OrchError::RussulaTimeout { phase, hosts, .. } if phase == "stop" && hosts == &["i-1", "10.0.0.1:7001"]
+        ));
+        assert_eq!(
+            err.to_string(),
+            "russula server coordinator: the stop phase timed out after 300s on hosts: [i-1, 10.0.0.1:7001]"
+        );
+    }
 }
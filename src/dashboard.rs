@@ -1,15 +1,25 @@
 // Copyright Amazon.com, Inc. or its affiliates. All Rights Reserved.
 // SPDX-License-Identifier: Apache-2.0
 
-use crate::{error::OrchResult, upload_object, InstanceDetail, STATE};
+use crate::{
+    error::{OrchError, OrchResult},
+    upload_object, InstanceDetail, STATE,
+};
 use aws_sdk_s3::primitives::ByteStream;
 use bytes::Bytes;
 use tracing::info;
 
+pub mod attach;
+pub mod live;
+pub mod progress;
+pub mod status;
+pub mod timeline;
+
 pub enum Step<'a> {
     UploadIndex,
     ServerHostsRunning(&'a Vec<InstanceDetail>),
     ClientHostsRunning(&'a Vec<InstanceDetail>),
+    UploadTimeline,
 }
 
 pub async fn update_dashboard(
@@ -25,6 +35,7 @@ pub async fn update_dashboard(
         Step::ClientHostsRunning(instances) => {
             update_instance_running(s3_client, instances, unique_id).await
         }
+        Step::UploadTimeline => timeline::upload(s3_client, unique_id).await,
     }
 }
 
@@ -36,7 +47,9 @@ async fn upload_index_html(s3_client: &aws_sdk_s3::Client, unique_id: &str) -> O
 
     // Upload a status file to s3:
     let index_file = std::fs::read_to_string("index.html")
-        .unwrap()
+        .map_err(|err| OrchError::Init {
+            dbg: format!("Failed to read index.html: {}", err),
+        })?
         .replace("template_unique_id", unique_id)
         .replace("template_server_prefix", &template_server_prefix)
         .replace("template_client_prefix", &template_client_prefix)
@@ -49,7 +62,9 @@ async fn upload_index_html(s3_client: &aws_sdk_s3::Client, unique_id: &str) -> O
         &format!("{unique_id}/index.html"),
     )
     .await
-    .unwrap();
+    .map_err(|err| OrchError::S3 {
+        dbg: format!("Failed to upload index.html: {}", err),
+    })?;
     println!("Status: URL: {status}");
     info!("Status: URL: {status}");
 
@@ -64,8 +79,7 @@ async fn update_instance_running(
     let endpoint_type = &instances[0].endpoint_type.as_str();
     let mut instance_ip_id = String::new();
     instances.iter().for_each(|instance| {
-        let id = instance.instance_id().unwrap();
-        let string = format!("{} {}", instance.ip, id);
+        let string = format!("{} {}", instance.ip, instance.instance_id);
         instance_ip_id.push_str(&string);
     });
 
@@ -80,6 +94,8 @@ async fn update_instance_running(
         &format!("{unique_id}/{}-step-0", endpoint_type.to_lowercase()),
     )
     .await
-    .unwrap();
+    .map_err(|err| OrchError::S3 {
+        dbg: format!("Failed to update {} dashboard: {}", endpoint_type, err),
+    })?;
     Ok(())
 }
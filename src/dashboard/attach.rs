@@ -0,0 +1,172 @@
+// Copyright Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+use super::progress::StepProgress;
+use crate::{
+    error::{OrchError, OrchResult},
+    manifest::Manifest,
+    poll::Backoff,
+    ssm_utils::{list_invocation_status, poll_invocations},
+    STATE,
+};
+use aws_types::region::Region;
+use clap::Args;
+use indicatif::MultiProgress;
+use std::collections::{BTreeMap, BTreeSet};
+
+#[derive(Args, Debug)]
+pub struct AttachArgs {
+    /// The unique_id of the run. ex: 2023-10-11T17:05:09Z-v2.0.0
+    #[arg(long)]
+    unique_id: String,
+}
+
+/// A SSM command sent to the run's hosts.
+struct HostCommand {
+    command_id: String,
+    comment: String,
+    instance_ids: Vec<String>,
+    requested_at: i64,
+}
+
+impl AttachArgs {
+    /// Render a read-only view of the run's progress, rebuilt from the run's
+    /// manifest and the SSM commands sent to its hosts.
+    ///
+    /// Exits once the run's results have been collected.
+    pub async fn run(&self, aws_config: &aws_types::SdkConfig) -> OrchResult<()> {
+        let s3_client = aws_sdk_s3::Client::new(aws_config);
+        let shared_config_vpc = aws_config::from_env()
+            .region(Region::new(STATE.vpc_region))
+            .load()
+            .await;
+        let ssm_client = aws_sdk_ssm::Client::new(&shared_config_vpc);
+
+        let multi_progress = MultiProgress::new();
+        let mut steps: BTreeMap<String, StepProgress> = BTreeMap::new();
+        let mut done = BTreeSet::new();
+        let mut backoff = Backoff::ssm();
+        println!("Attached to run: {}", self.unique_id);
+        loop {
+            let finished = done.len();
+            let manifest = Manifest::download(&s3_client, &self.unique_id).await?;
+
+            let mut commands = Vec::new();
+            for instance_id in manifest.hosts.keys() {
+                for command in list_host_commands(&ssm_client, instance_id).await? {
+                    if !commands
+                        .iter()
+                        .any(|known: &HostCommand| known.command_id == command.command_id)
+                    {
+                        commands.push(command);
+                    }
+                }
+            }
+            commands.sort_by_key(|command| command.requested_at);
+
+            for command in commands.iter() {
+                if done.contains(&command.command_id) {
+                    continue;
+                }
+                let progress = steps.entry(command.command_id.clone()).or_insert_with(|| {
+                    StepProgress::new(
+                        &multi_progress,
+                        &command_host_group(&command.instance_ids, &manifest.hosts),
+                        &command.comment,
+                        &command.instance_ids,
+                    )
+                });
+                let invocations =
+                    list_invocation_status("attach", &ssm_client, &command.command_id).await?;
+                progress.update(&invocations);
+                match poll_invocations(&command.command_id, &invocations) {
+                    Ok(poll) if poll.is_ready() => progress.finish(),
+                    Ok(_) => continue,
+                    Err(_) => progress.abandon(),
+                }
+                done.insert(command.command_id.clone());
+            }
+
+            // the presigned urls are the last thing written to the manifest
+            if !manifest.presigned_urls.is_empty() {
+                multi_progress.clear().ok();
+                println!("Run complete. Presigned urls:");
+                for (key, url) in manifest.presigned_urls.iter() {
+                    println!("{key}: {url}");
+                }
+                return Ok(());
+            }
+            if done.len() > finished {
+                backoff.reset();
+            }
+            backoff.wait().await;
+        }
+    }
+}
+
+async fn list_host_commands(
+    ssm_client: &aws_sdk_ssm::Client,
+    instance_id: &str,
+) -> OrchResult<Vec<HostCommand>> {
+    let mut commands = Vec::new();
+    let mut next_token = None;
+    loop {
+        let output = ssm_client
+            .list_commands()
+            .instance_id(instance_id)
+            .set_next_token(next_token)
+            .send()
+            .await
+            .map_err(|err| OrchError::Ssm {
+                dbg: format!("Failed to list commands for {}: {}", instance_id, err),
+            })?;
+        for command in output.commands().unwrap_or_default() {
+            let Some(command_id) = command.command_id() else {
+                continue;
+            };
+            commands.push(HostCommand {
+                command_id: command_id.to_string(),
+                comment: command.comment().unwrap_or_default().to_string(),
+                instance_ids: command.instance_ids().unwrap_or_default().to_vec(),
+                requested_at: command
+                    .requested_date_time()
+                    .map_or(0, |requested| requested.secs()),
+            });
+        }
+        next_token = output.next_token().map(String::from);
+        if next_token.is_none() {
+            return Ok(commands);
+        }
+    }
+}
+
+/// The host group of the command's instances, or `all` if the command was sent
+/// to multiple host groups.
+fn command_host_group(instance_ids: &[String], hosts: &BTreeMap<String, String>) -> String {
+    let groups: BTreeSet<&str> = instance_ids
+        .iter()
+        .map(|instance_id| hosts.get(instance_id).map_or("unknown", String::as_str))
+        .collect();
+    match groups.len() {
+        1 => groups.into_iter().next().unwrap_or("all").to_string(),
+        _ => "all".to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn host_group_of_command() {
+        let hosts = BTreeMap::from([
+            ("i-1".to_string(), "server".to_string()),
+            ("i-2".to_string(), "server".to_string()),
+            ("i-3".to_string(), "client".to_string()),
+        ]);
+        let ids = |ids: &[&str]| ids.iter().map(|id| id.to_string()).collect::<Vec<_>>();
+        assert_eq!(command_host_group(&ids(&["i-1", "i-2"]), &hosts), "server");
+        assert_eq!(command_host_group(&ids(&["i-1", "i-3"]), &hosts), "all");
+        assert_eq!(command_host_group(&ids(&["i-4"]), &hosts), "unknown");
+    }
+}
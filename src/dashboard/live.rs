@@ -0,0 +1,128 @@
+// Copyright Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::{report::escape_html, russula::netbench::RunProgress, s3_utils::upload_object, STATE};
+use aws_sdk_s3::primitives::ByteStream;
+use core::time::Duration;
+use std::{
+    collections::BTreeMap,
+    net::SocketAddr,
+    sync::Mutex,
+    time::{SystemTime, UNIX_EPOCH},
+};
+use tracing::warn;
+
+// How often the progress of the running Workers is published to the dashboard
+const PUBLISH_INTERVAL: Duration = Duration::from_secs(10);
+// The dashboard's steps 0-3 are the instances coming up and being configured
+const PROGRESS_STEP: u8 = 4;
+
+// The latest progress of each Worker: (host_group, peer) -> (unix_millis, progress)
+static LIVE: Mutex<BTreeMap<(String, SocketAddr), (u64, RunProgress)>> =
+    Mutex::new(BTreeMap::new());
+
+fn unix_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_millis() as u64)
+        .unwrap_or_default()
+}
+
+/// Record the progress reported by a running Worker.
+pub fn record(host_group: &str, peer: SocketAddr, progress: RunProgress) {
+    LIVE.lock()
+        .expect("live progress lock")
+        .insert((host_group.to_string(), peer), (unix_millis(), progress));
+}
+
+/// Forget the progress of the host group's Workers, once its coordinator starts
+/// a scenario, so that the Workers of the previous scenario aren't published.
+pub fn clear(host_group: &str) {
+    LIVE.lock()
+        .expect("live progress lock")
+        .retain(|(group, _peer), _progress| group != host_group);
+}
+
+/// The progress of the host group's Workers, a line each. A Worker whose bytes
+/// stop growing is stalled.
+/// ```text
+/// Run progress: 10.0.1.5:7000 sent: 12.0 MB, received: 1.2 MB, connections: 4 (3s ago)
+/// ```
+fn render(
+    live: &BTreeMap<(String, SocketAddr), (u64, RunProgress)>,
+    host_group: &str,
+    now: u64,
+) -> Option<String> {
+    let lines: Vec<String> = live
+        .iter()
+        .filter(|((group, _peer), _progress)| group == host_group)
+        .map(|((_group, peer), (at, progress))| {
+            let age = Duration::from_millis(now.saturating_sub(*at)).as_secs();
+            escape_html(&format!("{peer} {progress} ({age}s ago)"))
+        })
+        .collect();
+    (!lines.is_empty()).then(|| format!("Run progress: {}", lines.join("<br>")))
+}
+
+/// Publish the progress of the running Workers to the dashboard until
+/// dropped, as the step after the host group's configuration.
+pub async fn publish(s3_client: &aws_sdk_s3::Client, unique_id: &str) {
+    loop {
+        tokio::time::sleep(PUBLISH_INTERVAL).await;
+        for host_group in ["server", "client"] {
+            let rendered = {
+                let live = LIVE.lock().expect("live progress lock");
+                render(&live, host_group, unix_millis())
+            };
+            let Some(rendered) = rendered else {
+                continue;
+            };
+            // example: "unique_id/client-step-4"
+            let key = format!("{unique_id}/{host_group}-step-{PROGRESS_STEP}");
+            if let Err(err) = upload_object(
+                s3_client,
+                STATE.s3_log_bucket,
+                ByteStream::from(rendered.into_bytes()),
+                &key,
+            )
+            .await
+            {
+                // the dashboard is best effort, so don't fail the run
+                warn!("Failed to publish the run progress to {}: {}", key, err);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn render_progress() {
+        let peer = |port| SocketAddr::from(([10, 0, 1, 5], port));
+        let progress = RunProgress {
+            bytes_sent: 12_000_000,
+            bytes_received: 1_200_000,
+            connections: 4,
+        };
+        let live = BTreeMap::from([
+            (("client".to_string(), peer(7001)), (1_000, progress)),
+            (("client".to_string(), peer(7002)), (4_000, progress)),
+            (("server".to_string(), peer(7000)), (4_000, progress)),
+        ]);
+        assert_eq!(
+            render(&live, "client", 4_000).unwrap(),
+            "Run progress: 10.0.1.5:7001 sent: 12.0 MB, received: 1.2 MB, connections: 4 (3s ago)\
+            <br>10.0.1.5:7002 sent: 12.0 MB, received: 1.2 MB, connections: 4 (0s ago)"
+        );
+        assert_eq!(render(&live, "router", 4_000), None);
+
+        record("live-test", peer(7000), progress);
+        record("live-test-other", peer(7000), progress);
+        clear("live-test");
+        let live = LIVE.lock().unwrap();
+        assert!(render(&live, "live-test", 4_000).is_none());
+        assert!(render(&live, "live-test-other", 4_000).is_some());
+    }
+}
@@ -0,0 +1,92 @@
+// Copyright Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::ssm_utils::InvocationStatus;
+use aws_sdk_ssm::types::CommandInvocationStatus;
+use core::time::Duration;
+use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
+use std::collections::BTreeMap;
+
+const TICK_CHARS: &str = "⠁⠂⠄⡀⢀⠠⠐⠈ ";
+
+/// Terminal progress for a single SSM step on a host group.
+///
+/// Renders one bar for the step followed by a status line per instance so
+/// that it is possible to tell which host is stuck.
+/// ```text
+/// ⠂ [00:01:10] ████████████░░░░░░░░░░       1/2       server: build_russula_server
+///     ⠂ i-0a1b2c3d4e: Success
+///     ⠂ i-0f9e8d7c6b: InProgress
+/// ```
+pub struct StepProgress {
+    bar: ProgressBar,
+    instances: BTreeMap<String, ProgressBar>,
+}
+
+impl StepProgress {
+    pub fn new(
+        multi_progress: &MultiProgress,
+        host_group: &str,
+        step: &str,
+        instance_ids: &[String],
+    ) -> Self {
+        let bar = multi_progress.add(ProgressBar::new(instance_ids.len() as u64));
+        bar.set_style(
+            ProgressStyle::with_template(
+                "{spinner} [{elapsed_precise}] {bar:40.cyan/blue} {pos:>7}/{len:7} {msg}",
+            )
+            .expect("valid progress bar template")
+            .tick_chars(TICK_CHARS),
+        );
+        bar.set_message(format!("{host_group}: {step}"));
+        bar.enable_steady_tick(Duration::from_secs(1));
+
+        let mut instances = BTreeMap::new();
+        let mut prev = bar.clone();
+        for instance_id in instance_ids {
+            let line = multi_progress.insert_after(&prev, ProgressBar::new_spinner());
+            line.set_style(
+                ProgressStyle::with_template("    {spinner} {msg}")
+                    .expect("valid progress bar template")
+                    .tick_chars(TICK_CHARS),
+            );
+            line.set_message(format!("{instance_id}: Pending"));
+            line.enable_steady_tick(Duration::from_secs(1));
+            prev = line.clone();
+            instances.insert(instance_id.clone(), line);
+        }
+
+        StepProgress { bar, instances }
+    }
+
+    pub fn update(&self, invocations: &[InvocationStatus]) {
+        let mut completed = 0;
+        for invocation in invocations {
+            if invocation.status == CommandInvocationStatus::Success {
+                completed += 1;
+            }
+            if let Some(line) = self.instances.get(&invocation.instance_id) {
+                line.set_message(format!(
+                    "{}: {:?}",
+                    invocation.instance_id, invocation.status
+                ));
+            }
+        }
+        self.bar.set_position(completed);
+    }
+
+    pub fn finish(&self) {
+        for line in self.instances.values() {
+            line.finish_and_clear();
+        }
+        self.bar.finish();
+    }
+
+    /// Leave the per instance status on screen so the failed host is visible.
+    pub fn abandon(&self) {
+        for line in self.instances.values() {
+            line.abandon();
+        }
+        self.bar.abandon();
+    }
+}
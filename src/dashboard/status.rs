@@ -0,0 +1,312 @@
+// Copyright Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::{
+    error::{OrchError, OrchResult},
+    s3_utils::list_objects,
+    STATE,
+};
+use aws_sdk_ec2::types::Filter;
+use aws_types::region::Region;
+use clap::Args;
+use std::{
+    collections::BTreeMap,
+    time::{Duration, SystemTime},
+};
+
+#[derive(Args, Debug)]
+pub struct StatusArgs {
+    /// The unique_id of the run. ex: 2023-10-11T17:05:09Z-v2.0.0
+    #[arg(long)]
+    unique_id: String,
+}
+
+/// A host of the run, found by the Name tag of its instance.
+#[derive(Debug, PartialEq, Eq)]
+struct HostStatus {
+    host_group: String,
+    instance_id: String,
+    state: String,
+    steps: Vec<StepStatus>,
+}
+
+/// A SSM command invocation on a host.
+#[derive(Debug, PartialEq, Eq)]
+struct StepStatus {
+    comment: String,
+    status: String,
+    requested_at: i64,
+    finished_at: Option<i64>,
+}
+
+impl HostStatus {
+    /// The step the host is executing, or the last step it executed.
+    fn phase(&self) -> String {
+        match self.steps.iter().max_by_key(|step| step.requested_at) {
+            Some(step) => format!("{} ({})", step.comment, step.status),
+            None => "no steps".to_string(),
+        }
+    }
+
+    /// The last completed steps, most recent first.
+    fn completed(&self, count: usize) -> Vec<&StepStatus> {
+        let mut completed: Vec<&StepStatus> = self
+            .steps
+            .iter()
+            .filter(|step| step.finished_at.is_some())
+            .collect();
+        completed.sort_by_key(|step| std::cmp::Reverse(step.finished_at));
+        completed.truncate(count);
+        completed
+    }
+}
+
+impl StatusArgs {
+    /// Print a snapshot of the run: each host's state and current step, its
+    /// last completed steps and the artifacts uploaded to the run's folder.
+    pub async fn run(&self, aws_config: &aws_types::SdkConfig) -> OrchResult<()> {
+        let s3_client = aws_sdk_s3::Client::new(aws_config);
+        let shared_config_vpc = aws_config::from_env()
+            .region(Region::new(STATE.vpc_region))
+            .load()
+            .await;
+        let ec2_client = aws_sdk_ec2::Client::new(&shared_config_vpc);
+        let ssm_client = aws_sdk_ssm::Client::new(&shared_config_vpc);
+
+        let mut hosts = list_hosts(&ec2_client, &self.unique_id).await?;
+        for host in hosts.iter_mut() {
+            host.steps = list_host_steps(&ssm_client, &host.instance_id).await?;
+        }
+        let objects = list_objects(
+            &s3_client,
+            STATE.s3_log_bucket,
+            &format!("{}/", self.unique_id),
+        )
+        .await?;
+        if hosts.is_empty() && objects.is_empty() {
+            return Err(OrchError::Init {
+                dbg: format!("Run {} not found", self.unique_id),
+            });
+        }
+        print!("{}", render_status(&self.unique_id, &hosts, &objects));
+        Ok(())
+    }
+}
+
+async fn list_hosts(
+    ec2_client: &aws_sdk_ec2::Client,
+    unique_id: &str,
+) -> OrchResult<Vec<HostStatus>> {
+    let suffix = STATE.instance_name(unique_id, "");
+    let mut hosts = Vec::new();
+    let mut next_token = None;
+    loop {
+        let output = ec2_client
+            .describe_instances()
+            .filters(
+                Filter::builder()
+                    .name("tag:Name")
+                    .values(format!("*{suffix}"))
+                    .build(),
+            )
+            .set_next_token(next_token)
+            .send()
+            .await
+            .map_err(|err| OrchError::Ec2 {
+                dbg: format!("Couldn't describe instances: {}", err),
+            })?;
+        for instance in output
+            .reservations()
+            .unwrap_or_default()
+            .iter()
+            .flat_map(|reservation| reservation.instances().unwrap_or_default())
+        {
+            let name = instance
+                .tags()
+                .unwrap_or_default()
+                .iter()
+                .find(|tag| tag.key() == Some("Name"))
+                .and_then(|tag| tag.value())
+                .unwrap_or_default();
+            hosts.push(HostStatus {
+                host_group: name.strip_suffix(&suffix).unwrap_or(name).to_string(),
+                instance_id: instance.instance_id().unwrap_or("unknown").to_string(),
+                state: instance
+                    .state()
+                    .and_then(|state| state.name())
+                    .map_or("unknown".to_string(), |state| state.as_str().to_string()),
+                steps: Vec::new(),
+            });
+        }
+        next_token = output.next_token().map(String::from);
+        if next_token.is_none() {
+            hosts.sort_by(|a, b| {
+                (&a.host_group, &a.instance_id).cmp(&(&b.host_group, &b.instance_id))
+            });
+            return Ok(hosts);
+        }
+    }
+}
+
+async fn list_host_steps(
+    ssm_client: &aws_sdk_ssm::Client,
+    instance_id: &str,
+) -> OrchResult<Vec<StepStatus>> {
+    let mut steps = Vec::new();
+    let mut next_token = None;
+    loop {
+        let output = ssm_client
+            .list_command_invocations()
+            .instance_id(instance_id)
+            // the plugins hold the time the step finished
+            .details(true)
+            .set_next_token(next_token)
+            .send()
+            .await
+            .map_err(|err| OrchError::Ssm {
+                dbg: format!("Failed to list invocations for {}: {}", instance_id, err),
+            })?;
+        for invocation in output.command_invocations().unwrap_or_default() {
+            let status = invocation
+                .status()
+                .map_or("Unknown", |status| status.as_str());
+            let finished = matches!(status, "Success" | "Failed" | "Cancelled" | "TimedOut");
+            steps.push(StepStatus {
+                comment: invocation.comment().unwrap_or_default().to_string(),
+                status: status.to_string(),
+                requested_at: invocation
+                    .requested_date_time()
+                    .map_or(0, |requested| requested.secs()),
+                finished_at: invocation
+                    .command_plugins()
+                    .unwrap_or_default()
+                    .iter()
+                    .filter_map(|plugin| plugin.response_finish_date_time())
+                    .map(|finished| finished.secs())
+                    .max()
+                    .filter(|_| finished),
+            });
+        }
+        next_token = output.next_token().map(String::from);
+        if next_token.is_none() {
+            return Ok(steps);
+        }
+    }
+}
+
+// The number of completed steps printed for each host
+const COMPLETED_STEPS: usize = 3;
+
+fn render_status(unique_id: &str, hosts: &[HostStatus], objects: &BTreeMap<String, u64>) -> String {
+    let mut out = format!("Run: {unique_id}\n\nHosts:\n");
+    if hosts.is_empty() {
+        out.push_str("  no instances found, the hosts were deleted or not launched yet\n");
+    }
+    for host in hosts {
+        out.push_str(&format!(
+            "  {} {} [{}]: {}\n",
+            host.host_group,
+            host.instance_id,
+            host.state,
+            host.phase()
+        ));
+        for step in host.completed(COMPLETED_STEPS) {
+            out.push_str(&format!(
+                "    {} {} ({})\n",
+                step.finished_at.map_or(String::new(), format_time),
+                step.comment,
+                step.status
+            ));
+        }
+    }
+
+    out.push_str("\nArtifacts:\n");
+    let artifacts = group_artifacts(unique_id, objects);
+    if artifacts.is_empty() {
+        out.push_str("  none\n");
+    }
+    for (name, (count, len)) in artifacts {
+        out.push_str(&format!("  {name}: {count} objects, {len} bytes\n"));
+    }
+    out
+}
+
+/// The object count and size of each top level file or folder of the run.
+pub(crate) fn group_artifacts(
+    unique_id: &str,
+    objects: &BTreeMap<String, u64>,
+) -> BTreeMap<String, (usize, u64)> {
+    let mut artifacts: BTreeMap<String, (usize, u64)> = BTreeMap::new();
+    for (key, len) in objects {
+        let Some(path) = key.strip_prefix(&format!("{unique_id}/")) else {
+            continue;
+        };
+        let name = match path.split_once('/') {
+            Some((folder, _)) => format!("{folder}/"),
+            None => path.to_string(),
+        };
+        let artifact = artifacts.entry(name).or_default();
+        artifact.0 += 1;
+        artifact.1 += len;
+    }
+    artifacts
+}
+
+fn format_time(secs: i64) -> String {
+    let time = SystemTime::UNIX_EPOCH + Duration::from_secs(secs.max(0) as u64);
+    humantime::format_rfc3339_seconds(time).to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn run_status() {
+        let step =
+            |comment: &str, status: &str, requested_at: i64, finished_at: Option<i64>| StepStatus {
+                comment: comment.to_string(),
+                status: status.to_string(),
+                requested_at,
+                finished_at,
+            };
+        let hosts = vec![
+            HostStatus {
+                host_group: "client".to_string(),
+                instance_id: "i-1".to_string(),
+                state: "running".to_string(),
+                steps: vec![
+                    step("configure_host", "Success", 10, Some(60)),
+                    step("run_russula_worker_client", "InProgress", 90, None),
+                    step("build_netbench", "Success", 60, Some(80)),
+                ],
+            },
+            HostStatus {
+                host_group: "server".to_string(),
+                instance_id: "i-2".to_string(),
+                state: "pending".to_string(),
+                steps: vec![],
+            },
+        ];
+        let objects = BTreeMap::from([
+            ("run-1/manifest.json".to_string(), 100),
+            ("run-1/results/request_response/client.json".to_string(), 10),
+            ("run-1/results/request_response/server.json".to_string(), 20),
+        ]);
+        assert_eq!(
+            render_status("run-1", &hosts, &objects),
+            "Run: run-1
+
+Hosts:
+  client i-1 [running]: run_russula_worker_client (InProgress)
+    1970-01-01T00:01:20Z build_netbench (Success)
+    1970-01-01T00:01:00Z configure_host (Success)
+  server i-2 [pending]: no steps
+
+Artifacts:
+  manifest.json: 1 objects, 100 bytes
+  results/: 2 objects, 30 bytes
+"
+        );
+    }
+}
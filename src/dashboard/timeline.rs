@@ -0,0 +1,330 @@
+// Copyright Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::{
+    error::{OrchError, OrchResult},
+    report::escape_html,
+    s3_utils::{download_object, upload_object},
+    STATE,
+};
+use aws_sdk_s3::primitives::ByteStream;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    sync::Mutex,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+// The events of this run, in the order they were recorded
+static TIMELINE: Mutex<Vec<Entry>> = Mutex::new(Vec::new());
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum Event {
+    StepStarted {
+        host_group: String,
+        step: String,
+    },
+    StepFinished {
+        host_group: String,
+        step: String,
+        success: bool,
+    },
+    HostState {
+        instance_id: String,
+        state: String,
+    },
+    // A russula coordinator reached a state
+    Transition {
+        coordinator: String,
+        state: String,
+    },
+}
+
+/// A line of the run's `timeline.jsonl`.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Entry {
+    pub unix_millis: u64,
+    #[serde(flatten)]
+    pub event: Event,
+}
+
+/// Append an event to the run's timeline.
+pub fn record(event: Event) {
+    let unix_millis = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_millis() as u64)
+        .unwrap_or_default();
+    TIMELINE
+        .lock()
+        .expect("timeline lock")
+        .push(Entry { unix_millis, event });
+}
+
+fn timeline_key(unique_id: &str) -> String {
+    format!("{unique_id}/timeline.jsonl")
+}
+
+pub fn to_jsonl(entries: &[Entry]) -> String {
+    entries
+        .iter()
+        .filter_map(|entry| serde_json::to_string(entry).ok())
+        .map(|line| line + "\n")
+        .collect()
+}
+
+/// Parse a timeline, skipping the lines of unknown events.
+pub fn parse_jsonl(jsonl: &str) -> Vec<Entry> {
+    jsonl
+        .lines()
+        .filter_map(|line| serde_json::from_str(line).ok())
+        .collect()
+}
+
+pub async fn upload(s3_client: &aws_sdk_s3::Client, unique_id: &str) -> OrchResult<()> {
+    let jsonl = to_jsonl(&TIMELINE.lock().expect("timeline lock"));
+    let key = timeline_key(unique_id);
+    upload_object(
+        s3_client,
+        STATE.s3_log_bucket,
+        ByteStream::from(jsonl.into_bytes()),
+        &key,
+    )
+    .await
+    .map_err(|err| OrchError::S3 {
+        dbg: format!("Failed to upload {}: {}", key, err),
+    })?;
+    Ok(())
+}
+
+pub async fn download(s3_client: &aws_sdk_s3::Client, unique_id: &str) -> OrchResult<Vec<Entry>> {
+    let key = timeline_key(unique_id);
+    let jsonl = download_object(s3_client, STATE.s3_log_bucket, &key)
+        .await
+        .map_err(|err| OrchError::S3 {
+            dbg: format!("Failed to download {}: {}", key, err),
+        })?
+        .body
+        .collect()
+        .await
+        .map_err(|err| OrchError::S3 {
+            dbg: format!("Failed to download {}: {}", key, err),
+        })?
+        .into_bytes();
+    Ok(parse_jsonl(&String::from_utf8_lossy(&jsonl)))
+}
+
+/// A bar of the Gantt view.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Segment {
+    pub lane: String,
+    pub label: String,
+    pub start: u64,
+    pub end: u64,
+}
+
+/// Turn the events into bars: a step lasts from its start to its finish, while a
+/// host or coordinator stays in a state until its next one. Anything still open
+/// ends with the timeline.
+pub fn segments(entries: &[Entry]) -> Vec<Segment> {
+    let end = entries
+        .iter()
+        .map(|entry| entry.unix_millis)
+        .max()
+        .unwrap_or_default();
+    let mut segments = Vec::new();
+    // lane -> steps which haven't finished, oldest first
+    let mut running: BTreeMap<String, Vec<u64>> = BTreeMap::new();
+    // lane -> the current state and since when
+    let mut states: BTreeMap<String, (String, u64)> = BTreeMap::new();
+
+    for entry in entries {
+        let at = entry.unix_millis;
+        match &entry.event {
+            Event::StepStarted { host_group, step } => {
+                running
+                    .entry(format!("{host_group}: {step}"))
+                    .or_default()
+                    .push(at);
+            }
+            Event::StepFinished {
+                host_group,
+                step,
+                success,
+            } => {
+                let lane = format!("{host_group}: {step}");
+                let Some(start) = running.get_mut(&lane).filter(|s| !s.is_empty()) else {
+                    continue;
+                };
+                let start = start.remove(0);
+                let label = if *success { "success" } else { "failed" };
+                segments.push(Segment {
+                    lane,
+                    label: label.to_string(),
+                    start,
+                    end: at,
+                });
+            }
+            Event::HostState {
+                instance_id: lane,
+                state,
+            }
+            | Event::Transition {
+                coordinator: lane,
+                state,
+            } => {
+                let lane = match &entry.event {
+                    Event::Transition { .. } => format!("{lane} coordinator"),
+                    _ => lane.clone(),
+                };
+                if let Some((label, start)) = states.insert(lane.clone(), (state.clone(), at)) {
+                    segments.push(Segment {
+                        lane,
+                        label,
+                        start,
+                        end: at,
+                    });
+                }
+            }
+        }
+    }
+
+    for (lane, starts) in running {
+        for start in starts {
+            segments.push(Segment {
+                lane: lane.clone(),
+                label: "running".to_string(),
+                start,
+                end,
+            });
+        }
+    }
+    for (lane, (label, start)) in states {
+        segments.push(Segment {
+            lane,
+            label,
+            start,
+            end,
+        });
+    }
+    segments.sort_by_key(|segment| segment.start);
+    segments
+}
+
+/// A Gantt style view of the timeline, with a row per lane in the order the
+/// lanes first appear.
+pub fn gantt_html(unique_id: &str, entries: &[Entry]) -> String {
+    let segments = segments(entries);
+    let start = segments.iter().map(|s| s.start).min().unwrap_or_default();
+    let end = segments.iter().map(|s| s.end).max().unwrap_or_default();
+    let total = (end - start).max(1) as f64;
+
+    let mut lanes: Vec<(&str, Vec<&Segment>)> = Vec::new();
+    for segment in segments.iter() {
+        match lanes.iter_mut().find(|(lane, _)| *lane == segment.lane) {
+            Some((_, lane_segments)) => lane_segments.push(segment),
+            None => lanes.push((&segment.lane, vec![segment])),
+        }
+    }
+
+    let mut html = format!(
+        "<html><body><h2>Timeline {}</h2><p>total: {}</p><table style=\"width:100%\">",
+        escape_html(unique_id),
+        humantime::format_duration(std::time::Duration::from_secs((end - start) / 1000))
+    );
+    for (lane, lane_segments) in lanes {
+        html.push_str(&format!(
+            "<tr><td style=\"white-space:nowrap\">{}</td><td style=\"width:80%;position:relative\">",
+            escape_html(lane)
+        ));
+        for segment in lane_segments {
+            let elapsed = std::time::Duration::from_secs((segment.end - segment.start) / 1000);
+            html.push_str(&format!(
+                "<div title=\"{} {}\" style=\"position:absolute;top:2px;height:16px;left:{:.2}%;width:{:.2}%;min-width:1px;background:{}\"></div>",
+                escape_html(&segment.label),
+                humantime::format_duration(elapsed),
+                (segment.start - start) as f64 / total * 100.0,
+                (segment.end - segment.start) as f64 / total * 100.0,
+                if segment.label == "failed" { "#d62728" } else { "#1f77b4" },
+            ));
+        }
+        html.push_str("&nbsp;</td></tr>");
+    }
+    html.push_str("</table></body></html>");
+    html
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn timeline_segments() {
+        let entry = |unix_millis, event| Entry { unix_millis, event };
+        let step = |step: &str| (String::from("server"), step.to_string());
+        let (host_group, build) = step("build_driver");
+        let entries = vec![
+            entry(
+                0,
+                Event::HostState {
+                    instance_id: "i-1".to_string(),
+                    state: "Pending".to_string(),
+                },
+            ),
+            entry(
+                1000,
+                Event::HostState {
+                    instance_id: "i-1".to_string(),
+                    state: "Running".to_string(),
+                },
+            ),
+            entry(
+                2000,
+                Event::StepStarted {
+                    host_group: host_group.clone(),
+                    step: build.clone(),
+                },
+            ),
+            entry(
+                5000,
+                Event::StepFinished {
+                    host_group,
+                    step: build,
+                    success: true,
+                },
+            ),
+            entry(
+                6000,
+                Event::Transition {
+                    coordinator: "client".to_string(),
+                    state: "ready".to_string(),
+                },
+            ),
+        ];
+
+        let jsonl = to_jsonl(&entries);
+        assert!(jsonl.starts_with(
+            r#"{"unix_millis":0,"event":"host_state","instance_id":"i-1","state":"Pending"}"#
+        ));
+        assert_eq!(parse_jsonl(&(jsonl + "{\"event\":\"unknown\"}\n")), entries);
+
+        let segment = |lane: &str, label: &str, start, end| Segment {
+            lane: lane.to_string(),
+            label: label.to_string(),
+            start,
+            end,
+        };
+        assert_eq!(
+            segments(&entries),
+            vec![
+                segment("i-1", "Pending", 0, 1000),
+                segment("i-1", "Running", 1000, 6000),
+                segment("server: build_driver", "success", 2000, 5000),
+                segment("client coordinator", "ready", 6000, 6000),
+            ]
+        );
+        let html = gantt_html("run", &entries);
+        assert!(html.contains("<td style=\"white-space:nowrap\">server: build_driver</td>"));
+        assert!(html.contains("title=\"success 3s\""), "{html}");
+    }
+}
@@ -0,0 +1,395 @@
+// Copyright Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::{
+    config::OrchestratorConfig,
+    error::{OrchError, OrchResult},
+    Args, STATE,
+};
+use aws_sdk_ec2::types::Filter;
+use aws_sdk_iam::types::PolicyEvaluationDecisionType;
+use aws_sdk_s3::primitives::ByteStream;
+use aws_types::region::Region;
+use clap::Args as ClapArgs;
+use std::process::Command;
+
+// The API calls made by a run, as IAM actions
+const RUN_ACTIONS: &[&str] = &[
+    "ec2:RunInstances",
+    "ec2:TerminateInstances",
+    "ec2:DescribeInstances",
+    "ec2:DescribeInstanceTypes",
+    "ec2:DescribeSubnets",
+    "ec2:DescribeVpcEndpoints",
+    "ec2:ModifyInstanceAttribute",
+    "ec2:CreateSecurityGroup",
+    "ec2:DeleteSecurityGroup",
+    "ec2:AuthorizeSecurityGroupIngress",
+    "ec2:CreateLaunchTemplate",
+    "ec2:DeleteLaunchTemplate",
+    "ec2:CreateTags",
+    "iam:GetInstanceProfile",
+    "iam:PassRole",
+    "ssm:SendCommand",
+    "ssm:ListCommands",
+    "ssm:ListCommandInvocations",
+    "ssm:GetCommandInvocation",
+    "ssm:DescribeInstanceInformation",
+    "ssm:GetParameter",
+    "ssm:CreateDocument",
+    "ssm:DescribeDocument",
+    "ssm:UpdateDocument",
+    "ssm:UpdateDocumentDefaultVersion",
+];
+// Only made with `dedicated_vpc`
+const DEDICATED_VPC_ACTIONS: &[&str] = &[
+    "ec2:CreateVpc",
+    "ec2:DeleteVpc",
+    "ec2:CreateSubnet",
+    "ec2:DeleteSubnet",
+    "ec2:CreateInternetGateway",
+    "ec2:AttachInternetGateway",
+    "ec2:DetachInternetGateway",
+    "ec2:DeleteInternetGateway",
+    "ec2:CreateRouteTable",
+    "ec2:CreateRoute",
+    "ec2:AssociateRouteTable",
+    "ec2:DeleteRouteTable",
+    "ec2:AuthorizeSecurityGroupEgress",
+];
+// Only made with `trend`
+const TREND_ACTIONS: &[&str] = &["dynamodb:PutItem", "dynamodb:Query"];
+// Simulated against the objects and the buckets of the log buckets
+const S3_OBJECT_ACTIONS: &[&str] = &["s3:GetObject", "s3:PutObject", "s3:DeleteObject"];
+const S3_BUCKET_ACTIONS: &[&str] = &["s3:ListBucket", "s3:PutLifecycleConfiguration"];
+
+#[derive(ClapArgs, Debug)]
+pub struct DoctorArgs {}
+
+/// The outcome of a single check of the checklist.
+#[derive(Debug, PartialEq, Eq)]
+struct Check {
+    name: &'static str,
+    // The detail of a passed check, or why it failed
+    result: Result<String, String>,
+}
+
+impl DoctorArgs {
+    /// Check the local tools, AWS credentials and permissions, and the AWS
+    /// resources a run relies on, and print a pass/fail checklist.
+    ///
+    /// Fails if any check failed.
+    pub async fn run(&self, args: &Args, aws_config: &aws_types::SdkConfig) -> OrchResult<()> {
+        let shared_config_vpc = aws_config::from_env()
+            .region(Region::new(STATE.vpc_region))
+            .load()
+            .await;
+        let ec2_client = aws_sdk_ec2::Client::new(&shared_config_vpc);
+        let iam_client = aws_sdk_iam::Client::new(aws_config);
+        let s3_client = aws_sdk_s3::Client::new(aws_config);
+        let sts_client = aws_sdk_sts::Client::new(aws_config);
+
+        let mut checks = Vec::new();
+        let config = OrchestratorConfig::load(args.config.as_deref());
+        checks.push(Check {
+            name: "config",
+            result: match &config {
+                Ok(_) => Ok(format!(
+                    "{:?}",
+                    args.config.as_deref().unwrap_or("default".as_ref())
+                )),
+                Err(err) => Err(err.to_string()),
+            },
+        });
+        let config = config.unwrap_or_default();
+
+        for (name, program, purpose) in [
+            ("s2n-netbench cli", "s2n-netbench", "generates the report"),
+            (
+                "aws cli",
+                "aws",
+                "queries quotas, publishes notifications and encrypts secrets",
+            ),
+        ] {
+            checks.push(Check {
+                name,
+                result: match Command::new(program).arg("--version").output() {
+                    Ok(_) => Ok(format!("{program} {purpose}")),
+                    Err(err) => Err(format!("`{program}` not found on the PATH: {err}")),
+                },
+            });
+        }
+
+        let principal = principal_arn(&sts_client, &iam_client).await;
+        checks.push(Check {
+            name: "aws credentials",
+            result: principal.clone(),
+        });
+        checks.push(Check {
+            name: "iam permissions",
+            result: match principal {
+                Ok(principal) => check_permissions(&iam_client, &principal, &config).await,
+                Err(_) => Err("skipped without credentials".to_string()),
+            },
+        });
+        checks.push(Check {
+            name: "instance profile",
+            result: check_instance_profile(&iam_client).await,
+        });
+        checks.push(Check {
+            name: "subnet",
+            result: check_subnet(&ec2_client, &config).await,
+        });
+        checks.push(Check {
+            name: "ssh key pair",
+            result: check_key_pair(&ec2_client).await,
+        });
+        for (name, bucket) in [
+            ("log bucket", STATE.s3_log_bucket),
+            ("private log bucket", STATE.s3_private_log_bucket),
+        ] {
+            checks.push(Check {
+                name,
+                result: check_bucket(&s3_client, bucket).await,
+            });
+        }
+
+        print!("{}", checklist(&checks));
+        let failed = checks.iter().filter(|check| check.result.is_err()).count();
+        if failed > 0 {
+            return Err(OrchError::Init {
+                dbg: format!("{} of {} checks failed", failed, checks.len()),
+            });
+        }
+        Ok(())
+    }
+}
+
+fn checklist(checks: &[Check]) -> String {
+    checks
+        .iter()
+        .map(|check| match &check.result {
+            Ok(detail) => format!("[PASS] {}: {}\n", check.name, detail),
+            Err(reason) => format!("[FAIL] {}: {}\n", check.name, reason),
+        })
+        .collect()
+}
+
+/// The IAM user or role of the credentials, which the policies are simulated
+/// for.
+async fn principal_arn(
+    sts_client: &aws_sdk_sts::Client,
+    iam_client: &aws_sdk_iam::Client,
+) -> Result<String, String> {
+    let arn = sts_client
+        .get_caller_identity()
+        .send()
+        .await
+        .map_err(|err| format!("Missing or invalid AWS credentials: {}", err))?
+        .arn()
+        .ok_or("Missing the arn of the caller")?
+        .to_string();
+    let Some(role_name) = assumed_role_name(&arn) else {
+        return Ok(arn);
+    };
+    // the role's arn includes its path, unlike the session's
+    iam_client
+        .get_role()
+        .role_name(role_name)
+        .send()
+        .await
+        .map_err(|err| format!("Failed to get role {}: {}", role_name, err))?
+        .role()
+        .map(|role| role.arn().unwrap_or(&arn).to_string())
+        .ok_or(format!("Role {} not found", role_name))
+}
+
+/// ex: arn:aws:sts::123456789012:assumed-role/NetbenchCi/session -> NetbenchCi
+fn assumed_role_name(arn: &str) -> Option<&str> {
+    let (_account, resource) = arn.strip_prefix("arn:aws:sts::")?.split_once(':')?;
+    resource.strip_prefix("assumed-role/")?.split('/').next()
+}
+
+async fn check_permissions(
+    iam_client: &aws_sdk_iam::Client,
+    principal: &str,
+    config: &OrchestratorConfig,
+) -> Result<String, String> {
+    let mut actions: Vec<&str> = RUN_ACTIONS.to_vec();
+    if config.dedicated_vpc.is_some() {
+        actions.extend(DEDICATED_VPC_ACTIONS);
+    }
+    if config.trend.is_some() {
+        actions.extend(TREND_ACTIONS);
+    }
+    let buckets = [STATE.s3_log_bucket, STATE.s3_private_log_bucket];
+    let simulations = [
+        (actions, vec!["*".to_string()]),
+        (
+            S3_OBJECT_ACTIONS.to_vec(),
+            buckets
+                .iter()
+                .map(|bucket| format!("arn:aws:s3:::{bucket}/*"))
+                .collect(),
+        ),
+        (
+            S3_BUCKET_ACTIONS.to_vec(),
+            buckets
+                .iter()
+                .map(|bucket| format!("arn:aws:s3:::{bucket}"))
+                .collect(),
+        ),
+    ];
+
+    let mut simulated = 0;
+    let mut denied = Vec::new();
+    for (actions, resources) in simulations {
+        let mut marker = None;
+        loop {
+            let output = iam_client
+                .simulate_principal_policy()
+                .policy_source_arn(principal)
+                .set_action_names(Some(actions.iter().map(|a| a.to_string()).collect()))
+                .set_resource_arns(Some(resources.clone()))
+                .set_marker(marker)
+                .send()
+                .await
+                .map_err(|err| {
+                    format!("Failed to simulate the policies of {}: {}", principal, err)
+                })?;
+            for result in output.evaluation_results().unwrap_or_default() {
+                simulated += 1;
+                if result.eval_decision() != Some(&PolicyEvaluationDecisionType::Allowed) {
+                    denied.push(format!(
+                        "{} on {}",
+                        result.eval_action_name().unwrap_or("unknown"),
+                        result.eval_resource_name().unwrap_or("*")
+                    ));
+                }
+            }
+            marker = output.marker().map(String::from);
+            if !output.is_truncated() || marker.is_none() {
+                break;
+            }
+        }
+    }
+    match denied.is_empty() {
+        true => Ok(format!("{} actions allowed for {}", simulated, principal)),
+        false => Err(format!("{} denied: {}", principal, denied.join(", "))),
+    }
+}
+
+async fn check_instance_profile(iam_client: &aws_sdk_iam::Client) -> Result<String, String> {
+    let profile = iam_client
+        .get_instance_profile()
+        .instance_profile_name(STATE.instance_profile)
+        .send()
+        .await
+        .map_err(|err| format!("{} not found: {}", STATE.instance_profile, err))?;
+    let roles = profile
+        .instance_profile()
+        .and_then(|profile| profile.roles())
+        .unwrap_or_default();
+    match roles.first().and_then(|role| role.role_name()) {
+        Some(role) => Ok(format!("{} with role {}", STATE.instance_profile, role)),
+        None => Err(format!("{} has no role", STATE.instance_profile)),
+    }
+}
+
+/// The subnet the hosts are launched in, found by its tag. Skipped with
+/// `dedicated_vpc`, which creates the subnet for the run.
+async fn check_subnet(
+    ec2_client: &aws_sdk_ec2::Client,
+    config: &OrchestratorConfig,
+) -> Result<String, String> {
+    if config.dedicated_vpc.is_some() {
+        return Ok("created for each run with dedicated_vpc".to_string());
+    }
+    let (tag, value) = match &config.private_network {
+        Some(private_network) => (
+            private_network.subnet_tag.0.as_str(),
+            private_network.subnet_tag.1.as_str(),
+        ),
+        None => STATE.subnet_tag_value,
+    };
+    let subnets = ec2_client
+        .describe_subnets()
+        .filters(Filter::builder().name(tag).values(value).build())
+        .send()
+        .await
+        .map_err(|err| format!("Couldn't describe subnets: {}", err))?;
+    match subnets
+        .subnets()
+        .and_then(|subnets| subnets.first())
+        .and_then(|subnet| subnet.subnet_id())
+    {
+        Some(subnet_id) => Ok(format!("{} ({}={})", subnet_id, tag, value)),
+        None => Err(format!(
+            "No subnet tagged {}={} in {}",
+            tag, value, STATE.vpc_region
+        )),
+    }
+}
+
+async fn check_key_pair(ec2_client: &aws_sdk_ec2::Client) -> Result<String, String> {
+    ec2_client
+        .describe_key_pairs()
+        .key_names(STATE.ssh_key_name)
+        .send()
+        .await
+        .map(|_| format!("{} in {}", STATE.ssh_key_name, STATE.vpc_region))
+        .map_err(|err| format!("{} not found: {}", STATE.ssh_key_name, err))
+}
+
+/// Write, then delete, an object to verify the bucket exists and is writable.
+async fn check_bucket(s3_client: &aws_sdk_s3::Client, bucket: &str) -> Result<String, String> {
+    let key = format!("doctor/{}", STATE.version);
+    s3_client
+        .put_object()
+        .bucket(bucket)
+        .key(&key)
+        .body(ByteStream::from_static(b"netbench doctor"))
+        .send()
+        .await
+        .map_err(|err| format!("Failed to write to {}: {}", bucket, err))?;
+    s3_client
+        .delete_object()
+        .bucket(bucket)
+        .key(&key)
+        .send()
+        .await
+        .map_err(|err| format!("Failed to delete from {}: {}", bucket, err))?;
+    Ok(format!("{} is writable", bucket))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn doctor_checklist() {
+        assert_eq!(
+            assumed_role_name("arn:aws:sts::123456789012:assumed-role/NetbenchCi/session"),
+            Some("NetbenchCi")
+        );
+        assert_eq!(
+            assumed_role_name("arn:aws:iam::123456789012:user/netbench"),
+            None
+        );
+
+        let checks = [
+            Check {
+                name: "aws cli",
+                result: Ok("aws queries quotas".to_string()),
+            },
+            Check {
+                name: "subnet",
+                result: Err("No subnet tagged k=v in us-east-1".to_string()),
+            },
+        ];
+        assert_eq!(
+            checklist(&checks),
+            "[PASS] aws cli: aws queries quotas\n[FAIL] subnet: No subnet tagged k=v in us-east-1\n"
+        );
+    }
+}
@@ -1,31 +1,53 @@
 // Copyright Amazon.com, Inc. or its affiliates. All Rights Reserved.
 // SPDX-License-Identifier: Apache-2.0
 
-use self::instance::poll_state;
+use self::instance::poll_states;
 use crate::{
-    ec2_utils::instance::delete_instance,
+    ec2_utils::{instance::delete_instance, launch_template::delete_launch_template, vpc::RunVpc},
     error::{OrchError, OrchResult},
+    Scenario,
 };
 use std::{net::IpAddr, str::FromStr, time::Duration};
 use tracing::info;
 
-mod cluster;
+mod health_check;
+pub mod host_os;
 mod instance;
 mod launch_plan;
+mod launch_template;
+pub mod lease;
+pub mod pool;
+mod preflight;
+mod vpc;
 
+pub use health_check::ensure_healthy;
+pub use host_os::{HostOs, LinuxDistro};
 pub use instance::{EndpointType, InstanceDetail};
-pub use launch_plan::LaunchPlan;
+pub use launch_plan::{host_groups, LaunchPlan};
 
 pub struct InfraDetail {
     pub security_group_id: String,
+    // The launch template created for the hosts, deleted with them
+    pub launch_template_id: Option<String>,
+    // The network created for the run, deleted once the hosts are
+    pub vpc: Option<RunVpc>,
     pub clients: Vec<InstanceDetail>,
     pub servers: Vec<InstanceDetail>,
+    pub routers: Vec<InstanceDetail>,
+    // Hosts without a russula Worker
+    pub observers: Vec<InstanceDetail>,
 }
 
 impl InfraDetail {
     pub async fn cleanup(&self, ec2_client: &aws_sdk_ec2::Client) -> OrchResult<()> {
         self.delete_instances(ec2_client).await?;
         self.delete_security_group(ec2_client).await?;
+        if let Some(launch_template_id) = &self.launch_template_id {
+            delete_launch_template(ec2_client, launch_template_id).await?;
+        }
+        if let Some(vpc) = &self.vpc {
+            vpc.delete(ec2_client).await?;
+        }
         Ok(())
     }
 
@@ -36,25 +58,143 @@ impl InfraDetail {
             .collect()
     }
 
-    pub fn client_ips(&self) -> Vec<IpAddr> {
+    /// The hosts used to run a specific scenario.
+    ///
+    /// The infra is sized for the largest scenario so smaller scenarios only run on
+    /// the first `scenario.servers`, `scenario.clients` and `scenario.routers` hosts.
+    pub fn for_scenario(&self, scenario: &Scenario) -> InfraDetail {
+        InfraDetail {
+            security_group_id: self.security_group_id.clone(),
+            launch_template_id: self.launch_template_id.clone(),
+            vpc: self.vpc.clone(),
+            clients: self
+                .clients
+                .iter()
+                .take(scenario.clients)
+                .cloned()
+                .collect(),
+            servers: self
+                .servers
+                .iter()
+                .take(scenario.servers)
+                .cloned()
+                .collect(),
+            routers: self
+                .routers
+                .iter()
+                .take(scenario.routers)
+                .cloned()
+                .collect(),
+            observers: self.observers.clone(),
+        }
+    }
+
+    /// The hosts of all the groups.
+    pub fn hosts(&self) -> impl Iterator<Item = &InstanceDetail> {
+        self.servers
+            .iter()
+            .chain(self.clients.iter())
+            .chain(self.routers.iter())
+            .chain(self.observers.iter())
+    }
+
+    /// The hosts with the `role`.
+    pub fn hosts_mut(&mut self, role: &EndpointType) -> &mut Vec<InstanceDetail> {
+        match role {
+            EndpointType::Server => &mut self.servers,
+            EndpointType::Client => &mut self.clients,
+            EndpointType::Router => &mut self.routers,
+            EndpointType::Observer => &mut self.observers,
+        }
+    }
+
+    /// The hosts of the group named `host_group`.
+    pub fn group(&self, host_group: &str) -> Vec<&InstanceDetail> {
+        self.hosts()
+            .filter(|host| host.group() == host_group)
+            .collect()
+    }
+
+    /// The private ips of the hosts of the group named `host_group`.
+    pub fn group_ips(&self, host_group: &str) -> Vec<IpAddr> {
+        self.group(host_group)
+            .iter()
+            .filter_map(|host| IpAddr::from_str(&host.private_ip).ok())
+            .collect()
+    }
+
+    /// The names of the groups, in the order their first host was launched.
+    pub fn group_names(&self) -> Vec<String> {
+        let mut names: Vec<String> = Vec::new();
+        for host in self.hosts() {
+            let name = host.group();
+            if !names.contains(&name) {
+                names.push(name);
+            }
+        }
+        names
+    }
+
+    pub fn server_ids(&self) -> Vec<String> {
+        self.servers
+            .iter()
+            .map(|instance| instance.instance_id.clone())
+            .collect()
+    }
+
+    pub fn client_ids(&self) -> Vec<String> {
         self.clients
             .iter()
-            .map(|instance| IpAddr::from_str(&instance.ip).unwrap())
+            .map(|instance| instance.instance_id.clone())
+            .collect()
+    }
+
+    pub fn router_ids(&self) -> Vec<String> {
+        self.routers
+            .iter()
+            .map(|instance| instance.instance_id.clone())
+            .collect()
+    }
+
+    /// The host with `instance_id`.
+    pub fn host_mut(&mut self, instance_id: &str) -> Option<&mut InstanceDetail> {
+        self.servers
+            .iter_mut()
+            .chain(self.clients.iter_mut())
+            .chain(self.routers.iter_mut())
+            .chain(self.observers.iter_mut())
+            .find(|instance| instance.instance_id == instance_id)
+    }
+
+    /// The ids of all the instances.
+    pub fn instance_ids(&self) -> Vec<String> {
+        self.hosts()
+            .map(|instance| instance.instance_id.clone())
             .collect()
     }
+
+    /// The hosts which run Linux. The steps which rely on Linux tools skip
+    /// Windows clients.
+    pub fn linux_hosts(&self, client_os: HostOs) -> InfraDetail {
+        InfraDetail {
+            security_group_id: self.security_group_id.clone(),
+            launch_template_id: self.launch_template_id.clone(),
+            vpc: self.vpc.clone(),
+            clients: match client_os {
+                HostOs::Linux => self.clients.clone(),
+                HostOs::Windows => Vec::new(),
+            },
+            servers: self.servers.clone(),
+            routers: self.routers.clone(),
+            observers: self.observers.clone(),
+        }
+    }
 }
 
 impl InfraDetail {
     async fn delete_instances(&self, ec2_client: &aws_sdk_ec2::Client) -> OrchResult<()> {
         info!("Start: deleting instances");
-        let ids: Vec<String> = self
-            .servers
-            .iter()
-            .chain(self.clients.iter())
-            .map(|instance| instance.instance_id().unwrap().to_string())
-            .collect();
-
-        delete_instance(ec2_client, ids).await?;
+        delete_instance(ec2_client, self.instance_ids()).await?;
         Ok(())
     }
 
@@ -0,0 +1,320 @@
+// Copyright Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+use super::{
+    instance::delete_instance, EndpointType, HostOs, InfraDetail, InstanceDetail, LaunchPlan,
+};
+use crate::{
+    error::{OrchError, OrchResult},
+    poll::Backoff,
+    ssm_utils::{
+        command_id, list_invocation_status, send_command, InvocationStatus, Step,
+        MAX_COMMAND_INSTANCES,
+    },
+};
+use aws_sdk_ssm::types::{
+    CommandInvocationStatus, InstanceInformationFilter, InstanceInformationFilterKey, PingStatus,
+};
+use core::{task::Poll, time::Duration};
+use std::{collections::BTreeSet, time::Instant};
+use tokio::net::TcpStream;
+use tracing::{info, warn};
+
+// Free space required in the home dir, which holds the netbench builds and results
+const MIN_DISK_AVAIL_KB: u64 = 10 * 1024 * 1024;
+// Max clock offset (seconds) and the number of 10s polls to wait for chrony to sync
+const MAX_CLOCK_OFFSET_SEC: f32 = 0.1;
+const CLOCK_SYNC_TRIES: u32 = 12;
+// A freshly launched host takes a while to register with SSM
+const SSM_PING_TIMEOUT: Duration = Duration::from_secs(300);
+const RUSSULA_PORT_TIMEOUT: Duration = Duration::from_secs(30);
+// How long the temporary listener on the russula ports is kept open
+const PORT_LISTENER_SEC: u64 = 120;
+
+/// A host which failed a health check.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Unhealthy {
+    pub instance_id: String,
+    pub reason: &'static str,
+}
+
+/// Check the health of the hosts and replace the unhealthy ones, up to
+/// `retries` times before giving up.
+///
+/// A host is healthy if it's online in SSM, has enough free disk space, its clock
+/// is synchronized and the orchestrator can reach its russula port.
+pub async fn ensure_healthy(
+    launch_plan: &LaunchPlan,
+    ec2_client: &aws_sdk_ec2::Client,
+    ssm_client: &aws_sdk_ssm::Client,
+    unique_id: &str,
+    infra: &mut InfraDetail,
+    retries: u32,
+) -> OrchResult<()> {
+    let security_group_id = infra.security_group_id.clone();
+    let mut launched = infra.instance_ids().len();
+    let mut attempt = 0;
+    loop {
+        let unhealthy = check_hosts(
+            ssm_client,
+            infra,
+            launch_plan.private_network,
+            launch_plan.client_os,
+        )
+        .await?;
+        if unhealthy.is_empty() {
+            info!("all hosts are healthy");
+            return Ok(());
+        }
+        for host in unhealthy.iter() {
+            warn!(instance_id = %host.instance_id, reason = host.reason, "unhealthy host");
+        }
+        if attempt == retries {
+            return Err(OrchError::Ec2 {
+                dbg: format!(
+                    "Hosts still unhealthy after {} replacement attempts: {:?}",
+                    retries, unhealthy
+                ),
+            });
+        }
+        attempt += 1;
+        // replacements count towards the budget
+        launched += unhealthy.len();
+
+        for Unhealthy { instance_id, .. } in unhealthy {
+            let Some(host) = infra.host_mut(&instance_id) else {
+                continue;
+            };
+            let group = launch_plan.host_group(host)?;
+            launch_plan
+                .budget
+                .check(launched, launch_plan.instance_type(group))?;
+            delete_instance(ec2_client, vec![instance_id.clone()]).await?;
+            let replacement = launch_plan
+                .launch_replacement(ec2_client, unique_id, &security_group_id, host)
+                .await?;
+            info!(
+                "replaced {} {} with {} (attempt {}/{})",
+                replacement.group(),
+                instance_id,
+                replacement.instance_id,
+                attempt,
+                retries
+            );
+            *host = replacement;
+        }
+    }
+}
+
+async fn check_hosts(
+    ssm_client: &aws_sdk_ssm::Client,
+    infra: &InfraDetail,
+    private_network: bool,
+    client_os: HostOs,
+) -> OrchResult<Vec<Unhealthy>> {
+    let hosts: Vec<&InstanceDetail> = infra.hosts().collect();
+    let mut unhealthy = Vec::new();
+
+    let online = wait_ssm_online(ssm_client, infra.instance_ids()).await?;
+    let mut checked_ids = Vec::new();
+    for host in hosts.iter() {
+        if online.contains(&host.instance_id) {
+            // the checks rely on Linux tools, and the Windows firewall only
+            // opens the russula port once the host is configured
+            if host.endpoint_type == EndpointType::Client && client_os.is_windows() {
+                continue;
+            }
+            checked_ids.push(host.instance_id.clone());
+        } else {
+            unhealthy.push(Unhealthy {
+                instance_id: host.instance_id.clone(),
+                reason: "ssm agent is not online",
+            });
+        }
+    }
+    if checked_ids.is_empty() {
+        return Ok(unhealthy);
+    }
+
+    let cmd = send_command(
+        vec![],
+        Step::HealthCheck,
+        "all",
+        "health_check",
+        ssm_client,
+        checked_ids.clone(),
+        health_check_cmds(),
+    )
+    .await?;
+    let mut backoff = Backoff::ssm();
+    let failed = loop {
+        let invocations = list_invocation_status("all", ssm_client, command_id(&cmd)?).await?;
+        if let Poll::Ready(failed) = failed_invocations(&checked_ids, &invocations) {
+            break failed;
+        }
+        backoff.wait().await;
+    };
+
+    for host in hosts {
+        if !checked_ids.contains(&host.instance_id) {
+            continue;
+        }
+        if failed.contains(&host.instance_id) {
+            unhealthy.push(Unhealthy {
+                instance_id: host.instance_id.clone(),
+                reason: "disk space or clock sync check failed",
+            });
+            continue;
+        }
+        // russula is relayed over ssm in a private network
+        if !private_network
+            && host.endpoint_type.has_worker()
+            && !russula_port_reachable(host).await
+        {
+            unhealthy.push(Unhealthy {
+                instance_id: host.instance_id.clone(),
+                reason: "russula port is not reachable",
+            });
+        }
+    }
+
+    Ok(unhealthy)
+}
+
+/// The instances which are online in SSM, once all of them are or the timeout
+/// expires.
+async fn wait_ssm_online(
+    ssm_client: &aws_sdk_ssm::Client,
+    instance_ids: Vec<String>,
+) -> OrchResult<BTreeSet<String>> {
+    let start = Instant::now();
+    let mut backoff = Backoff::ssm();
+    loop {
+        let mut online = BTreeSet::new();
+        // the filter takes at most as many instances as a command targets
+        for batch in instance_ids.chunks(MAX_COMMAND_INSTANCES) {
+            let mut next_token = None;
+            loop {
+                let info = ssm_client
+                    .describe_instance_information()
+                    .instance_information_filter_list(
+                        InstanceInformationFilter::builder()
+                            .key(InstanceInformationFilterKey::InstanceIds)
+                            .set_value_set(Some(batch.to_vec()))
+                            .build(),
+                    )
+                    .set_next_token(next_token)
+                    .send()
+                    .await
+                    .map_err(|err| OrchError::Ssm {
+                        dbg: format!("Failed to describe instance information: {}", err),
+                    })?;
+                online.extend(
+                    info.instance_information_list()
+                        .unwrap_or_default()
+                        .iter()
+                        .filter(|info| info.ping_status() == Some(&PingStatus::Online))
+                        .filter_map(|info| info.instance_id().map(String::from)),
+                );
+                next_token = info.next_token().map(String::from);
+                if next_token.is_none() {
+                    break;
+                }
+            }
+        }
+        if online.len() == instance_ids.len() || start.elapsed() > SSM_PING_TIMEOUT {
+            return Ok(online);
+        }
+        backoff.wait().await;
+    }
+}
+
+/// Check the free disk space and clock sync, and open a temporary listener on
+/// the russula ports so that the orchestrator can check they're reachable.
+fn health_check_cmds() -> Vec<String> {
+    let (first_port, last_port) = (
+        EndpointType::Server.russula_port(),
+        EndpointType::Router.russula_port(),
+    );
+    vec![
+        format!("avail=$(df --output=avail -k /home/ec2-user | tail -1); [ $avail -ge {MIN_DISK_AVAIL_KB} ] || {{ echo \"low disk space: $avail KB\"; exit 1; }}"),
+        format!("chronyc waitsync {CLOCK_SYNC_TRIES} {MAX_CLOCK_OFFSET_SEC} || {{ echo 'clock not synchronized'; exit 1; }}"),
+        format!("for port in $(seq {first_port} {last_port}); do (timeout {PORT_LISTENER_SEC} python3 -m http.server $port > /dev/null 2>&1 &); done"),
+    ]
+}
+
+/// The instances on which the command failed, once it has finished on all of
+/// `instance_ids`.
+fn failed_invocations(
+    instance_ids: &[String],
+    invocations: &[InvocationStatus],
+) -> Poll<BTreeSet<String>> {
+    let mut failed = BTreeSet::new();
+    for instance_id in instance_ids {
+        let status = invocations
+            .iter()
+            .find(|invocation| &invocation.instance_id == instance_id)
+            .map(|invocation| &invocation.status);
+        match status {
+            Some(CommandInvocationStatus::Success) => (),
+            None
+            | Some(CommandInvocationStatus::Delayed)
+            | Some(CommandInvocationStatus::InProgress)
+            | Some(CommandInvocationStatus::Pending) => return Poll::Pending,
+            Some(_) => {
+                failed.insert(instance_id.clone());
+            }
+        }
+    }
+    Poll::Ready(failed)
+}
+
+async fn russula_port_reachable(host: &InstanceDetail) -> bool {
+    let addr = (host.ip.as_str(), host.endpoint_type.russula_port());
+    let start = Instant::now();
+    while start.elapsed() < RUSSULA_PORT_TIMEOUT {
+        let connect = tokio::time::timeout(Duration::from_secs(5), TcpStream::connect(addr));
+        if let Ok(Ok(_stream)) = connect.await {
+            return true;
+        }
+        tokio::time::sleep(Duration::from_secs(1)).await;
+    }
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn invocation(instance_id: &str, status: CommandInvocationStatus) -> InvocationStatus {
+        InvocationStatus {
+            instance_id: instance_id.to_string(),
+            comment: "health_check".to_string(),
+            status,
+        }
+    }
+
+    #[test]
+    fn failed_health_check_invocations() {
+        let ids = vec!["i-1".to_string(), "i-2".to_string()];
+
+        // not yet listed for i-2
+        let invocations = [invocation("i-1", CommandInvocationStatus::Failed)];
+        assert!(failed_invocations(&ids, &invocations).is_pending());
+
+        let invocations = [
+            invocation("i-1", CommandInvocationStatus::Success),
+            invocation("i-2", CommandInvocationStatus::InProgress),
+        ];
+        assert!(failed_invocations(&ids, &invocations).is_pending());
+
+        let invocations = [
+            invocation("i-1", CommandInvocationStatus::Success),
+            invocation("i-2", CommandInvocationStatus::Failed),
+        ];
+        assert_eq!(
+            failed_invocations(&ids, &invocations),
+            Poll::Ready(BTreeSet::from(["i-2".to_string()]))
+        );
+    }
+}
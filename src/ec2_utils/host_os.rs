@@ -0,0 +1,286 @@
+// Copyright Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+use base64::{engine::general_purpose, Engine as _};
+use serde::{Deserialize, Serialize};
+
+/// The operating system of a host group.
+///
+/// Servers and routers always run Amazon Linux. Clients can run Windows Server
+/// to compare the client behavior of a driver across platforms.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HostOs {
+    #[default]
+    Linux,
+    Windows,
+}
+
+impl HostOs {
+    /// The public SSM parameter holding the latest AMI.
+    pub fn ami_parameter(&self) -> &'static str {
+        match self {
+            HostOs::Linux => {
+                "/aws/service/ami-amazon-linux-latest/al2023-ami-kernel-default-x86_64"
+            }
+            HostOs::Windows => {
+                "/aws/service/ami-windows-latest/Windows_Server-2022-English-Full-Base"
+            }
+        }
+    }
+
+    /// Terminate the host after `shutdown_min` even if the orchestrator dies.
+    pub fn user_data(&self, shutdown_min: u16) -> String {
+        let script = match self {
+            HostOs::Linux => format!("sudo shutdown -P +{}", shutdown_min),
+            HostOs::Windows => format!(
+                "<powershell>shutdown /s /t {}</powershell>",
+                shutdown_min as u32 * 60
+            ),
+        };
+        general_purpose::STANDARD.encode(script)
+    }
+
+    /// The SSM document which runs the commands of a step.
+    pub fn run_script_document(&self) -> &'static str {
+        match self {
+            HostOs::Linux => "AWS-RunShellScript",
+            HostOs::Windows => "AWS-RunPowerShellScript",
+        }
+    }
+
+    pub fn is_windows(&self) -> bool {
+        matches!(self, HostOs::Windows)
+    }
+}
+
+impl std::fmt::Display for HostOs {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            HostOs::Linux => write!(f, "linux"),
+            HostOs::Windows => write!(f, "windows"),
+        }
+    }
+}
+
+/// The distribution of the Linux hosts, so that the kernel and network stack
+/// of a distribution can be benchmarked.
+///
+/// Every profile runs the drivers and russula as `ec2-user`, which is created
+/// at boot on the distributions without it, so the paths on the hosts are the
+/// same for all distributions.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LinuxDistro {
+    #[default]
+    Al2023,
+    Ubuntu,
+}
+
+impl LinuxDistro {
+    /// The public SSM parameter holding the latest AMI.
+    pub fn ami_parameter(&self) -> &'static str {
+        match self {
+            LinuxDistro::Al2023 => HostOs::Linux.ami_parameter(),
+            LinuxDistro::Ubuntu => {
+                "/aws/service/canonical/ubuntu/server/24.04/stable/current/amd64/hvm/ebs-gp3/ami-id"
+            }
+        }
+    }
+
+    /// Terminate the host after `shutdown_min` even if the orchestrator dies.
+    ///
+    /// The `ec2-user` account is created before the SSM agent runs any step,
+    /// since the steps are ordered by markers in its home.
+    pub fn user_data(&self, shutdown_min: u16) -> String {
+        match self {
+            LinuxDistro::Al2023 => HostOs::Linux.user_data(shutdown_min),
+            LinuxDistro::Ubuntu => general_purpose::STANDARD.encode(format!(
+                "#!/bin/bash\nid ec2-user || useradd --create-home --shell /bin/bash ec2-user\nshutdown -P +{}\n",
+                shutdown_min
+            )),
+        }
+    }
+
+    /// The root device of the AMI, which is resized by the launch template.
+    pub fn root_device_name(&self) -> &'static str {
+        match self {
+            LinuxDistro::Al2023 => "/dev/xvda",
+            LinuxDistro::Ubuntu => "/dev/sda1",
+        }
+    }
+
+    /// The SSM document which configures the hosts, since the install
+    /// commands differ between distributions.
+    pub fn configure_document_name(&self) -> &'static str {
+        match self {
+            LinuxDistro::Al2023 => "netbench-configure-host",
+            LinuxDistro::Ubuntu => "netbench-configure-host-ubuntu",
+        }
+    }
+
+    /// Install the tools which the commands shared by all distributions rely
+    /// on, ex: the aws cli.
+    pub fn bootstrap_cmds(&self) -> Vec<String> {
+        match self {
+            // the AMI ships with the aws cli
+            LinuxDistro::Al2023 => vec![],
+            LinuxDistro::Ubuntu => {
+                vec!["command -v aws || snap install aws-cli --classic".to_string()]
+            }
+        }
+    }
+
+    pub fn upgrade_cmd(&self) -> String {
+        match self {
+            LinuxDistro::Al2023 => "yum upgrade -y".to_string(),
+            LinuxDistro::Ubuntu => {
+                "apt-get update && DEBIAN_FRONTEND=noninteractive apt-get upgrade -y".to_string()
+            }
+        }
+    }
+
+    pub fn install_cmd(&self, packages: &[&str]) -> String {
+        match self {
+            LinuxDistro::Al2023 => format!("yum install -y {}", packages.join(" ")),
+            LinuxDistro::Ubuntu => format!(
+                "DEBIAN_FRONTEND=noninteractive apt-get install -y {}",
+                packages.join(" ")
+            ),
+        }
+    }
+
+    /// The packages needed to build and profile the drivers and russula.
+    pub fn packages(&self) -> &'static [&'static str] {
+        match self {
+            LinuxDistro::Al2023 => &[
+                "cargo",
+                "cmake",
+                "git",
+                "perl",
+                "openssl-devel",
+                "bpftrace",
+                "perf",
+                "sysstat",
+                "tcpdump",
+                "iproute-tc",
+                "iptables-nft",
+                "kernel-modules-extra",
+                "tree",
+            ],
+            // perf and the netem modules are packaged per kernel
+            LinuxDistro::Ubuntu => &[
+                "build-essential",
+                "cargo",
+                "cmake",
+                "git",
+                "perl",
+                "pkg-config",
+                "libssl-dev",
+                "bpftrace",
+                "linux-tools-$(uname -r)",
+                "sysstat",
+                "tcpdump",
+                "iproute2",
+                "iptables",
+                "linux-modules-extra-$(uname -r)",
+                "tree",
+            ],
+        }
+    }
+
+    /// Install the CloudWatch agent, which Ubuntu doesn't package.
+    pub fn cloud_watch_agent_install_cmd(&self) -> String {
+        match self {
+            LinuxDistro::Al2023 => self.install_cmd(&["amazon-cloudwatch-agent"]),
+            LinuxDistro::Ubuntu => "curl -sSfLo /tmp/amazon-cloudwatch-agent.deb https://amazoncloudwatch-agent.s3.amazonaws.com/ubuntu/amd64/latest/amazon-cloudwatch-agent.deb && dpkg -i -E /tmp/amazon-cloudwatch-agent.deb".to_string(),
+        }
+    }
+}
+
+impl std::fmt::Display for LinuxDistro {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LinuxDistro::Al2023 => write!(f, "al2023"),
+            LinuxDistro::Ubuntu => write!(f, "ubuntu"),
+        }
+    }
+}
+
+/// Install a package unless it's already installed, on a host of any
+/// [`LinuxDistro`]. The package must have the same name on all of them.
+pub fn ensure_package_cmd(package: &str) -> String {
+    format!(
+        "if command -v dnf > /dev/null; then rpm -q {package} || dnf install -y {package}; else dpkg -s {package} > /dev/null || DEBIAN_FRONTEND=noninteractive apt-get install -y {package}; fi"
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn host_os() {
+        assert_eq!(HostOs::default(), HostOs::Linux);
+        assert_eq!(
+            serde_json::from_str::<HostOs>("\"windows\"").unwrap(),
+            HostOs::Windows
+        );
+        assert!(HostOs::Windows
+            .ami_parameter()
+            .starts_with("/aws/service/ami-windows-latest/"));
+
+        let user_data = general_purpose::STANDARD
+            .decode(HostOs::Windows.user_data(2))
+            .unwrap();
+        assert_eq!(
+            String::from_utf8(user_data).unwrap(),
+            "<powershell>shutdown /s /t 120</powershell>"
+        );
+        assert_eq!(
+            HostOs::Linux.user_data(2),
+            general_purpose::STANDARD.encode("sudo shutdown -P +2")
+        );
+        assert_eq!(
+            HostOs::Windows.run_script_document(),
+            "AWS-RunPowerShellScript"
+        );
+    }
+
+    #[test]
+    fn linux_distro() {
+        assert_eq!(LinuxDistro::default(), LinuxDistro::Al2023);
+        assert_eq!(
+            serde_json::from_str::<LinuxDistro>("\"ubuntu\"").unwrap(),
+            LinuxDistro::Ubuntu
+        );
+        assert!(serde_json::from_str::<LinuxDistro>("\"debian\"").is_err());
+        assert_eq!(
+            LinuxDistro::Al2023.ami_parameter(),
+            HostOs::Linux.ami_parameter()
+        );
+        assert!(LinuxDistro::Ubuntu
+            .ami_parameter()
+            .starts_with("/aws/service/canonical/ubuntu/"));
+        assert_eq!(
+            LinuxDistro::Al2023.install_cmd(&["mdadm", "tree"]),
+            "yum install -y mdadm tree"
+        );
+        assert_eq!(
+            LinuxDistro::Ubuntu.install_cmd(&["mdadm"]),
+            "DEBIAN_FRONTEND=noninteractive apt-get install -y mdadm"
+        );
+        assert!(LinuxDistro::Al2023.bootstrap_cmds().is_empty());
+        assert_eq!(LinuxDistro::Al2023.user_data(2), HostOs::Linux.user_data(2));
+        let user_data = general_purpose::STANDARD
+            .decode(LinuxDistro::Ubuntu.user_data(2))
+            .unwrap();
+        let user_data = String::from_utf8(user_data).unwrap();
+        assert!(user_data.contains("useradd --create-home"));
+        assert!(user_data.ends_with("shutdown -P +2\n"));
+        assert_ne!(
+            LinuxDistro::Al2023.configure_document_name(),
+            LinuxDistro::Ubuntu.configure_document_name()
+        );
+    }
+}
@@ -2,23 +2,34 @@
 // SPDX-License-Identifier: Apache-2.0
 
 use crate::{
+    config::HostGroup,
+    dashboard::timeline,
+    ec2_utils::{host_os::HostOs, launch_template::LaunchTemplate},
     error::{OrchError, OrchResult},
+    poll::Backoff,
     state::STATE,
     LaunchPlan,
 };
 use aws_sdk_ec2::types::{
-    BlockDeviceMapping, EbsBlockDevice, IamInstanceProfileSpecification, Instance,
+    AttributeBooleanValue, BlockDeviceMapping, EbsBlockDevice, Instance,
     InstanceNetworkInterfaceSpecification, InstanceStateName, InstanceType, ResourceType,
     ShutdownBehavior, Tag, TagSpecification,
 };
-use base64::{engine::general_purpose, Engine as _};
-use std::time::Duration;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 use tracing::info;
 
-#[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Debug)]
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Debug, Serialize, Deserialize)]
 pub enum EndpointType {
+    #[serde(alias = "server")]
     Server,
+    #[serde(alias = "client")]
     Client,
+    #[serde(alias = "router")]
+    Router,
+    // Launched and configured with the other hosts, without a russula Worker
+    #[serde(alias = "observer")]
+    Observer,
 }
 
 impl EndpointType {
@@ -26,61 +37,98 @@ impl EndpointType {
         match self {
             EndpointType::Server => "Server",
             EndpointType::Client => "Client",
+            EndpointType::Router => "Router",
+            EndpointType::Observer => "Observer",
         }
     }
+
+    /// The hosts of the role run a russula Worker.
+    pub fn has_worker(&self) -> bool {
+        !matches!(self, EndpointType::Observer)
+    }
+
+    /// The port the host group's russula Worker listens on. Each host group
+    /// uses a distinct port so that Workers can co-exist on a single host.
+    pub fn russula_port(&self) -> u16 {
+        let offset = match self {
+            EndpointType::Server => 0,
+            EndpointType::Client => 1,
+            EndpointType::Router => 2,
+            // unused
+            EndpointType::Observer => 3,
+        };
+        STATE.russula_port + offset
+    }
 }
 
-#[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Debug)]
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Debug, Serialize, Deserialize)]
 pub struct InstanceDetail {
     pub endpoint_type: EndpointType,
+    // The name of the host's group. Missing from the manifests of older runs
+    #[serde(default)]
+    pub host_group: String,
     pub instance_id: String,
     pub ip: String,
+    // Used to route traffic within the subnet
+    pub private_ip: String,
 }
 
 impl InstanceDetail {
-    pub fn new(endpoint_type: EndpointType, instance: Instance, ip: String) -> Self {
+    pub fn new(
+        endpoint_type: EndpointType,
+        host_group: &str,
+        instance: Instance,
+        (ip, private_ip): (String, String),
+    ) -> OrchResult<Self> {
         let instance_id = instance
             .instance_id()
             .ok_or(OrchError::Ec2 {
                 dbg: "No instance id".to_string(),
-            })
-            .unwrap()
+            })?
             .to_string();
 
-        InstanceDetail {
+        Ok(InstanceDetail {
             endpoint_type,
+            host_group: host_group.to_string(),
             instance_id,
             ip,
-        }
+            private_ip,
+        })
     }
 
     pub fn instance_id(&self) -> OrchResult<&str> {
         Ok(&self.instance_id)
     }
+
+    /// The name of the host's group, which defaults to its role.
+    pub fn group(&self) -> String {
+        if self.host_group.is_empty() {
+            self.endpoint_type.as_str().to_lowercase()
+        } else {
+            self.host_group.clone()
+        }
+    }
 }
 
 pub async fn launch_instance(
     ec2_client: &aws_sdk_ec2::Client,
-    launch_plan: &LaunchPlan<'_>,
+    launch_plan: &LaunchPlan,
     unique_id: &str,
     count: usize,
-    endpoint_type: EndpointType,
+    group: &HostGroup,
 ) -> OrchResult<Vec<Instance>> {
-    let instance_type = InstanceType::from(STATE.instance_type);
-    let run_result = ec2_client
+    let instance_type = InstanceType::from(launch_plan.instance_type(group));
+    let host_os = match group.role {
+        EndpointType::Client => launch_plan.client_os,
+        EndpointType::Server | EndpointType::Router | EndpointType::Observer => HostOs::Linux,
+    };
+    let launch_template = launch_plan.launch_template.as_ref().ok_or(OrchError::Ec2 {
+        dbg: "The launch template wasn't created".to_string(),
+    })?;
+    let mut run_instances = ec2_client
         .run_instances()
-        .key_name(STATE.ssh_key_name)
-        .iam_instance_profile(
-            IamInstanceProfileSpecification::builder()
-                .arn(&launch_plan.instance_profile_arn)
-                .build(),
-        )
+        .launch_template(launch_template.specification())
         .instance_type(instance_type)
-        .image_id(&launch_plan.ami_id)
-        .instance_initiated_shutdown_behavior(ShutdownBehavior::Terminate)
-        .user_data(
-            general_purpose::STANDARD.encode(format!("sudo shutdown -P +{}", STATE.shutdown_min)),
-        )
         // give the instances human readable names. name is set via tags
         .tag_specifications(
             TagSpecification::builder()
@@ -88,31 +136,46 @@ pub async fn launch_instance(
                 .tags(
                     Tag::builder()
                         .key("Name")
-                        .value(STATE.instance_name(unique_id, endpoint_type))
+                        .value(STATE.instance_name(unique_id, &group.name))
                         .build(),
                 )
                 .build(),
-        )
-        .block_device_mappings(
-            BlockDeviceMapping::builder()
-                .device_name("/dev/xvda")
-                .ebs(
-                    EbsBlockDevice::builder()
-                        .delete_on_termination(true)
-                        .volume_size(50)
-                        .build(),
-                )
-                .build(),
-        )
-        .network_interfaces(
-            InstanceNetworkInterfaceSpecification::builder()
-                .associate_public_ip_address(true)
-                .delete_on_termination(true)
-                .device_index(0)
-                .subnet_id(&launch_plan.subnet_id)
-                .groups(&launch_plan.security_group_id)
-                .build(),
-        )
+        );
+    if let LaunchTemplate::Existing { .. } = launch_template {
+        // the run's network and lifetime aren't part of an existing template
+        run_instances = run_instances
+            .instance_initiated_shutdown_behavior(ShutdownBehavior::Terminate)
+            .user_data(launch_plan.distro.user_data(launch_plan.shutdown_min))
+            .network_interfaces(
+                InstanceNetworkInterfaceSpecification::builder()
+                    .associate_public_ip_address(!launch_plan.private_network)
+                    .delete_on_termination(true)
+                    .device_index(0)
+                    .subnet_id(&launch_plan.subnet_id)
+                    .groups(&launch_plan.security_group_id)
+                    .build(),
+            );
+    }
+    if host_os.is_windows() {
+        // the launch template captures the Linux AMI, its user data and its
+        // root device
+        run_instances = run_instances
+            .image_id(&launch_plan.client_ami_id)
+            .user_data(host_os.user_data(launch_plan.shutdown_min))
+            .block_device_mappings(
+                BlockDeviceMapping::builder()
+                    .device_name("/dev/sda1")
+                    .ebs(
+                        // room for the msvc build tools
+                        EbsBlockDevice::builder()
+                            .delete_on_termination(true)
+                            .volume_size(100)
+                            .build(),
+                    )
+                    .build(),
+            );
+    }
+    let run_result = run_instances
         .min_count(count as i32)
         .max_count(count as i32)
         .dry_run(false)
@@ -131,57 +194,158 @@ pub async fn launch_instance(
 pub async fn delete_instance(ec2_client: &aws_sdk_ec2::Client, ids: Vec<String>) -> OrchResult<()> {
     ec2_client
         .terminate_instances()
-        .set_instance_ids(Some(ids))
+        .set_instance_ids(Some(ids.clone()))
         .send()
         .await
         .map_err(|err| OrchError::Ec2 {
             dbg: err.to_string(),
         })?;
+    for instance_id in ids {
+        timeline::record(timeline::Event::HostState {
+            instance_id,
+            state: InstanceStateName::ShuttingDown.as_str().to_string(),
+        });
+    }
     Ok(())
 }
 
-pub async fn poll_state(
-    enumerate: usize,
+// The instance ids described per call
+const DESCRIBE_BATCH: usize = 200;
+
+/// Describe `instance_ids`, in batches of [`DESCRIBE_BATCH`] and following
+/// the pagination of each batch, keyed by instance id.
+pub async fn describe_instances(
+    ec2_client: &aws_sdk_ec2::Client,
+    instance_ids: &[String],
+) -> OrchResult<BTreeMap<String, Instance>> {
+    let mut described = BTreeMap::new();
+    for batch in instance_ids.chunks(DESCRIBE_BATCH) {
+        let mut next_token = None;
+        loop {
+            let output = ec2_client
+                .describe_instances()
+                .set_instance_ids(Some(batch.to_vec()))
+                .set_next_token(next_token)
+                .send()
+                .await
+                .map_err(|err| OrchError::Ec2 {
+                    dbg: format!("Failed to describe instances {:?}: {}", batch, err),
+                })?;
+            for instance in output
+                .reservations()
+                .unwrap_or_default()
+                .iter()
+                .flat_map(|reservation| reservation.instances().unwrap_or_default())
+            {
+                if let Some(instance_id) = instance.instance_id() {
+                    described.insert(instance_id.to_string(), instance.clone());
+                }
+            }
+            next_token = output.next_token().map(String::from);
+            if next_token.is_none() {
+                break;
+            }
+        }
+    }
+    Ok(described)
+}
+
+/// Wait for all `instances` to reach `desired_state` and return the ip the
+/// orchestrator reaches each of them by, and its private ip, in the order of
+/// `instances`.
+///
+/// The instances are described together on each poll, rather than with a call
+/// per instance, so that large fleets aren't throttled.
+pub async fn poll_states(
     endpoint_type: &EndpointType,
     ec2_client: &aws_sdk_ec2::Client,
-    instance: &Instance,
+    instances: &[Instance],
     desired_state: InstanceStateName,
-) -> OrchResult<String> {
-    // Wait for running state
-    let mut actual_state = InstanceStateName::Pending;
-    let mut ip = None;
-    while actual_state != desired_state {
-        tokio::time::sleep(Duration::from_secs(1)).await;
-        let result = ec2_client
-            .describe_instances()
-            .instance_ids(instance.instance_id().unwrap())
-            .send()
-            .await
-            .unwrap();
-        let res = result.reservations().unwrap();
-        ip = res
-            .get(0)
-            .unwrap()
-            .instances()
-            .unwrap()
-            .get(0)
-            .unwrap()
-            .public_ip_address()
-            .map(String::from);
-        actual_state = res.get(0).unwrap().instances().unwrap()[0]
-            .state()
-            .unwrap()
-            .name()
-            .unwrap()
-            .clone();
-
-        info!(
-            "{:?} {} state: {:?}",
-            endpoint_type, enumerate, actual_state
-        );
+    private_network: bool,
+) -> OrchResult<Vec<(String, String)>> {
+    let instance_ids = instances
+        .iter()
+        .enumerate()
+        .map(|(enumerate, instance)| {
+            instance
+                .instance_id()
+                .map(String::from)
+                .ok_or(OrchError::Ec2 {
+                    dbg: format!("No instance id for {:?} {}", endpoint_type, enumerate),
+                })
+        })
+        .collect::<OrchResult<Vec<String>>>()?;
+
+    let mut recorded_states: BTreeMap<String, InstanceStateName> = BTreeMap::new();
+    let mut backoff = Backoff::ec2();
+    loop {
+        backoff.wait().await;
+        let described = describe_instances(ec2_client, &instance_ids).await?;
+        let mut ips = Vec::new();
+        let mut changed = false;
+        for (enumerate, instance_id) in instance_ids.iter().enumerate() {
+            let described = described.get(instance_id).ok_or(OrchError::Ec2Instance {
+                instance_id: instance_id.to_string(),
+                dbg: "Instance not found in describe_instances".to_string(),
+            })?;
+            let actual_state =
+                described
+                    .state()
+                    .and_then(|state| state.name())
+                    .ok_or(OrchError::Ec2Instance {
+                        instance_id: instance_id.to_string(),
+                        dbg: "Missing instance state".to_string(),
+                    })?;
+            if recorded_states.get(instance_id) != Some(actual_state) {
+                timeline::record(timeline::Event::HostState {
+                    instance_id: instance_id.to_string(),
+                    state: actual_state.as_str().to_string(),
+                });
+                info!(
+                    "{:?} {} state: {:?}",
+                    endpoint_type, enumerate, actual_state
+                );
+                recorded_states.insert(instance_id.to_string(), actual_state.clone());
+                changed = true;
+            }
+            if *actual_state != desired_state {
+                continue;
+            }
+
+            let private_ip = described.private_ip_address().map(String::from);
+            let ip = if private_network {
+                private_ip.clone()
+            } else {
+                described.public_ip_address().map(String::from)
+            };
+            ips.push(ip.zip(private_ip).ok_or(OrchError::Ec2Instance {
+                instance_id: instance_id.to_string(),
+                dbg: format!("No ip in state {:?}", actual_state),
+            })?);
+        }
+        if ips.len() == instance_ids.len() {
+            return Ok(ips);
+        }
+        if changed {
+            backoff.reset();
+        }
     }
+}
 
-    ip.ok_or(crate::error::OrchError::Ec2 {
-        dbg: "".to_string(),
-    })
+/// Allow a router to forward traffic which isn't addressed to it.
+pub async fn disable_source_dest_check(
+    ec2_client: &aws_sdk_ec2::Client,
+    instance_id: &str,
+) -> OrchResult<()> {
+    ec2_client
+        .modify_instance_attribute()
+        .instance_id(instance_id)
+        .source_dest_check(AttributeBooleanValue::builder().value(false).build())
+        .send()
+        .await
+        .map_err(|err| OrchError::Ec2Instance {
+            instance_id: instance_id.to_string(),
+            dbg: format!("Failed to disable source/dest check: {}", err),
+        })?;
+    Ok(())
 }
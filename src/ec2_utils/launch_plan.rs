@@ -2,157 +2,430 @@
 // SPDX-License-Identifier: Apache-2.0
 
 use crate::{
+    config::{Budget, HostGroup, Storage},
+    control_plane,
     ec2_utils::{
-        instance::{launch_instance, EndpointType, InstanceDetail},
-        poll_state,
+        host_os::{HostOs, LinuxDistro},
+        instance::{disable_source_dest_check, launch_instance, EndpointType, InstanceDetail},
+        launch_template::{create_launch_template, LaunchTemplate},
+        poll_states,
+        preflight::{check_subnet_ips, check_vcpu_quota},
+        vpc::RunVpc,
     },
     error::{OrchError, OrchResult},
-    InfraDetail, Scenario, STATE,
+    run_spec::RunGroup,
+    InfraDetail, OrchestratorConfig, Scenario, STATE,
 };
 use aws_sdk_ec2::types::{
     Filter, InstanceStateName, IpPermission, IpRange, ResourceType, TagSpecification,
 };
-use std::time::Duration;
-use tracing::info;
+use std::{collections::BTreeSet, net::Ipv4Addr, time::Duration};
+use tokio::process::Command;
+use tracing::{info, warn};
 
 #[derive(Clone)]
-pub struct LaunchPlan<'a> {
+pub struct LaunchPlan {
     pub subnet_id: String,
     pub security_group_id: String,
     pub ami_id: String,
+    // The distribution of the Linux hosts, which the `ami_id` is the latest
+    // AMI of
+    pub distro: LinuxDistro,
+    // The client hosts run a different AMI when they aren't Linux hosts
+    pub client_os: HostOs,
+    pub client_ami_id: String,
     pub instance_profile_arn: String,
-    pub scenario: &'a Scenario,
+    // CIDRs allowed to reach the hosts' ssh and russula ports
+    pub ingress_cidrs: Vec<String>,
+    // Launch the hosts without public ips
+    pub private_network: bool,
+    // Reach the hosts by their private ips from within their VPC
+    pub private_ips: bool,
+    // The infra is shared by all scenarios so launch enough hosts for the
+    // largest one.
+    pub host_groups: Vec<HostGroup>,
+    pub budget: Budget,
+    pub instance_type: String,
+    // The hosts shutdown (and terminate) after this long
+    pub shutdown_min: u16,
+    // The root volume and instance store of the Linux hosts
+    pub storage: Storage,
+    // Created by `launch` unless an existing template is set in the config
+    pub launch_template: Option<LaunchTemplate>,
+    // The network created for the run with `dedicated_vpc`
+    pub vpc: Option<RunVpc>,
 }
 
-impl<'a> LaunchPlan<'a> {
+impl LaunchPlan {
     pub async fn create(
         unique_id: &str,
         ec2_client: &aws_sdk_ec2::Client,
         iam_client: &aws_sdk_iam::Client,
         ssm_client: &aws_sdk_ssm::Client,
-        scenario: &'a Scenario,
-    ) -> Self {
-        let instance_profile_arn = get_instance_profile(iam_client).await.unwrap();
-        let (subnet_id, vpc_id) = get_subnet_vpc_ids(ec2_client).await.unwrap();
-        let ami_id = get_latest_ami(ssm_client).await.unwrap();
-        // Create a security group
-        let security_group_id = create_security_group(ec2_client, &vpc_id, unique_id)
-            .await
-            .unwrap();
-
-        LaunchPlan {
+        group: &RunGroup,
+        config: &OrchestratorConfig,
+    ) -> OrchResult<Self> {
+        let instance_type = group.instance_type.as_str();
+        let distro = group.distro;
+        let private_network = config.private_network.as_ref();
+        let private_ips = config.control_plane.private_ips;
+        let ingress_cidrs = match private_network {
+            // russula is relayed over ssm so the hosts don't accept any external traffic
+            Some(_) => Vec::new(),
+            None if private_ips && config.ingress_cidrs.is_empty() => {
+                vec![format!("{}/32", orchestrator_private_ip()?)]
+            }
+            None if config.ingress_cidrs.is_empty() => {
+                vec![format!("{}/32", detect_public_ip().await?)]
+            }
+            None => config.ingress_cidrs.clone(),
+        };
+        info!("Restricting ingress to: {:?}", ingress_cidrs);
+
+        let subnet_tag = private_network.map_or(STATE.subnet_tag_value, |private_network| {
+            (
+                private_network.subnet_tag.0.as_str(),
+                private_network.subnet_tag.1.as_str(),
+            )
+        });
+        let instance_profile_arn = get_instance_profile(iam_client).await?;
+        let host_groups = host_groups(&config.host_groups, &group.scenarios)?;
+        // abort before creating any resources
+        let instances = host_groups.iter().map(|group| group.count).sum();
+        let mut instance_types = BTreeSet::new();
+        for group in host_groups.iter() {
+            instance_types.insert(group.instance_type.as_deref().unwrap_or(instance_type));
+        }
+        for group_type in instance_types {
+            config.budget.check(instances, group_type)?;
+            let group_instances = host_groups
+                .iter()
+                .filter(|group| {
+                    group.instance_type.as_deref().unwrap_or(instance_type) == group_type
+                })
+                .map(|group| group.count)
+                .sum();
+            check_vcpu_quota(ec2_client, group_type, group_instances).await?;
+        }
+        let ami_id = get_latest_ami(ssm_client, distro.ami_parameter()).await?;
+        let client_ami_id = match config.client_os {
+            HostOs::Linux => ami_id.clone(),
+            client_os => get_latest_ami(ssm_client, client_os.ami_parameter()).await?,
+        };
+
+        let vpc = match &config.dedicated_vpc {
+            Some(dedicated_vpc) => {
+                Some(RunVpc::create(ec2_client, unique_id, &dedicated_vpc.cidr).await?)
+            }
+            None => None,
+        };
+        let network = async {
+            let (subnet_id, vpc_id) = match &vpc {
+                Some(vpc) => vpc.subnet_vpc_ids()?,
+                None => {
+                    let (subnet_id, vpc_id) = get_subnet_vpc_ids(ec2_client, subnet_tag).await?;
+                    if private_network.is_some() {
+                        check_vpc_endpoints(ec2_client, &vpc_id).await?;
+                    }
+                    check_subnet_ips(ec2_client, &subnet_id, instances).await?;
+                    (subnet_id, vpc_id)
+                }
+            };
+            // Create a security group
+            let security_group_id = create_security_group(ec2_client, &vpc_id, unique_id).await?;
+            Ok::<_, OrchError>((subnet_id, security_group_id))
+        }
+        .await;
+        let (subnet_id, security_group_id) = match (network, &vpc) {
+            (Ok(network), _) => network,
+            (Err(err), Some(vpc)) => {
+                // don't leave the run's network behind
+                if let Err(delete_err) = vpc.delete(ec2_client).await {
+                    warn!("Failed to delete the run's vpc: {}", delete_err);
+                }
+                return Err(err);
+            }
+            (Err(err), None) => return Err(err),
+        };
+
+        Ok(LaunchPlan {
             ami_id,
+            distro,
+            client_os: config.client_os,
+            client_ami_id,
             subnet_id,
             security_group_id,
             instance_profile_arn,
-            scenario,
-        }
+            ingress_cidrs,
+            private_network: private_network.is_some(),
+            private_ips,
+            host_groups,
+            budget: config.budget.clone(),
+            instance_type: instance_type.to_string(),
+            shutdown_min: STATE.shutdown_min,
+            storage: config.storage.clone(),
+            launch_template: config
+                .launch_template
+                .clone()
+                .map(|name| LaunchTemplate::Existing { name }),
+            vpc,
+        })
     }
 
     pub async fn launch(
-        &self,
+        &mut self,
         ec2_client: &aws_sdk_ec2::Client,
         unique_id: &str,
     ) -> OrchResult<InfraDetail> {
-        let servers = launch_instance(
-            ec2_client,
-            self,
-            unique_id,
-            self.scenario.servers,
-            EndpointType::Server,
-        )
-        .await?;
-
-        let clients = launch_instance(
-            ec2_client,
-            self,
-            unique_id,
-            self.scenario.clients,
-            EndpointType::Client,
-        )
-        .await?;
+        if self.launch_template.is_none() {
+            let id = create_launch_template(ec2_client, self, unique_id).await?;
+            self.launch_template = Some(LaunchTemplate::Owned { id });
+        }
+        let mut launched = Vec::new();
+        for group in self.host_groups.iter() {
+            let instances =
+                launch_instance(ec2_client, self, unique_id, group.count, group).await?;
+            launched.push((group, instances));
+        }
 
         let mut infra = InfraDetail {
             security_group_id: self.security_group_id.clone(),
+            launch_template_id: self
+                .launch_template
+                .as_ref()
+                .and_then(LaunchTemplate::owned_id)
+                .map(String::from),
+            vpc: self.vpc.clone(),
             clients: Vec::new(),
             servers: Vec::new(),
+            routers: Vec::new(),
+            observers: Vec::new(),
         };
-        for (i, server) in servers.into_iter().enumerate() {
-            let endpoint_type = EndpointType::Server;
-            let server_ip = poll_state(
-                i,
-                &endpoint_type,
+        for (group, instances) in launched {
+            let ips = poll_states(
+                &group.role,
                 ec2_client,
-                &server,
+                &instances,
                 InstanceStateName::Running,
+                self.reach_private_ips(),
             )
             .await?;
-
-            let server = InstanceDetail::new(endpoint_type, server, server_ip);
-            infra.servers.push(server);
+            for (instance, ip) in instances.into_iter().zip(ips) {
+                let host = InstanceDetail::new(group.role.clone(), &group.name, instance, ip)?;
+                if host.endpoint_type == EndpointType::Router {
+                    disable_source_dest_check(ec2_client, &host.instance_id).await?;
+                }
+                infra.hosts_mut(&group.role).push(host);
+            }
         }
 
-        for (i, client) in clients.into_iter().enumerate() {
-            let endpoint_type = EndpointType::Client;
-            let client_ip = poll_state(
-                i,
-                &endpoint_type,
-                ec2_client,
-                &client,
-                InstanceStateName::Running,
-            )
-            .await?;
-
-            let client = InstanceDetail::new(endpoint_type, client, client_ip);
-            infra.clients.push(client);
-        }
-
-        configure_networking(ec2_client, &infra).await?;
+        configure_networking(ec2_client, &infra, &self.ingress_cidrs).await?;
 
         // wait for instance to spawn
         tokio::time::sleep(Duration::from_secs(50)).await;
 
         Ok(infra)
     }
+
+    /// The instance type of the hosts of `group`.
+    pub fn instance_type<'a>(&'a self, group: &'a HostGroup) -> &'a str {
+        group
+            .instance_type
+            .as_deref()
+            .unwrap_or(&self.instance_type)
+    }
+
+    /// The group `host` was launched in.
+    pub fn host_group(&self, host: &InstanceDetail) -> OrchResult<&HostGroup> {
+        self.host_groups
+            .iter()
+            .find(|group| group.name == host.group())
+            .ok_or(OrchError::Ec2 {
+                dbg: format!("No host group {} for {}", host.group(), host.instance_id),
+            })
+    }
+
+    /// The number of hosts launched.
+    /// Whether the orchestrator reaches the hosts, and russula, by their
+    /// private ips.
+    fn reach_private_ips(&self) -> bool {
+        self.private_network || self.private_ips
+    }
+
+    pub fn instances(&self) -> usize {
+        self.host_groups.iter().map(|group| group.count).sum()
+    }
+
+    /// Launch a single host to replace an unhealthy `host`, in the same group,
+    /// and allow traffic between it and the other hosts.
+    pub async fn launch_replacement(
+        &self,
+        ec2_client: &aws_sdk_ec2::Client,
+        unique_id: &str,
+        security_group_id: &str,
+        host: &InstanceDetail,
+    ) -> OrchResult<InstanceDetail> {
+        let group = self.host_group(host)?;
+        let instance = launch_instance(ec2_client, self, unique_id, 1, group)
+            .await?
+            .pop()
+            .ok_or(OrchError::Ec2 {
+                dbg: format!("No replacement {} instance launched", group.name),
+            })?;
+        let ip = poll_states(
+            &group.role,
+            ec2_client,
+            std::slice::from_ref(&instance),
+            InstanceStateName::Running,
+            self.reach_private_ips(),
+        )
+        .await?
+        .remove(0);
+        let replacement = InstanceDetail::new(group.role.clone(), &group.name, instance, ip)?;
+        if replacement.endpoint_type == EndpointType::Router {
+            disable_source_dest_check(ec2_client, &replacement.instance_id).await?;
+        }
+        authorize_hosts(ec2_client, security_group_id, &[&replacement]).await?;
+        Ok(replacement)
+    }
 }
 
+/// The groups of hosts launched for `scenarios`: the `configured` groups, and a
+/// group named after each of the server, client and router roles without a
+/// configured group, sized for the largest scenario.
+///
+/// Errors if the configured groups of a role have fewer hosts than a scenario
+/// needs.
+pub fn host_groups(configured: &[HostGroup], scenarios: &[Scenario]) -> OrchResult<Vec<HostGroup>> {
+    let mut groups = configured.to_vec();
+    for (role, needed) in [
+        (
+            EndpointType::Server,
+            scenarios.iter().map(|s| s.servers).max(),
+        ),
+        (
+            EndpointType::Client,
+            scenarios.iter().map(|s| s.clients).max(),
+        ),
+        (
+            EndpointType::Router,
+            scenarios.iter().map(|s| s.routers).max(),
+        ),
+    ] {
+        let needed = needed.unwrap_or(0);
+        let role_groups: Vec<&HostGroup> = configured
+            .iter()
+            .filter(|group| group.role == role)
+            .collect();
+        let name = role.as_str().to_lowercase();
+        if role_groups.is_empty() {
+            if needed > 0 {
+                groups.push(HostGroup {
+                    name,
+                    role,
+                    count: needed,
+                    instance_type: None,
+                });
+            }
+            continue;
+        }
+        let count: usize = role_groups.iter().map(|group| group.count).sum();
+        if count < needed {
+            return Err(OrchError::Init {
+                dbg: format!(
+                    "The {} host groups have {} hosts but a scenario needs {}",
+                    name, count, needed
+                ),
+            });
+        }
+    }
+    Ok(groups)
+}
+
+/// Allow all traffic between the run's hosts, and only allow the orchestrator
+/// (`ingress_cidrs`) to reach the ssh and russula ports.
 async fn configure_networking(
     ec2_client: &aws_sdk_ec2::Client,
     infra: &InfraDetail,
+    ingress_cidrs: &[String],
 ) -> OrchResult<()> {
-    let host_ip_ranges: Vec<IpRange> = infra
-        .clients
-        .iter()
-        .chain(infra.servers.iter())
-        .map(|instance_detail| {
-            info!(
-                "{:?}: {} -- {}",
-                instance_detail.endpoint_type,
-                instance_detail.instance_id().unwrap(),
-                instance_detail.ip
-            );
+    let hosts: Vec<&InstanceDetail> = infra.hosts().collect();
+    authorize_hosts(ec2_client, &infra.security_group_id, &hosts).await?;
 
-            IpRange::builder()
-                .cidr_ip(format!("{}/32", instance_detail.ip))
-                .build()
-        })
+    // empty for a private network
+    if ingress_cidrs.is_empty() {
+        return Ok(());
+    }
+    let orchestrator_ip_ranges: Vec<IpRange> = ingress_cidrs
+        .iter()
+        .map(|cidr| IpRange::builder().cidr_ip(cidr).build())
         .collect();
+    let russula_ports = (
+        EndpointType::Server.russula_port(),
+        EndpointType::Router.russula_port(),
+    );
+    let mut orchestrator_ip_permissions = Vec::new();
+    for ((from_port, to_port), protocol) in [
+        ((22, 22), "tcp"),
+        (russula_ports, "tcp"),
+        (russula_ports, "udp"),
+    ] {
+        orchestrator_ip_permissions.push(
+            IpPermission::builder()
+                .from_port(from_port.into())
+                .to_port(to_port.into())
+                .ip_protocol(protocol)
+                .set_ip_ranges(Some(orchestrator_ip_ranges.clone()))
+                .build(),
+        );
+    }
+    ec2_client
+        .authorize_security_group_ingress()
+        .group_id(infra.security_group_id.clone())
+        .set_ip_permissions(Some(orchestrator_ip_permissions))
+        .send()
+        .await
+        .map_err(|err| OrchError::Ec2 {
+            dbg: err.to_string(),
+        })?;
 
-    let ssh_ip_range = IpRange::builder().cidr_ip("0.0.0.0/0").build();
-    // TODO can we make this more restrictive?
-    let russula_ip_range = IpRange::builder().cidr_ip("0.0.0.0/0").build();
+    Ok(())
+}
+
+/// Allow all traffic to and from `hosts` within the security group.
+async fn authorize_hosts(
+    ec2_client: &aws_sdk_ec2::Client,
+    security_group_id: &str,
+    hosts: &[&InstanceDetail],
+) -> OrchResult<()> {
+    let mut host_ips = BTreeSet::new();
+    for instance_detail in hosts {
+        info!(
+            "{:?}: {} -- {}",
+            instance_detail.endpoint_type,
+            instance_detail.instance_id().unwrap_or("unknown"),
+            instance_detail.ip
+        );
+        host_ips.insert(&instance_detail.ip);
+        // routed traffic arrives from the private ips
+        host_ips.insert(&instance_detail.private_ip);
+    }
+    let host_ip_permission = IpPermission::builder()
+        .from_port(-1)
+        .to_port(-1)
+        .ip_protocol("-1")
+        .set_ip_ranges(Some(
+            host_ips
+                .into_iter()
+                .map(|ip| IpRange::builder().cidr_ip(format!("{}/32", ip)).build())
+                .collect(),
+        ))
+        .build();
 
     ec2_client
         .authorize_security_group_egress()
-        .group_id(infra.security_group_id.clone())
-        .ip_permissions(
-            IpPermission::builder()
-                .from_port(-1)
-                .to_port(-1)
-                .ip_protocol("-1")
-                .set_ip_ranges(Some(host_ip_ranges.clone()))
-                .build(),
-        )
+        .group_id(security_group_id)
+        .ip_permissions(host_ip_permission.clone())
         .send()
         .await
         .map_err(|err| OrchError::Ec2 {
@@ -160,31 +433,8 @@ async fn configure_networking(
         })?;
     ec2_client
         .authorize_security_group_ingress()
-        .group_id(infra.security_group_id.clone())
-        .ip_permissions(
-            IpPermission::builder()
-                .from_port(-1)
-                .to_port(-1)
-                .ip_protocol("-1")
-                .set_ip_ranges(Some(host_ip_ranges.clone()))
-                .build(),
-        )
-        .ip_permissions(
-            IpPermission::builder()
-                .from_port(22)
-                .to_port(22)
-                .ip_protocol("tcp")
-                .ip_ranges(ssh_ip_range)
-                .build(),
-        )
-        .ip_permissions(
-            IpPermission::builder()
-                .from_port(STATE.russula_port.into())
-                .to_port(STATE.russula_port.into())
-                .ip_protocol("tcp")
-                .ip_ranges(russula_ip_range)
-                .build(),
-        )
+        .group_id(security_group_id)
+        .ip_permissions(host_ip_permission)
         .send()
         .await
         .map_err(|err| OrchError::Ec2 {
@@ -194,6 +444,66 @@ async fn configure_networking(
     Ok(())
 }
 
+// Responds with the public ip of the caller
+const CHECK_IP_URL: &str = "https://checkip.amazonaws.com";
+
+/// The orchestrator's own private ip, when it runs in the hosts' region.
+fn orchestrator_private_ip() -> OrchResult<Ipv4Addr> {
+    let deployment = control_plane::deployment();
+    if deployment.region.as_deref() != Some(STATE.vpc_region) {
+        return Err(OrchError::Init {
+            dbg: format!(
+                "control_plane private_ips requires the orchestrator to run in {}, not {:?} ({})",
+                STATE.vpc_region,
+                deployment.region,
+                deployment.platform.as_str()
+            ),
+        });
+    }
+    deployment.private_ip.ok_or(OrchError::Init {
+        dbg: "Failed to detect the orchestrator's private ip. Set `ingress_cidrs` in the config"
+            .to_string(),
+    })
+}
+
+/// Detect the public ip of the orchestrator, over https so that the security
+/// group isn't opened to an ip injected on the way.
+///
+/// Set `ingress_cidrs` in the config if the orchestrator's traffic doesn't
+/// egress from a single ip (proxy, etc).
+async fn detect_public_ip() -> OrchResult<Ipv4Addr> {
+    let output = Command::new("curl")
+        .args(["--silent", "--show-error", "--fail", "--max-time", "10"])
+        .arg(CHECK_IP_URL)
+        .output()
+        .await
+        .map_err(|err| OrchError::Init {
+            dbg: format!(
+                "Failed to run curl to detect the orchestrator's public ip. Set `ingress_cidrs` in the config: {}",
+                err
+            ),
+        })?;
+    if !output.status.success() {
+        return Err(OrchError::Init {
+            dbg: format!(
+                "Failed to detect the orchestrator's public ip. Set `ingress_cidrs` in the config: {}",
+                String::from_utf8_lossy(&output.stderr)
+            ),
+        });
+    }
+    let body = String::from_utf8_lossy(&output.stdout);
+    parse_check_ip(&body).ok_or(OrchError::Init {
+        dbg: format!(
+            "Unexpected response from {}. Set `ingress_cidrs` in the config: {}",
+            CHECK_IP_URL, body
+        ),
+    })
+}
+
+fn parse_check_ip(body: &str) -> Option<Ipv4Addr> {
+    body.trim().parse().ok()
+}
+
 async fn create_security_group(
     ec2_client: &aws_sdk_ec2::Client,
     vpc_id: &str,
@@ -221,7 +531,9 @@ async fn create_security_group(
             dbg: err.to_string(),
         })?
         .group_id()
-        .expect("expected security_group_id")
+        .ok_or(OrchError::Ec2 {
+            dbg: "Missing security_group_id".to_string(),
+        })?
         .into();
     Ok(security_group_id)
 }
@@ -236,17 +548,24 @@ async fn get_instance_profile(iam_client: &aws_sdk_iam::Client) -> OrchResult<St
             dbg: err.to_string(),
         })?
         .instance_profile()
-        .unwrap()
-        .arn()
-        .unwrap()
+        .and_then(|profile| profile.arn())
+        .ok_or(OrchError::Iam {
+            dbg: format!(
+                "Missing arn for instance profile {}",
+                STATE.instance_profile
+            ),
+        })?
         .into();
     Ok(instance_profile_arn)
 }
 
-async fn get_latest_ami(ssm_client: &aws_sdk_ssm::Client) -> OrchResult<String> {
+async fn get_latest_ami(
+    ssm_client: &aws_sdk_ssm::Client,
+    ami_parameter: &str,
+) -> OrchResult<String> {
     let ami_id = ssm_client
         .get_parameter()
-        .name("/aws/service/ami-amazon-linux-latest/al2023-ami-kernel-default-x86_64")
+        .name(ami_parameter)
         .with_decryption(true)
         .send()
         .await
@@ -254,9 +573,10 @@ async fn get_latest_ami(ssm_client: &aws_sdk_ssm::Client) -> OrchResult<String>
             dbg: err.to_string(),
         })?
         .parameter()
-        .expect("expected ami value")
-        .value()
-        .expect("expected ami value")
+        .and_then(|parameter| parameter.value())
+        .ok_or(OrchError::Ssm {
+            dbg: "Missing ami value".to_string(),
+        })?
         .into();
     Ok(ami_id)
 }
@@ -268,13 +588,16 @@ async fn get_latest_ami(ssm_client: &aws_sdk_ssm::Client) -> OrchResult<String>
 //      There is some connection between Security Groups and
 //      Subnets such that they have to be "in the same network"
 //       I'm unclear here.
-async fn get_subnet_vpc_ids(ec2_client: &aws_sdk_ec2::Client) -> OrchResult<(String, String)> {
+async fn get_subnet_vpc_ids(
+    ec2_client: &aws_sdk_ec2::Client,
+    subnet_tag: (&str, &str),
+) -> OrchResult<(String, String)> {
     let describe_subnet_output = ec2_client
         .describe_subnets()
         .filters(
             Filter::builder()
-                .name(STATE.subnet_tag_value.0)
-                .values(STATE.subnet_tag_value.1)
+                .name(subnet_tag.0)
+                .values(subnet_tag.1)
                 .build(),
         )
         .send()
@@ -282,12 +605,19 @@ async fn get_subnet_vpc_ids(ec2_client: &aws_sdk_ec2::Client) -> OrchResult<(Str
         .map_err(|e| OrchError::Ec2 {
             dbg: format!("Couldn't describe subnets: {:#?}", e),
         })?;
-    assert_eq!(
-        describe_subnet_output.subnets().expect("No subnets?").len(),
-        1
-    );
+    let subnets = describe_subnet_output.subnets().unwrap_or_default();
+    if subnets.len() != 1 {
+        return Err(OrchError::Ec2 {
+            dbg: format!(
+                "Expected exactly 1 subnet tagged {}={} but found {}",
+                subnet_tag.0,
+                subnet_tag.1,
+                subnets.len()
+            ),
+        });
+    }
 
-    let subnet = &describe_subnet_output.subnets().unwrap()[0];
+    let subnet = &subnets[0];
     let subnet_id = subnet.subnet_id().ok_or(OrchError::Ec2 {
         dbg: "Couldn't find subnet".into(),
     })?;
@@ -296,3 +626,94 @@ async fn get_subnet_vpc_ids(ec2_client: &aws_sdk_ec2::Client) -> OrchResult<(Str
     })?;
     Ok((subnet_id.into(), vpc_id.into()))
 }
+
+// The hosts in a private subnet reach these services through VPC endpoints
+const PRIVATE_NETWORK_SERVICES: [&str; 4] = ["ssm", "ssmmessages", "ec2messages", "s3"];
+
+/// Warn about missing VPC endpoints, which are required in a private subnet
+/// without a NAT gateway.
+async fn check_vpc_endpoints(ec2_client: &aws_sdk_ec2::Client, vpc_id: &str) -> OrchResult<()> {
+    let endpoints = ec2_client
+        .describe_vpc_endpoints()
+        .filters(Filter::builder().name("vpc-id").values(vpc_id).build())
+        .send()
+        .await
+        .map_err(|err| OrchError::Ec2 {
+            dbg: format!("Couldn't describe vpc endpoints: {}", err),
+        })?;
+    let service_names: Vec<&str> = endpoints
+        .vpc_endpoints()
+        .unwrap_or_default()
+        .iter()
+        .filter_map(|endpoint| endpoint.service_name())
+        .collect();
+    for service in PRIVATE_NETWORK_SERVICES {
+        let service_name = format!("com.amazonaws.{}.{}", STATE.vpc_region, service);
+        if !service_names.contains(&service_name.as_str()) {
+            warn!(
+                "No vpc endpoint for {} in {}. The hosts need a NAT gateway to reach it",
+                service_name, vpc_id
+            );
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn check_ip_response() {
+        assert_eq!(
+            parse_check_ip("203.0.113.7\n"),
+            Some(Ipv4Addr::new(203, 0, 113, 7))
+        );
+        assert_eq!(parse_check_ip(""), None);
+        assert_eq!(parse_check_ip("<html>captive portal</html>"), None);
+        assert_eq!(parse_check_ip("2001:db8::1"), None);
+    }
+
+    #[test]
+    fn resolve_host_groups() {
+        let scenario = |servers: usize, clients: usize| Scenario {
+            name: "request_response.json".to_string(),
+            path: std::path::PathBuf::from("request_response.json"),
+            servers,
+            clients,
+            routers: 0,
+        };
+        let scenarios = [scenario(1, 2), scenario(2, 1)];
+        let group = |name: &str, role: EndpointType, count: usize| HostGroup {
+            name: name.to_string(),
+            role,
+            count,
+            instance_type: None,
+        };
+
+        // a group per role, without routers
+        let groups = host_groups(&[], &scenarios).unwrap();
+        assert_eq!(
+            groups,
+            [
+                group("server", EndpointType::Server, 2),
+                group("client", EndpointType::Client, 2)
+            ]
+        );
+
+        let configured = [
+            group("client-small", EndpointType::Client, 1),
+            group("client-large", EndpointType::Client, 1),
+            group("observer", EndpointType::Observer, 1),
+        ];
+        let groups = host_groups(&configured, &scenarios).unwrap();
+        let names: Vec<&str> = groups.iter().map(|group| group.name.as_str()).collect();
+        assert_eq!(
+            names,
+            ["client-small", "client-large", "observer", "server"]
+        );
+
+        // the scenarios need 2 clients
+        assert!(host_groups(&configured[..1], &scenarios).is_err());
+    }
+}
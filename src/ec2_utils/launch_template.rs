@@ -0,0 +1,175 @@
+// Copyright Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::{
+    error::{OrchError, OrchResult},
+    state::STATE,
+    LaunchPlan,
+};
+use aws_sdk_ec2::types::{
+    LaunchTemplateBlockDeviceMappingRequest, LaunchTemplateEbsBlockDeviceRequest,
+    LaunchTemplateIamInstanceProfileSpecificationRequest,
+    LaunchTemplateInstanceNetworkInterfaceSpecificationRequest, LaunchTemplateSpecification,
+    LaunchTemplateTagSpecificationRequest, RequestLaunchTemplateData, ResourceType,
+    ShutdownBehavior, Tag, TagSpecification, VolumeType,
+};
+use tracing::info;
+
+/// The launch template the hosts of a run are launched from.
+#[derive(Clone, Debug, PartialEq)]
+pub enum LaunchTemplate {
+    // Created from the launch plan and deleted with the run's hosts
+    Owned { id: String },
+    // An existing template from the config. Only the run's network, lifetime
+    // and instance type are set by the orchestrator
+    Existing { name: String },
+}
+
+impl LaunchTemplate {
+    pub fn specification(&self) -> LaunchTemplateSpecification {
+        match self {
+            LaunchTemplate::Owned { id } => LaunchTemplateSpecification::builder()
+                .launch_template_id(id)
+                .version("$Latest")
+                .build(),
+            LaunchTemplate::Existing { name } => LaunchTemplateSpecification::builder()
+                .launch_template_name(name)
+                .version("$Default")
+                .build(),
+        }
+    }
+
+    /// The id of the template if it was created for the run.
+    pub fn owned_id(&self) -> Option<&str> {
+        match self {
+            LaunchTemplate::Owned { id } => Some(id),
+            LaunchTemplate::Existing { .. } => None,
+        }
+    }
+}
+
+/// Launch template names can't contain the `:` of the rfc3339 unique_id.
+fn template_name(unique_id: &str) -> String {
+    STATE.security_group_name(unique_id).replace(':', "-")
+}
+
+/// Create a launch template capturing the AMI, instance profile, network, tags
+/// and user data of the run's hosts.
+pub async fn create_launch_template(
+    ec2_client: &aws_sdk_ec2::Client,
+    launch_plan: &LaunchPlan,
+    unique_id: &str,
+) -> OrchResult<String> {
+    let name = template_name(unique_id);
+    let template_data = RequestLaunchTemplateData::builder()
+        .key_name(STATE.ssh_key_name)
+        .iam_instance_profile(
+            LaunchTemplateIamInstanceProfileSpecificationRequest::builder()
+                .arn(&launch_plan.instance_profile_arn)
+                .build(),
+        )
+        .image_id(&launch_plan.ami_id)
+        .instance_initiated_shutdown_behavior(ShutdownBehavior::Terminate)
+        .user_data(launch_plan.distro.user_data(launch_plan.shutdown_min))
+        .block_device_mappings(
+            LaunchTemplateBlockDeviceMappingRequest::builder()
+                .device_name(launch_plan.distro.root_device_name())
+                .ebs(
+                    LaunchTemplateEbsBlockDeviceRequest::builder()
+                        .delete_on_termination(true)
+                        .volume_size(launch_plan.storage.root_volume_gb() as i32)
+                        .volume_type(VolumeType::from(launch_plan.storage.volume_type.as_str()))
+                        .set_iops(launch_plan.storage.iops.map(|iops| iops as i32))
+                        .set_throughput(
+                            launch_plan
+                                .storage
+                                .throughput
+                                .map(|throughput| throughput as i32),
+                        )
+                        .build(),
+                )
+                .build(),
+        )
+        .network_interfaces(
+            LaunchTemplateInstanceNetworkInterfaceSpecificationRequest::builder()
+                .associate_public_ip_address(!launch_plan.private_network)
+                .delete_on_termination(true)
+                .device_index(0)
+                .subnet_id(&launch_plan.subnet_id)
+                .groups(&launch_plan.security_group_id)
+                .build(),
+        )
+        // the instances are named by endpoint type when they are launched
+        .tag_specifications(
+            LaunchTemplateTagSpecificationRequest::builder()
+                .resource_type(ResourceType::Volume)
+                .tags(Tag::builder().key("Name").value(&name).build())
+                .build(),
+        )
+        .build();
+
+    let id = ec2_client
+        .create_launch_template()
+        .launch_template_name(&name)
+        .version_description("netbench run")
+        .launch_template_data(template_data)
+        .tag_specifications(
+            TagSpecification::builder()
+                .resource_type(ResourceType::LaunchTemplate)
+                .tags(Tag::builder().key("Name").value(&name).build())
+                .build(),
+        )
+        .send()
+        .await
+        .map_err(|err| OrchError::Ec2 {
+            dbg: format!("Failed to create launch template {}: {}", name, err),
+        })?
+        .launch_template()
+        .and_then(|template| template.launch_template_id())
+        .ok_or(OrchError::Ec2 {
+            dbg: format!("Missing id of launch template {}", name),
+        })?
+        .to_string();
+    info!("Created launch template {} {}", name, id);
+    Ok(id)
+}
+
+pub async fn delete_launch_template(ec2_client: &aws_sdk_ec2::Client, id: &str) -> OrchResult<()> {
+    info!("Start: deleting launch template");
+    ec2_client
+        .delete_launch_template()
+        .launch_template_id(id)
+        .send()
+        .await
+        .map_err(|err| OrchError::Ec2 {
+            dbg: format!("Failed to delete launch template {}: {}", id, err),
+        })?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn launch_template() {
+        assert_eq!(
+            template_name("2023-10-11T17:05:09Z-v2.0.0"),
+            "netbench_2023-10-11T17-05-09Z-v2.0.0"
+        );
+
+        let owned = LaunchTemplate::Owned {
+            id: "lt-0abc".to_string(),
+        };
+        assert_eq!(owned.owned_id(), Some("lt-0abc"));
+        assert_eq!(owned.specification().version(), Some("$Latest"));
+
+        let existing = LaunchTemplate::Existing {
+            name: "netbench-c5n".to_string(),
+        };
+        assert_eq!(existing.owned_id(), None);
+        let specification = existing.specification();
+        assert_eq!(specification.launch_template_name(), Some("netbench-c5n"));
+        assert_eq!(specification.version(), Some("$Default"));
+    }
+}
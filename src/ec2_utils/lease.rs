@@ -0,0 +1,115 @@
+// Copyright Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::{
+    ec2_utils::{HostOs, InfraDetail},
+    error::{OrchError, OrchResult},
+    ssm_utils,
+};
+use core::time::Duration;
+use std::time::Instant;
+use tracing::info;
+
+// Launching, configuring and building the hosts
+const SETUP: Duration = Duration::from_secs(30 * 60);
+// A job which isn't bounded by --duration
+const JOB: Duration = Duration::from_secs(10 * 60);
+// Added to every estimate so that a slow run isn't cut short
+const MARGIN: Duration = Duration::from_secs(30 * 60);
+
+/// How long a job is expected to take, including its warm-up run.
+pub fn job_estimate(duration: Option<Duration>, warmup: bool) -> Duration {
+    let job = duration.unwrap_or(JOB);
+    match warmup {
+        // the warm-up runs the scenario to completion
+        true => job + JOB,
+        false => job,
+    }
+}
+
+/// How long the hosts of a run are expected to be needed.
+pub fn lifetime_estimate(jobs: usize, job_estimate: Duration) -> Duration {
+    SETUP + job_estimate * jobs as u32 + MARGIN
+}
+
+/// The lifetime in whole minutes, as passed to `shutdown -P +<min>`.
+pub fn shutdown_min(lifetime: Duration) -> OrchResult<u16> {
+    u16::try_from(lifetime.as_secs().div_ceil(60)).map_err(|_err| OrchError::Init {
+        dbg: format!(
+            "The hosts' lifetime {} is too long",
+            humantime::format_duration(lifetime)
+        ),
+    })
+}
+
+/// Tracks when the hosts shutdown, which they schedule themselves so that
+/// they don't outlive a crashed orchestrator.
+pub struct HostLease {
+    expires_at: Instant,
+}
+
+impl HostLease {
+    pub fn new(shutdown_min: u16) -> Self {
+        HostLease {
+            expires_at: Instant::now() + Duration::from_secs(shutdown_min as u64 * 60),
+        }
+    }
+
+    fn needs_extension(&self, remaining: Duration) -> bool {
+        self.expires_at.saturating_duration_since(Instant::now()) < remaining + MARGIN
+    }
+
+    /// Reschedule the hosts' shutdown if the `remaining` run time would overrun
+    /// the lease.
+    pub async fn ensure(
+        &mut self,
+        ssm_client: &aws_sdk_ssm::Client,
+        infra: &InfraDetail,
+        client_os: HostOs,
+        remaining: Duration,
+    ) -> OrchResult<()> {
+        if !self.needs_extension(remaining) {
+            return Ok(());
+        }
+        let lifetime = remaining + MARGIN;
+        let shutdown_min = shutdown_min(lifetime)?;
+        info!(
+            "The run is overrunning the hosts' shutdown timer. Extending it by {} min",
+            shutdown_min
+        );
+        ssm_utils::common::extend_lease(
+            ssm_client,
+            infra.linux_hosts(client_os).instance_ids(),
+            shutdown_min,
+        )
+        .await?;
+        if client_os.is_windows() {
+            ssm_utils::windows::extend_lease(ssm_client, infra.client_ids(), shutdown_min).await?;
+        }
+        self.expires_at = Instant::now() + lifetime;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn host_lease() {
+        let job = job_estimate(None, false);
+        assert_eq!(job, JOB);
+        assert_eq!(job_estimate(None, true), JOB * 2);
+        let soak = job_estimate(Some(Duration::from_secs(2 * 60 * 60)), false);
+        assert_eq!(soak, Duration::from_secs(2 * 60 * 60));
+
+        // 30m setup + 3 * 10m + 30m margin
+        assert_eq!(shutdown_min(lifetime_estimate(3, job)).unwrap(), 90);
+        assert_eq!(shutdown_min(Duration::from_secs(61)).unwrap(), 2);
+        assert!(shutdown_min(Duration::from_secs(u16::MAX as u64 * 60 + 60)).is_err());
+
+        let lease = HostLease::new(90);
+        assert!(!lease.needs_extension(job * 3));
+        assert!(lease.needs_extension(job * 7));
+    }
+}
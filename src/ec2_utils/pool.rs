@@ -0,0 +1,484 @@
+// Copyright Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+use super::{
+    ensure_healthy, vpc::RunVpc, EndpointType, InfraDetail, InstanceDetail, LaunchPlan, LinuxDistro,
+};
+use crate::{
+    check_requirements, duration,
+    error::{OrchError, OrchResult},
+    poll,
+    run_spec::RunSpec,
+    s3_utils::{delete_object, download_object, list_objects, upload_object},
+    ssm_utils, Args, OrchestratorConfig, Scenario, STATE,
+};
+use aws_sdk_s3::primitives::ByteStream;
+use aws_types::region::Region;
+use bytes::Bytes;
+use clap::Subcommand;
+use core::time::Duration;
+use indicatif::MultiProgress;
+use serde::{Deserialize, Serialize};
+use std::time::SystemTime;
+use tracing::info;
+
+/// Infra pools are stored as `infra/<name>.json`.
+pub const POOL_PREFIX: &str = "infra";
+
+#[derive(Subcommand, Debug)]
+pub enum InfraCommand {
+    /// Launch and configure hosts for the scenarios (`--scenario-file` or
+    /// `--run-spec`), which are kept alive to be reused by `--use-infra <name>`
+    Create {
+        name: String,
+        /// How long the hosts are kept alive before they shutdown
+        #[arg(long, value_parser = duration::parse_duration, default_value = "8h")]
+        lifetime: Duration,
+    },
+    /// Show the hosts of the infra pools
+    Status { name: Option<String> },
+    /// Delete the hosts of an infra pool
+    Destroy { name: String },
+}
+
+impl InfraCommand {
+    pub async fn run(
+        &self,
+        unique_id: &str,
+        args: &Args,
+        aws_config: &aws_types::SdkConfig,
+    ) -> OrchResult<()> {
+        let s3_client = aws_sdk_s3::Client::new(aws_config);
+        let shared_config_vpc = aws_config::from_env()
+            .region(Region::new(STATE.vpc_region))
+            .load()
+            .await;
+        let ec2_client = aws_sdk_ec2::Client::new(&shared_config_vpc);
+        match self {
+            InfraCommand::Create { name, lifetime } => {
+                let pool = create_pool(unique_id, name, *lifetime, args, aws_config).await?;
+                println!("{}", pool);
+            }
+            InfraCommand::Status { name } => {
+                let names = match name {
+                    Some(name) => vec![name.clone()],
+                    None => list_pools(&s3_client).await?,
+                };
+                for name in names {
+                    let pool = InfraPool::download(&s3_client, &name).await?;
+                    println!("{}", pool);
+                    for host in pool.hosts() {
+                        let state = instance_state(&ec2_client, &host.instance_id).await?;
+                        println!(
+                            "  {} {} {} {}",
+                            host.endpoint_type.as_str().to_lowercase(),
+                            host.instance_id,
+                            host.ip,
+                            state
+                        );
+                    }
+                }
+            }
+            InfraCommand::Destroy { name } => {
+                let pool = InfraPool::download(&s3_client, name).await?;
+                pool.infra().cleanup(&ec2_client).await?;
+                delete_object(&s3_client, STATE.s3_log_bucket, &InfraPool::key(name)).await?;
+                println!("Destroyed infra pool {name}");
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A named set of configured hosts which outlive a run.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct InfraPool {
+    pub name: String,
+    // The unique_id the hosts were launched with
+    pub unique_id: String,
+    pub instance_type: String,
+    // Missing from the pools created before the distributions were selectable
+    #[serde(default)]
+    pub distro: LinuxDistro,
+    pub private_network: bool,
+    pub security_group_id: String,
+    #[serde(default)]
+    pub launch_template_id: Option<String>,
+    #[serde(default)]
+    pub vpc: Option<RunVpc>,
+    // The hosts shutdown at this time (rfc3339)
+    pub expires_at: String,
+    pub servers: Vec<InstanceDetail>,
+    pub clients: Vec<InstanceDetail>,
+    pub routers: Vec<InstanceDetail>,
+    // Missing from the pools created before observers
+    #[serde(default)]
+    pub observers: Vec<InstanceDetail>,
+}
+
+impl InfraPool {
+    fn key(name: &str) -> String {
+        format!("{POOL_PREFIX}/{name}.json")
+    }
+
+    pub fn infra(&self) -> InfraDetail {
+        InfraDetail {
+            security_group_id: self.security_group_id.clone(),
+            launch_template_id: self.launch_template_id.clone(),
+            vpc: self.vpc.clone(),
+            servers: self.servers.clone(),
+            clients: self.clients.clone(),
+            routers: self.routers.clone(),
+            observers: self.observers.clone(),
+        }
+    }
+
+    fn hosts(&self) -> impl Iterator<Item = &InstanceDetail> {
+        self.servers
+            .iter()
+            .chain(self.clients.iter())
+            .chain(self.routers.iter())
+            .chain(self.observers.iter())
+    }
+
+    /// Error if the pool can't run the scenarios.
+    pub fn check_fits(
+        &self,
+        scenarios: &[Scenario],
+        instance_type: &str,
+        distro: LinuxDistro,
+        private_network: bool,
+    ) -> OrchResult<()> {
+        let mismatch = |dbg: String| OrchError::Init {
+            dbg: format!("Infra pool {} can't be used: {}", self.name, dbg),
+        };
+        if self.instance_type != instance_type {
+            return Err(mismatch(format!(
+                "instance type {} != {}",
+                self.instance_type, instance_type
+            )));
+        }
+        if self.distro != distro {
+            return Err(mismatch(format!("distro {} != {}", self.distro, distro)));
+        }
+        if self.private_network != private_network {
+            return Err(mismatch(format!(
+                "private_network {} != {}",
+                self.private_network, private_network
+            )));
+        }
+        for scenario in scenarios {
+            for (endpoint_type, needed, hosts) in [
+                (EndpointType::Server, scenario.servers, &self.servers),
+                (EndpointType::Client, scenario.clients, &self.clients),
+                (EndpointType::Router, scenario.routers, &self.routers),
+            ] {
+                if needed > hosts.len() {
+                    return Err(mismatch(format!(
+                        "{} needs {} {} hosts but the pool has {}",
+                        scenario.name,
+                        needed,
+                        endpoint_type.as_str().to_lowercase(),
+                        hosts.len()
+                    )));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    pub async fn download(s3_client: &aws_sdk_s3::Client, name: &str) -> OrchResult<Self> {
+        let key = Self::key(name);
+        let object = download_object(s3_client, STATE.s3_log_bucket, &key)
+            .await
+            .map_err(|_err| OrchError::Init {
+                dbg: format!("No infra pool named {}", name),
+            })?;
+        let body = object
+            .body
+            .collect()
+            .await
+            .map_err(|err| OrchError::S3 {
+                dbg: format!("Failed to read {}: {}", key, err),
+            })?
+            .into_bytes();
+        serde_json::from_slice(&body).map_err(|err| OrchError::Init {
+            dbg: format!("Failed to parse {}: {}", key, err),
+        })
+    }
+
+    async fn upload(&self, s3_client: &aws_sdk_s3::Client) -> OrchResult<()> {
+        let key = Self::key(&self.name);
+        let body = serde_json::to_vec_pretty(self).map_err(|err| OrchError::Init {
+            dbg: format!("Failed to serialize {}: {}", key, err),
+        })?;
+        upload_object(
+            s3_client,
+            STATE.s3_log_bucket,
+            ByteStream::from(Bytes::from(body)),
+            &key,
+        )
+        .await
+        .map_err(|err| OrchError::S3 {
+            dbg: format!("Failed to upload {}: {}", key, err),
+        })?;
+        Ok(())
+    }
+}
+
+impl std::fmt::Display for InfraPool {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}: {} x {} ({} servers, {} clients, {} routers) expires at {}",
+            self.name,
+            self.servers.len() + self.clients.len() + self.routers.len(),
+            self.instance_type,
+            self.servers.len(),
+            self.clients.len(),
+            self.routers.len(),
+            self.expires_at
+        )
+    }
+}
+
+async fn create_pool(
+    unique_id: &str,
+    name: &str,
+    lifetime: Duration,
+    args: &Args,
+    aws_config: &aws_types::SdkConfig,
+) -> OrchResult<InfraPool> {
+    if name.is_empty() || name.contains('/') {
+        return Err(OrchError::Init {
+            dbg: format!("Invalid infra pool name {:?}", name),
+        });
+    }
+    let shutdown_min = u16::try_from(lifetime.as_secs() / 60).map_err(|_err| OrchError::Init {
+        dbg: format!(
+            "Infra pool lifetime {} is too long",
+            humantime::format_duration(lifetime)
+        ),
+    })?;
+    let config = OrchestratorConfig::load(args.config.as_deref())?;
+    poll::configure(config.polling);
+    if config.client_os.is_windows() {
+        return Err(OrchError::Init {
+            dbg: "Infra pools are only created with linux clients".to_string(),
+        });
+    }
+    let run_spec = args.run_spec.as_deref().map(RunSpec::load).transpose()?;
+    let mut groups = check_requirements(args, run_spec.as_ref(), &config, aws_config).await?;
+    let group = match (groups.pop(), groups.is_empty()) {
+        (Some(group), true) => group,
+        _ => {
+            return Err(OrchError::Init {
+                dbg: "An infra pool is created for a single instance type".to_string(),
+            })
+        }
+    };
+
+    let s3_client = aws_sdk_s3::Client::new(aws_config);
+    if InfraPool::download(&s3_client, name).await.is_ok() {
+        return Err(OrchError::Init {
+            dbg: format!("Infra pool {} already exists", name),
+        });
+    }
+    let iam_client = aws_sdk_iam::Client::new(aws_config);
+    let shared_config_vpc = aws_config::from_env()
+        .region(Region::new(STATE.vpc_region))
+        .load()
+        .await;
+    let ec2_client = aws_sdk_ec2::Client::new(&shared_config_vpc);
+    let ssm_client = aws_sdk_ssm::Client::new(&shared_config_vpc);
+
+    let mut launch_plan = LaunchPlan::create(
+        unique_id,
+        &ec2_client,
+        &iam_client,
+        &ssm_client,
+        &group,
+        &config,
+    )
+    .await?;
+    config
+        .budget
+        .check_lifetime(launch_plan.instances(), &group.instance_type, shutdown_min)?;
+    launch_plan.shutdown_min = shutdown_min;
+    let mut infra = launch_plan.launch(&ec2_client, unique_id).await?;
+    let configured = async {
+        ensure_healthy(
+            &launch_plan,
+            &ec2_client,
+            &ssm_client,
+            unique_id,
+            &mut infra,
+            args.health_check_retries,
+        )
+        .await?;
+
+        // the drivers are built by each run from the local source
+        let multi_progress = MultiProgress::new();
+        for (host_group, instance_ids) in [
+            ("server", infra.server_ids()),
+            ("client", infra.client_ids()),
+            ("router", infra.router_ids()),
+        ] {
+            if instance_ids.is_empty() {
+                continue;
+            }
+            ssm_utils::common::configure_host_group(
+                host_group,
+                &ssm_client,
+                instance_ids,
+                &[],
+                unique_id,
+                ssm_utils::common::HostSetup::Configure {
+                    distro: group.distro,
+                    shutdown_min,
+                    build_cache: config.build_cache.as_ref(),
+                    instance_store: config.storage.instance_store,
+                },
+                &multi_progress,
+            )
+            .await?;
+        }
+        Ok::<(), OrchError>(())
+    }
+    .await;
+    if let Err(err) = configured {
+        // don't leave the hosts running if the pool is unusable
+        infra.cleanup(&ec2_client).await?;
+        return Err(err);
+    }
+
+    let pool = InfraPool {
+        name: name.to_string(),
+        unique_id: unique_id.to_string(),
+        instance_type: group.instance_type.clone(),
+        distro: group.distro,
+        private_network: launch_plan.private_network,
+        security_group_id: infra.security_group_id.clone(),
+        launch_template_id: infra.launch_template_id.clone(),
+        vpc: infra.vpc.clone(),
+        expires_at: humantime::format_rfc3339_seconds(
+            SystemTime::now() + Duration::from_secs(shutdown_min as u64 * 60),
+        )
+        .to_string(),
+        servers: infra.servers,
+        clients: infra.clients,
+        routers: infra.routers,
+        observers: infra.observers,
+    };
+    pool.upload(&s3_client).await?;
+    info!("Created infra pool {}", name);
+    Ok(pool)
+}
+
+async fn list_pools(s3_client: &aws_sdk_s3::Client) -> OrchResult<Vec<String>> {
+    Ok(
+        list_objects(s3_client, STATE.s3_log_bucket, &format!("{POOL_PREFIX}/"))
+            .await?
+            .keys()
+            .filter_map(|key| {
+                key.strip_prefix(&format!("{POOL_PREFIX}/"))
+                    .and_then(|name| name.strip_suffix(".json"))
+                    .map(String::from)
+            })
+            .collect(),
+    )
+}
+
+async fn instance_state(ec2_client: &aws_sdk_ec2::Client, instance_id: &str) -> OrchResult<String> {
+    let result = ec2_client
+        .describe_instances()
+        .instance_ids(instance_id)
+        .send()
+        .await
+        .map_err(|err| OrchError::Ec2Instance {
+            instance_id: instance_id.to_string(),
+            dbg: format!("Failed to describe instance: {}", err),
+        })?;
+    Ok(result
+        .reservations()
+        .and_then(|reservations| reservations.first())
+        .and_then(|reservation| reservation.instances())
+        .and_then(|instances| instances.first())
+        .and_then(|instance| instance.state())
+        .and_then(|state| state.name())
+        .map_or("unknown".to_string(), |state| state.as_str().to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    fn host(endpoint_type: EndpointType, id: &str) -> InstanceDetail {
+        InstanceDetail {
+            endpoint_type,
+            host_group: String::new(),
+            instance_id: id.to_string(),
+            ip: "10.0.0.1".to_string(),
+            private_ip: "10.0.0.1".to_string(),
+        }
+    }
+
+    #[test]
+    fn infra_pool_fits() {
+        let pool = InfraPool {
+            name: "dev".to_string(),
+            unique_id: "2024-01-09T05:25:30Z-v2.0.1".to_string(),
+            instance_type: "c5.4xlarge".to_string(),
+            distro: LinuxDistro::Al2023,
+            private_network: false,
+            security_group_id: "sg-1".to_string(),
+            launch_template_id: Some("lt-1".to_string()),
+            vpc: None,
+            expires_at: "2024-01-09T13:25:30Z".to_string(),
+            servers: vec![host(EndpointType::Server, "i-1")],
+            clients: vec![
+                host(EndpointType::Client, "i-2"),
+                host(EndpointType::Client, "i-3"),
+            ],
+            routers: Vec::new(),
+            observers: Vec::new(),
+        };
+        let scenario = |clients: usize, routers: usize| Scenario {
+            name: "request_response.json".to_string(),
+            path: PathBuf::from("request_response.json"),
+            clients,
+            servers: 1,
+            routers,
+        };
+
+        assert!(pool
+            .check_fits(
+                &[scenario(1, 0), scenario(2, 0)],
+                "c5.4xlarge",
+                LinuxDistro::Al2023,
+                false
+            )
+            .is_ok());
+        assert!(pool
+            .check_fits(&[scenario(3, 0)], "c5.4xlarge", LinuxDistro::Al2023, false)
+            .is_err());
+        assert!(pool
+            .check_fits(&[scenario(1, 1)], "c5.4xlarge", LinuxDistro::Al2023, false)
+            .is_err());
+        assert!(pool
+            .check_fits(&[scenario(1, 0)], "c5n.4xlarge", LinuxDistro::Al2023, false)
+            .is_err());
+        assert!(pool
+            .check_fits(&[scenario(1, 0)], "c5.4xlarge", LinuxDistro::Al2023, true)
+            .is_err());
+        assert!(pool
+            .check_fits(&[scenario(1, 0)], "c5.4xlarge", LinuxDistro::Ubuntu, false)
+            .is_err());
+
+        // round trips through s3
+        let json = serde_json::to_string(&pool).unwrap();
+        let pool: InfraPool = serde_json::from_str(&json).unwrap();
+        assert_eq!(pool.infra().instance_ids(), ["i-1", "i-2", "i-3"]);
+    }
+}
@@ -0,0 +1,201 @@
+// Copyright Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::{
+    error::{OrchError, OrchResult},
+    STATE,
+};
+use aws_sdk_ec2::types::{Filter, InstanceType};
+use aws_types::region::Region;
+use tracing::{info, warn};
+
+/// Fail fast if the subnet doesn't have a free ip for each host, rather than
+/// partially launching the hosts.
+pub async fn check_subnet_ips(
+    ec2_client: &aws_sdk_ec2::Client,
+    subnet_id: &str,
+    instances: usize,
+) -> OrchResult<()> {
+    let available = ec2_client
+        .describe_subnets()
+        .subnet_ids(subnet_id)
+        .send()
+        .await
+        .map_err(|err| OrchError::Ec2 {
+            dbg: format!("Couldn't describe subnet {}: {}", subnet_id, err),
+        })?
+        .subnets()
+        .and_then(|subnets| subnets.first())
+        .and_then(|subnet| subnet.available_ip_address_count())
+        .unwrap_or_default();
+    if (available as usize) < instances {
+        return Err(OrchError::Init {
+            dbg: format!(
+                "Subnet {} has {} free ips but {} hosts are needed",
+                subnet_id, available, instances
+            ),
+        });
+    }
+    Ok(())
+}
+
+/// Fail fast if launching `instances` hosts would exceed the account's on-demand
+/// vCPU quota for the instance family, rather than hitting an
+/// `InsufficientInstanceCapacity` or `VcpuLimitExceeded` error halfway through
+/// the launch.
+///
+/// The check is skipped, with a warning, if the quota can't be queried.
+pub async fn check_vcpu_quota(
+    ec2_client: &aws_sdk_ec2::Client,
+    instance_type: &str,
+    instances: usize,
+) -> OrchResult<()> {
+    let Some(quota_code) = vcpu_quota_code(instance_type) else {
+        warn!("No known vCPU quota for {}", instance_type);
+        return Ok(());
+    };
+    let quota = match query_quota(quota_code).await {
+        Ok(quota) => quota,
+        Err(err) => {
+            warn!("Skipping the vCPU quota check: {}", err);
+            return Ok(());
+        }
+    };
+
+    let required = instance_vcpus(ec2_client, instance_type).await? * instances as u64;
+    let in_use = running_vcpus(ec2_client, quota_code).await?;
+    info!(
+        "vCPU quota {}: {} in use, {} required, quota {}",
+        quota_code, in_use, required, quota
+    );
+    if in_use + required > quota {
+        return Err(OrchError::Init {
+            dbg: format!(
+                "Launching {} {} hosts needs {} vCPUs but only {} of the {} vCPU quota ({}) are free in {}",
+                instances,
+                instance_type,
+                required,
+                quota.saturating_sub(in_use),
+                quota,
+                quota_code,
+                STATE.vpc_region
+            ),
+        });
+    }
+    Ok(())
+}
+
+/// The service quota code for the on-demand vCPU limit of the instance type's
+/// family. ex: c5.4xlarge -> Standard (A, C, D, H, I, M, R, T, Z) instances
+fn vcpu_quota_code(instance_type: &str) -> Option<&'static str> {
+    let family = instance_type.split('.').next()?;
+    if family.starts_with("inf") {
+        return Some("L-1945791B");
+    }
+    match family.chars().next()? {
+        'a' | 'c' | 'd' | 'h' | 'i' | 'm' | 'r' | 't' | 'z' => Some("L-1216C47A"),
+        'f' => Some("L-74FC7D96"),
+        'g' | 'v' => Some("L-DB2E81BA"),
+        'p' => Some("L-417A185B"),
+        'x' => Some("L-7295265B"),
+        _ => None,
+    }
+}
+
+async fn query_quota(quota_code: &str) -> OrchResult<u64> {
+    let shared_config_vpc = aws_config::from_env()
+        .region(Region::new(STATE.vpc_region))
+        .load()
+        .await;
+    let output = aws_sdk_servicequotas::Client::new(&shared_config_vpc)
+        .get_service_quota()
+        .service_code("ec2")
+        .quota_code(quota_code)
+        .send()
+        .await
+        .map_err(|err| OrchError::Init {
+            dbg: format!("Failed to query quota {}: {}", quota_code, err),
+        })?;
+    output
+        .quota()
+        .and_then(|quota| quota.value())
+        .map(|quota| quota as u64)
+        .ok_or(OrchError::Init {
+            dbg: format!("Missing value of quota {}", quota_code),
+        })
+}
+
+async fn instance_vcpus(ec2_client: &aws_sdk_ec2::Client, instance_type: &str) -> OrchResult<u64> {
+    let vcpus = ec2_client
+        .describe_instance_types()
+        .instance_types(InstanceType::from(instance_type))
+        .send()
+        .await
+        .map_err(|err| OrchError::Ec2 {
+            dbg: format!("Couldn't describe {}: {}", instance_type, err),
+        })?
+        .instance_types()
+        .and_then(|types| types.first())
+        .and_then(|info| info.v_cpu_info())
+        .and_then(|vcpu| vcpu.default_v_cpus())
+        .ok_or(OrchError::Ec2 {
+            dbg: format!("Missing vCPU info for {}", instance_type),
+        })?;
+    Ok(vcpus as u64)
+}
+
+/// The vCPUs of the running instances which count towards the same quota.
+async fn running_vcpus(ec2_client: &aws_sdk_ec2::Client, quota_code: &str) -> OrchResult<u64> {
+    let mut vcpus = 0;
+    let mut next_token = None;
+    loop {
+        let output = ec2_client
+            .describe_instances()
+            .filters(
+                Filter::builder()
+                    .name("instance-state-name")
+                    .values("pending")
+                    .values("running")
+                    .build(),
+            )
+            .set_next_token(next_token)
+            .send()
+            .await
+            .map_err(|err| OrchError::Ec2 {
+                dbg: format!("Couldn't describe instances: {}", err),
+            })?;
+        for instance in output
+            .reservations()
+            .unwrap_or_default()
+            .iter()
+            .flat_map(|reservation| reservation.instances().unwrap_or_default())
+        {
+            let same_quota = instance
+                .instance_type()
+                .and_then(|instance_type| vcpu_quota_code(instance_type.as_str()))
+                == Some(quota_code);
+            if let (true, Some(cpu)) = (same_quota, instance.cpu_options()) {
+                vcpus +=
+                    (cpu.core_count().unwrap_or(0) * cpu.threads_per_core().unwrap_or(1)) as u64;
+            }
+        }
+        next_token = output.next_token().map(String::from);
+        if next_token.is_none() {
+            return Ok(vcpus);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn quota_code() {
+        assert_eq!(vcpu_quota_code("c5.4xlarge"), Some("L-1216C47A"));
+        assert_eq!(vcpu_quota_code("c5n.18xlarge"), Some("L-1216C47A"));
+        assert_eq!(vcpu_quota_code("inf2.xlarge"), Some("L-1945791B"));
+        assert_eq!(vcpu_quota_code("p4d.24xlarge"), Some("L-417A185B"));
+        assert_eq!(vcpu_quota_code("u-6tb1.metal"), None);
+    }
+}
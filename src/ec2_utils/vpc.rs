@@ -0,0 +1,258 @@
+// Copyright Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::{
+    error::{OrchError, OrchResult},
+    state::STATE,
+};
+use aws_sdk_ec2::types::{ResourceType, Tag, TagSpecification};
+use serde::{Deserialize, Serialize};
+use std::{future::Future, time::Duration};
+use tracing::{info, warn};
+
+/// The network resources created for a run with `dedicated_vpc`. A resource
+/// is only set once it has been created, so that a partially created network
+/// can be torn down.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct RunVpc {
+    pub vpc_id: Option<String>,
+    pub subnet_id: Option<String>,
+    pub internet_gateway_id: Option<String>,
+    pub route_table_id: Option<String>,
+}
+
+fn tags(resource_type: ResourceType, unique_id: &str) -> TagSpecification {
+    TagSpecification::builder()
+        .resource_type(resource_type)
+        .tags(
+            Tag::builder()
+                .key("Name")
+                .value(STATE.security_group_name(unique_id))
+                .build(),
+        )
+        .tags(Tag::builder().key("netbench_run").value(unique_id).build())
+        .build()
+}
+
+fn create_err(resource: &str, err: impl std::fmt::Display) -> OrchError {
+    OrchError::Ec2 {
+        dbg: format!("Failed to create the run's {}: {}", resource, err),
+    }
+}
+
+impl RunVpc {
+    /// Create a VPC with a single public subnet spanning `cidr`, routed to the
+    /// internet through its own gateway. Whatever was created is torn down if
+    /// a step fails.
+    pub async fn create(
+        ec2_client: &aws_sdk_ec2::Client,
+        unique_id: &str,
+        cidr: &str,
+    ) -> OrchResult<Self> {
+        let mut vpc = RunVpc::default();
+        if let Err(err) = vpc.create_resources(ec2_client, unique_id, cidr).await {
+            if let Err(delete_err) = vpc.delete(ec2_client).await {
+                warn!("Failed to delete the partially created vpc: {}", delete_err);
+            }
+            return Err(err);
+        }
+        info!(
+            "Created vpc {:?} with subnet {:?}",
+            vpc.vpc_id, vpc.subnet_id
+        );
+        Ok(vpc)
+    }
+
+    async fn create_resources(
+        &mut self,
+        ec2_client: &aws_sdk_ec2::Client,
+        unique_id: &str,
+        cidr: &str,
+    ) -> OrchResult<()> {
+        let vpc_id = ec2_client
+            .create_vpc()
+            .cidr_block(cidr)
+            .tag_specifications(tags(ResourceType::Vpc, unique_id))
+            .send()
+            .await
+            .map_err(|err| create_err("vpc", err))?
+            .vpc()
+            .and_then(|vpc| vpc.vpc_id())
+            .map(String::from)
+            .ok_or_else(|| create_err("vpc", "missing id"))?;
+        self.vpc_id = Some(vpc_id.clone());
+
+        let internet_gateway_id = ec2_client
+            .create_internet_gateway()
+            .tag_specifications(tags(ResourceType::InternetGateway, unique_id))
+            .send()
+            .await
+            .map_err(|err| create_err("internet gateway", err))?
+            .internet_gateway()
+            .and_then(|gateway| gateway.internet_gateway_id())
+            .map(String::from)
+            .ok_or_else(|| create_err("internet gateway", "missing id"))?;
+        // tracked once attached since the teardown detaches it before deleting it
+        ec2_client
+            .attach_internet_gateway()
+            .internet_gateway_id(&internet_gateway_id)
+            .vpc_id(&vpc_id)
+            .send()
+            .await
+            .map_err(|err| create_err("internet gateway", err))?;
+        self.internet_gateway_id = Some(internet_gateway_id.clone());
+
+        let subnet_id = ec2_client
+            .create_subnet()
+            .vpc_id(&vpc_id)
+            .cidr_block(cidr)
+            .tag_specifications(tags(ResourceType::Subnet, unique_id))
+            .send()
+            .await
+            .map_err(|err| create_err("subnet", err))?
+            .subnet()
+            .and_then(|subnet| subnet.subnet_id())
+            .map(String::from)
+            .ok_or_else(|| create_err("subnet", "missing id"))?;
+        self.subnet_id = Some(subnet_id.clone());
+
+        let route_table_id = ec2_client
+            .create_route_table()
+            .vpc_id(&vpc_id)
+            .tag_specifications(tags(ResourceType::RouteTable, unique_id))
+            .send()
+            .await
+            .map_err(|err| create_err("route table", err))?
+            .route_table()
+            .and_then(|route_table| route_table.route_table_id())
+            .map(String::from)
+            .ok_or_else(|| create_err("route table", "missing id"))?;
+        self.route_table_id = Some(route_table_id.clone());
+        ec2_client
+            .create_route()
+            .route_table_id(&route_table_id)
+            .destination_cidr_block("0.0.0.0/0")
+            .gateway_id(&internet_gateway_id)
+            .send()
+            .await
+            .map_err(|err| create_err("route", err))?;
+        ec2_client
+            .associate_route_table()
+            .route_table_id(&route_table_id)
+            .subnet_id(&subnet_id)
+            .send()
+            .await
+            .map_err(|err| create_err("route table association", err))?;
+        Ok(())
+    }
+
+    /// The subnet and vpc ids, once created.
+    pub fn subnet_vpc_ids(&self) -> OrchResult<(String, String)> {
+        match (&self.subnet_id, &self.vpc_id) {
+            (Some(subnet_id), Some(vpc_id)) => Ok((subnet_id.clone(), vpc_id.clone())),
+            _ => Err(OrchError::Ec2 {
+                dbg: "The run's vpc wasn't created".to_string(),
+            }),
+        }
+    }
+
+    /// Delete the network once the run's hosts and security group are deleted.
+    ///
+    /// The subnet can't be deleted until its instances have terminated, so each
+    /// deletion is retried for a few minutes.
+    pub async fn delete(&self, ec2_client: &aws_sdk_ec2::Client) -> OrchResult<()> {
+        info!("Start: deleting the run's vpc");
+        if let Some(subnet_id) = &self.subnet_id {
+            retry("subnet", || {
+                ec2_client.delete_subnet().subnet_id(subnet_id).send()
+            })
+            .await?;
+        }
+        if let Some(route_table_id) = &self.route_table_id {
+            retry("route table", || {
+                ec2_client
+                    .delete_route_table()
+                    .route_table_id(route_table_id)
+                    .send()
+            })
+            .await?;
+        }
+        if let (Some(internet_gateway_id), Some(vpc_id)) = (&self.internet_gateway_id, &self.vpc_id)
+        {
+            retry("internet gateway", || {
+                ec2_client
+                    .detach_internet_gateway()
+                    .internet_gateway_id(internet_gateway_id)
+                    .vpc_id(vpc_id)
+                    .send()
+            })
+            .await?;
+            retry("internet gateway", || {
+                ec2_client
+                    .delete_internet_gateway()
+                    .internet_gateway_id(internet_gateway_id)
+                    .send()
+            })
+            .await?;
+        }
+        if let Some(vpc_id) = &self.vpc_id {
+            retry("vpc", || ec2_client.delete_vpc().vpc_id(vpc_id).send()).await?;
+        }
+        Ok(())
+    }
+}
+
+// Retry every 10s for up to 5 minutes, while the resource's dependents are
+// being deleted
+async fn retry<F, Fut, T, E>(resource: &str, mut delete: F) -> OrchResult<()>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T, E>>,
+    E: std::fmt::Display,
+{
+    let mut retries = 30;
+    loop {
+        match delete().await {
+            Ok(_) => return Ok(()),
+            Err(err) if retries == 0 => {
+                return Err(OrchError::Ec2 {
+                    dbg: format!("Failed to delete the run's {}: {}", resource, err),
+                })
+            }
+            Err(_err) => {
+                retries -= 1;
+                tokio::time::sleep(Duration::from_secs(10)).await;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn run_vpc() {
+        let vpc = RunVpc {
+            vpc_id: Some("vpc-1".to_string()),
+            ..Default::default()
+        };
+        // a vpc without a subnet can't be launched into
+        assert!(vpc.subnet_vpc_ids().is_err());
+
+        let vpc = RunVpc {
+            subnet_id: Some("subnet-1".to_string()),
+            internet_gateway_id: Some("igw-1".to_string()),
+            route_table_id: Some("rtb-1".to_string()),
+            ..vpc
+        };
+        assert_eq!(
+            vpc.subnet_vpc_ids().unwrap(),
+            ("subnet-1".to_string(), "vpc-1".to_string())
+        );
+
+        // persisted with infra pools
+        let json = serde_json::to_string(&vpc).unwrap();
+        assert_eq!(serde_json::from_str::<RunVpc>(&json).unwrap(), vpc);
+    }
+}
@@ -2,14 +2,55 @@
 // SPDX-License-Identifier: Apache-2.0
 
 #![allow(unused)]
+use crate::russula::RussulaError;
+use core::time::Duration;
+
 pub type OrchResult<T, E = OrchError> = Result<T, E>;
 
 #[derive(Debug)]
 pub enum OrchError {
-    Init { dbg: String },
-    Ec2 { dbg: String },
-    Iam { dbg: String },
-    Ssm { dbg: String },
+    Init {
+        dbg: String,
+    },
+    Ec2 {
+        dbg: String,
+    },
+    Ec2Instance {
+        instance_id: String,
+        dbg: String,
+    },
+    Iam {
+        dbg: String,
+    },
+    Ssm {
+        dbg: String,
+    },
+    SsmCommand {
+        step: String,
+        command_id: String,
+        dbg: String,
+    },
+    S3 {
+        dbg: String,
+    },
+    DynamoDb {
+        dbg: String,
+    },
+    Russula {
+        endpoint: String,
+        dbg: String,
+    },
+    // A russula phase exceeded its budget. `hosts` didn't complete the phase:
+    // their instance id, or russula addr if unknown
+    RussulaTimeout {
+        endpoint: String,
+        phase: String,
+        timeout: Duration,
+        hosts: Vec<String>,
+        dbg: String,
+    },
+    // The run was stopped by Ctrl-C
+    Interrupted,
 }
 
 impl std::fmt::Display for OrchError {
@@ -17,10 +58,51 @@ impl std::fmt::Display for OrchError {
         match self {
             OrchError::Init { dbg } => write!(f, "{}", dbg),
             OrchError::Ec2 { dbg } => write!(f, "{}", dbg),
+            OrchError::Ec2Instance { instance_id, dbg } => {
+                write!(f, "instance: {} {}", instance_id, dbg)
+            }
             OrchError::Iam { dbg } => write!(f, "{}", dbg),
             OrchError::Ssm { dbg } => write!(f, "{}", dbg),
+            OrchError::SsmCommand {
+                step,
+                command_id,
+                dbg,
+            } => write!(f, "step: {} command_id: {} {}", step, command_id, dbg),
+            OrchError::S3 { dbg } => write!(f, "{}", dbg),
+            OrchError::DynamoDb { dbg } => write!(f, "{}", dbg),
+            OrchError::Russula { endpoint, dbg } => write!(f, "russula {}: {}", endpoint, dbg),
+            OrchError::RussulaTimeout {
+                endpoint,
+                phase,
+                timeout,
+                hosts,
+                dbg,
+            } => {
+                write!(
+                    f,
+                    "russula {}: the {} phase timed out after {:?} on hosts: [{}]",
+                    endpoint,
+                    phase,
+                    timeout,
+                    hosts.join(", ")
+                )?;
+                if !dbg.is_empty() {
+                    write!(f, " {}", dbg)?;
+                }
+                Ok(())
+            }
+            OrchError::Interrupted => write!(f, "Interrupted"),
         }
     }
 }
 
 impl std::error::Error for OrchError {}
+
+impl OrchError {
+    pub fn russula(endpoint: &str, err: RussulaError) -> Self {
+        OrchError::Russula {
+            endpoint: endpoint.to_string(),
+            dbg: err.to_string(),
+        }
+    }
+}
@@ -0,0 +1,178 @@
+// Copyright Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Orchestrate [s2n-netbench](https://github.com/aws/s2n-netbench) runs on EC2.
+//!
+//! The `orchestrator` binary is a thin wrapper around [`cli::run`]. The
+//! primitives it's built on can be embedded by other tools:
+//!
+//! - [`ec2_utils`]: launch, describe and delete the hosts of a run, and manage
+//!   pools of hosts which are kept alive across runs.
+//! - [`ssm_utils`]: configure the hosts, build the netbench drivers and run
+//!   commands on the hosts over SSM.
+//! - [`s3_utils`]: upload, list and download the artifacts of a run.
+//! - [`russula`]: coordinate processes across hosts. A Coordinator drives
+//!   Workers through the states of a [`russula::Protocol`], ex: the netbench
+//!   server and client drivers in [`russula::netbench`].
+//!
+//! The [`state::STATE`] constants name the AWS resources the orchestrator
+//! relies on, and every fallible API returns an [`error::OrchResult`].
+//!
+//! ex: run a command on Workers started with `russula_cli command-worker`
+//!
+//! ```no_run
+//! use netbench_orch::russula::{command, RussulaBuilder, RussulaResult};
+//! use std::{collections::BTreeSet, time::Duration};
+//!
+//! async fn uptime() -> RussulaResult<()> {
+//!     let workers = BTreeSet::from(["10.0.0.3:9000".parse().unwrap()]);
+//!     let params = command::CommandParams {
+//!         command: Some("uptime".to_string()),
+//!         ..Default::default()
+//!     };
+//!     let protocol = command::CoordProtocol::new().with_params(params);
+//!     let mut coord = RussulaBuilder::new(workers, protocol, Duration::from_secs(5))
+//!         .build()
+//!         .await?;
+//!     coord.run_till_worker_running().await?;
+//!     coord.run_till_done().await?;
+//!     for (worker, exit) in coord.worker_exits() {
+//!         println!("{worker}: {exit}");
+//!     }
+//!     Ok(())
+//! }
+//! ```
+
+use error::{OrchError, OrchResult};
+use scenario::schema::NetbenchScenario;
+use std::{
+    fs::File,
+    path::{Path, PathBuf},
+};
+
+pub mod cli;
+mod config;
+mod control_plane;
+mod coordination_utils;
+mod dashboard;
+mod doctor;
+pub mod duration;
+pub mod ec2_utils;
+pub mod error;
+mod logging;
+mod manifest;
+mod notify;
+mod orchestrator;
+mod plan;
+mod poll;
+pub mod report;
+mod run_spec;
+pub mod russula;
+pub mod s3_utils;
+mod scenario;
+mod serve;
+mod shutdown;
+pub mod ssm_utils;
+pub mod state;
+
+use cli::{check_requirements, execute, resolve_groups, run_status, Args};
+use config::OrchestratorConfig;
+use dashboard::*;
+use ec2_utils::*;
+use s3_utils::*;
+use ssm_utils::*;
+use state::*;
+
+// TODO
+// - install netbench drivers from crates.io
+// - save hash of private source
+//   - get private src exec from s3
+// - cleanup dashboard
+// - enum for orch steps
+//   - add timing data
+//
+// # Expanding Russula/Cli
+//
+// # Optimization
+// - tar.gz private source
+// - enum for driver build type (git, source, crates.io)
+//
+// - use release build instead of debug
+// - experiment with uploading and downloading netbench exec
+
+// Expand the user provided scenario paths. Directories are expanded to the
+// `.json` files they contain, sorted by name so the run order is predictable.
+fn scenario_paths(paths: &[PathBuf]) -> OrchResult<Vec<PathBuf>> {
+    let mut scenario_paths = Vec::new();
+    for path in paths {
+        if path.is_dir() {
+            let mut dir_paths: Vec<PathBuf> = std::fs::read_dir(path)
+                .map_err(|err| OrchError::Init {
+                    dbg: format!("Failed to read scenario dir {:?}: {}", path, err),
+                })?
+                .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+                .filter(|path| path.extension().is_some_and(|ext| ext == "json"))
+                .collect();
+            dir_paths.sort();
+            scenario_paths.extend(dir_paths);
+        } else {
+            scenario_paths.push(path.clone());
+        }
+    }
+
+    if scenario_paths.is_empty() {
+        return Err(OrchError::Init {
+            dbg: "No scenario files found".to_string(),
+        });
+    }
+    Ok(scenario_paths)
+}
+
+fn load_scenario(path: &Path) -> OrchResult<Scenario> {
+    let name = path
+        .file_name()
+        .and_then(|f| f.to_str())
+        .ok_or(OrchError::Init {
+            dbg: "Scenario file not specified".to_string(),
+        })?
+        .to_string();
+    let scenario_file = File::open(path).map_err(|_err| OrchError::Init {
+        dbg: format!("Scenario file not found: {:?}", path),
+    })?;
+    let scenario: NetbenchScenario =
+        serde_json::from_reader(scenario_file).map_err(|err| OrchError::Init {
+            dbg: format!("Failed to parse scenario file {:?}: {}", path, err),
+        })?;
+    scenario.validate().map_err(|err| OrchError::Init {
+        dbg: format!("{:?}: {}", path, err),
+    })?;
+
+    Ok(Scenario {
+        name,
+        path: path.to_path_buf(),
+        clients: scenario.clients.len(),
+        servers: scenario.servers.len(),
+        routers: scenario.routers.len(),
+    })
+}
+
+#[derive(Clone, Debug)]
+pub struct Scenario {
+    name: String,
+    path: PathBuf,
+    clients: usize,
+    servers: usize,
+    // The client traffic is routed through these hosts
+    routers: usize,
+}
+
+impl Scenario {
+    pub fn file_stem(&self) -> &str {
+        self.path
+            .as_path()
+            .file_stem()
+            .expect("expect scenario file")
+            .to_str()
+            .unwrap()
+    }
+}
@@ -0,0 +1,229 @@
+// Copyright Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::{
+    error::{OrchError, OrchResult},
+    upload_object, STATE,
+};
+use aws_sdk_s3::primitives::ByteStream;
+use core::{fmt, str::FromStr};
+use serde_json::{json, Map, Value};
+use std::{path::PathBuf, time::SystemTime};
+use tracing::{
+    field::{Field, Visit},
+    Event, Subscriber,
+};
+use tracing_appender::non_blocking::WorkerGuard;
+use tracing_subscriber::{
+    fmt::{format::Writer, FmtContext, FormatEvent, FormatFields, FormattedFields},
+    registry::LookupSpan,
+    EnvFilter,
+};
+
+/// The format of the orchestrator's log file.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum LogFormat {
+    #[default]
+    Text,
+    Json,
+}
+
+impl LogFormat {
+    pub fn as_str(&self) -> &str {
+        match self {
+            LogFormat::Text => "text",
+            LogFormat::Json => "json",
+        }
+    }
+}
+
+impl std::fmt::Display for LogFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+impl FromStr for LogFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(LogFormat::Text),
+            "json" => Ok(LogFormat::Json),
+            _ => Err(format!(
+                "unsupported log format: {}. expected text or json",
+                s
+            )),
+        }
+    }
+}
+
+/// The local log file of a run. ex: target/netbench/<unique_id>/orchestrator.log
+pub fn log_path(unique_id: &str) -> PathBuf {
+    PathBuf::from(STATE.workspace_dir)
+        .join(unique_id)
+        .join("orchestrator.log")
+}
+
+/// Log to the run's log file. The returned guard flushes the log when dropped.
+pub fn init(unique_id: &str, log_format: LogFormat) -> WorkerGuard {
+    let log_path = log_path(unique_id);
+    let _ = std::fs::create_dir_all(log_path.parent().expect("log dir"));
+    let file_appender = tracing_appender::rolling::never(
+        log_path.parent().expect("log dir"),
+        log_path.file_name().expect("log file name"),
+    );
+    let (non_blocking, guard) = tracing_appender::non_blocking(file_appender);
+    let subscriber = tracing_subscriber::fmt()
+        .with_env_filter(EnvFilter::from_default_env())
+        .with_writer(non_blocking)
+        .with_ansi(false);
+    match log_format {
+        LogFormat::Text => subscriber.init(),
+        LogFormat::Json => subscriber.event_format(JsonFormat).init(),
+    }
+    guard
+}
+
+/// Upload the run's log file to `<unique_id>/orchestrator.log`.
+///
+/// The log should be flushed (guard dropped) before uploading.
+pub async fn upload(s3_client: &aws_sdk_s3::Client, unique_id: &str) -> OrchResult<()> {
+    let log_path = log_path(unique_id);
+    let log = ByteStream::from_path(&log_path)
+        .await
+        .map_err(|err| OrchError::Init {
+            dbg: format!("Failed to read log {:?}: {}", log_path, err),
+        })?;
+    upload_object(
+        s3_client,
+        STATE.s3_log_bucket,
+        log,
+        &format!("{unique_id}/orchestrator.log"),
+    )
+    .await
+    .map_err(|err| OrchError::S3 {
+        dbg: format!("Failed to upload log: {}", err),
+    })?;
+    Ok(())
+}
+
+/// Formats each event as a single line json object, including the spans
+/// (phase, scenario, etc) that the event occurred in.
+///
+/// ex: {"timestamp":"..","level":"INFO","target":"orchestrator","spans":[{"name":"run","fields":"scenario=incast.json"}],"fields":{"message":".."}}
+struct JsonFormat;
+
+impl<S, N> FormatEvent<S, N> for JsonFormat
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+    N: for<'a> FormatFields<'a> + 'static,
+{
+    fn format_event(
+        &self,
+        ctx: &FmtContext<'_, S, N>,
+        mut writer: Writer<'_>,
+        event: &Event<'_>,
+    ) -> fmt::Result {
+        let mut spans = Vec::new();
+        if let Some(scope) = ctx.event_scope() {
+            for span in scope.from_root() {
+                let extensions = span.extensions();
+                let fields = extensions
+                    .get::<FormattedFields<N>>()
+                    .map(|fields| fields.fields.clone())
+                    .unwrap_or_default();
+                spans.push(json!({ "name": span.name(), "fields": fields }));
+            }
+        }
+
+        let mut fields = JsonVisitor(Map::new());
+        event.record(&mut fields);
+
+        let metadata = event.metadata();
+        let line = json!({
+            "timestamp": humantime::format_rfc3339_millis(SystemTime::now()).to_string(),
+            "level": metadata.level().as_str(),
+            "target": metadata.target(),
+            "spans": spans,
+            "fields": fields.0,
+        });
+        writeln!(writer, "{}", line)
+    }
+}
+
+struct JsonVisitor(Map<String, Value>);
+
+impl Visit for JsonVisitor {
+    fn record_f64(&mut self, field: &Field, value: f64) {
+        self.0.insert(field.name().to_string(), json!(value));
+    }
+
+    fn record_i64(&mut self, field: &Field, value: i64) {
+        self.0.insert(field.name().to_string(), json!(value));
+    }
+
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        self.0.insert(field.name().to_string(), json!(value));
+    }
+
+    fn record_bool(&mut self, field: &Field, value: bool) {
+        self.0.insert(field.name().to_string(), json!(value));
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.0.insert(field.name().to_string(), json!(value));
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        self.0
+            .insert(field.name().to_string(), json!(format!("{:?}", value)));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{
+        io::Write,
+        sync::{Arc, Mutex},
+    };
+    use tracing::{info, info_span};
+
+    #[derive(Clone, Default)]
+    struct Buf(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Buf {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn json_format() {
+        let buf = Buf::default();
+        let writer = buf.clone();
+        let subscriber = tracing_subscriber::fmt()
+            .with_writer(move || writer.clone())
+            .with_ansi(false)
+            .event_format(JsonFormat)
+            .finish();
+        tracing::subscriber::with_default(subscriber, || {
+            info_span!("run", scenario = "incast.json").in_scope(|| {
+                info!(hosts = 2, "running scenario");
+            });
+        });
+
+        let log = String::from_utf8(buf.0.lock().unwrap().clone()).unwrap();
+        let line: Value = serde_json::from_str(log.trim()).unwrap();
+        assert_eq!(line["level"], "INFO");
+        assert_eq!(line["fields"]["message"], "running scenario");
+        assert_eq!(line["fields"]["hosts"], 2);
+        assert_eq!(line["spans"][0]["name"], "run");
+        assert_eq!(line["spans"][0]["fields"], "scenario=\"incast.json\"");
+    }
+}
@@ -0,0 +1,133 @@
+// Copyright Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::{
+    config::Impairment,
+    coordination_utils::{DegradedPeer, DriverFailure},
+    ec2_utils::LinuxDistro,
+    error::{OrchError, OrchResult},
+    s3_utils::{download_object, upload_object},
+    ssm_utils::{
+        build_info::BuildInfo, calibration::CalibrationReport, clock_sync::ClockSync,
+        network_check::NetworkInterface, sysctl::HostSysctl, tuning::HostTuning, DriverSource,
+    },
+    Scenario, STATE,
+};
+use aws_sdk_s3::primitives::ByteStream;
+use bytes::Bytes;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// A machine readable description of a run, stored alongside the run's
+/// artifacts at `<unique_id>/manifest.json`.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct Manifest {
+    pub unique_id: String,
+    pub version: String,
+    pub scenarios: Vec<String>,
+    // The `--label key=value` pairs of the run, also recorded in the run index
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub labels: BTreeMap<String, String>,
+    // Scenario name -> sha256 of the scenario file, verified on each host
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub scenario_checksums: BTreeMap<String, String>,
+    // The repository the s2n-quic and tcp drivers were built from
+    #[serde(default)]
+    pub driver_source: DriverSource,
+    // The distribution of the Linux hosts
+    #[serde(default)]
+    pub distro: LinuxDistro,
+    // Artifact s3 key -> presigned url
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub presigned_urls: BTreeMap<String, String>,
+    // Netbench drivers which exited with an error
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub driver_failures: Vec<DriverFailure>,
+    // Hosts excluded from a scenario by the russula failure policy
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub degraded_peers: Vec<DegradedPeer>,
+    // Job result key -> network impairment applied during the job
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub impairments: BTreeMap<String, Impairment>,
+    // s3 keys of the compressed pcaps
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub pcaps: Vec<String>,
+    // The version and toolchain of each driver build
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub builds: Vec<BuildInfo>,
+    // Clock offset of each host, measured before running the scenarios
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub clock_sync: Vec<ClockSync>,
+    // The primary network interface of each host, verified before running the
+    // scenarios
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub network: Vec<NetworkInterface>,
+    // The tuning profile applied to each host, if `tuning` is configured
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tuning: Vec<HostTuning>,
+    // The kernel tunables of each host, read back once the `sysctl` settings
+    // are applied
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub sysctl: Vec<HostSysctl>,
+    // The throughput of each host probed before running the scenarios, if
+    // `calibration` is configured
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub calibration: Option<CalibrationReport>,
+    // Instance id -> host group, used to reattach to the run
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub hosts: BTreeMap<String, String>,
+}
+
+impl Manifest {
+    pub fn new(unique_id: &str, scenarios: &[Scenario]) -> Self {
+        Manifest {
+            unique_id: unique_id.to_string(),
+            version: STATE.version.to_string(),
+            scenarios: scenarios
+                .iter()
+                .map(|scenario| scenario.name.clone())
+                .collect(),
+            ..Default::default()
+        }
+    }
+
+    pub fn key(unique_id: &str) -> String {
+        format!("{unique_id}/manifest.json")
+    }
+
+    pub async fn upload(&self, s3_client: &aws_sdk_s3::Client) -> OrchResult<()> {
+        let manifest = serde_json::to_string_pretty(self).map_err(|err| OrchError::S3 {
+            dbg: err.to_string(),
+        })?;
+        upload_object(
+            s3_client,
+            STATE.s3_log_bucket,
+            ByteStream::from(Bytes::from(manifest)),
+            &Self::key(&self.unique_id),
+        )
+        .await
+        .map_err(|err| OrchError::S3 {
+            dbg: format!("Failed to upload manifest: {}", err),
+        })?;
+        Ok(())
+    }
+
+    pub async fn download(s3_client: &aws_sdk_s3::Client, unique_id: &str) -> OrchResult<Self> {
+        let key = Self::key(unique_id);
+        let manifest = download_object(s3_client, STATE.s3_log_bucket, &key)
+            .await
+            .map_err(|err| OrchError::S3 {
+                dbg: format!("Failed to download manifest {}: {}", key, err),
+            })?
+            .body
+            .collect()
+            .await
+            .map_err(|err| OrchError::S3 {
+                dbg: format!("Failed to download manifest {}: {}", key, err),
+            })?
+            .into_bytes();
+        serde_json::from_slice(&manifest).map_err(|err| OrchError::S3 {
+            dbg: format!("Failed to parse manifest {}: {}", key, err),
+        })
+    }
+}
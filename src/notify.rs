@@ -0,0 +1,214 @@
+// Copyright Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::{
+    config::Notification,
+    error::{OrchError, OrchResult},
+};
+use aws_types::region::Region;
+use core::time::Duration;
+use serde_json::json;
+use std::process::Stdio;
+use tokio::{io::AsyncWriteExt, process::Command};
+use tracing::{info, warn};
+
+/// How a run ended.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Outcome {
+    Succeeded,
+    Failed(String),
+    Interrupted,
+}
+
+/// The status of a finished run, sent to each of the configured notifications.
+#[derive(Clone, Debug)]
+pub struct RunStatus {
+    pub unique_id: String,
+    pub outcome: Outcome,
+    pub duration: Duration,
+    pub report_url: String,
+}
+
+impl RunStatus {
+    pub fn summary(&self) -> String {
+        let outcome = match &self.outcome {
+            Outcome::Succeeded => "succeeded".to_string(),
+            Outcome::Failed(err) => format!("failed: {err}"),
+            Outcome::Interrupted => "was interrupted".to_string(),
+        };
+        format!(
+            "Netbench run {} {} after {}. Report: {}",
+            self.unique_id,
+            outcome,
+            humantime::format_duration(Duration::from_secs(self.duration.as_secs())),
+            self.report_url
+        )
+    }
+
+    pub fn to_json(&self) -> serde_json::Value {
+        let (status, error) = match &self.outcome {
+            Outcome::Succeeded => ("succeeded", None),
+            Outcome::Failed(err) => ("failed", Some(err.as_str())),
+            Outcome::Interrupted => ("interrupted", None),
+        };
+        json!({
+            "unique_id": self.unique_id,
+            "status": status,
+            "error": error,
+            "duration_secs": self.duration.as_secs(),
+            "report_url": self.report_url,
+        })
+    }
+}
+
+/// Send the status of the run to each notification. A notification which can't
+/// be delivered is logged rather than failing the run.
+pub async fn notify(
+    notifications: &[Notification],
+    status: &RunStatus,
+    aws_config: &aws_types::SdkConfig,
+) {
+    for notification in notifications {
+        match send(notification, status, aws_config).await {
+            Ok(()) => info!("Sent the run status to {}", notification),
+            Err(err) => warn!("Failed to send the run status to {}: {}", notification, err),
+        }
+    }
+}
+
+async fn send(
+    notification: &Notification,
+    status: &RunStatus,
+    aws_config: &aws_types::SdkConfig,
+) -> OrchResult<()> {
+    match notification {
+        Notification::Slack { webhook_env } => {
+            let url = std::env::var(webhook_env).map_err(|_err| OrchError::Init {
+                dbg: format!("Missing slack webhook url in env var {}", webhook_env),
+            })?;
+            post_json(&url, &[], &json!({ "text": status.summary() }).to_string()).await
+        }
+        Notification::Sns { topic_arn } => {
+            // publish in the region of the topic. ex: arn:aws:sns:<region>:<account>:<name>
+            let mut config = aws_sdk_sns::config::Builder::from(aws_config);
+            if let Some(region) = topic_arn.split(':').nth(3) {
+                config = config.region(Region::new(region.to_string()));
+            }
+            aws_sdk_sns::Client::from_conf(config.build())
+                .publish()
+                .topic_arn(topic_arn)
+                .subject(format!("Netbench run {}", status.unique_id))
+                .message(status.summary())
+                .send()
+                .await
+                .map_err(|err| OrchError::Init {
+                    dbg: err.to_string(),
+                })?;
+            Ok(())
+        }
+        Notification::Webhook { url } => post_json(url, &[], &status.to_json().to_string()).await,
+    }
+}
+
+/// POST the json `body` to `url` with curl.
+///
+/// The url, `headers` and body are read by curl from its config on stdin rather
+/// than its arguments, which are visible to the other users of the host. ex: a
+/// slack webhook url or a github token
+pub(crate) async fn post_json(url: &str, headers: &[String], body: &str) -> OrchResult<()> {
+    let mut child = Command::new("curl")
+        .args(["--silent", "--show-error", "--fail", "-X", "POST"])
+        .args(["-H", "Content-Type: application/json"])
+        .args(["--max-time", "30", "--config", "-"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|err| OrchError::Init {
+            dbg: format!("Failed to run curl: {}", err),
+        })?;
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin
+            .write_all(curl_config(url, headers, body).as_bytes())
+            .await
+            .map_err(|err| OrchError::Init {
+                dbg: format!("Failed to write to curl: {}", err),
+            })?;
+    }
+    let output = child
+        .wait_with_output()
+        .await
+        .map_err(|err| OrchError::Init {
+            dbg: format!("Failed to run curl: {}", err),
+        })?;
+    if !output.status.success() {
+        return Err(OrchError::Init {
+            dbg: format!(
+                "Failed to post: {}",
+                String::from_utf8_lossy(&output.stderr)
+            ),
+        });
+    }
+    Ok(())
+}
+
+fn curl_config(url: &str, headers: &[String], body: &str) -> String {
+    // the body is sent as is, even if it starts with @
+    let mut config = format!("url = {}\n", quote(url));
+    for header in headers {
+        config.push_str(&format!("header = {}\n", quote(header)));
+    }
+    config.push_str(&format!("data-raw = {}\n", quote(body)));
+    config
+}
+
+/// Quote a parameter of a curl config, in which `\`, `"` and the line breaks
+/// are escaped.
+fn quote(value: &str) -> String {
+    let escaped = value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+        .replace('\r', "\\r")
+        .replace('\t', "\\t");
+    format!("\"{escaped}\"")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn run_status() {
+        let status = RunStatus {
+            unique_id: "2023-10-11T17:05:09Z-v2.0.0".to_string(),
+            outcome: Outcome::Failed("ssm timed out".to_string()),
+            duration: Duration::from_millis(3_723_400),
+            report_url: "https://d1.cloudfront.net/2023-10-11T17:05:09Z-v2.0.0/report/index.html"
+                .to_string(),
+        };
+        assert_eq!(
+            status.summary(),
+            "Netbench run 2023-10-11T17:05:09Z-v2.0.0 failed: ssm timed out after 1h 2m 3s. Report: https://d1.cloudfront.net/2023-10-11T17:05:09Z-v2.0.0/report/index.html"
+        );
+        let json = status.to_json();
+        assert_eq!(json["status"], "failed");
+        assert_eq!(json["error"], "ssm timed out");
+        assert_eq!(json["duration_secs"], 3723);
+
+        let status = RunStatus {
+            outcome: Outcome::Succeeded,
+            ..status
+        };
+        assert!(status.to_json()["error"].is_null());
+
+        assert_eq!(
+            curl_config(
+                "https://hooks.slack.com/services/T0/B0/x\"y",
+                &["Authorization: Bearer token".to_string()],
+                "{\"text\":\"a\\nb\"}\n"
+            ),
+            "url = \"https://hooks.slack.com/services/T0/B0/x\\\"y\"\nheader = \"Authorization: Bearer token\"\ndata-raw = \"{\\\"text\\\":\\\"a\\\\nb\\\"}\\n\"\n"
+        );
+    }
+}
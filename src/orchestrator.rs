@@ -2,27 +2,46 @@
 // SPDX-License-Identifier: Apache-2.0
 
 use crate::{
-    coordination_utils, dashboard,
-    ec2_utils::LaunchPlan,
+    config::OrchestratorConfig,
+    control_plane,
+    coordination_utils::{self, DegradedPeer, DriverFailure, RouterNetbenchRussula, RussulaAddrs},
+    dashboard,
+    ec2_utils::{
+        ensure_healthy,
+        lease::{self, HostLease},
+        pool::InfraPool,
+        HostOs, InfraDetail, LaunchPlan,
+    },
     error::{OrchError, OrchResult},
-    report::orch_generate_report,
-    ssm_utils, update_dashboard, upload_object, Args, Scenario, STATE,
+    index, list_objects,
+    manifest::Manifest,
+    report::{
+        github, orch_generate_report, presign_report, trend, upload_driver_failures, ReportOptions,
+    },
+    run_spec::{Driver, RunGroup},
+    russula::Transport,
+    scenario, shutdown, ssm_utils, update_dashboard, Args, NetbenchDriver, Scenario, STATE,
 };
-use aws_sdk_s3::primitives::ByteStream;
 use aws_types::region::Region;
-use tracing::info;
+use core::time::Duration;
+use indicatif::MultiProgress;
+use tracing::{info, info_span, warn, Instrument};
+
+// How often the hosts stream their output of a soak run (--duration) to s3
+const STREAM_INTERVAL: Duration = Duration::from_secs(10 * 60);
 
 // TODO
 // D- clap app
 // D- upload request_response.json
 // D- get STATE config from scenario.json
-// - save netbench output to different named files instead of server.json/client.json
+// D- run multiple scenarios on the same infra
+// D- save netbench output to different named files instead of server.json/client.json
 //
 // # Expanding Russula/Cli
 // D- pass scenario to russula_cli
 // - pass netbench_path to russula_cli
-// - pass scenario and path from coord -> worker?
-// - replace russula_cli russula_port with russula_pair_addr_list
+// D- pass scenario and path from coord -> worker?
+// D- replace russula_cli russula_port with russula_pair_addr_list
 //
 // # Optimization
 // - use release build instead of debug
@@ -30,10 +49,34 @@ use tracing::info;
 
 pub async fn run(
     unique_id: String,
-    _args: Args,
-    scenario: Scenario,
+    args: &Args,
+    config: &OrchestratorConfig,
+    group: &RunGroup,
     aws_config: &aws_types::SdkConfig,
 ) -> OrchResult<()> {
+    let scenarios = &group.scenarios;
+    // ssm port forwarding sessions only relay tcp
+    if config.private_network.is_some() && args.russula_transport == Transport::Udp {
+        return Err(OrchError::Init {
+            dbg: "The udp russula transport is not supported with a private_network".to_string(),
+        });
+    }
+    if config.client_os.is_windows() {
+        check_windows_clients(args, group)?;
+    }
+    let coordinator_ip =
+        control_plane::coordinator_ip(config.control_plane.russula_registration.as_ref())?;
+    if coordinator_ip.is_some() && args.russula_transport == Transport::Udp {
+        return Err(OrchError::Init {
+            dbg: "russula Workers only register over the tcp transport".to_string(),
+        });
+    }
+    if coordinator_ip.is_some() && config.client_os.is_windows() {
+        return Err(OrchError::Init {
+            dbg: "russula_registration isn't supported with windows clients".to_string(),
+        });
+    }
+
     let iam_client = aws_sdk_iam::Client::new(aws_config);
     let s3_client = aws_sdk_s3::Client::new(aws_config);
     let orch_provider_vpc = Region::new(STATE.vpc_region);
@@ -43,46 +86,155 @@ pub async fn run(
         .await;
     let ec2_client = aws_sdk_ec2::Client::new(&shared_config_vpc);
     let ssm_client = aws_sdk_ssm::Client::new(&shared_config_vpc);
+    let logs_client = aws_sdk_cloudwatchlogs::Client::new(&shared_config_vpc);
 
-    let scenario_file = ByteStream::from_path(scenario.path.as_path())
-        .await
-        .map_err(|err| OrchError::Init {
-            dbg: err.to_string(),
-        })?;
-    upload_object(
+    // the scenarios are uploaded with the run's certificates
+    let certs = match &config.tls {
+        Some(tls) => {
+            let certs = scenario::certs::RunCerts::generate(tls)?;
+            certs.upload(&s3_client, &unique_id).await?;
+            Some(certs)
+        }
+        None => None,
+    };
+    let scenario_checksums =
+        scenario::upload_scenarios(&s3_client, &unique_id, scenarios, certs.as_ref()).await?;
+
+    println!(
+        "Run: {unique_id}. Follow it from another machine with `attach --unique-id {unique_id}`"
+    );
+    let mut manifest = Manifest::new(&unique_id, scenarios);
+    manifest.driver_source = args.driver_source();
+    manifest.distro = group.distro;
+    manifest.scenario_checksums = scenario_checksums;
+    manifest.labels = args.labels.iter().cloned().collect();
+    manifest.upload(&s3_client).await?;
+    index::record(&s3_client, &manifest).await?;
+
+    update_dashboard(dashboard::Step::UploadIndex, &s3_client, &unique_id).await?;
+
+    // the hosts shutdown after the run is expected to complete
+    let job_estimate = lease::job_estimate(args.duration, args.warmup);
+    let shutdown_min = lease::shutdown_min(
+        args.host_lifetime
+            .unwrap_or_else(|| lease::lifetime_estimate(group.jobs.len(), job_estimate)),
+    )?;
+    let mut lease = HostLease::new(shutdown_min);
+
+    // Setup instances, or reuse the hosts of an infra pool
+    let pool = match &args.use_infra {
+        Some(name) => {
+            let pool = InfraPool::download(&s3_client, name).await?;
+            pool.check_fits(
+                scenarios,
+                &group.instance_type,
+                group.distro,
+                config.private_network.is_some(),
+            )?;
+            info!("Using infra pool {}", pool);
+            Some(pool)
+        }
+        None => None,
+    };
+    let infra = match &pool {
+        Some(pool) => pool.infra(),
+        None => {
+            async {
+                let mut launch_plan = LaunchPlan::create(
+                    &unique_id,
+                    &ec2_client,
+                    &iam_client,
+                    &ssm_client,
+                    group,
+                    config,
+                )
+                .await?;
+                config.budget.check_lifetime(
+                    launch_plan.instances(),
+                    &group.instance_type,
+                    shutdown_min,
+                )?;
+                launch_plan.shutdown_min = shutdown_min;
+                if args.capture_pcap.is_some() || args.profile.is_some() {
+                    launch_plan.storage.for_captures();
+                }
+                let mut infra = launch_plan.launch(&ec2_client, &unique_id).await?;
+                if let Err(err) = ensure_healthy(
+                    &launch_plan,
+                    &ec2_client,
+                    &ssm_client,
+                    &unique_id,
+                    &mut infra,
+                    args.health_check_retries,
+                )
+                .await
+                {
+                    // don't leave the hosts running once we give up
+                    infra.cleanup(&ec2_client).await?;
+                    return Err(err);
+                }
+                Ok::<InfraDetail, OrchError>(infra)
+            }
+            .instrument(info_span!("launch"))
+            .await?
+        }
+    };
+    let host_setup = match pool {
+        Some(_) => ssm_utils::common::HostSetup::Build,
+        None => ssm_utils::common::HostSetup::Configure {
+            distro: group.distro,
+            shutdown_min,
+            build_cache: config.build_cache.as_ref(),
+            instance_store: config.storage.instance_store,
+        },
+    };
+    let mut secrets = match ssm_utils::secrets::RunSecrets::prepare(
+        &shared_config_vpc,
         &s3_client,
-        STATE.s3_log_bucket,
-        scenario_file,
-        &format!("{unique_id}/{}", scenario.name),
+        &iam_client,
+        &unique_id,
+        &config.secrets,
     )
     .await
-    .unwrap();
-
-    update_dashboard(dashboard::Step::UploadIndex, &s3_client, &unique_id).await?;
+    {
+        Ok(secrets) => secrets,
+        Err(err) => {
+            cleanup_infra(&ec2_client, &infra, pool.as_ref()).await?;
+            return Err(err);
+        }
+    };
+    if shutdown::is_interrupted() {
+        shutdown::stage(
+            "discard the secrets",
+            secrets.cleanup(&ssm_client, &s3_client),
+        )
+        .await;
+        return shutdown_run(
+            &s3_client,
+            &ec2_client,
+            &unique_id,
+            &infra,
+            &manifest,
+            pool.as_ref(),
+            None,
+        )
+        .await;
+    }
+    let client_ids = infra.client_ids();
+    let server_ids = infra.server_ids();
+    // the hosts checked and tuned with Linux tools
+    let linux_infra = infra.linux_hosts(config.client_os);
 
-    // Setup instances
-    let infra = LaunchPlan::create(&unique_id, &ec2_client, &iam_client, &ssm_client, &scenario)
-        .await
-        .launch(&ec2_client, &unique_id)
-        .await?;
-    let client_ids: Vec<String> = infra
-        .clients
-        .clone()
-        .into_iter()
-        .map(|infra_detail| {
-            let id = infra_detail.instance_id().unwrap();
-            id.to_string()
-        })
-        .collect();
-    let server_ids: Vec<String> = infra
-        .servers
-        .clone()
-        .into_iter()
-        .map(|infra_detail| {
-            let id = infra_detail.instance_id().unwrap();
-            id.to_string()
-        })
-        .collect();
+    // record the hosts so that `attach` can follow the run's ssm commands
+    for host in infra.hosts() {
+        manifest
+            .hosts
+            .insert(host.instance_id.clone(), host.group());
+    }
+    for host_group in infra.group_names() {
+        info!("{} hosts: {:?}", host_group, infra.group_ips(&host_group));
+    }
+    manifest.upload(&s3_client).await?;
 
     update_dashboard(
         dashboard::Step::ServerHostsRunning(&infra.servers),
@@ -91,122 +243,844 @@ pub async fn run(
     )
     .await?;
     update_dashboard(
-        dashboard::Step::ServerHostsRunning(&infra.clients),
+        dashboard::Step::ClientHostsRunning(&infra.clients),
         &s3_client,
         &unique_id,
     )
     .await?;
 
     // custom driver
-    let dc_quic_server_driver = ssm_utils::dc_quic_server_driver(&unique_id, &scenario);
-    let dc_quic_client_driver = ssm_utils::dc_quic_client_driver(&unique_id, &scenario);
-    let quic_server_driver = ssm_utils::quic_server_driver(&unique_id, &scenario);
-    let quic_client_driver = ssm_utils::quic_client_driver(&unique_id, &scenario);
-    let tcp_server_driver = ssm_utils::tcp_server_driver(&unique_id, &scenario);
-    let tcp_client_driver = ssm_utils::tcp_client_driver(&unique_id, &scenario);
+    let dc_quic_server_driver = ssm_utils::dc_quic_server_driver(&unique_id);
+    let dc_quic_client_driver = ssm_utils::dc_quic_client_driver(&unique_id);
+    // cloned since the manifest is updated while the drivers are built
+    let driver_source = &manifest.driver_source.clone();
+    let quic_server_driver = ssm_utils::quic_server_driver(driver_source);
+    let quic_client_driver = ssm_utils::quic_client_driver(driver_source);
+    let tcp_server_driver = ssm_utils::tcp_server_driver(driver_source);
+    let tcp_client_driver = ssm_utils::tcp_client_driver(driver_source);
+    let iperf3_server_driver = ssm_utils::iperf3_server_driver();
+    let iperf3_client_driver = ssm_utils::iperf3_client_driver();
 
-    let client_driver_to_run = &tcp_client_driver;
-    let server_driver_to_run = &tcp_server_driver;
+    let configure = async {
+        // upload local driver source so that it can be built on the hosts
+        for driver in [
+            &dc_quic_server_driver,
+            &dc_quic_client_driver,
+            &quic_server_driver,
+            &quic_client_driver,
+            &tcp_server_driver,
+            &tcp_client_driver,
+        ] {
+            driver.upload_local_source(&s3_client, &unique_id).await?;
+        }
 
-    // configure and build
-    {
-        let mut build_cmds = ssm_utils::common::collect_config_cmds(
-            "server",
-            &ssm_client,
-            server_ids.clone(),
-            &[
+        // configure and build the server and client hosts concurrently
+        {
+            let multi_progress = MultiProgress::new();
+            let mut server_drivers = vec![
                 &dc_quic_server_driver,
                 &quic_server_driver,
                 &tcp_server_driver,
-            ],
-            &unique_id,
-        )
-        .await;
-        let client_build_cmds = ssm_utils::common::collect_config_cmds(
-            "client",
-            &ssm_client,
-            client_ids.clone(),
-            &[
+            ];
+            let mut client_drivers = vec![
                 &dc_quic_client_driver,
                 &quic_client_driver,
                 &tcp_client_driver,
-            ],
-            &unique_id,
-        )
-        .await;
-        build_cmds.extend(client_build_cmds);
-        ssm_utils::common::wait_complete(
-            "Setup hosts: update and install dependencies",
-            &ssm_client,
-            build_cmds,
-        )
-        .await;
+            ];
+            if config.iperf3.is_some() || config.calibration.is_some() {
+                server_drivers.push(&iperf3_server_driver);
+                client_drivers.push(&iperf3_client_driver);
+            }
+            let server_setup = ssm_utils::common::configure_host_group(
+                "server",
+                &ssm_client,
+                server_ids.clone(),
+                &server_drivers,
+                &unique_id,
+                host_setup,
+                &multi_progress,
+            );
+            let client_setup = async {
+                match config.client_os {
+                    HostOs::Linux => {
+                        ssm_utils::common::configure_host_group(
+                            "client",
+                            &ssm_client,
+                            client_ids.clone(),
+                            &client_drivers,
+                            &unique_id,
+                            host_setup,
+                            &multi_progress,
+                        )
+                        .await
+                    }
+                    HostOs::Windows => {
+                        ssm_utils::windows::configure_host_group(
+                            &ssm_client,
+                            client_ids.clone(),
+                            &[&quic_client_driver, &tcp_client_driver],
+                            driver_source,
+                            &unique_id,
+                            host_setup,
+                            &multi_progress,
+                        )
+                        .await
+                    }
+                }
+            };
+            // routers only run russula
+            let router_setup = ssm_utils::common::configure_host_group(
+                "router",
+                &ssm_client,
+                infra.router_ids(),
+                &[],
+                &unique_id,
+                host_setup,
+                &multi_progress,
+            );
+            tokio::try_join!(server_setup, client_setup, async {
+                match infra.routers.is_empty() {
+                    true => Ok(()),
+                    false => router_setup.await,
+                }
+            })?;
 
-        info!("Host setup Successful");
-    }
+            info!("Host setup Successful");
+        }
 
-    // run russula
-    {
-        let mut server_russula = coordination_utils::ServerNetbenchRussula::new(
+        // every host runs the exact scenario files validated locally
+        ssm_utils::common::distribute_scenarios(
             &ssm_client,
-            &infra,
-            server_ids.clone(),
-            &scenario,
-            server_driver_to_run,
+            linux_infra.instance_ids(),
+            &unique_id,
+            &manifest.scenario_checksums,
         )
-        .await;
+        .await?;
+        if certs.is_some() {
+            ssm_utils::common::install_certs(
+                &ssm_client,
+                server_ids.clone(),
+                linux_infra.client_ids(),
+                &unique_id,
+            )
+            .await?;
+        }
+        if config.client_os.is_windows() {
+            ssm_utils::windows::distribute_scenarios(
+                &ssm_client,
+                client_ids.clone(),
+                &unique_id,
+                &manifest.scenario_checksums,
+            )
+            .await?;
+        }
 
-        let mut client_russula = coordination_utils::ClientNetbenchRussula::new(
+        manifest.clock_sync = ssm_utils::clock_sync::verify_clock_sync(
             &ssm_client,
-            &infra,
-            client_ids.clone(),
-            &scenario,
-            client_driver_to_run,
+            &linux_infra,
+            args.max_clock_offset,
         )
-        .await;
+        .await?;
+        manifest.network =
+            ssm_utils::network_check::verify_network(&ssm_client, &linux_infra, config.mtu).await?;
+        if let Some(tuning) = &config.tuning {
+            manifest.tuning =
+                ssm_utils::tuning::apply_tuning(&ssm_client, &linux_infra, tuning).await?;
+        }
+        if !config.sysctl.is_empty() {
+            manifest.sysctl =
+                ssm_utils::sysctl::apply_sysctl(&ssm_client, &linux_infra, &config.sysctl).await?;
+        }
+        // measured after tuning so that it reflects the hosts the scenarios run on
+        if let Some(calibration) = &config.calibration {
+            manifest.calibration = Some(
+                ssm_utils::calibration::calibrate(
+                    &ssm_client,
+                    &s3_client,
+                    &linux_infra,
+                    &unique_id,
+                    &group.instance_type,
+                    calibration,
+                )
+                .await?,
+            );
+        }
+        if let Some(iperf3) = &config.iperf3 {
+            ssm_utils::iperf3::run_baseline(&ssm_client, &linux_infra, &unique_id, iperf3).await?;
+        }
+        manifest.builds =
+            ssm_utils::build_info::download_build_info(&s3_client, &unique_id).await?;
+        for build in manifest.builds.iter() {
+            info!("Built {}", build);
+        }
+        manifest.upload(&s3_client).await?;
+        Ok::<(), OrchError>(())
+    }
+    .instrument(info_span!("configure"));
+    let configured = tokio::select! {
+        result = configure => result,
+        _ = shutdown::interrupted() => {
+            shutdown::stage("shred the secrets", secrets.cleanup(&ssm_client, &s3_client)).await;
+            return shutdown_run(&s3_client, &ec2_client, &unique_id, &infra, &manifest, pool.as_ref(), None).await;
+        }
+    };
+    // hosts which failed to configure, or were refused by a check such as the
+    // clock sync, aren't left running
+    if let Err(err) = configured {
+        if let Err(err) = secrets.cleanup(&ssm_client, &s3_client).await {
+            warn!("{}", err);
+        }
+        cleanup_infra(&ec2_client, &infra, pool.as_ref()).await?;
+        return Err(err);
+    }
+    // the drivers read the secrets from the hosts. The secrets delivered to
+    // some of the hosts are shredded
+    if let Err(err) = secrets
+        .deliver(&ssm_client, linux_infra.instance_ids())
+        .await
+    {
+        if let Err(err) = secrets.cleanup(&ssm_client, &s3_client).await {
+            warn!("{}", err);
+        }
+        cleanup_infra(&ec2_client, &infra, pool.as_ref()).await?;
+        return Err(err);
+    }
 
-        // run client/server
-        server_russula.wait_workers_running(&ssm_client).await;
-        client_russula.wait_done(&ssm_client).await;
-        server_russula.wait_done(&ssm_client).await;
+    // the (server, client) drivers of a job
+    let driver_pair = |driver: Driver| match driver {
+        Driver::S2nQuicDc => (&dc_quic_server_driver, &dc_quic_client_driver),
+        Driver::S2nQuic => (&quic_server_driver, &quic_client_driver),
+        Driver::Tcp => (&tcp_server_driver, &tcp_client_driver),
+    };
+
+    // rather than finding out that a driver is broken once its job runs
+    if !args.skip_smoke {
+        let mut drivers = Vec::new();
+        for job in group.jobs.iter() {
+            if !drivers.contains(&job.driver) {
+                drivers.push(job.driver);
+            }
+        }
+        let drivers: Vec<_> = drivers.into_iter().map(driver_pair).collect();
+        if let Err(err) = ssm_utils::smoke::smoke_test(&ssm_client, &linux_infra, &drivers)
+            .instrument(info_span!("smoke"))
+            .await
+        {
+            if let Err(err) = secrets.cleanup(&ssm_client, &s3_client).await {
+                warn!("{}", err);
+            }
+            cleanup_infra(&ec2_client, &infra, pool.as_ref()).await?;
+            return Err(err);
+        }
     }
 
-    // copy netbench results
-    {
-        let copy_server_netbench = ssm_utils::server::upload_netbench_data(
-            &ssm_client,
-            server_ids.clone(),
-            &unique_id,
-            &scenario,
-            server_driver_to_run,
+    let worker_opts = ssm_utils::WorkerOptions {
+        transport: args.russula_transport,
+        sys_metrics_interval: args.sys_metrics_interval,
+        profile: args.profile,
+        capture_pcap: args.capture_pcap,
+        collector: config.collector,
+        driver_instances: args.driver_instances,
+        warmup: false,
+        duration: args.duration,
+        stream_interval: args
+            .stream_interval
+            .or(args.duration.map(|_| STREAM_INTERVAL)),
+        stream_to: None,
+        failure_policy: args.failure_policy,
+        client_os: config.client_os,
+        coordinator_ip,
+        russula_timeouts: config.russula_timeouts.with_duration(args.duration),
+    };
+    let russula_addrs = RussulaAddrs::new(
+        &infra,
+        config.private_network.is_some(),
+        coordinator_ip.is_some(),
+    )
+    .await?;
+
+    // run each job on the same infra
+    let mut interrupted = false;
+    for (i, job) in group.jobs.iter().enumerate() {
+        // the lifetime of a pool's hosts is managed with `infra create`
+        if pool.is_none() {
+            let remaining = job_estimate * (group.jobs.len() - i) as u32;
+            lease
+                .ensure(&ssm_client, &infra, config.client_os, remaining)
+                .await?;
+        }
+        let scenario = &job.scenario;
+        let (server_driver_to_run, client_driver_to_run) = driver_pair(job.driver);
+        interrupted = async {
+            info!("Running scenario: {} with {}", scenario.name, job.driver);
+            let scenario_infra = infra.for_scenario(scenario);
+            let client_ids = scenario_infra.client_ids();
+            let server_ids = scenario_infra.server_ids();
+
+            // emulate degraded network conditions
+            let impairment = job.impairment.as_ref();
+            if let Some(impairment) = impairment {
+                info!("Applying impairment: {}", impairment.netem_args());
+                ssm_utils::common::configure_impairment(
+                    &ssm_client,
+                    scenario_infra.instance_ids(),
+                    Some(impairment),
+                )
+                .await?;
+                manifest
+                    .impairments
+                    .insert(job.result_key(), impairment.clone());
+                manifest.upload(&s3_client).await?;
+            }
+
+            // run russula
+            let russula = async {
+                // route the client traffic through the routers before netbench starts
+                let mut router_russula = if scenario_infra.routers.is_empty() {
+                    None
+                } else {
+                    let mut router_russula = RouterNetbenchRussula::new(
+                        &ssm_client,
+                        &scenario_infra,
+                        &russula_addrs,
+                        &worker_opts,
+                    )
+                    .await?;
+                    router_russula.wait_workers_routing(&ssm_client).await?;
+                    ssm_utils::router::configure_client_routes(&ssm_client, &scenario_infra, true)
+                        .await?;
+                    Some(router_russula)
+                };
+
+                if args.warmup {
+                    warm_up(
+                        &ssm_client,
+                        &scenario_infra,
+                        &russula_addrs,
+                        scenario,
+                        server_driver_to_run,
+                        client_driver_to_run,
+                        &worker_opts,
+                    )
+                    .await?;
+                }
+
+                // the hosts stream their output to s3 while the job runs
+                let worker_opts = ssm_utils::WorkerOptions {
+                    stream_to: Some(format!(
+                        "{}/streams/{}",
+                        STATE.s3_path(&unique_id),
+                        job.result_key()
+                    )),
+                    ..worker_opts.clone()
+                };
+                let mut server_russula = coordination_utils::ServerNetbenchRussula::new(
+                    &ssm_client,
+                    &scenario_infra,
+                    &russula_addrs,
+                    server_ids.clone(),
+                    scenario,
+                    server_driver_to_run,
+                    &worker_opts,
+                )
+                .await?;
+
+                let mut client_russula = coordination_utils::ClientNetbenchRussula::new(
+                    &ssm_client,
+                    &scenario_infra,
+                    &russula_addrs,
+                    client_ids.clone(),
+                    scenario,
+                    client_driver_to_run,
+                    &worker_opts,
+                )
+                .await?;
+
+                // run client/server
+                let interrupted = tokio::select! {
+                    result = async {
+                        server_russula.wait_workers_running(&ssm_client).await?;
+                        tokio::select! {
+                            result = client_russula.wait_done(&ssm_client) => result?,
+                            _ = dashboard::live::publish(&s3_client, &unique_id) => (),
+                        }
+                        server_russula.wait_done(&ssm_client).await
+                    } => {
+                        result?;
+                        false
+                    }
+                    _ = shutdown::interrupted() => true,
+                };
+                if interrupted {
+                    // Kill the server drivers so that they write their results. The
+                    // client drivers exit once the servers are gone and the hosts
+                    // are deleted regardless.
+                    shutdown::stage(
+                        "stop the server workers",
+                        server_russula.wait_done(&ssm_client),
+                    )
+                    .await;
+                    if let Some(router_russula) = router_russula.as_mut() {
+                        shutdown::stage(
+                            "stop the router workers",
+                            router_russula.wait_done(&ssm_client),
+                        )
+                        .await;
+                    }
+                } else if let Some(router_russula) = router_russula.as_mut() {
+                    ssm_utils::router::configure_client_routes(&ssm_client, &scenario_infra, false)
+                        .await?;
+                    router_russula.wait_done(&ssm_client).await?;
+                }
+
+                // hosts excluded by the failure policy are reported with the results
+                let degraded_peers: Vec<DegradedPeer> = server_russula
+                    .degraded_peers()
+                    .into_iter()
+                    .chain(client_russula.degraded_peers())
+                    .chain(
+                        router_russula
+                            .iter()
+                            .flat_map(|router| router.degraded_peers(&scenario.name)),
+                    )
+                    .collect();
+                if !degraded_peers.is_empty() {
+                    for peer in degraded_peers.iter() {
+                        warn!("{}", peer);
+                    }
+                    manifest.degraded_peers.extend(degraded_peers);
+                    manifest.upload(&s3_client).await?;
+                }
+
+                // fail the run instead of producing empty results. drivers are
+                // expected to fail once killed by an interrupt
+                let driver_failures: Vec<DriverFailure> = server_russula
+                    .driver_failures()
+                    .into_iter()
+                    .chain(client_russula.driver_failures())
+                    .collect();
+                if !interrupted && !driver_failures.is_empty() {
+                    manifest.driver_failures = driver_failures;
+                    manifest.upload(&s3_client).await?;
+                    upload_driver_failures(&s3_client, &unique_id, &manifest.driver_failures)
+                        .await?;
+                    log_failed_host_events(
+                        &logs_client,
+                        &scenario_infra,
+                        &manifest.driver_failures,
+                    )
+                    .await;
+                    if let Err(err) = secrets.cleanup(&ssm_client, &s3_client).await {
+                        warn!("{}", err);
+                    }
+                    cleanup_infra(&ec2_client, &infra, pool.as_ref()).await?;
+                    return Err(DriverFailure::to_error(&manifest.driver_failures));
+                }
+                Ok::<bool, OrchError>(interrupted)
+            };
+            let interrupted = match russula.await {
+                // a phase which exceeded its budget aborts the run
+                Err(err @ OrchError::RussulaTimeout { .. }) => {
+                    warn!("{}", err);
+                    abort_scenario(
+                        &ssm_client,
+                        &logs_client,
+                        &scenario_infra.linux_hosts(config.client_os),
+                        &unique_id,
+                        &job.result_key(),
+                        &err,
+                    )
+                    .await;
+                    if let Err(err) = secrets.cleanup(&ssm_client, &s3_client).await {
+                        warn!("{}", err);
+                    }
+                    cleanup_infra(&ec2_client, &infra, pool.as_ref()).await?;
+                    shutdown::abort_stage(
+                        "upload the timeline",
+                        update_dashboard(dashboard::Step::UploadTimeline, &s3_client, &unique_id),
+                    )
+                    .await;
+                    return Err(err);
+                }
+                result => result?,
+            };
+
+            if impairment.is_some() && !interrupted {
+                ssm_utils::common::configure_impairment(
+                    &ssm_client,
+                    scenario_infra.instance_ids(),
+                    None,
+                )
+                .await?;
+            }
+
+            // copy netbench results, which are partial if interrupted
+            let copy_results = async {
+                let copy_server_netbench = ssm_utils::server::upload_netbench_data(
+                    &ssm_client,
+                    server_ids.clone(),
+                    &unique_id,
+                    &job.result_key(),
+                    server_driver_to_run,
+                )
+                .await?;
+                let copy_client_netbench = match config.client_os {
+                    HostOs::Linux => {
+                        ssm_utils::client::upload_netbench_data(
+                            &ssm_client,
+                            client_ids.clone(),
+                            &unique_id,
+                            &job.result_key(),
+                            client_driver_to_run,
+                        )
+                        .await?
+                    }
+                    HostOs::Windows => {
+                        ssm_utils::windows::upload_netbench_data(
+                            &ssm_client,
+                            client_ids.clone(),
+                            &unique_id,
+                            &job.result_key(),
+                            client_driver_to_run,
+                        )
+                        .await?
+                    }
+                };
+                ssm_utils::common::wait_complete(
+                    "client_server",
+                    &ssm_client,
+                    vec![copy_server_netbench, copy_client_netbench],
+                    &MultiProgress::new(),
+                )
+                .await?;
+                info!("client_server netbench copy results!: Successful");
+                Ok::<(), OrchError>(())
+            };
+            if interrupted {
+                shutdown::stage("upload the partial results", copy_results).await;
+            } else {
+                copy_results.await?;
+            }
+            Ok::<bool, OrchError>(interrupted || shutdown::is_interrupted())
+        }
+        .instrument(info_span!(
+            "run",
+            scenario = %scenario.name,
+            driver = %job.driver,
+            variant = job.variant.as_deref().unwrap_or("-"),
+            iteration = job.iteration.unwrap_or(1)
+        ))
+        .await?;
+        if interrupted {
+            break;
+        }
+    }
+    if interrupted {
+        shutdown::stage(
+            "shred the secrets",
+            secrets.cleanup(&ssm_client, &s3_client),
         )
         .await;
-        let copy_client_netbench = ssm_utils::client::upload_netbench_data(
-            &ssm_client,
-            client_ids.clone(),
+        return shutdown_run(
+            &s3_client,
+            &ec2_client,
             &unique_id,
-            &scenario,
-            client_driver_to_run,
+            &infra,
+            &manifest,
+            pool.as_ref(),
+            Some(ReportOptions {
+                download: args.download_options(),
+                netbench: config.netbench_report,
+            }),
         )
         .await;
-        ssm_utils::common::wait_complete(
-            "client_server_netbench_copy_results",
-            &ssm_client,
-            vec![copy_server_netbench, copy_client_netbench],
+    }
+
+    async {
+        // Record the location of the pcaps
+        if worker_opts.capture_pcap.is_some() {
+            manifest.pcaps = list_objects(
+                &s3_client,
+                STATE.s3_log_bucket,
+                &format!("{unique_id}/pcap/"),
+            )
+            .await?
+            .into_keys()
+            .collect();
+            manifest.upload(&s3_client).await?;
+        }
+
+        // Copy results back
+        orch_generate_report(
+            &s3_client,
+            &unique_id,
+            &manifest.degraded_peers,
+            ReportOptions {
+                download: args.download_options(),
+                netbench: config.netbench_report,
+            },
         )
-        .await;
-        info!("client_server netbench copy results!: Successful");
+        .await?;
+
+        if let (Some(github), Some(pr)) = (&config.github, args.github_pr) {
+            // the results are already collected so don't fail the run
+            if let Err(err) = github::post_comparison(&s3_client, &unique_id, github, pr).await {
+                warn!("Failed to comment on {}#{}: {}", github.repo, pr, err);
+            }
+        }
+        if let Some(trend) = &config.trend {
+            let dynamodb_client = aws_sdk_dynamodb::Client::new(aws_config);
+            if let Err(err) = trend::record(
+                &s3_client,
+                &dynamodb_client,
+                trend,
+                &unique_id,
+                &group.instance_type,
+                &manifest.builds,
+            )
+            .await
+            {
+                warn!("Failed to record the trend in {}: {}", trend.table, err);
+            }
+        }
+
+        // Share results with users who don't have access to the bucket
+        manifest.presigned_urls =
+            presign_report(&s3_client, &unique_id, args.presign_expiry).await?;
+        manifest.upload(&s3_client).await?;
+        println!(
+            "Presigned urls (valid for {}):",
+            humantime::format_duration(args.presign_expiry)
+        );
+        for (key, url) in manifest.presigned_urls.iter() {
+            println!("{key}: {url}");
+        }
+        Ok::<(), OrchError>(())
+    }
+    .instrument(info_span!("collect"))
+    .await?;
+
+    // Cleanup. The hosts of an infra pool outlive the run so they're restored to
+    // their default tunables
+    if !config.sysctl.is_empty() {
+        if let Err(err) = ssm_utils::sysctl::revert_sysctl(&ssm_client, &linux_infra).await {
+            warn!("Failed to revert the kernel tunables: {}", err);
+        }
+    }
+    // The secrets are shredded before the hosts are deleted
+    let shredded = secrets.cleanup(&ssm_client, &s3_client).await;
+    cleanup_infra(&ec2_client, &infra, pool.as_ref()).await?;
+    shredded?;
+    update_dashboard(dashboard::Step::UploadTimeline, &s3_client, &unique_id).await?;
+
+    Ok(())
+}
+
+/// Windows clients only run the steps needed to run the client drivers, so
+/// reject the jobs which need the others.
+fn check_windows_clients(args: &Args, group: &RunGroup) -> OrchResult<()> {
+    let unsupported = |what: &str| OrchError::Init {
+        dbg: format!("{} isn't supported with windows clients", what),
+    };
+    if args.use_infra.is_some() {
+        return Err(unsupported("--use-infra"));
     }
+    for job in group.jobs.iter() {
+        if job.driver == Driver::S2nQuicDc {
+            // built from a local source with Linux commands
+            return Err(unsupported("the s2n-quic-dc driver"));
+        }
+        if job.scenario.routers > 0 {
+            return Err(unsupported("routing through router hosts"));
+        }
+        if job.impairment.is_some() {
+            return Err(unsupported("a network impairment"));
+        }
+    }
+    Ok(())
+}
+
+/// Run the scenario once, with the Workers discarding the results, before the
+/// measured run.
+async fn warm_up(
+    ssm_client: &aws_sdk_ssm::Client,
+    infra: &InfraDetail,
+    russula_addrs: &RussulaAddrs,
+    scenario: &Scenario,
+    server_driver: &NetbenchDriver,
+    client_driver: &NetbenchDriver,
+    worker_opts: &ssm_utils::WorkerOptions,
+) -> OrchResult<()> {
+    info!("Warming up scenario: {}", scenario.name);
+    let worker_opts = ssm_utils::WorkerOptions {
+        warmup: true,
+        duration: None,
+        stream_interval: None,
+        ..worker_opts.clone()
+    };
+    let mut server_russula = coordination_utils::ServerNetbenchRussula::new(
+        ssm_client,
+        infra,
+        russula_addrs,
+        infra.server_ids(),
+        scenario,
+        server_driver,
+        &worker_opts,
+    )
+    .await?;
+    let mut client_russula = coordination_utils::ClientNetbenchRussula::new(
+        ssm_client,
+        infra,
+        russula_addrs,
+        infra.client_ids(),
+        scenario,
+        client_driver,
+        &worker_opts,
+    )
+    .await?;
 
-    // Copy results back
-    orch_generate_report(&s3_client, &unique_id).await;
+    tokio::select! {
+        result = async {
+            server_russula.wait_workers_running(ssm_client).await?;
+            client_russula.wait_done(ssm_client).await?;
+            server_russula.wait_done(ssm_client).await
+        } => result?,
+        // the measured run which follows handles the interrupt
+        _ = shutdown::interrupted() => {
+            shutdown::stage(
+                "stop the warm-up server workers",
+                server_russula.wait_done(ssm_client),
+            )
+            .await;
+        }
+    }
+    Ok(())
+}
 
-    // Cleanup
+/// Delete the hosts, unless they belong to an infra pool.
+async fn cleanup_infra(
+    ec2_client: &aws_sdk_ec2::Client,
+    infra: &InfraDetail,
+    pool: Option<&InfraPool>,
+) -> OrchResult<()> {
+    if let Some(pool) = pool {
+        info!("Keeping the hosts of infra pool {}", pool.name);
+        return Ok(());
+    }
     infra
-        .cleanup(&ec2_client)
+        .cleanup(ec2_client)
         .await
-        .map_err(|err| eprintln!("Failed to cleanup resources. {}", err))
-        .unwrap();
+        .map_err(|err| OrchError::Ec2 {
+            dbg: format!("Failed to cleanup resources. {}", err),
+        })
+}
 
-    Ok(())
+/// The final stages of an interrupted run: generate a report from the partial
+/// results, if `report` is set once results were collected, and delete the hosts (unless they belong to
+/// an infra pool).
+async fn shutdown_run(
+    s3_client: &aws_sdk_s3::Client,
+    ec2_client: &aws_sdk_ec2::Client,
+    unique_id: &str,
+    infra: &InfraDetail,
+    manifest: &Manifest,
+    pool: Option<&InfraPool>,
+    report: Option<ReportOptions>,
+) -> OrchResult<()> {
+    if let Some(options) = report {
+        shutdown::stage(
+            "generate the report",
+            orch_generate_report(s3_client, unique_id, &manifest.degraded_peers, options),
+        )
+        .await;
+    }
+    // not bounded by a timeout since a partial cleanup would leave hosts running
+    warn!("Interrupted: delete the hosts");
+    cleanup_infra(ec2_client, infra, pool).await?;
+    shutdown::stage(
+        "upload the timeline",
+        update_dashboard(dashboard::Step::UploadTimeline, s3_client, unique_id),
+    )
+    .await;
+    Err(OrchError::Interrupted)
+}
+
+/// Log the recent russula and driver log events of the hosts with a failed
+/// driver, which are shipped to cloudwatch by the hosts.
+async fn log_failed_host_events(
+    logs_client: &aws_sdk_cloudwatchlogs::Client,
+    infra: &InfraDetail,
+    driver_failures: &[DriverFailure],
+) {
+    for failure in driver_failures {
+        let ip = failure.endpoint.ip().to_string();
+        // the endpoint is a local port forward in a private network
+        let Some(host) = infra
+            .servers
+            .iter()
+            .chain(infra.clients.iter())
+            .find(|host| host.ip == ip)
+        else {
+            continue;
+        };
+        log_host_events(logs_client, &host.instance_id).await;
+    }
+}
+
+async fn log_host_events(logs_client: &aws_sdk_cloudwatchlogs::Client, instance_id: &str) {
+    match ssm_utils::cloud_watch::recent_log_events(
+        logs_client,
+        instance_id,
+        core::time::Duration::from_secs(600),
+        50,
+    )
+    .await
+    {
+        Ok(events) => {
+            for event in events {
+                warn!(instance_id = %instance_id, "{}", event);
+            }
+        }
+        Err(err) => warn!("{}", err),
+    }
+}
+
+/// Abort a scenario whose russula phase timed out: kill the Workers and their
+/// drivers, keep the partial results under `checkpoints/` and log the recent
+/// events of the hosts which didn't complete the phase.
+async fn abort_scenario(
+    ssm_client: &aws_sdk_ssm::Client,
+    logs_client: &aws_sdk_cloudwatchlogs::Client,
+    infra: &InfraDetail,
+    unique_id: &str,
+    result_key: &str,
+    err: &OrchError,
+) {
+    let wait = |cmd| async move {
+        ssm_utils::common::wait_complete("all", ssm_client, vec![cmd], &MultiProgress::new()).await
+    };
+    shutdown::abort_stage("kill the russula workers", async {
+        wait(ssm_utils::common::kill_workers(ssm_client, infra.instance_ids()).await?).await
+    })
+    .await;
+    shutdown::abort_stage("upload the partial results", async {
+        wait(
+            ssm_utils::common::checkpoint_netbench_data(
+                ssm_client,
+                infra.instance_ids(),
+                unique_id,
+                result_key,
+            )
+            .await?,
+        )
+        .await
+    })
+    .await;
+    if let OrchError::RussulaTimeout { hosts, .. } = err {
+        for host in hosts.iter() {
+            if infra.instance_ids().contains(host) {
+                log_host_events(logs_client, host).await;
+            }
+        }
+    }
 }
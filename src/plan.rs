@@ -0,0 +1,206 @@
+// Copyright Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::{
+    config::HostGroup,
+    ec2_utils::{self, EndpointType},
+    error::{OrchError, OrchResult},
+    Scenario, STATE,
+};
+use std::{
+    collections::BTreeSet,
+    io::{BufRead, IsTerminal, Write},
+};
+
+pub mod asl;
+
+// On-demand linux prices (USD/hour) in the vpc region
+const ON_DEMAND_HOURLY_USD: [(&str, f64); 6] = [
+    ("c5.4xlarge", 0.68),
+    ("c5.9xlarge", 1.53),
+    ("c5.18xlarge", 3.06),
+    ("c5n.4xlarge", 0.864),
+    ("c5n.9xlarge", 1.944),
+    ("c5n.18xlarge", 3.888),
+];
+
+/// The resolved plan of a run, displayed for confirmation before any host is
+/// launched since runs cost real money.
+pub struct RunPlan<'a> {
+    pub region: &'a str,
+    pub instance_type: &'a str,
+    // The groups of hosts launched for the scenarios, with the run's
+    // `instance_type` unless they have their own
+    pub host_groups: Vec<HostGroup>,
+    pub scenarios: Vec<&'a str>,
+}
+
+impl<'a> RunPlan<'a> {
+    pub fn new(
+        scenarios: &'a [Scenario],
+        instance_type: &'a str,
+        configured: &[HostGroup],
+    ) -> OrchResult<Self> {
+        Ok(RunPlan {
+            region: STATE.vpc_region,
+            instance_type,
+            host_groups: ec2_utils::host_groups(configured, scenarios)?,
+            scenarios: scenarios
+                .iter()
+                .map(|scenario| scenario.name.as_str())
+                .collect(),
+        })
+    }
+
+    fn group_type<'g>(&'g self, group: &'g HostGroup) -> &'g str {
+        group.instance_type.as_deref().unwrap_or(self.instance_type)
+    }
+
+    pub fn instances(&self) -> usize {
+        self.host_groups.iter().map(|group| group.count).sum()
+    }
+
+    /// The number of hosts of the groups with `role`.
+    pub fn hosts(&self, role: EndpointType) -> usize {
+        self.host_groups
+            .iter()
+            .filter(|group| group.role == role)
+            .map(|group| group.count)
+            .sum()
+    }
+
+    /// The instance types of the hosts.
+    pub fn instance_types(&self) -> BTreeSet<&str> {
+        self.host_groups
+            .iter()
+            .map(|group| self.group_type(group))
+            .collect()
+    }
+
+    /// The estimated cost per hour of the hosts, if the price of each group's
+    /// instance type is known.
+    pub fn hourly_usd(&self) -> Option<f64> {
+        self.host_groups
+            .iter()
+            .map(|group| {
+                hourly_price(self.group_type(group)).map(|price| price * group.count as f64)
+            })
+            .sum()
+    }
+
+    /// Display the plan and ask for confirmation, unless `yes` is set.
+    pub fn confirm(&self, yes: bool) -> OrchResult<()> {
+        println!("{}", self);
+        if yes {
+            return Ok(());
+        }
+        if !std::io::stdin().is_terminal() {
+            return Err(OrchError::Init {
+                dbg: "Pass --yes to launch the hosts without a confirmation prompt".to_string(),
+            });
+        }
+        print!("Launch the hosts? [y/N] ");
+        let _ = std::io::stdout().flush();
+        let mut answer = String::new();
+        std::io::stdin()
+            .lock()
+            .read_line(&mut answer)
+            .map_err(|err| OrchError::Init {
+                dbg: format!("Failed to read confirmation: {}", err),
+            })?;
+        match answer.trim().to_lowercase().as_str() {
+            "y" | "yes" => Ok(()),
+            _ => Err(OrchError::Init {
+                dbg: "Run cancelled".to_string(),
+            }),
+        }
+    }
+}
+
+fn hourly_price(instance_type: &str) -> Option<f64> {
+    ON_DEMAND_HOURLY_USD
+        .iter()
+        .find(|(known, _price)| *known == instance_type)
+        .map(|(_instance_type, price)| *price)
+}
+
+impl std::fmt::Display for RunPlan<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "region: {}", self.region)?;
+        let groups: Vec<String> = self
+            .host_groups
+            .iter()
+            .map(|group| {
+                format!(
+                    "{}: {} x {}",
+                    group.name,
+                    group.count,
+                    self.group_type(group)
+                )
+            })
+            .collect();
+        writeln!(f, "hosts: {} ({})", self.instances(), groups.join(", "))?;
+        match self.hourly_usd() {
+            Some(hourly_usd) => writeln!(f, "estimated cost: ${:.2}/hour", hourly_usd)?,
+            None => {
+                let unknown: Vec<&str> = self
+                    .instance_types()
+                    .into_iter()
+                    .filter(|instance_type| hourly_price(instance_type).is_none())
+                    .collect();
+                writeln!(f, "estimated cost: unknown for {}", unknown.join(", "))?
+            }
+        }
+        write!(f, "scenarios: {}", self.scenarios.join(", "))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn run_plan_cost() {
+        let scenarios = vec![Scenario {
+            name: "request_response.json".to_string(),
+            path: "scripts/request_response.json".into(),
+            clients: 2,
+            servers: 1,
+            routers: 0,
+        }];
+        let plan = RunPlan {
+            region: "us-east-1",
+            ..RunPlan::new(&scenarios, "c5.4xlarge", &[]).unwrap()
+        };
+        assert_eq!(
+            plan.to_string(),
+            "region: us-east-1\nhosts: 3 (server: 1 x c5.4xlarge, client: 2 x c5.4xlarge)\nestimated cost: $2.04/hour\nscenarios: request_response.json"
+        );
+
+        let plan = RunPlan {
+            instance_type: "u-6tb1.metal",
+            ..plan
+        };
+        assert_eq!(plan.hourly_usd(), None);
+
+        // an extra group, on a larger instance type than the run's
+        let configured = [HostGroup {
+            name: "observer".to_string(),
+            role: EndpointType::Observer,
+            count: 1,
+            instance_type: Some("c5n.18xlarge".to_string()),
+        }];
+        let plan = RunPlan::new(&scenarios, "c5.4xlarge", &configured).unwrap();
+        assert_eq!(plan.instances(), 4);
+        assert_eq!(plan.hosts(EndpointType::Client), 2);
+        assert_eq!(
+            plan.instance_types(),
+            BTreeSet::from(["c5.4xlarge", "c5n.18xlarge"])
+        );
+        let hourly_usd = plan.hourly_usd().unwrap();
+        assert!((hourly_usd - (3.0 * 0.68 + 3.888)).abs() < 1e-9);
+        assert!(plan
+            .to_string()
+            .contains("hosts: 4 (observer: 1 x c5n.18xlarge, server: 1 x c5.4xlarge"));
+    }
+}
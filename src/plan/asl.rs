@@ -0,0 +1,378 @@
+// Copyright Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::{
+    config::HostGroup,
+    ec2_utils::EndpointType,
+    error::{OrchError, OrchResult},
+    plan::RunPlan,
+    resolve_groups,
+    run_spec::{RunGroup, RunSpec},
+    ssm_utils::Step,
+    Args, OrchestratorConfig,
+};
+use clap::Args as ClapArgs;
+use serde_json::{json, Map, Value};
+use std::path::PathBuf;
+
+// Retry the wait for a SSM command every 15s for up to 2h
+const WAIT_INTERVAL_SECONDS: u64 = 15;
+const WAIT_MAX_ATTEMPTS: u64 = 480;
+
+#[derive(ClapArgs, Debug)]
+pub struct ExportAslArgs {
+    /// Where to write the definition. Printed if not set
+    #[arg(long)]
+    output: Option<PathBuf>,
+
+    /// Prefix of the Lambda functions invoked by the state machine. ex:
+    /// netbench-launch
+    #[arg(long, default_value = "netbench")]
+    function_prefix: String,
+}
+
+impl ExportAslArgs {
+    /// Export the run's plan, resolved from the scenario files or run spec, as
+    /// an AWS Step Functions state machine. Nothing is launched.
+    pub fn run(&self, args: &Args) -> OrchResult<()> {
+        let config = OrchestratorConfig::load(args.config.as_deref())?;
+        let run_spec = args.run_spec.as_deref().map(RunSpec::load).transpose()?;
+        let groups = resolve_groups(args, run_spec.as_ref(), &config)?;
+        let definition = serde_json::to_string_pretty(&state_machine(
+            &groups,
+            &config.host_groups,
+            &self.function_prefix,
+        )?)
+        .expect("serialize the state machine");
+        match &self.output {
+            Some(output) => {
+                std::fs::write(output, definition).map_err(|err| OrchError::Init {
+                    dbg: format!("Failed to write {:?}: {}", output, err),
+                })?;
+                println!("State machine: {}", output.display());
+            }
+            None => println!("{definition}"),
+        }
+        Ok(())
+    }
+}
+
+/// The states of a state machine, or of the item processor of a Map state.
+#[derive(Default)]
+struct States(Map<String, Value>);
+
+impl States {
+    fn add(&mut self, name: &str, state: Value) -> &mut Self {
+        self.0.insert(name.to_string(), state);
+        self
+    }
+
+    /// Invoke the `<prefix>-<function>` Lambda with the state's input.
+    fn lambda(
+        &mut self,
+        name: &str,
+        prefix: &str,
+        function: &str,
+        result_path: &str,
+        next: &str,
+    ) -> &mut Self {
+        self.add(
+            name,
+            json!({
+                "Type": "Task",
+                "Resource": "arn:aws:states:::lambda:invoke",
+                "Parameters": { "FunctionName": format!("{prefix}-{function}"), "Payload.$": "$" },
+                "ResultSelector": { "Payload.$": "$.Payload" },
+                "ResultPath": result_path,
+                "Next": next,
+                "Catch": [catch_all()],
+            }),
+        )
+    }
+
+    /// Send the SSM command of a step to a host group and wait for it with the
+    /// `<prefix>-wait-command` Lambda, which fails with `CommandPending` until
+    /// the command completes on every host.
+    ///
+    /// The commands are read from the `commands` returned by the launch Lambda,
+    /// keyed by step.
+    fn ssm(
+        &mut self,
+        name: &str,
+        prefix: &str,
+        host_group: &str,
+        step: Step,
+        next: &str,
+    ) -> &mut Self {
+        let wait = format!("Wait{name}");
+        self.add(
+            name,
+            json!({
+                "Type": "Task",
+                "Resource": "arn:aws:states:::aws-sdk:ssm:sendCommand",
+                "Parameters": {
+                    "DocumentName": "AWS-RunShellScript",
+                    "Comment": step.as_str(),
+                    "InstanceIds.$": format!("$.hosts.Payload.{host_group}"),
+                    "Parameters": {
+                        "commands.$": format!("$.hosts.Payload.commands.{}", step.as_str()),
+                    },
+                },
+                "ResultSelector": { "CommandId.$": "$.Command.CommandId" },
+                "ResultPath": "$.command",
+                "Next": wait,
+                "Catch": [catch_all()],
+            }),
+        );
+        self.add(
+            &wait,
+            json!({
+                "Type": "Task",
+                "Resource": "arn:aws:states:::lambda:invoke",
+                "Parameters": { "FunctionName": format!("{prefix}-wait-command"), "Payload.$": "$.command" },
+                "ResultPath": null,
+                "Retry": [{
+                    "ErrorEquals": ["CommandPending"],
+                    "IntervalSeconds": WAIT_INTERVAL_SECONDS,
+                    "MaxAttempts": WAIT_MAX_ATTEMPTS,
+                    "BackoffRate": 1,
+                }],
+                "Next": next,
+                "Catch": [catch_all()],
+            }),
+        )
+    }
+
+    fn build(self, start_at: &str) -> Value {
+        json!({ "StartAt": start_at, "States": self.0 })
+    }
+}
+
+// Any failure jumps to the cleanup so that the hosts are never leaked
+fn catch_all() -> Value {
+    json!({ "ErrorEquals": ["States.ALL"], "ResultPath": "$.error", "Next": "Cleanup" })
+}
+
+/// The orchestration phases of each group of the run (launch, configure,
+/// build, run each job, collect, cleanup) as an Amazon States Language
+/// definition with Lambda and SSM task stubs.
+pub fn state_machine(
+    groups: &[RunGroup],
+    host_groups: &[HostGroup],
+    function_prefix: &str,
+) -> OrchResult<Value> {
+    let prefix = function_prefix;
+
+    let mut job_states = States::default();
+    job_states
+        .ssm(
+            "StartWorkers",
+            prefix,
+            "all",
+            Step::RunRussula,
+            "Coordinate",
+        )
+        .lambda(
+            "Coordinate",
+            prefix,
+            "coordinate",
+            "$.coordinate",
+            "UploadResults",
+        )
+        .ssm(
+            "UploadResults",
+            prefix,
+            "all",
+            Step::UploadNetbenchRawData,
+            "JobDone",
+        )
+        .add("JobDone", json!({ "Type": "Succeed" }));
+    // a failed job fails the group, which is cleaned up by the group's catch
+    let mut job = job_states.build("StartWorkers");
+    for state in job["States"].as_object_mut().expect("states").values_mut() {
+        if let Some(catch) = state.get_mut("Catch") {
+            *catch = json!([{ "ErrorEquals": ["States.ALL"], "Next": "JobFailed" }]);
+        }
+    }
+    job["States"]["JobFailed"] = json!({ "Type": "Fail", "Error": "JobFailed" });
+
+    let mut group_states = States::default();
+    group_states
+        .lambda("Launch", prefix, "launch", "$.hosts", "Configure")
+        .ssm("Configure", prefix, "all", Step::Configure, "BuildRussula")
+        .ssm(
+            "BuildRussula",
+            prefix,
+            "all",
+            Step::BuildRussula,
+            "BuildDriver",
+        )
+        // the launch Lambda resolves the build commands of the group's drivers
+        .ssm(
+            "BuildDriver",
+            prefix,
+            "all",
+            Step::BuildDriver(String::new()),
+            "Jobs",
+        )
+        .add(
+            "Jobs",
+            json!({
+                "Type": "Map",
+                "ItemsPath": "$.jobs",
+                "ItemSelector": { "job.$": "$$.Map.Item.Value", "hosts.$": "$.hosts" },
+                // jobs share the hosts so they run one after the other
+                "MaxConcurrency": 1,
+                "ItemProcessor": job,
+                "ResultPath": null,
+                "Next": "Collect",
+                "Catch": [catch_all()],
+            }),
+        )
+        .lambda("Collect", prefix, "report", "$.report", "Cleanup")
+        .add(
+            "Cleanup",
+            json!({
+                "Type": "Task",
+                "Resource": "arn:aws:states:::lambda:invoke",
+                "Parameters": { "FunctionName": format!("{prefix}-cleanup"), "Payload.$": "$" },
+                "ResultPath": null,
+                "Next": "CheckFailed",
+            }),
+        )
+        .add(
+            "CheckFailed",
+            json!({
+                "Type": "Choice",
+                "Choices": [{ "Variable": "$.error", "IsPresent": true, "Next": "GroupFailed" }],
+                "Default": "GroupDone",
+            }),
+        )
+        .add(
+            "GroupFailed",
+            json!({ "Type": "Fail", "Error": "GroupFailed" }),
+        )
+        .add("GroupDone", json!({ "Type": "Succeed" }));
+
+    let plan = groups
+        .iter()
+        .map(|group| group_plan(group, host_groups))
+        .collect::<OrchResult<Vec<Value>>>()?;
+    let mut states = States::default();
+    states
+        .add(
+            "Plan",
+            json!({ "Type": "Pass", "Result": plan, "ResultPath": "$.groups", "Next": "Groups" }),
+        )
+        .add(
+            "Groups",
+            json!({
+                "Type": "Map",
+                "ItemsPath": "$.groups",
+                // each group is run on its own hosts, one after the other
+                "MaxConcurrency": 1,
+                "ItemProcessor": group_states.build("Launch"),
+                "End": true,
+            }),
+        );
+    let mut definition = states.build("Plan");
+    definition["Comment"] = json!("Netbench orchestration: launch, configure, build, run each job, collect and cleanup the hosts of each group");
+    Ok(definition)
+}
+
+/// The input of a group: the hosts to launch and the jobs to run on them.
+fn group_plan(group: &RunGroup, host_groups: &[HostGroup]) -> OrchResult<Value> {
+    let plan = RunPlan::new(&group.scenarios, &group.instance_type, host_groups)?;
+    let jobs: Vec<Value> = group
+        .jobs
+        .iter()
+        .map(|job| {
+            json!({
+                "scenario": job.scenario.name,
+                "driver": job.driver.as_str(),
+                "result_key": job.result_key(),
+                "impairment": job.impairment.as_ref().map(|impairment| impairment.netem_args()),
+            })
+        })
+        .collect();
+    Ok(json!({
+        "instance_type": group.instance_type,
+        "region": plan.region,
+        "servers": plan.hosts(EndpointType::Server),
+        "clients": plan.hosts(EndpointType::Client),
+        "routers": plan.hosts(EndpointType::Router),
+        "host_groups": plan.host_groups,
+        "jobs": jobs,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{config::OrchestratorConfig, Scenario};
+
+    // Every transition must point at a state of the same States
+    fn check_transitions(machine: &Value) {
+        let states = machine["States"].as_object().unwrap();
+        assert!(states.contains_key(machine["StartAt"].as_str().unwrap()));
+        for (name, state) in states {
+            let mut targets: Vec<&Value> = vec![&state["Next"], &state["Default"]];
+            for key in ["Catch", "Choices"] {
+                targets.extend(
+                    state[key]
+                        .as_array()
+                        .into_iter()
+                        .flatten()
+                        .map(|t| &t["Next"]),
+                );
+            }
+            for target in targets.into_iter().filter_map(Value::as_str) {
+                assert!(states.contains_key(target), "{name} -> {target}");
+            }
+            let terminal = matches!(state["Type"].as_str(), Some("Succeed" | "Fail"));
+            assert!(
+                terminal
+                    || state["Next"].is_string()
+                    || state["End"] == true
+                    || state["Type"] == "Choice",
+                "{name} doesn't transition"
+            );
+            if state.get("ItemProcessor").is_some() {
+                check_transitions(&state["ItemProcessor"]);
+            }
+        }
+    }
+
+    #[test]
+    fn asl_state_machine() {
+        let scenario = Scenario {
+            name: "request_response.json".to_string(),
+            path: "scripts/request_response.json".into(),
+            clients: 1,
+            servers: 2,
+            routers: 0,
+        };
+        let group = RunGroup::new(vec![scenario], &OrchestratorConfig::default());
+        let machine = state_machine(&[group], &[], "netbench").unwrap();
+        check_transitions(&machine);
+
+        let plan = &machine["States"]["Plan"]["Result"][0];
+        assert_eq!(plan["servers"], 2);
+        assert_eq!(plan["jobs"][0]["driver"], "tcp");
+        assert_eq!(plan["jobs"][0]["result_key"], "request_response");
+
+        let group = &machine["States"]["Groups"]["ItemProcessor"]["States"];
+        assert_eq!(
+            group["Launch"]["Parameters"]["FunctionName"],
+            "netbench-launch"
+        );
+        assert_eq!(
+            group["BuildDriver"]["Parameters"]["Parameters"]["commands.$"],
+            "$.hosts.Payload.commands.build_driver"
+        );
+        assert_eq!(
+            group["WaitBuildDriver"]["Retry"][0]["ErrorEquals"][0],
+            "CommandPending"
+        );
+    }
+}
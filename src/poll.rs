@@ -0,0 +1,85 @@
+// Copyright Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::config::Polling;
+use core::time::Duration;
+use std::sync::Mutex;
+
+// The polling config of the current run. Defaults to `Polling::default()`
+static POLLING: Mutex<Option<Polling>> = Mutex::new(None);
+
+/// Poll remote state with the delays of the run's config.
+pub fn configure(polling: Polling) {
+    *POLLING.lock().expect("polling lock") = Some(polling);
+}
+
+fn polling() -> Polling {
+    POLLING.lock().expect("polling lock").unwrap_or_default()
+}
+
+/// The (min, max) delays between polls of russula Workers.
+pub fn russula_delays() -> (Duration, Duration) {
+    polling().russula()
+}
+
+/// The delays between polls of a remote state, which start at `min` and double
+/// while the state doesn't change, up to `max`.
+#[derive(Clone, Debug)]
+pub struct Backoff {
+    min: Duration,
+    max: Duration,
+    delay: Duration,
+}
+
+impl Backoff {
+    pub fn new((min, max): (Duration, Duration)) -> Self {
+        Backoff {
+            min,
+            max,
+            delay: min,
+        }
+    }
+
+    /// Poll the status of SSM commands.
+    pub fn ssm() -> Self {
+        Backoff::new(polling().ssm())
+    }
+
+    /// Poll the state of EC2 instances.
+    pub fn ec2() -> Self {
+        Backoff::new(polling().ec2())
+    }
+
+    /// The delay before the next poll. The following delay is doubled.
+    pub fn next_delay(&mut self) -> Duration {
+        let delay = self.delay;
+        self.delay = (self.delay * 2).min(self.max);
+        delay
+    }
+
+    pub async fn wait(&mut self) {
+        tokio::time::sleep(self.next_delay()).await
+    }
+
+    /// The polled state changed, so it's likely to change again soon.
+    pub fn reset(&mut self) {
+        self.delay = self.min;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn backoff() {
+        let secs = Duration::from_secs;
+        let mut backoff = Backoff::new((secs(1), secs(30)));
+        let delays: Vec<u64> = (0..7).map(|_| backoff.next_delay().as_secs()).collect();
+        assert_eq!(delays, [1, 2, 4, 8, 16, 30, 30]);
+
+        backoff.reset();
+        assert_eq!(backoff.next_delay(), secs(1));
+        assert_eq!(Backoff::ssm().next_delay(), secs(1));
+    }
+}
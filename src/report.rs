@@ -1,68 +1,614 @@
 // Copyright Amazon.com, Inc. or its affiliates. All Rights Reserved.
 // SPDX-License-Identifier: Apache-2.0
 
-use crate::{s3_utils::*, state::*};
+use crate::{
+    config::NetbenchReport,
+    coordination_utils::{DegradedPeer, DriverFailure},
+    dashboard::timeline,
+    error::{OrchError, OrchResult},
+    manifest::Manifest,
+    s3_utils::*,
+    ssm_utils::{
+        build_info::{BuildInfo, BUILD_INFO_DIR},
+        network_check::NetworkInterface,
+        tuning::HostTuning,
+    },
+    state::*,
+};
 use aws_sdk_s3::primitives::{ByteStream, SdkBody};
-use std::process::Command;
+use clap::Subcommand;
+use core::time::Duration;
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
 use tempdir::TempDir;
-use tracing::{debug, info, trace};
+use tracing::{debug, info, warn};
 
-pub async fn orch_generate_report(s3_client: &aws_sdk_s3::Client, unique_id: &str) {
-    let tmp_dir = TempDir::new(unique_id).unwrap().into_path();
-    let tmp_dir = tmp_dir.to_str().unwrap();
+mod anomalies;
+pub mod collector;
+pub mod compare;
+mod diff;
+pub mod github;
+mod iperf3;
+mod latency;
+mod netbench;
+pub mod render;
+mod stats;
+mod streams;
+mod summary;
+mod sys_metrics;
+pub mod trend;
+
+pub use collector::{parse_run, CollectorResult};
+
+#[derive(Subcommand, Debug)]
+pub enum ReportCommand {
+    /// Render the timeline of a run's orchestration events as a Gantt chart
+    Timeline {
+        /// The unique_id of the run. ex: 2023-10-11T17:05:09Z-v2.0.0
+        unique_id: String,
+        /// Where to write the chart
+        #[arg(long, default_value = "timeline.html")]
+        output: PathBuf,
+    },
+    /// Print the summary of a run's results
+    Summary {
+        /// The unique_id of the run. ex: 2023-10-11T17:05:09Z-v2.0.0
+        unique_id: String,
+        /// The format of the summary: terminal, markdown or html
+        #[arg(long, default_value_t = render::Format::Terminal)]
+        format: render::Format,
+    },
+    /// Chart a metric of a scenario over the last runs recorded in the trend table
+    Trend {
+        /// The job's result key without the iteration. ex: request_response-lossy
+        #[arg(long)]
+        scenario: String,
+        /// ex: s2n-quic
+        #[arg(long)]
+        driver: String,
+        /// ex: c5.4xlarge
+        #[arg(long)]
+        instance_type: String,
+        /// ex: throughput_mean_bps, connections or <trace>_p99_us
+        #[arg(long, default_value = "throughput_mean_bps")]
+        metric: String,
+        /// The number of runs to chart
+        #[arg(long, default_value_t = 30)]
+        last: usize,
+        /// The DynamoDB table of the `trend` orchestrator config
+        #[arg(long, default_value = "netbench-trend")]
+        table: String,
+        /// Where to write the chart
+        #[arg(long, default_value = "trend.html")]
+        output: PathBuf,
+    },
+}
+
+impl ReportCommand {
+    pub async fn run(&self, aws_config: &aws_types::SdkConfig) -> OrchResult<()> {
+        let s3_client = aws_sdk_s3::Client::new(aws_config);
+        match self {
+            ReportCommand::Timeline { unique_id, output } => {
+                let entries = timeline::download(&s3_client, unique_id).await?;
+                std::fs::write(output, timeline::gantt_html(unique_id, &entries)).map_err(
+                    |err| OrchError::Init {
+                        dbg: format!("Failed to write {:?}: {}", output, err),
+                    },
+                )?;
+                println!("Timeline: {}", output.display());
+            }
+            ReportCommand::Summary { unique_id, format } => {
+                let summary = summary::download(&s3_client, unique_id).await?;
+                println!("{}", summary::render(format.renderer(), &summary));
+            }
+            ReportCommand::Trend {
+                scenario,
+                driver,
+                instance_type,
+                metric,
+                last,
+                table,
+                output,
+            } => {
+                let dynamodb_client = aws_sdk_dynamodb::Client::new(aws_config);
+                let series = trend::TrendRecord::series(scenario, driver, instance_type);
+                let records = trend::query(&dynamodb_client, table, &series, *last).await?;
+                if records.is_empty() {
+                    return Err(OrchError::Init {
+                        dbg: format!("No runs of {} in {}", series, table),
+                    });
+                }
+                let spec = trend::trend_spec(&series, metric, &records);
+                std::fs::write(output, trend::trend_html(&series, &spec)).map_err(|err| {
+                    OrchError::Init {
+                        dbg: format!("Failed to write {:?}: {}", output, err),
+                    }
+                })?;
+                println!("Trend of {} runs: {}", records.len(), output.display());
+            }
+        }
+        Ok(())
+    }
+}
+
+/// How the report of a run is generated.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ReportOptions {
+    pub download: DownloadOptions,
+    pub netbench: NetbenchReport,
+}
+
+pub async fn orch_generate_report(
+    s3_client: &aws_sdk_s3::Client,
+    unique_id: &str,
+    degraded_peers: &[DegradedPeer],
+    options: ReportOptions,
+) -> OrchResult<()> {
+    let tmp_dir = TempDir::new(unique_id)
+        .map_err(|err| OrchError::Init {
+            dbg: err.to_string(),
+        })?
+        .into_path();
 
     // download results from s3 -----------------------
-    let mut cmd = Command::new("aws");
-    let output = cmd
-        .args([
-            "s3",
-            "sync",
-            &format!("s3://{}/{}", STATE.s3_log_bucket, unique_id),
-            tmp_dir,
-        ])
-        .output()
-        .unwrap();
-    debug!("{:?}", cmd);
-    trace!("{:?}", output);
-    assert!(cmd.status().expect("aws sync").success(), "aws sync");
-
-    // CLI ---------------------------
-    let results_path = format!("{}/results", tmp_dir);
-    let report_path = format!("{}/report", tmp_dir);
-    let mut cmd = Command::new("s2n-netbench");
-    cmd.args(["report-tree", &results_path, &report_path]);
-    debug!("{:?}", cmd);
-    let status = cmd.status().expect("s2n-netbench command failed");
-    assert!(status.success(), " s2n-netbench command failed");
+    let downloaded = sync_from_s3(
+        s3_client,
+        STATE.s3_log_bucket,
+        unique_id,
+        &tmp_dir,
+        options.download,
+        &[],
+    )
+    .await?;
+    debug!("downloaded {} objects to {:?}", downloaded, tmp_dir);
+    // the output streamed by hosts which didn't upload their results
+    let assembled = streams::assemble(&tmp_dir)?;
+    if assembled > 0 {
+        warn!("reassembled {} files from the streamed output", assembled);
+    }
+
+    // s2n-netbench's own reports ---------------------------
+    let (netbench_tree, netbench_per_job) = netbench::generate_report(&tmp_dir, options.netbench)?;
+
+    // pages linked from the dashboard: (title, path relative to the run)
+    let mut pages = vec![("Final Report", "report/index.html")];
+    if netbench_per_job {
+        pages.push(("s2n-netbench reports", "report/netbench.html"));
+    }
+
+    // align system metrics with the netbench run ---------
+    if sys_metrics::generate_report(&tmp_dir)? {
+        pages.push(("System Metrics", "report/sysmetrics.html"));
+    }
+    // tail latency of the drivers overlaid per job
+    if latency::generate_report(&tmp_dir)? {
+        pages.push(("Latency", "report/latency.html"));
+    }
+    // the network ceiling of the instances, for context on the drivers' results
+    if iperf3::generate_report(&tmp_dir)? {
+        pages.push(("iperf3 baseline", "report/iperf3.html"));
+    }
+    // a versioned summary of the results for CI jobs and dashboards
+    summary::generate_report(&tmp_dir, unique_id, degraded_peers)?;
+    if netbench_tree {
+        pages.push(("Summary", "report/summary.html"));
+    } else {
+        // the summary is the final report, ex: s2n-netbench isn't installed
+        let index_path = tmp_dir.join("report").join("index.html");
+        std::fs::copy(tmp_dir.join("report").join("summary.html"), &index_path).map_err(|err| {
+            OrchError::Init {
+                dbg: format!("Failed to write {:?}: {}", index_path, err),
+            }
+        })?;
+    }
+    pages.push(("Summary (json)", "report/summary.json"));
+    // the spread of jobs which were run multiple times (--iterations)
+    let metrics = compare::summarize(&tmp_dir.join("sysmetrics"))?;
+    if stats::generate_report(&tmp_dir, &metrics)? {
+        pages.push(("Iterations", "report/iterations.html"));
+    }
+    if generate_builds_page(&tmp_dir)? {
+        pages.push(("Builds", "report/builds.html"));
+    }
+    // flag suspicious intervals and hosts rather than silently averaging them
+    let anomalies = anomalies::detect_in(&tmp_dir)?;
+    for anomaly in anomalies.iter() {
+        warn!("anomaly: {}", anomaly);
+    }
+    // hosts which deviated from the norm of their instance type before the run
+    let calibration_warnings = calibration_warnings(&tmp_dir);
+    if generate_hosts_page(&tmp_dir)? {
+        pages.push(("Hosts", "report/hosts.html"));
+    }
+    if generate_flamegraph_index(&tmp_dir)? {
+        pages.push(("Flamegraphs", "report/flamegraphs.html"));
+    }
 
     // upload report to s3 -----------------------
-    let mut cmd = Command::new("aws");
-    let output = cmd
-        .args([
-            "s3",
-            "sync",
-            tmp_dir,
-            &format!("s3://{}/{}", STATE.s3_log_bucket, unique_id),
-        ])
-        .output()
-        .unwrap();
-    debug!("{:?}", cmd);
-    trace!("{:?}", output);
-    assert!(cmd.status().expect("aws sync").success(), "aws sync");
-
-    update_report_url(s3_client, unique_id).await;
+    let uploaded = sync_to_s3(s3_client, &tmp_dir, STATE.s3_log_bucket, unique_id, &[]).await?;
+    debug!("uploaded {} objects from {:?}", uploaded, tmp_dir);
+
+    update_report_url(
+        s3_client,
+        unique_id,
+        &pages,
+        degraded_peers,
+        &anomalies,
+        &calibration_warnings,
+    )
+    .await?;
 
     info!("Report Finished!: Successful: true");
     info!("URL: {}/report/index.html", STATE.cf_url(unique_id));
+    Ok(())
+}
+
+async fn update_report_url(
+    s3_client: &aws_sdk_s3::Client,
+    unique_id: &str,
+    pages: &[(&str, &str)],
+    degraded_peers: &[DegradedPeer],
+    anomalies: &[anomalies::Anomaly],
+    calibration_warnings: &[String],
+) -> OrchResult<()> {
+    let mut html = pages
+        .iter()
+        .map(|(title, path)| format!("<a href=\"{}/{path}\">{title}</a>", STATE.cf_url(unique_id)))
+        .collect::<Vec<String>>()
+        .join(" | ");
+    html.push_str(&degraded_peers_html(degraded_peers));
+    html.push_str(&anomalies::warnings_html(anomalies));
+    html.push_str(&calibration_warnings_html(calibration_warnings));
+    update_finished_step(s3_client, unique_id, html).await
+}
+
+/// A notice listing the hosts excluded from the run, since the results don't
+/// include their traffic.
+fn degraded_peers_html(degraded_peers: &[DegradedPeer]) -> String {
+    if degraded_peers.is_empty() {
+        return String::new();
+    }
+    let mut html = String::from("<p><b>Degraded: hosts were excluded from the run</b></p><ul>");
+    for peer in degraded_peers {
+        html.push_str(&format!("<li>{}</li>", escape_html(&peer.to_string())));
+    }
+    html.push_str("</ul>");
+    html
+}
+
+/// The calibration warnings recorded in the downloaded `<dir>/manifest.json`.
+fn calibration_warnings(dir: &Path) -> Vec<String> {
+    std::fs::read(dir.join("manifest.json"))
+        .ok()
+        .and_then(|manifest| serde_json::from_slice::<Manifest>(&manifest).ok())
+        .and_then(|manifest| manifest.calibration)
+        .map(|calibration| calibration.warnings)
+        .unwrap_or_default()
+}
+
+/// A warning listing the hosts whose calibration deviated from the norm of
+/// their instance type, since their results may be skewed by a noisy neighbor.
+fn calibration_warnings_html(warnings: &[String]) -> String {
+    if warnings.is_empty() {
+        return String::new();
+    }
+    let mut html = String::from(
+        "<p><b>Warnings: hosts deviated from the calibration norm, consider discarding the results</b></p><ul>",
+    );
+    for warning in warnings {
+        html.push_str(&format!("<li>{}</li>", escape_html(warning)));
+    }
+    html.push_str("</ul>");
+    html
+}
+
+/// List the build info of each driver, downloaded to `<dir>/build_info`, in
+/// `<dir>/report/builds.html`.
+///
+/// Returns false if no build info was recorded.
+fn generate_builds_page(dir: &Path) -> OrchResult<bool> {
+    let build_info_dir = dir.join(BUILD_INFO_DIR);
+    let mut files = Vec::new();
+    collect_files(&build_info_dir, "txt", &mut files)?;
+    if files.is_empty() {
+        return Ok(false);
+    }
+
+    let mut builds = Vec::new();
+    for file in files.iter() {
+        let (Some(host_group), Some(driver)) = (
+            file.parent()
+                .and_then(|parent| parent.file_name())
+                .and_then(|name| name.to_str()),
+            file.file_stem().and_then(|name| name.to_str()),
+        ) else {
+            continue;
+        };
+        let info = std::fs::read_to_string(file).map_err(|err| OrchError::Init {
+            dbg: format!("Failed to read {:?}: {}", file, err),
+        })?;
+        builds.push(BuildInfo::parse(host_group, driver, &info));
+    }
+
+    let report_path = dir.join("report").join("builds.html");
+    std::fs::write(&report_path, builds_html(&builds)).map_err(|err| OrchError::Init {
+        dbg: format!("Failed to write {:?}: {}", report_path, err),
+    })?;
+    Ok(true)
+}
+
+fn builds_html(builds: &[BuildInfo]) -> String {
+    let mut html = String::from(
+        "<html><body><h2>Builds</h2><table><tr><th>host group</th><th>driver</th><th>git remote</th><th>git sha</th><th>cargo</th><th>rustc</th><th>build command</th></tr>",
+    );
+    for build in builds {
+        html.push_str(&format!(
+            "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td><code>{}</code></td></tr>",
+            escape_html(&build.host_group),
+            escape_html(&build.driver),
+            escape_html(&build.git_remote),
+            escape_html(&build.git_sha),
+            escape_html(&build.cargo),
+            escape_html(&build.rustc),
+            escape_html(&build.build_cmd),
+        ));
+    }
+    html.push_str("</table></body></html>");
+    html
 }
 
-async fn update_report_url(s3_client: &aws_sdk_s3::Client, unique_id: &str) {
-    let body = ByteStream::new(SdkBody::from(format!(
-        "<a href=\"{}/report/index.html\">Final Report</a>",
-        STATE.cf_url(unique_id)
-    )));
+/// List the network interface and tuning profile of each host, recorded in
+/// the downloaded `<dir>/manifest.json`, in `<dir>/report/hosts.html`.
+///
+/// Returns false if neither was recorded.
+fn generate_hosts_page(dir: &Path) -> OrchResult<bool> {
+    let Ok(manifest) = std::fs::read(dir.join("manifest.json")) else {
+        return Ok(false);
+    };
+    let manifest: Manifest = serde_json::from_slice(&manifest).map_err(|err| OrchError::Init {
+        dbg: format!("Failed to parse manifest: {}", err),
+    })?;
+    if manifest.network.is_empty() && manifest.tuning.is_empty() {
+        return Ok(false);
+    }
+    let report_path = dir.join("report").join("hosts.html");
+    std::fs::write(
+        &report_path,
+        hosts_html(&manifest.network, &manifest.tuning),
+    )
+    .map_err(|err| OrchError::Init {
+        dbg: format!("Failed to write {:?}: {}", report_path, err),
+    })?;
+    Ok(true)
+}
+
+fn hosts_html(network: &[NetworkInterface], tuning: &[HostTuning]) -> String {
+    let mut html = String::from(
+        "<html><body><h2>Network</h2><table><tr><th>host group</th><th>instance</th><th>interface</th><th>driver</th><th>mtu</th><th>rx queues</th></tr>",
+    );
+    for interface in network {
+        html.push_str(&format!(
+            "<tr><td>{}</td><td>{}</td><td>{}</td><td>{} {}</td><td>{}</td><td>{}</td></tr>",
+            escape_html(&interface.host_group),
+            escape_html(&interface.instance_id),
+            escape_html(&interface.interface),
+            escape_html(&interface.driver),
+            escape_html(&interface.driver_version),
+            interface.mtu,
+            interface.rx_queues,
+        ));
+    }
+    html.push_str("</table>");
+    html.push_str("<h2>Tuning</h2>");
+    if tuning.is_empty() {
+        html.push_str("<p>The hosts weren't tuned</p></body></html>");
+        return html;
+    }
+    html.push_str("<table><tr><th>host group</th><th>instance</th><th>irq cpus</th><th>online cpus</th><th>governor</th><th>irqbalance</th></tr>");
+    for host in tuning {
+        html.push_str(&format!(
+            "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>",
+            escape_html(&host.host_group),
+            escape_html(&host.instance_id),
+            escape_html(&host.irq_cpus),
+            escape_html(&host.online_cpus),
+            escape_html(&host.governor),
+            escape_html(&host.irqbalance),
+        ));
+    }
+    html.push_str("</table></body></html>");
+    html
+}
+
+/// Link the flamegraphs downloaded to `<dir>/flamegraph` from
+/// `<dir>/report/flamegraphs.html`.
+///
+/// Returns false if the run wasn't profiled.
+fn generate_flamegraph_index(dir: &Path) -> OrchResult<bool> {
+    let mut flamegraphs = Vec::new();
+    collect_files(&dir.join("flamegraph"), "svg", &mut flamegraphs)?;
+    flamegraphs.sort();
+    if flamegraphs.is_empty() {
+        return Ok(false);
+    }
+
+    let mut html = String::from("<html><body><h2>Flamegraphs</h2><ul>");
+    for path in flamegraphs {
+        let relative_path = path
+            .strip_prefix(dir)
+            .unwrap_or(&path)
+            .display()
+            .to_string();
+        html.push_str(&format!(
+            "<li><a href=\"../{relative_path}\">{relative_path}</a></li>"
+        ));
+    }
+    html.push_str("</ul></body></html>");
+
+    let index_path = dir.join("report").join("flamegraphs.html");
+    std::fs::create_dir_all(dir.join("report"))
+        .and_then(|_| std::fs::write(&index_path, html))
+        .map_err(|err| OrchError::Init {
+            dbg: format!("Failed to write {:?}: {}", index_path, err),
+        })?;
+    Ok(true)
+}
+
+/// Recursively collect the files in `dir` with the `extension`.
+fn collect_files(dir: &Path, extension: &str, files: &mut Vec<PathBuf>) -> OrchResult<()> {
+    if !dir.is_dir() {
+        return Ok(());
+    }
+    let entries = std::fs::read_dir(dir).map_err(|err| OrchError::Init {
+        dbg: format!("Failed to read {:?}: {}", dir, err),
+    })?;
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if path.is_dir() {
+            collect_files(&path, extension, files)?;
+        } else if path.extension().is_some_and(|ext| ext == extension) {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
+/// Show the exit status and stderr of failed netbench drivers on the dashboard
+/// in place of the report.
+pub async fn upload_driver_failures(
+    s3_client: &aws_sdk_s3::Client,
+    unique_id: &str,
+    failures: &[DriverFailure],
+) -> OrchResult<()> {
+    update_finished_step(s3_client, unique_id, driver_failures_html(failures)).await
+}
+
+fn driver_failures_html(failures: &[DriverFailure]) -> String {
+    let mut html = String::from("<b>Failed: netbench driver exited with an error</b>");
+    for failure in failures {
+        html.push_str(&format!(
+            "<p>{}</p><pre>{}</pre>",
+            escape_html(&failure.to_string()),
+            escape_html(&failure.exit.stderr_tail)
+        ));
+    }
+    html
+}
+
+pub(crate) fn escape_html(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+async fn update_finished_step(
+    s3_client: &aws_sdk_s3::Client,
+    unique_id: &str,
+    html: String,
+) -> OrchResult<()> {
+    let body = ByteStream::new(SdkBody::from(html));
     let key = format!("{}/finished-step-0", unique_id);
-    let _ = upload_object(s3_client, STATE.s3_log_bucket, body, &key)
+    upload_object(s3_client, STATE.s3_log_bucket, body, &key)
         .await
-        .unwrap();
+        .map_err(|err| OrchError::S3 {
+            dbg: format!("Failed to upload {}: {}", key, err),
+        })?;
+    Ok(())
+}
+
+/// Generate presigned urls for the report index and the raw netbench results so that
+/// they can be shared with users who don't have access to the bucket.
+///
+/// Returns a map of s3 key -> presigned url.
+pub async fn presign_report(
+    s3_client: &aws_sdk_s3::Client,
+    unique_id: &str,
+    expires_in: Duration,
+) -> OrchResult<BTreeMap<String, String>> {
+    let results = list_objects(
+        s3_client,
+        STATE.s3_log_bucket,
+        &format!("{unique_id}/results/"),
+    )
+    .await?;
+    let keys = std::iter::once(format!("{unique_id}/report/index.html")).chain(results.into_keys());
+
+    let mut presigned_urls = BTreeMap::new();
+    for key in keys {
+        let url = presign_object(s3_client, STATE.s3_log_bucket, &key, expires_in).await?;
+        presigned_urls.insert(key, url);
+    }
+    Ok(presigned_urls)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::russula::netbench::ProcessExit;
+
+    #[test]
+    fn driver_failure_diagnostics() {
+        let failure = DriverFailure {
+            host_group: "client".to_string(),
+            endpoint: "127.0.0.1:9000".parse().unwrap(),
+            scenario: "request_response.json".to_string(),
+            exit: ProcessExit {
+                code: Some(1),
+                stderr_tail: "error: <connection refused>".to_string(),
+                ..Default::default()
+            },
+        };
+        let html = driver_failures_html(&[failure]);
+        assert!(html.contains(
+            "client driver on 127.0.0.1:9000 failed for scenario request_response.json. exit code: 1"
+        ));
+        assert!(html.contains("<pre>error: &lt;connection refused&gt;</pre>"));
+    }
+
+    #[test]
+    fn hosts_page() {
+        let interface = NetworkInterface {
+            instance_id: "i-1".to_string(),
+            host_group: "server".to_string(),
+            interface: "ens5".to_string(),
+            driver: "ena".to_string(),
+            driver_version: "2.8.6g".to_string(),
+            mtu: 9001,
+            rx_queues: 8,
+        };
+        let html = hosts_html(&[interface], &[]);
+        assert!(html.contains("<td>ena 2.8.6g</td><td>9001</td><td>8</td>"));
+        assert!(html.contains("The hosts weren't tuned"));
+
+        let tuning = HostTuning {
+            instance_id: "i-1".to_string(),
+            host_group: "server".to_string(),
+            irq_cpus: "0-1".to_string(),
+            online_cpus: "0-7".to_string(),
+            governor: "performance".to_string(),
+            irqbalance: "inactive".to_string(),
+        };
+        let html = hosts_html(&[], &[tuning]);
+        assert!(html.contains(
+            "<td>0-1</td><td>0-7</td><td>performance</td><td>inactive</td></tr></table>"
+        ));
+    }
+
+    #[test]
+    fn degraded_peers_notice() {
+        assert_eq!(degraded_peers_html(&[]), "");
+
+        let peer = DegradedPeer {
+            host_group: "server".to_string(),
+            endpoint: "127.0.0.1:9000".parse().unwrap(),
+            scenario: "request_response.json".to_string(),
+            dbg: "NetworkFail <reset>".to_string(),
+        };
+        let html = degraded_peers_html(&[peer]);
+        assert!(html.contains(
+            "<li>server host 127.0.0.1:9000 was excluded from scenario request_response.json. NetworkFail &lt;reset&gt;</li>"
+        ));
+    }
 }
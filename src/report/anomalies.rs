@@ -0,0 +1,330 @@
+// Copyright Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+use super::{
+    compare::group_hosts,
+    escape_html,
+    stats::Summary,
+    sys_metrics::{HostMetrics, Sample},
+};
+use crate::error::OrchResult;
+use std::{collections::BTreeMap, fmt, path::Path};
+
+// An interval whose throughput drops below this fraction of the host's median
+// throughput is a stall
+const STALL_FRACTION: f64 = 0.1;
+// An interval with this many times the host's median retransmits, and at least
+// RETRANSMIT_STORM_MIN, is a retransmit storm
+const RETRANSMIT_STORM_FACTOR: u64 = 10;
+const RETRANSMIT_STORM_MIN: u64 = 100;
+// A host whose throughput varies more than this (stddev / mean) across the
+// intervals is unstable
+const UNSTABLE_CV: f64 = 0.5;
+// A host whose average throughput deviates more than this many stddevs from the
+// other hosts of its group is an outlier
+const OUTLIER_STDDEVS: f64 = 3.0;
+// ... and by more than this fraction, so that near identical hosts aren't
+// flagged
+const OUTLIER_MIN_DEVIATION: f64 = 0.2;
+// Fewer intervals aren't enough to tell an anomaly from the ramp up and down
+const MIN_INTERVALS: usize = 5;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum AnomalyKind {
+    Stall {
+        at_s: f64,
+        kbps: f64,
+    },
+    RetransmitStorm {
+        at_s: f64,
+        retransmits: u64,
+    },
+    UnstableThroughput {
+        cv: f64,
+    },
+    Outlier {
+        kbps: f64,
+        group_kbps: f64,
+        stddevs: f64,
+    },
+}
+
+/// Suspicious data in the system metrics of a host, which would otherwise be
+/// silently averaged into the results.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Anomaly {
+    // The job and host group. ex: request_response/s2n-quic/client
+    pub group: String,
+    // ex: client-i-0123-s2n-quic.sysmetrics.csv
+    pub host: String,
+    pub kind: AnomalyKind,
+}
+
+impl fmt::Display for Anomaly {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}: ", self.group, self.host)?;
+        match &self.kind {
+            AnomalyKind::Stall { at_s, kbps } => {
+                write!(f, "throughput stalled at {at_s:.1}s ({kbps:.1} kB/s)")
+            }
+            AnomalyKind::RetransmitStorm { at_s, retransmits } => {
+                write!(f, "{retransmits} tcp retransmits at {at_s:.1}s")
+            }
+            AnomalyKind::UnstableThroughput { cv } => {
+                write!(f, "throughput varies by {:.0}% across intervals", cv * 100.0)
+            }
+            AnomalyKind::Outlier {
+                kbps,
+                group_kbps,
+                stddevs,
+            } => write!(
+                f,
+                "throughput {kbps:.1} kB/s is {stddevs:.1} stddevs from the other hosts ({group_kbps:.1} kB/s)"
+            ),
+        }
+    }
+}
+
+fn kbps(sample: &Sample) -> f64 {
+    sample.rx_kbps + sample.tx_kbps
+}
+
+fn median(values: &[f64]) -> f64 {
+    Summary::new(values).median
+}
+
+fn host_name(host: &HostMetrics) -> String {
+    Path::new(&host.name)
+        .file_name()
+        .map_or(host.name.clone(), |name| name.to_string_lossy().to_string())
+}
+
+/// The anomalies within the intervals of a single host. The first and last
+/// intervals are skipped since they include the ramp up and down.
+fn interval_anomalies(group: &str, host: &HostMetrics) -> Vec<Anomaly> {
+    let samples = &host.samples;
+    if samples.len() < MIN_INTERVALS {
+        return Vec::new();
+    }
+    let start = samples[0].unix_millis;
+    let at_s = |sample: &Sample| sample.unix_millis.saturating_sub(start) as f64 / 1000.0;
+    let steady = &samples[1..samples.len() - 1];
+    let anomaly = |kind| Anomaly {
+        group: group.to_string(),
+        host: host_name(host),
+        kind,
+    };
+    let mut anomalies = Vec::new();
+
+    let throughput: Vec<f64> = steady.iter().map(kbps).collect();
+    let median_kbps = median(&throughput);
+    for sample in steady.iter() {
+        if median_kbps > 0.0 && kbps(sample) < median_kbps * STALL_FRACTION {
+            anomalies.push(anomaly(AnomalyKind::Stall {
+                at_s: at_s(sample),
+                kbps: kbps(sample),
+            }));
+        }
+    }
+
+    // retransmits are cumulative so each interval's are the delta from the last
+    let retransmits: Vec<u64> = samples
+        .windows(2)
+        .map(|pair| {
+            pair[1]
+                .tcp_retrans_segs
+                .saturating_sub(pair[0].tcp_retrans_segs)
+        })
+        .collect();
+    let median_retransmits = median(&retransmits.iter().map(|r| *r as f64).collect::<Vec<_>>());
+    let storm = (median_retransmits as u64 * RETRANSMIT_STORM_FACTOR).max(RETRANSMIT_STORM_MIN);
+    for (sample, retransmits) in samples[1..].iter().zip(retransmits) {
+        if retransmits >= storm {
+            anomalies.push(anomaly(AnomalyKind::RetransmitStorm {
+                at_s: at_s(sample),
+                retransmits,
+            }));
+        }
+    }
+
+    // a stall is reported on its own rather than as instability
+    let unstalled: Vec<f64> = throughput
+        .into_iter()
+        .filter(|kbps| *kbps >= median_kbps * STALL_FRACTION)
+        .collect();
+    let summary = Summary::new(&unstalled);
+    if summary.mean > 0.0 && summary.stddev / summary.mean > UNSTABLE_CV {
+        anomalies.push(anomaly(AnomalyKind::UnstableThroughput {
+            cv: summary.stddev / summary.mean,
+        }));
+    }
+    anomalies
+}
+
+/// The hosts whose average throughput deviates from the other hosts of the
+/// group. Each host is compared to the others so that an outlier doesn't
+/// inflate the stddev it is measured against.
+fn outliers(group: &str, hosts: &[HostMetrics]) -> Vec<Anomaly> {
+    let averages: Vec<f64> = hosts
+        .iter()
+        .map(|host| host.samples.iter().map(kbps).sum::<f64>() / host.samples.len().max(1) as f64)
+        .collect();
+    // at least 2 other hosts are needed for a stddev
+    if averages.len() < 3 {
+        return Vec::new();
+    }
+    let mut anomalies = Vec::new();
+    for (i, (host, kbps)) in hosts.iter().zip(averages.iter()).enumerate() {
+        let others: Vec<f64> = averages
+            .iter()
+            .enumerate()
+            .filter(|(j, _)| *j != i)
+            .map(|(_, kbps)| *kbps)
+            .collect();
+        let summary = Summary::new(&others);
+        let deviation = (kbps - summary.mean).abs();
+        if summary.mean == 0.0 || deviation / summary.mean <= OUTLIER_MIN_DEVIATION {
+            continue;
+        }
+        let stddevs = if summary.stddev > 0.0 {
+            deviation / summary.stddev
+        } else {
+            f64::INFINITY
+        };
+        if stddevs > OUTLIER_STDDEVS {
+            anomalies.push(Anomaly {
+                group: group.to_string(),
+                host: host_name(host),
+                kind: AnomalyKind::Outlier {
+                    kbps: *kbps,
+                    group_kbps: summary.mean,
+                    stddevs,
+                },
+            });
+        }
+    }
+    anomalies
+}
+
+pub fn detect(groups: &BTreeMap<String, Vec<HostMetrics>>) -> Vec<Anomaly> {
+    let mut anomalies = Vec::new();
+    for (group, hosts) in groups {
+        for host in hosts {
+            anomalies.extend(interval_anomalies(group, host));
+        }
+        anomalies.extend(outliers(group, hosts));
+    }
+    anomalies
+}
+
+/// Detect the anomalies in the system metrics downloaded to `<dir>/sysmetrics`.
+pub fn detect_in(dir: &Path) -> OrchResult<Vec<Anomaly>> {
+    Ok(detect(&group_hosts(&dir.join("sysmetrics"))?))
+}
+
+/// A warnings section listing the anomalies, empty if there are none.
+pub fn warnings_html(anomalies: &[Anomaly]) -> String {
+    if anomalies.is_empty() {
+        return String::new();
+    }
+    let mut html = String::from(
+        "<p><b>Warnings: anomalies in the system metrics, check the affected results</b></p><ul>",
+    );
+    for anomaly in anomalies {
+        html.push_str(&format!("<li>{}</li>", escape_html(&anomaly.to_string())));
+    }
+    html.push_str("</ul>");
+    html
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn host(name: &str, samples: &[(f64, u64)]) -> HostMetrics {
+        HostMetrics {
+            name: format!("sysmetrics/request_response/tcp/{name}"),
+            samples: samples
+                .iter()
+                .enumerate()
+                .map(|(i, (tx_kbps, tcp_retrans_segs))| Sample {
+                    unix_millis: 1_700_000_000_000 + i as u64 * 1000,
+                    tx_kbps: *tx_kbps,
+                    tcp_retrans_segs: *tcp_retrans_segs,
+                    ..Default::default()
+                })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn detect_anomalies() {
+        let group = "request_response/tcp/client";
+        let steady = host(
+            "client-i-1",
+            &[
+                (10.0, 0),
+                (100.0, 1),
+                (100.0, 2),
+                (100.0, 3),
+                (100.0, 4),
+                (5.0, 5),
+            ],
+        );
+        assert_eq!(interval_anomalies(group, &steady), vec![]);
+
+        let stalled = host(
+            "client-i-2",
+            &[
+                (100.0, 0),
+                (100.0, 1),
+                (1.0, 2),
+                (100.0, 3),
+                (100.0, 4),
+                (100.0, 5),
+            ],
+        );
+        assert_eq!(
+            interval_anomalies(group, &stalled),
+            vec![Anomaly {
+                group: group.to_string(),
+                host: "client-i-2".to_string(),
+                kind: AnomalyKind::Stall {
+                    at_s: 2.0,
+                    kbps: 1.0
+                },
+            }]
+        );
+        let storm = host(
+            "client-i-3",
+            &[
+                (100.0, 0),
+                (100.0, 1),
+                (100.0, 2),
+                (100.0, 500),
+                (100.0, 501),
+                (100.0, 502),
+            ],
+        );
+        assert_eq!(
+            interval_anomalies(group, &storm)[0].to_string(),
+            "request_response/tcp/client client-i-3: 498 tcp retransmits at 3.0s"
+        );
+
+        let hosts = vec![
+            host("client-i-1", &[(100.0, 0); 5]),
+            host("client-i-2", &[(102.0, 0); 5]),
+            host("client-i-3", &[(98.0, 0); 5]),
+            host("client-i-4", &[(40.0, 0); 5]),
+        ];
+        let outliers = outliers(group, &hosts);
+        assert_eq!(outliers.len(), 1, "{outliers:?}");
+        assert_eq!(outliers[0].host, "client-i-4");
+
+        let html = warnings_html(&outliers);
+        assert!(
+            html.contains("<li>request_response/tcp/client client-i-4: throughput 40.0 kB/s is")
+        );
+        assert_eq!(warnings_html(&[]), "");
+    }
+}
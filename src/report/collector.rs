@@ -0,0 +1,133 @@
+// Copyright Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::error::{OrchError, OrchResult};
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, path::Path};
+
+/// The result of a netbench driver, as written by the netbench collector.
+///
+/// Counters and profiles are keyed by the index of their trace in `traces`,
+/// ex: `{"traces": ["send", "receive"], "intervals": [{"time": 1000000000,
+/// "counters": {"0": 1200}, "profiles": {"1": {"p50": 1200, ..}}}]}`.
+///
+/// Unknown fields are ignored so that newer collectors can still be parsed.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct CollectorResult {
+    pub traces: Vec<String>,
+    pub intervals: Vec<Interval>,
+}
+
+/// The counters and latency profiles of the traces over an interval.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Interval {
+    // Nanoseconds since the start of the driver
+    pub time: u64,
+    #[serde(default)]
+    pub counters: BTreeMap<usize, u64>,
+    #[serde(default)]
+    pub profiles: BTreeMap<usize, Profile>,
+}
+
+/// The latency percentiles of a profiled trace, in nanoseconds.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Profile {
+    pub p50: f64,
+    pub p90: f64,
+    pub p99: f64,
+    pub p999: f64,
+}
+
+impl Profile {
+    pub fn percentiles(&self) -> [f64; 4] {
+        [self.p50, self.p90, self.p99, self.p999]
+    }
+}
+
+impl CollectorResult {
+    pub fn parse(json: &str) -> serde_json::Result<Self> {
+        serde_json::from_str(json)
+    }
+
+    /// The name of the trace at `id`, ignoring ids without a trace.
+    pub fn trace(&self, id: usize) -> Option<&str> {
+        self.traces.get(id).map(String::as_str)
+    }
+
+    /// The counters of the interval, keyed by trace name.
+    pub fn counters<'a>(&'a self, interval: &'a Interval) -> impl Iterator<Item = (&'a str, u64)> {
+        interval
+            .counters
+            .iter()
+            .filter_map(|(id, value)| Some((self.trace(*id)?, *value)))
+    }
+
+    /// The profiles of the interval, keyed by trace name.
+    pub fn profiles<'a>(
+        &'a self,
+        interval: &'a Interval,
+    ) -> impl Iterator<Item = (&'a str, &'a Profile)> {
+        interval
+            .profiles
+            .iter()
+            .filter_map(|(id, profile)| Some((self.trace(*id)?, profile)))
+    }
+
+    /// The connections opened over the interval, counted by the `connect`
+    /// trace.
+    pub fn connections(&self, interval: &Interval) -> u64 {
+        self.counters(interval)
+            .filter(|(trace, _value)| *trace == "connect")
+            .map(|(_trace, value)| value)
+            .sum()
+    }
+}
+
+/// Parse the collector output of a netbench driver, ex:
+/// `results/request_response/s2n-quic/client-0.json`.
+pub fn parse_run(path: &Path) -> OrchResult<CollectorResult> {
+    let json = std::fs::read_to_string(path).map_err(|err| OrchError::Init {
+        dbg: format!("Failed to read {:?}: {}", path, err),
+    })?;
+    CollectorResult::parse(&json).map_err(|err| OrchError::Init {
+        dbg: format!("Failed to parse collector result {:?}: {}", path, err),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn collector_result() {
+        let json = json!({
+            "traces": ["send", "receive", "request", "connect"],
+            "intervals": [
+                { "time": 1_000_000_000u64, "counters": { "0": 10, "1": 20, "3": 2, "9": 5 }, "profiles": { "2": { "p50": 1000, "p99": 3000 } }, "unknown": true },
+                { "time": 2_000_000_000u64 },
+            ],
+        });
+        let dir = tempdir::TempDir::new("collector").unwrap();
+        let path = dir.path().join("client-0.json");
+        std::fs::write(&path, json.to_string()).unwrap();
+
+        let result = parse_run(&path).unwrap();
+        assert_eq!(result.intervals.len(), 2);
+        let first = &result.intervals[0];
+        assert_eq!(
+            result.counters(first).collect::<Vec<_>>(),
+            vec![("send", 10), ("receive", 20), ("connect", 2)]
+        );
+        assert_eq!(result.connections(first), 2);
+        let profiles: Vec<_> = result.profiles(first).collect();
+        assert_eq!(profiles.len(), 1);
+        assert_eq!(profiles[0].0, "request");
+        assert_eq!(profiles[0].1.percentiles(), [1000.0, 0.0, 3000.0, 0.0]);
+        assert_eq!(result.connections(&result.intervals[1]), 0);
+
+        assert!(CollectorResult::parse("{}").is_err());
+        assert!(parse_run(&dir.path().join("missing.json")).is_err());
+    }
+}
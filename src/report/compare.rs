@@ -0,0 +1,283 @@
+// Copyright Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+use super::{
+    collect_files, diff,
+    render::{Format, Renderer},
+    sys_metrics::{parse_samples, HostMetrics},
+};
+use crate::{
+    error::{OrchError, OrchResult},
+    s3_utils::{baseline::resolve_run, sync_from_s3, DownloadOptions},
+    state::STATE,
+};
+use clap::Args;
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
+use tempdir::TempDir;
+use tracing::info;
+
+#[derive(Args, Debug)]
+pub struct CompareArgs {
+    /// The run to compare: a baseline name or a unique_id
+    run: String,
+
+    /// The run to compare against: a baseline name or a unique_id. ex: main-latest
+    #[arg(long, default_value = "main")]
+    baseline: String,
+
+    /// Also write an html chart overlaying the throughput and latency of the
+    /// runs at each interval. ex: compare.html
+    #[arg(long)]
+    chart: Option<PathBuf>,
+
+    /// Highlight the intervals of the chart where the run differs from the
+    /// baseline by more than this percentage
+    #[arg(long, default_value_t = 10.0)]
+    divergence_pct: f64,
+
+    /// The format of the comparison: markdown, html or terminal
+    #[arg(long, default_value_t = Format::Markdown)]
+    format: Format,
+}
+
+impl CompareArgs {
+    /// Print a comparison of the system metrics of the runs.
+    pub async fn run(&self, aws_config: &aws_types::SdkConfig) -> OrchResult<()> {
+        let s3_client = aws_sdk_s3::Client::new(aws_config);
+        let unique_id = resolve_run(&s3_client, STATE.s3_log_bucket, &self.run).await?;
+        let baseline_id = resolve_run(&s3_client, STATE.s3_log_bucket, &self.baseline).await?;
+        let current = download_metrics(&s3_client, &unique_id).await?;
+        let baseline = download_metrics(&s3_client, &baseline_id).await?;
+        println!(
+            "{}",
+            render_comparison(
+                self.format.renderer(),
+                &unique_id,
+                &current,
+                Some(&(&baseline_id, baseline))
+            )
+        );
+
+        if let Some(chart) = &self.chart {
+            let current_dir = diff::download_run(&s3_client, &unique_id).await?;
+            let baseline_dir = diff::download_run(&s3_client, &baseline_id).await?;
+            let diffs = diff::interval_diffs(current_dir.path(), baseline_dir.path())?;
+            let html = diff::diff_html(&unique_id, &baseline_id, &diffs, self.divergence_pct);
+            std::fs::write(chart, html).map_err(|err| OrchError::Init {
+                dbg: format!("Failed to write {:?}: {}", chart, err),
+            })?;
+            let diverging: usize = diffs
+                .iter()
+                .map(|diff| diff.diverging(self.divergence_pct))
+                .sum();
+            info!(
+                "Wrote {:?}: {} diverging intervals across {} charts",
+                chart,
+                diverging,
+                diffs.len()
+            );
+        }
+        Ok(())
+    }
+}
+
+/// The system metrics of a host group for a job, averaged across the hosts.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct JobMetrics {
+    pub cpu_busy_avg: f64,
+    pub tx_kbps_avg: f64,
+    pub tcp_retrans: u64,
+}
+
+pub async fn download_metrics(
+    s3_client: &aws_sdk_s3::Client,
+    unique_id: &str,
+) -> OrchResult<BTreeMap<String, JobMetrics>> {
+    let tmp_dir = TempDir::new(unique_id).map_err(|err| OrchError::Init {
+        dbg: err.to_string(),
+    })?;
+    sync_from_s3(
+        s3_client,
+        STATE.s3_log_bucket,
+        &format!("{unique_id}/sysmetrics"),
+        tmp_dir.path(),
+        DownloadOptions::default(),
+        &[],
+    )
+    .await?;
+    summarize(tmp_dir.path())
+}
+
+/// Summarize the system metrics csvs in `dir` by job and host group.
+///
+/// ex: request_response/s2n-quic/client
+pub(super) fn summarize(dir: &Path) -> OrchResult<BTreeMap<String, JobMetrics>> {
+    Ok(group_hosts(dir)?
+        .into_iter()
+        .map(|(key, hosts)| {
+            let count = hosts.len() as f64;
+            let tx_kbps_avg = |host: &HostMetrics| {
+                host.samples
+                    .iter()
+                    .map(|sample| sample.tx_kbps)
+                    .sum::<f64>()
+                    / host.samples.len().max(1) as f64
+            };
+            let metrics = JobMetrics {
+                cpu_busy_avg: hosts.iter().map(HostMetrics::cpu_busy_avg).sum::<f64>() / count,
+                tx_kbps_avg: hosts.iter().map(tx_kbps_avg).sum::<f64>() / count,
+                tcp_retrans: hosts.iter().map(HostMetrics::tcp_retrans).sum(),
+            };
+            (key, metrics)
+        })
+        .collect())
+}
+
+/// The system metrics csvs in `dir` of each host, grouped by job and host
+/// group.
+pub(super) fn group_hosts(dir: &Path) -> OrchResult<BTreeMap<String, Vec<HostMetrics>>> {
+    let mut csv_files = Vec::new();
+    collect_files(dir, "csv", &mut csv_files)?;
+
+    let mut hosts: BTreeMap<String, Vec<HostMetrics>> = BTreeMap::new();
+    for path in csv_files {
+        // ex: request_response/s2n-quic/client-i-0123-s2n-quic.sysmetrics.csv
+        let (Some(job), Some(host_group)) = (
+            path.parent()
+                .and_then(|parent| parent.strip_prefix(dir).ok()),
+            path.file_name()
+                .and_then(|name| name.to_str())
+                .and_then(|name| name.split('-').next()),
+        ) else {
+            continue;
+        };
+        let csv = std::fs::read_to_string(&path).map_err(|err| OrchError::Init {
+            dbg: format!("Failed to read {:?}: {}", path, err),
+        })?;
+        hosts
+            .entry(format!("{}/{host_group}", job.display()))
+            .or_default()
+            .push(HostMetrics {
+                name: path.display().to_string(),
+                samples: parse_samples(&csv),
+            });
+    }
+    Ok(hosts)
+}
+
+pub fn render_comparison(
+    renderer: &dyn Renderer,
+    unique_id: &str,
+    current: &BTreeMap<String, JobMetrics>,
+    baseline: Option<&(&str, BTreeMap<String, JobMetrics>)>,
+) -> String {
+    let report = |unique_id: &str| {
+        renderer.link(
+            unique_id,
+            &format!("{}/report/index.html", STATE.cf_url(unique_id)),
+        )
+    };
+    let runs = match baseline {
+        Some((baseline_id, _)) => {
+            format!(
+                "Run: {}\nBaseline: {}",
+                report(unique_id),
+                report(baseline_id)
+            )
+        }
+        None => format!(
+            "Run: {}\nNo baseline run to compare against",
+            report(unique_id)
+        ),
+    };
+    let mut blocks = vec![
+        renderer.heading("Netbench results"),
+        renderer.paragraph(&runs),
+    ];
+    if current.is_empty() {
+        blocks.push(renderer.paragraph("No system metrics were collected"));
+        return renderer.document(&blocks);
+    }
+
+    let rows: Vec<Vec<String>> = current
+        .iter()
+        .map(|(key, metrics)| {
+            let base = baseline.and_then(|(_, baseline)| baseline.get(key));
+            vec![
+                renderer.text(key),
+                with_change(metrics.cpu_busy_avg, base.map(|base| base.cpu_busy_avg)),
+                with_change(metrics.tx_kbps_avg, base.map(|base| base.tx_kbps_avg)),
+                with_change(
+                    metrics.tcp_retrans as f64,
+                    base.map(|base| base.tcp_retrans as f64),
+                ),
+            ]
+        })
+        .collect();
+    blocks.push(renderer.table(&["job", "cpu busy %", "tx kB/s", "tcp retransmits"], &rows));
+    renderer.document(&blocks)
+}
+
+// ex: 45.0 (+3.1%)
+fn with_change(value: f64, baseline: Option<f64>) -> String {
+    match baseline {
+        Some(baseline) if baseline != 0.0 => {
+            format!(
+                "{value:.1} ({:+.1}%)",
+                (value - baseline) / baseline * 100.0
+            )
+        }
+        _ => format!("{value:.1}"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::report::render::Markdown;
+
+    #[test]
+    fn comparison_table() {
+        let metrics = |cpu_busy_avg, tx_kbps_avg, tcp_retrans| JobMetrics {
+            cpu_busy_avg,
+            tx_kbps_avg,
+            tcp_retrans,
+        };
+        let current = BTreeMap::from([
+            (
+                "request_response/s2n-quic/client".to_string(),
+                metrics(55.0, 1100.0, 0),
+            ),
+            (
+                "request_response/s2n-quic/server".to_string(),
+                metrics(40.0, 900.0, 3),
+            ),
+        ]);
+        let baseline = BTreeMap::from([(
+            "request_response/s2n-quic/client".to_string(),
+            metrics(50.0, 1000.0, 2),
+        )]);
+        let markdown = render_comparison(&Markdown, "run", &current, Some(&("base", baseline)));
+        assert!(markdown.contains("Baseline: [base]"), "{}", markdown);
+        assert!(
+            markdown.contains(
+                "| request_response/s2n-quic/client | 55.0 (+10.0%) | 1100.0 (+10.0%) | 0.0 (-100.0%) |"
+            ),
+            "{}",
+            markdown
+        );
+        // no baseline for the job
+        assert!(
+            markdown.contains("| request_response/s2n-quic/server | 40.0 | 900.0 | 3.0 |"),
+            "{}",
+            markdown
+        );
+
+        let markdown = render_comparison(&Markdown, "run", &BTreeMap::new(), None);
+        assert!(markdown.contains("No baseline run"), "{}", markdown);
+        assert!(markdown.contains("No system metrics"), "{}", markdown);
+    }
+}
@@ -0,0 +1,316 @@
+// Copyright Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+use super::{
+    compare::group_hosts,
+    escape_html,
+    latency::{job_latency, LatencySample},
+    sys_metrics::HostMetrics,
+};
+use crate::{
+    error::{OrchError, OrchResult},
+    s3_utils::{sync_from_s3, DownloadOptions},
+    state::STATE,
+};
+use serde_json::{json, Value};
+use std::path::Path;
+use tempdir::TempDir;
+
+// The latency percentile compared at each interval. see latency::PERCENTILES
+const LATENCY_PERCENTILE: usize = 2;
+
+/// A metric of a job at each interval of a run and of its baseline. Intervals
+/// are matched by their index since both runs sample at the same interval.
+#[derive(Clone, Debug, PartialEq)]
+pub struct IntervalDiff {
+    // ex: request_response/s2n-quic/client
+    pub job: String,
+    // ex: tx kB/s
+    pub metric: String,
+    pub points: Vec<DiffPoint>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct DiffPoint {
+    // Seconds since the start of the run
+    pub time_s: f64,
+    pub current: Option<f64>,
+    pub baseline: Option<f64>,
+}
+
+impl DiffPoint {
+    /// The interval diverges if the run differs from the baseline by more than
+    /// `divergence_pct` percent.
+    pub fn diverges(&self, divergence_pct: f64) -> bool {
+        match (self.current, self.baseline) {
+            (Some(current), Some(baseline)) if baseline != 0.0 => {
+                ((current - baseline) / baseline * 100.0).abs() > divergence_pct
+            }
+            _ => false,
+        }
+    }
+}
+
+impl IntervalDiff {
+    fn new(job: &str, metric: String, current: &[(f64, f64)], baseline: &[(f64, f64)]) -> Self {
+        let points = (0..current.len().max(baseline.len()))
+            .map(|i| DiffPoint {
+                time_s: current.get(i).or(baseline.get(i)).map_or(0.0, |p| p.0),
+                current: current.get(i).map(|p| p.1),
+                baseline: baseline.get(i).map(|p| p.1),
+            })
+            .collect();
+        IntervalDiff {
+            job: job.to_string(),
+            metric,
+            points,
+        }
+    }
+
+    pub fn diverging(&self, divergence_pct: f64) -> usize {
+        self.points
+            .iter()
+            .filter(|point| point.diverges(divergence_pct))
+            .count()
+    }
+}
+
+/// Download the system metrics and netbench results of a run.
+pub async fn download_run(s3_client: &aws_sdk_s3::Client, unique_id: &str) -> OrchResult<TempDir> {
+    let tmp_dir = TempDir::new(unique_id).map_err(|err| OrchError::Init {
+        dbg: err.to_string(),
+    })?;
+    for folder in ["sysmetrics", "results"] {
+        sync_from_s3(
+            s3_client,
+            STATE.s3_log_bucket,
+            &format!("{unique_id}/{folder}"),
+            &tmp_dir.path().join(folder),
+            DownloadOptions::default(),
+            &[],
+        )
+        .await?;
+    }
+    Ok(tmp_dir)
+}
+
+/// The throughput, from the system metrics, and the tail latency, from the
+/// netbench client results, of each job at each interval of the runs
+/// downloaded to `current` and `baseline`.
+///
+/// Jobs missing from either run aren't compared.
+pub fn interval_diffs(current: &Path, baseline: &Path) -> OrchResult<Vec<IntervalDiff>> {
+    let mut diffs = Vec::new();
+    let baseline_hosts = group_hosts(&baseline.join("sysmetrics"))?;
+    for (job, hosts) in group_hosts(&current.join("sysmetrics"))? {
+        if let Some(baseline_hosts) = baseline_hosts.get(&job) {
+            diffs.push(IntervalDiff::new(
+                &job,
+                "tx kB/s".to_string(),
+                &tx_kbps_series(&hosts),
+                &tx_kbps_series(baseline_hosts),
+            ));
+        }
+    }
+
+    let baseline_latency = job_latency(baseline)?;
+    for (job, latency) in job_latency(current)? {
+        for ((trace, driver), series) in latency {
+            let Some(baseline_series) = baseline_latency
+                .get(&job)
+                .and_then(|latency| latency.get(&(trace.clone(), driver.clone())))
+            else {
+                continue;
+            };
+            let p99 = |series: &[LatencySample]| {
+                series
+                    .iter()
+                    .map(|sample| (sample.time_s, sample.percentiles[LATENCY_PERCENTILE]))
+                    .collect::<Vec<_>>()
+            };
+            diffs.push(IntervalDiff::new(
+                &format!("{job}/{driver}"),
+                format!("{trace} p99 latency (us)"),
+                &p99(&series),
+                &p99(baseline_series),
+            ));
+        }
+    }
+    Ok(diffs)
+}
+
+/// The transmit rate of a host group at each interval, averaged across its
+/// hosts like `compare::JobMetrics`, with the time since its first sample.
+fn tx_kbps_series(hosts: &[HostMetrics]) -> Vec<(f64, f64)> {
+    let len = hosts
+        .iter()
+        .map(|host| host.samples.len())
+        .max()
+        .unwrap_or(0);
+    (0..len)
+        .map(|i| {
+            let samples: Vec<f64> = hosts
+                .iter()
+                .filter_map(|host| host.samples.get(i).map(|sample| sample.tx_kbps))
+                .collect();
+            let time_s = hosts
+                .iter()
+                .find_map(|host| {
+                    let first = host.samples.first()?.unix_millis;
+                    Some(host.samples.get(i)?.unix_millis.saturating_sub(first) as f64 / 1000.0)
+                })
+                .unwrap_or(i as f64);
+            (
+                time_s,
+                samples.iter().sum::<f64>() / samples.len().max(1) as f64,
+            )
+        })
+        .collect()
+}
+
+/// A vega-lite spec overlaying the run and its baseline, with a red rule at
+/// each diverging interval.
+fn diff_spec(diff: &IntervalDiff, divergence_pct: f64) -> Value {
+    let mut values = Vec::new();
+    for point in diff.points.iter() {
+        for (run, value) in [("candidate", point.current), ("baseline", point.baseline)] {
+            if let Some(value) = value {
+                values.push(json!({
+                    "time_s": point.time_s,
+                    "run": run,
+                    "value": value,
+                    "diverges": point.diverges(divergence_pct),
+                }));
+            }
+        }
+    }
+    let x = json!({ "field": "time_s", "type": "quantitative", "title": "time (s)" });
+    json!({
+        "$schema": "https://vega.github.io/schema/vega-lite/v5.json",
+        "title": format!("{} {}", diff.job, diff.metric),
+        "width": 600,
+        "data": { "values": values },
+        "layer": [
+            {
+                "transform": [{ "filter": "datum.diverges && datum.run == 'candidate'" }],
+                "mark": { "type": "rule", "color": "red", "opacity": 0.3, "strokeWidth": 4 },
+                "encoding": { "x": x },
+            },
+            {
+                "mark": "line",
+                "encoding": {
+                    "x": x,
+                    "y": { "field": "value", "type": "quantitative", "title": diff.metric },
+                    "color": { "field": "run", "type": "nominal" },
+                },
+            },
+        ],
+    })
+}
+
+pub fn diff_html(
+    unique_id: &str,
+    baseline_id: &str,
+    diffs: &[IntervalDiff],
+    divergence_pct: f64,
+) -> String {
+    let mut html = format!(
+        "<html><head>\
+        <script src=\"https://cdn.jsdelivr.net/npm/vega@5\"></script>\
+        <script src=\"https://cdn.jsdelivr.net/npm/vega-lite@5\"></script>\
+        <script src=\"https://cdn.jsdelivr.net/npm/vega-embed@6\"></script>\
+        </head><body><h2>{} vs {}</h2>\
+        <p>Intervals where the run differs from the baseline by more than {divergence_pct}% are highlighted in red</p>",
+        escape_html(unique_id),
+        escape_html(baseline_id),
+    );
+    if diffs.is_empty() {
+        html.push_str("<p>No job was found in both runs</p>");
+    }
+    for (i, diff) in diffs.iter().enumerate() {
+        html.push_str(&format!(
+            "<h3>{} {}: {} diverging intervals</h3><div id=\"chart{i}\"></div><script>vegaEmbed('#chart{i}', {});</script>",
+            escape_html(&diff.job),
+            escape_html(&diff.metric),
+            diff.diverging(divergence_pct),
+            // keep the spec from closing the script tag
+            diff_spec(diff, divergence_pct)
+                .to_string()
+                .replace("</", "<\\/")
+        ));
+    }
+    html.push_str("</body></html>");
+    html
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn interval_diff() {
+        let write = |dir: &Path, tx_kbps: &[f64], p99: &[u64]| {
+            let metrics_dir = dir.join("sysmetrics/request_response/s2n-quic");
+            std::fs::create_dir_all(&metrics_dir).unwrap();
+            let mut csv = "unix_millis,cpu_user,cpu_system,cpu_idle,cpu_iowait,mem_free_kb,tcp_estab,tcp_retrans_segs,rx_kbps,tx_kbps\n".to_string();
+            for (i, tx_kbps) in tx_kbps.iter().enumerate() {
+                csv.push_str(&format!(
+                    "{},10,5,85,0,1000,1,0,100,{tx_kbps}\n",
+                    1000 + i * 1000
+                ));
+            }
+            std::fs::write(metrics_dir.join("client-i-1-s2n-quic.sysmetrics.csv"), csv).unwrap();
+
+            let results_dir = dir.join("results/request_response/s2n-quic");
+            std::fs::create_dir_all(&results_dir).unwrap();
+            let intervals: Vec<Value> = p99
+                .iter()
+                .enumerate()
+                .map(|(i, p99)| {
+                    json!({ "time": (i as u64 + 1) * 1_000_000_000, "profiles": { "0": { "p50": 1000, "p90": 2000, "p99": p99, "p999": 9000 } } })
+                })
+                .collect();
+            let result = json!({ "traces": ["request"], "intervals": intervals });
+            std::fs::write(results_dir.join("client-0.json"), result.to_string()).unwrap();
+        };
+        let current = tempdir::TempDir::new("current").unwrap();
+        let baseline = tempdir::TempDir::new("baseline").unwrap();
+        // a mid-run stall which barely moves the mean
+        write(
+            current.path(),
+            &[1000.0, 200.0, 1000.0, 1000.0],
+            &[3000, 30000, 3000],
+        );
+        write(
+            baseline.path(),
+            &[1000.0, 1000.0, 1000.0],
+            &[3000, 3100, 3000],
+        );
+
+        let diffs = interval_diffs(current.path(), baseline.path()).unwrap();
+        assert_eq!(diffs.len(), 2);
+        let throughput = &diffs[0];
+        assert_eq!(throughput.job, "request_response/s2n-quic/client");
+        assert_eq!(throughput.points.len(), 4);
+        assert_eq!(
+            throughput.points[1],
+            DiffPoint {
+                time_s: 1.0,
+                current: Some(200.0),
+                baseline: Some(1000.0),
+            }
+        );
+        assert_eq!(throughput.points[3].baseline, None);
+        assert_eq!(throughput.diverging(10.0), 1);
+
+        let latency = &diffs[1];
+        assert_eq!(latency.job, "request_response/s2n-quic");
+        assert_eq!(latency.metric, "request p99 latency (us)");
+        assert_eq!(latency.diverging(10.0), 1);
+        assert_eq!(latency.diverging(1000.0), 0);
+
+        let html = diff_html("run", "base", &diffs, 10.0);
+        assert!(html.contains("tx kB/s: 1 diverging intervals"), "{html}");
+        assert!(html.contains(r#""diverges":true"#), "{html}");
+    }
+}
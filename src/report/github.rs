@@ -0,0 +1,58 @@
+// Copyright Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+use super::{
+    compare::{download_metrics, render_comparison},
+    render::Markdown,
+};
+use crate::{
+    config::Github,
+    error::{OrchError, OrchResult},
+    notify::post_json,
+    s3_utils::baseline::resolve_baseline,
+    state::STATE,
+};
+use serde_json::json;
+use tracing::info;
+
+/// Post a markdown comparison of the run against the `github.baseline` run as a
+/// comment on the PR.
+///
+/// Requires `curl` and the token in the `github.token_env` env var.
+pub async fn post_comparison(
+    s3_client: &aws_sdk_s3::Client,
+    unique_id: &str,
+    github: &Github,
+    pr: u64,
+) -> OrchResult<()> {
+    let token = std::env::var(&github.token_env).map_err(|_err| OrchError::Init {
+        dbg: format!("Missing github token in env var {}", github.token_env),
+    })?;
+
+    let current = download_metrics(s3_client, unique_id).await?;
+    let baseline_id = resolve_baseline(s3_client, STATE.s3_log_bucket, &github.baseline).await?;
+    let baseline = match &baseline_id {
+        Some(baseline_id) => Some((
+            baseline_id.as_str(),
+            download_metrics(s3_client, baseline_id).await?,
+        )),
+        None => None,
+    };
+    let body = render_comparison(&Markdown, unique_id, &current, baseline.as_ref());
+
+    let url = format!(
+        "https://api.github.com/repos/{}/issues/{pr}/comments",
+        github.repo
+    );
+    post_json(
+        &url,
+        &[
+            format!("Authorization: Bearer {token}"),
+            "Accept: application/vnd.github+json".to_string(),
+        ],
+        &json!({ "body": body }).to_string(),
+    )
+    .await?;
+    info!("Posted the comparison on {}#{}", github.repo, pr);
+    Ok(())
+}
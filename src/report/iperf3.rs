@@ -0,0 +1,214 @@
+// Copyright Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+use super::{collect_files, escape_html};
+use crate::{
+    error::{OrchError, OrchResult},
+    ssm_utils::iperf3::IPERF3_DIR,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::path::Path;
+
+/// The iperf3 baseline of a client for a protocol, parsed from the `--json`
+/// output of iperf3.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Iperf3Result {
+    // The instance id of the client
+    pub client: String,
+    // tcp or udp
+    pub protocol: String,
+    pub sent_bps: f64,
+    pub received_bps: f64,
+    // tcp only
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub retransmits: Option<u64>,
+    // udp only
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub jitter_ms: Option<f64>,
+    // udp only
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub lost_percent: Option<f64>,
+    // The error reported by iperf3 if the test failed
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// Parse the json output of an iperf3 client.
+///
+/// tcp tests report the `sum_sent` and `sum_received` of their streams, while
+/// udp tests report a `sum` (and a `sum_received` since iperf3 3.13).
+fn parse_result(client: &str, protocol: &str, json: &str) -> Iperf3Result {
+    let mut result = Iperf3Result {
+        client: client.to_string(),
+        protocol: protocol.to_string(),
+        ..Default::default()
+    };
+    let output: Value = match serde_json::from_str(json) {
+        Ok(output) => output,
+        Err(err) => {
+            result.error = Some(format!("unparsable iperf3 output: {}", err));
+            return result;
+        }
+    };
+    if let Some(error) = output.get("error").and_then(Value::as_str) {
+        result.error = Some(error.to_string());
+        return result;
+    }
+    let end = &output["end"];
+    let bps = |sum: &str| end[sum]["bits_per_second"].as_f64();
+    match protocol {
+        "udp" => {
+            result.sent_bps = bps("sum").unwrap_or_default();
+            result.received_bps = bps("sum_received").unwrap_or(result.sent_bps);
+            result.jitter_ms = end["sum"]["jitter_ms"].as_f64();
+            result.lost_percent = end["sum"]["lost_percent"].as_f64();
+        }
+        _ => {
+            result.sent_bps = bps("sum_sent").unwrap_or_default();
+            result.received_bps = bps("sum_received").unwrap_or_default();
+            result.retransmits = end["sum_sent"]["retransmits"].as_u64();
+        }
+    }
+    result
+}
+
+/// Parse the results downloaded to `<dir>/iperf3`, which are named
+/// `client-<instance_id>-<protocol>.json`.
+pub fn collect(dir: &Path) -> OrchResult<Vec<Iperf3Result>> {
+    let mut files = Vec::new();
+    collect_files(&dir.join(IPERF3_DIR), "json", &mut files)?;
+    files.sort();
+
+    let mut results = Vec::new();
+    for path in files {
+        let Some((client, protocol)) = path
+            .file_stem()
+            .and_then(|stem| stem.to_str())
+            .and_then(|stem| stem.strip_prefix("client-"))
+            .and_then(|stem| stem.rsplit_once('-'))
+        else {
+            continue;
+        };
+        let json = std::fs::read_to_string(&path).map_err(|err| OrchError::Init {
+            dbg: format!("Failed to read {:?}: {}", path, err),
+        })?;
+        results.push(parse_result(client, protocol, &json));
+    }
+    Ok(results)
+}
+
+fn gbps(bps: f64) -> String {
+    format!("{:.2}", bps / 1e9)
+}
+
+fn iperf3_html(results: &[Iperf3Result]) -> String {
+    let mut html = String::from("<html><body><h2>iperf3 baseline</h2>");
+    // the clients run concurrently so their sum is the ceiling of the hosts
+    for protocol in ["tcp", "udp"] {
+        let received: f64 = results
+            .iter()
+            .filter(|result| result.protocol == protocol && result.error.is_none())
+            .map(|result| result.received_bps)
+            .sum();
+        if received > 0.0 {
+            html.push_str(&format!(
+                "<p>{} received across the clients: {} Gbit/s</p>",
+                protocol,
+                gbps(received)
+            ));
+        }
+    }
+    html.push_str("<table><tr><th>client</th><th>protocol</th><th>sent (Gbit/s)</th><th>received (Gbit/s)</th><th>retransmits</th><th>jitter (ms)</th><th>lost (%)</th><th>error</th></tr>");
+    let optional = |value: Option<String>| value.unwrap_or_else(|| "-".to_string());
+    for result in results {
+        html.push_str(&format!(
+            "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>",
+            escape_html(&result.client),
+            escape_html(&result.protocol),
+            gbps(result.sent_bps),
+            gbps(result.received_bps),
+            optional(result.retransmits.map(|retransmits| retransmits.to_string())),
+            optional(result.jitter_ms.map(|jitter| format!("{jitter:.3}"))),
+            optional(result.lost_percent.map(|lost| format!("{lost:.2}"))),
+            escape_html(result.error.as_deref().unwrap_or_default()),
+        ));
+    }
+    html.push_str("</table></body></html>");
+    html
+}
+
+/// Tabulate the iperf3 baseline in `<dir>/report/iperf3.html`.
+///
+/// Returns false if the run didn't include a baseline.
+pub fn generate_report(dir: &Path) -> OrchResult<bool> {
+    let results = collect(dir)?;
+    if results.is_empty() {
+        return Ok(false);
+    }
+    let report_path = dir.join("report").join("iperf3.html");
+    std::fs::create_dir_all(dir.join("report"))
+        .and_then(|_| std::fs::write(&report_path, iperf3_html(&results)))
+        .map_err(|err| OrchError::Init {
+            dbg: format!("Failed to write {:?}: {}", report_path, err),
+        })?;
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn iperf3_results() {
+        let dir = tempdir::TempDir::new("iperf3").unwrap();
+        let iperf3_dir = dir.path().join(IPERF3_DIR);
+        std::fs::create_dir_all(&iperf3_dir).unwrap();
+        let tcp = json!({
+            "start": { "test_start": { "protocol": "TCP" } },
+            "end": {
+                "sum_sent": { "bits_per_second": 9.5e9, "retransmits": 12 },
+                "sum_received": { "bits_per_second": 9.4e9 },
+            },
+        });
+        let udp = json!({
+            "end": {
+                "sum": { "bits_per_second": 5e9, "jitter_ms": 0.012, "lost_percent": 0.5 },
+            },
+        });
+        let failed = json!({ "error": "unable to connect to server: Connection refused" });
+        std::fs::write(iperf3_dir.join("client-i-1-tcp.json"), tcp.to_string()).unwrap();
+        std::fs::write(iperf3_dir.join("client-i-1-udp.json"), udp.to_string()).unwrap();
+        std::fs::write(iperf3_dir.join("client-i-2-tcp.json"), failed.to_string()).unwrap();
+
+        let results = collect(dir.path()).unwrap();
+        assert_eq!(
+            results[0],
+            Iperf3Result {
+                client: "i-1".to_string(),
+                protocol: "tcp".to_string(),
+                sent_bps: 9.5e9,
+                received_bps: 9.4e9,
+                retransmits: Some(12),
+                ..Default::default()
+            }
+        );
+        assert_eq!(results[1].protocol, "udp");
+        assert_eq!(results[1].received_bps, 5e9);
+        assert_eq!(results[1].jitter_ms, Some(0.012));
+        assert_eq!(results[1].lost_percent, Some(0.5));
+        assert_eq!(results[2].client, "i-2");
+        assert!(results[2]
+            .error
+            .as_ref()
+            .unwrap()
+            .contains("Connection refused"));
+        assert!(parse_result("i-3", "tcp", "").error.is_some());
+
+        assert!(generate_report(dir.path()).unwrap());
+        let html = std::fs::read_to_string(dir.path().join("report/iperf3.html")).unwrap();
+        // the failed client isn't included in the total
+        assert!(html.contains("tcp received across the clients: 9.40 Gbit/s"));
+    }
+}
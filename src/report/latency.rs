@@ -0,0 +1,271 @@
+// Copyright Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+use super::{collect_files, escape_html, parse_run, CollectorResult};
+use crate::error::{OrchError, OrchResult};
+use serde_json::{json, Value};
+use std::{collections::BTreeMap, path::Path};
+use tracing::warn;
+
+// The latency percentiles reported by the netbench collector for each profiled
+// trace, in nanoseconds
+pub(super) const PERCENTILES: [&str; 4] = ["p50", "p90", "p99", "p999"];
+
+/// The latency percentiles of a trace over a collector interval, in
+/// microseconds.
+#[derive(Clone, Debug, PartialEq)]
+pub struct LatencySample {
+    // Seconds since the start of the run
+    pub time_s: f64,
+    pub percentiles: [f64; 4],
+}
+
+/// The latency series of each profiled trace of a netbench client result,
+/// keyed by the trace name, in microseconds.
+pub fn parse_client_result(json: &str) -> Option<BTreeMap<String, Vec<LatencySample>>> {
+    CollectorResult::parse(json)
+        .ok()
+        .map(|result| client_latency(&result))
+}
+
+fn client_latency(result: &CollectorResult) -> BTreeMap<String, Vec<LatencySample>> {
+    let mut series: BTreeMap<String, Vec<LatencySample>> = BTreeMap::new();
+    for interval in result.intervals.iter() {
+        for (trace, profile) in result.profiles(interval) {
+            series
+                .entry(trace.to_string())
+                .or_default()
+                .push(LatencySample {
+                    time_s: interval.time as f64 / 1e9,
+                    percentiles: profile.percentiles().map(|ns| ns / 1000.0),
+                });
+        }
+    }
+    series
+}
+
+/// Merge the series of a driver's clients, keeping the slowest client at each
+/// interval since the tail is what's being compared.
+pub(super) fn merge_clients(clients: Vec<Vec<LatencySample>>) -> Vec<LatencySample> {
+    let mut merged: Vec<LatencySample> = Vec::new();
+    for series in clients {
+        for (i, sample) in series.into_iter().enumerate() {
+            match merged.get_mut(i) {
+                Some(slowest) => {
+                    for (slowest, value) in slowest.percentiles.iter_mut().zip(sample.percentiles) {
+                        *slowest = slowest.max(value);
+                    }
+                }
+                None => merged.push(sample),
+            }
+        }
+    }
+    merged
+}
+
+/// The latency series of a job: (trace, driver) -> samples
+pub(super) type JobLatency = BTreeMap<(String, String), Vec<LatencySample>>;
+
+/// Chart the latency percentiles of the netbench client results in
+/// `<dir>/results` into `<dir>/report/latency.html`, overlaying the drivers of
+/// each job. The vega-lite spec of each job is uploaded alongside as
+/// `report/latency/<job>.vl.json`.
+///
+/// Returns false if no client result had latency percentiles.
+pub fn generate_report(dir: &Path) -> OrchResult<bool> {
+    let jobs = job_latency(dir)?;
+    if jobs.is_empty() {
+        return Ok(false);
+    }
+
+    let spec_dir = dir.join("report").join("latency");
+    let write = |path: &Path, contents: String| {
+        std::fs::create_dir_all(path.parent().unwrap_or(dir))
+            .and_then(|_| std::fs::write(path, contents))
+            .map_err(|err| OrchError::Init {
+                dbg: format!("Failed to write {:?}: {}", path, err),
+            })
+    };
+    let mut specs = Vec::new();
+    for (job, latency) in jobs.iter() {
+        let spec = latency_spec(job, latency);
+        write(
+            &spec_dir.join(format!("{}.vl.json", job.replace('/', "_"))),
+            spec.to_string(),
+        )?;
+        specs.push((job.as_str(), spec));
+    }
+    write(
+        &dir.join("report").join("latency.html"),
+        latency_html(&specs),
+    )?;
+    Ok(true)
+}
+
+/// The latency series of the netbench client results in `<dir>/results`, by
+/// job, with the slowest client of each driver at each interval.
+pub(super) fn job_latency(dir: &Path) -> OrchResult<BTreeMap<String, JobLatency>> {
+    let results_dir = dir.join("results");
+    let mut files = Vec::new();
+    collect_files(&results_dir, "json", &mut files)?;
+    files.sort();
+
+    // job -> (trace, driver) -> series of each client
+    let mut jobs: BTreeMap<String, BTreeMap<(String, String), Vec<_>>> = BTreeMap::new();
+    for path in files {
+        // ex: results/request_response/s2n-quic/client-0.json
+        let Some(relative) = path.strip_prefix(&results_dir).ok() else {
+            continue;
+        };
+        let components: Vec<&str> = relative
+            .iter()
+            .filter_map(|component| component.to_str())
+            .collect();
+        let [job @ .., driver, file_name] = components.as_slice() else {
+            continue;
+        };
+        if job.is_empty() || !file_name.starts_with("client-") {
+            continue;
+        }
+        let result = match parse_run(&path) {
+            Ok(result) => result,
+            Err(err) => {
+                warn!("skipping unparsable client result: {}", err);
+                continue;
+            }
+        };
+        for (trace, series) in client_latency(&result) {
+            jobs.entry(job.join("/"))
+                .or_default()
+                .entry((trace, driver.to_string()))
+                .or_default()
+                .push(series);
+        }
+    }
+
+    Ok(jobs
+        .into_iter()
+        .map(|(job, latency)| {
+            let latency = latency
+                .into_iter()
+                .map(|(key, clients)| (key, merge_clients(clients)))
+                .filter(|(_key, series)| !series.is_empty())
+                .collect::<JobLatency>();
+            (job, latency)
+        })
+        .filter(|(_job, latency)| !latency.is_empty())
+        .collect())
+}
+
+/// A vega-lite spec with a row per percentile and a line per driver and trace.
+fn latency_spec(job: &str, latency: &JobLatency) -> Value {
+    let mut values = Vec::new();
+    for ((trace, driver), series) in latency {
+        for sample in series {
+            for (percentile, latency_us) in PERCENTILES.iter().zip(sample.percentiles) {
+                values.push(json!({
+                    "trace": trace,
+                    "driver": driver,
+                    "percentile": percentile,
+                    "time_s": sample.time_s,
+                    "latency_us": latency_us,
+                }));
+            }
+        }
+    }
+    json!({
+        "$schema": "https://vega.github.io/schema/vega-lite/v5.json",
+        "title": job,
+        "data": { "values": values },
+        "facet": { "row": { "field": "percentile", "sort": PERCENTILES } },
+        "resolve": { "scale": { "y": "independent" } },
+        "spec": {
+            "width": 600,
+            "mark": "line",
+            "encoding": {
+                "x": { "field": "time_s", "type": "quantitative", "title": "time (s)" },
+                "y": { "field": "latency_us", "type": "quantitative", "title": "latency (us)" },
+                "color": { "field": "driver", "type": "nominal" },
+                "strokeDash": { "field": "trace", "type": "nominal" },
+            },
+        },
+    })
+}
+
+fn latency_html(specs: &[(&str, Value)]) -> String {
+    let mut html = String::from(
+        "<html><head>\
+        <script src=\"https://cdn.jsdelivr.net/npm/vega@5\"></script>\
+        <script src=\"https://cdn.jsdelivr.net/npm/vega-lite@5\"></script>\
+        <script src=\"https://cdn.jsdelivr.net/npm/vega-embed@6\"></script>\
+        </head><body><h2>Latency</h2>",
+    );
+    for (i, (job, spec)) in specs.iter().enumerate() {
+        html.push_str(&format!(
+            "<h3>{}</h3><div id=\"chart{i}\"></div><script>vegaEmbed('#chart{i}', {});</script>",
+            escape_html(job),
+            // keep the spec from closing the script tag
+            spec.to_string().replace("</", "<\\/")
+        ));
+    }
+    html.push_str("</body></html>");
+    html
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn latency_percentiles() {
+        let result = |p99| {
+            json!({
+                "traces": ["lbl", "request", "response"],
+                "intervals": [
+                    { "time": 1_000_000_000u64, "profiles": { "1": { "p50": 1000, "p90": 2000, "p99": p99, "p999": 9000 } } },
+                    { "time": 2_000_000_000u64, "profiles": { "1": { "p50": 1500, "p90": 2500, "p99": 4000, "p999": 9500 }, "7": {} } },
+                ],
+            })
+            .to_string()
+        };
+        let series = parse_client_result(&result(3000)).unwrap();
+        assert_eq!(series.len(), 1);
+        assert_eq!(
+            series["request"][0],
+            LatencySample {
+                time_s: 1.0,
+                percentiles: [1.0, 2.0, 3.0, 9.0]
+            }
+        );
+        assert!(parse_client_result("{}").is_none());
+
+        // the slowest client at each interval
+        let slow = parse_client_result(&result(8000)).unwrap();
+        let merged = merge_clients(vec![
+            series["request"].clone(),
+            slow["request"][..1].to_vec(),
+        ]);
+        assert_eq!(merged.len(), 2);
+        assert_eq!(merged[0].percentiles[2], 8.0);
+        assert_eq!(merged[1].percentiles[2], 4.0);
+
+        let dir = tempdir::TempDir::new("latency").unwrap();
+        for (driver, p99) in [("s2n-quic", 3000), ("tcp", 8000)] {
+            let driver_dir = dir.path().join("results/request_response").join(driver);
+            std::fs::create_dir_all(&driver_dir).unwrap();
+            std::fs::write(driver_dir.join("client-0.json"), result(p99)).unwrap();
+            std::fs::write(driver_dir.join("server-0.json"), "{}").unwrap();
+        }
+        assert!(generate_report(dir.path()).unwrap());
+        let spec =
+            std::fs::read_to_string(dir.path().join("report/latency/request_response.vl.json"))
+                .unwrap();
+        assert!(spec.contains(r#""driver":"tcp""#), "{spec}");
+        assert!(spec.contains(r#""latency_us":8.0"#), "{spec}");
+        assert!(
+            std::fs::read_to_string(dir.path().join("report/latency.html"))
+                .unwrap()
+                .contains("<h3>request_response</h3>")
+        );
+    }
+}
@@ -0,0 +1,165 @@
+// Copyright Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+use super::{
+    collect_files,
+    render::{Html, Renderer},
+};
+use crate::{
+    config::NetbenchReport,
+    error::{OrchError, OrchResult},
+};
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+    process::Command,
+};
+use tracing::{debug, warn};
+
+/// Render the results in `<dir>/results` with s2n-netbench's own report
+/// commands, into `<dir>/report`.
+///
+/// Returns whether `report-tree` rendered `report/index.html`, and whether
+/// the per job reports were indexed in `report/netbench.html`. A failure of
+/// s2n-netbench is only a warning since the orchestrator's report is still
+/// uploaded.
+pub fn generate_report(dir: &Path, options: NetbenchReport) -> OrchResult<(bool, bool)> {
+    if options.disable {
+        return Ok((false, false));
+    }
+    let results_path = dir.join("results");
+    let report_path = dir.join("report");
+
+    let mut cmd = Command::new("s2n-netbench");
+    cmd.arg("report-tree").arg(&results_path).arg(&report_path);
+    let tree = run(cmd);
+
+    if !options.per_job {
+        return Ok((tree, false));
+    }
+    let mut reports = Vec::new();
+    for (job, inputs) in job_results(&results_path)? {
+        // ex: report/netbench/request_response/s2n-quic.json
+        let output = report_path
+            .join("netbench")
+            .join(&job)
+            .with_extension("json");
+        if let Some(parent) = output.parent() {
+            std::fs::create_dir_all(parent).map_err(|err| OrchError::Init {
+                dbg: format!("Failed to create {:?}: {}", parent, err),
+            })?;
+        }
+        let mut cmd = Command::new("s2n-netbench");
+        cmd.arg("report").args(&inputs).arg("-o").arg(&output);
+        if run(cmd) {
+            reports.push(job);
+        }
+    }
+    if reports.is_empty() {
+        return Ok((tree, false));
+    }
+
+    let index_path = report_path.join("netbench.html");
+    std::fs::write(&index_path, index_html(&reports)).map_err(|err| OrchError::Init {
+        dbg: format!("Failed to write {:?}: {}", index_path, err),
+    })?;
+    Ok((tree, true))
+}
+
+// Run a s2n-netbench report command, warning if it fails
+fn run(mut cmd: Command) -> bool {
+    debug!("{:?}", cmd);
+    match cmd.status() {
+        Ok(status) if status.success() => true,
+        Ok(status) => {
+            warn!("{:?} failed: {}", cmd, status);
+            false
+        }
+        Err(err) => {
+            warn!("Failed to run s2n-netbench: {}", err);
+            false
+        }
+    }
+}
+
+/// The result files of each job and driver, keyed by their folder relative to
+/// `results_dir`. ex: request_response/s2n-quic
+fn job_results(results_dir: &Path) -> OrchResult<BTreeMap<PathBuf, Vec<PathBuf>>> {
+    let mut files = Vec::new();
+    collect_files(results_dir, "json", &mut files)?;
+    files.sort();
+
+    let mut jobs: BTreeMap<PathBuf, Vec<PathBuf>> = BTreeMap::new();
+    for path in files {
+        let Some(job) = path
+            .parent()
+            .and_then(|parent| parent.strip_prefix(results_dir).ok())
+        else {
+            continue;
+        };
+        // a result of a driver is at least at <job>/<driver>/
+        if job.components().count() < 2 {
+            continue;
+        }
+        jobs.entry(job.to_path_buf()).or_default().push(path);
+    }
+    Ok(jobs)
+}
+
+fn index_html(reports: &[PathBuf]) -> String {
+    let renderer = Html;
+    let rows: Vec<Vec<String>> = reports
+        .iter()
+        .map(|job| {
+            let job = job.display().to_string();
+            vec![renderer.link(&job, &format!("netbench/{job}.json"))]
+        })
+        .collect();
+    renderer.document(&[
+        renderer.heading("s2n-netbench reports"),
+        renderer.table(&["job/driver"], &rows),
+    ])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn netbench_reports() {
+        let dir = tempdir::TempDir::new("netbench_report").unwrap();
+        let results = dir.path().join("results");
+        for file in [
+            "request_response/s2n-quic/client-i-1-s2n-quic.json",
+            "request_response/s2n-quic/server-i-2-s2n-quic.json",
+            "request_response/tcp/client-i-1-tcp.json",
+            "build_info.json",
+        ] {
+            let path = results.join(file);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, "{}").unwrap();
+        }
+
+        let jobs = job_results(&results).unwrap();
+        assert_eq!(
+            jobs.keys().collect::<Vec<_>>(),
+            [
+                Path::new("request_response/s2n-quic"),
+                Path::new("request_response/tcp")
+            ]
+        );
+        assert_eq!(jobs[Path::new("request_response/s2n-quic")].len(), 2);
+        assert!(index_html(&jobs.into_keys().collect::<Vec<_>>()).contains(
+            "<td><a href=\"netbench/request_response/tcp.json\">request_response/tcp</a></td>"
+        ));
+
+        let disabled = NetbenchReport {
+            disable: true,
+            per_job: true,
+        };
+        assert_eq!(
+            generate_report(dir.path(), disabled).unwrap(),
+            (false, false)
+        );
+    }
+}
@@ -0,0 +1,271 @@
+// Copyright Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+use super::escape_html;
+use std::{fmt, str::FromStr};
+
+/// Renders the building blocks of a report in an output format, so that the
+/// same data drives the hosted report, PR comments and the console.
+pub trait Renderer {
+    /// Plain text, ex: a job name in a table cell
+    fn text(&self, text: &str) -> String {
+        text.to_string()
+    }
+
+    fn heading(&self, text: &str) -> String;
+
+    /// `text` may contain rendered blocks, ex: the output of `link`
+    fn paragraph(&self, text: &str) -> String;
+
+    fn link(&self, text: &str, url: &str) -> String;
+
+    /// Flag a cell, ex: a noisy metric
+    fn emphasis(&self, text: &str) -> String;
+
+    /// `rows` are rendered cells, ex: the output of `text` or `link`
+    fn table(&self, headers: &[&str], rows: &[Vec<String>]) -> String;
+
+    /// Wrap the rendered blocks into a standalone document.
+    fn document(&self, blocks: &[String]) -> String {
+        blocks.concat()
+    }
+}
+
+/// The output format of a report, chosen with `--format`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Format {
+    Html,
+    // GitHub-flavored
+    Markdown,
+    // ANSI
+    #[default]
+    Terminal,
+}
+
+impl Format {
+    pub fn as_str(&self) -> &str {
+        match self {
+            Format::Html => "html",
+            Format::Markdown => "markdown",
+            Format::Terminal => "terminal",
+        }
+    }
+
+    pub fn renderer(&self) -> &'static dyn Renderer {
+        match self {
+            Format::Html => &Html,
+            Format::Markdown => &Markdown,
+            Format::Terminal => &Terminal,
+        }
+    }
+}
+
+impl fmt::Display for Format {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "html" => Ok(Format::Html),
+            "markdown" | "md" => Ok(Format::Markdown),
+            "terminal" => Ok(Format::Terminal),
+            _ => Err(format!(
+                "unsupported report format: {}. expected html, markdown or terminal",
+                s
+            )),
+        }
+    }
+}
+
+/// The pages uploaded with the run's report, ex: `report/iterations.html`
+pub struct Html;
+
+impl Renderer for Html {
+    fn text(&self, text: &str) -> String {
+        escape_html(text)
+    }
+
+    fn heading(&self, text: &str) -> String {
+        format!("<h2>{}</h2>", escape_html(text))
+    }
+
+    fn paragraph(&self, text: &str) -> String {
+        format!("<p>{text}</p>")
+    }
+
+    fn link(&self, text: &str, url: &str) -> String {
+        format!("<a href=\"{url}\">{}</a>", escape_html(text))
+    }
+
+    fn emphasis(&self, text: &str) -> String {
+        format!("<b>{}</b>", escape_html(text))
+    }
+
+    fn table(&self, headers: &[&str], rows: &[Vec<String>]) -> String {
+        let mut html = String::from("<table><tr>");
+        for header in headers {
+            html.push_str(&format!("<th>{}</th>", escape_html(header)));
+        }
+        html.push_str("</tr>");
+        for row in rows {
+            html.push_str("<tr>");
+            for cell in row {
+                html.push_str(&format!("<td>{cell}</td>"));
+            }
+            html.push_str("</tr>");
+        }
+        html.push_str("</table>");
+        html
+    }
+
+    fn document(&self, blocks: &[String]) -> String {
+        format!("<html><body>{}</body></html>", blocks.concat())
+    }
+}
+
+/// GitHub-flavored markdown, ex: the comparison posted on a PR
+pub struct Markdown;
+
+impl Renderer for Markdown {
+    fn heading(&self, text: &str) -> String {
+        format!("### {text}\n\n")
+    }
+
+    fn paragraph(&self, text: &str) -> String {
+        format!("{text}\n\n")
+    }
+
+    fn link(&self, text: &str, url: &str) -> String {
+        format!("[{text}]({url})")
+    }
+
+    fn emphasis(&self, text: &str) -> String {
+        format!("**{text}**")
+    }
+
+    fn table(&self, headers: &[&str], rows: &[Vec<String>]) -> String {
+        let row = |cells: Vec<&str>| format!("| {} |\n", cells.join(" | "));
+        let mut markdown = row(headers.to_vec());
+        markdown.push_str(&format!("|{}\n", "---|".repeat(headers.len())));
+        for cells in rows {
+            markdown.push_str(&row(cells.iter().map(String::as_str).collect()));
+        }
+        markdown
+    }
+}
+
+const BOLD: &str = "\x1b[1m";
+const UNDERLINE: &str = "\x1b[4m";
+const RESET: &str = "\x1b[0m";
+
+/// A console with ANSI escapes, ex: `report summary`
+pub struct Terminal;
+
+impl Renderer for Terminal {
+    fn heading(&self, text: &str) -> String {
+        format!("{BOLD}{text}{RESET}\n\n")
+    }
+
+    fn paragraph(&self, text: &str) -> String {
+        format!("{text}\n\n")
+    }
+
+    fn link(&self, text: &str, url: &str) -> String {
+        format!("{text} ({UNDERLINE}{url}{RESET})")
+    }
+
+    fn emphasis(&self, text: &str) -> String {
+        format!("{BOLD}{text}{RESET}")
+    }
+
+    fn table(&self, headers: &[&str], rows: &[Vec<String>]) -> String {
+        // align the columns on the visible width of the cells
+        let mut widths: Vec<usize> = headers.iter().map(|header| visible_len(header)).collect();
+        for row in rows {
+            for (width, cell) in widths.iter_mut().zip(row) {
+                *width = (*width).max(visible_len(cell));
+            }
+        }
+        let line = |cells: Vec<String>| {
+            let cells: Vec<String> = cells
+                .into_iter()
+                .zip(widths.iter())
+                .map(|(cell, width)| {
+                    let padding = width - visible_len(&cell);
+                    format!("{cell}{}", " ".repeat(padding))
+                })
+                .collect();
+            format!("{}\n", cells.join("  ").trim_end())
+        };
+
+        let mut table = line(
+            headers
+                .iter()
+                .map(|header| format!("{BOLD}{header}{RESET}"))
+                .collect(),
+        );
+        for row in rows {
+            table.push_str(&line(row.clone()));
+        }
+        table
+    }
+}
+
+// The length of the text as displayed, excluding ANSI escapes
+fn visible_len(text: &str) -> usize {
+    let mut len = 0;
+    let mut escape = false;
+    for c in text.chars() {
+        match (escape, c) {
+            (false, '\x1b') => escape = true,
+            (true, 'm') => escape = false,
+            (true, _) => (),
+            (false, _) => len += 1,
+        }
+    }
+    len
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn render_formats() {
+        let render = |format: &str| {
+            let renderer = format.parse::<Format>().unwrap().renderer();
+            let rows = vec![
+                vec!["rr".to_string(), renderer.emphasis("noisy")],
+                vec![renderer.text("incast <10>"), renderer.link("report", "/r")],
+            ];
+            renderer.document(&[
+                renderer.heading("Jobs"),
+                renderer.table(&["job", "note"], &rows),
+            ])
+        };
+
+        assert_eq!(
+            render("html"),
+            "<html><body><h2>Jobs</h2><table><tr><th>job</th><th>note</th></tr>\
+            <tr><td>rr</td><td><b>noisy</b></td></tr>\
+            <tr><td>incast &lt;10&gt;</td><td><a href=\"/r\">report</a></td></tr></table></body></html>"
+        );
+        assert_eq!(
+            render("markdown"),
+            "### Jobs\n\n| job | note |\n|---|---|\n| rr | **noisy** |\n| incast <10> | [report](/r) |\n"
+        );
+        assert_eq!(
+            render("terminal"),
+            "\x1b[1mJobs\x1b[0m\n\n\
+            \x1b[1mjob\x1b[0m          \x1b[1mnote\x1b[0m\n\
+            rr           \x1b[1mnoisy\x1b[0m\n\
+            incast <10>  report (\x1b[4m/r\x1b[0m)\n"
+        );
+        assert!("pdf".parse::<Format>().is_err());
+    }
+}
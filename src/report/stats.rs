@@ -0,0 +1,216 @@
+// Copyright Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+use super::{
+    compare::JobMetrics,
+    render::{Html, Renderer},
+};
+use crate::{
+    error::{OrchError, OrchResult},
+    run_spec::ITERATION_SEPARATOR,
+};
+use std::{collections::BTreeMap, path::Path};
+use tracing::warn;
+
+// A metric is flagged as noisy if its coefficient of variation (stddev / mean)
+// across the iterations exceeds this
+const NOISY_CV: f64 = 0.1;
+
+// Two-sided 95% t-distribution critical values for 1..=30 degrees of freedom
+const T_95: [f64; 30] = [
+    12.706, 4.303, 3.182, 2.776, 2.571, 2.447, 2.365, 2.306, 2.262, 2.228, 2.201, 2.179, 2.160,
+    2.145, 2.131, 2.120, 2.110, 2.101, 2.093, 2.086, 2.080, 2.074, 2.069, 2.064, 2.060, 2.056,
+    2.052, 2.048, 2.045, 2.042,
+];
+// The normal approximation for larger samples
+const Z_95: f64 = 1.96;
+
+/// The distribution of a metric across iterations.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Summary {
+    pub iterations: usize,
+    pub mean: f64,
+    pub median: f64,
+    // Sample standard deviation
+    pub stddev: f64,
+    // Half width of the 95% confidence interval of the mean
+    pub ci_95: f64,
+}
+
+impl Summary {
+    pub fn new(values: &[f64]) -> Self {
+        let n = values.len();
+        let mean = values.iter().sum::<f64>() / n.max(1) as f64;
+        let mut sorted = values.to_vec();
+        sorted.sort_by(f64::total_cmp);
+        let median = match n {
+            0 => 0.0,
+            n if n.is_multiple_of(2) => (sorted[n / 2 - 1] + sorted[n / 2]) / 2.0,
+            n => sorted[n / 2],
+        };
+        let stddev = if n > 1 {
+            (values
+                .iter()
+                .map(|value| (value - mean).powi(2))
+                .sum::<f64>()
+                / (n - 1) as f64)
+                .sqrt()
+        } else {
+            0.0
+        };
+        let critical = T_95.get(n.saturating_sub(2)).copied().unwrap_or(Z_95);
+        let ci_95 = if n > 1 {
+            critical * stddev / (n as f64).sqrt()
+        } else {
+            0.0
+        };
+        Summary {
+            iterations: n,
+            mean,
+            median,
+            stddev,
+            ci_95,
+        }
+    }
+
+    /// The spread of the iterations is large relative to the mean.
+    pub fn is_noisy(&self) -> bool {
+        self.mean != 0.0 && self.stddev / self.mean.abs() > NOISY_CV
+    }
+}
+
+/// Summaries of each metric of a job across its iterations, keyed by the
+/// metric name.
+pub type JobSummary = BTreeMap<&'static str, Summary>;
+
+/// Group the metrics of each iteration of a job, ex:
+/// `request_response-iter2/s2n-quic/client`, and summarize them.
+///
+/// Jobs which were only run once are skipped.
+pub fn summarize_iterations(
+    metrics: &BTreeMap<String, JobMetrics>,
+) -> BTreeMap<String, JobSummary> {
+    let mut iterations: BTreeMap<String, Vec<&JobMetrics>> = BTreeMap::new();
+    for (key, job_metrics) in metrics {
+        let Some((result_key, rest)) = key.split_once('/') else {
+            continue;
+        };
+        let Some((job, iteration)) = result_key.rsplit_once(ITERATION_SEPARATOR) else {
+            continue;
+        };
+        if iteration.parse::<u32>().is_err() {
+            continue;
+        }
+        iterations
+            .entry(format!("{job}/{rest}"))
+            .or_default()
+            .push(job_metrics);
+    }
+
+    iterations
+        .into_iter()
+        .filter(|(_key, job_metrics)| job_metrics.len() > 1)
+        .map(|(key, job_metrics)| {
+            let summarize = |metric: fn(&JobMetrics) -> f64| {
+                Summary::new(&job_metrics.iter().map(|m| metric(m)).collect::<Vec<f64>>())
+            };
+            let summary = BTreeMap::from([
+                ("cpu busy %", summarize(|m| m.cpu_busy_avg)),
+                ("tx kB/s", summarize(|m| m.tx_kbps_avg)),
+                ("tcp retransmits", summarize(|m| m.tcp_retrans as f64)),
+            ]);
+            (key, summary)
+        })
+        .collect()
+}
+
+/// Render the summaries into `<dir>/report/iterations.html`.
+///
+/// Returns false if no job was run more than once.
+pub fn generate_report(dir: &Path, metrics: &BTreeMap<String, JobMetrics>) -> OrchResult<bool> {
+    let summaries = summarize_iterations(metrics);
+    if summaries.is_empty() {
+        return Ok(false);
+    }
+
+    let report_path = dir.join("report").join("iterations.html");
+    std::fs::create_dir_all(dir.join("report"))
+        .and_then(|_| std::fs::write(&report_path, iterations_html(&summaries)))
+        .map_err(|err| OrchError::Init {
+            dbg: format!("Failed to write {:?}: {}", report_path, err),
+        })?;
+    Ok(true)
+}
+
+fn iterations_html(summaries: &BTreeMap<String, JobSummary>) -> String {
+    let renderer = Html;
+    let mut rows = Vec::new();
+    for (key, summary) in summaries {
+        for (metric, summary) in summary {
+            let noisy = if summary.is_noisy() {
+                warn!("noisy {} across iterations: {}", metric, key);
+                renderer.emphasis("noisy")
+            } else {
+                String::new()
+            };
+            rows.push(vec![
+                renderer.text(key),
+                renderer.text(metric),
+                summary.iterations.to_string(),
+                format!("{:.1}", summary.mean),
+                format!("{:.1}", summary.median),
+                format!("{:.1}", summary.stddev),
+                format!("&plusmn;{:.1}", summary.ci_95),
+                noisy,
+            ]);
+        }
+    }
+    renderer.document(&[
+        renderer.heading("Iterations"),
+        renderer.table(
+            &[
+                "job", "metric", "n", "mean", "median", "stddev", "95% ci", "",
+            ],
+            &rows,
+        ),
+    ])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn iteration_summaries() {
+        let summary = Summary::new(&[10.0, 12.0, 11.0, 13.0]);
+        assert_eq!(summary.mean, 11.5);
+        assert_eq!(summary.median, 11.5);
+        assert!((summary.stddev - 1.291).abs() < 0.001, "{:?}", summary);
+        // t(3) = 3.182
+        assert!((summary.ci_95 - 2.054).abs() < 0.001, "{:?}", summary);
+        assert!(summary.is_noisy());
+        assert!(!Summary::new(&[100.0, 101.0, 99.0]).is_noisy());
+        assert_eq!(Summary::new(&[5.0]).ci_95, 0.0);
+
+        let metrics = |cpu_busy_avg| JobMetrics {
+            cpu_busy_avg,
+            tx_kbps_avg: 1000.0,
+            tcp_retrans: 0,
+        };
+        let all = BTreeMap::from([
+            ("rr-iter1/tcp/client".to_string(), metrics(50.0)),
+            ("rr-iter2/tcp/client".to_string(), metrics(52.0)),
+            ("rr-iter10/tcp/client".to_string(), metrics(51.0)),
+            // a job which was only run once
+            ("other/tcp/client".to_string(), metrics(50.0)),
+        ]);
+        let summaries = summarize_iterations(&all);
+        assert_eq!(summaries.len(), 1);
+        let summary = &summaries["rr/tcp/client"];
+        assert_eq!(summary["cpu busy %"].iterations, 3);
+        assert_eq!(summary["cpu busy %"].median, 51.0);
+        assert_eq!(summary["tx kB/s"].stddev, 0.0);
+        assert!(iterations_html(&summaries)
+            .contains("<td>rr/tcp/client</td><td>cpu busy %</td><td>3</td><td>51.0</td>"));
+    }
+}
@@ -0,0 +1,159 @@
+// Copyright Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::{
+    error::{OrchError, OrchResult},
+    russula::netbench::stream_chunk,
+};
+use std::{
+    collections::BTreeMap,
+    io::Write,
+    path::{Path, PathBuf},
+};
+use tracing::info;
+
+/// Reassemble the output which the hosts streamed to `<dir>/streams` during
+/// the run, for the files which weren't uploaded at the end of the run. ex:
+/// the host failed during a soak run.
+///
+/// The files are written where the final upload would have put them, so that
+/// the rest of the report doesn't distinguish them. Returns the number of
+/// reassembled files.
+pub fn assemble(dir: &Path) -> OrchResult<usize> {
+    let streams_dir = dir.join("streams");
+    let mut chunks = Vec::new();
+    collect_chunks(&streams_dir, &mut chunks)?;
+
+    let mut assembled = 0;
+    for (file, chunks) in streamed_files(&streams_dir, chunks) {
+        let destination = dir.join(destination(&file));
+        if destination.exists() {
+            continue;
+        }
+        info!(
+            "reassembling {:?} from {} chunks",
+            destination,
+            chunks.len()
+        );
+        concat(&chunks, &destination)?;
+        assembled += 1;
+    }
+    Ok(assembled)
+}
+
+/// The chunks of each streamed file in order, keyed by the file's path
+/// relative to `streams_dir`. ex: request_response/s2n-quic/client-i-1-s2n-quic.json
+fn streamed_files(streams_dir: &Path, chunks: Vec<PathBuf>) -> BTreeMap<PathBuf, Vec<PathBuf>> {
+    let mut files: BTreeMap<PathBuf, Vec<(u32, PathBuf)>> = BTreeMap::new();
+    for chunk in chunks {
+        let Some((file, seq)) = chunk
+            .strip_prefix(streams_dir)
+            .ok()
+            .and_then(|relative| relative.to_str())
+            .and_then(stream_chunk)
+        else {
+            continue;
+        };
+        files
+            .entry(PathBuf::from(file))
+            .or_default()
+            .push((seq, chunk));
+    }
+    files
+        .into_iter()
+        .map(|(file, mut chunks)| {
+            chunks.sort();
+            (
+                file,
+                chunks.into_iter().map(|(_seq, chunk)| chunk).collect(),
+            )
+        })
+        .collect()
+}
+
+// The folder which the file would have been uploaded to at the end of the run
+fn destination(file: &Path) -> PathBuf {
+    let folder = match file.to_string_lossy().ends_with(".sysmetrics.csv") {
+        true => "sysmetrics",
+        false => "results",
+    };
+    Path::new(folder).join(file)
+}
+
+fn concat(chunks: &[PathBuf], destination: &Path) -> OrchResult<()> {
+    let err = |err: std::io::Error| OrchError::Init {
+        dbg: format!("Failed to write {:?}: {}", destination, err),
+    };
+    if let Some(parent) = destination.parent() {
+        std::fs::create_dir_all(parent).map_err(err)?;
+    }
+    let mut file = std::fs::File::create(destination).map_err(err)?;
+    for chunk in chunks {
+        let bytes = std::fs::read(chunk).map_err(|err| OrchError::Init {
+            dbg: format!("Failed to read {:?}: {}", chunk, err),
+        })?;
+        file.write_all(&bytes).map_err(err)?;
+    }
+    Ok(())
+}
+
+fn collect_chunks(dir: &Path, chunks: &mut Vec<PathBuf>) -> OrchResult<()> {
+    if !dir.is_dir() {
+        return Ok(());
+    }
+    let entries = std::fs::read_dir(dir).map_err(|err| OrchError::Init {
+        dbg: format!("Failed to read {:?}: {}", dir, err),
+    })?;
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if path.is_dir() {
+            collect_chunks(&path, chunks)?;
+        } else {
+            chunks.push(path);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn assemble_streams() {
+        let dir = tempdir::TempDir::new("streams").unwrap();
+        let driver_dir = Path::new("request_response").join("s2n-quic");
+        for (file, content) in [
+            ("client-i-1-s2n-quic.json.00001", "second"),
+            ("client-i-1-s2n-quic.json.00000", "first "),
+            ("client-i-1-s2n-quic.sysmetrics.csv.00000", "unix_millis\n"),
+            ("server-i-2-s2n-quic.json.00000", "partial"),
+            ("not-a-chunk.json", "{}"),
+        ] {
+            let path = dir.path().join("streams").join(&driver_dir).join(file);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, content).unwrap();
+        }
+        // the server's result was uploaded at the end of the run
+        let uploaded = dir
+            .path()
+            .join("results")
+            .join(&driver_dir)
+            .join("server-i-2-s2n-quic.json");
+        std::fs::create_dir_all(uploaded.parent().unwrap()).unwrap();
+        std::fs::write(&uploaded, "final").unwrap();
+
+        assert_eq!(assemble(dir.path()).unwrap(), 2);
+        let read = |folder: &str, file: &str| {
+            std::fs::read_to_string(dir.path().join(folder).join(&driver_dir).join(file)).unwrap()
+        };
+        assert_eq!(read("results", "client-i-1-s2n-quic.json"), "first second");
+        assert_eq!(
+            read("sysmetrics", "client-i-1-s2n-quic.sysmetrics.csv"),
+            "unix_millis\n"
+        );
+        assert_eq!(read("results", "server-i-2-s2n-quic.json"), "final");
+        // already assembled
+        assert_eq!(assemble(dir.path()).unwrap(), 0);
+    }
+}
@@ -0,0 +1,474 @@
+// Copyright Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+use super::{
+    collect_files,
+    iperf3::{self, Iperf3Result},
+    latency::{merge_clients, parse_client_result, PERCENTILES},
+    render::{Html, Renderer},
+    CollectorResult,
+};
+use crate::{
+    coordination_utils::DegradedPeer,
+    error::{OrchError, OrchResult},
+    s3_utils::download_object,
+    state::STATE,
+};
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, path::Path};
+use tracing::warn;
+
+/// Bumped whenever a field is removed or its meaning changes. Fields may be
+/// added without bumping the version, so consumers should ignore unknown
+/// fields.
+pub const SUMMARY_SCHEMA_VERSION: u32 = 1;
+
+/// A machine-readable summary of a run's results, written to
+/// `report/summary.json` for CI jobs and dashboards.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct RunSummary {
+    pub schema_version: u32,
+    pub unique_id: String,
+    pub jobs: Vec<JobSummary>,
+    // The iperf3 baseline of each client and protocol, if the run included one
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub iperf3: Vec<Iperf3Result>,
+}
+
+/// The results of a driver for a job, aggregated across its hosts.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct JobSummary {
+    // The job's result key. ex: request_response-lossy-iter2
+    pub job: String,
+    // ex: s2n-quic
+    pub driver: String,
+    pub clients: usize,
+    pub servers: usize,
+    pub throughput: Throughput,
+    // Trace -> latency percentiles in microseconds, of the slowest client at
+    // each interval, averaged across the intervals
+    pub latency_us: BTreeMap<String, Percentiles>,
+    // Connections opened by the clients
+    pub connections: u64,
+    pub errors: Errors,
+}
+
+/// The bytes sent and received by all the clients, in bits per second.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Throughput {
+    pub mean_bps: f64,
+    pub max_bps: f64,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Percentiles {
+    pub p50: f64,
+    pub p90: f64,
+    pub p99: f64,
+    pub p999: f64,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Errors {
+    // Hosts excluded from the job's scenario by the russula failure policy
+    pub excluded_hosts: usize,
+    // Result files which couldn't be parsed
+    pub unparsable_results: usize,
+}
+
+/// The counters of a netbench result, per interval: (time in ns, bytes sent
+/// and received, connections opened).
+fn parse_counters(json: &str) -> Option<Vec<(f64, f64, u64)>> {
+    let result = CollectorResult::parse(json).ok()?;
+    let intervals = result
+        .intervals
+        .iter()
+        .map(|interval| {
+            let bytes: u64 = result
+                .counters(interval)
+                .filter(|(trace, _value)| matches!(*trace, "send" | "receive"))
+                .map(|(_trace, value)| value)
+                .sum();
+            (
+                interval.time as f64,
+                bytes as f64,
+                result.connections(interval),
+            )
+        })
+        .collect();
+    Some(intervals)
+}
+
+/// The throughput of each interval across the clients. Interval `i` of each
+/// client covers the same period since the drivers start together.
+fn throughput(clients: &[Vec<(f64, f64, u64)>]) -> Throughput {
+    let mut intervals: Vec<(f64, f64)> = Vec::new();
+    for client in clients {
+        for (i, (time, bytes, _connections)) in client.iter().enumerate() {
+            match intervals.get_mut(i) {
+                Some(interval) => interval.1 += bytes,
+                None => intervals.push((*time, *bytes)),
+            }
+        }
+    }
+    let mut start = 0.0;
+    let rates: Vec<f64> = intervals
+        .into_iter()
+        .filter_map(|(time, bytes)| {
+            let duration_s = (time - start) / 1e9;
+            start = time;
+            (duration_s > 0.0).then_some(bytes * 8.0 / duration_s)
+        })
+        .collect();
+    if rates.is_empty() {
+        return Throughput::default();
+    }
+    Throughput {
+        mean_bps: rates.iter().sum::<f64>() / rates.len() as f64,
+        max_bps: rates.iter().copied().fold(0.0, f64::max),
+    }
+}
+
+/// The degraded peers recorded for the scenario of a job. Peers are recorded
+/// by scenario name (ex: request_response.json) while jobs are keyed by their
+/// result key (ex: request_response-lossy-iter2).
+fn excluded_hosts(job: &str, degraded_peers: &[DegradedPeer]) -> usize {
+    degraded_peers
+        .iter()
+        .filter(|peer| {
+            let stem = peer.scenario.trim_end_matches(".json");
+            job == stem
+                || job
+                    .strip_prefix(stem)
+                    .is_some_and(|variant| variant.starts_with('-'))
+        })
+        .count()
+}
+
+#[derive(Default)]
+struct JobResults {
+    clients: Vec<String>,
+    servers: usize,
+    unparsable: usize,
+}
+
+/// Summarize the netbench results in `<dir>/results`, which are laid out as
+/// `<job>/<driver>/<host_group>-*.json`.
+pub fn summarize(
+    dir: &Path,
+    unique_id: &str,
+    degraded_peers: &[DegradedPeer],
+) -> OrchResult<RunSummary> {
+    let results_dir = dir.join("results");
+    let mut files = Vec::new();
+    collect_files(&results_dir, "json", &mut files)?;
+    files.sort();
+
+    let mut jobs: BTreeMap<(String, String), JobResults> = BTreeMap::new();
+    for path in files {
+        let Some(relative) = path.strip_prefix(&results_dir).ok() else {
+            continue;
+        };
+        let components: Vec<&str> = relative
+            .iter()
+            .filter_map(|component| component.to_str())
+            .collect();
+        let [job @ .., driver, file_name] = components.as_slice() else {
+            continue;
+        };
+        if job.is_empty() {
+            continue;
+        }
+        let results = jobs.entry((job.join("/"), driver.to_string())).or_default();
+        if file_name.starts_with("server-") {
+            results.servers += 1;
+        } else if file_name.starts_with("client-") {
+            let json = std::fs::read_to_string(&path).map_err(|err| OrchError::Init {
+                dbg: format!("Failed to read {:?}: {}", path, err),
+            })?;
+            results.clients.push(json);
+        }
+    }
+
+    let jobs = jobs
+        .into_iter()
+        .map(|((job, driver), mut results)| {
+            let mut counters = Vec::new();
+            let mut latency: BTreeMap<String, Vec<_>> = BTreeMap::new();
+            for json in results.clients.iter() {
+                match (parse_counters(json), parse_client_result(json)) {
+                    (Some(client_counters), Some(traces)) => {
+                        counters.push(client_counters);
+                        for (trace, series) in traces {
+                            latency.entry(trace).or_default().push(series);
+                        }
+                    }
+                    _ => {
+                        warn!("skipping unparsable client result of {}/{}", job, driver);
+                        results.unparsable += 1;
+                    }
+                }
+            }
+            let latency_us = latency
+                .into_iter()
+                .filter_map(|(trace, clients)| {
+                    let series = merge_clients(clients);
+                    if series.is_empty() {
+                        return None;
+                    }
+                    let mut mean = [0.0; PERCENTILES.len()];
+                    for sample in series.iter() {
+                        for (mean, value) in mean.iter_mut().zip(sample.percentiles) {
+                            *mean += value / series.len() as f64;
+                        }
+                    }
+                    let [p50, p90, p99, p999] = mean;
+                    Some((
+                        trace,
+                        Percentiles {
+                            p50,
+                            p90,
+                            p99,
+                            p999,
+                        },
+                    ))
+                })
+                .collect();
+            JobSummary {
+                clients: results.clients.len(),
+                servers: results.servers,
+                throughput: throughput(&counters),
+                latency_us,
+                connections: counters
+                    .iter()
+                    .flatten()
+                    .map(|(_time, _bytes, connections)| connections)
+                    .sum(),
+                errors: Errors {
+                    excluded_hosts: excluded_hosts(&job, degraded_peers),
+                    unparsable_results: results.unparsable,
+                },
+                job,
+                driver,
+            }
+        })
+        .collect();
+    Ok(RunSummary {
+        schema_version: SUMMARY_SCHEMA_VERSION,
+        unique_id: unique_id.to_string(),
+        jobs,
+        iperf3: iperf3::collect(dir)?,
+    })
+}
+
+/// Render the jobs of the summary, and the latency of their traces.
+pub fn render(renderer: &dyn Renderer, summary: &RunSummary) -> String {
+    let mbps = |bps: f64| format!("{:.1}", bps / 1e6);
+    let jobs: Vec<Vec<String>> = summary
+        .jobs
+        .iter()
+        .map(|job| {
+            let errors = job.errors.excluded_hosts + job.errors.unparsable_results;
+            vec![
+                renderer.text(&job.job),
+                renderer.text(&job.driver),
+                format!("{}/{}", job.clients, job.servers),
+                mbps(job.throughput.mean_bps),
+                mbps(job.throughput.max_bps),
+                job.connections.to_string(),
+                match errors {
+                    0 => String::new(),
+                    errors => renderer.emphasis(&errors.to_string()),
+                },
+            ]
+        })
+        .collect();
+    let latency: Vec<Vec<String>> = summary
+        .jobs
+        .iter()
+        .flat_map(|job| {
+            job.latency_us.iter().map(|(trace, percentiles)| {
+                vec![
+                    renderer.text(&job.job),
+                    renderer.text(&job.driver),
+                    renderer.text(trace),
+                    format!("{:.1}", percentiles.p50),
+                    format!("{:.1}", percentiles.p90),
+                    format!("{:.1}", percentiles.p99),
+                    format!("{:.1}", percentiles.p999),
+                ]
+            })
+        })
+        .collect();
+
+    let mut blocks = vec![renderer.heading(&format!("Summary of {}", summary.unique_id))];
+    if jobs.is_empty() {
+        blocks.push(renderer.paragraph("No results were collected"));
+        return renderer.document(&blocks);
+    }
+    blocks.push(renderer.table(
+        &[
+            "job",
+            "driver",
+            "clients/servers",
+            "mean Mbps",
+            "max Mbps",
+            "connections",
+            "errors",
+        ],
+        &jobs,
+    ));
+    if !latency.is_empty() {
+        blocks.push(renderer.heading("Latency (us)"));
+        blocks.push(renderer.table(
+            &["job", "driver", "trace", "p50", "p90", "p99", "p99.9"],
+            &latency,
+        ));
+    }
+    renderer.document(&blocks)
+}
+
+/// Download the `report/summary.json` of a run.
+pub async fn download(s3_client: &aws_sdk_s3::Client, unique_id: &str) -> OrchResult<RunSummary> {
+    let key = format!("{unique_id}/report/summary.json");
+    let object = download_object(s3_client, STATE.s3_log_bucket, &key)
+        .await
+        .map_err(|err| OrchError::S3 {
+            dbg: format!("Failed to download {}: {}", key, err),
+        })?;
+    let body = object.body.collect().await.map_err(|err| OrchError::S3 {
+        dbg: format!("Failed to read {}: {}", key, err),
+    })?;
+    let summary: RunSummary =
+        serde_json::from_slice(&body.into_bytes()).map_err(|err| OrchError::Init {
+            dbg: format!("Failed to parse {}: {}", key, err),
+        })?;
+    if summary.schema_version != SUMMARY_SCHEMA_VERSION {
+        return Err(OrchError::Init {
+            dbg: format!(
+                "Unsupported summary schema_version {}",
+                summary.schema_version
+            ),
+        });
+    }
+    Ok(summary)
+}
+
+/// Write the summary of the results to `<dir>/report/summary.json`, and
+/// rendered to `<dir>/report/summary.html`.
+pub fn generate_report(
+    dir: &Path,
+    unique_id: &str,
+    degraded_peers: &[DegradedPeer],
+) -> OrchResult<()> {
+    let summary = summarize(dir, unique_id, degraded_peers)?;
+    let json_path = dir.join("report").join("summary.json");
+    let html_path = dir.join("report").join("summary.html");
+    std::fs::create_dir_all(dir.join("report"))
+        .and_then(|_| {
+            std::fs::write(
+                &json_path,
+                serde_json::to_string_pretty(&summary).expect("summary serializes"),
+            )
+        })
+        .and_then(|_| std::fs::write(&html_path, render(&Html, &summary)))
+        .map_err(|err| OrchError::Init {
+            dbg: format!("Failed to write {:?}: {}", dir.join("report"), err),
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn run_summary() {
+        let result = |bytes: u64| {
+            json!({
+                "traces": ["send", "receive", "request", "connect"],
+                "intervals": [
+                    { "time": 1_000_000_000u64, "counters": { "0": bytes, "1": bytes, "3": 2 }, "profiles": { "2": { "p50": 1000, "p90": 2000, "p99": 3000, "p999": 4000 } } },
+                    { "time": 2_000_000_000u64, "counters": { "0": bytes * 2 }, "profiles": { "2": { "p50": 3000, "p90": 4000, "p99": 5000, "p999": 6000 } } },
+                ],
+            })
+            .to_string()
+        };
+        let dir = tempdir::TempDir::new("summary").unwrap();
+        let driver_dir = dir
+            .path()
+            .join("results/request_response-lossy-iter2/s2n-quic");
+        std::fs::create_dir_all(&driver_dir).unwrap();
+        std::fs::write(driver_dir.join("client-i-1-s2n-quic.json"), result(1000)).unwrap();
+        std::fs::write(driver_dir.join("client-i-2-s2n-quic.json"), result(500)).unwrap();
+        std::fs::write(driver_dir.join("client-i-3-s2n-quic.json"), "{}").unwrap();
+        std::fs::write(driver_dir.join("server-i-4-s2n-quic.json"), "{}").unwrap();
+
+        let peer = |scenario: &str| DegradedPeer {
+            host_group: "client".to_string(),
+            endpoint: "127.0.0.1:9001".parse().unwrap(),
+            scenario: scenario.to_string(),
+            dbg: "unreachable".to_string(),
+        };
+        let peers = [
+            peer("request_response.json"),
+            peer("request_response_incast.json"),
+        ];
+        generate_report(dir.path(), "run-1", &peers).unwrap();
+        let summary: RunSummary = serde_json::from_str(
+            &std::fs::read_to_string(dir.path().join("report/summary.json")).unwrap(),
+        )
+        .unwrap();
+        assert_eq!(summary.schema_version, SUMMARY_SCHEMA_VERSION);
+        assert!(summary.iperf3.is_empty());
+        assert_eq!(summary.jobs.len(), 1);
+        let job = &summary.jobs[0];
+        assert_eq!(job.job, "request_response-lossy-iter2");
+        assert_eq!(job.driver, "s2n-quic");
+        assert_eq!((job.clients, job.servers), (3, 1));
+        // (1000 + 1000 + 500 + 500) bytes in the first second, 3000 in the next
+        assert_eq!(
+            job.throughput,
+            Throughput {
+                mean_bps: 24_000.0,
+                max_bps: 24_000.0,
+            }
+        );
+        assert_eq!(job.connections, 4);
+        // the slowest client at each interval
+        assert_eq!(
+            job.latency_us["request"],
+            Percentiles {
+                p50: 2.0,
+                p90: 3.0,
+                p99: 4.0,
+                p999: 5.0,
+            }
+        );
+        assert_eq!(
+            job.errors,
+            Errors {
+                excluded_hosts: 1,
+                unparsable_results: 1,
+            }
+        );
+
+        let markdown = render(&crate::report::render::Markdown, &summary);
+        assert!(
+            markdown.contains(
+                "| request_response-lossy-iter2 | s2n-quic | 3/1 | 0.0 | 0.0 | 4 | **2** |"
+            ),
+            "{}",
+            markdown
+        );
+        assert!(
+            markdown.contains(
+                "| request_response-lossy-iter2 | s2n-quic | request | 2.0 | 3.0 | 4.0 | 5.0 |"
+            ),
+            "{}",
+            markdown
+        );
+        let html = std::fs::read_to_string(dir.path().join("report/summary.html")).unwrap();
+        assert!(html.contains("<td>3/1</td>"), "{}", html);
+    }
+}
@@ -0,0 +1,208 @@
+// Copyright Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+use super::collect_files;
+use crate::error::{OrchError, OrchResult};
+use std::path::Path;
+use tracing::{debug, warn};
+
+// Hosts whose cpu is busier than this are flagged as saturated
+const CPU_SATURATED_PCT: f64 = 90.0;
+
+/// A single sample written by `scripts/sys_metrics.sh`.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Sample {
+    pub unix_millis: u64,
+    pub cpu_user: f64,
+    pub cpu_system: f64,
+    pub cpu_idle: f64,
+    pub cpu_iowait: f64,
+    pub mem_free_kb: u64,
+    pub tcp_estab: u64,
+    // Cumulative since boot
+    pub tcp_retrans_segs: u64,
+    pub rx_kbps: f64,
+    pub tx_kbps: f64,
+}
+
+impl Sample {
+    fn parse(line: &str) -> Option<Self> {
+        let fields: Vec<&str> = line.trim().split(',').collect();
+        match fields.as_slice() {
+            [unix_millis, cpu_user, cpu_system, cpu_idle, cpu_iowait, mem_free_kb, tcp_estab, tcp_retrans_segs, rx_kbps, tx_kbps] => {
+                Some(Sample {
+                    unix_millis: unix_millis.parse().ok()?,
+                    cpu_user: cpu_user.parse().ok()?,
+                    cpu_system: cpu_system.parse().ok()?,
+                    cpu_idle: cpu_idle.parse().ok()?,
+                    cpu_iowait: cpu_iowait.parse().ok()?,
+                    mem_free_kb: mem_free_kb.parse().ok()?,
+                    tcp_estab: tcp_estab.parse().ok()?,
+                    tcp_retrans_segs: tcp_retrans_segs.parse().ok()?,
+                    rx_kbps: rx_kbps.parse().ok()?,
+                    tx_kbps: tx_kbps.parse().ok()?,
+                })
+            }
+            _ => None,
+        }
+    }
+
+    fn cpu_busy(&self) -> f64 {
+        100.0 - self.cpu_idle
+    }
+}
+
+/// Parse the samples of a sys metrics csv, skipping the header and any partially
+/// written lines.
+pub fn parse_samples(csv: &str) -> Vec<Sample> {
+    csv.lines().skip(1).filter_map(Sample::parse).collect()
+}
+
+/// The system metrics of a single host for a netbench run.
+///
+/// The sidecar is started and stopped along with netbench so the samples are
+/// aligned to the netbench intervals by their offset from the first sample.
+#[derive(Debug)]
+pub struct HostMetrics {
+    // ex: sysmetrics/request_response/s2n-quic/client-i-0123-s2n-quic.sysmetrics.csv
+    pub name: String,
+    pub samples: Vec<Sample>,
+}
+
+impl HostMetrics {
+    pub fn cpu_busy_avg(&self) -> f64 {
+        if self.samples.is_empty() {
+            return 0.0;
+        }
+        self.samples.iter().map(Sample::cpu_busy).sum::<f64>() / self.samples.len() as f64
+    }
+
+    pub fn cpu_busy_max(&self) -> f64 {
+        self.samples
+            .iter()
+            .map(Sample::cpu_busy)
+            .fold(0.0, f64::max)
+    }
+
+    pub fn is_cpu_saturated(&self) -> bool {
+        self.cpu_busy_max() >= CPU_SATURATED_PCT
+    }
+
+    /// Segments retransmitted during the run.
+    pub fn tcp_retrans(&self) -> u64 {
+        match (self.samples.first(), self.samples.last()) {
+            (Some(first), Some(last)) => {
+                last.tcp_retrans_segs.saturating_sub(first.tcp_retrans_segs)
+            }
+            _ => 0,
+        }
+    }
+
+    fn to_html(&self) -> String {
+        let start = self.samples.first().map_or(0, |sample| sample.unix_millis);
+        let mut html = format!(
+            "<h3>{}</h3><p>cpu busy avg: {:.1}% max: {:.1}%{} | tcp retransmits: {}</p>",
+            self.name,
+            self.cpu_busy_avg(),
+            self.cpu_busy_max(),
+            if self.is_cpu_saturated() {
+                " (saturated)"
+            } else {
+                ""
+            },
+            self.tcp_retrans()
+        );
+        html.push_str(
+            "<table><tr><th>interval (s)</th><th>cpu busy %</th><th>cpu iowait %</th>\
+             <th>mem free kB</th><th>tcp estab</th><th>tcp retrans</th>\
+             <th>rx kB/s</th><th>tx kB/s</th></tr>",
+        );
+        let mut prev_retrans = self
+            .samples
+            .first()
+            .map_or(0, |sample| sample.tcp_retrans_segs);
+        for sample in self.samples.iter() {
+            html.push_str(&format!(
+                "<tr><td>{:.1}</td><td>{:.1}</td><td>{:.1}</td><td>{}</td><td>{}</td><td>{}</td><td>{:.1}</td><td>{:.1}</td></tr>",
+                sample.unix_millis.saturating_sub(start) as f64 / 1000.0,
+                sample.cpu_busy(),
+                sample.cpu_iowait,
+                sample.mem_free_kb,
+                sample.tcp_estab,
+                sample.tcp_retrans_segs.saturating_sub(prev_retrans),
+                sample.rx_kbps,
+                sample.tx_kbps,
+            ));
+            prev_retrans = sample.tcp_retrans_segs;
+        }
+        html.push_str("</table>");
+        html
+    }
+}
+
+/// Render the system metrics downloaded to `<dir>/sysmetrics` into
+/// `<dir>/report/sysmetrics.html`.
+///
+/// Returns false if there were no system metrics to report.
+pub fn generate_report(dir: &Path) -> OrchResult<bool> {
+    let sys_metrics_dir = dir.join("sysmetrics");
+    let mut csv_files = Vec::new();
+    collect_files(&sys_metrics_dir, "csv", &mut csv_files)?;
+    csv_files.sort();
+    if csv_files.is_empty() {
+        debug!("no system metrics found in {:?}", sys_metrics_dir);
+        return Ok(false);
+    }
+
+    let mut html = String::from("<html><body><h2>System metrics</h2>");
+    for path in csv_files {
+        let csv = std::fs::read_to_string(&path).map_err(|err| OrchError::Init {
+            dbg: format!("Failed to read {:?}: {}", path, err),
+        })?;
+        let host = HostMetrics {
+            name: path
+                .strip_prefix(dir)
+                .unwrap_or(&path)
+                .display()
+                .to_string(),
+            samples: parse_samples(&csv),
+        };
+        if host.is_cpu_saturated() {
+            warn!("cpu saturated during run: {}", host.name);
+        }
+        html.push_str(&host.to_html());
+    }
+    html.push_str("</body></html>");
+
+    let report_path = dir.join("report").join("sysmetrics.html");
+    std::fs::create_dir_all(dir.join("report"))
+        .and_then(|_| std::fs::write(&report_path, html))
+        .map_err(|err| OrchError::Init {
+            dbg: format!("Failed to write {:?}: {}", report_path, err),
+        })?;
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_sys_metrics() {
+        let csv = "unix_millis,cpu_user,cpu_system,cpu_idle,cpu_iowait,mem_free_kb,tcp_estab,tcp_retrans_segs,rx_kbps,tx_kbps
+1700000000000,50,10,40,0,1000,5,100,10.5,20.0
+1700000001000,80,15,5,0,900,5,130,11.0,21.0
+1700000002000,80,15";
+        let host = HostMetrics {
+            name: "client".to_string(),
+            samples: parse_samples(csv),
+        };
+
+        // the partially written line is skipped
+        assert_eq!(host.samples.len(), 2);
+        assert_eq!(host.cpu_busy_max(), 95.0);
+        assert_eq!(host.cpu_busy_avg(), 77.5);
+        assert!(host.is_cpu_saturated());
+        assert_eq!(host.tcp_retrans(), 30);
+    }
+}
@@ -0,0 +1,333 @@
+// Copyright Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+use super::{
+    escape_html,
+    summary::{self, RunSummary},
+};
+use crate::{
+    config::Trend,
+    error::{OrchError, OrchResult},
+    run_spec::ITERATION_SEPARATOR,
+    ssm_utils::build_info::BuildInfo,
+};
+use aws_sdk_dynamodb::types::AttributeValue;
+use serde_json::{json, Value};
+use std::{
+    collections::{BTreeMap, HashMap},
+    time::SystemTime,
+};
+use tracing::info;
+
+/// The metrics of a job and driver in a run, stored as an item of the trend
+/// table.
+///
+/// The table's partition key is `series`
+/// (`<scenario>#<driver>#<instance_type>`) and its sort key is `run`
+/// (`<date>#<unique_id>`), so the runs of a series are ordered by date.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TrendRecord {
+    // The job's result key without the iteration. ex: request_response-lossy
+    pub scenario: String,
+    pub driver: String,
+    pub instance_type: String,
+    // The commit of the client driver, or `unknown`
+    pub git_sha: String,
+    // rfc3339. ex: 2023-10-11T17:05:09Z
+    pub date: String,
+    // The unique_id of the run, with the iteration of the job if any
+    pub run_id: String,
+    pub metrics: BTreeMap<String, f64>,
+}
+
+impl TrendRecord {
+    pub fn series(scenario: &str, driver: &str, instance_type: &str) -> String {
+        format!("{scenario}#{driver}#{instance_type}")
+    }
+
+    /// The records of each job and driver in the summary of a run.
+    pub fn from_summary(
+        summary: &RunSummary,
+        instance_type: &str,
+        builds: &[BuildInfo],
+        date: &str,
+    ) -> Vec<Self> {
+        summary
+            .jobs
+            .iter()
+            .map(|job| {
+                let (scenario, run_id) = match job.job.rsplit_once(ITERATION_SEPARATOR) {
+                    Some((scenario, iteration)) if iteration.parse::<u32>().is_ok() => (
+                        scenario.to_string(),
+                        format!("{}{ITERATION_SEPARATOR}{iteration}", summary.unique_id),
+                    ),
+                    _ => (job.job.clone(), summary.unique_id.clone()),
+                };
+                let git_sha = builds
+                    .iter()
+                    .find(|build| build.host_group == "client" && build.driver == job.driver)
+                    .map_or("unknown", |build| build.git_sha.as_str());
+
+                let mut metrics = BTreeMap::from([
+                    ("throughput_mean_bps".to_string(), job.throughput.mean_bps),
+                    ("throughput_max_bps".to_string(), job.throughput.max_bps),
+                    ("connections".to_string(), job.connections as f64),
+                ]);
+                for (trace, latency) in job.latency_us.iter() {
+                    for (percentile, value) in [
+                        ("p50", latency.p50),
+                        ("p90", latency.p90),
+                        ("p99", latency.p99),
+                        ("p999", latency.p999),
+                    ] {
+                        metrics.insert(format!("{trace}_{percentile}_us"), value);
+                    }
+                }
+                TrendRecord {
+                    scenario,
+                    driver: job.driver.clone(),
+                    instance_type: instance_type.to_string(),
+                    git_sha: git_sha.to_string(),
+                    date: date.to_string(),
+                    run_id,
+                    metrics,
+                }
+            })
+            .collect()
+    }
+
+    fn to_item(&self) -> HashMap<String, AttributeValue> {
+        let metrics = self
+            .metrics
+            .iter()
+            .map(|(name, value)| (name.clone(), AttributeValue::N(value.to_string())))
+            .collect();
+        HashMap::from([
+            (
+                "series".to_string(),
+                AttributeValue::S(Self::series(
+                    &self.scenario,
+                    &self.driver,
+                    &self.instance_type,
+                )),
+            ),
+            (
+                "run".to_string(),
+                AttributeValue::S(format!("{}#{}", self.date, self.run_id)),
+            ),
+            (
+                "scenario".to_string(),
+                AttributeValue::S(self.scenario.clone()),
+            ),
+            ("driver".to_string(), AttributeValue::S(self.driver.clone())),
+            (
+                "instance_type".to_string(),
+                AttributeValue::S(self.instance_type.clone()),
+            ),
+            (
+                "git_sha".to_string(),
+                AttributeValue::S(self.git_sha.clone()),
+            ),
+            ("date".to_string(), AttributeValue::S(self.date.clone())),
+            ("run_id".to_string(), AttributeValue::S(self.run_id.clone())),
+            ("metrics".to_string(), AttributeValue::M(metrics)),
+        ])
+    }
+
+    fn from_item(item: &HashMap<String, AttributeValue>) -> Option<Self> {
+        let string = |name: &str| item.get(name)?.as_s().ok().cloned();
+        let metrics = item
+            .get("metrics")?
+            .as_m()
+            .ok()?
+            .iter()
+            .filter_map(|(name, value)| Some((name.clone(), value.as_n().ok()?.parse().ok()?)))
+            .collect();
+        Some(TrendRecord {
+            scenario: string("scenario")?,
+            driver: string("driver")?,
+            instance_type: string("instance_type")?,
+            git_sha: string("git_sha")?,
+            date: string("date")?,
+            run_id: string("run_id")?,
+            metrics,
+        })
+    }
+}
+
+/// Write the metrics of a run's `report/summary.json` to the trend table.
+pub async fn record(
+    s3_client: &aws_sdk_s3::Client,
+    dynamodb_client: &aws_sdk_dynamodb::Client,
+    trend: &Trend,
+    unique_id: &str,
+    instance_type: &str,
+    builds: &[BuildInfo],
+) -> OrchResult<()> {
+    let summary = summary::download(s3_client, unique_id).await?;
+    let date = humantime::format_rfc3339_seconds(SystemTime::now()).to_string();
+    let records = TrendRecord::from_summary(&summary, instance_type, builds, &date);
+    for record in records.iter() {
+        dynamodb_client
+            .put_item()
+            .table_name(&trend.table)
+            .set_item(Some(record.to_item()))
+            .send()
+            .await
+            .map_err(|err| OrchError::DynamoDb {
+                dbg: format!("Failed to write the trend to {}: {}", trend.table, err),
+            })?;
+    }
+    info!(
+        "Recorded {} trend records in {}",
+        records.len(),
+        trend.table
+    );
+    Ok(())
+}
+
+/// The last `last` records of a series, oldest first.
+pub async fn query(
+    dynamodb_client: &aws_sdk_dynamodb::Client,
+    table: &str,
+    series: &str,
+    last: usize,
+) -> OrchResult<Vec<TrendRecord>> {
+    let output = dynamodb_client
+        .query()
+        .table_name(table)
+        .key_condition_expression("series = :series")
+        .expression_attribute_values(":series", AttributeValue::S(series.to_string()))
+        .scan_index_forward(false)
+        .limit(last.try_into().unwrap_or(i32::MAX))
+        .send()
+        .await
+        .map_err(|err| OrchError::DynamoDb {
+            dbg: format!("Failed to query {} in {}: {}", series, table, err),
+        })?;
+    let mut records: Vec<TrendRecord> = output
+        .items()
+        .unwrap_or_default()
+        .iter()
+        .filter_map(TrendRecord::from_item)
+        .collect();
+    records.reverse();
+    Ok(records)
+}
+
+/// A vega-lite line chart of a metric over the runs, labelled by commit.
+pub fn trend_spec(series: &str, metric: &str, records: &[TrendRecord]) -> Value {
+    let values: Vec<Value> = records
+        .iter()
+        .filter_map(|record| {
+            Some(json!({
+                "date": record.date,
+                "value": record.metrics.get(metric)?,
+                "git_sha": record.git_sha,
+                "run_id": record.run_id,
+            }))
+        })
+        .collect();
+    json!({
+        "$schema": "https://vega.github.io/schema/vega-lite/v5.json",
+        "title": format!("{series}: {metric}"),
+        "width": 800,
+        "data": { "values": values },
+        "mark": { "type": "line", "point": true },
+        "encoding": {
+            "x": { "field": "date", "type": "temporal", "title": "run date" },
+            "y": { "field": "value", "type": "quantitative", "title": metric },
+            "tooltip": [
+                { "field": "run_id", "type": "nominal" },
+                { "field": "git_sha", "type": "nominal" },
+                { "field": "value", "type": "quantitative" },
+            ],
+        },
+    })
+}
+
+pub fn trend_html(series: &str, spec: &Value) -> String {
+    format!(
+        "<html><head>\
+        <script src=\"https://cdn.jsdelivr.net/npm/vega@5\"></script>\
+        <script src=\"https://cdn.jsdelivr.net/npm/vega-lite@5\"></script>\
+        <script src=\"https://cdn.jsdelivr.net/npm/vega-embed@6\"></script>\
+        </head><body><h2>{}</h2><div id=\"chart\"></div>\
+        <script>vegaEmbed('#chart', {});</script></body></html>",
+        escape_html(series),
+        // keep the spec from closing the script tag
+        spec.to_string().replace("</", "<\\/")
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::report::summary::{
+        Errors, JobSummary, Percentiles, Throughput, SUMMARY_SCHEMA_VERSION,
+    };
+
+    #[test]
+    fn trend_records() {
+        let job = |job: &str| JobSummary {
+            job: job.to_string(),
+            driver: "s2n-quic".to_string(),
+            clients: 1,
+            servers: 1,
+            throughput: Throughput {
+                mean_bps: 100.0,
+                max_bps: 200.0,
+            },
+            latency_us: BTreeMap::from([(
+                "request".to_string(),
+                Percentiles {
+                    p50: 1.0,
+                    p90: 2.0,
+                    p99: 3.0,
+                    p999: 4.0,
+                },
+            )]),
+            connections: 2,
+            errors: Errors::default(),
+        };
+        let summary = RunSummary {
+            schema_version: SUMMARY_SCHEMA_VERSION,
+            unique_id: "run-1".to_string(),
+            jobs: vec![job("request_response"), job("request_response-lossy-iter2")],
+            iperf3: vec![],
+        };
+        let builds = [BuildInfo::parse(
+            "client",
+            "s2n-quic",
+            "git_remote=https://github.com/aws/s2n-quic\ngit_sha=abc123\n",
+        )];
+        let records =
+            TrendRecord::from_summary(&summary, "c5.4xlarge", &builds, "2023-10-11T17:05:09Z");
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0].scenario, "request_response");
+        assert_eq!(records[0].run_id, "run-1");
+        assert_eq!(records[0].git_sha, "abc123");
+        assert_eq!(records[0].metrics["request_p99_us"], 3.0);
+        assert_eq!(records[0].metrics["connections"], 2.0);
+        assert_eq!(records[1].scenario, "request_response-lossy");
+        assert_eq!(records[1].run_id, "run-1-iter2");
+
+        let item = records[1].to_item();
+        assert_eq!(
+            item["series"].as_s().unwrap(),
+            "request_response-lossy#s2n-quic#c5.4xlarge"
+        );
+        assert_eq!(
+            item["run"].as_s().unwrap(),
+            "2023-10-11T17:05:09Z#run-1-iter2"
+        );
+        assert_eq!(TrendRecord::from_item(&item).unwrap(), records[1]);
+
+        let spec = trend_spec("series", "throughput_mean_bps", &records);
+        assert_eq!(spec["data"]["values"].as_array().unwrap().len(), 2);
+        assert!(trend_spec("series", "missing", &records)["data"]["values"]
+            .as_array()
+            .unwrap()
+            .is_empty());
+    }
+}
@@ -0,0 +1,419 @@
+// Copyright Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::{
+    config::{Impairment, OrchestratorConfig},
+    ec2_utils::LinuxDistro,
+    error::{OrchError, OrchResult},
+    s3_utils::upload_object,
+    Scenario, STATE,
+};
+use aws_sdk_s3::primitives::ByteStream;
+use bytes::Bytes;
+use core::str::FromStr;
+use serde::Deserialize;
+use std::{collections::BTreeMap, fs::File, path::Path, path::PathBuf};
+
+/// A matrix of benchmark jobs, loaded from a json file with `--run-spec`.
+///
+/// A job is run for each combination of driver, scenario, instance type, Linux
+/// distribution and impairment. Jobs on the same instance type and
+/// distribution share the hosts.
+/// ```json
+/// {
+///   "drivers": ["s2n-quic", "tcp"],
+///   "scenarios": ["scripts/request_response.json"],
+///   "instance_types": ["c5.4xlarge", "c5n.4xlarge"],
+///   "distros": ["al2023", "ubuntu"],
+///   "impairments": { "baseline": {}, "lossy": { "loss_pct": 1 } }
+/// }
+/// ```
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RunSpec {
+    pub drivers: Vec<String>,
+    // Scenario files or directories of scenario files
+    pub scenarios: Vec<PathBuf>,
+    #[serde(default = "default_instance_types")]
+    pub instance_types: Vec<String>,
+    // Defaults to the distro in the orchestrator config
+    #[serde(default)]
+    pub distros: Vec<LinuxDistro>,
+    // Name -> impairment. Defaults to the impairments in the orchestrator config
+    #[serde(default)]
+    pub impairments: BTreeMap<String, Impairment>,
+}
+
+fn default_instance_types() -> Vec<String> {
+    vec![STATE.instance_type.to_string()]
+}
+
+impl RunSpec {
+    pub fn load(path: &Path) -> OrchResult<Self> {
+        let file = File::open(path).map_err(|err| OrchError::Init {
+            dbg: format!("Failed to open run spec {:?}: {}", path, err),
+        })?;
+        let spec: RunSpec = serde_json::from_reader(file).map_err(|err| OrchError::Init {
+            dbg: format!("Failed to parse run spec {:?}: {}", path, err),
+        })?;
+        spec.validate(&format!("{:?}", path))
+    }
+
+    /// Parse a run spec received as json, ex: from a queue.
+    pub fn parse(json: &str, source: &str) -> OrchResult<Self> {
+        let spec: RunSpec = serde_json::from_str(json).map_err(|err| OrchError::Init {
+            dbg: format!("Failed to parse run spec {}: {}", source, err),
+        })?;
+        spec.validate(source)
+    }
+
+    fn validate(self, source: &str) -> OrchResult<Self> {
+        if self.drivers.is_empty() || self.scenarios.is_empty() || self.instance_types.is_empty() {
+            return Err(OrchError::Init {
+                dbg: format!(
+                    "Run spec {} needs at least one driver, scenario and instance type",
+                    source
+                ),
+            });
+        }
+        for driver in self.drivers.iter() {
+            Driver::from_str(driver).map_err(|dbg| OrchError::Init { dbg })?;
+        }
+        Ok(self)
+    }
+
+    /// Expand the matrix into a group of jobs per instance type and
+    /// distribution.
+    pub fn groups(&self, scenarios: &[Scenario], config: &OrchestratorConfig) -> Vec<RunGroup> {
+        let drivers: Vec<Driver> = self
+            .drivers
+            .iter()
+            .filter_map(|driver| driver.parse().ok())
+            .collect();
+        let distros = match self.distros.is_empty() {
+            true => vec![config.distro],
+            false => self.distros.clone(),
+        };
+        self.instance_types
+            .iter()
+            .flat_map(|instance_type| distros.iter().map(move |distro| (instance_type, *distro)))
+            .map(|(instance_type, distro)| {
+                let mut jobs = Vec::new();
+                for scenario in scenarios {
+                    for driver in drivers.iter() {
+                        if self.impairments.is_empty() {
+                            jobs.push(Job::new(scenario, *driver, config));
+                        }
+                        for (name, impairment) in self.impairments.iter() {
+                            jobs.push(Job {
+                                scenario: scenario.clone(),
+                                driver: *driver,
+                                impairment: Some(impairment.clone())
+                                    .filter(|impairment| *impairment != Impairment::default()),
+                                variant: Some(name.clone()),
+                                iteration: None,
+                            });
+                        }
+                    }
+                }
+                RunGroup {
+                    instance_type: instance_type.clone(),
+                    distro,
+                    scenarios: scenarios.to_vec(),
+                    jobs,
+                }
+            })
+            .collect()
+    }
+}
+
+/// Separates the iteration from the rest of a job's result key.
+pub const ITERATION_SEPARATOR: &str = "-iter";
+
+/// A pair of netbench server and client drivers.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Driver {
+    S2nQuic,
+    S2nQuicDc,
+    Tcp,
+}
+
+impl Driver {
+    pub fn as_str(&self) -> &str {
+        match self {
+            Driver::S2nQuic => "s2n-quic",
+            Driver::S2nQuicDc => "s2n-quic-dc",
+            Driver::Tcp => "tcp",
+        }
+    }
+}
+
+impl std::fmt::Display for Driver {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+impl FromStr for Driver {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "s2n-quic" => Ok(Driver::S2nQuic),
+            "s2n-quic-dc" => Ok(Driver::S2nQuicDc),
+            "tcp" => Ok(Driver::Tcp),
+            _ => Err(format!(
+                "unsupported driver: {}. expected s2n-quic, s2n-quic-dc or tcp",
+                s
+            )),
+        }
+    }
+}
+
+/// A scenario run with a driver under an impairment.
+#[derive(Clone, Debug)]
+pub struct Job {
+    pub scenario: Scenario,
+    pub driver: Driver,
+    pub impairment: Option<Impairment>,
+    // The name of the run spec impairment, which distinguishes the results
+    pub variant: Option<String>,
+    // The repetition of the job when it's run multiple times, starting at 1
+    pub iteration: Option<u32>,
+}
+
+impl Job {
+    /// A job with the impairment from the orchestrator config.
+    pub fn new(scenario: &Scenario, driver: Driver, config: &OrchestratorConfig) -> Self {
+        Job {
+            scenario: scenario.clone(),
+            driver,
+            impairment: config.impairment(scenario).cloned(),
+            variant: None,
+            iteration: None,
+        }
+    }
+
+    /// The s3 folder of the job's results, under `results/`.
+    ///
+    /// ex: request_response, request_response-lossy, request_response-lossy-iter2
+    pub fn result_key(&self) -> String {
+        let mut result_key = self.scenario.file_stem().to_string();
+        if let Some(variant) = &self.variant {
+            result_key.push_str(&format!("-{variant}"));
+        }
+        if let Some(iteration) = self.iteration {
+            result_key.push_str(&format!("{ITERATION_SEPARATOR}{iteration}"));
+        }
+        result_key
+    }
+}
+
+/// Jobs which share the hosts of an instance type and distribution.
+#[derive(Clone, Debug)]
+pub struct RunGroup {
+    pub instance_type: String,
+    pub distro: LinuxDistro,
+    pub scenarios: Vec<Scenario>,
+    pub jobs: Vec<Job>,
+}
+
+impl RunGroup {
+    /// The jobs of a run without a run spec: each scenario with the tcp driver.
+    pub fn new(scenarios: Vec<Scenario>, config: &OrchestratorConfig) -> Self {
+        RunGroup {
+            instance_type: STATE.instance_type.to_string(),
+            distro: config.distro,
+            jobs: scenarios
+                .iter()
+                .map(|scenario| Job::new(scenario, Driver::Tcp, config))
+                .collect(),
+            scenarios,
+        }
+    }
+
+    /// Repeat the jobs `iterations` times on the same hosts. Each iteration's
+    /// results are stored separately.
+    pub fn with_iterations(mut self, iterations: u32) -> Self {
+        if iterations <= 1 {
+            return self;
+        }
+        self.jobs = (1..=iterations)
+            .flat_map(|iteration| {
+                self.jobs.iter().cloned().map(move |job| Job {
+                    iteration: Some(iteration),
+                    ..job
+                })
+            })
+            .collect();
+        self
+    }
+
+    /// The unique_id of the group's run within a matrix run.
+    ///
+    /// ex: run-c5.4xlarge, or run-c5.4xlarge-ubuntu on another distribution
+    /// than the default
+    pub fn unique_id(&self, unique_id: &str) -> String {
+        match self.distro {
+            LinuxDistro::Al2023 => format!("{unique_id}-{}", self.instance_type),
+            distro => format!("{unique_id}-{}-{distro}", self.instance_type),
+        }
+    }
+}
+
+/// Upload a report linking the report of each group at `<unique_id>/matrix.html`.
+///
+/// `outcomes` is the error of each group, if it failed.
+pub async fn upload_matrix_report(
+    s3_client: &aws_sdk_s3::Client,
+    unique_id: &str,
+    groups: &[RunGroup],
+    outcomes: &[Option<String>],
+) -> OrchResult<()> {
+    let html = matrix_report_html(unique_id, groups, outcomes);
+    upload_object(
+        s3_client,
+        STATE.s3_log_bucket,
+        ByteStream::from(Bytes::from(html)),
+        &format!("{unique_id}/matrix.html"),
+    )
+    .await
+    .map_err(|err| OrchError::S3 {
+        dbg: format!("Failed to upload the matrix report: {}", err),
+    })?;
+    println!("Matrix report: {}/matrix.html", STATE.cf_url(unique_id));
+    Ok(())
+}
+
+fn matrix_report_html(unique_id: &str, groups: &[RunGroup], outcomes: &[Option<String>]) -> String {
+    let mut html = format!(
+        "<html><body><h2>{unique_id}</h2><table><tr><th>instance type</th><th>distro</th><th>scenario</th><th>driver</th><th>impairment</th><th>report</th></tr>"
+    );
+    for (group, outcome) in groups.iter().zip(outcomes) {
+        let group_id = group.unique_id(unique_id);
+        let report = match outcome {
+            None => format!(
+                "<a href=\"{}/report/index.html\">{group_id}</a>",
+                STATE.cf_url(&group_id)
+            ),
+            Some(err) => format!("failed: {err}"),
+        };
+        // list the jobs once rather than once per iteration
+        for job in group
+            .jobs
+            .iter()
+            .filter(|job| job.iteration.unwrap_or(1) == 1)
+        {
+            html.push_str(&format!(
+                "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{report}</td></tr>",
+                group.instance_type,
+                group.distro,
+                job.scenario.name,
+                job.driver,
+                job.variant.as_deref().unwrap_or("-"),
+            ));
+        }
+    }
+    html.push_str("</table></body></html>");
+    html
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn run_spec_matrix() {
+        let spec: RunSpec = serde_json::from_str(
+            r#"{
+                "drivers": ["s2n-quic", "tcp"],
+                "scenarios": ["scripts/request_response.json"],
+                "instance_types": ["c5.4xlarge", "c5n.4xlarge"],
+                "impairments": { "baseline": {}, "lossy": { "loss_pct": 1 } }
+            }"#,
+        )
+        .unwrap();
+        let scenario = Scenario {
+            name: "request_response.json".to_string(),
+            path: PathBuf::from("scripts/request_response.json"),
+            clients: 1,
+            servers: 1,
+            routers: 0,
+        };
+        let groups = spec.groups(&[scenario], &OrchestratorConfig::default());
+        assert_eq!(groups.len(), 2);
+        assert_eq!(groups[1].unique_id("run"), "run-c5n.4xlarge");
+
+        let jobs = &groups[0].jobs;
+        assert_eq!(jobs.len(), 4);
+        assert_eq!(jobs[0].driver, Driver::S2nQuic);
+        // an empty impairment is a baseline without an impairment
+        assert_eq!(jobs[0].impairment, None);
+        assert_eq!(jobs[0].result_key(), "request_response-baseline");
+        assert_eq!(jobs[1].result_key(), "request_response-lossy");
+        assert!(jobs[1].impairment.is_some());
+        assert_eq!(jobs[3].driver, Driver::Tcp);
+
+        let outcomes = [None, Some("capacity".to_string())];
+        let html = matrix_report_html("run", &groups, &outcomes);
+        assert!(
+            html.contains("run-c5.4xlarge/report/index.html"),
+            "{}",
+            html
+        );
+        assert!(html.contains("failed: capacity"), "{}", html);
+
+        let group = groups[0].clone().with_iterations(3);
+        assert_eq!(group.jobs.len(), 3 * groups[0].jobs.len());
+        assert_eq!(
+            group.jobs[0].result_key(),
+            "request_response-baseline-iter1"
+        );
+        assert_eq!(
+            group.jobs.last().unwrap().result_key(),
+            "request_response-lossy-iter3"
+        );
+    }
+
+    #[test]
+    fn run_spec_distros() {
+        let scenario = Scenario {
+            name: "request_response.json".to_string(),
+            path: PathBuf::from("scripts/request_response.json"),
+            clients: 1,
+            servers: 1,
+            routers: 0,
+        };
+        let spec = RunSpec::parse(
+            r#"{
+                "drivers": ["tcp"],
+                "scenarios": ["scripts/request_response.json"],
+                "distros": ["al2023", "ubuntu"]
+            }"#,
+            "test",
+        )
+        .unwrap();
+        let groups = spec.groups(
+            std::slice::from_ref(&scenario),
+            &OrchestratorConfig::default(),
+        );
+        assert_eq!(groups.len(), 2);
+        assert_eq!(groups[0].distro, LinuxDistro::Al2023);
+        assert_eq!(groups[0].unique_id("run"), "run-c5.4xlarge");
+        assert_eq!(groups[1].unique_id("run"), "run-c5.4xlarge-ubuntu");
+
+        // defaults to the distro of the config
+        let spec = RunSpec::parse(
+            r#"{ "drivers": ["tcp"], "scenarios": ["scripts/request_response.json"] }"#,
+            "test",
+        )
+        .unwrap();
+        let config = OrchestratorConfig {
+            distro: LinuxDistro::Ubuntu,
+            ..Default::default()
+        };
+        let groups = spec.groups(&[scenario], &config);
+        assert_eq!(groups.len(), 1);
+        assert_eq!(groups[0].distro, LinuxDistro::Ubuntu);
+    }
+}
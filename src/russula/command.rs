@@ -0,0 +1,239 @@
+// Copyright Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+//! A generic protocol whose Workers run an arbitrary command, rather than the
+//! netbench collector and driver, so that russula can coordinate other
+//! benchmarks. ex: iperf3, custom load generators or s2n-tls benches
+//!
+//! The command is a template, run with `sh -c` (`powershell -Command` on
+//! Windows), which can reference:
+//! - `{id}`: the id of the host. ex: the ec2 instance id
+//! - `{peer<N>}` and `{peer<N>_port}`: the ip and port of the Nth peer. ex: an iperf3 server
+//!
+//! ex: `iperf3 -c {peer0} -p {peer0_port} -t 30 -J`
+
+use crate::{
+    duration::parse_duration,
+    russula::{RussulaError, RussulaResult},
+};
+use core::time::Duration;
+use serde::{Deserialize, Serialize};
+use std::{
+    fs::File,
+    net::SocketAddr,
+    path::PathBuf,
+    process::{Command, Stdio},
+    time::Instant,
+};
+use structopt::StructOpt;
+
+mod coord;
+mod worker;
+
+// CheckWorker(params) --->  WaitCoordInit
+//                              | (apply params)
+//                              v
+// CheckWorker   <---------  Ready
+//    |
+//    v
+// Ready
+//    | (user)
+//    v
+// RunAt(time)   --------->  Ready
+//                              |
+//                              v
+//                           Run
+//                              | (self: wait till time)
+//                              v
+// RunAt(time)   <---------  Running
+//    |
+//    v
+// WorkersRunning ---------> Running
+//                              |
+//                              v
+//                           RunningAwaitComplete
+//                              | (self: the command exits or its duration elapses)
+//                              v
+// WorkersRunning <---------  Stopped
+//    |
+//    v
+// Done          --------->  Stopped
+//                              |
+//                              v
+//                           Done
+pub use coord::*;
+pub use worker::*;
+
+#[derive(StructOpt, Debug, Clone)]
+pub struct CommandContext {
+    // The command template to run. Can also be specified by the Coordinator.
+    #[structopt(long)]
+    command: Option<String>,
+
+    // Comma separated addresses of the command's peers. ex: the iperf3 servers.
+    // Can also be specified by the Coordinator.
+    #[structopt(long, use_delimiter = true)]
+    peers: Vec<SocketAddr>,
+
+    // The id of the host, ex: the ec2 instance id. Defaults to the Worker id.
+    #[structopt(long)]
+    instance_id: Option<String>,
+
+    // The command's stdout is written to `<output_dir>/command-<id>.out`
+    #[structopt(long, default_value = ".")]
+    output_dir: PathBuf,
+
+    // Stop the command once this much time has elapsed rather than waiting for
+    // it to exit, ex: for a server. Can also be specified by the Coordinator.
+    #[structopt(long, parse(try_from_str = parse_duration))]
+    duration: Option<Duration>,
+}
+
+/// Parameters shipped by the Coordinator to the Workers with the CheckWorker
+/// state, which override the Worker's command line arguments.
+#[derive(StructOpt, Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CommandParams {
+    // The command template to run. ex: iperf3 -c {peer0} -p {peer0_port} -J
+    #[structopt(long)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub command: Option<String>,
+
+    // Comma separated addresses of the command's peers
+    #[structopt(long, use_delimiter = true)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub peers: Option<Vec<SocketAddr>>,
+
+    // Stop the command once this much time has elapsed
+    #[structopt(long, parse(try_from_str = parse_duration))]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub duration: Option<Duration>,
+}
+
+impl CommandContext {
+    /// Override the command line arguments with the Coordinator's parameters.
+    pub(crate) fn apply(&mut self, params: &CommandParams) {
+        if let Some(command) = &params.command {
+            self.command = Some(command.clone());
+        }
+        if let Some(peers) = &params.peers {
+            self.peers = peers.clone();
+        }
+        if let Some(duration) = params.duration {
+            self.duration = Some(duration);
+        }
+    }
+
+    #[cfg(test)]
+    pub fn testing(command: &str) -> Self {
+        CommandContext {
+            command: Some(command.to_string()),
+            peers: vec![],
+            instance_id: None,
+            output_dir: std::env::temp_dir(),
+            duration: None,
+        }
+    }
+
+    fn host_id<'a>(&'a self, worker_id: &'a str) -> &'a str {
+        self.instance_id.as_deref().unwrap_or(worker_id)
+    }
+
+    /// The command rendered for this host, with its stdout redirected to
+    /// [`CommandContext::output_file`].
+    pub(crate) fn command(&self, worker_id: &str) -> RussulaResult<Command> {
+        let template = self.command.as_deref().ok_or_else(|| RussulaError::Usage {
+            dbg: "command not specified by the command line or the Coordinator".to_string(),
+        })?;
+        let rendered = render(template, self.host_id(worker_id), &self.peers)?;
+
+        let output_file = self.output_file(worker_id);
+        let stdout = File::create(&output_file).map_err(|err| RussulaError::Usage {
+            dbg: format!("Failed to create {:?}: {}", output_file, err),
+        })?;
+        let mut cmd = shell(&rendered);
+        cmd.stdout(Stdio::from(stdout));
+        Ok(cmd)
+    }
+
+    /// ex: command-i-0123.out
+    pub(crate) fn output_file(&self, worker_id: &str) -> PathBuf {
+        self.output_dir
+            .join(format!("command-{}.out", self.host_id(worker_id)))
+    }
+
+    /// The time at which the command of a duration bounded run is stopped.
+    pub(crate) fn run_until(&self) -> Option<Instant> {
+        self.duration.map(|duration| Instant::now() + duration)
+    }
+}
+
+#[cfg(unix)]
+fn shell(command: &str) -> Command {
+    let mut cmd = Command::new("sh");
+    cmd.args(["-c", command]);
+    cmd
+}
+
+#[cfg(windows)]
+fn shell(command: &str) -> Command {
+    let mut cmd = Command::new("powershell");
+    cmd.args(["-NoProfile", "-Command", command]);
+    cmd
+}
+
+/// Substitute the host id and peers into a command template.
+fn render(template: &str, id: &str, peers: &[SocketAddr]) -> RussulaResult<String> {
+    let mut command = template.replace("{id}", id);
+    for (i, peer) in peers.iter().enumerate() {
+        command = command
+            .replace(&format!("{{peer{i}_port}}"), &peer.port().to_string())
+            .replace(&format!("{{peer{i}}}"), &peer.ip().to_string());
+    }
+    if command.contains("{peer") {
+        return Err(RussulaError::Usage {
+            dbg: format!(
+                "command references a peer which wasn't specified ({} peers): {}",
+                peers.len(),
+                template
+            ),
+        });
+    }
+    Ok(command)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn render_command() {
+        let peers: Vec<SocketAddr> = (0..11)
+            .map(|i| format!("10.0.0.{i}:{}", 5200 + i).parse().unwrap())
+            .collect();
+        assert_eq!(
+            render(
+                "iperf3 -c {peer0} -p {peer0_port} --logfile {id}.log",
+                "i-0123",
+                &peers
+            )
+            .unwrap(),
+            "iperf3 -c 10.0.0.0 -p 5200 --logfile i-0123.log"
+        );
+        assert_eq!(
+            render("{peer1} {peer10}", "i-0123", &peers).unwrap(),
+            "10.0.0.1 10.0.0.10"
+        );
+        assert!(render("iperf3 -c {peer1}", "i-0123", &peers[..1]).is_err());
+
+        let mut ctx = CommandContext::testing("echo {id}");
+        ctx.apply(&CommandParams {
+            peers: Some(peers[..1].to_vec()),
+            duration: Some(Duration::from_secs(5)),
+            ..Default::default()
+        });
+        assert_eq!(ctx.command.as_deref(), Some("echo {id}"));
+        assert_eq!(ctx.peers, peers[..1]);
+        assert!(ctx.run_until().is_some());
+        assert!(ctx.output_file("w-1").ends_with("command-w-1.out"));
+    }
+}
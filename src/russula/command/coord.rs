@@ -0,0 +1,233 @@
+// Copyright Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::russula::{
+    command::{CommandParams, WorkerState},
+    error::{RussulaError, RussulaResult},
+    event::{EventRecorder, EventType},
+    message::WorkerStatus,
+    netbench::{unix_millis, ProcessExit},
+    network_utils::Msg,
+    protocol::{private, Protocol},
+    transport::{Transport, TransportStream},
+    StateApi, TransitionStep,
+};
+use async_trait::async_trait;
+use core::{fmt::Debug, time::Duration};
+use serde::{Deserialize, Serialize};
+use std::{
+    net::SocketAddr,
+    sync::{Arc, OnceLock},
+    time::SystemTime,
+};
+use tracing::{debug, info};
+
+// How far in the future to schedule the start of the workers. The workers need
+// to receive the start time before it elapses so this should be larger than the
+// worker's poll delay.
+const DEFAULT_RUN_AT_DELAY: Duration = Duration::from_secs(15);
+
+// Only used when creating a state variant
+const PLACEHOLDER_START_AT: u64 = 0;
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub enum CoordState {
+    // Ship the command to the workers
+    CheckWorker(CommandParams),
+    Ready,
+    // Run the workers at the specified time (milliseconds since the unix epoch)
+    RunAt(u64),
+    WorkersRunning,
+    Done,
+}
+
+#[derive(Debug, Clone)]
+pub struct CoordProtocol {
+    state: CoordState,
+    worker_state: WorkerState,
+    event_recorder: EventRecorder,
+    run_at_delay: Duration,
+    // Shared by all instances of the protocol so that every worker receives
+    // the same start time.
+    start_at: Arc<OnceLock<u64>>,
+}
+
+impl Default for CoordProtocol {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl CoordProtocol {
+    pub fn new() -> Self {
+        CoordProtocol {
+            state: CoordState::CheckWorker(CommandParams::default()),
+            worker_state: WorkerState::WaitCoordInit,
+            event_recorder: EventRecorder::default(),
+            run_at_delay: DEFAULT_RUN_AT_DELAY,
+            start_at: Arc::new(OnceLock::new()),
+        }
+    }
+
+    /// The command shipped to the workers.
+    pub fn with_params(mut self, params: CommandParams) -> Self {
+        self.state = CoordState::CheckWorker(params);
+        self
+    }
+
+    /// How far in the future the workers should be scheduled to start.
+    pub fn with_run_at_delay(mut self, run_at_delay: Duration) -> Self {
+        self.run_at_delay = run_at_delay;
+        self
+    }
+}
+
+impl private::Protocol for CoordProtocol {
+    fn event_recorder(&mut self) -> &mut EventRecorder {
+        &mut self.event_recorder
+    }
+}
+
+#[async_trait]
+impl Protocol for CoordProtocol {
+    type State = CoordState;
+    fn name(&self) -> String {
+        format!("command-c-{}", 0)
+    }
+
+    fn role(&self) -> &'static str {
+        "coordinator"
+    }
+
+    async fn connect(
+        &self,
+        addr: &SocketAddr,
+        transport: Transport,
+    ) -> RussulaResult<TransportStream> {
+        info!("--- Coordinator: attempt to connect on: {}", addr);
+        transport.connect(addr).await
+    }
+
+    fn update_peer_state(&mut self, msg: Msg) -> RussulaResult<()> {
+        let status = WorkerStatus::<WorkerState>::from_msg(msg)?;
+        let pid = status.pid;
+        self.worker_state = status.into_state()?;
+        debug!(
+            ?pid,
+            "{} ... peer_state {:?}",
+            self.name(),
+            self.worker_state
+        );
+        if let WorkerState::Stopped(exits) = &self.worker_state {
+            for exit in exits {
+                info!("{} worker command stopped. {}", self.name(), exit);
+            }
+        }
+
+        Ok(())
+    }
+
+    fn state(&self) -> &Self::State {
+        &self.state
+    }
+
+    fn state_mut(&mut self) -> &mut Self::State {
+        &mut self.state
+    }
+
+    fn ready_state(&self) -> Self::State {
+        CoordState::Ready
+    }
+
+    fn done_state(&self) -> Self::State {
+        CoordState::Done
+    }
+
+    fn worker_running_state(&self) -> Self::State {
+        CoordState::WorkersRunning
+    }
+
+    fn worker_exits(&self) -> &[ProcessExit] {
+        match &self.worker_state {
+            WorkerState::Stopped(exits) => exits,
+            _ => &[],
+        }
+    }
+
+    async fn run(&mut self, stream: &TransportStream) -> RussulaResult<Option<Msg>> {
+        match self.state_mut() {
+            CoordState::CheckWorker(_) => {
+                self.state().notify_peer(stream).await?;
+                self.await_next_msg(stream).await
+            }
+            CoordState::Ready => {
+                let run_at_delay = self.run_at_delay;
+                let start_at = *self
+                    .start_at
+                    .get_or_init(|| unix_millis(SystemTime::now() + run_at_delay));
+                self.state_mut()
+                    .transition_to(stream, CoordState::RunAt(start_at))
+                    .await?;
+                Ok(None)
+            }
+            CoordState::RunAt(_start_at) => {
+                self.state().notify_peer(stream).await?;
+                self.await_next_msg(stream).await
+            }
+            CoordState::WorkersRunning => {
+                self.state().notify_peer(stream).await?;
+                self.await_next_msg(stream).await
+            }
+            CoordState::Done => {
+                self.state().notify_peer(stream).await?;
+                Ok(None)
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl StateApi for CoordState {
+    fn name_prefix(&self) -> String {
+        "command-coord".to_string()
+    }
+
+    fn transition_step(&self) -> TransitionStep {
+        match self {
+            CoordState::CheckWorker(_) => TransitionStep::AwaitNext(WorkerState::Ready.as_bytes()),
+            CoordState::Ready => TransitionStep::UserDriven,
+            CoordState::RunAt(_) => TransitionStep::AwaitNext(WorkerState::Running(0).as_bytes()),
+            CoordState::WorkersRunning => {
+                TransitionStep::AwaitNext(WorkerState::Stopped(vec![]).as_bytes())
+            }
+            CoordState::Done => TransitionStep::Finished,
+        }
+    }
+
+    fn next_state(&self) -> Self {
+        match self {
+            CoordState::CheckWorker(_) => CoordState::Ready,
+            // FIXME error prone. The start time is set when running the Ready state
+            CoordState::Ready => CoordState::RunAt(PLACEHOLDER_START_AT),
+            CoordState::RunAt(_) => CoordState::WorkersRunning,
+            CoordState::WorkersRunning => CoordState::Done,
+            CoordState::Done => CoordState::Done,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::russula::model;
+
+    #[test]
+    fn protocol_model() {
+        let stats = model::check(
+            CoordState::CheckWorker(CommandParams::default()),
+            WorkerState::WaitCoordInit,
+        )
+        .unwrap();
+        assert!(stats.states > 1);
+    }
+}
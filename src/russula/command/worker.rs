@@ -0,0 +1,242 @@
+// Copyright Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+use super::{CommandContext, CommandParams};
+use crate::russula::{
+    command::CoordState,
+    error::{RussulaError, RussulaResult},
+    event::{EventRecorder, EventType},
+    netbench::{sleep_until_unix_millis, stderr_log, ProcessExit, Supervisor, KILL_GRACE_PERIOD},
+    network_utils::Msg,
+    protocol::{private, Protocol},
+    transport::{Transport, TransportStream},
+    StateApi, TransitionStep,
+};
+use async_trait::async_trait;
+use core::fmt::Debug;
+use serde::{Deserialize, Serialize};
+use std::{net::SocketAddr, time::Instant};
+use tracing::{debug, info};
+
+// Only used when creating a state variant
+const PLACEHOLDER_PID: u32 = 1000;
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub enum WorkerState {
+    WaitCoordInit,
+    Ready,
+    Run,
+    Running(#[serde(skip)] u32),
+    RunningAwaitComplete(#[serde(skip)] u32),
+    // The exit status of the command
+    Stopped(Vec<ProcessExit>),
+    Done,
+}
+
+#[derive(Clone)]
+pub struct WorkerProtocol {
+    id: String,
+    state: WorkerState,
+    coord_state: CoordState,
+    command_ctx: CommandContext,
+    event_recorder: EventRecorder,
+    supervisor: Option<Supervisor>,
+    // When the command of a duration bounded run is stopped
+    run_until: Option<Instant>,
+}
+
+impl WorkerProtocol {
+    pub fn new(id: String, command_ctx: CommandContext) -> Self {
+        WorkerProtocol {
+            id,
+            state: WorkerState::WaitCoordInit,
+            coord_state: CoordState::CheckWorker(CommandParams::default()),
+            command_ctx,
+            event_recorder: EventRecorder::default(),
+            supervisor: None,
+            run_until: None,
+        }
+    }
+}
+
+impl private::Protocol for WorkerProtocol {
+    fn event_recorder(&mut self) -> &mut EventRecorder {
+        &mut self.event_recorder
+    }
+}
+
+#[async_trait]
+impl Protocol for WorkerProtocol {
+    type State = WorkerState;
+
+    fn name(&self) -> String {
+        format!("command-w-{}", self.id)
+    }
+
+    fn role(&self) -> &'static str {
+        "worker"
+    }
+
+    async fn connect(
+        &self,
+        addr: &SocketAddr,
+        transport: Transport,
+    ) -> RussulaResult<TransportStream> {
+        info!("{} listening on: {}", self.name(), addr);
+        let stream = transport.listen(addr).await?;
+        info!("{} success connection: {addr}", self.name());
+
+        Ok(stream)
+    }
+
+    fn update_peer_state(&mut self, msg: Msg) -> RussulaResult<()> {
+        self.coord_state = CoordState::from_msg(msg)?;
+        debug!("{} ... peer_state {:?}", self.name(), self.coord_state);
+        // Only reconfigure before running the command
+        if let (CoordState::CheckWorker(params), WorkerState::WaitCoordInit | WorkerState::Ready) =
+            (&self.coord_state, &self.state)
+        {
+            info!("{} apply command params {:?}", self.name(), params);
+            self.command_ctx.apply(params);
+        }
+
+        Ok(())
+    }
+
+    fn state(&self) -> &Self::State {
+        &self.state
+    }
+
+    fn state_mut(&mut self) -> &mut Self::State {
+        &mut self.state
+    }
+
+    fn ready_state(&self) -> Self::State {
+        WorkerState::Ready
+    }
+
+    fn done_state(&self) -> Self::State {
+        WorkerState::Done
+    }
+
+    fn worker_running_state(&self) -> Self::State {
+        unimplemented!()
+    }
+
+    async fn run(&mut self, stream: &TransportStream) -> RussulaResult<Option<Msg>> {
+        match self.state_mut() {
+            WorkerState::WaitCoordInit => self.await_next_msg(stream).await,
+            WorkerState::Ready => {
+                self.state().notify_peer(stream).await?;
+                self.await_next_msg(stream).await
+            }
+            WorkerState::Run => {
+                // Wait for the synchronized start time so that all the workers
+                // start at the same time
+                if let CoordState::RunAt(start_at) = self.coord_state {
+                    info!("{} waiting to run at: {}", self.name(), start_at);
+                    sleep_until_unix_millis(start_at).await;
+                }
+
+                let cmd = self.command_ctx.command(&self.id)?;
+                info!("{} run command {:?}", self.name(), cmd);
+                let supervisor = Supervisor::spawn(cmd, &stderr_log(&self.name(), 0))?;
+                let pid = supervisor.pid();
+                self.supervisor = Some(supervisor);
+                self.run_until = self.command_ctx.run_until();
+
+                *self.state_mut() = WorkerState::Running(pid);
+                Ok(None)
+            }
+            WorkerState::Running(_pid) => {
+                self.state().notify_peer(stream).await?;
+                self.await_next_msg(stream).await
+            }
+            WorkerState::RunningAwaitComplete(pid) => {
+                let pid = *pid;
+                self.state().notify_peer(stream).await?;
+
+                // Reap the process so that it doesn't become a zombie. The command
+                // of a duration bounded run is stopped once the duration elapses
+                let stop = self
+                    .run_until
+                    .is_some_and(|run_until| Instant::now() >= run_until);
+                let supervisor = self.supervisor.as_mut().ok_or(RussulaError::Usage {
+                    dbg: "the command isn't running".to_string(),
+                })?;
+                let exit = match stop {
+                    true => supervisor.poll_kill(KILL_GRACE_PERIOD)?,
+                    false => supervisor.try_wait()?,
+                };
+
+                match exit {
+                    Some(exit) => {
+                        info!("Command COMPLETED! pid: {} {}", pid, exit);
+                        self.state_mut()
+                            .transition_to(stream, WorkerState::Stopped(vec![exit]))
+                            .await?;
+                    }
+                    None => debug!("command still RUNNING! pid: {}", pid),
+                }
+
+                Ok(None)
+            }
+            WorkerState::Stopped(_exit) => {
+                self.state().notify_peer(stream).await?;
+                self.await_next_msg(stream).await
+            }
+            WorkerState::Done => {
+                self.state().notify_peer(stream).await?;
+                Ok(None)
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl StateApi for WorkerState {
+    fn name_prefix(&self) -> String {
+        "command-worker".to_string()
+    }
+
+    fn is_worker_state(&self) -> bool {
+        true
+    }
+
+    fn pid(&self) -> Option<u32> {
+        match self {
+            WorkerState::Running(pid) | WorkerState::RunningAwaitComplete(pid) => Some(*pid),
+            _ => None,
+        }
+    }
+
+    fn transition_step(&self) -> TransitionStep {
+        match self {
+            WorkerState::WaitCoordInit => TransitionStep::AwaitNext(
+                CoordState::CheckWorker(CommandParams::default()).as_bytes(),
+            ),
+            WorkerState::Ready => TransitionStep::AwaitNext(CoordState::RunAt(0).as_bytes()),
+            WorkerState::Run => TransitionStep::SelfDriven,
+            WorkerState::Running(_) => {
+                TransitionStep::AwaitNext(CoordState::WorkersRunning.as_bytes())
+            }
+            WorkerState::RunningAwaitComplete(_) => TransitionStep::SelfDriven,
+            WorkerState::Stopped(_) => TransitionStep::AwaitNext(CoordState::Done.as_bytes()),
+            WorkerState::Done => TransitionStep::Finished,
+        }
+    }
+
+    fn next_state(&self) -> Self {
+        match self {
+            WorkerState::WaitCoordInit => WorkerState::Ready,
+            WorkerState::Ready => WorkerState::Run,
+            // FIXME error prone
+            WorkerState::Run => WorkerState::Running(PLACEHOLDER_PID),
+            WorkerState::Running(pid) => WorkerState::RunningAwaitComplete(*pid),
+            // FIXME error prone. The exit status is set when running the RunningAwaitComplete state
+            WorkerState::RunningAwaitComplete(_) => WorkerState::Stopped(vec![]),
+            WorkerState::Stopped(_) => WorkerState::Done,
+            WorkerState::Done => WorkerState::Done,
+        }
+    }
+}
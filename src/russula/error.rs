@@ -1,6 +1,7 @@
 // Copyright Amazon.com, Inc. or its affiliates. All Rights Reserved.
 // SPDX-License-Identifier: Apache-2.0
 
+use std::net::SocketAddr;
 use tokio::io::ErrorKind;
 
 pub type RussulaResult<T, E = RussulaError> = Result<T, E>;
@@ -12,6 +13,11 @@ pub enum RussulaError {
     NetworkBlocked { dbg: String },
     BadMsg { dbg: String },
     Usage { dbg: String },
+    PeersFailed { dbg: String },
+    // The peer didn't connect (or register) within the connect timeout
+    PeerTimeout { peer: SocketAddr, dbg: String },
+    // Reported by the Worker in its WorkerStatus
+    WorkerFailed { dbg: String },
 }
 
 impl std::fmt::Display for RussulaError {
@@ -24,6 +30,9 @@ impl std::fmt::Display for RussulaError {
             RussulaError::NetworkBlocked { dbg } => write!(f, "NetworkBlocked {}", dbg),
             RussulaError::BadMsg { dbg } => write!(f, "BadMsg {}", dbg),
             RussulaError::Usage { dbg } => write!(f, "Usage {}", dbg),
+            RussulaError::PeersFailed { dbg } => write!(f, "PeersFailed {}", dbg),
+            RussulaError::PeerTimeout { peer, dbg } => write!(f, "PeerTimeout {} {}", peer, dbg),
+            RussulaError::WorkerFailed { dbg } => write!(f, "WorkerFailed {}", dbg),
         }
     }
 }
@@ -6,6 +6,7 @@ use super::{
     network_utils,
     network_utils::Msg,
     states::{StateApi, TransitionStep},
+    transport::TransportStream,
     RussulaResult,
 };
 use async_trait::async_trait;
@@ -13,7 +14,6 @@ use bytes::Bytes;
 use core::{fmt::Debug, task::Poll, time::Duration};
 use serde::{Deserialize, Serialize};
 use std::{fmt::Display, net::SocketAddr};
-use tokio::net::TcpStream;
 use tracing::{debug, info};
 
 pub enum EventType {
@@ -0,0 +1,93 @@
+// Copyright Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::russula::{RussulaError, RussulaResult};
+use core::str::FromStr;
+
+/// How a Coordinator treats Workers which are unreachable or fail with a fatal
+/// error.
+///
+/// Failed Workers are excluded from the remaining coordination, so the other
+/// Workers can still reach the desired state.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum FailurePolicy {
+    /// Fail the coordination as soon as any Worker fails
+    #[default]
+    FailFast,
+    /// Continue while at least `n` Workers are healthy
+    ContinueWithQuorum(usize),
+    /// Continue while any Worker is healthy
+    BestEffort,
+}
+
+impl FailurePolicy {
+    /// Error if the coordination can't continue with `failed` of the `peers`
+    /// Workers failed.
+    pub fn check(&self, peers: usize, failed: usize) -> RussulaResult<()> {
+        let healthy = peers.saturating_sub(failed);
+        let min_healthy = match self {
+            FailurePolicy::FailFast => peers,
+            FailurePolicy::ContinueWithQuorum(quorum) => *quorum,
+            FailurePolicy::BestEffort => 1,
+        };
+        if healthy < min_healthy.max(1) {
+            return Err(RussulaError::PeersFailed {
+                dbg: format!("{} of {} workers failed. policy: {}", failed, peers, self),
+            });
+        }
+        Ok(())
+    }
+}
+
+impl std::fmt::Display for FailurePolicy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FailurePolicy::FailFast => write!(f, "fail-fast"),
+            FailurePolicy::ContinueWithQuorum(quorum) => write!(f, "quorum:{}", quorum),
+            FailurePolicy::BestEffort => write!(f, "best-effort"),
+        }
+    }
+}
+
+impl FromStr for FailurePolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.split_once(':') {
+            None if s == "fail-fast" => Ok(FailurePolicy::FailFast),
+            None if s == "best-effort" => Ok(FailurePolicy::BestEffort),
+            Some(("quorum", quorum)) => quorum
+                .parse()
+                .map(FailurePolicy::ContinueWithQuorum)
+                .map_err(|err| format!("invalid quorum {}: {}", quorum, err)),
+            _ => Err(format!(
+                "unsupported failure policy: {}. expected fail-fast, quorum:<n> or best-effort",
+                s
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn failure_policy() {
+        assert!(FailurePolicy::FailFast.check(10, 0).is_ok());
+        assert!(FailurePolicy::FailFast.check(10, 1).is_err());
+
+        let quorum = FailurePolicy::from_str("quorum:8").unwrap();
+        assert_eq!(quorum, FailurePolicy::ContinueWithQuorum(8));
+        assert!(quorum.check(10, 2).is_ok());
+        assert!(quorum.check(10, 3).is_err());
+
+        let best_effort = FailurePolicy::from_str("best-effort").unwrap();
+        assert!(best_effort.check(10, 9).is_ok());
+        assert!(best_effort.check(10, 10).is_err());
+
+        assert_eq!(quorum.to_string(), "quorum:8");
+        assert!(FailurePolicy::from_str("quorum:x").is_err());
+        assert!(FailurePolicy::from_str("none").is_err());
+    }
+}
@@ -0,0 +1,138 @@
+// Copyright Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+use super::{
+    error::RussulaError, netbench::RunProgress, network_utils::Msg, states::StateApi, RussulaResult,
+};
+use bytes::Bytes;
+use serde::{Deserialize, Serialize};
+
+/// The payload of each notification sent by a Worker.
+///
+/// The state is wrapped so that the pid of the netbench driver and the error
+/// which failed the Worker's session reach the Coordinator, rather than being
+/// uploaded to s3 by the host's SSM script. Running Workers also report the
+/// progress of their drivers.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct WorkerStatus<S> {
+    pub state: S,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pid: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub progress: Option<RunProgress>,
+}
+
+impl<S: StateApi> WorkerStatus<S> {
+    pub fn new(state: &S, error: Option<String>) -> Self {
+        WorkerStatus {
+            state: state.clone(),
+            pid: state.pid(),
+            error,
+            progress: None,
+        }
+    }
+
+    pub fn with_progress(mut self, progress: Option<RunProgress>) -> Self {
+        self.progress = progress;
+        self
+    }
+
+    pub fn as_bytes(&self) -> Bytes {
+        serde_json::to_string(self).unwrap().into()
+    }
+
+    /// Parse a msg from a Worker. A bare state, sent by an older Worker, is
+    /// accepted as a status without a pid or error.
+    pub fn from_msg(msg: Msg) -> RussulaResult<Self> {
+        let bad_msg = || RussulaError::BadMsg {
+            dbg: format!(
+                "received a malformed msg. len: {} data: {:?}",
+                msg.len, msg.data
+            ),
+        };
+        let value: serde_json::Value = serde_json::from_slice(&msg.data).map_err(|_| bad_msg())?;
+        if is_status(&value) {
+            serde_json::from_value(value).map_err(|_| bad_msg())
+        } else {
+            let state = serde_json::from_value(value).map_err(|_| bad_msg())?;
+            Ok(WorkerStatus {
+                state,
+                pid: None,
+                error: None,
+                progress: None,
+            })
+        }
+    }
+
+    /// The reported state, or the error which failed the Worker's session.
+    pub fn into_state(self) -> RussulaResult<S> {
+        match self.error {
+            Some(dbg) => Err(RussulaError::WorkerFailed { dbg }),
+            None => Ok(self.state),
+        }
+    }
+}
+
+// State variants are CamelCase so a `state` key can only be a WorkerStatus
+fn is_status(value: &serde_json::Value) -> bool {
+    matches!(value, serde_json::Value::Object(map) if map.contains_key("state"))
+}
+
+/// The serialized state carried by a msg, unwrapping a [`WorkerStatus`].
+pub(crate) fn state_value(msg: &[u8]) -> Option<serde_json::Value> {
+    match serde_json::from_slice(msg).ok()? {
+        serde_json::Value::Object(mut map) if map.contains_key("state") => map.remove("state"),
+        value => Some(value),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::russula::netbench::server::WorkerState;
+
+    #[test]
+    fn worker_status() {
+        let status = WorkerStatus::new(&WorkerState::RunningAwaitKill(42), None);
+        assert_eq!(status.pid, Some(42));
+        let bytes = status.as_bytes();
+        assert_eq!(bytes, "{\"state\":\"RunningAwaitKill\",\"pid\":42}");
+        assert_eq!(
+            state_value(&bytes),
+            Some(serde_json::Value::String("RunningAwaitKill".to_string()))
+        );
+
+        let status = WorkerStatus::<WorkerState>::from_msg(Msg::new(bytes)).unwrap();
+        assert_eq!(status.pid, Some(42));
+        assert!(matches!(
+            status.into_state(),
+            Ok(WorkerState::RunningAwaitKill(_))
+        ));
+
+        // a bare state
+        let status = WorkerStatus::<WorkerState>::from_msg(Msg::new("\"Ready\"".into())).unwrap();
+        assert_eq!(status.pid, None);
+        assert!(matches!(status.into_state(), Ok(WorkerState::Ready)));
+
+        let failed = WorkerStatus::new(&WorkerState::Run, Some("spawn failed".to_string()));
+        let status = WorkerStatus::<WorkerState>::from_msg(Msg::new(failed.as_bytes())).unwrap();
+        assert!(matches!(
+            status.into_state(),
+            Err(RussulaError::WorkerFailed { dbg }) if dbg == "spawn failed"
+        ));
+
+        let progress = RunProgress {
+            bytes_sent: 10,
+            bytes_received: 20,
+            connections: 1,
+        };
+        let running = WorkerStatus::new(&WorkerState::RunningAwaitKill(42), None)
+            .with_progress(Some(progress));
+        let status = WorkerStatus::<WorkerState>::from_msg(Msg::new(running.as_bytes())).unwrap();
+        assert_eq!(status.progress, Some(progress));
+
+        assert!(WorkerStatus::<WorkerState>::from_msg(Msg::new("{\"state\":1}".into())).is_err());
+    }
+}
@@ -3,21 +3,42 @@
 
 #![allow(unused)]
 use crate::russula::protocol::{ProtocolInstance, SockProtocol};
-use core::{task::Poll, time::Duration};
+use core::{future::Future, pin::Pin, task::Poll, time::Duration};
 use paste::paste;
-use std::{collections::BTreeSet, net::SocketAddr};
-use tracing::{debug, error, info, warn};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    net::SocketAddr,
+};
+use tracing::{debug, error, info, info_span, warn, Instrument};
 
+use netbench::ProcessExit;
+
+pub mod command;
 mod error;
 mod event;
+mod failure_policy;
+mod message;
+#[cfg(test)]
+mod model;
 pub mod netbench;
 mod network_utils;
+mod progress;
 mod protocol;
 mod states;
+mod transport;
 
-use error::{RussulaError, RussulaResult};
-use protocol::Protocol;
+pub use error::{RussulaError, RussulaResult};
+pub use failure_policy::FailurePolicy;
+use progress::EventSink;
+pub use progress::{RussulaEvent, RussulaEvents};
+pub use protocol::Protocol;
 use states::{StateApi, TransitionStep};
+pub use transport::Transport;
+use transport::{Registrar, TransportStream};
+
+// How long a registering Worker retries dialing the Coordinator, which is
+// typically started after the Workers
+const REGISTER_TIMEOUT: Duration = Duration::from_secs(300);
 
 // TODO
 // D- hide State from russula API..
@@ -33,13 +54,32 @@ pub struct Russula<P: Protocol> {
     // The Worker can be list of size >=1
     instance_list: Vec<ProtocolInstance<P>>,
     poll_delay: Duration,
+    // The delay doubles while the peers don't transition, up to the max
+    max_poll_delay: Duration,
+    // The number of peer transitions, used to reset the poll delay
+    transitions: u64,
+    failure_policy: FailurePolicy,
+    // The number of peers, including the ones which failed to connect
+    peers: usize,
+    // Peers excluded from the coordination: addr -> failure
+    failed_peers: BTreeMap<SocketAddr, String>,
+    events: EventSink,
 }
 
 macro_rules! state_api {
 {$state:ident} => {paste!{
     pub async fn [<run_till_ $state>](&mut self) -> RussulaResult<()> {
-        while self.[<poll_ $state>]().await?.is_pending() {
-            tokio::time::sleep(self.poll_delay).await;
+        let mut delay = self.poll_delay;
+        loop {
+            let transitions = self.transitions;
+            if self.[<poll_ $state>]().await?.is_ready() {
+                break;
+            }
+            if self.transitions != transitions {
+                delay = self.poll_delay;
+            }
+            tokio::time::sleep(delay).await;
+            delay = (delay * 2).min(self.max_poll_delay);
         }
 
         Ok(())
@@ -47,10 +87,35 @@ macro_rules! state_api {
 
     pub async fn [<poll_ $state>](&mut self) -> RussulaResult<Poll<()>> {
         for peer in self.instance_list.iter_mut() {
-            if let Err(err) = peer.protocol.[<poll_ $state>](&peer.stream).await {
+            if self.failed_peers.contains_key(&peer.addr) {
+                continue;
+            }
+            let span = peer.span();
+            let from = peer.protocol.state().variant();
+            let poll = peer.protocol.[<poll_ $state>](&peer.stream).instrument(span).await;
+            let to = peer.protocol.state().variant();
+            if from != to {
+                self.transitions += 1;
+                self.events.emit(RussulaEvent::PeerTransitioned { peer: peer.addr, from, to });
+            }
+            if let Some(progress) = peer.protocol.take_worker_progress() {
+                self.events.emit(RussulaEvent::PeerProgress { peer: peer.addr, progress });
+            }
+            if let Err(err) = poll {
                 if err.is_fatal() {
                     error!("{} {}", err, peer.addr);
-                    panic!("{} {}", err, peer.addr);
+                    let excluded = self.failure_policy != FailurePolicy::FailFast;
+                    self.events.emit(RussulaEvent::PeerError {
+                        peer: peer.addr,
+                        error: err.to_string(),
+                        excluded,
+                    });
+                    if !excluded {
+                        return Err(err);
+                    }
+                    warn!(peer = %peer.addr, policy = %self.failure_policy, "excluding failed peer");
+                    self.failed_peers.insert(peer.addr, err.to_string());
+                    self.failure_policy.check(self.peers, self.failed_peers.len())?;
                 }
             }
         }
@@ -62,9 +127,12 @@ macro_rules! state_api {
         Ok(poll)
     }
 
-    /// Check if all instances are at the desired state
+    /// Check if all instances, excluding failed peers, are at the desired state
     fn [< is_ $state _state>](&self) -> bool {
         for peer in self.instance_list.iter() {
+            if self.failed_peers.contains_key(&peer.addr) {
+                continue;
+            }
             let protocol_state = peer.protocol.state();
             // All instance must be at the desired state
             if !peer.protocol.[< is_ $state _state>]() {
@@ -74,16 +142,111 @@ macro_rules! state_api {
         }
         true
     }
+
+    /// The peers, excluding failed peers, which aren't at the desired state.
+    /// ex: the peers which held up a `run_till_*` which timed out
+    pub fn [<pending_ $state _peers>](&self) -> Vec<SocketAddr> {
+        self.instance_list
+            .iter()
+            .filter(|peer| !self.failed_peers.contains_key(&peer.addr))
+            .filter(|peer| !peer.protocol.[< is_ $state _state>]())
+            .map(|peer| peer.addr)
+            .collect()
+    }
 }};
 }
 
 impl<P: Protocol + Send> Russula<P> {
+    /// A Russula whose peers are connected over already established streams,
+    /// bypassing `Protocol::connect`.
+    #[cfg(test)]
+    pub(crate) fn from_streams(
+        peers: Vec<(SocketAddr, transport::TransportStream, P)>,
+        poll_delay: Duration,
+    ) -> Self {
+        let peers_len = peers.len();
+        Russula {
+            instance_list: peers
+                .into_iter()
+                .map(|(addr, stream, protocol)| ProtocolInstance {
+                    addr,
+                    stream,
+                    protocol,
+                })
+                .collect(),
+            poll_delay,
+            max_poll_delay: poll_delay,
+            transitions: 0,
+            failure_policy: FailurePolicy::default(),
+            peers: peers_len,
+            failed_peers: BTreeMap::new(),
+            events: EventSink::default(),
+        }
+    }
+
+    #[cfg(test)]
+    pub(crate) fn with_failure_policy(mut self, failure_policy: FailurePolicy) -> Self {
+        self.failure_policy = failure_policy;
+        self
+    }
+
     state_api!(ready);
     state_api!(done);
     /// Should only be called by Coordinators
     state_api!(worker_running);
+
+    /// The exit status of the netbench processes on each worker which has stopped.
+    /// A worker running multiple drivers reports an exit status per driver.
+    ///
+    /// Should only be called by Coordinators
+    pub fn worker_exits(&self) -> Vec<(SocketAddr, ProcessExit)> {
+        self.instance_list
+            .iter()
+            .filter(|peer| !self.failed_peers.contains_key(&peer.addr))
+            .flat_map(|peer| {
+                peer.protocol
+                    .worker_exits()
+                    .iter()
+                    .map(|exit| (peer.addr, exit.clone()))
+            })
+            .collect()
+    }
+
+    /// Peers which were unreachable or failed, and were excluded from the
+    /// coordination by the [`FailurePolicy`]: addr -> failure.
+    ///
+    /// Should only be called by Coordinators
+    pub fn failed_peers(&self) -> &BTreeMap<SocketAddr, String> {
+        &self.failed_peers
+    }
+
+    /// Subscribe to the progress of the peers, as an alternative to checking
+    /// their state between calls to `poll_*`.
+    ///
+    /// The stream starts with a `PeerConnected` for each connected peer and a
+    /// `PeerError` for each excluded peer, followed by the events of the
+    /// following `poll_*` and `run_till_*` calls. Only the latest stream
+    /// receives events.
+    pub fn events(&mut self) -> RussulaEvents {
+        let events = self.events.subscribe();
+        for peer in self.instance_list.iter() {
+            if !self.failed_peers.contains_key(&peer.addr) {
+                self.events
+                    .emit(RussulaEvent::PeerConnected { peer: peer.addr });
+            }
+        }
+        for (peer, error) in self.failed_peers.iter() {
+            self.events.emit(RussulaEvent::PeerError {
+                peer: *peer,
+                error: error.clone(),
+                excluded: true,
+            });
+        }
+        events
+    }
 }
 
+#[derive(Clone)]
 pub struct RussulaBuilder<P: Protocol> {
     // Address for the Coordinator and Worker to communicate on.
     //
@@ -91,7 +254,25 @@ pub struct RussulaBuilder<P: Protocol> {
     // The Worker gets its own addr to 'listen' on.
     russula_pair_addr_list: Vec<SockProtocol<P>>,
     poll_delay: Duration,
+    max_poll_delay: Duration,
+    // How long to retry connecting to each peer. Defaults to 3 `max_poll_delay`
+    connect_timeout: Option<Duration>,
     protocol: P,
+    transport: Transport,
+    failure_policy: FailurePolicy,
+    connection: Connection,
+}
+
+/// Which side of a Coordinator and Worker pair dials the other.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+enum Connection {
+    // The Coordinator connects to the Workers' addrs, which they listen on
+    #[default]
+    Direct,
+    // The Worker dials the Coordinator at this addr and registers as its addr
+    Register(SocketAddr),
+    // The Coordinator listens on this addr for its Workers to register
+    AcceptRegistrations(SocketAddr),
 }
 
 impl<P: Protocol> RussulaBuilder<P> {
@@ -104,65 +285,572 @@ impl<P: Protocol> RussulaBuilder<P> {
         Self {
             russula_pair_addr_list: peer_list,
             poll_delay,
+            max_poll_delay: poll_delay,
+            connect_timeout: None,
             protocol,
+            transport: Transport::default(),
+            failure_policy: FailurePolicy::default(),
+            connection: Connection::default(),
+        }
+    }
+
+    /// The transport used to communicate with peers. Defaults to TCP.
+    ///
+    /// The Coordinator and Workers must use the same transport.
+    pub fn transport(mut self, transport: Transport) -> Self {
+        self.transport = transport;
+        self
+    }
+
+    /// Back off from `poll_delay` up to `max_poll_delay` while the peers don't
+    /// transition. Defaults to polling every `poll_delay`.
+    ///
+    pub fn max_poll_delay(mut self, max_poll_delay: Duration) -> Self {
+        self.max_poll_delay = max_poll_delay.max(self.poll_delay);
+        self
+    }
+
+    /// Retry connecting to each peer, backing off like the polls, until the
+    /// timeout elapses. Defaults to 3 times the `max_poll_delay`, or 5 minutes
+    /// for a Worker registering with a Coordinator.
+    ///
+    /// A Coordinator accepting registrations waits as long for its Workers to
+    /// register.
+    pub fn connect_timeout(mut self, connect_timeout: Duration) -> Self {
+        self.connect_timeout = Some(connect_timeout);
+        self
+    }
+
+    /// How unreachable or failed Workers are handled. Defaults to
+    /// [`FailurePolicy::FailFast`].
+    ///
+    /// Should only be called by Coordinators
+    pub fn failure_policy(mut self, failure_policy: FailurePolicy) -> Self {
+        self.failure_policy = failure_policy;
+        self
+    }
+
+    /// Should only be called by Workers. Dial the Coordinator at `coordinator`
+    /// and register as this Worker's addr, rather than listening for the
+    /// Coordinator to connect. The Worker's addr is the one the Coordinator
+    /// knows it by, ex: its private ip, and isn't bound.
+    ///
+    /// Only supported over TCP.
+    pub fn register(mut self, coordinator: SocketAddr) -> Self {
+        self.connection = Connection::Register(coordinator);
+        self
+    }
+
+    /// Should only be called by Coordinators. Listen on `listen` for the
+    /// Workers to [`register`](Self::register), rather than connecting to
+    /// them, so the Workers don't need a reachable address.
+    ///
+    /// Workers which don't register in time are handled by the
+    /// [`FailurePolicy`]. Only supported over TCP.
+    pub fn accept_registrations(mut self, listen: SocketAddr) -> Self {
+        self.connection = Connection::AcceptRegistrations(listen);
+        self
+    }
+
+    /// Should only be called by Workers. Run back to back sessions, returning to
+    /// an idle (listening) state once a session is Done and accepting the next
+    /// Coordinator session.
+    ///
+    /// Each session starts from the initial protocol so run parameters shipped by a
+    /// Coordinator only apply to its own session. A failed session is logged.
+    pub async fn run_daemon(self)
+    where
+        P: Send,
+    {
+        for session in 1.. {
+            let builder = self.clone();
+            let result = async {
+                let mut worker = builder.build().await?;
+                worker.run_till_ready().await?;
+                worker.run_till_done().await
+            }
+            .instrument(info_span!("session", session))
+            .await;
+            match result {
+                Ok(()) => info!(session, "session done. awaiting the next Coordinator"),
+                Err(err) => {
+                    error!(session, "session failed: {}", err);
+                    tokio::time::sleep(self.poll_delay).await;
+                }
+            }
         }
     }
 
     pub async fn build(self) -> RussulaResult<Russula<P>> {
+        let peers = self.russula_pair_addr_list.len();
+        if self.connection != Connection::Direct && self.transport != Transport::Tcp {
+            return Err(RussulaError::Usage {
+                dbg: format!("Workers can't register over {}", self.transport),
+            });
+        }
+        let (stream_protocol_list, failed_peers) = match self.connection {
+            Connection::AcceptRegistrations(listen) => self.accept_registrations_on(listen).await?,
+            Connection::Direct | Connection::Register(_) => self.connect_peers().await?,
+        };
+
+        self.failure_policy.check(peers, failed_peers.len())?;
+        Ok(Russula {
+            instance_list: stream_protocol_list,
+            poll_delay: self.poll_delay,
+            max_poll_delay: self.max_poll_delay,
+            transitions: 0,
+            failure_policy: self.failure_policy,
+            peers,
+            failed_peers,
+            events: EventSink::default(),
+        })
+    }
+
+    fn connect_timeout_or_default(&self) -> Duration {
+        self.connect_timeout.unwrap_or(match self.connection {
+            Connection::Register(_) => REGISTER_TIMEOUT,
+            _ => self.max_poll_delay * 3,
+        })
+    }
+
+    #[allow(clippy::type_complexity)]
+    async fn connect_peers(
+        &self,
+    ) -> RussulaResult<(Vec<ProtocolInstance<P>>, BTreeMap<SocketAddr, String>)> {
         let mut stream_protocol_list = Vec::new();
-        for (addr, protocol) in self.russula_pair_addr_list.into_iter() {
+        let mut failed_peers = BTreeMap::new();
+        let connect_timeout = self.connect_timeout_or_default();
+        'peers: for (addr, protocol) in self.russula_pair_addr_list.iter().cloned() {
             let stream;
-            let mut retry_attempts = 3;
+            let deadline = tokio::time::Instant::now() + connect_timeout;
+            let mut delay = self.poll_delay;
             loop {
-                if retry_attempts == 0 {
-                    return Err(RussulaError::NetworkConnectionRefused {
-                        dbg: "Failed to connect to peer".to_string(),
-                    });
-                }
-                match protocol.connect(&addr).await {
+                let connect: Pin<Box<dyn Future<Output = RussulaResult<TransportStream>> + Send>> =
+                    match self.connection {
+                        Connection::Register(coordinator) => {
+                            let transport = self.transport;
+                            Box::pin(async move { transport.register(&coordinator, &addr).await })
+                        }
+                        _ => protocol.connect(&addr, self.transport),
+                    };
+                match connect
+                    .instrument(protocol::peer_span(&addr, &protocol))
+                    .await
+                {
                     Ok(connect) => {
                         stream = connect;
                         break;
                     }
+                    Err(err) if tokio::time::Instant::now() + delay > deadline => {
+                        warn!("Try disabling VPN and check your network connectivity");
+                        let err = RussulaError::PeerTimeout {
+                            peer: addr,
+                            dbg: format!(
+                                "Failed to connect to peer within {:?}: {}",
+                                connect_timeout, err
+                            ),
+                        };
+                        if self.failure_policy == FailurePolicy::FailFast {
+                            return Err(err);
+                        }
+                        warn!(peer = %addr, policy = %self.failure_policy, "excluding unreachable peer");
+                        failed_peers.insert(addr, err.to_string());
+                        continue 'peers;
+                    }
                     Err(err) => {
-                        warn!(
-                            "Failed to connect.. waiting before retrying. Retry attempts left: {}. addr: {} dbg: {}",
-                            retry_attempts, addr, err
+                        debug!(
+                            "Failed to connect.. waiting {:?} before retrying. addr: {} dbg: {}",
+                            delay, addr, err
                         );
-                        warn!("Try disabling VPN and check your network connectivity");
-                        tokio::time::sleep(self.poll_delay).await;
+                        tokio::time::sleep(delay).await;
+                        delay = (delay * 2).min(self.max_poll_delay);
                     }
                 }
-                retry_attempts -= 1
             }
 
-            info!("Coordinator: successfully connected to {}", addr);
+            info!(peer = %addr, "connected");
             stream_protocol_list.push(ProtocolInstance {
                 addr,
                 stream,
                 protocol,
             });
         }
+        Ok((stream_protocol_list, failed_peers))
+    }
 
-        Ok(Russula {
-            instance_list: stream_protocol_list,
-            poll_delay: self.poll_delay,
-        })
+    /// Wait for each peer to register, until the connect timeout elapses.
+    #[allow(clippy::type_complexity)]
+    async fn accept_registrations_on(
+        &self,
+        listen: SocketAddr,
+    ) -> RussulaResult<(Vec<ProtocolInstance<P>>, BTreeMap<SocketAddr, String>)> {
+        let connect_timeout = self.connect_timeout_or_default();
+        let deadline = tokio::time::Instant::now() + connect_timeout;
+        let registrar = Registrar::bind(&listen).await?;
+        let mut pending: BTreeMap<SocketAddr, P> =
+            self.russula_pair_addr_list.iter().cloned().collect();
+        let mut stream_protocol_list = Vec::new();
+        while !pending.is_empty() {
+            let Ok(registered) = tokio::time::timeout_at(deadline, registrar.accept()).await else {
+                break;
+            };
+            let (addr, stream) = match registered {
+                Ok(registered) => registered,
+                Err(err) => {
+                    warn!("rejected registration: {}", err);
+                    continue;
+                }
+            };
+            // ex: a Worker of a previous session, or a retried registration
+            let Some(protocol) = pending.remove(&addr) else {
+                warn!(peer = %addr, "ignoring registration of an unknown or registered peer");
+                continue;
+            };
+            info!(peer = %addr, "registered");
+            stream_protocol_list.push(ProtocolInstance {
+                addr,
+                stream,
+                protocol,
+            });
+        }
+        stream_protocol_list.sort_by_key(|peer| peer.addr);
+
+        let mut failed_peers = BTreeMap::new();
+        for addr in pending.into_keys() {
+            let err = RussulaError::PeerTimeout {
+                peer: addr,
+                dbg: format!("Peer didn't register within {:?}", connect_timeout),
+            };
+            if self.failure_policy == FailurePolicy::FailFast {
+                return Err(err);
+            }
+            warn!(peer = %addr, policy = %self.failure_policy, "excluding unregistered peer");
+            failed_peers.insert(addr, err.to_string());
+        }
+        Ok((stream_protocol_list, failed_peers))
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::russula::netbench::{client, server};
+    use crate::russula::netbench::{client, router, server};
     use futures::future::join_all;
     use std::str::FromStr;
 
     const POLL_DELAY_DURATION: Duration = Duration::from_secs(1);
 
+    // The protocols are driven over in-memory streams with paused time, so the
+    // poll delays complete instantly and no ports are bound.
+    #[tokio::test(start_paused = true)]
+    async fn netbench_server_protocol_memory() {
+        let _ = env_logger::try_init();
+
+        let mut coord_peers = Vec::new();
+        let mut workers = Vec::new();
+        for id in 0..3 {
+            let addr = SocketAddr::from_str(&format!("127.0.0.1:{}", 9400 + id)).unwrap();
+            let (coord_stream, worker_stream) = transport::MemoryStream::pair();
+            coord_peers.push((addr, coord_stream, server::CoordProtocol::new()));
+
+            let protocol = server::WorkerProtocol::new(
+                addr.port().to_string(),
+                netbench::ServerContext::testing(),
+            );
+            workers.push(tokio::spawn(async move {
+                let mut worker = Russula::from_streams(
+                    vec![(addr, worker_stream, protocol)],
+                    POLL_DELAY_DURATION,
+                );
+                worker.run_till_done().await.unwrap();
+                worker
+            }));
+        }
+
+        let mut coord = Russula::from_streams(coord_peers, POLL_DELAY_DURATION);
+        coord.run_till_ready().await.unwrap();
+        coord.run_till_worker_running().await.unwrap();
+        coord.run_till_done().await.unwrap();
+
+        for worker in join_all(workers).await {
+            assert!(worker.unwrap().is_done_state());
+        }
+        // the simulated netbench servers were killed by the workers
+        assert_eq!(coord.worker_exits().len(), 3);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn command_protocol_memory() {
+        let _ = env_logger::try_init();
+
+        let addr = SocketAddr::from_str("127.0.0.1:9700").unwrap();
+        let (coord_stream, worker_stream) = transport::MemoryStream::pair();
+        let params = command::CommandParams {
+            command: Some("echo {id}-{peer0_port}".to_string()),
+            peers: Some(vec![SocketAddr::from_str("127.0.0.1:5201").unwrap()]),
+            ..Default::default()
+        };
+        let coord_protocol = command::CoordProtocol::new().with_params(params);
+
+        let ctx = command::CommandContext::testing("exit 1");
+        let output_file = ctx.output_file("command-memory");
+        let protocol = command::WorkerProtocol::new("command-memory".to_string(), ctx);
+        let worker = tokio::spawn(async move {
+            let mut worker =
+                Russula::from_streams(vec![(addr, worker_stream, protocol)], POLL_DELAY_DURATION);
+            worker.run_till_done().await.unwrap();
+        });
+
+        let mut coord = Russula::from_streams(
+            vec![(addr, coord_stream, coord_protocol)],
+            POLL_DELAY_DURATION,
+        );
+        coord.run_till_ready().await.unwrap();
+        coord.run_till_worker_running().await.unwrap();
+        coord.run_till_done().await.unwrap();
+        worker.await.unwrap();
+
+        // the Coordinator's command overrides the Worker's
+        let exits = coord.worker_exits();
+        assert_eq!(exits.len(), 1);
+        assert!(exits[0].1.success(), "{}", exits[0].1);
+        assert_eq!(
+            std::fs::read_to_string(&output_file).unwrap(),
+            "command-memory-5201\n"
+        );
+        let _ = std::fs::remove_file(output_file);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn russula_events() {
+        use futures::{FutureExt, StreamExt};
+        let _ = env_logger::try_init();
+
+        let addr = SocketAddr::from_str("127.0.0.1:9800").unwrap();
+        let (coord_stream, worker_stream) = transport::MemoryStream::pair();
+        let protocol =
+            server::WorkerProtocol::new("9800".to_string(), netbench::ServerContext::testing());
+        tokio::spawn(async move {
+            let mut worker =
+                Russula::from_streams(vec![(addr, worker_stream, protocol)], POLL_DELAY_DURATION);
+            worker.run_till_done().await
+        });
+
+        let mut coord = Russula::from_streams(
+            vec![(addr, coord_stream, server::CoordProtocol::new())],
+            POLL_DELAY_DURATION,
+        );
+        let mut events = coord.events();
+        coord.run_till_ready().await.unwrap();
+        coord.run_till_worker_running().await.unwrap();
+        coord.run_till_done().await.unwrap();
+
+        assert_eq!(
+            events.next().await,
+            Some(RussulaEvent::PeerConnected { peer: addr })
+        );
+        let mut transitions = Vec::new();
+        while let Some(Some(event)) = events.next().now_or_never() {
+            match event {
+                RussulaEvent::PeerTransitioned { peer, from, to } => {
+                    assert_eq!(peer, addr);
+                    transitions.push((from, to));
+                }
+                event => panic!("unexpected {:?}", event),
+            }
+        }
+        assert!(!transitions.is_empty());
+        // each transition starts where the previous one ended
+        for pair in transitions.windows(2) {
+            assert_eq!(pair[0].1, pair[1].0);
+        }
+        assert_eq!(transitions[0].0, "CheckWorker");
+        assert_eq!(transitions.last().unwrap().1, "Done");
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn pending_peers() {
+        let mut coord_peers = Vec::new();
+        // the Worker of the second peer never runs
+        let mut silent_streams = Vec::new();
+        for id in 0..2 {
+            let addr = SocketAddr::from_str(&format!("127.0.0.1:{}", 9900 + id)).unwrap();
+            let (coord_stream, worker_stream) = transport::MemoryStream::pair();
+            coord_peers.push((addr, coord_stream, server::CoordProtocol::new()));
+            if id == 1 {
+                silent_streams.push(worker_stream);
+                continue;
+            }
+            let protocol =
+                server::WorkerProtocol::new(id.to_string(), netbench::ServerContext::testing());
+            tokio::spawn(async move {
+                let mut worker = Russula::from_streams(
+                    vec![(addr, worker_stream, protocol)],
+                    POLL_DELAY_DURATION,
+                );
+                worker.run_till_done().await
+            });
+        }
+
+        let silent = SocketAddr::from_str("127.0.0.1:9901").unwrap();
+        let mut coord = Russula::from_streams(coord_peers, POLL_DELAY_DURATION);
+        assert_eq!(coord.pending_done_peers().len(), 2);
+        let ready = tokio::time::timeout(Duration::from_secs(60), coord.run_till_ready()).await;
+        assert!(ready.is_err());
+        assert_eq!(coord.pending_ready_peers(), vec![silent]);
+        assert_eq!(coord.pending_done_peers().len(), 2);
+    }
+
+    /// Run a server Coordinator with 3 Workers, one of which is disconnected
+    /// before the Coordinator's first msg.
+    async fn run_server_with_failed_worker(
+        failure_policy: FailurePolicy,
+    ) -> (RussulaResult<()>, Russula<server::CoordProtocol>) {
+        let mut coord_peers = Vec::new();
+        for id in 0..3 {
+            let addr = SocketAddr::from_str(&format!("127.0.0.1:{}", 9600 + id)).unwrap();
+            let coord_chaos = (id == 0).then_some(transport::Chaos {
+                disconnect_after: Some(0),
+                ..Default::default()
+            });
+            let (coord_stream, worker_stream) =
+                transport::MemoryStream::pair_with_chaos(coord_chaos, None);
+            coord_peers.push((addr, coord_stream, server::CoordProtocol::new()));
+
+            let protocol = server::WorkerProtocol::new(
+                addr.port().to_string(),
+                netbench::ServerContext::testing(),
+            );
+            tokio::spawn(async move {
+                let mut worker = Russula::from_streams(
+                    vec![(addr, worker_stream, protocol)],
+                    POLL_DELAY_DURATION,
+                );
+                worker.run_till_done().await
+            });
+        }
+
+        let mut coord = Russula::from_streams(coord_peers, POLL_DELAY_DURATION)
+            .with_failure_policy(failure_policy);
+        let result = async {
+            coord.run_till_ready().await?;
+            coord.run_till_worker_running().await?;
+            coord.run_till_done().await
+        }
+        .await;
+        (result, coord)
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn failure_policy_excludes_failed_workers() {
+        let _ = env_logger::try_init();
+
+        let (result, coord) = run_server_with_failed_worker(FailurePolicy::FailFast).await;
+        assert!(matches!(result, Err(RussulaError::NetworkFail { .. })));
+        assert!(coord.failed_peers().is_empty());
+
+        let (result, coord) =
+            run_server_with_failed_worker(FailurePolicy::ContinueWithQuorum(2)).await;
+        assert!(result.is_ok(), "{:?}", result);
+        assert_eq!(
+            coord.failed_peers().keys().collect::<Vec<_>>(),
+            [&SocketAddr::from_str("127.0.0.1:9600").unwrap()]
+        );
+        assert_eq!(coord.worker_exits().len(), 2);
+
+        let (result, _coord) =
+            run_server_with_failed_worker(FailurePolicy::ContinueWithQuorum(3)).await;
+        assert!(matches!(result, Err(RussulaError::PeersFailed { .. })));
+    }
+
+    /// Run a router Coordinator and Worker over streams which inject the faults.
+    ///
+    /// Returns None if they didn't converge or fail within an hour (of paused time).
+    async fn run_router_with_chaos(
+        coord_chaos: transport::Chaos,
+        worker_chaos: transport::Chaos,
+    ) -> Option<(RussulaResult<()>, RussulaResult<()>)> {
+        let addr = SocketAddr::from_str("127.0.0.1:9500").unwrap();
+        let (coord_stream, worker_stream) =
+            transport::MemoryStream::pair_with_chaos(Some(coord_chaos), Some(worker_chaos));
+        let coord = async move {
+            let mut coord = Russula::from_streams(
+                vec![(addr, coord_stream, router::CoordProtocol::new())],
+                POLL_DELAY_DURATION,
+            );
+            coord.run_till_ready().await?;
+            coord.run_till_worker_running().await?;
+            coord.run_till_done().await
+        };
+        let worker = async move {
+            let protocol = router::WorkerProtocol::new(
+                addr.port().to_string(),
+                netbench::RouterContext::testing(),
+            );
+            let mut worker =
+                Russula::from_streams(vec![(addr, worker_stream, protocol)], POLL_DELAY_DURATION);
+            worker.run_till_done().await
+        };
+        tokio::time::timeout(Duration::from_secs(3600), async {
+            tokio::join!(coord, worker)
+        })
+        .await
+        .ok()
+    }
+
+    // Property: despite dropped, duplicated and delayed msgs and disconnects, the
+    // Coordinator and Worker never hang. Each either reaches Done or fails with a
+    // network error.
+    #[tokio::test(start_paused = true)]
+    async fn russula_chaos() {
+        let _ = env_logger::try_init();
+
+        for seed in 0..100 {
+            let chaos = |seed: u64| transport::Chaos {
+                seed,
+                drop_pct: 20,
+                duplicate_pct: 20,
+                max_delay: POLL_DELAY_DURATION * 2,
+                disconnect_after: seed.is_multiple_of(4).then_some(seed as usize % 10),
+            };
+            let (coord, worker) = run_router_with_chaos(chaos(seed), chaos(seed + 1000))
+                .await
+                .unwrap_or_else(|| panic!("seed {seed}: didn't converge or fail"));
+            for result in [coord, worker] {
+                if let Err(err) = result {
+                    assert!(
+                        matches!(err, RussulaError::NetworkFail { .. }),
+                        "seed {seed}: {err}"
+                    );
+                }
+            }
+        }
+    }
+
+    // Property: duplicated and delayed msgs don't prevent the Coordinator and
+    // Worker from converging.
+    #[tokio::test(start_paused = true)]
+    async fn russula_chaos_converges() {
+        let _ = env_logger::try_init();
+
+        for seed in 0..100 {
+            let chaos = |seed: u64| transport::Chaos {
+                seed,
+                duplicate_pct: 50,
+                max_delay: POLL_DELAY_DURATION * 2,
+                ..Default::default()
+            };
+            let (coord, worker) = run_router_with_chaos(chaos(seed), chaos(seed + 1000))
+                .await
+                .unwrap_or_else(|| panic!("seed {seed}: didn't converge"));
+            assert!(coord.is_ok(), "seed {seed}: {:?}", coord);
+            assert!(worker.is_ok(), "seed {seed}: {:?}", worker);
+        }
+    }
+
     #[tokio::test]
     async fn netbench_server_protocol() {
-        env_logger::init();
+        let _ = env_logger::try_init();
 
         let mut worker_addrs = Vec::new();
         let mut workers = Vec::new();
@@ -237,9 +925,187 @@ mod tests {
         }
     }
 
+    #[tokio::test]
+    async fn netbench_server_protocol_udp() {
+        let _ = env_logger::try_init();
+
+        let mut worker_addrs = Vec::new();
+        let mut workers = Vec::new();
+        for port in [9101, 9102, 9103] {
+            let sock = SocketAddr::from_str(&format!("127.0.0.1:{}", port)).unwrap();
+            let worker = tokio::spawn(async move {
+                let worker = RussulaBuilder::new(
+                    BTreeSet::from_iter([sock]),
+                    server::WorkerProtocol::new(
+                        sock.port().to_string(),
+                        netbench::ServerContext::testing(),
+                    ),
+                    POLL_DELAY_DURATION,
+                )
+                .transport(Transport::Udp);
+                let mut worker = worker.build().await.unwrap();
+                worker.run_till_done().await.unwrap();
+                worker
+            });
+
+            workers.push(worker);
+            worker_addrs.push(sock);
+        }
+
+        let addr = BTreeSet::from_iter(worker_addrs);
+        let coord = RussulaBuilder::new(addr, server::CoordProtocol::new(), POLL_DELAY_DURATION)
+            .transport(Transport::Udp);
+        let mut coord = coord.build().await.unwrap();
+        coord.run_till_ready().await.unwrap();
+        coord.run_till_worker_running().await.unwrap();
+        while coord.poll_done().await.unwrap().is_pending() {}
+
+        let worker_join = join_all(workers).await;
+        for w in worker_join {
+            assert!(w.unwrap().is_done_state());
+        }
+    }
+
+    #[tokio::test]
+    async fn netbench_server_protocol_registration() {
+        let _ = env_logger::try_init();
+
+        let coord_addr = SocketAddr::from_str("127.0.0.1:9400").unwrap();
+        let mut worker_addrs = Vec::new();
+        let mut workers = Vec::new();
+        for port in [9401, 9402] {
+            // the Workers register under their addr rather than binding it
+            let sock = SocketAddr::from_str(&format!("127.0.0.1:{}", port)).unwrap();
+            let worker = tokio::spawn(async move {
+                let worker = RussulaBuilder::new(
+                    BTreeSet::from_iter([sock]),
+                    server::WorkerProtocol::new(
+                        sock.port().to_string(),
+                        netbench::ServerContext::testing(),
+                    ),
+                    POLL_DELAY_DURATION,
+                )
+                .register(coord_addr);
+                let mut worker = worker.build().await.unwrap();
+                worker.run_till_done().await.unwrap();
+                worker
+            });
+
+            workers.push(worker);
+            worker_addrs.push(sock);
+        }
+
+        let addr = BTreeSet::from_iter(worker_addrs.clone());
+        let coord = RussulaBuilder::new(addr, server::CoordProtocol::new(), POLL_DELAY_DURATION)
+            .accept_registrations(coord_addr);
+        let mut coord = coord.build().await.unwrap();
+        assert_eq!(
+            coord
+                .instance_list
+                .iter()
+                .map(|peer| peer.addr)
+                .collect::<Vec<_>>(),
+            worker_addrs
+        );
+        coord.run_till_ready().await.unwrap();
+        coord.run_till_worker_running().await.unwrap();
+        while coord.poll_done().await.unwrap().is_pending() {}
+
+        let worker_join = join_all(workers).await;
+        for w in worker_join {
+            assert!(w.unwrap().is_done_state());
+        }
+
+        // registration is only supported over tcp
+        let udp = RussulaBuilder::new(
+            BTreeSet::from_iter([coord_addr]),
+            server::CoordProtocol::new(),
+            POLL_DELAY_DURATION,
+        )
+        .transport(Transport::Udp)
+        .accept_registrations(coord_addr);
+        assert!(udp.build().await.is_err());
+    }
+
+    #[tokio::test]
+    async fn netbench_worker_daemon() {
+        let _ = env_logger::try_init();
+
+        let sock = SocketAddr::from_str("127.0.0.1:9301").unwrap();
+        let daemon = tokio::spawn(
+            RussulaBuilder::new(
+                BTreeSet::from_iter([sock]),
+                server::WorkerProtocol::new(
+                    sock.port().to_string(),
+                    netbench::ServerContext::testing(),
+                ),
+                POLL_DELAY_DURATION,
+            )
+            .run_daemon(),
+        );
+
+        // the same worker accepts sequential coordinator sessions
+        for _session in 0..2 {
+            let coord = RussulaBuilder::new(
+                BTreeSet::from_iter([sock]),
+                server::CoordProtocol::new(),
+                POLL_DELAY_DURATION,
+            );
+            let mut coord = coord.build().await.unwrap();
+            coord.run_till_ready().await.unwrap();
+            coord.run_till_worker_running().await.unwrap();
+            coord.run_till_done().await.unwrap();
+
+            // wait for the worker to finish notifying Done and listen again
+            tokio::time::sleep(POLL_DELAY_DURATION * 5).await;
+        }
+        assert!(!daemon.is_finished());
+        daemon.abort();
+    }
+
+    #[tokio::test]
+    async fn netbench_router_protocol() {
+        let _ = env_logger::try_init();
+
+        let mut worker_addrs = Vec::new();
+        let mut workers = Vec::new();
+        for port in [9201, 9202] {
+            let sock = SocketAddr::from_str(&format!("127.0.0.1:{}", port)).unwrap();
+            let worker = tokio::spawn(async move {
+                let worker = RussulaBuilder::new(
+                    BTreeSet::from_iter([sock]),
+                    router::WorkerProtocol::new(
+                        sock.port().to_string(),
+                        netbench::RouterContext::testing(),
+                    ),
+                    POLL_DELAY_DURATION,
+                );
+                let mut worker = worker.build().await.unwrap();
+                worker.run_till_done().await.unwrap();
+                worker
+            });
+
+            workers.push(worker);
+            worker_addrs.push(sock);
+        }
+
+        let addr = BTreeSet::from_iter(worker_addrs);
+        let coord = RussulaBuilder::new(addr, router::CoordProtocol::new(), POLL_DELAY_DURATION);
+        let mut coord = coord.build().await.unwrap();
+        coord.run_till_ready().await.unwrap();
+        // routing is configured before the server and client workers are started
+        coord.run_till_worker_running().await.unwrap();
+        while coord.poll_done().await.unwrap().is_pending() {}
+
+        let worker_join = join_all(workers).await;
+        for w in worker_join {
+            assert!(w.unwrap().is_done_state());
+        }
+    }
+
     #[tokio::test]
     async fn netbench_client_protocol() {
-        env_logger::init();
+        let _ = env_logger::try_init();
         let mut worker_addrs = Vec::new();
         let mut workers = Vec::new();
 
@@ -278,7 +1144,7 @@ mod tests {
         let c1 = tokio::spawn(async move {
             let addr = BTreeSet::from_iter(worker_addrs);
 
-            let protocol = client::CoordProtocol::new();
+            let protocol = client::CoordProtocol::new().with_run_at_delay(POLL_DELAY_DURATION * 3);
             let coord = RussulaBuilder::new(addr, protocol, POLL_DELAY_DURATION);
             let mut coord = coord.build().await.unwrap();
             coord.run_till_ready().await.unwrap();
@@ -0,0 +1,373 @@
+// Copyright Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+//! An explicit-state model checker for the Coordinator and Worker state
+//! machines of a protocol.
+//!
+//! The model explores every interleaving of the steps of a Coordinator and
+//! one of its Workers, which exchange the variants of their states over a
+//! reliable and ordered connection:
+//!
+//! - an `AwaitNext` state can notify its peer of its state at any time (ex:
+//!   when its read queue is drained) and transitions once it receives the
+//!   expected variant, ignoring any other msg
+//! - a `UserDriven` or `SelfDriven` state can transition at any time and
+//!   notifies its peer of the next state
+//! - a `Finished` state takes no further steps
+//!
+//! A Coordinator manages each of its Workers with a separate instance of the
+//! protocol, so a pair is representative of a Coordinator with any number of
+//! Workers. Consecutive duplicate msgs are collapsed since the peers only
+//! compare the variant of a msg, which keeps the model finite.
+
+use super::states::{state_variant, StateApi, TransitionStep};
+use std::collections::{BTreeMap, VecDeque};
+
+/// The number of distinct states explored by [`check`].
+#[derive(Debug)]
+pub struct ModelStats {
+    pub states: usize,
+}
+
+#[derive(Clone, Debug)]
+struct System<C, W> {
+    coord: C,
+    worker: W,
+    // msgs sent by the Coordinator which the Worker hasn't read
+    to_worker: VecDeque<String>,
+    // msgs sent by the Worker which the Coordinator hasn't read
+    to_coord: VecDeque<String>,
+}
+
+type Key = (String, String, VecDeque<String>, VecDeque<String>);
+
+impl<C: StateApi, W: StateApi> System<C, W> {
+    fn key(&self) -> Key {
+        (
+            state_key(&self.coord),
+            state_key(&self.worker),
+            self.to_worker.clone(),
+            self.to_coord.clone(),
+        )
+    }
+
+    fn is_done(&self) -> bool {
+        is_finished(&self.coord) && is_finished(&self.worker)
+    }
+
+    /// The systems reachable in one step of either peer, along with a
+    /// description of the step.
+    fn successors(&self) -> Vec<(String, Self)> {
+        let mut next = Vec::new();
+        for (desc, coord, to_worker, to_coord) in
+            steps(&self.coord, &self.to_coord, &self.to_worker)
+        {
+            next.push((
+                format!("coordinator {desc}"),
+                System {
+                    coord,
+                    worker: self.worker.clone(),
+                    to_worker,
+                    to_coord,
+                },
+            ));
+        }
+        for (desc, worker, to_coord, to_worker) in
+            steps(&self.worker, &self.to_worker, &self.to_coord)
+        {
+            next.push((
+                format!("worker {desc}"),
+                System {
+                    coord: self.coord.clone(),
+                    worker,
+                    to_worker,
+                    to_coord,
+                },
+            ));
+        }
+        next
+    }
+}
+
+/// Explore every reachable state of a Coordinator and a Worker, starting at
+/// `coord` and `worker`, and check that both can always reach their Done state.
+///
+/// This rules out deadlocks, where both peers wait for a msg which never
+/// arrives, and livelocks, where the peers keep transitioning without ever
+/// reaching Done. Since the model is finite, a fair scheduling of the steps
+/// then eventually reaches Done. Returns the trace to a stuck state otherwise.
+pub fn check<C: StateApi, W: StateApi>(coord: C, worker: W) -> Result<ModelStats, String> {
+    let init = System {
+        coord,
+        worker,
+        to_worker: VecDeque::new(),
+        to_coord: VecDeque::new(),
+    };
+
+    // breadth first, so that the traces are as short as possible
+    let mut systems: Vec<System<C, W>> = vec![init.clone()];
+    let mut index: BTreeMap<Key, usize> = BTreeMap::from([(init.key(), 0)]);
+    // the step which first reached each system: (parent, step)
+    let mut parents: Vec<Option<(usize, String)>> = vec![None];
+    let mut edges: Vec<Vec<usize>> = Vec::new();
+    let mut queue = VecDeque::from([0]);
+    while let Some(i) = queue.pop_front() {
+        let mut succ = Vec::new();
+        for (desc, next) in systems[i].successors() {
+            let key = next.key();
+            let j = match index.get(&key) {
+                Some(j) => *j,
+                None => {
+                    let j = systems.len();
+                    systems.push(next);
+                    index.insert(key, j);
+                    parents.push(Some((i, desc)));
+                    queue.push_back(j);
+                    j
+                }
+            };
+            succ.push(j);
+        }
+        if edges.len() <= i {
+            edges.resize(i + 1, Vec::new());
+        }
+        edges[i] = succ;
+    }
+    edges.resize(systems.len(), Vec::new());
+
+    // the systems which can reach Done, by walking the steps backwards from it
+    let mut reverse = vec![Vec::new(); systems.len()];
+    for (i, succ) in edges.iter().enumerate() {
+        for j in succ {
+            reverse[*j].push(i);
+        }
+    }
+    let mut can_finish = vec![false; systems.len()];
+    let mut stack: Vec<usize> = (0..systems.len())
+        .filter(|i| systems[*i].is_done())
+        .collect();
+    for i in stack.iter() {
+        can_finish[*i] = true;
+    }
+    while let Some(j) = stack.pop() {
+        for i in reverse[j].iter() {
+            if !can_finish[*i] {
+                can_finish[*i] = true;
+                stack.push(*i);
+            }
+        }
+    }
+
+    // the systems from which either peer can still transition. The others are
+    // stalled: the peers keep notifying each other but wait for msgs which
+    // never arrive
+    let variants = |i: usize| (systems[i].coord.variant(), systems[i].worker.variant());
+    let mut can_progress = vec![false; systems.len()];
+    let mut stack: Vec<usize> = (0..systems.len())
+        .filter(|i| edges[*i].iter().any(|j| variants(*i) != variants(*j)))
+        .collect();
+    for i in stack.iter() {
+        can_progress[*i] = true;
+    }
+    while let Some(j) = stack.pop() {
+        for i in reverse[j].iter() {
+            if !can_progress[*i] && variants(*i) == variants(j) {
+                can_progress[*i] = true;
+                stack.push(*i);
+            }
+        }
+    }
+
+    // systems are numbered in breadth first order, so the first stuck system
+    // has the shortest trace. Report a stalled system if any, since it is
+    // where the protocol gets stuck rather than one of the steps leading to it
+    let stuck = |i: &usize| !can_finish[*i];
+    let stalled = (0..systems.len()).find(|i| stuck(i) && !can_progress[*i]);
+    match stalled.or_else(|| (0..systems.len()).find(stuck)) {
+        None => Ok(ModelStats {
+            states: systems.len(),
+        }),
+        Some(stuck) => {
+            let kind = match can_progress[stuck] {
+                false => "deadlock",
+                true => "livelock",
+            };
+            Err(format!(
+                "{kind}, Done is unreachable from: {}\ntrace:\n{}",
+                describe(&systems[stuck]),
+                trace(&parents, stuck).join("\n")
+            ))
+        }
+    }
+}
+
+// The steps available to a peer: (description, state, outbox, inbox)
+fn steps<S: StateApi>(
+    state: &S,
+    inbox: &VecDeque<String>,
+    outbox: &VecDeque<String>,
+) -> Vec<(String, S, VecDeque<String>, VecDeque<String>)> {
+    let transition = |inbox: VecDeque<String>| {
+        let next = state.next_state();
+        let outbox = send(outbox, &next);
+        (next, outbox, inbox)
+    };
+    match state.transition_step() {
+        TransitionStep::Finished => vec![],
+        TransitionStep::UserDriven | TransitionStep::SelfDriven => {
+            let (next, outbox, inbox) = transition(inbox.clone());
+            vec![(
+                format!("{} -> {}", state.variant(), next.variant()),
+                next,
+                outbox,
+                inbox,
+            )]
+        }
+        TransitionStep::AwaitNext(expected) => {
+            let expected = state_variant(&expected);
+            let mut steps = vec![(
+                format!("notify {}", state.variant()),
+                state.clone(),
+                send(outbox, state),
+                inbox.clone(),
+            )];
+            let mut inbox = inbox.clone();
+            if let Some(msg) = inbox.pop_front() {
+                if Some(&msg) == expected.as_ref() {
+                    let (next, outbox, inbox) = transition(inbox);
+                    steps.push((
+                        format!("recv {msg}: {} -> {}", state.variant(), next.variant()),
+                        next,
+                        outbox,
+                        inbox,
+                    ));
+                } else {
+                    steps.push((
+                        format!("recv {msg}: ignored"),
+                        state.clone(),
+                        outbox.clone(),
+                        inbox,
+                    ));
+                }
+            }
+            steps
+        }
+    }
+}
+
+fn send<S: StateApi>(outbox: &VecDeque<String>, state: &S) -> VecDeque<String> {
+    let mut outbox = outbox.clone();
+    let msg = state.variant();
+    if outbox.back() != Some(&msg) {
+        outbox.push_back(msg);
+    }
+    outbox
+}
+
+fn is_finished<S: StateApi>(state: &S) -> bool {
+    matches!(state.transition_step(), TransitionStep::Finished)
+}
+
+// States only differ by the fields which are known at runtime, so the variant
+// identifies a state of the model
+fn state_key<S: StateApi>(state: &S) -> String {
+    state.variant()
+}
+
+fn describe<C: StateApi, W: StateApi>(system: &System<C, W>) -> String {
+    format!(
+        "coordinator: {}, worker: {}, to worker: {:?}, to coordinator: {:?}",
+        system.coord.variant(),
+        system.worker.variant(),
+        system.to_worker,
+        system.to_coord
+    )
+}
+
+fn trace(parents: &[Option<(usize, String)>], mut i: usize) -> Vec<String> {
+    let mut trace = Vec::new();
+    while let Some((parent, step)) = &parents[i] {
+        trace.push(format!("  {step}"));
+        i = *parent;
+    }
+    trace.reverse();
+    trace
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Clone, Debug, Serialize, Deserialize)]
+    enum Coord {
+        Start,
+        Stop,
+        Done,
+    }
+
+    // A Worker which waits for a msg the Coordinator never sends
+    #[derive(Clone, Debug, Serialize, Deserialize)]
+    enum Worker {
+        WaitStart,
+        WaitKill,
+        Done,
+    }
+
+    impl StateApi for Coord {
+        fn name_prefix(&self) -> String {
+            "coord".to_string()
+        }
+
+        fn transition_step(&self) -> TransitionStep {
+            match self {
+                Coord::Start => TransitionStep::AwaitNext(Worker::WaitKill.as_bytes()),
+                Coord::Stop => TransitionStep::AwaitNext(Worker::Done.as_bytes()),
+                Coord::Done => TransitionStep::Finished,
+            }
+        }
+
+        fn next_state(&self) -> Self {
+            match self {
+                Coord::Start => Coord::Stop,
+                Coord::Stop | Coord::Done => Coord::Done,
+            }
+        }
+    }
+
+    impl StateApi for Worker {
+        fn name_prefix(&self) -> String {
+            "worker".to_string()
+        }
+
+        fn transition_step(&self) -> TransitionStep {
+            match self {
+                Worker::WaitStart => TransitionStep::AwaitNext(Coord::Start.as_bytes()),
+                Worker::WaitKill => TransitionStep::AwaitNext(Coord::Done.as_bytes()),
+                Worker::Done => TransitionStep::Finished,
+            }
+        }
+
+        fn next_state(&self) -> Self {
+            match self {
+                Worker::WaitStart => Worker::WaitKill,
+                Worker::WaitKill | Worker::Done => Worker::Done,
+            }
+        }
+    }
+
+    #[test]
+    fn stuck_protocol() {
+        let err = check(Coord::Start, Worker::WaitStart).unwrap_err();
+        assert!(
+            err.starts_with(
+                "deadlock, Done is unreachable from: coordinator: Stop, worker: WaitKill"
+            ),
+            "{err}"
+        );
+        assert!(
+            err.contains("coordinator recv WaitKill: Start -> Stop"),
+            "{err}"
+        );
+    }
+}
@@ -1,13 +1,34 @@
 // Copyright Amazon.com, Inc. or its affiliates. All Rights Reserved.
 // SPDX-License-Identifier: Apache-2.0
 
-use std::{net::SocketAddr, path::PathBuf};
+use crate::{
+    duration::parse_duration,
+    russula::{RussulaError, RussulaResult},
+};
+use core::{str::FromStr, time::Duration};
+use serde::{Deserialize, Serialize};
+use std::{
+    fs::File,
+    net::SocketAddr,
+    path::PathBuf,
+    process::{Command, Stdio},
+    time::{Instant, SystemTime, UNIX_EPOCH},
+};
 use structopt::{clap::arg_enum, StructOpt};
+pub(crate) use supervisor::Supervisor;
+use tracing::info;
 
 mod client_coord;
 mod client_worker;
+mod router_coord;
+mod router_worker;
+mod run_progress;
 mod server_coord;
 mod server_worker;
+mod supervisor;
+
+pub use run_progress::RunProgress;
+pub use supervisor::ProcessExit;
 
 #[derive(StructOpt, Debug, Clone)]
 pub struct ClientContext {
@@ -18,8 +39,9 @@ pub struct ClientContext {
     #[structopt(long, default_value = "/home/ec2-user/bin")]
     netbench_path: PathBuf,
 
+    // The netbench driver. Can also be specified by the Coordinator.
     #[structopt(long)]
-    driver: String,
+    driver: Option<String>,
 
     // The name of the scenario file.
     //
@@ -30,6 +52,60 @@ pub struct ClientContext {
     // The list of Server to connect to
     #[structopt(long)]
     netbench_servers: Vec<SocketAddr>,
+
+    // The number of netbench drivers to run concurrently, each on its own netbench
+    // port. Can also be specified by the Coordinator.
+    #[structopt(long, default_value = "1")]
+    driver_instances: u16,
+
+    // The ec2 instance id, used to name the netbench output file so that results
+    // from multiple hosts don't overwrite each other.
+    #[structopt(long)]
+    instance_id: Option<String>,
+
+    // Sample system metrics (cpu, memory, network) at this interval while netbench
+    // is running. Disabled if not specified.
+    #[structopt(long, parse(try_from_str = parse_duration))]
+    sys_metrics_interval: Option<Duration>,
+
+    // Profile the netbench driver while it's running. ex: perf
+    #[structopt(long)]
+    profile: Option<Profiler>,
+
+    // Capture the netbench traffic with tcpdump while netbench is running.
+    #[structopt(long)]
+    capture_pcap: bool,
+
+    // How often the netbench collector samples the driver. Defaults to the
+    // collector's default.
+    #[structopt(long, parse(try_from_str = parse_duration))]
+    collector_interval: Option<Duration>,
+
+    // Disable the netbench collector's bpf probes, which reduces the size of the
+    // output.
+    #[structopt(long)]
+    collector_disable_bpf: bool,
+
+    // Discard the netbench results, ex: of a warm-up run. Can also be specified by
+    // the Coordinator.
+    #[structopt(long)]
+    warmup: bool,
+
+    // Stop the drivers once this much time has elapsed, ex: for a soak run. Can
+    // also be specified by the Coordinator.
+    #[structopt(long, parse(try_from_str = parse_duration))]
+    duration: Option<Duration>,
+
+    // Upload the output appended since the last upload to this s3 prefix while
+    // netbench is running, ex: for a soak run. Can also be specified by the
+    // Coordinator.
+    #[structopt(long)]
+    stream_to: Option<String>,
+
+    // How often the output is uploaded to `stream_to`. Can also be specified by
+    // the Coordinator.
+    #[structopt(long, parse(try_from_str = parse_duration))]
+    stream_interval: Option<Duration>,
 }
 
 #[derive(StructOpt, Debug, Clone)]
@@ -41,8 +117,9 @@ pub struct ServerContext {
     #[structopt(long, default_value = "/home/ec2-user/bin")]
     netbench_path: PathBuf,
 
+    // The netbench driver. Can also be specified by the Coordinator.
     #[structopt(long)]
-    driver: String,
+    driver: Option<String>,
 
     // The name of the scenario file.
     //
@@ -52,36 +129,689 @@ pub struct ServerContext {
 
     #[structopt(long, default_value = "4433")]
     netbench_port: u16,
+
+    // The number of netbench drivers to run concurrently, each on its own netbench
+    // port. Can also be specified by the Coordinator.
+    #[structopt(long, default_value = "1")]
+    driver_instances: u16,
+
+    // The ec2 instance id, used to name the netbench output file so that results
+    // from multiple hosts don't overwrite each other.
+    #[structopt(long)]
+    instance_id: Option<String>,
+
+    // Sample system metrics (cpu, memory, network) at this interval while netbench
+    // is running. Disabled if not specified.
+    #[structopt(long, parse(try_from_str = parse_duration))]
+    sys_metrics_interval: Option<Duration>,
+
+    // Profile the netbench driver while it's running. ex: perf
+    #[structopt(long)]
+    profile: Option<Profiler>,
+
+    // Capture the netbench traffic with tcpdump while netbench is running.
+    #[structopt(long)]
+    capture_pcap: bool,
+
+    // How often the netbench collector samples the driver. Defaults to the
+    // collector's default.
+    #[structopt(long, parse(try_from_str = parse_duration))]
+    collector_interval: Option<Duration>,
+
+    // Disable the netbench collector's bpf probes, which reduces the size of the
+    // output.
+    #[structopt(long)]
+    collector_disable_bpf: bool,
+
+    // Discard the netbench results, ex: of a warm-up run. Can also be specified by
+    // the Coordinator.
+    #[structopt(long)]
+    warmup: bool,
+
+    // Upload the output appended since the last upload to this s3 prefix while
+    // netbench is running, ex: for a soak run. Can also be specified by the
+    // Coordinator.
+    #[structopt(long)]
+    stream_to: Option<String>,
+
+    // How often the output is uploaded to `stream_to`. Can also be specified by
+    // the Coordinator.
+    #[structopt(long, parse(try_from_str = parse_duration))]
+    stream_interval: Option<Duration>,
+}
+
+#[derive(StructOpt, Debug, Clone)]
+pub struct RouterContext {
+    #[structopt(long)]
+    testing: bool,
+}
+
+impl RouterContext {
+    #[cfg(test)]
+    pub fn testing() -> Self {
+        RouterContext { testing: true }
+    }
+
+    /// Enable or disable forwarding (and NAT) of the client traffic through this
+    /// host with `scripts/router.sh`.
+    pub(crate) fn configure_forwarding(&self, enable: bool) -> RussulaResult<()> {
+        let action = if enable { "enable" } else { "disable" };
+        if self.testing {
+            info!("testing: skip {} forwarding", action);
+            return Ok(());
+        }
+
+        let output = Command::new("bash")
+            .args(["scripts/router.sh", action])
+            .output()
+            .map_err(|err| RussulaError::Usage {
+                dbg: format!("Failed to {} forwarding: {}", action, err),
+            })?;
+        if !output.status.success() {
+            return Err(RussulaError::Usage {
+                dbg: format!(
+                    "Failed to {} forwarding: {}",
+                    action,
+                    String::from_utf8_lossy(&output.stderr)
+                ),
+            });
+        }
+        Ok(())
+    }
 }
 
 impl ServerContext {
+    /// Override the command line arguments with the Coordinator's run parameters.
+    pub(crate) fn apply(&mut self, params: &RunParams) {
+        if let Some(driver) = &params.driver {
+            self.driver = Some(driver.clone());
+        }
+        if let Some(scenario) = &params.scenario {
+            self.scenario = scenario.clone();
+        }
+        if let Some(netbench_port) = params.netbench_port {
+            self.netbench_port = netbench_port;
+        }
+        if let Some(driver_instances) = params.driver_instances {
+            self.driver_instances = driver_instances;
+        }
+        if let Some(warmup) = params.warmup {
+            self.warmup = warmup;
+        }
+        if let Some(stream_to) = &params.stream_to {
+            self.stream_to = Some(stream_to.clone());
+        }
+        if let Some(stream_interval) = params.stream_interval {
+            self.stream_interval = Some(stream_interval);
+        }
+    }
+
+    pub(crate) fn driver(&self) -> RussulaResult<&str> {
+        required_driver(&self.driver)
+    }
+
+    pub(crate) fn driver_instances(&self) -> RussulaResult<u16> {
+        valid_driver_instances(self.driver_instances)
+    }
+
+    /// Each driver instance listens on its own port, starting at `netbench_port`.
+    pub(crate) fn netbench_port(&self, instance: u16) -> u16 {
+        self.netbench_port + instance
+    }
+
     #[cfg(test)]
     pub fn testing() -> Self {
         ServerContext {
             netbench_path: "".into(),
-            driver: "".to_string(),
+            driver: None,
             scenario: "".to_string(),
             testing: true,
             netbench_port: 4433,
+            driver_instances: 1,
+            instance_id: None,
+            sys_metrics_interval: None,
+            profile: None,
+            capture_pcap: false,
+            collector_interval: None,
+            collector_disable_bpf: false,
+            warmup: false,
+            stream_to: None,
+            stream_interval: None,
+        }
+    }
+
+    /// Spawn tcpdump if enabled.
+    pub(crate) fn spawn_pcap_capture(&self, worker_id: &str) -> RussulaResult<Option<Supervisor>> {
+        if !self.capture_pcap {
+            return Ok(None);
+        }
+        let filter = format!(
+            "portrange {}-{}",
+            self.netbench_port(0),
+            self.netbench_port(self.driver_instances.saturating_sub(1))
+        );
+        spawn_pcap_capture(&self.output_file(worker_id, 0), &filter).map(Some)
+    }
+
+    /// Where a driver instance writes its results. The results of a warm-up run
+    /// are discarded.
+    pub(crate) fn netbench_output(&self, worker_id: &str, instance: u16) -> Stdio {
+        netbench_output(self.warmup, &self.output_file(worker_id, instance))
+    }
+
+    /// Options for the netbench collector, which precede the driver.
+    pub(crate) fn collector_args(&self) -> Vec<String> {
+        collector_args(self.collector_interval, self.collector_disable_bpf)
+    }
+
+    /// The netbench output file of a driver instance. ex: server-i-0123-s2n-quic.json
+    pub(crate) fn output_file(&self, worker_id: &str, instance: u16) -> String {
+        let host_id = self.instance_id.as_deref().unwrap_or(worker_id);
+        netbench_output_file(
+            "server",
+            &instance_host_id(host_id, instance),
+            self.driver.as_deref().unwrap_or_default(),
+        )
+    }
+
+    /// The progress of the driver instances while they run. The output of a
+    /// warm-up run is discarded so it has no progress.
+    pub(crate) fn run_progress(&self, worker_id: &str) -> Option<RunProgress> {
+        if self.warmup || self.testing {
+            return None;
         }
+        let output_files: Vec<String> = (0..self.driver_instances)
+            .map(|instance| self.output_file(worker_id, instance))
+            .collect();
+        RunProgress::read(&output_files)
+    }
+
+    /// Spawn the system metrics sidecar if enabled.
+    pub(crate) fn spawn_sys_metrics(&self, worker_id: &str) -> RussulaResult<Option<Supervisor>> {
+        self.sys_metrics_interval
+            .map(|interval| spawn_sys_metrics(&self.output_file(worker_id, 0), interval))
+            .transpose()
+    }
+
+    /// Spawn the profiler if enabled.
+    pub(crate) fn spawn_profiler(&self, worker_id: &str) -> RussulaResult<Option<Supervisor>> {
+        self.profile
+            .map(|profiler| profiler.spawn(&self.output_file(worker_id, 0)))
+            .transpose()
+    }
+
+    /// Spawn the result stream if enabled.
+    pub(crate) fn spawn_result_stream(&self, worker_id: &str) -> RussulaResult<Option<Supervisor>> {
+        let (Some(stream_to), Some(interval)) = (&self.stream_to, self.stream_interval) else {
+            return Ok(None);
+        };
+        let output_files: Vec<String> = (0..self.driver_instances)
+            .map(|instance| self.output_file(worker_id, instance))
+            .collect();
+        spawn_result_stream(
+            &self.output_file(worker_id, 0),
+            stream_to,
+            interval,
+            &streamed_files(&output_files, self.sys_metrics_interval.is_some()),
+        )
+        .map(Some)
     }
 }
 
 impl ClientContext {
+    /// Override the command line arguments with the Coordinator's run parameters.
+    pub(crate) fn apply(&mut self, params: &RunParams) {
+        if let Some(driver) = &params.driver {
+            self.driver = Some(driver.clone());
+        }
+        if let Some(scenario) = &params.scenario {
+            self.scenario = scenario.clone();
+        }
+        if let Some(netbench_servers) = &params.netbench_servers {
+            self.netbench_servers = netbench_servers.clone();
+        }
+        if let Some(duration) = params.duration {
+            self.duration = Some(duration);
+        }
+        if let Some(driver_instances) = params.driver_instances {
+            self.driver_instances = driver_instances;
+        }
+        if let Some(warmup) = params.warmup {
+            self.warmup = warmup;
+        }
+        if let Some(stream_to) = &params.stream_to {
+            self.stream_to = Some(stream_to.clone());
+        }
+        if let Some(stream_interval) = params.stream_interval {
+            self.stream_interval = Some(stream_interval);
+        }
+    }
+
+    pub(crate) fn driver(&self) -> RussulaResult<&str> {
+        required_driver(&self.driver)
+    }
+
+    pub(crate) fn driver_instances(&self) -> RussulaResult<u16> {
+        valid_driver_instances(self.driver_instances)
+    }
+
+    /// The servers for a driver instance. Client driver instance N connects to
+    /// server driver instance N on each server host.
+    pub(crate) fn netbench_servers(&self, instance: u16) -> Vec<SocketAddr> {
+        self.netbench_servers
+            .iter()
+            .map(|server| SocketAddr::new(server.ip(), server.port() + instance))
+            .collect()
+    }
+
     #[cfg(test)]
     pub fn testing() -> Self {
         ClientContext {
             netbench_servers: vec![],
             netbench_path: "".into(),
-            driver: "".to_string(),
+            driver: None,
             scenario: "".to_string(),
             testing: true,
+            driver_instances: 1,
+            instance_id: None,
+            sys_metrics_interval: None,
+            profile: None,
+            capture_pcap: false,
+            collector_interval: None,
+            collector_disable_bpf: false,
+            warmup: false,
+            duration: None,
+            stream_to: None,
+            stream_interval: None,
         }
     }
+
+    /// The time at which the drivers of a duration bounded run are stopped.
+    pub(crate) fn run_until(&self) -> Option<Instant> {
+        self.duration.map(|duration| Instant::now() + duration)
+    }
+
+    /// Spawn tcpdump if enabled.
+    pub(crate) fn spawn_pcap_capture(&self, worker_id: &str) -> RussulaResult<Option<Supervisor>> {
+        if !self.capture_pcap {
+            return Ok(None);
+        }
+        let filter = self
+            .netbench_servers
+            .iter()
+            .map(|server| {
+                format!(
+                    "(host {} and portrange {}-{})",
+                    server.ip(),
+                    server.port(),
+                    server.port() + self.driver_instances.saturating_sub(1)
+                )
+            })
+            .collect::<Vec<String>>()
+            .join(" or ");
+        spawn_pcap_capture(&self.output_file(worker_id, 0), &filter).map(Some)
+    }
+
+    /// Where a driver instance writes its results. The results of a warm-up run
+    /// are discarded.
+    pub(crate) fn netbench_output(&self, worker_id: &str, instance: u16) -> Stdio {
+        netbench_output(self.warmup, &self.output_file(worker_id, instance))
+    }
+
+    /// Options for the netbench collector, which precede the driver.
+    pub(crate) fn collector_args(&self) -> Vec<String> {
+        collector_args(self.collector_interval, self.collector_disable_bpf)
+    }
+
+    /// The netbench output file of a driver instance. ex: client-i-0123-s2n-quic.json
+    pub(crate) fn output_file(&self, worker_id: &str, instance: u16) -> String {
+        let host_id = self.instance_id.as_deref().unwrap_or(worker_id);
+        netbench_output_file(
+            "client",
+            &instance_host_id(host_id, instance),
+            self.driver.as_deref().unwrap_or_default(),
+        )
+    }
+
+    /// The progress of the driver instances while they run. The output of a
+    /// warm-up run is discarded so it has no progress.
+    pub(crate) fn run_progress(&self, worker_id: &str) -> Option<RunProgress> {
+        if self.warmup || self.testing {
+            return None;
+        }
+        let output_files: Vec<String> = (0..self.driver_instances)
+            .map(|instance| self.output_file(worker_id, instance))
+            .collect();
+        RunProgress::read(&output_files)
+    }
+
+    /// Spawn the system metrics sidecar if enabled.
+    pub(crate) fn spawn_sys_metrics(&self, worker_id: &str) -> RussulaResult<Option<Supervisor>> {
+        self.sys_metrics_interval
+            .map(|interval| spawn_sys_metrics(&self.output_file(worker_id, 0), interval))
+            .transpose()
+    }
+
+    /// Spawn the profiler if enabled.
+    pub(crate) fn spawn_profiler(&self, worker_id: &str) -> RussulaResult<Option<Supervisor>> {
+        self.profile
+            .map(|profiler| profiler.spawn(&self.output_file(worker_id, 0)))
+            .transpose()
+    }
+
+    /// Spawn the result stream if enabled.
+    pub(crate) fn spawn_result_stream(&self, worker_id: &str) -> RussulaResult<Option<Supervisor>> {
+        let (Some(stream_to), Some(interval)) = (&self.stream_to, self.stream_interval) else {
+            return Ok(None);
+        };
+        let output_files: Vec<String> = (0..self.driver_instances)
+            .map(|instance| self.output_file(worker_id, instance))
+            .collect();
+        spawn_result_stream(
+            &self.output_file(worker_id, 0),
+            stream_to,
+            interval,
+            &streamed_files(&output_files, self.sys_metrics_interval.is_some()),
+        )
+        .map(Some)
+    }
 }
 
-// CheckWorker   --------->  WaitCoordInit
-//                              |
+/// Run parameters shipped by the Coordinator to the Workers with the
+/// CheckWorker state. Parameters which are set override the Worker's command
+/// line arguments, which allows reconfiguring a Worker between runs.
+#[derive(StructOpt, Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RunParams {
+    // The netbench driver. ex: s2n-netbench-driver-server-s2n-quic
+    #[structopt(long)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub driver: Option<String>,
+
+    // The name of the scenario file. ex: request_response.json
+    #[structopt(long)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub scenario: Option<String>,
+
+    // The port the netbench server listens on. Only used by server Workers
+    #[structopt(long)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub netbench_port: Option<u16>,
+
+    // The netbench servers to connect to. Only used by client Workers
+    #[structopt(long)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub netbench_servers: Option<Vec<SocketAddr>>,
+
+    // The number of netbench drivers to run concurrently on each Worker
+    #[structopt(long)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub driver_instances: Option<u16>,
+
+    // Run netbench without keeping its results or running the sidecars, to warm
+    // up the hosts before the measured run
+    #[structopt(long)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub warmup: Option<bool>,
+
+    // Stop the client drivers once this much time has elapsed rather than when
+    // the scenario completes. Only used by client Workers
+    #[structopt(long, parse(try_from_str = parse_duration))]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub duration: Option<Duration>,
+
+    // Upload the output to this s3 prefix in chunks while netbench is running.
+    // ex: s3://<bucket>/<unique_id>/streams/<result_key>/<driver>
+    #[structopt(long)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stream_to: Option<String>,
+
+    // How often a chunk of the output is uploaded to `stream_to`
+    #[structopt(long, parse(try_from_str = parse_duration))]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stream_interval: Option<Duration>,
+}
+
+// The exit status of each driver is reported to the Coordinator, which bounds
+// the number of drivers by the russula msg size.
+pub const MAX_DRIVER_INSTANCES: u16 = 8;
+
+fn valid_driver_instances(driver_instances: u16) -> RussulaResult<u16> {
+    if !(1..=MAX_DRIVER_INSTANCES).contains(&driver_instances) {
+        return Err(RussulaError::Usage {
+            dbg: format!(
+                "driver instances must be 1-{}: {}",
+                MAX_DRIVER_INSTANCES, driver_instances
+            ),
+        });
+    }
+    Ok(driver_instances)
+}
+
+// The first driver instance keeps the host id so that single driver runs are
+// named as before. ex: i-0123, i-0123.1
+fn instance_host_id(host_id: &str, instance: u16) -> String {
+    match instance {
+        0 => host_id.to_string(),
+        _ => format!("{host_id}.{instance}"),
+    }
+}
+
+fn required_driver(driver: &Option<String>) -> RussulaResult<&str> {
+    driver.as_deref().ok_or_else(|| RussulaError::Usage {
+        dbg: "netbench driver not specified by the command line or the Coordinator".to_string(),
+    })
+}
+
+/// The driver name without the netbench prefix.
+///
+/// ex: s2n-netbench-driver-server-s2n-quic -> server-s2n-quic
+pub fn driver_short_name(driver: &str) -> &str {
+    driver
+        .trim_start_matches("s2n-netbench-driver-")
+        .trim_start_matches("netbench-driver-")
+        .trim_end_matches(".json")
+}
+
+fn netbench_output_file(endpoint: &str, host_id: &str, driver: &str) -> String {
+    format!("{endpoint}-{host_id}-{}.json", driver_short_name(driver))
+}
+
+fn collector_args(interval: Option<Duration>, disable_bpf: bool) -> Vec<String> {
+    let mut args = Vec::new();
+    if let Some(interval) = interval {
+        args.push("--interval".to_string());
+        args.push(humantime::format_duration(interval).to_string());
+    }
+    if disable_bpf {
+        args.push("--disable-bpf".to_string());
+    }
+    args
+}
+
+fn netbench_output(warmup: bool, output_file: &str) -> Stdio {
+    if warmup {
+        return Stdio::null();
+    }
+    File::create(output_file)
+        .expect("failed to open log")
+        .into()
+}
+
+/// The stderr log of a netbench driver instance. ex: target/server-w-9000.1.stderr
+pub(crate) fn stderr_log(name: &str, instance: u16) -> PathBuf {
+    PathBuf::from(format!(
+        "target/{}.stderr",
+        instance_host_id(name, instance)
+    ))
+}
+
+/// The system metrics file which accompanies a netbench output file.
+///
+/// ex: client-i-0123-s2n-quic.sysmetrics.csv
+pub fn sys_metrics_file(output_file: &str) -> String {
+    format!("{}.sysmetrics.csv", output_file.trim_end_matches(".json"))
+}
+
+/// Profile the netbench driver while it's running.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Profiler {
+    Perf,
+}
+
+impl Profiler {
+    // perf writes the profile once it receives SIGTERM, which can take a while
+    pub(crate) const STOP_GRACE_PERIOD: Duration = Duration::from_secs(30);
+
+    pub fn as_str(&self) -> &str {
+        match self {
+            Profiler::Perf => "perf",
+        }
+    }
+
+    fn spawn(&self, output_file: &str) -> RussulaResult<Supervisor> {
+        let profile_file = profile_file(output_file);
+        let mut cmd = match self {
+            Profiler::Perf => {
+                let mut cmd = Command::new("perf");
+                // Record system wide since the driver is launched by the collector
+                cmd.args(["record", "-F", "99", "-a", "-g", "-o", &profile_file]);
+                cmd
+            }
+        };
+        cmd.stdout(Stdio::null());
+        info!("profiling with {} to {}", self, profile_file);
+        Supervisor::spawn(cmd, &PathBuf::from(format!("target/{profile_file}.stderr")))
+    }
+}
+
+impl std::fmt::Display for Profiler {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+impl FromStr for Profiler {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "perf" => Ok(Profiler::Perf),
+            _ => Err(format!("unsupported profiler: {}. expected perf", s)),
+        }
+    }
+}
+
+/// The profile which accompanies a netbench output file. Converted to a
+/// flamegraph when the results are uploaded.
+///
+/// ex: client-i-0123-s2n-quic.perf.data
+pub fn profile_file(output_file: &str) -> String {
+    format!("{}.perf.data", output_file.trim_end_matches(".json"))
+}
+
+/// The pcap which accompanies a netbench output file. tcpdump appends the
+/// rotation index to the file name.
+///
+/// ex: client-i-0123-s2n-quic.pcap0
+pub fn pcap_file(output_file: &str) -> String {
+    format!("{}.pcap", output_file.trim_end_matches(".json"))
+}
+
+/// The files of a Worker which are streamed: the netbench output of each driver
+/// instance and the system metrics if they are sampled.
+pub fn streamed_files(output_files: &[String], sys_metrics: bool) -> Vec<String> {
+    let mut files = output_files.to_vec();
+    if let (true, Some(output_file)) = (sys_metrics, output_files.first()) {
+        files.push(sys_metrics_file(output_file));
+    }
+    files
+}
+
+/// The file which a streamed chunk is part of, and the chunk's sequence number.
+///
+/// ex: client-i-0123-s2n-quic.json.00002 -> (client-i-0123-s2n-quic.json, 2)
+pub fn stream_chunk(chunk: &str) -> Option<(&str, u32)> {
+    let (file, seq) = chunk.rsplit_once('.')?;
+    if seq.len() != 5 {
+        return None;
+    }
+    Some((file, seq.parse().ok()?))
+}
+
+// The result stream uploads the remainder of the output once it receives SIGTERM
+pub(crate) const RESULT_STREAM_STOP_GRACE_PERIOD: Duration = Duration::from_secs(60);
+
+// How long to wait for the netbench process to exit after SIGTERM before sending SIGKILL
+pub(crate) const KILL_GRACE_PERIOD: Duration = Duration::from_secs(5);
+
+// tcpdump flushes the capture once it receives SIGTERM
+pub(crate) const PCAP_STOP_GRACE_PERIOD: Duration = Duration::from_secs(5);
+// Rotate the capture every 100MB, keeping at most 10 files (1GB)
+const PCAP_FILE_SIZE_MB: u32 = 100;
+const PCAP_FILE_COUNT: u32 = 10;
+// Only capture the headers
+const PCAP_SNAPLEN: u32 = 256;
+
+fn spawn_pcap_capture(output_file: &str, filter: &str) -> RussulaResult<Supervisor> {
+    let pcap_file = pcap_file(output_file);
+    let mut cmd = Command::new("tcpdump");
+    cmd.args([
+        "-i",
+        "any",
+        "-s",
+        &PCAP_SNAPLEN.to_string(),
+        "-C",
+        &PCAP_FILE_SIZE_MB.to_string(),
+        "-W",
+        &PCAP_FILE_COUNT.to_string(),
+        "-w",
+        &pcap_file,
+        filter,
+    ])
+    .stdout(Stdio::null());
+    info!("capturing '{}' to {}", filter, pcap_file);
+    Supervisor::spawn(cmd, &PathBuf::from(format!("target/{pcap_file}.stderr")))
+}
+
+fn spawn_sys_metrics(output_file: &str, interval: Duration) -> RussulaResult<Supervisor> {
+    let sys_metrics_file = sys_metrics_file(output_file);
+    let mut cmd = Command::new("bash");
+    cmd.args([
+        "scripts/sys_metrics.sh",
+        &sys_metrics_file,
+        // vmstat only accepts whole seconds
+        &interval.as_secs().max(1).to_string(),
+    ]);
+    info!("sampling system metrics to {}", sys_metrics_file);
+    Supervisor::spawn(
+        cmd,
+        &PathBuf::from(format!("target/{sys_metrics_file}.stderr")),
+    )
+}
+
+fn spawn_result_stream(
+    output_file: &str,
+    stream_to: &str,
+    interval: Duration,
+    files: &[String],
+) -> RussulaResult<Supervisor> {
+    let mut cmd = Command::new("bash");
+    cmd.args([
+        "scripts/stream_results.sh",
+        stream_to,
+        &interval.as_secs().max(1).to_string(),
+    ])
+    .args(files)
+    .stdout(Stdio::null());
+    info!("streaming {:?} to {}", files, stream_to);
+    Supervisor::spawn(
+        cmd,
+        &PathBuf::from(format!("target/{output_file}.stream.stderr")),
+    )
+}
+
+// CheckWorker(params) --->  WaitCoordInit
+//                              | (apply params)
 //                              v
 // CheckWorker   <---------  Ready
 //    |
@@ -118,8 +848,28 @@ pub mod server {
     pub use super::{server_coord::*, server_worker::*};
 }
 
-// CheckWorker   --------->  WaitCoordInit
-//                              |
+/// Milliseconds since the unix epoch.
+///
+/// Used to communicate a wall-clock instant between hosts, which assumes that
+/// the host clocks are synchronized (NTP).
+pub(crate) fn unix_millis(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as u64
+}
+
+/// Sleep until the wall-clock instant `start_at` (milliseconds since the unix epoch).
+///
+/// Returns immediately if the instant has already passed.
+pub(crate) async fn sleep_until_unix_millis(start_at: u64) {
+    let start_at = UNIX_EPOCH + Duration::from_millis(start_at);
+    if let Ok(remaining) = start_at.duration_since(SystemTime::now()) {
+        tokio::time::sleep(remaining).await;
+    }
+}
+
+// CheckWorker(params) --->  WaitCoordInit
+//                              | (apply params)
 //                              v
 // CheckWorker   <---------  Ready
 //    |
@@ -127,13 +877,13 @@ pub mod server {
 // Ready
 //    | (user)
 //    v
-// RunWorker     --------->  Ready
+// RunAt(time)   --------->  Ready
 //                              |
 //                              v
 //                           Run
-//                              | (self)
+//                              | (self: wait till time)
 //                              v
-// RunWorker     <---------  Running
+// RunAt(time)   <---------  Running
 //    |
 //    v
 // WorkersRunning ---------> Running
@@ -152,3 +902,170 @@ pub mod server {
 pub mod client {
     pub use super::{client_coord::*, client_worker::*};
 }
+
+// CheckWorker     --------->  WaitCoordInit
+//                                |
+//                                v
+// CheckWorker     <---------  Ready
+//    |
+//    v
+// Ready
+//    | (user)
+//    v
+// ConfigureWorker --------->  Ready
+//                                |
+//                                v
+//                             Configure
+//                                | (self: enable forwarding)
+//                                v
+// ConfigureWorker <---------  RoutingAwaitTeardown
+//    |
+//    v
+// WorkersRouting
+//    | (user)
+//    v
+// TeardownWorker  --------->  RoutingAwaitTeardown
+//                                |
+//                                v
+//                             Teardown
+//                                | (self: disable forwarding)
+//                                v
+// WorkerTornDown  <---------  Stopped
+//    |
+//    v
+// Done            --------->  Stopped
+//                                |
+//                                v
+//                             Done
+pub mod router {
+    pub use super::{router_coord::*, router_worker::*};
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::russula::{network_utils::Msg, StateApi};
+
+    #[test]
+    fn output_file_name() {
+        let mut ctx = ClientContext::testing();
+        ctx.driver = Some("s2n-netbench-driver-client-s2n-quic".to_string());
+        assert_eq!(
+            ctx.output_file("9000", 0),
+            "client-9000-client-s2n-quic.json"
+        );
+
+        ctx.instance_id = Some("i-0123".to_string());
+        assert_eq!(
+            ctx.output_file("9000", 0),
+            "client-i-0123-client-s2n-quic.json"
+        );
+        assert_eq!(
+            ctx.output_file("9000", 1),
+            "client-i-0123.1-client-s2n-quic.json"
+        );
+    }
+
+    #[test]
+    fn collector_options() {
+        let mut ctx = ServerContext::testing();
+        assert!(ctx.collector_args().is_empty());
+
+        ctx.collector_interval = Some(Duration::from_millis(100));
+        ctx.collector_disable_bpf = true;
+        assert_eq!(
+            ctx.collector_args(),
+            vec!["--interval", "100ms", "--disable-bpf"]
+        );
+    }
+
+    #[test]
+    fn driver_instance_ports() {
+        let mut ctx = ClientContext::testing();
+        ctx.apply(&RunParams {
+            netbench_servers: Some(vec!["10.0.0.1:4433".parse().unwrap()]),
+            driver_instances: Some(2),
+            duration: Some(Duration::from_secs(3600)),
+            ..Default::default()
+        });
+        assert_eq!(ctx.driver_instances().unwrap(), 2);
+        // a soak run
+        assert!(ctx
+            .run_until()
+            .is_some_and(|run_until| run_until > Instant::now()));
+        assert_eq!(
+            ctx.netbench_servers(1),
+            vec!["10.0.0.1:4434".parse().unwrap()]
+        );
+
+        ctx.driver_instances = MAX_DRIVER_INSTANCES + 1;
+        assert!(ctx.driver_instances().is_err());
+        ctx.driver_instances = 0;
+        assert!(ctx.driver_instances().is_err());
+    }
+
+    #[test]
+    fn apply_run_params() {
+        let params = RunParams {
+            driver: Some("s2n-netbench-driver-server-s2n-quic".to_string()),
+            netbench_port: Some(9443),
+            ..Default::default()
+        };
+        let mut ctx = ServerContext::testing();
+        assert!(ctx.driver().is_err());
+        ctx.apply(&params);
+        assert_eq!(ctx.driver().unwrap(), "s2n-netbench-driver-server-s2n-quic");
+        assert_eq!(ctx.netbench_port, 9443);
+        // unset params don't override the command line
+        assert_eq!(ctx.scenario, "");
+        assert_eq!(ctx.driver_instances().unwrap(), 1);
+        assert!(!ctx.warmup);
+        ctx.apply(&RunParams {
+            warmup: Some(true),
+            ..Default::default()
+        });
+        assert!(ctx.warmup);
+
+        // the params are shipped with the CheckWorker state
+        let state = server::CoordState::CheckWorker(params.clone());
+        let msg = Msg::new(state.as_bytes());
+        let server::CoordState::CheckWorker(recv) = server::CoordState::from_msg(msg).unwrap()
+        else {
+            panic!("expected CheckWorker");
+        };
+        assert_eq!(recv, params);
+    }
+
+    #[test]
+    fn result_stream() {
+        let mut ctx = ClientContext::testing();
+        ctx.driver = Some("s2n-netbench-driver-client-s2n-quic".to_string());
+        // not streamed unless the Coordinator asks for it
+        assert!(ctx.spawn_result_stream("9000").unwrap().is_none());
+        ctx.apply(&RunParams {
+            stream_to: Some("s3://bucket/run/streams/request_response/s2n-quic".to_string()),
+            stream_interval: Some(Duration::from_secs(600)),
+            ..Default::default()
+        });
+        assert!(ctx.stream_to.is_some());
+        assert_eq!(ctx.stream_interval, Some(Duration::from_secs(600)));
+
+        let output_files = vec![ctx.output_file("9000", 0), ctx.output_file("9000", 1)];
+        assert_eq!(
+            streamed_files(&output_files, true),
+            vec![
+                "client-9000-client-s2n-quic.json",
+                "client-9000.1-client-s2n-quic.json",
+                "client-9000-client-s2n-quic.sysmetrics.csv",
+            ]
+        );
+        assert_eq!(streamed_files(&output_files, false).len(), 2);
+
+        assert_eq!(
+            stream_chunk("client-9000-client-s2n-quic.json.00012"),
+            Some(("client-9000-client-s2n-quic.json", 12))
+        );
+        assert_eq!(stream_chunk("client-9000-client-s2n-quic.json"), None);
+        assert_eq!(stream_chunk("client.json.1"), None);
+    }
+}
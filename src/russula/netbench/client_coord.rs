@@ -4,23 +4,38 @@
 use crate::russula::{
     error::{RussulaError, RussulaResult},
     event::{EventRecorder, EventType},
-    netbench::client::WorkerState,
+    message::WorkerStatus,
+    netbench::{client::WorkerState, unix_millis, ProcessExit, RunParams, RunProgress},
     network_utils::Msg,
     protocol::{private, Protocol},
+    transport::{Transport, TransportStream},
     StateApi, TransitionStep,
 };
 use async_trait::async_trait;
-use core::fmt::Debug;
+use core::{fmt::Debug, time::Duration};
 use serde::{Deserialize, Serialize};
-use std::net::SocketAddr;
-use tokio::net::TcpStream;
+use std::{
+    net::SocketAddr,
+    sync::{Arc, OnceLock},
+    time::SystemTime,
+};
 use tracing::{debug, info};
 
-#[derive(Copy, Clone, Debug, Serialize, Deserialize)]
+// How far in the future to schedule the start of the workers. The workers need
+// to receive the start time before it elapses so this should be larger than the
+// worker's poll delay.
+const DEFAULT_RUN_AT_DELAY: Duration = Duration::from_secs(15);
+
+// Only used when creating a state variant
+const PLACEHOLDER_START_AT: u64 = 0;
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub enum CoordState {
-    CheckWorker,
+    // Ship the run parameters to the workers
+    CheckWorker(RunParams),
     Ready,
-    RunWorker,
+    // Run the workers at the specified time (milliseconds since the unix epoch)
+    RunAt(u64),
     WorkersRunning,
     Done,
 }
@@ -29,17 +44,44 @@ pub enum CoordState {
 pub struct CoordProtocol {
     state: CoordState,
     worker_state: WorkerState,
+    // The progress last reported by the worker
+    worker_progress: Option<RunProgress>,
     event_recorder: EventRecorder,
+    run_at_delay: Duration,
+    // Shared by all instances of the protocol so that every worker receives
+    // the same start time.
+    start_at: Arc<OnceLock<u64>>,
+}
+
+impl Default for CoordProtocol {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl CoordProtocol {
     pub fn new() -> Self {
         CoordProtocol {
-            state: CoordState::CheckWorker,
+            state: CoordState::CheckWorker(RunParams::default()),
             worker_state: WorkerState::WaitCoordInit,
+            worker_progress: None,
             event_recorder: EventRecorder::default(),
+            run_at_delay: DEFAULT_RUN_AT_DELAY,
+            start_at: Arc::new(OnceLock::new()),
         }
     }
+
+    /// The run parameters shipped to the workers.
+    pub fn with_params(mut self, params: RunParams) -> Self {
+        self.state = CoordState::CheckWorker(params);
+        self
+    }
+
+    /// How far in the future the workers should be scheduled to start.
+    pub fn with_run_at_delay(mut self, run_at_delay: Duration) -> Self {
+        self.run_at_delay = run_at_delay;
+        self
+    }
 }
 
 impl private::Protocol for CoordProtocol {
@@ -55,16 +97,37 @@ impl Protocol for CoordProtocol {
         format!("client-c-{}", 0)
     }
 
-    async fn connect(&self, addr: &SocketAddr) -> RussulaResult<TcpStream> {
-        info!("--- Coordinator: attempt to connect on: {}", addr);
+    fn role(&self) -> &'static str {
+        "coordinator"
+    }
 
-        let connect = TcpStream::connect(addr).await.map_err(RussulaError::from)?;
-        Ok(connect)
+    async fn connect(
+        &self,
+        addr: &SocketAddr,
+        transport: Transport,
+    ) -> RussulaResult<TransportStream> {
+        info!("--- Coordinator: attempt to connect on: {}", addr);
+        transport.connect(addr).await
     }
 
     fn update_peer_state(&mut self, msg: Msg) -> RussulaResult<()> {
-        self.worker_state = WorkerState::from_msg(msg)?;
-        debug!("{} ... peer_state {:?}", self.name(), self.worker_state);
+        let status = WorkerStatus::<WorkerState>::from_msg(msg)?;
+        let pid = status.pid;
+        if status.progress.is_some() {
+            self.worker_progress = status.progress;
+        }
+        self.worker_state = status.into_state()?;
+        debug!(
+            ?pid,
+            "{} ... peer_state {:?}",
+            self.name(),
+            self.worker_state
+        );
+        if let WorkerState::Stopped(exits) = &self.worker_state {
+            for exit in exits {
+                info!("{} worker netbench process stopped. {}", self.name(), exit);
+            }
+        }
 
         Ok(())
     }
@@ -89,19 +152,34 @@ impl Protocol for CoordProtocol {
         CoordState::WorkersRunning
     }
 
-    async fn run(&mut self, stream: &TcpStream) -> RussulaResult<Option<Msg>> {
+    fn worker_exits(&self) -> &[ProcessExit] {
+        match &self.worker_state {
+            WorkerState::Stopped(exits) => exits,
+            _ => &[],
+        }
+    }
+
+    fn take_worker_progress(&mut self) -> Option<RunProgress> {
+        self.worker_progress.take()
+    }
+
+    async fn run(&mut self, stream: &TransportStream) -> RussulaResult<Option<Msg>> {
         match self.state_mut() {
-            CoordState::CheckWorker => {
+            CoordState::CheckWorker(_) => {
                 self.state().notify_peer(stream).await?;
                 self.await_next_msg(stream).await
             }
             CoordState::Ready => {
+                let run_at_delay = self.run_at_delay;
+                let start_at = *self
+                    .start_at
+                    .get_or_init(|| unix_millis(SystemTime::now() + run_at_delay));
                 self.state_mut()
-                    .transition_self_or_user_driven(stream)
+                    .transition_to(stream, CoordState::RunAt(start_at))
                     .await?;
                 Ok(None)
             }
-            CoordState::RunWorker => {
+            CoordState::RunAt(_start_at) => {
                 self.state().notify_peer(stream).await?;
                 self.await_next_msg(stream).await
             }
@@ -125,11 +203,11 @@ impl StateApi for CoordState {
 
     fn transition_step(&self) -> TransitionStep {
         match self {
-            CoordState::CheckWorker => TransitionStep::AwaitNext(WorkerState::Ready.as_bytes()),
+            CoordState::CheckWorker(_) => TransitionStep::AwaitNext(WorkerState::Ready.as_bytes()),
             CoordState::Ready => TransitionStep::UserDriven,
-            CoordState::RunWorker => TransitionStep::AwaitNext(WorkerState::Running(0).as_bytes()),
+            CoordState::RunAt(_) => TransitionStep::AwaitNext(WorkerState::Running(0).as_bytes()),
             CoordState::WorkersRunning => {
-                TransitionStep::AwaitNext(WorkerState::Stopped.as_bytes())
+                TransitionStep::AwaitNext(WorkerState::Stopped(vec![]).as_bytes())
             }
             CoordState::Done => TransitionStep::Finished,
         }
@@ -137,9 +215,10 @@ impl StateApi for CoordState {
 
     fn next_state(&self) -> Self {
         match self {
-            CoordState::CheckWorker => CoordState::Ready,
-            CoordState::Ready => CoordState::RunWorker,
-            CoordState::RunWorker => CoordState::WorkersRunning,
+            CoordState::CheckWorker(_) => CoordState::Ready,
+            // FIXME error prone. The start time is set when running the Ready state
+            CoordState::Ready => CoordState::RunAt(PLACEHOLDER_START_AT),
+            CoordState::RunAt(_) => CoordState::WorkersRunning,
             CoordState::WorkersRunning => CoordState::Done,
             CoordState::Done => CoordState::Done,
         }
@@ -148,7 +227,16 @@ impl StateApi for CoordState {
 
 #[cfg(test)]
 mod tests {
-
-    #[tokio::test]
-    async fn netbench_state() {}
+    use super::*;
+    use crate::russula::model;
+
+    #[test]
+    fn protocol_model() {
+        let stats = model::check(
+            CoordState::CheckWorker(RunParams::default()),
+            WorkerState::WaitCoordInit,
+        )
+        .unwrap();
+        assert!(stats.states > 1);
+    }
 }
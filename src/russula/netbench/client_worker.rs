@@ -1,34 +1,38 @@
 // Copyright Amazon.com, Inc. or its affiliates. All Rights Reserved.
 // SPDX-License-Identifier: Apache-2.0
 
-use super::ClientContext;
+use super::{ClientContext, RunParams};
 use crate::russula::{
     error::{RussulaError, RussulaResult},
     event::{EventRecorder, EventType},
-    netbench::client::CoordState,
+    netbench::{
+        client::CoordState, sleep_until_unix_millis, stderr_log, supervisor::Supervisor,
+        ProcessExit, Profiler, KILL_GRACE_PERIOD, PCAP_STOP_GRACE_PERIOD,
+        RESULT_STREAM_STOP_GRACE_PERIOD,
+    },
     network_utils::Msg,
     protocol::{private, Protocol},
+    transport::{Transport, TransportStream},
     StateApi, TransitionStep,
 };
 use async_trait::async_trait;
 use core::fmt::Debug;
 use serde::{Deserialize, Serialize};
-use std::{fs::File, net::SocketAddr, process::Command};
-use sysinfo::{Pid, PidExt, ProcessExt, SystemExt};
-use tokio::net::{TcpListener, TcpStream};
+use std::{fs::File, net::SocketAddr, path::PathBuf, process::Command, time::Instant};
 use tracing::{debug, info, warn};
 
 // Only used when creating a state variant
 const PLACEHOLDER_PID: u32 = 1000;
 
-#[derive(Copy, Clone, Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub enum WorkerState {
     WaitCoordInit,
     Ready,
     Run,
     Running(#[serde(skip)] u32),
     RunningAwaitComplete(#[serde(skip)] u32),
-    Stopped,
+    // The exit status of each driver instance
+    Stopped(Vec<ProcessExit>),
     Done,
 }
 
@@ -39,6 +43,14 @@ pub struct WorkerProtocol {
     coord_state: CoordState,
     netbench_ctx: ClientContext,
     event_recorder: EventRecorder,
+    // A supervisor per driver instance
+    supervisors: Vec<Supervisor>,
+    sys_metrics: Option<Supervisor>,
+    profiler: Option<Supervisor>,
+    pcap_capture: Option<Supervisor>,
+    result_stream: Option<Supervisor>,
+    // When the drivers of a duration bounded run are stopped
+    run_until: Option<Instant>,
 }
 
 impl WorkerProtocol {
@@ -46,9 +58,41 @@ impl WorkerProtocol {
         WorkerProtocol {
             id,
             state: WorkerState::WaitCoordInit,
-            coord_state: CoordState::CheckWorker,
+            coord_state: CoordState::CheckWorker(RunParams::default()),
             netbench_ctx,
             event_recorder: EventRecorder::default(),
+            supervisors: Vec::new(),
+            sys_metrics: None,
+            profiler: None,
+            pcap_capture: None,
+            result_stream: None,
+            run_until: None,
+        }
+    }
+}
+
+impl WorkerProtocol {
+    async fn stop_sidecars(&mut self) {
+        if let Some(sys_metrics) = self.sys_metrics.take() {
+            if let Err(err) = sys_metrics.kill() {
+                warn!("{} failed to stop sys metrics: {}", self.name(), err);
+            }
+        }
+        if let Some(mut profiler) = self.profiler.take() {
+            if let Err(err) = profiler.stop(Profiler::STOP_GRACE_PERIOD).await {
+                warn!("{} failed to stop profiler: {}", self.name(), err);
+            }
+        }
+        if let Some(mut pcap_capture) = self.pcap_capture.take() {
+            if let Err(err) = pcap_capture.stop(PCAP_STOP_GRACE_PERIOD).await {
+                warn!("{} failed to stop pcap capture: {}", self.name(), err);
+            }
+        }
+        // last, so that the remainder of the system metrics is uploaded
+        if let Some(mut result_stream) = self.result_stream.take() {
+            if let Err(err) = result_stream.stop(RESULT_STREAM_STOP_GRACE_PERIOD).await {
+                warn!("{} failed to stop result stream: {}", self.name(), err);
+            }
         }
     }
 }
@@ -67,11 +111,17 @@ impl Protocol for WorkerProtocol {
         format!("client-w-{}", self.id)
     }
 
-    async fn connect(&self, addr: &SocketAddr) -> RussulaResult<TcpStream> {
-        let listener = TcpListener::bind(addr).await.unwrap();
-        info!("{} listening on: {}", self.name(), addr);
+    fn role(&self) -> &'static str {
+        "worker"
+    }
 
-        let (stream, _local_addr) = listener.accept().await.map_err(RussulaError::from)?;
+    async fn connect(
+        &self,
+        addr: &SocketAddr,
+        transport: Transport,
+    ) -> RussulaResult<TransportStream> {
+        info!("{} listening on: {}", self.name(), addr);
+        let stream = transport.listen(addr).await?;
         info!("{} success connection: {addr}", self.name());
 
         Ok(stream)
@@ -80,6 +130,13 @@ impl Protocol for WorkerProtocol {
     fn update_peer_state(&mut self, msg: Msg) -> RussulaResult<()> {
         self.coord_state = CoordState::from_msg(msg)?;
         debug!("{} ... peer_state {:?}", self.name(), self.coord_state);
+        // Only reconfigure before running netbench
+        if let (CoordState::CheckWorker(params), WorkerState::WaitCoordInit | WorkerState::Ready) =
+            (&self.coord_state, &self.state)
+        {
+            info!("{} apply run params {:?}", self.name(), params);
+            self.netbench_ctx.apply(params);
+        }
 
         Ok(())
     }
@@ -104,7 +161,7 @@ impl Protocol for WorkerProtocol {
         unimplemented!()
     }
 
-    async fn run(&mut self, stream: &TcpStream) -> RussulaResult<Option<Msg>> {
+    async fn run(&mut self, stream: &TransportStream) -> RussulaResult<Option<Msg>> {
         match self.state_mut() {
             WorkerState::WaitCoordInit => {
                 // self.state().notify_peer(stream).await?;
@@ -115,47 +172,68 @@ impl Protocol for WorkerProtocol {
                 self.await_next_msg(stream).await
             }
             WorkerState::Run => {
-                let child = match &self.netbench_ctx.testing {
-                    false => {
-                        let output_log_file = format!("{}.json", self.name());
-                        let output_log_file =
-                            File::create(output_log_file).expect("failed to open log");
-
-                        info!("{} run netbench process", self.name());
-                        println!("{} run netbench process", self.name());
-
-                        let netbench_path = self.netbench_ctx.netbench_path.to_str().unwrap();
-                        let collector = format!("{}/s2n-netbench-collector", netbench_path);
-                        // driver value ex.: netbench-driver-s2n-quic-client
-                        let driver = format!("{}/{}", netbench_path, self.netbench_ctx.driver);
-                        let scenario = format!("{}/{}", netbench_path, self.netbench_ctx.scenario);
-
-                        let mut cmd = Command::new(collector);
-
-                        // SCENARIO=request_response.json SERVER_0=127.0.0.1:8888 SERVER_1=127.0.0.1:9999 s2n-netbench-collector s2n-netbench-driver-client-s2n-quic
-                        for (i, peer_list) in self.netbench_ctx.netbench_servers.iter().enumerate()
-                        {
-                            let server_idx = format!("SERVER_{}", i);
-                            cmd.env(server_idx, peer_list.to_string());
-                        }
+                // Wait for the synchronized start time so that all clients start
+                // at the same time
+                if let CoordState::RunAt(start_at) = self.coord_state {
+                    info!("{} waiting to run at: {}", self.name(), start_at);
+                    sleep_until_unix_millis(start_at).await;
+                }
 
-                        cmd.args([&driver, "--scenario", &scenario])
-                            .stdout(output_log_file);
-                        println!("{:?}", cmd);
-                        debug!("{:?}", cmd);
-                        cmd.spawn()
-                            .expect("Failed to start netbench client process")
-                    }
-                    true => {
-                        info!("{} run sim_netbench_client", self.name());
-                        Command::new("sh")
-                            .args(["scripts/sim_netbench_client.sh", &self.name()])
-                            .spawn()
-                            .expect("Failed to start sim_netbench_client process")
-                    }
-                };
+                for instance in 0..self.netbench_ctx.driver_instances()? {
+                    let cmd = match &self.netbench_ctx.testing {
+                        false => {
+                            let output_log_file =
+                                self.netbench_ctx.netbench_output(&self.id, instance);
+
+                            info!("{} run netbench process {}", self.name(), instance);
+
+                            let netbench_path = self.netbench_ctx.netbench_path.to_str().unwrap();
+                            let collector = format!("{}/s2n-netbench-collector", netbench_path);
+                            // driver value ex.: netbench-driver-s2n-quic-client
+                            let driver =
+                                format!("{}/{}", netbench_path, self.netbench_ctx.driver()?);
+                            let scenario =
+                                format!("{}/{}", netbench_path, self.netbench_ctx.scenario);
+
+                            let mut cmd = Command::new(collector);
+
+                            // SCENARIO=request_response.json SERVER_0=127.0.0.1:8888 SERVER_1=127.0.0.1:9999 s2n-netbench-collector s2n-netbench-driver-client-s2n-quic
+                            for (i, peer_list) in self
+                                .netbench_ctx
+                                .netbench_servers(instance)
+                                .iter()
+                                .enumerate()
+                            {
+                                let server_idx = format!("SERVER_{}", i);
+                                cmd.env(server_idx, peer_list.to_string());
+                            }
+
+                            cmd.args(self.netbench_ctx.collector_args());
+                            cmd.args([&driver, "--scenario", &scenario])
+                                .stdout(output_log_file);
+                            debug!("{:?}", cmd);
+                            cmd
+                        }
+                        true => {
+                            info!("{} run sim_netbench_client", self.name());
+                            let mut cmd = Command::new("sh");
+                            cmd.args(["scripts/sim_netbench_client.sh", &self.name()]);
+                            cmd
+                        }
+                    };
 
-                let pid = child.id();
+                    let stderr_log = stderr_log(&self.name(), instance);
+                    self.supervisors.push(Supervisor::spawn(cmd, &stderr_log)?);
+                }
+                let pid = self.supervisors[0].pid();
+                self.run_until = self.netbench_ctx.run_until();
+                // a warm-up run isn't measured
+                if !self.netbench_ctx.warmup {
+                    self.sys_metrics = self.netbench_ctx.spawn_sys_metrics(&self.id)?;
+                    self.profiler = self.netbench_ctx.spawn_profiler(&self.id)?;
+                    self.pcap_capture = self.netbench_ctx.spawn_pcap_capture(&self.id)?;
+                    self.result_stream = self.netbench_ctx.spawn_result_stream(&self.id)?;
+                }
                 debug!(
                     "{}----------------------------child id {}",
                     self.name(),
@@ -166,59 +244,43 @@ impl Protocol for WorkerProtocol {
                 Ok(None)
             }
             WorkerState::Running(_pid) => {
-                self.state().notify_peer(stream).await?;
+                let progress = self.netbench_ctx.run_progress(&self.id);
+                self.state().notify_peer_progress(stream, progress).await?;
                 self.await_next_msg(stream).await
             }
             WorkerState::RunningAwaitComplete(pid) => {
                 let pid = *pid;
-                self.state().notify_peer(stream).await?;
+                let progress = self.netbench_ctx.run_progress(&self.id);
+                self.state().notify_peer_progress(stream, progress).await?;
+
+                // Reap the processes so that they don't become zombies. The drivers
+                // of a duration bounded run are stopped once the duration elapses
+                let stop = self
+                    .run_until
+                    .is_some_and(|run_until| Instant::now() >= run_until);
+                let mut exits = Vec::new();
+                for supervisor in self.supervisors.iter_mut() {
+                    exits.push(match stop {
+                        true => supervisor.poll_kill(KILL_GRACE_PERIOD)?,
+                        false => supervisor.try_wait()?,
+                    });
+                }
 
-                let pid = Pid::from_u32(pid);
-                let mut system = sysinfo::System::new_all();
-
-                let process = system.process(pid);
-                // let is_process_complete = !system.refresh_process(pid);
-
-                if let Some(process) = process {
-                    debug!(
-                        "process still RUNNING! pid: {} status: {:?} ----------------------------",
-                        process.pid(),
-                        process.status()
-                    );
-                    // FIXME somethings is causing the collector to become a Zombie process.
-                    //
-                    // We can detect the zombie process and continue because this is a testing
-                    // utility but we should come back and fix this
-                    // - Collector issue
-                    // - how Russula calls Collector issue
-                    // - Command::stdout file issue
-                    //
-                    // root       54245  Sl ./target/debug/russula_cli --protocol NetbenchClientWorker --port 9000 --peer-list 54.198.168.151:4433
-                    // root       54688  Z  [netbench-collec] <defunct>
-
-                    if let sysinfo::ProcessStatus::Zombie = process.status() {
-                        warn!(
-                            "Process pid: {} is a Zombie.. ignoring and continuing",
-                            process.pid()
-                        );
+                // Wait for every driver instance to complete
+                match exits.into_iter().collect::<Option<Vec<_>>>() {
+                    Some(exits) => {
+                        info!("Process COMPLETED! pid: {} {:?}", pid, exits);
+                        self.stop_sidecars().await;
                         self.state_mut()
-                            .transition_self_or_user_driven(stream)
+                            .transition_to(stream, WorkerState::Stopped(exits))
                             .await?;
                     }
-                } else {
-                    info!(
-                        "Process COMPLETED! pid: {} ----------------------------",
-                        pid
-                    );
-
-                    self.state_mut()
-                        .transition_self_or_user_driven(stream)
-                        .await?;
+                    None => debug!("process still RUNNING! pid: {}", pid),
                 }
 
                 Ok(None)
             }
-            WorkerState::Stopped => {
+            WorkerState::Stopped(_exit) => {
                 self.state().notify_peer(stream).await?;
                 self.await_next_msg(stream).await
             }
@@ -236,18 +298,29 @@ impl StateApi for WorkerState {
         "client-worker".to_string()
     }
 
+    fn is_worker_state(&self) -> bool {
+        true
+    }
+
+    fn pid(&self) -> Option<u32> {
+        match self {
+            WorkerState::Running(pid) | WorkerState::RunningAwaitComplete(pid) => Some(*pid),
+            _ => None,
+        }
+    }
+
     fn transition_step(&self) -> TransitionStep {
         match self {
             WorkerState::WaitCoordInit => {
-                TransitionStep::AwaitNext(CoordState::CheckWorker.as_bytes())
+                TransitionStep::AwaitNext(CoordState::CheckWorker(RunParams::default()).as_bytes())
             }
-            WorkerState::Ready => TransitionStep::AwaitNext(CoordState::RunWorker.as_bytes()),
+            WorkerState::Ready => TransitionStep::AwaitNext(CoordState::RunAt(0).as_bytes()),
             WorkerState::Run => TransitionStep::SelfDriven,
             WorkerState::Running(_) => {
                 TransitionStep::AwaitNext(CoordState::WorkersRunning.as_bytes())
             }
             WorkerState::RunningAwaitComplete(_) => TransitionStep::SelfDriven,
-            WorkerState::Stopped => TransitionStep::AwaitNext(CoordState::Done.as_bytes()),
+            WorkerState::Stopped(_) => TransitionStep::AwaitNext(CoordState::Done.as_bytes()),
             WorkerState::Done => TransitionStep::Finished,
         }
     }
@@ -259,8 +332,9 @@ impl StateApi for WorkerState {
             // FIXME error prone
             WorkerState::Run => WorkerState::Running(PLACEHOLDER_PID),
             WorkerState::Running(pid) => WorkerState::RunningAwaitComplete(*pid),
-            WorkerState::RunningAwaitComplete(_) => WorkerState::Stopped,
-            WorkerState::Stopped => WorkerState::Done,
+            // FIXME error prone. The exit status is set when running the RunningAwaitComplete state
+            WorkerState::RunningAwaitComplete(_) => WorkerState::Stopped(vec![]),
+            WorkerState::Stopped(_) => WorkerState::Done,
             WorkerState::Done => WorkerState::Done,
         }
     }
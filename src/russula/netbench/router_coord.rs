@@ -0,0 +1,178 @@
+// Copyright Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::russula::{
+    error::{RussulaError, RussulaResult},
+    event::{EventRecorder, EventType},
+    message::WorkerStatus,
+    netbench::router_worker::WorkerState,
+    network_utils::Msg,
+    protocol::{private, Protocol},
+    transport::{Transport, TransportStream},
+    StateApi, TransitionStep,
+};
+use async_trait::async_trait;
+use core::fmt::Debug;
+use serde::{Deserialize, Serialize};
+use std::net::SocketAddr;
+use tracing::debug;
+
+#[derive(Copy, Clone, Debug, Serialize, Deserialize)]
+pub enum CoordState {
+    CheckWorker,
+    Ready,
+    ConfigureWorker,
+    WorkersRouting,
+    TeardownWorker,
+    WorkerTornDown,
+    Done,
+}
+
+#[derive(Clone, Debug)]
+pub struct CoordProtocol {
+    state: CoordState,
+    worker_state: WorkerState,
+    event_recorder: EventRecorder,
+}
+
+impl Default for CoordProtocol {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl CoordProtocol {
+    pub fn new() -> Self {
+        CoordProtocol {
+            state: CoordState::CheckWorker,
+            worker_state: WorkerState::WaitCoordInit,
+            event_recorder: EventRecorder::default(),
+        }
+    }
+}
+
+impl private::Protocol for CoordProtocol {
+    fn event_recorder(&mut self) -> &mut EventRecorder {
+        &mut self.event_recorder
+    }
+}
+
+#[async_trait]
+impl Protocol for CoordProtocol {
+    type State = CoordState;
+    fn name(&self) -> String {
+        format!("router-c-{}", 0)
+    }
+
+    fn role(&self) -> &'static str {
+        "coordinator"
+    }
+
+    async fn connect(
+        &self,
+        addr: &SocketAddr,
+        transport: Transport,
+    ) -> RussulaResult<TransportStream> {
+        transport.connect(addr).await
+    }
+
+    fn update_peer_state(&mut self, msg: Msg) -> RussulaResult<()> {
+        let status = WorkerStatus::<WorkerState>::from_msg(msg)?;
+        let pid = status.pid;
+        self.worker_state = status.into_state()?;
+        debug!(
+            ?pid,
+            "{} ... peer_state {:?}",
+            self.name(),
+            self.worker_state
+        );
+
+        Ok(())
+    }
+
+    fn state(&self) -> &Self::State {
+        &self.state
+    }
+
+    fn state_mut(&mut self) -> &mut Self::State {
+        &mut self.state
+    }
+
+    fn ready_state(&self) -> Self::State {
+        CoordState::Ready
+    }
+
+    fn done_state(&self) -> Self::State {
+        CoordState::Done
+    }
+
+    fn worker_running_state(&self) -> Self::State {
+        CoordState::WorkersRouting
+    }
+
+    async fn run(&mut self, stream: &TransportStream) -> RussulaResult<Option<Msg>> {
+        match self.state_mut() {
+            CoordState::CheckWorker | CoordState::ConfigureWorker | CoordState::TeardownWorker => {
+                self.state().notify_peer(stream).await?;
+                self.await_next_msg(stream).await
+            }
+            CoordState::Ready | CoordState::WorkersRouting | CoordState::WorkerTornDown => {
+                self.state_mut()
+                    .transition_self_or_user_driven(stream)
+                    .await?;
+                Ok(None)
+            }
+            CoordState::Done => {
+                self.state().notify_peer(stream).await?;
+                Ok(None)
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl StateApi for CoordState {
+    fn name_prefix(&self) -> String {
+        "router-coord".to_string()
+    }
+
+    fn transition_step(&self) -> TransitionStep {
+        match self {
+            CoordState::CheckWorker => TransitionStep::AwaitNext(WorkerState::Ready.as_bytes()),
+            CoordState::Ready => TransitionStep::UserDriven,
+            CoordState::ConfigureWorker => {
+                TransitionStep::AwaitNext(WorkerState::RoutingAwaitTeardown.as_bytes())
+            }
+            CoordState::WorkersRouting => TransitionStep::UserDriven,
+            CoordState::TeardownWorker => {
+                TransitionStep::AwaitNext(WorkerState::Stopped.as_bytes())
+            }
+            CoordState::WorkerTornDown => TransitionStep::UserDriven,
+            CoordState::Done => TransitionStep::Finished,
+        }
+    }
+
+    fn next_state(&self) -> Self {
+        match self {
+            CoordState::CheckWorker => CoordState::Ready,
+            CoordState::Ready => CoordState::ConfigureWorker,
+            CoordState::ConfigureWorker => CoordState::WorkersRouting,
+            CoordState::WorkersRouting => CoordState::TeardownWorker,
+            CoordState::TeardownWorker => CoordState::WorkerTornDown,
+            CoordState::WorkerTornDown => CoordState::Done,
+            CoordState::Done => CoordState::Done,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::russula::model;
+
+    #[test]
+    fn protocol_model() {
+        let stats = model::check(CoordState::CheckWorker, WorkerState::WaitCoordInit).unwrap();
+        assert!(stats.states > 1);
+    }
+}
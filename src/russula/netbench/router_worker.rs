@@ -0,0 +1,185 @@
+// Copyright Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+use super::RouterContext;
+use crate::russula::{
+    error::{RussulaError, RussulaResult},
+    event::{EventRecorder, EventType},
+    netbench::router_coord::CoordState,
+    network_utils::Msg,
+    protocol::{private, Protocol},
+    transport::{Transport, TransportStream},
+    StateApi, TransitionStep,
+};
+use async_trait::async_trait;
+use core::fmt::Debug;
+use serde::{Deserialize, Serialize};
+use std::net::SocketAddr;
+use tracing::{debug, info};
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub enum WorkerState {
+    WaitCoordInit,
+    Ready,
+    Configure,
+    RoutingAwaitTeardown,
+    Teardown,
+    Stopped,
+    Done,
+}
+
+#[derive(Clone, Debug)]
+pub struct WorkerProtocol {
+    id: String,
+    state: WorkerState,
+    coord_state: CoordState,
+    router_ctx: RouterContext,
+    event_recorder: EventRecorder,
+}
+
+impl WorkerProtocol {
+    pub fn new(id: String, router_ctx: RouterContext) -> Self {
+        WorkerProtocol {
+            id,
+            state: WorkerState::WaitCoordInit,
+            coord_state: CoordState::CheckWorker,
+            router_ctx,
+            event_recorder: EventRecorder::default(),
+        }
+    }
+}
+
+impl private::Protocol for WorkerProtocol {
+    fn event_recorder(&mut self) -> &mut EventRecorder {
+        &mut self.event_recorder
+    }
+}
+
+#[async_trait]
+impl Protocol for WorkerProtocol {
+    type State = WorkerState;
+
+    fn name(&self) -> String {
+        format!("router-w-{}", self.id)
+    }
+
+    fn role(&self) -> &'static str {
+        "worker"
+    }
+
+    async fn connect(
+        &self,
+        addr: &SocketAddr,
+        transport: Transport,
+    ) -> RussulaResult<TransportStream> {
+        info!("{} listening on: {}", self.name(), addr);
+        let stream = transport.listen(addr).await?;
+        info!("{} success connection: {addr}", self.name());
+
+        Ok(stream)
+    }
+
+    fn update_peer_state(&mut self, msg: Msg) -> RussulaResult<()> {
+        self.coord_state = CoordState::from_msg(msg)?;
+        debug!("{} ... peer_state {:?}", self.name(), self.coord_state);
+
+        Ok(())
+    }
+
+    fn state(&self) -> &Self::State {
+        &self.state
+    }
+
+    fn state_mut(&mut self) -> &mut Self::State {
+        &mut self.state
+    }
+
+    fn ready_state(&self) -> Self::State {
+        WorkerState::Ready
+    }
+
+    fn done_state(&self) -> Self::State {
+        WorkerState::Done
+    }
+
+    fn worker_running_state(&self) -> Self::State {
+        unimplemented!()
+    }
+
+    async fn run(&mut self, stream: &TransportStream) -> RussulaResult<Option<Msg>> {
+        match self.state_mut() {
+            WorkerState::WaitCoordInit => self.await_next_msg(stream).await,
+            WorkerState::Ready => {
+                self.state().notify_peer(stream).await?;
+                self.await_next_msg(stream).await
+            }
+            WorkerState::Configure => {
+                info!("{} enable forwarding", self.name());
+                self.router_ctx.configure_forwarding(true)?;
+                self.state_mut()
+                    .transition_self_or_user_driven(stream)
+                    .await?;
+                Ok(None)
+            }
+            WorkerState::RoutingAwaitTeardown => {
+                self.state().notify_peer(stream).await?;
+                self.await_next_msg(stream).await
+            }
+            WorkerState::Teardown => {
+                info!("{} disable forwarding", self.name());
+                self.router_ctx.configure_forwarding(false)?;
+                self.state_mut()
+                    .transition_self_or_user_driven(stream)
+                    .await?;
+                Ok(None)
+            }
+            WorkerState::Stopped => {
+                self.state().notify_peer(stream).await?;
+                self.await_next_msg(stream).await
+            }
+            WorkerState::Done => {
+                self.state().notify_peer(stream).await?;
+                Ok(None)
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl StateApi for WorkerState {
+    fn name_prefix(&self) -> String {
+        "router-worker".to_string()
+    }
+
+    fn is_worker_state(&self) -> bool {
+        true
+    }
+
+    fn transition_step(&self) -> TransitionStep {
+        match self {
+            WorkerState::WaitCoordInit => {
+                TransitionStep::AwaitNext(CoordState::CheckWorker.as_bytes())
+            }
+            WorkerState::Ready => TransitionStep::AwaitNext(CoordState::ConfigureWorker.as_bytes()),
+            WorkerState::Configure => TransitionStep::SelfDriven,
+            WorkerState::RoutingAwaitTeardown => {
+                TransitionStep::AwaitNext(CoordState::TeardownWorker.as_bytes())
+            }
+            WorkerState::Teardown => TransitionStep::SelfDriven,
+            WorkerState::Stopped => TransitionStep::AwaitNext(CoordState::Done.as_bytes()),
+            WorkerState::Done => TransitionStep::Finished,
+        }
+    }
+
+    fn next_state(&self) -> Self {
+        match self {
+            WorkerState::WaitCoordInit => WorkerState::Ready,
+            WorkerState::Ready => WorkerState::Configure,
+            WorkerState::Configure => WorkerState::RoutingAwaitTeardown,
+            WorkerState::RoutingAwaitTeardown => WorkerState::Teardown,
+            WorkerState::Teardown => WorkerState::Stopped,
+            WorkerState::Stopped => WorkerState::Done,
+            WorkerState::Done => WorkerState::Done,
+        }
+    }
+}
@@ -0,0 +1,171 @@
+// Copyright Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, fmt::Display};
+
+/// The progress of the netbench drivers on a Worker while they run, summed
+/// over the intervals written so far by their collectors.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RunProgress {
+    pub bytes_sent: u64,
+    pub bytes_received: u64,
+    // The connections opened by the drivers, counted by the `connect` trace
+    pub connections: u64,
+}
+
+impl Display for RunProgress {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "sent: {:.1} MB, received: {:.1} MB, connections: {}",
+            self.bytes_sent as f64 / 1e6,
+            self.bytes_received as f64 / 1e6,
+            self.connections
+        )
+    }
+}
+
+// The fields of a collector interval needed for the progress
+#[derive(Deserialize)]
+struct Interval {
+    #[serde(default)]
+    counters: BTreeMap<usize, u64>,
+}
+
+#[derive(Deserialize)]
+struct Output {
+    traces: Vec<String>,
+    intervals: Vec<Interval>,
+}
+
+impl RunProgress {
+    /// Parse the output of a collector which may still be running, ignoring
+    /// the interval being written. ex: `{"traces":["send"],"intervals":[{"counters":{"0":10}},{"cou`
+    pub(crate) fn parse(output: &str) -> Option<Self> {
+        let output = match serde_json::from_str::<Output>(output) {
+            Ok(output) => output,
+            Err(_) => parse_partial(output)?,
+        };
+
+        let mut progress = RunProgress::default();
+        for interval in output.intervals.iter() {
+            for (id, value) in interval.counters.iter() {
+                match output.traces.get(*id).map(String::as_str) {
+                    Some("send") => progress.bytes_sent += value,
+                    Some("receive") => progress.bytes_received += value,
+                    Some("connect") => progress.connections += value,
+                    _ => (),
+                }
+            }
+        }
+        Some(progress)
+    }
+
+    /// The progress of the drivers writing to `output_files`, or None if none
+    /// of them has written its traces yet.
+    pub(crate) fn read(output_files: &[String]) -> Option<Self> {
+        output_files
+            .iter()
+            .filter_map(|file| std::fs::read_to_string(file).ok())
+            .filter_map(|output| RunProgress::parse(&output))
+            .reduce(|total, progress| RunProgress {
+                bytes_sent: total.bytes_sent + progress.bytes_sent,
+                bytes_received: total.bytes_received + progress.bytes_received,
+                connections: total.connections + progress.connections,
+            })
+    }
+}
+
+// The collector writes the traces before the intervals, appending each interval
+// as it elapses.
+fn parse_partial(output: &str) -> Option<Output> {
+    let (head, intervals) = output.split_once("\"intervals\"")?;
+    let (_, traces) = head.split_once("\"traces\"")?;
+    let traces = json_value(traces.trim_start().strip_prefix(':')?)?.0;
+
+    let mut rest = intervals.trim_start().strip_prefix(':')?.trim_start();
+    rest = rest.strip_prefix('[')?;
+    let mut parsed = Vec::new();
+    loop {
+        rest = rest.trim_start_matches(|c: char| c == ',' || c.is_whitespace());
+        let Some((interval, len)) = json_value::<Interval>(rest) else {
+            break;
+        };
+        parsed.push(interval);
+        rest = &rest[len..];
+    }
+    Some(Output {
+        traces,
+        intervals: parsed,
+    })
+}
+
+// The value at the start of `json` and its length, ignoring what follows
+fn json_value<T: for<'a> Deserialize<'a>>(json: &str) -> Option<(T, usize)> {
+    let mut values = serde_json::Deserializer::from_str(json).into_iter::<T>();
+    let value = values.next()?.ok()?;
+    Some((value, values.byte_offset()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn run_progress() {
+        let complete = r#"{"traces":["send","receive","connect"],"intervals":[
+            {"time":1000000000,"counters":{"0":1000,"1":2000,"2":2}},
+            {"time":2000000000,"counters":{"0":500,"9":7}}
+        ]}"#;
+        let progress = RunProgress {
+            bytes_sent: 1500,
+            bytes_received: 2000,
+            connections: 2,
+        };
+        assert_eq!(RunProgress::parse(complete), Some(progress));
+
+        // the second interval is still being written
+        let cut = complete.find(r#""9":7"#).unwrap();
+        let first = RunProgress {
+            bytes_sent: 1000,
+            ..progress
+        };
+        assert_eq!(RunProgress::parse(&complete[..cut]), Some(first));
+        assert_eq!(
+            RunProgress::parse(r#"{"traces":["send"],"intervals":["#),
+            Some(RunProgress::default())
+        );
+        assert_eq!(RunProgress::parse(r#"{"traces":["se"#), None);
+        assert_eq!(RunProgress::parse(""), None);
+
+        let dir = tempdir::TempDir::new("run_progress").unwrap();
+        let file = |name: &str, output: &str| {
+            let path = dir.path().join(name);
+            std::fs::write(&path, output).unwrap();
+            path.to_str().unwrap().to_string()
+        };
+        let files = [
+            file("server-0.json", complete),
+            file("server-1.json", &complete[..cut]),
+            dir.path()
+                .join("missing.json")
+                .to_str()
+                .unwrap()
+                .to_string(),
+        ];
+        assert_eq!(
+            RunProgress::read(&files),
+            Some(RunProgress {
+                bytes_sent: 2500,
+                bytes_received: 4000,
+                connections: 4,
+            })
+        );
+        assert_eq!(RunProgress::read(&files[2..]), None);
+        assert_eq!(
+            progress.to_string(),
+            "sent: 0.0 MB, received: 0.0 MB, connections: 2"
+        );
+    }
+}
@@ -4,21 +4,23 @@
 use crate::russula::{
     error::{RussulaError, RussulaResult},
     event::{EventRecorder, EventType},
-    netbench::server_worker::WorkerState,
+    message::WorkerStatus,
+    netbench::{server_worker::WorkerState, ProcessExit, RunParams, RunProgress},
     network_utils::Msg,
     protocol::{private, Protocol},
+    transport::{Transport, TransportStream},
     StateApi, TransitionStep,
 };
 use async_trait::async_trait;
 use core::fmt::Debug;
 use serde::{Deserialize, Serialize};
 use std::net::SocketAddr;
-use tokio::net::TcpStream;
 use tracing::{debug, info};
 
-#[derive(Copy, Clone, Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub enum CoordState {
-    CheckWorker,
+    // Ship the run parameters to the workers
+    CheckWorker(RunParams),
     Ready,
     RunWorker,
     WorkersRunning,
@@ -31,17 +33,32 @@ pub enum CoordState {
 pub struct CoordProtocol {
     state: CoordState,
     worker_state: WorkerState,
+    // The progress last reported by the worker
+    worker_progress: Option<RunProgress>,
     event_recorder: EventRecorder,
 }
 
+impl Default for CoordProtocol {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl CoordProtocol {
     pub fn new() -> Self {
         CoordProtocol {
-            state: CoordState::CheckWorker,
+            state: CoordState::CheckWorker(RunParams::default()),
             worker_state: WorkerState::WaitCoordInit,
+            worker_progress: None,
             event_recorder: EventRecorder::default(),
         }
     }
+
+    /// The run parameters shipped to the workers.
+    pub fn with_params(mut self, params: RunParams) -> Self {
+        self.state = CoordState::CheckWorker(params);
+        self
+    }
 }
 
 impl private::Protocol for CoordProtocol {
@@ -57,16 +74,36 @@ impl Protocol for CoordProtocol {
         format!("server-c-{}", 0)
     }
 
-    async fn connect(&self, addr: &SocketAddr) -> RussulaResult<TcpStream> {
-        info!("attempt to connect on: {}", addr);
+    fn role(&self) -> &'static str {
+        "coordinator"
+    }
 
-        let connect = TcpStream::connect(addr).await.map_err(RussulaError::from)?;
-        Ok(connect)
+    async fn connect(
+        &self,
+        addr: &SocketAddr,
+        transport: Transport,
+    ) -> RussulaResult<TransportStream> {
+        transport.connect(addr).await
     }
 
     fn update_peer_state(&mut self, msg: Msg) -> RussulaResult<()> {
-        self.worker_state = WorkerState::from_msg(msg)?;
-        debug!("{} ... peer_state {:?}", self.name(), self.worker_state);
+        let status = WorkerStatus::<WorkerState>::from_msg(msg)?;
+        let pid = status.pid;
+        if status.progress.is_some() {
+            self.worker_progress = status.progress;
+        }
+        self.worker_state = status.into_state()?;
+        debug!(
+            ?pid,
+            "{} ... peer_state {:?}",
+            self.name(),
+            self.worker_state
+        );
+        if let WorkerState::Stopped(exits) = &self.worker_state {
+            for exit in exits {
+                info!("{} worker netbench process stopped. {}", self.name(), exit);
+            }
+        }
 
         Ok(())
     }
@@ -91,9 +128,20 @@ impl Protocol for CoordProtocol {
         CoordState::WorkersRunning
     }
 
-    async fn run(&mut self, stream: &TcpStream) -> RussulaResult<Option<Msg>> {
+    fn worker_exits(&self) -> &[ProcessExit] {
+        match &self.worker_state {
+            WorkerState::Stopped(exits) => exits,
+            _ => &[],
+        }
+    }
+
+    fn take_worker_progress(&mut self) -> Option<RunProgress> {
+        self.worker_progress.take()
+    }
+
+    async fn run(&mut self, stream: &TransportStream) -> RussulaResult<Option<Msg>> {
         match self.state_mut() {
-            CoordState::CheckWorker => {
+            CoordState::CheckWorker(_) => {
                 self.state().notify_peer(stream).await?;
                 self.await_next_msg(stream).await
             }
@@ -140,13 +188,15 @@ impl StateApi for CoordState {
 
     fn transition_step(&self) -> TransitionStep {
         match self {
-            CoordState::CheckWorker => TransitionStep::AwaitNext(WorkerState::Ready.as_bytes()),
+            CoordState::CheckWorker(_) => TransitionStep::AwaitNext(WorkerState::Ready.as_bytes()),
             CoordState::Ready => TransitionStep::UserDriven,
             CoordState::RunWorker => {
                 TransitionStep::AwaitNext(WorkerState::RunningAwaitKill(0).as_bytes())
             }
             CoordState::WorkersRunning => TransitionStep::UserDriven,
-            CoordState::KillWorker => TransitionStep::AwaitNext(WorkerState::Stopped.as_bytes()),
+            CoordState::KillWorker => {
+                TransitionStep::AwaitNext(WorkerState::Stopped(vec![]).as_bytes())
+            }
             CoordState::WorkerKilled => TransitionStep::UserDriven,
             CoordState::Done => TransitionStep::Finished,
         }
@@ -154,7 +204,7 @@ impl StateApi for CoordState {
 
     fn next_state(&self) -> Self {
         match self {
-            CoordState::CheckWorker => CoordState::Ready,
+            CoordState::CheckWorker(_) => CoordState::Ready,
             CoordState::Ready => CoordState::RunWorker,
             CoordState::RunWorker => CoordState::WorkersRunning,
             CoordState::WorkersRunning => CoordState::KillWorker,
@@ -167,7 +217,16 @@ impl StateApi for CoordState {
 
 #[cfg(test)]
 mod tests {
-
-    #[tokio::test]
-    async fn netbench_state() {}
+    use super::*;
+    use crate::russula::model;
+
+    #[test]
+    fn protocol_model() {
+        let stats = model::check(
+            CoordState::CheckWorker(RunParams::default()),
+            WorkerState::WaitCoordInit,
+        )
+        .unwrap();
+        assert!(stats.states > 1);
+    }
 }
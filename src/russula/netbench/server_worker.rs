@@ -1,38 +1,42 @@
 // Copyright Amazon.com, Inc. or its affiliates. All Rights Reserved.
 // SPDX-License-Identifier: Apache-2.0
 
-use super::ServerContext;
+use super::{RunParams, ServerContext};
 use crate::russula::{
     error::{RussulaError, RussulaResult},
     event::{EventRecorder, EventType},
-    netbench::server_coord::CoordState,
+    netbench::{
+        server_coord::CoordState, stderr_log, supervisor::Supervisor, ProcessExit, Profiler,
+        KILL_GRACE_PERIOD, PCAP_STOP_GRACE_PERIOD, RESULT_STREAM_STOP_GRACE_PERIOD,
+    },
     network_utils::Msg,
     protocol::{private, Protocol},
+    transport::{Transport, TransportStream},
     StateApi, TransitionStep,
 };
 use async_trait::async_trait;
-use core::fmt::Debug;
+use core::{fmt::Debug, time::Duration};
 use serde::{Deserialize, Serialize};
 use std::{
     fs::File,
     net::SocketAddr,
+    path::PathBuf,
     process::{Command, Stdio},
 };
-use sysinfo::{Pid, PidExt, ProcessExt, SystemExt};
-use tokio::net::{TcpListener, TcpStream};
-use tracing::{debug, info};
+use tracing::{debug, info, warn};
 
 // Only used when creating a state variant
 const PLACEHOLDER_PID: u32 = 1000;
 
-#[derive(Copy, Clone, Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub enum WorkerState {
     WaitCoordInit,
     Ready,
     Run,
     RunningAwaitKill(#[serde(skip)] u32),
     Killing(#[serde(skip)] u32),
-    Stopped,
+    // The exit status of each driver instance
+    Stopped(Vec<ProcessExit>),
     Done,
 }
 
@@ -43,6 +47,12 @@ pub struct WorkerProtocol {
     coord_state: CoordState,
     netbench_ctx: ServerContext,
     event_recorder: EventRecorder,
+    // A supervisor per driver instance
+    supervisors: Vec<Supervisor>,
+    sys_metrics: Option<Supervisor>,
+    profiler: Option<Supervisor>,
+    pcap_capture: Option<Supervisor>,
+    result_stream: Option<Supervisor>,
 }
 
 impl WorkerProtocol {
@@ -50,9 +60,40 @@ impl WorkerProtocol {
         WorkerProtocol {
             id,
             state: WorkerState::WaitCoordInit,
-            coord_state: CoordState::CheckWorker,
+            coord_state: CoordState::CheckWorker(RunParams::default()),
             netbench_ctx,
             event_recorder: EventRecorder::default(),
+            supervisors: Vec::new(),
+            sys_metrics: None,
+            profiler: None,
+            pcap_capture: None,
+            result_stream: None,
+        }
+    }
+}
+
+impl WorkerProtocol {
+    async fn stop_sidecars(&mut self) {
+        if let Some(sys_metrics) = self.sys_metrics.take() {
+            if let Err(err) = sys_metrics.kill() {
+                warn!("{} failed to stop sys metrics: {}", self.name(), err);
+            }
+        }
+        if let Some(mut profiler) = self.profiler.take() {
+            if let Err(err) = profiler.stop(Profiler::STOP_GRACE_PERIOD).await {
+                warn!("{} failed to stop profiler: {}", self.name(), err);
+            }
+        }
+        if let Some(mut pcap_capture) = self.pcap_capture.take() {
+            if let Err(err) = pcap_capture.stop(PCAP_STOP_GRACE_PERIOD).await {
+                warn!("{} failed to stop pcap capture: {}", self.name(), err);
+            }
+        }
+        // last, so that the remainder of the system metrics is uploaded
+        if let Some(mut result_stream) = self.result_stream.take() {
+            if let Err(err) = result_stream.stop(RESULT_STREAM_STOP_GRACE_PERIOD).await {
+                warn!("{} failed to stop result stream: {}", self.name(), err);
+            }
         }
     }
 }
@@ -71,11 +112,17 @@ impl Protocol for WorkerProtocol {
         format!("server-w-{}", self.id)
     }
 
-    async fn connect(&self, addr: &SocketAddr) -> RussulaResult<TcpStream> {
-        let listener = TcpListener::bind(addr).await.unwrap();
-        info!("{} listening on: {}", self.name(), addr);
+    fn role(&self) -> &'static str {
+        "worker"
+    }
 
-        let (stream, _local_addr) = listener.accept().await.map_err(RussulaError::from)?;
+    async fn connect(
+        &self,
+        addr: &SocketAddr,
+        transport: Transport,
+    ) -> RussulaResult<TransportStream> {
+        info!("{} listening on: {}", self.name(), addr);
+        let stream = transport.listen(addr).await?;
         info!("{} success connection: {addr}", self.name());
 
         Ok(stream)
@@ -84,6 +131,13 @@ impl Protocol for WorkerProtocol {
     fn update_peer_state(&mut self, msg: Msg) -> RussulaResult<()> {
         self.coord_state = CoordState::from_msg(msg)?;
         debug!("{} ... peer_state {:?}", self.name(), self.coord_state);
+        // Only reconfigure before running netbench
+        if let (CoordState::CheckWorker(params), WorkerState::WaitCoordInit | WorkerState::Ready) =
+            (&self.coord_state, &self.state)
+        {
+            info!("{} apply run params {:?}", self.name(), params);
+            self.netbench_ctx.apply(params);
+        }
 
         Ok(())
     }
@@ -108,7 +162,7 @@ impl Protocol for WorkerProtocol {
         unimplemented!()
     }
 
-    async fn run(&mut self, stream: &TcpStream) -> RussulaResult<Option<Msg>> {
+    async fn run(&mut self, stream: &TransportStream) -> RussulaResult<Option<Msg>> {
         match self.state_mut() {
             WorkerState::WaitCoordInit => {
                 // self.notify_peer(stream).await?;
@@ -119,45 +173,54 @@ impl Protocol for WorkerProtocol {
                 self.await_next_msg(stream).await
             }
             WorkerState::Run => {
-                let child = match &self.netbench_ctx.testing {
-                    false => {
-                        let output_log_file = format!("{}.json", self.name());
-                        let output_log_file =
-                            File::create(output_log_file).expect("failed to open log");
-
-                        // sudo SCENARIO=./target/netbench/connect.json ./target/release/netbench-collector
-                        //   ./target/release/netbench-driver-s2n-quic-server
-                        info!("{} run task netbench", self.name());
-                        println!("{} run task netbench", self.name());
-
-                        let netbench_path = self.netbench_ctx.netbench_path.to_str().unwrap();
-                        let collector = format!("{}/s2n-netbench-collector", netbench_path);
-                        // driver value ex.: netbench-driver-s2n-quic-server
-                        let driver = format!("{}/{}", netbench_path, self.netbench_ctx.driver);
-                        let scenario = format!("{}/{}", netbench_path, self.netbench_ctx.scenario);
-
-                        debug!("netbench_port: {}", self.netbench_ctx.netbench_port);
-
-                        let mut cmd = Command::new(collector);
-                        cmd.env("PORT", self.netbench_ctx.netbench_port.to_string());
-                        // cmd.arg("--disable-bpf");
-                        cmd.args([&driver, "--scenario", &scenario])
-                            .stdout(output_log_file);
-                        println!("{:?}", cmd);
-                        debug!("{:?}", cmd);
-                        cmd.spawn()
-                            .expect("Failed to start netbench server process")
-                    }
-                    true => {
-                        info!("{} run task sim_netbench_server", self.state().name(stream));
-                        Command::new("sh")
-                            .args(["scripts/sim_netbench_server.sh", &self.name()])
-                            .spawn()
-                            .expect("Failed to start echo process")
-                    }
-                };
-
-                let pid = child.id();
+                for instance in 0..self.netbench_ctx.driver_instances()? {
+                    let cmd = match &self.netbench_ctx.testing {
+                        false => {
+                            let output_log_file =
+                                self.netbench_ctx.netbench_output(&self.id, instance);
+
+                            // sudo SCENARIO=./target/netbench/connect.json ./target/release/netbench-collector
+                            //   ./target/release/netbench-driver-s2n-quic-server
+                            info!("{} run task netbench {}", self.name(), instance);
+
+                            let netbench_path = self.netbench_ctx.netbench_path.to_str().unwrap();
+                            let collector = format!("{}/s2n-netbench-collector", netbench_path);
+                            // driver value ex.: netbench-driver-s2n-quic-server
+                            let driver =
+                                format!("{}/{}", netbench_path, self.netbench_ctx.driver()?);
+                            let scenario =
+                                format!("{}/{}", netbench_path, self.netbench_ctx.scenario);
+
+                            let netbench_port = self.netbench_ctx.netbench_port(instance);
+                            debug!("netbench_port: {}", netbench_port);
+
+                            let mut cmd = Command::new(collector);
+                            cmd.env("PORT", netbench_port.to_string());
+                            cmd.args(self.netbench_ctx.collector_args());
+                            cmd.args([&driver, "--scenario", &scenario])
+                                .stdout(output_log_file);
+                            debug!("{:?}", cmd);
+                            cmd
+                        }
+                        true => {
+                            info!("{} run task sim_netbench_server", self.state().name(stream));
+                            let mut cmd = Command::new("sh");
+                            cmd.args(["scripts/sim_netbench_server.sh", &self.name()]);
+                            cmd
+                        }
+                    };
+
+                    let stderr_log = stderr_log(&self.name(), instance);
+                    self.supervisors.push(Supervisor::spawn(cmd, &stderr_log)?);
+                }
+                let pid = self.supervisors[0].pid();
+                // a warm-up run isn't measured
+                if !self.netbench_ctx.warmup {
+                    self.sys_metrics = self.netbench_ctx.spawn_sys_metrics(&self.id)?;
+                    self.profiler = self.netbench_ctx.spawn_profiler(&self.id)?;
+                    self.pcap_capture = self.netbench_ctx.spawn_pcap_capture(&self.id)?;
+                    self.result_stream = self.netbench_ctx.spawn_result_stream(&self.id)?;
+                }
                 debug!(
                     "{}----------------------------child id {}",
                     self.state().name(stream),
@@ -168,24 +231,27 @@ impl Protocol for WorkerProtocol {
                 Ok(None)
             }
             WorkerState::RunningAwaitKill(_pid) => {
-                self.state().notify_peer(stream).await?;
+                let progress = self.netbench_ctx.run_progress(&self.id);
+                self.state().notify_peer_progress(stream, progress).await?;
                 self.await_next_msg(stream).await
             }
-            WorkerState::Killing(pid) => {
-                let pid = Pid::from_u32(*pid);
-                let mut system = sysinfo::System::new_all();
-                if system.refresh_process(pid) {
-                    let process = system.process(pid).unwrap();
-                    let kill = process.kill();
-                    debug!("did KILL pid: {} {}----------------------------", pid, kill);
+            WorkerState::Killing(_pid) => {
+                // Kill the collectors along with the drivers they launched
+                let mut exits = Vec::new();
+                for supervisor in self.supervisors.iter_mut() {
+                    exits.push(supervisor.poll_kill(KILL_GRACE_PERIOD)?);
                 }
 
-                self.state_mut()
-                    .transition_self_or_user_driven(stream)
-                    .await?;
+                // Wait for every driver instance to exit
+                if let Some(exits) = exits.into_iter().collect::<Option<Vec<_>>>() {
+                    self.stop_sidecars().await;
+                    self.state_mut()
+                        .transition_to(stream, WorkerState::Stopped(exits))
+                        .await?;
+                }
                 Ok(None)
             }
-            WorkerState::Stopped => {
+            WorkerState::Stopped(_exit) => {
                 self.state().notify_peer(stream).await?;
                 self.await_next_msg(stream).await
             }
@@ -203,10 +269,21 @@ impl StateApi for WorkerState {
         "server-worker".to_string()
     }
 
+    fn is_worker_state(&self) -> bool {
+        true
+    }
+
+    fn pid(&self) -> Option<u32> {
+        match self {
+            WorkerState::RunningAwaitKill(pid) | WorkerState::Killing(pid) => Some(*pid),
+            _ => None,
+        }
+    }
+
     fn transition_step(&self) -> TransitionStep {
         match self {
             WorkerState::WaitCoordInit => {
-                TransitionStep::AwaitNext(CoordState::CheckWorker.as_bytes())
+                TransitionStep::AwaitNext(CoordState::CheckWorker(RunParams::default()).as_bytes())
             }
             WorkerState::Ready => TransitionStep::AwaitNext(CoordState::RunWorker.as_bytes()),
             WorkerState::Run => TransitionStep::SelfDriven,
@@ -214,7 +291,7 @@ impl StateApi for WorkerState {
                 TransitionStep::AwaitNext(CoordState::KillWorker.as_bytes())
             }
             WorkerState::Killing(_) => TransitionStep::SelfDriven,
-            WorkerState::Stopped => TransitionStep::AwaitNext(CoordState::Done.as_bytes()),
+            WorkerState::Stopped(_) => TransitionStep::AwaitNext(CoordState::Done.as_bytes()),
             WorkerState::Done => TransitionStep::Finished,
         }
     }
@@ -226,8 +303,9 @@ impl StateApi for WorkerState {
             // FIXME error prone
             WorkerState::Run => WorkerState::RunningAwaitKill(PLACEHOLDER_PID),
             WorkerState::RunningAwaitKill(pid) => WorkerState::Killing(*pid),
-            WorkerState::Killing(_) => WorkerState::Stopped,
-            WorkerState::Stopped => WorkerState::Done,
+            // FIXME error prone. The exit status is set when running the Killing state
+            WorkerState::Killing(_) => WorkerState::Stopped(vec![]),
+            WorkerState::Stopped(_) => WorkerState::Done,
             WorkerState::Done => WorkerState::Done,
         }
     }
@@ -0,0 +1,314 @@
+// Copyright Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::russula::error::{RussulaError, RussulaResult};
+use core::time::Duration;
+use serde::{Deserialize, Serialize};
+#[cfg(unix)]
+use std::os::unix::process::{CommandExt, ExitStatusExt};
+use std::{
+    fs::File,
+    io::{Read, Seek, SeekFrom},
+    path::{Path, PathBuf},
+    process::{Child, Command, ExitStatus},
+    sync::{Arc, Mutex},
+    time::Instant,
+};
+#[cfg(windows)]
+use std::{os::windows::process::CommandExt, process::Stdio};
+use tracing::{debug, info, warn};
+
+#[cfg(unix)]
+use libc::{SIGKILL, SIGTERM};
+// Windows has no signals. The values are only used to pick how the process
+// group is stopped
+#[cfg(windows)]
+const SIGTERM: i32 = 15;
+#[cfg(windows)]
+const SIGKILL: i32 = 9;
+#[cfg(windows)]
+const CREATE_NEW_PROCESS_GROUP: u32 = 0x0000_0200;
+
+// The amount of stderr reported to the Coordinator. Russula msgs are limited
+// to u16::MAX bytes.
+const STDERR_TAIL_BYTES: u64 = 4 * 1024;
+
+/// How a supervised process exited. Reported to the Coordinator.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ProcessExit {
+    pub code: Option<i32>,
+    pub signal: Option<i32>,
+    // The process was killed by the Supervisor
+    pub killed: bool,
+    // The last few KB of the process's stderr
+    pub stderr_tail: String,
+}
+
+impl ProcessExit {
+    pub fn success(&self) -> bool {
+        self.code == Some(0)
+    }
+
+    /// The process exited with an error before it was killed.
+    pub fn is_failure(&self) -> bool {
+        !self.killed && !self.success()
+    }
+}
+
+impl From<ExitStatus> for ProcessExit {
+    fn from(status: ExitStatus) -> Self {
+        ProcessExit {
+            code: status.code(),
+            #[cfg(unix)]
+            signal: status.signal(),
+            #[cfg(windows)]
+            signal: None,
+            ..Default::default()
+        }
+    }
+}
+
+impl std::fmt::Display for ProcessExit {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match (self.code, self.signal) {
+            (Some(code), _) => write!(f, "exit code: {}", code),
+            (None, Some(signal)) => write!(f, "killed by signal: {}", signal),
+            (None, None) => write!(f, "unknown exit"),
+        }
+    }
+}
+
+/// Supervises a netbench process.
+///
+/// The collector launches the driver as a child process, which doesn't get killed
+/// when the collector is killed. The process is therefore spawned in its own
+/// process group so that the collector and all of its children can be signaled
+/// together.
+#[derive(Clone, Debug)]
+pub struct Supervisor {
+    child: Arc<Mutex<Child>>,
+    pid: u32,
+    stderr_log: PathBuf,
+    // Set once SIGTERM has been sent to the process group
+    term_sent_at: Option<Instant>,
+}
+
+impl Supervisor {
+    /// Spawn `cmd` in a new process group, writing its stderr to `stderr_log`.
+    pub fn spawn(mut cmd: Command, stderr_log: &Path) -> RussulaResult<Self> {
+        if let Some(dir) = stderr_log.parent() {
+            std::fs::create_dir_all(dir).map_err(|err| RussulaError::Usage {
+                dbg: format!("Failed to create dir {:?}: {}", dir, err),
+            })?;
+        }
+        let stderr = File::create(stderr_log).map_err(|err| RussulaError::Usage {
+            dbg: format!("Failed to create stderr log {:?}: {}", stderr_log, err),
+        })?;
+        cmd.stderr(stderr);
+
+        // Create a new process group with the same id as the child's pid
+        #[cfg(unix)]
+        cmd.process_group(0);
+        #[cfg(windows)]
+        cmd.creation_flags(CREATE_NEW_PROCESS_GROUP);
+        let child = cmd.spawn().map_err(|err| RussulaError::Usage {
+            dbg: format!("Failed to spawn {:?}: {}", cmd, err),
+        })?;
+        let pid = child.id();
+        debug!("spawned process group: {}", pid);
+
+        Ok(Supervisor {
+            child: Arc::new(Mutex::new(child)),
+            pid,
+            stderr_log: stderr_log.to_path_buf(),
+            term_sent_at: None,
+        })
+    }
+
+    pub fn pid(&self) -> u32 {
+        self.pid
+    }
+
+    /// Returns the exit status if the process has exited.
+    ///
+    /// The remaining processes in the group are killed once the process exits.
+    pub fn try_wait(&self) -> RussulaResult<Option<ProcessExit>> {
+        let status = self
+            .child
+            .lock()
+            .expect("supervisor lock poisoned")
+            .try_wait()
+            .map_err(|err| RussulaError::Usage {
+                dbg: format!("Failed to wait on pid {}: {}", self.pid, err),
+            })?;
+
+        Ok(status.map(|status| {
+            // Cleanup any children (netbench driver) which outlived the collector
+            let _ = self.signal_group(SIGKILL);
+            let exit = ProcessExit {
+                killed: self.term_sent_at.is_some(),
+                stderr_tail: self.stderr_tail(),
+                ..ProcessExit::from(status)
+            };
+            info!("process group {} exited. {}", self.pid, exit);
+            exit
+        }))
+    }
+
+    /// Immediately kill the process group and reap the process.
+    pub fn kill(&self) -> RussulaResult<ProcessExit> {
+        self.signal_group(SIGKILL)?;
+        let status = self
+            .child
+            .lock()
+            .expect("supervisor lock poisoned")
+            .wait()
+            .map_err(|err| RussulaError::Usage {
+                dbg: format!("Failed to wait on pid {}: {}", self.pid, err),
+            })?;
+        Ok(ProcessExit {
+            killed: true,
+            ..ProcessExit::from(status)
+        })
+    }
+
+    fn stderr_tail(&self) -> String {
+        let tail = || -> std::io::Result<String> {
+            let mut file = File::open(&self.stderr_log)?;
+            let len = file.metadata()?.len();
+            file.seek(SeekFrom::Start(len.saturating_sub(STDERR_TAIL_BYTES)))?;
+            let mut tail = Vec::new();
+            file.read_to_end(&mut tail)?;
+            Ok(String::from_utf8_lossy(&tail).to_string())
+        };
+        tail().unwrap_or_else(|err| format!("Failed to read {:?}: {}", self.stderr_log, err))
+    }
+
+    /// Make progress towards killing the process group.
+    ///
+    /// The first call sends SIGTERM to the process group. If the process hasn't
+    /// exited after `grace` then SIGKILL is sent. Returns the exit status once the
+    /// process has exited.
+    pub fn poll_kill(&mut self, grace: Duration) -> RussulaResult<Option<ProcessExit>> {
+        if let Some(exit) = self.try_wait()? {
+            return Ok(Some(exit));
+        }
+
+        match self.term_sent_at {
+            None => {
+                info!("sending SIGTERM to process group {}", self.pid);
+                self.signal_group(SIGTERM)?;
+                self.term_sent_at = Some(Instant::now());
+            }
+            Some(term_sent_at) if term_sent_at.elapsed() > grace => {
+                warn!(
+                    "process group {} still running after {:?}. sending SIGKILL",
+                    self.pid, grace
+                );
+                self.signal_group(SIGKILL)?;
+            }
+            Some(_) => (),
+        }
+
+        self.try_wait()
+    }
+
+    /// Kill the process group, giving the process `grace` to exit after SIGTERM.
+    pub async fn stop(&mut self, grace: Duration) -> RussulaResult<ProcessExit> {
+        loop {
+            if let Some(exit) = self.poll_kill(grace)? {
+                return Ok(exit);
+            }
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+    }
+
+    #[cfg(unix)]
+    fn signal_group(&self, signal: libc::c_int) -> RussulaResult<()> {
+        // A negative pid signals every process in the process group
+        let ret = unsafe { libc::kill(-(self.pid as libc::pid_t), signal) };
+        if ret == 0 {
+            return Ok(());
+        }
+
+        let err = std::io::Error::last_os_error();
+        match err.raw_os_error() {
+            // The process group has already exited
+            Some(libc::ESRCH) => Ok(()),
+            _ => Err(RussulaError::Usage {
+                dbg: format!(
+                    "Failed to signal process group {} with {}: {}",
+                    self.pid, signal, err
+                ),
+            }),
+        }
+    }
+
+    /// Kill the process tree, since Windows processes can't be asked to exit.
+    /// The grace period of SIGTERM therefore doesn't apply.
+    #[cfg(windows)]
+    fn signal_group(&self, _signal: i32) -> RussulaResult<()> {
+        let status = Command::new("taskkill")
+            .args(["/T", "/F", "/PID", &self.pid.to_string()])
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status()
+            .map_err(|err| RussulaError::Usage {
+                dbg: format!("Failed to kill process tree {}: {}", self.pid, err),
+            })?;
+        // taskkill also fails once the process has already exited
+        if !status.success() {
+            debug!(
+                "taskkill of process tree {} exited with {}",
+                self.pid, status
+            );
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn kill_process_group() {
+        let tmp_dir = tempdir::TempDir::new("supervisor").unwrap();
+        let mut cmd = Command::new("sh");
+        // the child `sleep` should be killed along with the parent shell
+        cmd.args(["-c", "sleep 100 & sleep 100"]);
+        let mut supervisor = Supervisor::spawn(cmd, &tmp_dir.path().join("stderr")).unwrap();
+        assert!(supervisor.try_wait().unwrap().is_none());
+
+        let grace = Duration::from_secs(1);
+        let exit = loop {
+            if let Some(exit) = supervisor.poll_kill(grace).unwrap() {
+                break exit;
+            }
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        };
+        assert_eq!(exit.signal, Some(SIGTERM));
+        assert!(exit.killed);
+        assert!(!exit.is_failure());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn exit_code() {
+        let tmp_dir = tempdir::TempDir::new("supervisor").unwrap();
+        let mut cmd = Command::new("sh");
+        cmd.args(["-c", "echo driver failed >&2; exit 3"]);
+        let supervisor = Supervisor::spawn(cmd, &tmp_dir.path().join("stderr")).unwrap();
+        let exit = loop {
+            if let Some(exit) = supervisor.try_wait().unwrap() {
+                break exit;
+            }
+            std::thread::sleep(Duration::from_millis(10));
+        };
+        assert_eq!(exit.code, Some(3));
+        assert_eq!(exit.to_string(), "exit code: 3");
+        assert_eq!(exit.stderr_tail, "driver failed\n");
+        assert!(exit.is_failure());
+    }
+}
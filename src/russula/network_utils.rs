@@ -1,36 +1,64 @@
 // Copyright Amazon.com, Inc. or its affiliates. All Rights Reserved.
 // SPDX-License-Identifier: Apache-2.0
 
-use crate::russula::{RussulaError, RussulaResult};
-use bytes::Bytes;
-use tokio::net::TcpStream;
-use tracing::error;
+use crate::russula::{
+    transport::{TransportStream, UDP_HANDSHAKE},
+    RussulaError, RussulaResult,
+};
+use bytes::{BufMut, Bytes};
+use tokio::net::{TcpStream, UdpSocket};
+use tracing::{debug, error};
 
-pub async fn recv_msg(stream: &TcpStream) -> RussulaResult<Msg> {
+pub async fn recv_msg(stream: &TransportStream) -> RussulaResult<Msg> {
     stream.readable().await.map_err(|err| {
         error!("{}", err);
         RussulaError::from(err)
     })?;
-    read_msg(stream).await
+    match stream {
+        TransportStream::Tcp(stream) => read_msg(stream).await,
+        TransportStream::Udp(socket) => recv_datagram(socket).await,
+        #[cfg(test)]
+        TransportStream::Memory(stream) => decode_datagram(&stream.recv().await?),
+    }
 }
 
-pub async fn send_msg(stream: &TcpStream, msg: Msg) -> RussulaResult<usize> {
+pub async fn send_msg(stream: &TransportStream, msg: Msg) -> RussulaResult<usize> {
     stream.writable().await.map_err(|err| {
         error!("{}", err);
         RussulaError::from(err)
     })?;
-    write_msg(stream, msg).await
+    let data = encode_msg(msg);
+    match stream {
+        TransportStream::Tcp(stream) => write_all(stream, &data).await,
+        // Each msg is sent as a single datagram
+        TransportStream::Udp(socket) => socket.try_send(&data),
+        #[cfg(test)]
+        TransportStream::Memory(stream) => stream.send(&data).await,
+    }
+    .map_err(|err| {
+        error!("{}", err);
+        RussulaError::from(err)
+    })
 }
 
-async fn write_msg(stream: &TcpStream, msg: Msg) -> RussulaResult<usize> {
+// Large msgs (worker stderr, etc) might not fit in the socket buffer
+async fn write_all(stream: &TcpStream, data: &[u8]) -> std::io::Result<usize> {
+    let mut written = 0;
+    while written < data.len() {
+        match stream.try_write(&data[written..]) {
+            Ok(n) => written += n,
+            Err(err) if err.kind() == std::io::ErrorKind::WouldBlock => stream.writable().await?,
+            Err(err) => return Err(err),
+        }
+    }
+    Ok(written)
+}
+
+fn encode_msg(msg: Msg) -> Vec<u8> {
     let mut data: Vec<u8> = Vec::with_capacity((msg.len + 1).into());
     data.extend(msg.len.to_be_bytes());
     data.extend(msg.data);
-
-    stream.try_write(&data).map_err(|err| {
-        error!("{}", err);
-        RussulaError::from(err)
-    })
+    data
 }
 
 async fn read_msg(stream: &TcpStream) -> RussulaResult<Msg> {
@@ -42,18 +70,30 @@ async fn read_msg(stream: &TcpStream) -> RussulaResult<Msg> {
     if o == 0 {
         error!("read len 0");
         return Err(RussulaError::NetworkBlocked {
-            dbg: format!("read 0 data.. read socket closed?"),
+            dbg: "read 0 data.. read socket closed?".to_string(),
         });
     }
     let len = u16::from_be_bytes(len_buf);
 
+    // Large msgs can be split across multiple reads
     let mut data = Vec::with_capacity(len.into());
-    let read_bytes = stream.try_read_buf(&mut data).map_err(|err| {
-        error!("{}", err);
-        RussulaError::from(err)
-    })?;
+    while data.len() < len as usize {
+        let remaining = len as usize - data.len();
+        let mut remaining = (&mut data).limit(remaining);
+        match stream.try_read_buf(&mut remaining) {
+            Ok(0) => break,
+            Ok(_) => (),
+            Err(err) if err.kind() == std::io::ErrorKind::WouldBlock => {
+                stream.readable().await.map_err(RussulaError::from)?
+            }
+            Err(err) => {
+                error!("{}", err);
+                return Err(RussulaError::from(err));
+            }
+        }
+    }
 
-    if read_bytes == len as usize {
+    if data.len() == len as usize {
         Ok(Msg::new(data.into()))
     } else {
         let data = std::str::from_utf8(&data).unwrap_or("Unable to parse bytes as str!!");
@@ -63,7 +103,41 @@ async fn read_msg(stream: &TcpStream) -> RussulaResult<Msg> {
     }
 }
 
-#[derive(Debug)]
+async fn recv_datagram(socket: &UdpSocket) -> RussulaResult<Msg> {
+    let mut buf = vec![0; u16::MAX as usize + 2];
+    let len = socket.try_recv(&mut buf).map_err(|err| {
+        error!("{}", err);
+        RussulaError::from(err)
+    })?;
+    let datagram = &buf[..len];
+
+    // The Worker's handshake response was lost and the Coordinator retransmitted
+    // the handshake. Respond again and let the caller retry.
+    if datagram == UDP_HANDSHAKE {
+        debug!("received retransmitted udp handshake");
+        let _ = socket.try_send(UDP_HANDSHAKE);
+        return Err(RussulaError::NetworkBlocked {
+            dbg: "received udp handshake".to_string(),
+        });
+    }
+
+    decode_datagram(datagram)
+}
+
+fn decode_datagram(datagram: &[u8]) -> RussulaResult<Msg> {
+    match datagram {
+        [len_0, len_1, data @ ..]
+            if u16::from_be_bytes([*len_0, *len_1]) as usize == data.len() =>
+        {
+            Ok(Msg::new(Bytes::copy_from_slice(data)))
+        }
+        _ => Err(RussulaError::BadMsg {
+            dbg: format!("received a malformed datagram. len: {}", datagram.len()),
+        }),
+    }
+}
+
+#[derive(Clone, Debug)]
 pub struct Msg {
     pub len: u16,
     pub data: Bytes,
@@ -88,3 +162,40 @@ impl std::fmt::Display for Msg {
         write!(f, "Msg [ len: {} data: {} ]", self.len, data)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::russula::transport::MemoryStream;
+
+    #[tokio::test]
+    async fn memory_stream_msgs() {
+        let (coord, worker) = MemoryStream::pair();
+        for msg in [&b"\"Ready\""[..], b"\"Done\""] {
+            send_msg(&coord, Msg::new(Bytes::from_static(msg)))
+                .await
+                .unwrap();
+        }
+        assert_eq!(recv_msg(&worker).await.unwrap().as_bytes(), b"\"Ready\"");
+        assert_eq!(recv_msg(&worker).await.unwrap().as_bytes(), b"\"Done\"");
+        // an empty stream doesn't block
+        assert!(matches!(
+            recv_msg(&worker).await,
+            Err(RussulaError::NetworkBlocked { .. })
+        ));
+
+        drop(coord);
+        assert!(recv_msg(&worker).await.unwrap_err().is_fatal());
+    }
+
+    #[test]
+    fn datagram_round_trip() {
+        let datagram = encode_msg(Msg::new(Bytes::from_static(b"\"Ready\"")));
+        let msg = decode_datagram(&datagram).unwrap();
+        assert_eq!(msg.as_bytes(), b"\"Ready\"");
+
+        // truncated datagram
+        assert!(decode_datagram(&datagram[..datagram.len() - 1]).is_err());
+        assert!(decode_datagram(&[0]).is_err());
+    }
+}
@@ -0,0 +1,60 @@
+// Copyright Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+use super::netbench::RunProgress;
+use std::net::SocketAddr;
+use tokio::sync::mpsc::{unbounded_channel, UnboundedSender};
+use tokio_stream::wrappers::UnboundedReceiverStream;
+
+/// The progress of a Russula's peers, emitted as the Russula is polled.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[allow(clippy::enum_variant_names)]
+pub enum RussulaEvent {
+    PeerConnected {
+        peer: SocketAddr,
+    },
+    // The protocol of the peer moved to the next state. ex: Ready -> RunAt
+    PeerTransitioned {
+        peer: SocketAddr,
+        from: String,
+        to: String,
+    },
+    // The drivers of a running peer made progress
+    PeerProgress {
+        peer: SocketAddr,
+        progress: RunProgress,
+    },
+    // The peer failed. `excluded` if the FailurePolicy continues without it
+    PeerError {
+        peer: SocketAddr,
+        error: String,
+        excluded: bool,
+    },
+}
+
+/// A stream of [`RussulaEvent`], which ends when its Russula is dropped.
+pub type RussulaEvents = UnboundedReceiverStream<RussulaEvent>;
+
+// The subscriber of a Russula's events, if any
+#[derive(Default)]
+pub(crate) struct EventSink {
+    sender: Option<UnboundedSender<RussulaEvent>>,
+}
+
+impl EventSink {
+    pub(crate) fn subscribe(&mut self) -> RussulaEvents {
+        let (sender, receiver) = unbounded_channel();
+        self.sender = Some(sender);
+        UnboundedReceiverStream::new(receiver)
+    }
+
+    pub(crate) fn emit(&mut self, event: RussulaEvent) {
+        let Some(sender) = self.sender.as_ref() else {
+            return;
+        };
+        // stop emitting once the stream is dropped
+        if sender.send(event).is_err() {
+            self.sender = None;
+        }
+    }
+}
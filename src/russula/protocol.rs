@@ -4,9 +4,11 @@
 use super::{
     error::RussulaError,
     event::EventType,
+    netbench::{ProcessExit, RunProgress},
     network_utils,
     network_utils::Msg,
     states::{StateApi, TransitionStep},
+    transport::{Transport, TransportStream},
     RussulaResult,
 };
 use async_trait::async_trait;
@@ -15,8 +17,7 @@ use core::{fmt::Debug, task::Poll, time::Duration};
 use paste::paste;
 use serde::{Deserialize, Serialize};
 use std::net::SocketAddr;
-use tokio::net::TcpStream;
-use tracing::{debug, info};
+use tracing::{debug, info, info_span, Span};
 
 const NOTIFY_DONE_TIMEOUT: Duration = Duration::from_secs(1);
 
@@ -24,10 +25,28 @@ pub type SockProtocol<P> = (SocketAddr, P);
 
 pub(crate) struct ProtocolInstance<P: Protocol> {
     pub addr: SocketAddr,
-    pub stream: TcpStream,
+    pub stream: TransportStream,
     pub protocol: P,
 }
 
+impl<P: Protocol> ProtocolInstance<P> {
+    /// The span of a single poll of the peer, used to correlate the Coordinator
+    /// and Worker traces.
+    pub fn span(&self) -> Span {
+        peer_span(&self.addr, &self.protocol)
+    }
+}
+
+pub(crate) fn peer_span<P: Protocol>(addr: &SocketAddr, protocol: &P) -> Span {
+    info_span!(
+        "peer",
+        addr = %addr,
+        role = protocol.role(),
+        name = %protocol.name(),
+        state = ?protocol.state()
+    )
+}
+
 macro_rules! state_api {
 {$state:ident} => {paste!{
     fn [<$state _state>](&self) -> Self::State;
@@ -35,7 +54,7 @@ macro_rules! state_api {
     /// Check if the Instance is at the desired state
     fn [<is_ $state _state>](&self) -> bool {
         let state = self.[<$state _state>]();
-        matches!(self.state(), state)
+        self.state().eq(&state)
     }
 }};
 }
@@ -46,15 +65,35 @@ pub trait Protocol: private::Protocol + Clone {
 
     // TODO use version and app to negotiate version
     fn name(&self) -> String;
-    async fn connect(&self, addr: &SocketAddr) -> RussulaResult<TcpStream>;
-    async fn run(&mut self, stream: &TcpStream) -> RussulaResult<Option<Msg>>;
+    /// coordinator or worker
+    fn role(&self) -> &'static str;
+    async fn connect(
+        &self,
+        addr: &SocketAddr,
+        transport: Transport,
+    ) -> RussulaResult<TransportStream>;
+    async fn run(&mut self, stream: &TransportStream) -> RussulaResult<Option<Msg>>;
     fn update_peer_state(&mut self, msg: Msg) -> RussulaResult<()>;
     fn state(&self) -> &Self::State;
     fn state_mut(&mut self) -> &mut Self::State;
 
+    /// How each of the worker's netbench processes exited, as reported by the worker.
+    ///
+    /// Should only be called by Coordinators
+    fn worker_exits(&self) -> &[ProcessExit] {
+        &[]
+    }
+
+    /// The progress last reported by a running worker, if it wasn't taken yet.
+    ///
+    /// Should only be called by Coordinators
+    fn take_worker_progress(&mut self) -> Option<RunProgress> {
+        None
+    }
+
     // Ready ==============
     state_api!(ready);
-    async fn poll_ready(&mut self, stream: &TcpStream) -> RussulaResult<Poll<()>> {
+    async fn poll_ready(&mut self, stream: &TransportStream) -> RussulaResult<Poll<()>> {
         let state = self.ready_state();
         self.poll_state(stream, &state).await
     }
@@ -62,7 +101,7 @@ pub trait Protocol: private::Protocol + Clone {
     // Done ==============
     // state_api!(done);
     fn done_state(&self) -> Self::State;
-    async fn poll_done(&mut self, stream: &TcpStream) -> RussulaResult<Poll<()>> {
+    async fn poll_done(&mut self, stream: &TransportStream) -> RussulaResult<Poll<()>> {
         let state = self.done_state();
         self.poll_state(stream, &state).await
     }
@@ -80,7 +119,7 @@ pub trait Protocol: private::Protocol + Clone {
     /// Should only be called by Coordinators
     state_api!(worker_running);
     /// Check if worker the Instance is Running
-    async fn poll_worker_running(&mut self, stream: &TcpStream) -> RussulaResult<Poll<()>> {
+    async fn poll_worker_running(&mut self, stream: &TransportStream) -> RussulaResult<Poll<()>> {
         let state = self.worker_running_state();
         self.poll_state(stream, &state).await
     }
@@ -89,22 +128,39 @@ pub trait Protocol: private::Protocol + Clone {
     // 'run_current' action
     async fn poll_state(
         &mut self,
-        stream: &TcpStream,
+        stream: &TransportStream,
         state: &Self::State,
     ) -> RussulaResult<Poll<()>> {
         if !self.state().eq(state) {
             let prev = self.state().clone();
-            self.run_current(stream).await?;
-            debug!(
-                "{} poll_state--------{:?} -> {:?}",
-                self.name(),
-                prev,
-                self.state()
-            );
+            match self.run_current(stream).await {
+                Ok(()) => (),
+                // The peer may have closed the connection as soon as it reached Done,
+                // before the notification of our own transition to Done.
+                Err(RussulaError::NetworkConnectionRefused { dbg })
+                | Err(RussulaError::NetworkBlocked { dbg })
+                | Err(RussulaError::NetworkFail { dbg })
+                    if self.is_done_state() =>
+                {
+                    debug!("Ignore network failure since coordination is Done. {}", dbg)
+                }
+                Err(err) => {
+                    // Best effort, so that the Coordinator learns why the session failed
+                    // rather than only seeing the connection drop
+                    if err.is_fatal() && self.state().is_worker_state() {
+                        if let Err(notify_err) = self.state().notify_peer_error(stream, &err).await
+                        {
+                            debug!("Failed to report the error to the peer. {}", notify_err)
+                        }
+                    }
+                    return Err(err);
+                }
+            }
+            debug!(from = ?prev, to = ?self.state(), "poll_state");
         }
         // Notify the peer that the protocol has reached a terminal state
         if self.is_done_state() {
-            tracing::info!("{}", self.event_recorder());
+            info!(events = %self.event_recorder(), "done");
 
             // Notify 3 time in case of packet loss.. this is best effort
             for _i in 0..3 {
@@ -132,14 +188,14 @@ pub trait Protocol: private::Protocol + Clone {
         Ok(poll)
     }
 
-    async fn run_current(&mut self, stream: &TcpStream) -> RussulaResult<()> {
+    async fn run_current(&mut self, stream: &TransportStream) -> RussulaResult<()> {
         if let Some(msg) = self.run(stream).await? {
             self.update_peer_state(msg)?;
         }
         Ok(())
     }
 
-    async fn await_next_msg(&mut self, stream: &TcpStream) -> RussulaResult<Option<Msg>> {
+    async fn await_next_msg(&mut self, stream: &TransportStream) -> RussulaResult<Option<Msg>> {
         if !matches!(self.state().transition_step(), TransitionStep::AwaitNext(_)) {
             panic!(
                 "expected AwaitNext but found: {:?}",
@@ -157,11 +213,7 @@ pub trait Protocol: private::Protocol + Clone {
             match network_utils::recv_msg(stream).await {
                 Ok(msg) => {
                     self.on_event(EventType::RecvMsg);
-                    debug!(
-                        "{} <---- recv msg {}",
-                        self.name(),
-                        std::str::from_utf8(&msg.data).unwrap()
-                    );
+                    debug!(msg = std::str::from_utf8(&msg.data).unwrap(), "recv msg");
 
                     let state = self.state();
                     let should_transition = state.matches_transition_msg(stream, &msg).await?;
@@ -1,14 +1,18 @@
 // Copyright Amazon.com, Inc. or its affiliates. All Rights Reserved.
 // SPDX-License-Identifier: Apache-2.0
 
-use super::{error::RussulaError, network_utils::Msg};
-use crate::russula::{network_utils, RussulaResult};
+use super::{
+    error::RussulaError,
+    message::{self, WorkerStatus},
+    netbench::RunProgress,
+    network_utils::Msg,
+};
+use crate::russula::{network_utils, transport::TransportStream, RussulaResult};
 use async_trait::async_trait;
 use bytes::Bytes;
 use core::{fmt::Debug, task::Poll, time::Duration};
 use serde::{Deserialize, Serialize};
 use std::net::SocketAddr;
-use tokio::net::TcpStream;
 use tracing::{debug, info};
 
 #[derive(Debug)]
@@ -27,59 +31,111 @@ pub enum TransitionStep {
 pub trait StateApi: Send + Sync + Clone + Debug + Serialize + for<'a> Deserialize<'a> {
     fn name_prefix(&self) -> String;
 
-    fn name(&self, stream: &TcpStream) -> String {
+    fn name(&self, stream: &TransportStream) -> String {
         self.name_prefix().to_string()
     }
 
     fn transition_step(&self) -> TransitionStep;
     fn next_state(&self) -> Self;
 
-    async fn notify_peer(&self, stream: &TcpStream) -> RussulaResult<usize> {
-        let msg = Msg::new(self.as_bytes());
-        debug!(
-            "{} ----> send msg {}",
-            self.name(stream),
-            std::str::from_utf8(&msg.data).unwrap()
+    /// Worker states are sent wrapped in a [`WorkerStatus`].
+    fn is_worker_state(&self) -> bool {
+        false
+    }
+
+    /// The pid of the netbench driver, reported by Worker states which run it.
+    fn pid(&self) -> Option<u32> {
+        None
+    }
+
+    fn msg(&self, error: Option<String>) -> Msg {
+        if self.is_worker_state() {
+            Msg::new(WorkerStatus::new(self, error).as_bytes())
+        } else {
+            Msg::new(self.as_bytes())
+        }
+    }
+
+    async fn notify_peer(&self, stream: &TransportStream) -> RussulaResult<usize> {
+        let msg = self.msg(None);
+        debug!(msg = std::str::from_utf8(&msg.data).unwrap(), "send msg");
+        network_utils::send_msg(stream, msg).await
+    }
+
+    /// Notify the peer of a running Worker's state along with the progress of
+    /// its drivers.
+    async fn notify_peer_progress(
+        &self,
+        stream: &TransportStream,
+        progress: Option<RunProgress>,
+    ) -> RussulaResult<usize> {
+        let msg = Msg::new(
+            WorkerStatus::new(self, None)
+                .with_progress(progress)
+                .as_bytes(),
         );
+        debug!(msg = std::str::from_utf8(&msg.data).unwrap(), "send msg");
         network_utils::send_msg(stream, msg).await
     }
 
-    async fn transition_self_or_user_driven(&mut self, stream: &TcpStream) -> RussulaResult<()> {
-        info!(
-            "{} MOVING TO NEXT STATE. {:?} ===> {:?}",
-            self.name(stream),
-            self,
-            self.next_state()
+    /// Report the error which failed a Worker's session to the Coordinator.
+    async fn notify_peer_error(
+        &self,
+        stream: &TransportStream,
+        error: &RussulaError,
+    ) -> RussulaResult<usize> {
+        let msg = self.msg(Some(error.to_string()));
+        debug!(
+            msg = std::str::from_utf8(&msg.data).unwrap(),
+            "send error msg"
         );
+        network_utils::send_msg(stream, msg).await
+    }
+
+    async fn transition_self_or_user_driven(
+        &mut self,
+        stream: &TransportStream,
+    ) -> RussulaResult<()> {
+        info!(from = ?self, to = ?self.next_state(), "state transition");
 
         *self = self.next_state();
         self.notify_peer(stream).await.map(|_| ())
     }
 
-    async fn transition_next(&mut self, stream: &TcpStream) -> RussulaResult<()> {
-        info!(
-            "{} MOVING TO NEXT STATE. {:?} ===> {:?}",
-            self.name(stream),
-            self,
-            self.next_state()
-        );
+    async fn transition_next(&mut self, stream: &TransportStream) -> RussulaResult<()> {
+        info!(from = ?self, to = ?self.next_state(), "state transition");
 
         *self = self.next_state();
         self.notify_peer(stream).await.map(|_| ())
     }
 
+    /// Transition to a state which carries data only known at runtime (start
+    /// time, exit status, etc) rather than [`StateApi::next_state`].
+    async fn transition_to(
+        &mut self,
+        stream: &TransportStream,
+        next_state: Self,
+    ) -> RussulaResult<()> {
+        info!(from = ?self, to = ?next_state, "state transition");
+
+        *self = next_state;
+        self.notify_peer(stream).await.map(|_| ())
+    }
+
     async fn matches_transition_msg(
         &self,
-        stream: &TcpStream,
+        stream: &TransportStream,
         recv_msg: &Msg,
     ) -> RussulaResult<bool> {
         if let TransitionStep::AwaitNext(expected_msg) = self.transition_step() {
-            let should_transition_to_next = expected_msg == recv_msg.as_bytes();
+            // Only compare the state variant since some states carry data which is
+            // only known at runtime (start time, exit status, etc).
+            let should_transition_to_next =
+                state_variant(&expected_msg) == state_variant(recv_msg.as_bytes());
             debug!(
-                "{} expect: {} actual: {}",
-                self.name(stream),
-                std::str::from_utf8(&expected_msg).unwrap(),
-                std::str::from_utf8(&recv_msg.data).unwrap()
+                expect = std::str::from_utf8(&expected_msg).unwrap(),
+                actual = std::str::from_utf8(&recv_msg.data).unwrap(),
+                "match transition msg"
             );
             Ok(should_transition_to_next)
         } else {
@@ -95,19 +151,51 @@ pub trait StateApi: Send + Sync + Clone + Debug + Serialize + for<'a> Deserializ
         serde_json::to_string(self).unwrap().into()
     }
 
+    /// The name of the state's variant, ignoring its fields. ex: `RunAt`
+    fn variant(&self) -> String {
+        state_variant(&self.as_bytes()).unwrap_or_else(|| format!("{:?}", self))
+    }
+
     fn from_msg(msg: Msg) -> RussulaResult<Self> {
-        let msg_str = std::str::from_utf8(&msg.data).map_err(|_err| RussulaError::BadMsg {
-            dbg: format!(
-                "received a malformed msg. len: {} data: {:?}",
-                msg.len, msg.data
-            ),
-        })?;
-
-        serde_json::from_str(msg_str).map_err(|_err| RussulaError::BadMsg {
-            dbg: format!(
-                "received a malformed msg. len: {} data: {:?}",
-                msg.len, msg.data
-            ),
-        })
+        message::state_value(&msg.data)
+            .and_then(|state| serde_json::from_value(state).ok())
+            .ok_or_else(|| RussulaError::BadMsg {
+                dbg: format!(
+                    "received a malformed msg. len: {} data: {:?}",
+                    msg.len, msg.data
+                ),
+            })
+    }
+}
+
+// The name of the enum variant of a serialized state.
+//
+// Unit variants serialize as `"Ready"` while variants with data serialize as
+// `{"RunAt":1704778530000}`. Worker states are wrapped in a WorkerStatus.
+pub(crate) fn state_variant(msg: &[u8]) -> Option<String> {
+    match message::state_value(msg)? {
+        serde_json::Value::String(variant) => Some(variant),
+        serde_json::Value::Object(map) if map.len() == 1 => map.keys().next().cloned(),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn match_state_variant() {
+        assert_eq!(state_variant(b"\"Ready\""), Some("Ready".to_string()));
+        assert_eq!(
+            state_variant(b"{\"RunAt\":1704778530000}"),
+            state_variant(b"{\"RunAt\":0}")
+        );
+        assert_ne!(state_variant(b"\"Ready\""), state_variant(b"\"Done\""));
+        assert_eq!(state_variant(b"not json"), None);
+        assert_eq!(
+            state_variant(b"{\"state\":\"Ready\",\"pid\":42}"),
+            Some("Ready".to_string())
+        );
     }
 }
@@ -0,0 +1,229 @@
+// Copyright Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::russula::{
+    network_utils::{self, Msg},
+    RussulaError, RussulaResult,
+};
+use core::{str::FromStr, time::Duration};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use tokio::net::{TcpListener, TcpStream, UdpSocket};
+use tracing::{debug, info};
+
+#[cfg(test)]
+mod memory;
+#[cfg(test)]
+pub use memory::{Chaos, MemoryStream};
+
+// Exchanged by the Coordinator and Worker to establish a UDP 'connection'
+pub(crate) const UDP_HANDSHAKE: &[u8] = b"russula-udp-handshake";
+const UDP_HANDSHAKE_RETRY: usize = 5;
+const UDP_HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(1);
+// Sent by a Worker registering with a Coordinator, followed by the addr the
+// Coordinator knows the Worker by
+const REGISTRATION: &str = "russula-register ";
+const REGISTRATION_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// The transport used by the Coordinator and Worker to communicate.
+///
+/// Russula periodically re-sends the current state to its peer while waiting for
+/// a transition so a lost UDP datagram is recovered on the next poll.
+/// This makes UDP a good alternative in environments where long-lived TCP
+/// connections are unreliable (NAT, etc).
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Transport {
+    #[default]
+    Tcp,
+    Udp,
+}
+
+impl Transport {
+    pub fn as_str(&self) -> &str {
+        match self {
+            Transport::Tcp => "tcp",
+            Transport::Udp => "udp",
+        }
+    }
+
+    /// Called by the Coordinator to connect to a Worker
+    pub async fn connect(&self, addr: &SocketAddr) -> RussulaResult<TransportStream> {
+        info!("attempt to connect on: {} {}", self, addr);
+        match self {
+            Transport::Tcp => {
+                let stream = TcpStream::connect(addr).await.map_err(RussulaError::from)?;
+                Ok(TransportStream::Tcp(stream))
+            }
+            Transport::Udp => {
+                let local_addr = match addr {
+                    SocketAddr::V4(_) => SocketAddr::new(IpAddr::V4(Ipv4Addr::UNSPECIFIED), 0),
+                    SocketAddr::V6(_) => SocketAddr::new(IpAddr::V6(Ipv6Addr::UNSPECIFIED), 0),
+                };
+                let socket = UdpSocket::bind(local_addr)
+                    .await
+                    .map_err(RussulaError::from)?;
+                socket.connect(addr).await.map_err(RussulaError::from)?;
+
+                // retransmit the handshake until the Worker responds
+                let mut buf = [0; UDP_HANDSHAKE.len()];
+                for _ in 0..UDP_HANDSHAKE_RETRY {
+                    socket
+                        .send(UDP_HANDSHAKE)
+                        .await
+                        .map_err(RussulaError::from)?;
+                    match tokio::time::timeout(UDP_HANDSHAKE_TIMEOUT, socket.recv(&mut buf)).await {
+                        Ok(Ok(len)) if &buf[..len] == UDP_HANDSHAKE => {
+                            return Ok(TransportStream::Udp(socket))
+                        }
+                        Ok(Ok(_len)) => debug!("ignore unexpected msg during handshake"),
+                        Ok(Err(err)) => return Err(RussulaError::from(err)),
+                        Err(_elapsed) => debug!("udp handshake timed out.. retrying"),
+                    }
+                }
+                Err(RussulaError::NetworkConnectionRefused {
+                    dbg: format!("udp handshake with {} timed out", addr),
+                })
+            }
+        }
+    }
+
+    /// Called by the Worker to listen for a Coordinator
+    pub async fn listen(&self, addr: &SocketAddr) -> RussulaResult<TransportStream> {
+        match self {
+            Transport::Tcp => {
+                let listener = TcpListener::bind(addr).await.map_err(RussulaError::from)?;
+                info!("listening on: {} {}", self, addr);
+                let (stream, _local_addr) = listener.accept().await.map_err(RussulaError::from)?;
+                Ok(TransportStream::Tcp(stream))
+            }
+            Transport::Udp => {
+                let socket = UdpSocket::bind(addr).await.map_err(RussulaError::from)?;
+                info!("listening on: {} {}", self, addr);
+                let mut buf = [0; UDP_HANDSHAKE.len()];
+                loop {
+                    let (len, peer) = socket
+                        .recv_from(&mut buf)
+                        .await
+                        .map_err(RussulaError::from)?;
+                    if &buf[..len] == UDP_HANDSHAKE {
+                        socket.connect(peer).await.map_err(RussulaError::from)?;
+                        socket
+                            .send(UDP_HANDSHAKE)
+                            .await
+                            .map_err(RussulaError::from)?;
+                        return Ok(TransportStream::Udp(socket));
+                    }
+                }
+            }
+        }
+    }
+
+    /// Called by the Worker to dial a Coordinator accepting registrations, and
+    /// register as `worker`. Only supported over TCP.
+    pub async fn register(
+        &self,
+        coordinator: &SocketAddr,
+        worker: &SocketAddr,
+    ) -> RussulaResult<TransportStream> {
+        info!("registering as {} with: {} {}", worker, self, coordinator);
+        let stream = TcpStream::connect(coordinator)
+            .await
+            .map_err(RussulaError::from)?;
+        let stream = TransportStream::Tcp(stream);
+        network_utils::send_msg(&stream, Msg::new(format!("{REGISTRATION}{worker}").into()))
+            .await?;
+        Ok(stream)
+    }
+}
+
+/// Accepts the Workers dialing a Coordinator, ex: Workers without a reachable
+/// address in a private subnet or behind a NAT.
+pub(crate) struct Registrar {
+    listener: TcpListener,
+}
+
+impl Registrar {
+    pub async fn bind(addr: &SocketAddr) -> RussulaResult<Self> {
+        let listener = TcpListener::bind(addr).await.map_err(RussulaError::from)?;
+        info!("accepting registrations on: {}", addr);
+        Ok(Registrar { listener })
+    }
+
+    /// The next Worker to register, and the addr it registered as.
+    pub async fn accept(&self) -> RussulaResult<(SocketAddr, TransportStream)> {
+        let (stream, peer) = self.listener.accept().await.map_err(RussulaError::from)?;
+        let stream = TransportStream::Tcp(stream);
+        let registration = async {
+            loop {
+                match network_utils::recv_msg(&stream).await {
+                    Err(err) if !err.is_fatal() => continue,
+                    msg => break msg,
+                }
+            }
+        };
+        let msg = tokio::time::timeout(REGISTRATION_TIMEOUT, registration)
+            .await
+            .map_err(|_elapsed| RussulaError::BadMsg {
+                dbg: format!("{} didn't register within {:?}", peer, REGISTRATION_TIMEOUT),
+            })??;
+        let worker = parse_registration(&msg.data).ok_or(RussulaError::BadMsg {
+            dbg: format!("invalid registration from {}: {:?}", peer, msg.data),
+        })?;
+        debug!("{} registered as {}", peer, worker);
+        Ok((worker, stream))
+    }
+}
+
+fn parse_registration(data: &[u8]) -> Option<SocketAddr> {
+    std::str::from_utf8(data)
+        .ok()?
+        .strip_prefix(REGISTRATION)?
+        .parse()
+        .ok()
+}
+
+impl std::fmt::Display for Transport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+impl FromStr for Transport {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "tcp" => Ok(Transport::Tcp),
+            "udp" => Ok(Transport::Udp),
+            _ => Err(format!("unsupported transport: {}. expected tcp or udp", s)),
+        }
+    }
+}
+
+/// A connected stream between a Coordinator and Worker.
+#[derive(Debug)]
+pub enum TransportStream {
+    Tcp(TcpStream),
+    Udp(UdpSocket),
+    #[cfg(test)]
+    Memory(MemoryStream),
+}
+
+impl TransportStream {
+    pub async fn readable(&self) -> std::io::Result<()> {
+        match self {
+            TransportStream::Tcp(stream) => stream.readable().await,
+            TransportStream::Udp(socket) => socket.readable().await,
+            #[cfg(test)]
+            TransportStream::Memory(_stream) => Ok(()),
+        }
+    }
+
+    pub async fn writable(&self) -> std::io::Result<()> {
+        match self {
+            TransportStream::Tcp(stream) => stream.writable().await,
+            TransportStream::Udp(socket) => socket.writable().await,
+            #[cfg(test)]
+            TransportStream::Memory(_stream) => Ok(()),
+        }
+    }
+}
@@ -0,0 +1,204 @@
+// Copyright Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+use super::TransportStream;
+use bytes::{Bytes, BytesMut};
+use core::time::Duration;
+use futures::FutureExt;
+use std::sync::Mutex;
+use tokio::io::{AsyncReadExt, AsyncWriteExt, DuplexStream, ReadHalf, WriteHalf};
+
+/// Faults injected into the msgs sent on a [`MemoryStream`].
+///
+/// The faults are derived from the seed so a failing case can be replayed.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Chaos {
+    pub seed: u64,
+    // Percentage of msgs which are dropped
+    pub drop_pct: u64,
+    // Percentage of msgs which are delivered twice
+    pub duplicate_pct: u64,
+    // Each msg is delayed by up to this
+    pub max_delay: Duration,
+    // The stream is disconnected instead of sending the Nth msg
+    pub disconnect_after: Option<usize>,
+}
+
+#[derive(Debug, Default, PartialEq, Eq)]
+struct Faults {
+    drop: bool,
+    duplicate: bool,
+    delay: Duration,
+    disconnect: bool,
+}
+
+#[derive(Debug)]
+struct ChaosState {
+    chaos: Chaos,
+    rng: u64,
+    sent: usize,
+    disconnected: bool,
+}
+
+impl ChaosState {
+    fn new(chaos: Chaos) -> Self {
+        ChaosState {
+            chaos,
+            rng: chaos.seed,
+            sent: 0,
+            disconnected: false,
+        }
+    }
+
+    // splitmix64
+    fn next_u64(&mut self) -> u64 {
+        self.rng = self.rng.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.rng;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    fn next_faults(&mut self) -> Faults {
+        self.disconnected |= self.chaos.disconnect_after == Some(self.sent);
+        self.sent += 1;
+        if self.disconnected {
+            return Faults {
+                disconnect: true,
+                ..Default::default()
+            };
+        }
+        let max_delay_ms = self.chaos.max_delay.as_millis() as u64;
+        Faults {
+            drop: self.next_u64() % 100 < self.chaos.drop_pct,
+            duplicate: self.next_u64() % 100 < self.chaos.duplicate_pct,
+            delay: Duration::from_millis(self.next_u64() % (max_delay_ms + 1)),
+            disconnect: false,
+        }
+    }
+}
+
+/// An in-memory stream, backed by [`tokio::io::duplex`], which connects a
+/// Coordinator and Worker in tests without binding sockets.
+///
+/// Like the udp transport, msg boundaries are preserved and a msg is never
+/// waited on: reading an empty stream returns `WouldBlock`.
+#[derive(Debug)]
+pub struct MemoryStream {
+    reader: tokio::sync::Mutex<(ReadHalf<DuplexStream>, BytesMut)>,
+    writer: tokio::sync::Mutex<WriteHalf<DuplexStream>>,
+    chaos: Option<Mutex<ChaosState>>,
+}
+
+impl MemoryStream {
+    /// A connected Coordinator and Worker stream.
+    pub fn pair() -> (TransportStream, TransportStream) {
+        Self::pair_with_chaos(None, None)
+    }
+
+    /// A connected Coordinator and Worker stream, which inject faults into the
+    /// msgs they send.
+    pub fn pair_with_chaos(
+        coord: Option<Chaos>,
+        worker: Option<Chaos>,
+    ) -> (TransportStream, TransportStream) {
+        let (coord_stream, worker_stream) = tokio::io::duplex(u16::MAX as usize * 4);
+        (
+            TransportStream::Memory(MemoryStream::new(coord_stream, coord)),
+            TransportStream::Memory(MemoryStream::new(worker_stream, worker)),
+        )
+    }
+
+    fn new(stream: DuplexStream, chaos: Option<Chaos>) -> Self {
+        let (reader, writer) = tokio::io::split(stream);
+        MemoryStream {
+            reader: tokio::sync::Mutex::new((reader, BytesMut::new())),
+            writer: tokio::sync::Mutex::new(writer),
+            chaos: chaos.map(|chaos| Mutex::new(ChaosState::new(chaos))),
+        }
+    }
+
+    fn is_disconnected(&self) -> bool {
+        self.chaos
+            .as_ref()
+            .is_some_and(|chaos| chaos.lock().unwrap().disconnected)
+    }
+
+    /// Receive a length prefixed msg, if one has been completely written by the
+    /// peer.
+    pub async fn recv(&self) -> std::io::Result<Bytes> {
+        if self.is_disconnected() {
+            return Err(std::io::ErrorKind::ConnectionReset.into());
+        }
+        let mut reader = self.reader.lock().await;
+        let (stream, buf) = &mut *reader;
+        // drain the stream without waiting. `read_buf` is cancel safe
+        while let Some(read) = stream.read_buf(buf).now_or_never() {
+            if read? == 0 {
+                if buf.is_empty() {
+                    return Err(std::io::ErrorKind::UnexpectedEof.into());
+                }
+                break;
+            }
+        }
+        match buf.get(..2) {
+            Some(&[len_0, len_1])
+                if buf.len() >= 2 + u16::from_be_bytes([len_0, len_1]) as usize =>
+            {
+                let len = u16::from_be_bytes([len_0, len_1]) as usize;
+                Ok(buf.split_to(2 + len).freeze())
+            }
+            _ => Err(std::io::ErrorKind::WouldBlock.into()),
+        }
+    }
+
+    pub async fn send(&self, data: &[u8]) -> std::io::Result<usize> {
+        let faults = match &self.chaos {
+            Some(chaos) => chaos.lock().unwrap().next_faults(),
+            None => Faults::default(),
+        };
+        let mut writer = self.writer.lock().await;
+        if faults.disconnect {
+            let _ = writer.shutdown().await;
+            return Err(std::io::ErrorKind::ConnectionReset.into());
+        }
+        tokio::time::sleep(faults.delay).await;
+        if !faults.drop {
+            writer.write_all(data).await?;
+        }
+        if faults.duplicate {
+            writer.write_all(data).await?;
+        }
+        Ok(data.len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn chaos_faults() {
+        let chaos = Chaos {
+            seed: 7,
+            drop_pct: 50,
+            duplicate_pct: 100,
+            max_delay: Duration::from_millis(10),
+            disconnect_after: Some(20),
+        };
+        let mut state = ChaosState::new(chaos);
+        let faults: Vec<Faults> = (0..30).map(|_| state.next_faults()).collect();
+        assert!(faults[..20].iter().all(|faults| faults.duplicate));
+        assert!(faults[..20].iter().any(|faults| faults.drop));
+        assert!(faults[..20].iter().any(|faults| !faults.drop));
+        assert!(faults[..20]
+            .iter()
+            .all(|faults| faults.delay <= chaos.max_delay));
+        // the stream stays disconnected
+        assert!(faults[20..].iter().all(|faults| faults.disconnect));
+
+        // replayed from the seed
+        let mut replay = ChaosState::new(chaos);
+        assert_eq!(replay.next_faults(), faults[0]);
+    }
+}
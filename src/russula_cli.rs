@@ -1,32 +1,43 @@
 // Copyright Amazon.com, Inc. or its affiliates. All Rights Reserved.
 // SPDX-License-Identifier: Apache-2.0
 
-use crate::{duration::parse_duration, russula::netbench};
 use core::time::Duration;
-use error::OrchResult;
-use russula::{
-    netbench::{client, server},
-    RussulaBuilder,
+use netbench_orch::{
+    duration::parse_duration,
+    error::OrchResult,
+    russula::{
+        command,
+        netbench::{self, client, router, server},
+        Protocol, RussulaBuilder, Transport,
+    },
+};
+use std::{
+    collections::BTreeSet,
+    net::{Ipv4Addr, SocketAddr},
 };
-use std::{collections::BTreeSet, net::SocketAddr};
 use structopt::StructOpt;
-use tracing::debug;
+use tracing::{debug, info};
 use tracing_subscriber::EnvFilter;
 
-mod duration;
-mod error;
-mod russula;
-
 /// This utility is a convenient CLI wrapper around Russula and can be used to launch
 /// different protocols.
 ///
-/// It currently supports launching server/client Netbench protocols.
+/// It currently supports launching server/client/router Netbench protocols, and a
+/// generic protocol which runs an arbitrary command (ex: iperf3) on the Workers.
 
 #[derive(StructOpt, Debug)]
 struct Opt {
     #[structopt(long, parse(try_from_str=parse_duration), default_value = "5s")]
     poll_delay: Duration,
 
+    // The transport used to communicate with the peer: tcp or udp
+    #[structopt(long, default_value = "tcp")]
+    transport: Transport,
+
+    // Log filter directives (ex: info,russula=debug). Defaults to RUST_LOG
+    #[structopt(long)]
+    log_filter: Option<String>,
+
     #[structopt(subcommand)]
     protocol: RussulaProtocol,
 }
@@ -35,29 +46,158 @@ struct Opt {
 #[derive(StructOpt, Debug)]
 enum RussulaProtocol {
     NetbenchServerWorker {
-        // The port on which the Worker should 'listen' on.
+        #[structopt(flatten)]
+        listen: WorkerAddr,
+
+        // Return to an idle state after each run and accept the next Coordinator.
         #[structopt(long)]
-        russula_port: u16,
+        daemon: bool,
 
         #[structopt(flatten)]
         ctx: netbench::ServerContext,
     },
     NetbenchClientWorker {
-        // The port on which the Worker should 'listen' on.
+        #[structopt(flatten)]
+        listen: WorkerAddr,
+
+        // Return to an idle state after each run and accept the next Coordinator.
         #[structopt(long)]
-        russula_port: u16,
+        daemon: bool,
 
         #[structopt(flatten)]
         ctx: netbench::ClientContext,
     },
+    NetbenchRouterWorker {
+        #[structopt(flatten)]
+        listen: WorkerAddr,
+
+        // Return to an idle state after each run and accept the next Coordinator.
+        #[structopt(long)]
+        daemon: bool,
+
+        #[structopt(flatten)]
+        ctx: netbench::RouterContext,
+    },
+    /// Drive already-provisioned server Workers. ex: --workers 10.0.0.1:9000,10.0.0.2:9000
     NetbenchServerCoordinator {
-        #[structopt(long, required = true)]
-        russula_worker_addrs: Vec<SocketAddr>,
+        #[structopt(flatten)]
+        workers: Workers,
+
+        // Run parameters shipped to the Workers
+        #[structopt(flatten)]
+        params: netbench::RunParams,
     },
+    /// Drive already-provisioned client Workers. ex: --workers 10.0.0.3:9000
     NetbenchClientCoordinator {
+        #[structopt(flatten)]
+        workers: Workers,
+
+        // Run parameters shipped to the Workers
+        #[structopt(flatten)]
+        params: netbench::RunParams,
+    },
+    /// Drive already-provisioned router Workers. ex: --workers 10.0.0.4:9000
+    NetbenchRouterCoordinator {
+        #[structopt(flatten)]
+        workers: Workers,
+    },
+    CommandWorker {
+        #[structopt(flatten)]
+        listen: WorkerAddr,
+
+        // Return to an idle state after each run and accept the next Coordinator.
         #[structopt(long)]
-        russula_worker_addrs: Vec<SocketAddr>,
+        daemon: bool,
+
+        #[structopt(flatten)]
+        ctx: command::CommandContext,
     },
+    /// Run a command on already-provisioned Workers.
+    /// ex: --workers 10.0.0.3:9000 --command 'iperf3 -c {peer0} -J' --peers 10.0.0.1:5201
+    CommandCoordinator {
+        #[structopt(flatten)]
+        workers: Workers,
+
+        // The command shipped to the Workers
+        #[structopt(flatten)]
+        params: command::CommandParams,
+    },
+}
+
+#[derive(StructOpt, Debug, Clone)]
+struct WorkerAddr {
+    // The address the Worker listens on. Workers on the same host need distinct
+    // ports. ex: 0.0.0.0:9001
+    #[structopt(
+        long,
+        required_unless = "russula-port",
+        conflicts_with = "russula-port"
+    )]
+    russula_addr: Option<SocketAddr>,
+
+    // Listen on all interfaces. Shorthand for --russula-addr 0.0.0.0:<port>
+    #[structopt(long)]
+    russula_port: Option<u16>,
+
+    // Dial the Coordinator at this address and register as --russula-addr,
+    // rather than listening on it. ex: 10.0.0.10:9000
+    #[structopt(long, requires = "russula-addr")]
+    register: Option<SocketAddr>,
+}
+
+impl WorkerAddr {
+    fn listen_addr(&self) -> SocketAddr {
+        match (self.russula_addr, self.russula_port) {
+            (Some(addr), _) => addr,
+            (None, Some(port)) => SocketAddr::from((Ipv4Addr::UNSPECIFIED, port)),
+            (None, None) => unreachable!("russula-addr or russula-port is required"),
+        }
+    }
+
+    fn worker<P: Protocol>(&self, opt: &Opt, protocol: P) -> RussulaBuilder<P> {
+        let worker = RussulaBuilder::new(
+            BTreeSet::from_iter([self.listen_addr()]),
+            protocol,
+            opt.poll_delay,
+        )
+        .transport(opt.transport);
+        match self.register {
+            Some(coordinator) => worker.register(coordinator),
+            None => worker,
+        }
+    }
+}
+
+#[derive(StructOpt, Debug, Clone)]
+struct Workers {
+    // Comma separated addresses of the Workers to coordinate.
+    #[structopt(
+        long = "workers",
+        alias = "russula-worker-addrs",
+        required = true,
+        use_delimiter = true
+    )]
+    addrs: Vec<SocketAddr>,
+
+    // Listen on this address for the Workers to register, rather than
+    // connecting to them. ex: 0.0.0.0:9000
+    #[structopt(long)]
+    accept_registrations: Option<SocketAddr>,
+}
+
+impl Workers {
+    fn coordinator<P: Protocol>(&self, opt: &Opt, protocol: P) -> RussulaBuilder<P> {
+        let coord = RussulaBuilder::new(
+            BTreeSet::from_iter(self.addrs.iter().copied()),
+            protocol,
+            opt.poll_delay,
+        )
+        .transport(opt.transport);
+        match self.accept_registrations {
+            Some(listen) => coord.accept_registrations(listen),
+            None => coord,
+        }
+    }
 }
 
 #[tokio::main(flavor = "current_thread")]
@@ -66,78 +206,139 @@ async fn main() -> OrchResult<()> {
 
     let file_appender = tracing_appender::rolling::daily("./target", "russula.log");
     let (non_blocking, _guard) = tracing_appender::non_blocking(file_appender);
+    let env_filter = match &opt.log_filter {
+        Some(log_filter) => EnvFilter::try_new(log_filter).expect("invalid log filter"),
+        None => EnvFilter::from_default_env(),
+    };
     tracing_subscriber::fmt()
-        .with_env_filter(EnvFilter::from_default_env())
+        .with_env_filter(env_filter)
         .with_writer(non_blocking)
         .init();
 
     debug!("{:?}", opt);
-    println!("{:?}", opt);
     match &opt.protocol {
-        RussulaProtocol::NetbenchServerWorker { ctx, russula_port } => {
+        RussulaProtocol::NetbenchServerWorker {
+            ctx,
+            listen,
+            daemon,
+        } => {
             let netbench_ctx = ctx.clone();
-            let russula_port = *russula_port;
-            run_server_worker(opt, netbench_ctx, russula_port).await
+            let listen = listen.clone();
+            let daemon = *daemon;
+            run_server_worker(opt, netbench_ctx, listen, daemon).await
         }
-        RussulaProtocol::NetbenchClientWorker { ctx, russula_port } => {
+        RussulaProtocol::NetbenchClientWorker {
+            ctx,
+            listen,
+            daemon,
+        } => {
             let netbench_ctx = ctx.clone();
-            let russula_port = *russula_port;
-            run_client_worker(opt, netbench_ctx, russula_port).await
+            let listen = listen.clone();
+            let daemon = *daemon;
+            run_client_worker(opt, netbench_ctx, listen, daemon).await
         }
-        RussulaProtocol::NetbenchServerCoordinator {
-            russula_worker_addrs,
+        RussulaProtocol::NetbenchRouterWorker {
+            ctx,
+            listen,
+            daemon,
         } => {
-            let w = russula_worker_addrs.clone();
-            run_local_server_coordinator(opt, w).await
+            let router_ctx = ctx.clone();
+            let listen = listen.clone();
+            let daemon = *daemon;
+            run_router_worker(opt, router_ctx, listen, daemon).await
         }
-        RussulaProtocol::NetbenchClientCoordinator {
-            russula_worker_addrs,
+        RussulaProtocol::NetbenchServerCoordinator { workers, params } => {
+            let w = workers.clone();
+            let params = params.clone();
+            run_server_coordinator(opt, w, params).await
+        }
+        RussulaProtocol::NetbenchClientCoordinator { workers, params } => {
+            let w = workers.clone();
+            let params = params.clone();
+            run_client_coordinator(opt, w, params).await
+        }
+        RussulaProtocol::NetbenchRouterCoordinator { workers } => {
+            let w = workers.clone();
+            run_router_coordinator(opt, w).await
+        }
+        RussulaProtocol::CommandWorker {
+            ctx,
+            listen,
+            daemon,
         } => {
-            let w = russula_worker_addrs.clone();
-            run_local_client_coordinator(opt, w).await
+            let command_ctx = ctx.clone();
+            let listen = listen.clone();
+            let daemon = *daemon;
+            run_command_worker(opt, command_ctx, listen, daemon).await
+        }
+        RussulaProtocol::CommandCoordinator { workers, params } => {
+            let w = workers.clone();
+            let params = params.clone();
+            run_command_coordinator(opt, w, params).await
         }
     };
 
-    println!("cli done");
+    info!("cli done");
     Ok(())
 }
 
-async fn run_server_worker(opt: Opt, netbench_ctx: netbench::ServerContext, russula_port: u16) {
+async fn run_server_worker(
+    opt: Opt,
+    netbench_ctx: netbench::ServerContext,
+    listen: WorkerAddr,
+    daemon: bool,
+) {
     let uuid = uuid::Uuid::new_v4().to_string();
     let protocol = server::WorkerProtocol::new(uuid, netbench_ctx);
-    let worker = RussulaBuilder::new(
-        BTreeSet::from_iter([local_listen_addr(russula_port)]),
-        protocol,
-        opt.poll_delay,
-    );
+    let worker = listen.worker(&opt, protocol);
+    if daemon {
+        return worker.run_daemon().await;
+    }
     let mut worker = worker.build().await.unwrap();
     worker.run_till_ready().await.unwrap();
 
     worker.run_till_done().await.unwrap();
 }
 
-async fn run_client_worker(opt: Opt, netbench_ctx: netbench::ClientContext, russula_port: u16) {
+async fn run_client_worker(
+    opt: Opt,
+    netbench_ctx: netbench::ClientContext,
+    listen: WorkerAddr,
+    daemon: bool,
+) {
     let uuid = uuid::Uuid::new_v4().to_string();
     let protocol = client::WorkerProtocol::new(uuid, netbench_ctx);
-    let worker = RussulaBuilder::new(
-        BTreeSet::from_iter([local_listen_addr(russula_port)]),
-        protocol,
-        opt.poll_delay,
-    );
+    let worker = listen.worker(&opt, protocol);
+    if daemon {
+        return worker.run_daemon().await;
+    }
+    let mut worker = worker.build().await.unwrap();
+    worker.run_till_ready().await.unwrap();
+
+    worker.run_till_done().await.unwrap();
+}
+
+async fn run_router_worker(
+    opt: Opt,
+    router_ctx: netbench::RouterContext,
+    listen: WorkerAddr,
+    daemon: bool,
+) {
+    let uuid = uuid::Uuid::new_v4().to_string();
+    let protocol = router::WorkerProtocol::new(uuid, router_ctx);
+    let worker = listen.worker(&opt, protocol);
+    if daemon {
+        return worker.run_daemon().await;
+    }
     let mut worker = worker.build().await.unwrap();
     worker.run_till_ready().await.unwrap();
 
     worker.run_till_done().await.unwrap();
 }
 
-async fn run_local_server_coordinator(opt: Opt, russula_worker_addrs: Vec<SocketAddr>) {
-    let protocol = server::CoordProtocol::new();
-    let coord = RussulaBuilder::new(
-        // TODO for local testing.. we only connect to 1 local worker
-        BTreeSet::from_iter(russula_worker_addrs),
-        protocol,
-        opt.poll_delay,
-    );
+async fn run_server_coordinator(opt: Opt, workers: Workers, params: netbench::RunParams) {
+    let protocol = server::CoordProtocol::new().with_params(params);
+    let coord = workers.coordinator(&opt, protocol);
     let mut coord = coord.build().await.unwrap();
 
     coord.run_till_worker_running().await.unwrap();
@@ -150,14 +351,9 @@ async fn run_local_server_coordinator(opt: Opt, russula_worker_addrs: Vec<Socket
     coord.run_till_done().await.unwrap();
 }
 
-async fn run_local_client_coordinator(opt: Opt, russula_worker_addrs: Vec<SocketAddr>) {
-    let protocol = client::CoordProtocol::new();
-    let coord = RussulaBuilder::new(
-        // TODO for local testing.. we only connect to 1 local worker
-        BTreeSet::from_iter(russula_worker_addrs),
-        protocol,
-        opt.poll_delay,
-    );
+async fn run_client_coordinator(opt: Opt, workers: Workers, params: netbench::RunParams) {
+    let protocol = client::CoordProtocol::new().with_params(params);
+    let coord = workers.coordinator(&opt, protocol);
     let mut coord = coord.build().await.unwrap();
 
     coord.run_till_worker_running().await.unwrap();
@@ -165,6 +361,101 @@ async fn run_local_client_coordinator(opt: Opt, russula_worker_addrs: Vec<Socket
     coord.run_till_done().await.unwrap();
 }
 
-fn local_listen_addr(russula_port: u16) -> SocketAddr {
-    format!("0.0.0.0:{}", russula_port).parse().unwrap()
+async fn run_router_coordinator(opt: Opt, workers: Workers) {
+    let protocol = router::CoordProtocol::new();
+    let coord = workers.coordinator(&opt, protocol);
+    let mut coord = coord.build().await.unwrap();
+
+    coord.run_till_worker_running().await.unwrap();
+
+    println!("Waiting for user input to continue ... WorkersRouting");
+    let mut s = String::new();
+    let _ = std::io::stdin().read_line(&mut s);
+    println!("Tearing down routing ...");
+
+    coord.run_till_done().await.unwrap();
+}
+
+async fn run_command_worker(
+    opt: Opt,
+    command_ctx: command::CommandContext,
+    listen: WorkerAddr,
+    daemon: bool,
+) {
+    let uuid = uuid::Uuid::new_v4().to_string();
+    let protocol = command::WorkerProtocol::new(uuid, command_ctx);
+    let worker = listen.worker(&opt, protocol);
+    if daemon {
+        return worker.run_daemon().await;
+    }
+    let mut worker = worker.build().await.unwrap();
+    worker.run_till_ready().await.unwrap();
+
+    worker.run_till_done().await.unwrap();
+}
+
+async fn run_command_coordinator(opt: Opt, workers: Workers, params: command::CommandParams) {
+    let protocol = command::CoordProtocol::new().with_params(params);
+    let coord = workers.coordinator(&opt, protocol);
+    let mut coord = coord.build().await.unwrap();
+
+    coord.run_till_worker_running().await.unwrap();
+
+    coord.run_till_done().await.unwrap();
+    for (worker, exit) in coord.worker_exits() {
+        println!("{worker}: command {exit}");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_coordinator_workers() {
+        let opt = Opt::from_iter([
+            "russula_cli",
+            "netbench-server-coordinator",
+            "--workers",
+            "10.0.0.1:9000,10.0.0.2:9000",
+            "--scenario",
+            "incast.json",
+        ]);
+        let RussulaProtocol::NetbenchServerCoordinator { workers, params } = opt.protocol else {
+            panic!("expected server coordinator");
+        };
+        assert_eq!(
+            workers.addrs,
+            vec![
+                "10.0.0.1:9000".parse::<SocketAddr>().unwrap(),
+                "10.0.0.2:9000".parse().unwrap()
+            ]
+        );
+        assert_eq!(params.scenario.as_deref(), Some("incast.json"));
+        assert_eq!(params.driver, None);
+    }
+
+    #[test]
+    fn parse_worker_listen_addr() {
+        let listen_addr = |args: &[&str]| {
+            let opt = Opt::from_iter(
+                ["russula_cli", "netbench-router-worker"]
+                    .iter()
+                    .chain(args.iter()),
+            );
+            let RussulaProtocol::NetbenchRouterWorker { listen, .. } = opt.protocol else {
+                panic!("expected router worker");
+            };
+            listen.listen_addr()
+        };
+        assert_eq!(
+            listen_addr(&["--russula-addr", "127.0.0.1:9002"]),
+            "127.0.0.1:9002".parse().unwrap()
+        );
+        assert_eq!(
+            listen_addr(&["--russula-port", "9002"]),
+            "0.0.0.0:9002".parse().unwrap()
+        );
+        assert!(Opt::from_iter_safe(["russula_cli", "netbench-router-worker"]).is_err());
+    }
 }
@@ -1,6 +1,7 @@
 // Copyright Amazon.com, Inc. or its affiliates. All Rights Reserved.
 // SPDX-License-Identifier: Apache-2.0
 
+use crate::error::{OrchError, OrchResult};
 use aws_sdk_s3 as s3;
 use aws_sdk_s3::{
     error::SdkError,
@@ -8,23 +9,46 @@ use aws_sdk_s3::{
         get_object::{GetObjectError, GetObjectOutput},
         put_object::{PutObjectError, PutObjectOutput},
     },
+    presigning::PresigningConfig,
+    primitives::ByteStream,
+    types::{CompletedMultipartUpload, CompletedPart},
 };
-use std::{fs::File, io::prelude::*, path::Path};
+use core::time::Duration;
+use futures::stream::{self, StreamExt as _};
+use md5::{Digest, Md5};
+use std::{collections::BTreeMap, fs::File, io::prelude::*, path::Path};
 use tokio_stream::StreamExt;
+use tracing::{debug, warn};
+
+pub mod baseline;
+pub mod delta;
+pub mod fetch;
+pub mod index;
+pub mod prune;
 
 pub async fn download_object_to_file<P: AsRef<Path>>(
     client: &s3::Client,
     bucket_name: &str,
     key: &str,
     path: P,
-) -> Result<usize, SdkError<GetObjectError>> {
-    let mut file = File::create(path).unwrap();
+) -> OrchResult<usize> {
+    let mut file = File::create(path).map_err(|err| OrchError::S3 {
+        dbg: format!("Failed to create file for {}: {}", key, err),
+    })?;
 
-    let mut obj = download_object(client, bucket_name, key).await.unwrap();
+    let mut obj = download_object(client, bucket_name, key)
+        .await
+        .map_err(|err| OrchError::S3 {
+            dbg: format!("Failed to download {}: {}", key, err),
+        })?;
 
     let mut total_size = 0;
-    while let Some(bytes) = obj.body.try_next().await.unwrap() {
-        total_size += file.write(&bytes).unwrap();
+    while let Some(bytes) = obj.body.try_next().await.map_err(|err| OrchError::S3 {
+        dbg: format!("Failed to download {}: {}", key, err),
+    })? {
+        total_size += file.write(&bytes).map_err(|err| OrchError::S3 {
+            dbg: format!("Failed to write {}: {}", key, err),
+        })?;
     }
 
     Ok(total_size)
@@ -58,3 +82,559 @@ pub async fn upload_object(
         .send()
         .await
 }
+
+pub async fn delete_object(client: &s3::Client, bucket_name: &str, key: &str) -> OrchResult<()> {
+    client
+        .delete_object()
+        .bucket(bucket_name)
+        .key(key)
+        .send()
+        .await
+        .map_err(|err| OrchError::S3 {
+            dbg: format!("Failed to delete {}: {}", key, err),
+        })?;
+    Ok(())
+}
+
+// Objects larger than this are uploaded/downloaded in multiple parts.
+const MULTIPART_THRESHOLD: u64 = 16 * 1024 * 1024;
+// S3 requires that all parts except the last are at least 5MiB.
+const MULTIPART_CHUNK_SIZE: u64 = 8 * 1024 * 1024;
+
+/// Upload a local file, using a multipart upload for large files.
+pub async fn upload_file(
+    client: &s3::Client,
+    bucket_name: &str,
+    path: &Path,
+    key: &str,
+) -> OrchResult<()> {
+    let len = std::fs::metadata(path)
+        .map_err(|err| OrchError::S3 {
+            dbg: format!("Failed to read {:?}: {}", path, err),
+        })?
+        .len();
+
+    if len < MULTIPART_THRESHOLD {
+        let body = ByteStream::from_path(path)
+            .await
+            .map_err(|err| OrchError::S3 {
+                dbg: format!("Failed to read {:?}: {}", path, err),
+            })?;
+        client
+            .put_object()
+            .bucket(bucket_name)
+            .key(key)
+            .content_type(content_type(path))
+            .body(body)
+            .send()
+            .await
+            .map_err(|err| OrchError::S3 {
+                dbg: format!("Failed to upload {}: {}", key, err),
+            })?;
+        return Ok(());
+    }
+
+    let upload_id = client
+        .create_multipart_upload()
+        .bucket(bucket_name)
+        .key(key)
+        .content_type(content_type(path))
+        .send()
+        .await
+        .map_err(|err| OrchError::S3 {
+            dbg: format!("Failed to create multipart upload {}: {}", key, err),
+        })?
+        .upload_id()
+        .ok_or(OrchError::S3 {
+            dbg: format!("Missing multipart upload id for {}", key),
+        })?
+        .to_string();
+
+    // the parts of an upload which isn't completed are billed until aborted
+    let uploaded = async {
+        let mut file = File::open(path).map_err(|err| OrchError::S3 {
+            dbg: format!("Failed to open {:?}: {}", path, err),
+        })?;
+        let mut parts = Vec::new();
+        let mut part_number = 1;
+        loop {
+            let mut chunk = Vec::with_capacity(MULTIPART_CHUNK_SIZE as usize);
+            let read = (&mut file)
+                .take(MULTIPART_CHUNK_SIZE)
+                .read_to_end(&mut chunk)
+                .map_err(|err| OrchError::S3 {
+                    dbg: format!("Failed to read {:?}: {}", path, err),
+                })?;
+            if read == 0 {
+                break;
+            }
+
+            let part = client
+                .upload_part()
+                .bucket(bucket_name)
+                .key(key)
+                .upload_id(&upload_id)
+                .part_number(part_number)
+                .body(ByteStream::from(chunk))
+                .send()
+                .await
+                .map_err(|err| OrchError::S3 {
+                    dbg: format!("Failed to upload part {} of {}: {}", part_number, key, err),
+                })?;
+            parts.push(
+                CompletedPart::builder()
+                    .set_e_tag(part.e_tag().map(String::from))
+                    .part_number(part_number)
+                    .build(),
+            );
+            part_number += 1;
+        }
+
+        client
+            .complete_multipart_upload()
+            .bucket(bucket_name)
+            .key(key)
+            .upload_id(&upload_id)
+            .multipart_upload(
+                CompletedMultipartUpload::builder()
+                    .set_parts(Some(parts))
+                    .build(),
+            )
+            .send()
+            .await
+            .map_err(|err| OrchError::S3 {
+                dbg: format!("Failed to complete multipart upload {}: {}", key, err),
+            })?;
+        Ok::<(), OrchError>(())
+    }
+    .await;
+    if let Err(err) = uploaded {
+        if let Err(abort_err) = client
+            .abort_multipart_upload()
+            .bucket(bucket_name)
+            .key(key)
+            .upload_id(&upload_id)
+            .send()
+            .await
+        {
+            warn!("Failed to abort multipart upload {}: {}", key, abort_err);
+        }
+        return Err(err);
+    }
+    Ok(())
+}
+
+/// Download an object to a local file, using ranged requests for large objects.
+pub async fn download_file(
+    client: &s3::Client,
+    bucket_name: &str,
+    key: &str,
+    len: u64,
+    path: &Path,
+) -> OrchResult<()> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent).map_err(|err| OrchError::S3 {
+            dbg: format!("Failed to create {:?}: {}", parent, err),
+        })?;
+    }
+    let mut file = File::create(path).map_err(|err| OrchError::S3 {
+        dbg: format!("Failed to create {:?}: {}", path, err),
+    })?;
+
+    let mut offset = 0;
+    loop {
+        let start = offset;
+        let mut request = client.get_object().bucket(bucket_name).key(key);
+        if len >= MULTIPART_THRESHOLD {
+            let end = (offset + MULTIPART_CHUNK_SIZE).min(len) - 1;
+            request = request.range(format!("bytes={}-{}", offset, end));
+        }
+        let mut obj = request.send().await.map_err(|err| OrchError::S3 {
+            dbg: format!("Failed to download {}: {}", key, err),
+        })?;
+        while let Some(bytes) = obj.body.try_next().await.map_err(|err| OrchError::S3 {
+            dbg: format!("Failed to download {}: {}", key, err),
+        })? {
+            file.write_all(&bytes).map_err(|err| OrchError::S3 {
+                dbg: format!("Failed to write {:?}: {}", path, err),
+            })?;
+            offset += bytes.len() as u64;
+        }
+
+        if len < MULTIPART_THRESHOLD || offset >= len {
+            break;
+        }
+        // ex: the object was replaced by a smaller one since it was listed
+        if offset == start {
+            return Err(OrchError::S3 {
+                dbg: format!(
+                    "Failed to download {}: no bytes after {} of {}",
+                    key, offset, len
+                ),
+            });
+        }
+    }
+    Ok(())
+}
+
+/// List all objects, and their size, under a prefix.
+pub async fn list_objects(
+    client: &s3::Client,
+    bucket_name: &str,
+    prefix: &str,
+) -> OrchResult<BTreeMap<String, u64>> {
+    Ok(list_object_etags(client, bucket_name, prefix)
+        .await?
+        .into_iter()
+        .map(|(key, (len, _etag))| (key, len))
+        .collect())
+}
+
+/// List all objects, and their size and ETag, under a prefix.
+async fn list_object_etags(
+    client: &s3::Client,
+    bucket_name: &str,
+    prefix: &str,
+) -> OrchResult<BTreeMap<String, (u64, Option<String>)>> {
+    let mut objects = BTreeMap::new();
+    let mut continuation_token = None;
+    loop {
+        let output = client
+            .list_objects_v2()
+            .bucket(bucket_name)
+            .prefix(prefix)
+            .set_continuation_token(continuation_token)
+            .send()
+            .await
+            .map_err(|err| OrchError::S3 {
+                dbg: format!("Failed to list {}/{}: {}", bucket_name, prefix, err),
+            })?;
+
+        for object in output.contents().unwrap_or_default() {
+            if let Some(key) = object.key() {
+                let etag = object
+                    .e_tag()
+                    .map(|etag| etag.trim_matches('"').to_string());
+                objects.insert(key.to_string(), (object.size() as u64, etag));
+            }
+        }
+
+        continuation_token = output.next_continuation_token().map(String::from);
+        if continuation_token.is_none() {
+            break;
+        }
+    }
+    Ok(objects)
+}
+
+/// Upload the contents of a local directory to a prefix.
+///
+/// Similar to `aws s3 sync`, files which already exist with the same size are
+/// skipped. `exclude` is a list of path prefixes, relative to `local_dir`, to skip.
+pub async fn sync_to_s3(
+    client: &s3::Client,
+    local_dir: &Path,
+    bucket_name: &str,
+    prefix: &str,
+    exclude: &[&str],
+) -> OrchResult<usize> {
+    let prefix = prefix.trim_end_matches('/');
+    let existing = list_objects(client, bucket_name, prefix).await?;
+
+    let mut uploaded = 0;
+    for (relative_path, len) in local_files(local_dir)? {
+        if exclude
+            .iter()
+            .any(|exclude| relative_path.starts_with(exclude))
+        {
+            continue;
+        }
+
+        let key = format!("{}/{}", prefix, relative_path);
+        if existing.get(&key) == Some(&len) {
+            continue;
+        }
+
+        debug!("upload: {} -> {}", relative_path, key);
+        upload_file(client, bucket_name, &local_dir.join(&relative_path), &key).await?;
+        uploaded += 1;
+    }
+    Ok(uploaded)
+}
+
+/// How [`sync_from_s3`] downloads objects.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DownloadOptions {
+    // The number of objects downloaded concurrently
+    pub parallelism: usize,
+    // The number of times a failed or corrupted download is retried
+    pub retries: u32,
+}
+
+impl Default for DownloadOptions {
+    fn default() -> Self {
+        DownloadOptions {
+            parallelism: 16,
+            retries: 3,
+        }
+    }
+}
+
+/// Download all objects under a prefix to a local directory.
+///
+/// Similar to `aws s3 sync`, files which already exist with the same size are skipped.
+/// Up to `options.parallelism` objects are downloaded concurrently, and each download
+/// is verified against the object's size and ETag. `exclude` is a list of path
+/// prefixes, relative to `prefix`, to skip.
+pub async fn sync_from_s3(
+    client: &s3::Client,
+    bucket_name: &str,
+    prefix: &str,
+    local_dir: &Path,
+    options: DownloadOptions,
+    exclude: &[&str],
+) -> OrchResult<usize> {
+    // list the folder rather than every key starting with its name. ex: a matrix
+    // group's `<unique_id>-<group>` isn't part of `<unique_id>`
+    let prefix = format!("{}/", prefix.trim_end_matches('/'));
+    let mut downloads = Vec::new();
+    for (key, (len, etag)) in list_object_etags(client, bucket_name, &prefix).await? {
+        let relative_path = key
+            .trim_start_matches(&prefix)
+            .trim_start_matches('/')
+            .to_string();
+        if relative_path.is_empty()
+            || exclude
+                .iter()
+                .any(|exclude| relative_path.starts_with(exclude))
+        {
+            continue;
+        }
+
+        let path = local_dir.join(&relative_path);
+        if std::fs::metadata(&path).is_ok_and(|metadata| metadata.len() == len) {
+            continue;
+        }
+        downloads.push((key, len, etag, path));
+    }
+
+    let downloaded = downloads.len();
+    let mut results = stream::iter(downloads.into_iter().map(
+        |(key, len, etag, path)| async move {
+            debug!("download: {} -> {:?}", key, path);
+            download_verified(
+                client,
+                bucket_name,
+                &key,
+                len,
+                etag.as_deref(),
+                &path,
+                options.retries,
+            )
+            .await
+        },
+    ))
+    .buffer_unordered(options.parallelism.max(1));
+    while let Some(result) = futures::StreamExt::next(&mut results).await {
+        result?;
+    }
+    Ok(downloaded)
+}
+
+// Download an object, retrying with a backoff if the download fails or doesn't
+// match the object's size and ETag.
+async fn download_verified(
+    client: &s3::Client,
+    bucket_name: &str,
+    key: &str,
+    len: u64,
+    etag: Option<&str>,
+    path: &Path,
+    retries: u32,
+) -> OrchResult<()> {
+    let mut attempt = 0;
+    loop {
+        let result = match download_file(client, bucket_name, key, len, path).await {
+            Ok(()) => verify_download(key, len, etag, path),
+            Err(err) => Err(err),
+        };
+        match result {
+            Err(err) if attempt < retries => {
+                attempt += 1;
+                warn!("Retrying download {}/{}: {}", attempt, retries, err);
+                tokio::time::sleep(Duration::from_millis(500 << attempt)).await;
+            }
+            result => return result,
+        }
+    }
+}
+
+fn verify_download(key: &str, len: u64, etag: Option<&str>, path: &Path) -> OrchResult<()> {
+    let file = File::open(path).map_err(|err| OrchError::S3 {
+        dbg: format!("Failed to open {:?}: {}", path, err),
+    })?;
+    let downloaded = file
+        .metadata()
+        .map_err(|err| OrchError::S3 {
+            dbg: format!("Failed to read {:?}: {}", path, err),
+        })?
+        .len();
+    if downloaded != len {
+        return Err(OrchError::S3 {
+            dbg: format!(
+                "Downloaded {} bytes of {} but expected {}",
+                downloaded, key, len
+            ),
+        });
+    }
+    if let Some(etag) = etag {
+        let matches = etag_matches(file, len, etag).map_err(|err| OrchError::S3 {
+            dbg: format!("Failed to read {:?}: {}", path, err),
+        })?;
+        if matches == Some(false) {
+            return Err(OrchError::S3 {
+                dbg: format!("Checksum mismatch for {}: expected ETag {}", key, etag),
+            });
+        }
+    }
+    Ok(())
+}
+
+/// Whether the `len` bytes of `contents` match an S3 ETag, or `None` if the
+/// ETag can't be verified.
+///
+/// The ETag of a single part upload is the md5 of the object. The ETag of a
+/// multipart upload is the md5 of the concatenated part md5s, followed by
+/// `-<parts>`. Multipart ETags are verified assuming the 8MiB parts of
+/// [`upload_file`] and the aws cli. ETags of SSE-KMS encrypted objects aren't md5s
+/// and don't match either form.
+///
+/// The contents are hashed as they're read so that large objects aren't
+/// buffered in memory.
+fn etag_matches(mut contents: impl Read, len: u64, etag: &str) -> std::io::Result<Option<bool>> {
+    let (etag, multipart) = match etag.split_once('-') {
+        None if etag.len() == 32 => (etag, false),
+        None => return Ok(None),
+        Some((etag, parts)) => match parts.parse::<u64>() {
+            Ok(parts) if parts == len.div_ceil(MULTIPART_CHUNK_SIZE) => (etag, true),
+            _ => return Ok(None),
+        },
+    };
+
+    let mut object = Md5::new();
+    let mut part = Md5::new();
+    let mut part_len = 0;
+    let mut md5s = Vec::new();
+    let mut buf = vec![0; 64 * 1024];
+    loop {
+        // a read doesn't span two parts
+        let max = buf.len().min((MULTIPART_CHUNK_SIZE - part_len) as usize);
+        let read = contents.read(&mut buf[..max])?;
+        if read == 0 {
+            break;
+        }
+        object.update(&buf[..read]);
+        part.update(&buf[..read]);
+        part_len += read as u64;
+        if part_len == MULTIPART_CHUNK_SIZE {
+            md5s.extend_from_slice(&part.finalize_reset());
+            part_len = 0;
+        }
+    }
+    if part_len > 0 {
+        md5s.extend_from_slice(&part.finalize());
+    }
+
+    let digest = match multipart {
+        true => Md5::digest(&md5s),
+        false => object.finalize(),
+    };
+    Ok(Some(format!("{:x}", digest) == etag))
+}
+
+// Recursively list the files in a directory as (relative path, size) pairs.
+fn local_files(local_dir: &Path) -> OrchResult<Vec<(String, u64)>> {
+    let mut files = Vec::new();
+    let mut dirs = vec![local_dir.to_path_buf()];
+    while let Some(dir) = dirs.pop() {
+        let entries = std::fs::read_dir(&dir).map_err(|err| OrchError::S3 {
+            dbg: format!("Failed to read {:?}: {}", dir, err),
+        })?;
+        for entry in entries.filter_map(|entry| entry.ok()) {
+            let path = entry.path();
+            let metadata = entry.metadata().map_err(|err| OrchError::S3 {
+                dbg: format!("Failed to read {:?}: {}", path, err),
+            })?;
+            if metadata.is_dir() {
+                dirs.push(path);
+            } else if let Ok(relative_path) = path.strip_prefix(local_dir) {
+                files.push((relative_path.to_string_lossy().to_string(), metadata.len()));
+            }
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+// Set the content type so that the report can be viewed via cloudfront.
+fn content_type(path: &Path) -> &'static str {
+    match path.extension().and_then(|ext| ext.to_str()) {
+        Some("html") => "text/html",
+        Some("json") => "application/json",
+        Some("js") => "application/javascript",
+        Some("css") => "text/css",
+        Some("svg") => "image/svg+xml",
+        _ => "application/octet-stream",
+    }
+}
+
+/// Generate a presigned GET url which grants access to an object without
+/// requiring bucket access.
+///
+/// Presigned urls can be valid for at most 7 days.
+pub async fn presign_object(
+    client: &s3::Client,
+    bucket_name: &str,
+    key: &str,
+    expires_in: Duration,
+) -> OrchResult<String> {
+    let config = PresigningConfig::expires_in(expires_in).map_err(|err| OrchError::S3 {
+        dbg: format!("Invalid presign expiry: {}", err),
+    })?;
+    let request = client
+        .get_object()
+        .bucket(bucket_name)
+        .key(key)
+        .presigned(config)
+        .await
+        .map_err(|err| OrchError::S3 {
+            dbg: format!("Failed to presign {}: {}", key, err),
+        })?;
+    Ok(request.uri().to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn etags() {
+        let etag_matches = |contents: &[u8], etag: &str| {
+            etag_matches(contents, contents.len() as u64, etag).unwrap()
+        };
+        let etag = format!("{:x}", Md5::digest(b"netbench"));
+        assert_eq!(etag_matches(b"netbench", &etag), Some(true));
+        assert_eq!(etag_matches(b"netbench2", &etag), Some(false));
+
+        let contents = vec![7u8; MULTIPART_CHUNK_SIZE as usize + 10];
+        let mut md5s = Vec::new();
+        md5s.extend_from_slice(&Md5::digest(&contents[..MULTIPART_CHUNK_SIZE as usize]));
+        md5s.extend_from_slice(&Md5::digest(&contents[MULTIPART_CHUNK_SIZE as usize..]));
+        let etag = format!("{:x}-2", Md5::digest(&md5s));
+        assert_eq!(etag_matches(&contents, &etag), Some(true));
+        // uploaded with a different part size
+        let etag = format!("{:x}-3", Md5::digest(&md5s));
+        assert_eq!(etag_matches(&contents, &etag), None);
+        assert_eq!(etag_matches(b"netbench", "not-an-md5"), None);
+    }
+}
@@ -0,0 +1,175 @@
+// Copyright Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+use super::{delete_object, download_object, list_objects, prune::RunPrefix, upload_object};
+use crate::{
+    error::{OrchError, OrchResult},
+    STATE,
+};
+use aws_sdk_s3::primitives::ByteStream;
+use bytes::Bytes;
+use clap::Subcommand;
+use std::collections::BTreeMap;
+
+/// Runs which have been tagged as a baseline are stored as pointer objects under
+/// this prefix: `baselines/<name>` with the run's unique_id as the body.
+pub const BASELINE_PREFIX: &str = "baselines";
+
+#[derive(Subcommand, Debug)]
+pub enum BaselineCommand {
+    /// Tag a run as a named baseline, replacing the run previously tagged with
+    /// the name. ex: main-latest, v1.32.0
+    Tag {
+        name: String,
+        /// The unique_id of the run. ex: 2023-10-11T17:05:09Z-v2.0.0
+        unique_id: String,
+    },
+    /// Remove a baseline tag. The run itself is kept
+    Untag { name: String },
+    /// List the baselines and the runs they point to
+    List,
+}
+
+impl BaselineCommand {
+    pub async fn run(&self, aws_config: &aws_types::SdkConfig) -> OrchResult<()> {
+        let s3_client = aws_sdk_s3::Client::new(aws_config);
+        let bucket_name = STATE.s3_log_bucket;
+        match self {
+            BaselineCommand::Tag { name, unique_id } => {
+                tag_baseline(&s3_client, bucket_name, name, unique_id).await?;
+                println!("{name} -> {unique_id}");
+            }
+            BaselineCommand::Untag { name } => {
+                validate_name(name)?;
+                delete_object(
+                    &s3_client,
+                    bucket_name,
+                    &format!("{BASELINE_PREFIX}/{name}"),
+                )
+                .await?;
+                println!("Removed baseline {name}");
+            }
+            BaselineCommand::List => {
+                for (name, unique_id) in list_baselines(&s3_client, bucket_name).await? {
+                    println!("{name} -> {unique_id}");
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Point the baseline `name` at the run `unique_id`.
+pub async fn tag_baseline(
+    s3_client: &aws_sdk_s3::Client,
+    bucket_name: &str,
+    name: &str,
+    unique_id: &str,
+) -> OrchResult<()> {
+    validate_name(name)?;
+    if RunPrefix::parse(unique_id).is_none() {
+        return Err(OrchError::Init {
+            dbg: format!("Not a run unique_id: {}", unique_id),
+        });
+    }
+    if list_objects(s3_client, bucket_name, &format!("{unique_id}/"))
+        .await?
+        .is_empty()
+    {
+        return Err(OrchError::Init {
+            dbg: format!("Run {} not found in {}", unique_id, bucket_name),
+        });
+    }
+    upload_object(
+        s3_client,
+        bucket_name,
+        ByteStream::from(Bytes::from(unique_id.to_string())),
+        &format!("{BASELINE_PREFIX}/{name}"),
+    )
+    .await
+    .map_err(|err| OrchError::S3 {
+        dbg: format!("Failed to tag baseline {}: {}", name, err),
+    })?;
+    Ok(())
+}
+
+/// The unique_id of the run tagged as the baseline `name`, if any.
+pub async fn resolve_baseline(
+    s3_client: &aws_sdk_s3::Client,
+    bucket_name: &str,
+    name: &str,
+) -> OrchResult<Option<String>> {
+    let key = format!("{BASELINE_PREFIX}/{name}");
+    let Ok(object) = download_object(s3_client, bucket_name, &key).await else {
+        return Ok(None);
+    };
+    let unique_id = object
+        .body
+        .collect()
+        .await
+        .map_err(|err| OrchError::S3 {
+            dbg: format!("Failed to read baseline {}: {}", key, err),
+        })?
+        .into_bytes();
+    Ok(Some(String::from_utf8_lossy(&unique_id).trim().to_string()))
+}
+
+/// Resolve a baseline name or a run unique_id to a unique_id.
+pub async fn resolve_run(
+    s3_client: &aws_sdk_s3::Client,
+    bucket_name: &str,
+    name_or_unique_id: &str,
+) -> OrchResult<String> {
+    if RunPrefix::parse(name_or_unique_id).is_some() {
+        return Ok(name_or_unique_id.to_string());
+    }
+    resolve_baseline(s3_client, bucket_name, name_or_unique_id)
+        .await?
+        .ok_or(OrchError::Init {
+            dbg: format!("No baseline or run named {}", name_or_unique_id),
+        })
+}
+
+/// The baselines in the bucket: name -> unique_id.
+pub async fn list_baselines(
+    s3_client: &aws_sdk_s3::Client,
+    bucket_name: &str,
+) -> OrchResult<BTreeMap<String, String>> {
+    let mut baselines = BTreeMap::new();
+    for key in list_objects(s3_client, bucket_name, &format!("{BASELINE_PREFIX}/"))
+        .await?
+        .keys()
+    {
+        let name = key
+            .trim_start_matches(BASELINE_PREFIX)
+            .trim_start_matches('/');
+        if let Some(unique_id) = resolve_baseline(s3_client, bucket_name, name).await? {
+            baselines.insert(name.to_string(), unique_id);
+        }
+    }
+    Ok(baselines)
+}
+
+// Names are a single s3 key segment and can't be confused with a unique_id.
+fn validate_name(name: &str) -> OrchResult<()> {
+    if name.is_empty() || name.contains('/') || RunPrefix::parse(name).is_some() {
+        return Err(OrchError::Init {
+            dbg: format!("Invalid baseline name {:?}. ex: main-latest, v1.32.0", name),
+        });
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn baseline_names() {
+        assert!(validate_name("main-latest").is_ok());
+        assert!(validate_name("v1.32.0").is_ok());
+        assert!(validate_name("").is_err());
+        assert!(validate_name("main/latest").is_err());
+        assert!(validate_name("2024-01-09T05:25:30Z-v2.0.1").is_err());
+    }
+}
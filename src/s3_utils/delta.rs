@@ -0,0 +1,166 @@
+// Copyright Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+use super::{list_objects, local_files, upload_file};
+use crate::error::{OrchError, OrchResult};
+use aws_sdk_s3 as s3;
+use aws_sdk_s3::primitives::ByteStream;
+use sha2::{Digest, Sha256};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    io::Read,
+    path::Path,
+};
+use tracing::debug;
+
+/// The sha256 of each file of a local directory.
+///
+/// Files are uploaded by hash so that iterating on a local change only uploads
+/// the files which changed, and a host only downloads the files which differ
+/// from its copy.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct SourceManifest {
+    // relative path -> sha256
+    files: BTreeMap<String, String>,
+}
+
+impl SourceManifest {
+    /// Hash the files of `local_dir`. `exclude` is a list of path prefixes,
+    /// relative to `local_dir`, to skip.
+    pub fn from_dir(local_dir: &Path, exclude: &[&str]) -> OrchResult<Self> {
+        let mut files = BTreeMap::new();
+        for (relative_path, _len) in local_files(local_dir)? {
+            if exclude
+                .iter()
+                .any(|exclude| relative_path.starts_with(exclude))
+            {
+                continue;
+            }
+            let path = local_dir.join(&relative_path);
+            files.insert(relative_path, sha256_file(&path)?);
+        }
+        Ok(SourceManifest { files })
+    }
+
+    /// Identifies the contents of the directory.
+    pub fn digest(&self) -> String {
+        format!("{:x}", Sha256::digest(self.to_sha256sum()))
+    }
+
+    /// The manifest in the `sha256sum` format, ie. `<sha256>  <path>` lines.
+    pub fn to_sha256sum(&self) -> String {
+        self.files
+            .iter()
+            .map(|(path, hash)| format!("{}  {}\n", hash, path))
+            .collect()
+    }
+
+    /// Upload the files which aren't already stored under `prefix`, as
+    /// `<prefix>/<sha256>`. Returns the number of uploaded files.
+    pub async fn upload(
+        &self,
+        client: &s3::Client,
+        local_dir: &Path,
+        bucket_name: &str,
+        prefix: &str,
+    ) -> OrchResult<usize> {
+        let prefix = prefix.trim_end_matches('/');
+        let existing = list_objects(client, bucket_name, prefix).await?;
+
+        let mut uploaded = 0;
+        let mut hashes = BTreeSet::new();
+        for (relative_path, hash) in self.files.iter() {
+            let key = format!("{}/{}", prefix, hash);
+            // identical files are only uploaded once
+            if existing.contains_key(&key) || !hashes.insert(hash) {
+                continue;
+            }
+
+            debug!("upload: {} -> {}", relative_path, key);
+            upload_file(client, bucket_name, &local_dir.join(relative_path), &key).await?;
+            uploaded += 1;
+        }
+        Ok(uploaded)
+    }
+
+    /// Upload the manifest, in the `sha256sum` format, to `key`.
+    pub async fn upload_manifest(
+        &self,
+        client: &s3::Client,
+        bucket_name: &str,
+        key: &str,
+    ) -> OrchResult<()> {
+        client
+            .put_object()
+            .bucket(bucket_name)
+            .key(key)
+            .content_type("text/plain")
+            .body(ByteStream::from(self.to_sha256sum().into_bytes()))
+            .send()
+            .await
+            .map_err(|err| OrchError::S3 {
+                dbg: format!("Failed to upload {}: {}", key, err),
+            })?;
+        Ok(())
+    }
+}
+
+fn sha256_file(path: &Path) -> OrchResult<String> {
+    let mut file = std::fs::File::open(path).map_err(|err| OrchError::S3 {
+        dbg: format!("Failed to open {:?}: {}", path, err),
+    })?;
+    let mut hasher = Sha256::new();
+    let mut buf = vec![0; 64 * 1024];
+    loop {
+        let read = file.read(&mut buf).map_err(|err| OrchError::S3 {
+            dbg: format!("Failed to read {:?}: {}", path, err),
+        })?;
+        if read == 0 {
+            break;
+        }
+        hasher.update(&buf[..read]);
+    }
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn source_manifest() {
+        let dir = tempdir::TempDir::new("source").unwrap();
+        std::fs::create_dir_all(dir.path().join("src")).unwrap();
+        std::fs::create_dir_all(dir.path().join("target/debug")).unwrap();
+        std::fs::write(dir.path().join("Cargo.toml"), "[package]").unwrap();
+        std::fs::write(dir.path().join("src/main.rs"), "fn main() {}").unwrap();
+        std::fs::write(dir.path().join("target/debug/driver"), "elf").unwrap();
+
+        let manifest = SourceManifest::from_dir(dir.path(), &["target/"]).unwrap();
+        assert_eq!(
+            manifest.to_sha256sum(),
+            format!(
+                "{:x}  Cargo.toml\n{:x}  src/main.rs\n",
+                Sha256::digest("[package]"),
+                Sha256::digest("fn main() {}")
+            )
+        );
+
+        // only a change to the source changes the digest
+        let digest = manifest.digest();
+        std::fs::write(dir.path().join("target/debug/driver"), "elf2").unwrap();
+        assert_eq!(
+            SourceManifest::from_dir(dir.path(), &["target/"])
+                .unwrap()
+                .digest(),
+            digest
+        );
+        std::fs::write(dir.path().join("src/main.rs"), "fn main() { }").unwrap();
+        assert_ne!(
+            SourceManifest::from_dir(dir.path(), &["target/"])
+                .unwrap()
+                .digest(),
+            digest
+        );
+    }
+}
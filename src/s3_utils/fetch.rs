@@ -0,0 +1,126 @@
+// Copyright Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+use super::{list_objects, sync_from_s3, DownloadOptions};
+use crate::{
+    dashboard::status::group_artifacts,
+    error::{OrchError, OrchResult},
+    STATE,
+};
+use clap::Args;
+use std::{collections::BTreeMap, path::PathBuf};
+
+// The objects of a run which are only meaningful while it runs: the KMS
+// wrapped secrets and the cancel request.
+const EXCLUDE: &[&str] = &["secrets/", "cancel"];
+
+#[derive(Args, Debug)]
+pub struct FetchArgs {
+    /// The unique_id of the run. ex: 2023-10-11T17:05:09Z-v2.0.0
+    #[arg(long)]
+    unique_id: String,
+
+    /// The directory to download the run to. Defaults to ./<unique_id>
+    #[arg(long)]
+    out: Option<PathBuf>,
+}
+
+impl FetchArgs {
+    /// Download the run's folder, ex: results, logs, flamegraphs and manifest,
+    /// to a local directory which mirrors the folder's layout.
+    pub async fn run(
+        &self,
+        download: DownloadOptions,
+        aws_config: &aws_types::SdkConfig,
+    ) -> OrchResult<()> {
+        let s3_client = aws_sdk_s3::Client::new(aws_config);
+        let out = self
+            .out
+            .clone()
+            .unwrap_or_else(|| PathBuf::from(&self.unique_id));
+        let prefix = format!("{}/", self.unique_id);
+
+        let objects = fetched(
+            &self.unique_id,
+            list_objects(&s3_client, STATE.s3_log_bucket, &prefix).await?,
+        );
+        if objects.is_empty() {
+            return Err(OrchError::Init {
+                dbg: format!(
+                    "No artifacts of {} in {}",
+                    self.unique_id, STATE.s3_log_bucket
+                ),
+            });
+        }
+
+        let downloaded = sync_from_s3(
+            &s3_client,
+            STATE.s3_log_bucket,
+            &prefix,
+            &out,
+            download,
+            EXCLUDE,
+        )
+        .await?;
+        for (name, (count, len)) in group_artifacts(&self.unique_id, &objects) {
+            println!("  {name}: {count} objects, {len} bytes");
+        }
+        println!(
+            "Fetched {} objects ({} already present) to {}",
+            downloaded,
+            objects.len() - downloaded,
+            out.display()
+        );
+        Ok(())
+    }
+}
+
+/// The objects of the run which are fetched.
+fn fetched(unique_id: &str, objects: BTreeMap<String, u64>) -> BTreeMap<String, u64> {
+    let prefix = format!("{unique_id}/");
+    objects
+        .into_iter()
+        .filter(|(key, _len)| {
+            key.strip_prefix(&prefix).is_some_and(|path| {
+                !path.is_empty() && !EXCLUDE.iter().any(|exclude| path.starts_with(exclude))
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fetched_objects() {
+        let unique_id = "2023-10-11T17:05:09Z-v2.0.0";
+        let objects: BTreeMap<String, u64> = [
+            "manifest.json",
+            "cancel",
+            "secrets/tls.kms",
+            "results/request_response/s2n-quic/server.json",
+            "server/flamegraph.svg",
+        ]
+        .iter()
+        .map(|path| (format!("{unique_id}/{path}"), 10))
+        .chain([(format!("{unique_id}-other/manifest.json"), 10)])
+        .collect();
+
+        let fetched = fetched(unique_id, objects);
+        assert_eq!(
+            fetched.keys().collect::<Vec<_>>(),
+            [
+                "2023-10-11T17:05:09Z-v2.0.0/manifest.json",
+                "2023-10-11T17:05:09Z-v2.0.0/results/request_response/s2n-quic/server.json",
+                "2023-10-11T17:05:09Z-v2.0.0/server/flamegraph.svg",
+            ]
+        );
+        assert_eq!(
+            group_artifacts(unique_id, &fetched)
+                .into_keys()
+                .collect::<Vec<_>>(),
+            ["manifest.json", "results/", "server/"]
+        );
+    }
+}
@@ -0,0 +1,229 @@
+// Copyright Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+use super::{download_object, list_objects, prune::RunPrefix, upload_object};
+use crate::{
+    error::{OrchError, OrchResult},
+    manifest::Manifest,
+    report::render::Format,
+    STATE,
+};
+use aws_sdk_s3::primitives::ByteStream;
+use bytes::Bytes;
+use clap::Args;
+use serde::{Deserialize, Serialize};
+use std::{cmp::Reverse, collections::BTreeMap};
+
+/// Each run is indexed by an entry under this prefix: `index/<unique_id>.json`,
+/// so that runs can be found by their labels without reading every manifest.
+pub const INDEX_PREFIX: &str = "index";
+
+/// A label on a run. ex: `--label branch=my-feature`
+pub fn parse_label(label: &str) -> Result<(String, String), String> {
+    let (key, value) = label
+        .split_once('=')
+        .ok_or(format!("expected key=value but found: {}", label))?;
+    if key.is_empty()
+        || !key
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "-_.".contains(c))
+    {
+        return Err(format!(
+            "label keys may only contain alphanumerics, '-', '_' and '.': {}",
+            key
+        ));
+    }
+    Ok((key.to_string(), value.to_string()))
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RunIndexEntry {
+    pub unique_id: String,
+    #[serde(default)]
+    pub labels: BTreeMap<String, String>,
+    pub scenarios: Vec<String>,
+    pub report_url: String,
+}
+
+impl RunIndexEntry {
+    pub fn key(unique_id: &str) -> String {
+        format!("{INDEX_PREFIX}/{unique_id}.json")
+    }
+
+    /// Whether the run has all the `labels`.
+    fn matches(&self, labels: &[(String, String)]) -> bool {
+        labels
+            .iter()
+            .all(|(key, value)| self.labels.get(key) == Some(value))
+    }
+}
+
+/// Index the run described by the manifest.
+pub async fn record(s3_client: &aws_sdk_s3::Client, manifest: &Manifest) -> OrchResult<()> {
+    let entry = RunIndexEntry {
+        unique_id: manifest.unique_id.clone(),
+        labels: manifest.labels.clone(),
+        scenarios: manifest.scenarios.clone(),
+        report_url: format!("{}/report/index.html", STATE.cf_url(&manifest.unique_id)),
+    };
+    let key = RunIndexEntry::key(&entry.unique_id);
+    upload_object(
+        s3_client,
+        STATE.s3_log_bucket,
+        ByteStream::from(Bytes::from(
+            serde_json::to_string(&entry).expect("index entry serializes"),
+        )),
+        &key,
+    )
+    .await
+    .map_err(|err| OrchError::S3 {
+        dbg: format!("Failed to index the run {}: {}", key, err),
+    })?;
+    Ok(())
+}
+
+#[derive(Args, Debug)]
+pub struct ListRunsArgs {
+    /// Only list the runs with this label. May be repeated. ex: branch=my-feature
+    #[arg(long = "label", value_parser = parse_label)]
+    labels: Vec<(String, String)>,
+
+    /// The number of runs to list, newest first
+    #[arg(long, default_value_t = 20)]
+    last: usize,
+
+    /// The format of the list: terminal, markdown or html
+    #[arg(long, default_value_t = Format::Terminal)]
+    format: Format,
+}
+
+impl ListRunsArgs {
+    pub async fn run(&self, aws_config: &aws_types::SdkConfig) -> OrchResult<()> {
+        let s3_client = aws_sdk_s3::Client::new(aws_config);
+        let indexed = indexed_runs(
+            list_objects(&s3_client, STATE.s3_log_bucket, &format!("{INDEX_PREFIX}/"))
+                .await?
+                .into_keys(),
+        );
+
+        // read the entries from the newest run until enough of them match
+        let mut runs = Vec::new();
+        for run in indexed {
+            if runs.len() >= self.last {
+                break;
+            }
+            let entry = download_entry(&s3_client, &run.unique_id).await?;
+            if entry.matches(&self.labels) {
+                runs.push((run, entry));
+            }
+        }
+
+        let renderer = self.format.renderer();
+        let rows: Vec<Vec<String>> = runs
+            .iter()
+            .map(|(run, entry)| {
+                let labels: Vec<String> = entry
+                    .labels
+                    .iter()
+                    .map(|(key, value)| format!("{key}={value}"))
+                    .collect();
+                vec![
+                    renderer.text(&entry.unique_id),
+                    humantime::format_rfc3339_seconds(run.created).to_string(),
+                    renderer.text(&labels.join(",")),
+                    renderer.link("report", &entry.report_url),
+                ]
+            })
+            .collect();
+        println!(
+            "{}",
+            renderer.document(&[renderer.table(&["run", "started", "labels", "report"], &rows)])
+        );
+        Ok(())
+    }
+}
+
+/// The runs of the index keys, newest first.
+fn indexed_runs(keys: impl Iterator<Item = String>) -> Vec<RunPrefix> {
+    let mut runs: Vec<RunPrefix> = keys
+        .filter_map(|key| {
+            let unique_id = key
+                .strip_prefix(&format!("{INDEX_PREFIX}/"))?
+                .strip_suffix(".json")?
+                .to_string();
+            RunPrefix::parse(&unique_id)
+        })
+        .collect();
+    runs.sort_by_key(|run| Reverse(run.created));
+    runs
+}
+
+async fn download_entry(
+    s3_client: &aws_sdk_s3::Client,
+    unique_id: &str,
+) -> OrchResult<RunIndexEntry> {
+    let key = RunIndexEntry::key(unique_id);
+    let entry = download_object(s3_client, STATE.s3_log_bucket, &key)
+        .await
+        .map_err(|err| OrchError::S3 {
+            dbg: format!("Failed to download {}: {}", key, err),
+        })?
+        .body
+        .collect()
+        .await
+        .map_err(|err| OrchError::S3 {
+            dbg: format!("Failed to download {}: {}", key, err),
+        })?
+        .into_bytes();
+    serde_json::from_slice(&entry).map_err(|err| OrchError::S3 {
+        dbg: format!("Failed to parse {}: {}", key, err),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn run_index() {
+        assert_eq!(
+            parse_label("branch=my-feature=2").unwrap(),
+            ("branch".to_string(), "my-feature=2".to_string())
+        );
+        assert!(parse_label("branch").is_err());
+        assert!(parse_label("=main").is_err());
+        assert!(parse_label("a/b=c").is_err());
+
+        let runs = indexed_runs(
+            [
+                "index/2024-01-09T05:25:30Z-v2.0.1.json",
+                "index/2024-02-01T00:00:00Z-v2.0.1.json",
+                "index/not-a-run.json",
+            ]
+            .into_iter()
+            .map(String::from),
+        );
+        assert_eq!(
+            runs.iter()
+                .map(|run| run.unique_id.as_str())
+                .collect::<Vec<_>>(),
+            ["2024-02-01T00:00:00Z-v2.0.1", "2024-01-09T05:25:30Z-v2.0.1"]
+        );
+
+        let entry = RunIndexEntry {
+            unique_id: runs[0].unique_id.clone(),
+            labels: BTreeMap::from([
+                ("branch".to_string(), "my-feature".to_string()),
+                ("pr".to_string(), "12".to_string()),
+            ]),
+            scenarios: vec!["request_response.json".to_string()],
+            report_url: "https://example.com/report/index.html".to_string(),
+        };
+        assert!(entry.matches(&[]));
+        assert!(entry.matches(&[parse_label("branch=my-feature").unwrap()]));
+        assert!(!entry.matches(&[
+            parse_label("branch=my-feature").unwrap(),
+            parse_label("pr=13").unwrap()
+        ]));
+    }
+}
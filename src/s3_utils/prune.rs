@@ -0,0 +1,358 @@
+// Copyright Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+use super::{baseline::list_baselines, delete_object, index::RunIndexEntry, list_objects};
+use crate::{
+    duration::parse_duration,
+    error::{OrchError, OrchResult},
+    STATE,
+};
+use aws_sdk_s3::{
+    error::ProvideErrorMetadata,
+    types::{
+        AbortIncompleteMultipartUpload, BucketLifecycleConfiguration, Delete, ExpirationStatus,
+        LifecycleExpiration, LifecycleRule, LifecycleRuleFilter, ObjectIdentifier,
+    },
+};
+use clap::Args;
+use core::time::Duration;
+use std::{cmp::Reverse, collections::BTreeSet, time::SystemTime};
+use tracing::info;
+
+const LIFECYCLE_RULE_ID: &str = "netbench-orchestrator-retention";
+
+#[derive(Args, Debug)]
+pub struct PruneArgs {
+    /// Always keep the N most recent runs
+    #[arg(long)]
+    keep_last: Option<usize>,
+
+    /// Delete runs older than this. ex: 30days
+    #[arg(long, value_parser = parse_duration)]
+    older_than: Option<Duration>,
+
+    /// Print the runs which would be deleted without deleting them
+    #[arg(long)]
+    dry_run: bool,
+
+    /// Configure a bucket lifecycle rule which expires objects after N days.
+    ///
+    /// Lifecycle rules are applied by s3 and do not know about baselines, so
+    /// the rule is refused while runs are tagged as baselines. The bucket's
+    /// other lifecycle rules are kept.
+    #[arg(long)]
+    lifecycle_expire_days: Option<i32>,
+}
+
+impl PruneArgs {
+    pub async fn run(&self, aws_config: &aws_types::SdkConfig) -> OrchResult<()> {
+        let s3_client = aws_sdk_s3::Client::new(aws_config);
+        let policy = RetentionPolicy {
+            keep_last: self.keep_last,
+            older_than: self.older_than,
+        };
+
+        if let Some(days) = self.lifecycle_expire_days {
+            configure_lifecycle(&s3_client, STATE.s3_log_bucket, days).await?;
+            println!("Configured lifecycle rule: expire objects after {days} days");
+        }
+        if policy.keep_last.is_none() && policy.older_than.is_none() {
+            if self.lifecycle_expire_days.is_none() {
+                return Err(OrchError::Init {
+                    dbg: "Specify a retention policy: --keep-last and/or --older-than".to_string(),
+                });
+            }
+            return Ok(());
+        }
+
+        let pruned = prune(&s3_client, STATE.s3_log_bucket, &policy, self.dry_run).await?;
+        let action = if self.dry_run {
+            "would delete"
+        } else {
+            "deleted"
+        };
+        for unique_id in pruned.iter() {
+            println!("{action} {unique_id}");
+        }
+        println!("Pruned {} runs", pruned.len());
+        Ok(())
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct RetentionPolicy {
+    pub keep_last: Option<usize>,
+    pub older_than: Option<Duration>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RunPrefix {
+    pub unique_id: String,
+    pub created: SystemTime,
+}
+
+impl RunPrefix {
+    // The unique_id of a run is prefixed with the time it was started.
+    // ex: 2024-01-09T05:25:30Z-v2.0.1
+    pub fn parse(unique_id: &str) -> Option<Self> {
+        let (timestamp, _version) = unique_id.split_once('Z')?;
+        let created = humantime::parse_rfc3339(&format!("{timestamp}Z")).ok()?;
+        Some(RunPrefix {
+            unique_id: unique_id.to_string(),
+            created,
+        })
+    }
+}
+
+/// Delete runs which fall outside the retention policy. Runs tagged as
+/// baselines are always kept.
+///
+/// Returns the unique_id of the pruned runs.
+pub async fn prune(
+    s3_client: &aws_sdk_s3::Client,
+    bucket_name: &str,
+    policy: &RetentionPolicy,
+    dry_run: bool,
+) -> OrchResult<Vec<String>> {
+    let runs = list_runs(s3_client, bucket_name).await?;
+    let baselines = list_baselines(s3_client, bucket_name)
+        .await?
+        .into_values()
+        .collect();
+    let to_prune = select_runs_to_prune(runs, &baselines, policy, SystemTime::now());
+
+    if !dry_run {
+        for unique_id in to_prune.iter() {
+            info!("pruning run: {}", unique_id);
+            delete_prefix(s3_client, bucket_name, &format!("{unique_id}/")).await?;
+            delete_object(s3_client, bucket_name, &RunIndexEntry::key(unique_id)).await?;
+        }
+    }
+    Ok(to_prune)
+}
+
+/// List the runs in the bucket, sorted from newest to oldest.
+pub async fn list_runs(
+    s3_client: &aws_sdk_s3::Client,
+    bucket_name: &str,
+) -> OrchResult<Vec<RunPrefix>> {
+    let mut runs = Vec::new();
+    let mut continuation_token = None;
+    loop {
+        let output = s3_client
+            .list_objects_v2()
+            .bucket(bucket_name)
+            .delimiter("/")
+            .set_continuation_token(continuation_token)
+            .send()
+            .await
+            .map_err(|err| OrchError::S3 {
+                dbg: format!("Failed to list runs in {}: {}", bucket_name, err),
+            })?;
+
+        // Ignore prefixes which are not runs (resource folder, baselines, etc)
+        runs.extend(
+            output
+                .common_prefixes()
+                .unwrap_or_default()
+                .iter()
+                .filter_map(|prefix| prefix.prefix())
+                .filter_map(|prefix| RunPrefix::parse(prefix.trim_end_matches('/'))),
+        );
+
+        continuation_token = output.next_continuation_token().map(String::from);
+        if continuation_token.is_none() {
+            break;
+        }
+    }
+
+    runs.sort_by_key(|run| Reverse(run.created));
+    Ok(runs)
+}
+
+// `runs` is expected to be sorted from newest to oldest.
+fn select_runs_to_prune(
+    runs: Vec<RunPrefix>,
+    baselines: &BTreeSet<String>,
+    policy: &RetentionPolicy,
+    now: SystemTime,
+) -> Vec<String> {
+    let keep_last = policy.keep_last.unwrap_or(0);
+    runs.into_iter()
+        .enumerate()
+        .filter(|(i, _run)| *i >= keep_last)
+        .filter(|(_i, run)| match policy.older_than {
+            Some(older_than) => now
+                .duration_since(run.created)
+                .is_ok_and(|age| age > older_than),
+            None => true,
+        })
+        .filter(|(_i, run)| !baselines.contains(&run.unique_id))
+        .map(|(_i, run)| run.unique_id)
+        .collect()
+}
+
+async fn delete_prefix(
+    s3_client: &aws_sdk_s3::Client,
+    bucket_name: &str,
+    prefix: &str,
+) -> OrchResult<()> {
+    let keys: Vec<String> = list_objects(s3_client, bucket_name, prefix)
+        .await?
+        .into_keys()
+        .collect();
+
+    // DeleteObjects accepts at most 1000 keys per request
+    for keys in keys.chunks(1000) {
+        let objects = keys
+            .iter()
+            .map(|key| ObjectIdentifier::builder().key(key).build())
+            .collect();
+        s3_client
+            .delete_objects()
+            .bucket(bucket_name)
+            .delete(Delete::builder().set_objects(Some(objects)).build())
+            .send()
+            .await
+            .map_err(|err| OrchError::S3 {
+                dbg: format!("Failed to delete {}: {}", prefix, err),
+            })?;
+    }
+    Ok(())
+}
+
+async fn configure_lifecycle(
+    s3_client: &aws_sdk_s3::Client,
+    bucket_name: &str,
+    expire_days: i32,
+) -> OrchResult<()> {
+    let baselines = list_baselines(s3_client, bucket_name).await?;
+    if !baselines.is_empty() {
+        return Err(OrchError::Init {
+            dbg: format!(
+                "The lifecycle rule would expire the {} baseline runs. Untag them first: {:?}",
+                baselines.len(),
+                baselines.keys().collect::<Vec<_>>()
+            ),
+        });
+    }
+
+    // the configuration replaces all of the bucket's rules
+    let existing = match s3_client
+        .get_bucket_lifecycle_configuration()
+        .bucket(bucket_name)
+        .send()
+        .await
+    {
+        Ok(output) => output.rules().unwrap_or_default().to_vec(),
+        Err(err) if err.code() == Some("NoSuchLifecycleConfiguration") => Vec::new(),
+        Err(err) => {
+            return Err(OrchError::S3 {
+                dbg: format!("Failed to get the lifecycle of {}: {}", bucket_name, err),
+            })
+        }
+    };
+
+    let rule = LifecycleRule::builder()
+        .id(LIFECYCLE_RULE_ID)
+        .status(ExpirationStatus::Enabled)
+        .filter(LifecycleRuleFilter::Prefix("".to_string()))
+        .expiration(LifecycleExpiration::builder().days(expire_days).build())
+        .abort_incomplete_multipart_upload(
+            AbortIncompleteMultipartUpload::builder()
+                .days_after_initiation(1)
+                .build(),
+        )
+        .build();
+
+    s3_client
+        .put_bucket_lifecycle_configuration()
+        .bucket(bucket_name)
+        .lifecycle_configuration(
+            BucketLifecycleConfiguration::builder()
+                .set_rules(Some(merge_lifecycle_rules(existing, rule)))
+                .build(),
+        )
+        .send()
+        .await
+        .map_err(|err| OrchError::S3 {
+            dbg: format!("Failed to configure lifecycle for {}: {}", bucket_name, err),
+        })?;
+    Ok(())
+}
+
+/// Replace the retention rule among the bucket's rules.
+fn merge_lifecycle_rules(existing: Vec<LifecycleRule>, rule: LifecycleRule) -> Vec<LifecycleRule> {
+    existing
+        .into_iter()
+        .filter(|existing| existing.id() != Some(LIFECYCLE_RULE_ID))
+        .chain(std::iter::once(rule))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::s3_utils::baseline::BASELINE_PREFIX;
+
+    fn run(unique_id: &str) -> RunPrefix {
+        RunPrefix::parse(unique_id).unwrap()
+    }
+
+    #[test]
+    fn parse_run_prefix() {
+        assert!(RunPrefix::parse("2024-01-09T05:25:30Z-v2.0.1").is_some());
+        assert!(RunPrefix::parse("TS").is_none());
+        assert!(RunPrefix::parse(BASELINE_PREFIX).is_none());
+    }
+
+    #[test]
+    fn retention_policy() {
+        let now = humantime::parse_rfc3339("2024-02-01T00:00:00Z").unwrap();
+        let runs = vec![
+            run("2024-01-31T00:00:00Z-v2.1.3"),
+            run("2024-01-20T00:00:00Z-v2.1.3"),
+            run("2024-01-10T00:00:00Z-v2.1.3"),
+            run("2024-01-01T00:00:00Z-v2.1.3"),
+        ];
+        let baselines = BTreeSet::from_iter(["2024-01-01T00:00:00Z-v2.1.3".to_string()]);
+
+        let policy = RetentionPolicy {
+            keep_last: Some(1),
+            older_than: None,
+        };
+        assert_eq!(
+            select_runs_to_prune(runs.clone(), &baselines, &policy, now),
+            vec!["2024-01-20T00:00:00Z-v2.1.3", "2024-01-10T00:00:00Z-v2.1.3"]
+        );
+
+        let policy = RetentionPolicy {
+            keep_last: None,
+            older_than: Some(Duration::from_secs(15 * 24 * 60 * 60)),
+        };
+        assert_eq!(
+            select_runs_to_prune(runs.clone(), &baselines, &policy, now),
+            vec!["2024-01-10T00:00:00Z-v2.1.3"]
+        );
+
+        let policy = RetentionPolicy {
+            keep_last: Some(3),
+            older_than: Some(Duration::from_secs(15 * 24 * 60 * 60)),
+        };
+        assert!(select_runs_to_prune(runs, &baselines, &policy, now).is_empty());
+    }
+
+    #[test]
+    fn lifecycle_rules() {
+        let rule = |id: &str, days: i32| {
+            LifecycleRule::builder()
+                .id(id)
+                .status(ExpirationStatus::Enabled)
+                .expiration(LifecycleExpiration::builder().days(days).build())
+                .build()
+        };
+        let existing = vec![rule("logs", 7), rule(LIFECYCLE_RULE_ID, 30)];
+        let rules = merge_lifecycle_rules(existing, rule(LIFECYCLE_RULE_ID, 90));
+        assert_eq!(rules, vec![rule("logs", 7), rule(LIFECYCLE_RULE_ID, 90)]);
+    }
+}
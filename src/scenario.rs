@@ -0,0 +1,235 @@
+// Copyright Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::{
+    error::{OrchError, OrchResult},
+    s3_utils::upload_object,
+    Scenario, STATE,
+};
+use aws_sdk_s3::primitives::ByteStream;
+use certs::RunCerts;
+use clap::{Args, Subcommand};
+use sha2::{Digest, Sha256};
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+    process::Command,
+};
+use tempdir::TempDir;
+use tracing::{debug, info};
+
+pub mod certs;
+pub mod schema;
+
+/// Upload the scenario files to the run's folder, from which they're copied to
+/// the hosts, with the run's certificates if there are `certs`.
+///
+/// Returns the sha256 of each uploaded scenario file, keyed by the scenario name.
+pub async fn upload_scenarios(
+    s3_client: &aws_sdk_s3::Client,
+    unique_id: &str,
+    scenarios: &[Scenario],
+    certs: Option<&RunCerts>,
+) -> OrchResult<BTreeMap<String, String>> {
+    let mut checksums = BTreeMap::new();
+    for scenario in scenarios.iter() {
+        let mut contents = std::fs::read(&scenario.path).map_err(|err| OrchError::Init {
+            dbg: format!("Failed to read scenario {:?}: {}", scenario.path, err),
+        })?;
+        if let Some(certs) = certs {
+            contents = certs.apply(&scenario.name, &contents)?;
+        }
+        checksums.insert(scenario.name.clone(), sha256_hex(&contents));
+        upload_object(
+            s3_client,
+            STATE.s3_log_bucket,
+            ByteStream::from(contents),
+            &format!("{unique_id}/{}", scenario.name),
+        )
+        .await
+        .map_err(|err| OrchError::S3 {
+            dbg: format!("Failed to upload scenario {}: {}", scenario.name, err),
+        })?;
+    }
+    Ok(checksums)
+}
+
+fn sha256_hex(contents: &[u8]) -> String {
+    format!("{:x}", Sha256::digest(contents))
+}
+
+#[derive(Subcommand, Debug)]
+pub enum ScenarioCommand {
+    /// Generate scenario files using `s2n-netbench-scenarios`
+    ///
+    /// Each parameter can be specified multiple times. A scenario file is generated
+    /// for every combination of the provided values.
+    Generate(GenerateArgs),
+}
+
+#[derive(Args, Debug)]
+pub struct GenerateArgs {
+    /// The netbench scenario to generate
+    #[arg(long, default_value = "request_response")]
+    scenario: String,
+
+    /// Size of the client's request. ex: 1KB
+    #[arg(long)]
+    request_size: Vec<String>,
+
+    /// Size of the server's response. ex: 10MB
+    #[arg(long)]
+    response_size: Vec<String>,
+
+    /// Number of connections to open
+    #[arg(long)]
+    connections: Vec<String>,
+
+    /// Directory to write the generated scenario files to
+    #[arg(long, default_value_t = format!("{}/scenarios", STATE.workspace_dir))]
+    out_dir: String,
+}
+
+impl ScenarioCommand {
+    pub fn run(&self) -> OrchResult<()> {
+        match self {
+            ScenarioCommand::Generate(args) => {
+                let paths = generate(args)?;
+                for path in paths.iter() {
+                    println!("{}", path.display());
+                }
+                println!(
+                    "Generated {} scenarios. Run with: --scenario-file {}",
+                    paths.len(),
+                    args.out_dir
+                );
+                Ok(())
+            }
+        }
+    }
+}
+
+pub fn generate(args: &GenerateArgs) -> OrchResult<Vec<PathBuf>> {
+    let out_dir = Path::new(&args.out_dir);
+    std::fs::create_dir_all(out_dir).map_err(|err| OrchError::Init {
+        dbg: format!("Failed to create scenario dir {:?}: {}", out_dir, err),
+    })?;
+
+    let mut paths = Vec::new();
+    for params in param_combinations(args) {
+        // `s2n-netbench-scenarios` generates every scenario it knows about into a
+        // directory so generate into a tmp dir and only keep the one we want.
+        let tmp_dir = TempDir::new("netbench_scenarios").map_err(|err| OrchError::Init {
+            dbg: err.to_string(),
+        })?;
+
+        let mut cmd = Command::new("s2n-netbench-scenarios");
+        for (key, value) in params.iter() {
+            cmd.arg(format!("--{}.{}={}", args.scenario, key, value));
+        }
+        cmd.arg(tmp_dir.path());
+        debug!("{:?}", cmd);
+        let output = cmd.output().map_err(|_err| OrchError::Init {
+            dbg: "Missing `s2n-netbench-scenarios` cli. Please the Getting started section in the Readme".to_string(),
+        })?;
+        if !output.status.success() {
+            return Err(OrchError::Init {
+                dbg: format!(
+                    "s2n-netbench-scenarios failed: {}",
+                    String::from_utf8_lossy(&output.stderr)
+                ),
+            });
+        }
+
+        let generated = tmp_dir.path().join(format!("{}.json", args.scenario));
+        let path = out_dir.join(format!(
+            "{}.json",
+            scenario_file_stem(&args.scenario, &params)
+        ));
+        std::fs::copy(&generated, &path).map_err(|err| OrchError::Init {
+            dbg: format!("Failed to copy generated scenario {:?}: {}", generated, err),
+        })?;
+        info!("Generated scenario: {:?}", path);
+        paths.push(path);
+    }
+
+    Ok(paths)
+}
+
+// The cartesian product of all the user provided parameter values.
+fn param_combinations(args: &GenerateArgs) -> Vec<Vec<(&'static str, String)>> {
+    let params: [(&'static str, &Vec<String>); 3] = [
+        ("request_size", &args.request_size),
+        ("response_size", &args.response_size),
+        ("connections", &args.connections),
+    ];
+
+    let mut combinations = vec![vec![]];
+    for (key, values) in params {
+        // use the netbench default if the parameter was not specified
+        if values.is_empty() {
+            continue;
+        }
+
+        combinations = combinations
+            .into_iter()
+            .flat_map(|combination| {
+                values.iter().map(move |value| {
+                    let mut combination = combination.clone();
+                    combination.push((key, value.clone()));
+                    combination
+                })
+            })
+            .collect();
+    }
+    combinations
+}
+
+// ex: request_response-request_size_1KB-response_size_10MB
+fn scenario_file_stem(scenario: &str, params: &[(&str, String)]) -> String {
+    params
+        .iter()
+        .fold(scenario.to_string(), |mut stem, (key, value)| {
+            stem.push_str(&format!("-{key}_{value}"));
+            stem
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn scenario_checksum() {
+        // matches `sha256sum` on the hosts
+        assert_eq!(
+            sha256_hex(b"abc"),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+    }
+
+    #[test]
+    fn param_sweep() {
+        let args = GenerateArgs {
+            scenario: "request_response".to_string(),
+            request_size: vec!["1KB".to_string(), "2KB".to_string()],
+            response_size: vec![],
+            connections: vec!["1".to_string(), "10".to_string()],
+            out_dir: "".to_string(),
+        };
+
+        let stems: Vec<String> = param_combinations(&args)
+            .iter()
+            .map(|params| scenario_file_stem(&args.scenario, params))
+            .collect();
+        assert_eq!(
+            stems,
+            vec![
+                "request_response-request_size_1KB-connections_1",
+                "request_response-request_size_1KB-connections_10",
+                "request_response-request_size_2KB-connections_1",
+                "request_response-request_size_2KB-connections_10",
+            ]
+        );
+    }
+}
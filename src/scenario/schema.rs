@@ -0,0 +1,237 @@
+// Copyright Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::error::{OrchError, OrchResult};
+use serde::Deserialize;
+use serde_json::Value;
+
+// The subset of the s2n-netbench scenario types which the orchestrator relies
+// on. The client and server operations are kept as json since they are only
+// checked for their references to other parts of the scenario.
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct NetbenchScenario {
+    pub id: String,
+    pub clients: Vec<Client>,
+    pub servers: Vec<Server>,
+    #[serde(default)]
+    pub routers: Vec<Value>,
+    #[serde(default)]
+    pub traces: Vec<String>,
+    #[serde(default)]
+    pub certificates: Vec<Certificate>,
+}
+
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct Client {
+    #[serde(default)]
+    pub scenario: Vec<Value>,
+    #[serde(default)]
+    pub connections: Vec<Value>,
+    #[serde(default)]
+    pub certificate_authorities: Vec<u64>,
+}
+
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct Server {
+    #[serde(default)]
+    pub connections: Vec<Value>,
+    pub private_key: u64,
+    pub certificate: u64,
+    pub certificate_authority: u64,
+}
+
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct Certificate {
+    pub pem: String,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+struct Connect {
+    server_id: u64,
+    server_connection_id: u64,
+    client_connection_id: u64,
+}
+
+impl NetbenchScenario {
+    /// Check that the scenario is complete and that every certificate, trace,
+    /// server and connection it references exists.
+    pub fn validate(&self) -> OrchResult<()> {
+        let mut errors = Vec::new();
+        if self.id.is_empty() {
+            errors.push("id: must not be empty".to_string());
+        }
+        if self.clients.is_empty() {
+            errors.push("clients: at least one client is required".to_string());
+        }
+        if self.servers.is_empty() {
+            errors.push("servers: at least one server is required".to_string());
+        }
+        for (i, certificate) in self.certificates.iter().enumerate() {
+            if !certificate.pem.starts_with("-----BEGIN ") {
+                errors.push(format!("certificates[{i}].pem: not a pem encoded entry"));
+            }
+        }
+
+        for (i, server) in self.servers.iter().enumerate() {
+            for (field, id) in [
+                ("private_key", server.private_key),
+                ("certificate", server.certificate),
+                ("certificate_authority", server.certificate_authority),
+            ] {
+                self.check_certificate(&format!("servers[{i}].{field}"), id, &mut errors);
+            }
+            for (j, connection) in server.connections.iter().enumerate() {
+                self.check_ops(
+                    &format!("servers[{i}].connections[{j}]"),
+                    connection,
+                    None,
+                    &mut errors,
+                );
+            }
+        }
+
+        for (i, client) in self.clients.iter().enumerate() {
+            for (j, id) in client.certificate_authorities.iter().enumerate() {
+                self.check_certificate(
+                    &format!("clients[{i}].certificate_authorities[{j}]"),
+                    *id,
+                    &mut errors,
+                );
+            }
+            for (j, op) in client.scenario.iter().enumerate() {
+                self.check_ops(
+                    &format!("clients[{i}].scenario[{j}]"),
+                    op,
+                    Some(client),
+                    &mut errors,
+                );
+            }
+            for (j, connection) in client.connections.iter().enumerate() {
+                self.check_ops(
+                    &format!("clients[{i}].connections[{j}]"),
+                    connection,
+                    None,
+                    &mut errors,
+                );
+            }
+        }
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(OrchError::Init {
+                dbg: format!("Invalid scenario: {}", errors.join("; ")),
+            })
+        }
+    }
+
+    fn check_certificate(&self, path: &str, id: u64, errors: &mut Vec<String>) {
+        if id as usize >= self.certificates.len() {
+            errors.push(format!(
+                "{path}: certificate {id} not found ({} certificates)",
+                self.certificates.len()
+            ));
+        }
+    }
+
+    // Walk the (nested) operations and check the trace ids and, for the client
+    // scenario, the connect operations.
+    fn check_ops(
+        &self,
+        path: &str,
+        value: &Value,
+        client: Option<&Client>,
+        errors: &mut Vec<String>,
+    ) {
+        match value {
+            Value::Array(values) => {
+                for (i, value) in values.iter().enumerate() {
+                    self.check_ops(&format!("{path}[{i}]"), value, client, errors);
+                }
+            }
+            Value::Object(map) => {
+                for (key, value) in map {
+                    let path = format!("{path}.{key}");
+                    match (key.as_str(), client) {
+                        ("trace_id", _) => {
+                            let trace_id = value.as_u64().unwrap_or(u64::MAX);
+                            if trace_id as usize >= self.traces.len() {
+                                errors.push(format!(
+                                    "{path}: trace {value} not found ({} traces)",
+                                    self.traces.len()
+                                ));
+                            }
+                        }
+                        ("connect", Some(client)) => {
+                            self.check_connect(&path, value, client, errors)
+                        }
+                        _ => self.check_ops(&path, value, client, errors),
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn check_connect(&self, path: &str, value: &Value, client: &Client, errors: &mut Vec<String>) {
+        let connect: Connect = match serde_json::from_value(value.clone()) {
+            Ok(connect) => connect,
+            Err(err) => {
+                errors.push(format!("{path}: {err}"));
+                return;
+            }
+        };
+        let Some(server) = self.servers.get(connect.server_id as usize) else {
+            errors.push(format!(
+                "{path}.server_id: server {} not found ({} servers)",
+                connect.server_id,
+                self.servers.len()
+            ));
+            return;
+        };
+        if connect.server_connection_id as usize >= server.connections.len() {
+            errors.push(format!(
+                "{path}.server_connection_id: connection {} not found on server {}",
+                connect.server_connection_id, connect.server_id
+            ));
+        }
+        if connect.client_connection_id as usize >= client.connections.len() {
+            errors.push(format!(
+                "{path}.client_connection_id: connection {} not found on the client",
+                connect.client_connection_id
+            ));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn validate_scenario() {
+        let file = std::fs::File::open("scripts/request_response_multi_2_incast.json").unwrap();
+        let mut scenario: NetbenchScenario = serde_json::from_reader(file).unwrap();
+        scenario.validate().unwrap();
+
+        scenario.servers[1].certificate = 7;
+        scenario.clients[0].scenario = serde_json::from_str(
+            r#"[{"scope": {"threads": [[{"connect": {"server_id": 2, "server_connection_id": 0, "client_connection_id": 0}}],
+                [{"connect": {"server_id": 0, "server_connection_id": 1, "client_connection_id": 0}}]]}}]"#,
+        )
+        .unwrap();
+        scenario.traces.truncate(2);
+        let err = scenario.validate().unwrap_err().to_string();
+        assert!(
+            err.contains("servers[1].certificate: certificate 7 not found (3 certificates)"),
+            "{err}"
+        );
+        assert!(err.contains(
+            "clients[0].scenario[0].scope.threads[0][0].connect.server_id: server 2 not found (2 servers)"
+        ), "{err}");
+        assert!(err.contains(
+            "clients[0].scenario[0].scope.threads[1][0].connect.server_connection_id: connection 1 not found on server 0"
+        ), "{err}");
+        assert!(err.contains("trace 2 not found (2 traces)"), "{err}");
+    }
+}
@@ -0,0 +1,254 @@
+// Copyright Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::{
+    check_requirements,
+    error::{OrchError, OrchResult},
+    execute, notify, poll,
+    run_spec::RunSpec,
+    run_status, shutdown, Args, OrchestratorConfig, STATE,
+};
+use clap::Args as ClapArgs;
+use futures::stream::{FuturesUnordered, StreamExt};
+use serde_json::{json, Value};
+use std::{
+    future::Future,
+    pin::Pin,
+    time::{Duration, Instant, SystemTime},
+};
+use tracing::{info, warn};
+
+// How long a receive waits for a message before returning empty handed
+const WAIT_TIME_SECONDS: i32 = 20;
+
+#[derive(ClapArgs, Debug)]
+pub struct ServeArgs {
+    /// The SQS queue to receive run spec messages from. ex:
+    /// https://sqs.us-west-2.amazonaws.com/123456789012/netbench
+    #[arg(long)]
+    queue: String,
+
+    /// The SQS queue to send the status of each run to
+    #[arg(long)]
+    status_queue: Option<String>,
+
+    /// Max runs executed at the same time
+    #[arg(long, default_value_t = 1, value_parser = clap::value_parser!(u16).range(1..=8))]
+    concurrency: u16,
+}
+
+/// A run spec message received from the queue.
+#[derive(Clone, Debug, PartialEq)]
+struct Message {
+    message_id: String,
+    receipt_handle: String,
+    body: String,
+}
+
+type Run<'a> = Pin<Box<dyn Future<Output = (String, notify::RunStatus)> + 'a>>;
+type Receive<'a> = Pin<Box<dyn Future<Output = OrchResult<Vec<Message>>> + 'a>>;
+
+impl ServeArgs {
+    /// Long-poll the queue for run specs and execute them, at most
+    /// `concurrency` at a time, until interrupted.
+    ///
+    /// A message is deleted from the queue once its run is accepted, so a run
+    /// is executed at most once even if it outlives the queue's visibility
+    /// timeout.
+    pub async fn run(&self, args: &Args, aws_config: &aws_types::SdkConfig) -> OrchResult<()> {
+        let config = OrchestratorConfig::load(args.config.as_deref())?;
+        poll::configure(config.polling);
+        shutdown::install();
+        let sqs_client = aws_sdk_sqs::Client::new(aws_config);
+        info!("Serving run specs from {}", self.queue);
+
+        let mut in_flight: FuturesUnordered<Run> = FuturesUnordered::new();
+        let mut receiving: Option<Receive> = None;
+        loop {
+            let capacity = self.concurrency as usize - in_flight.len();
+            if receiving.is_none() && capacity > 0 && !shutdown::is_interrupted() {
+                receiving = Some(Box::pin(receive(&sqs_client, &self.queue, capacity)));
+            }
+            if receiving.is_none() && in_flight.is_empty() {
+                return Err(OrchError::Interrupted);
+            }
+
+            tokio::select! {
+                messages = async { receiving.as_mut().expect("receiving").await }, if receiving.is_some() => {
+                    receiving = None;
+                    let messages = match messages {
+                        Ok(messages) => messages,
+                        Err(err) => {
+                            warn!("Failed to receive from {}: {}", self.queue, err);
+                            tokio::time::sleep(Duration::from_secs(30)).await;
+                            continue;
+                        }
+                    };
+                    for message in messages {
+                        if let Some(run) = self.accept(&sqs_client, message, args, &config, aws_config).await {
+                            in_flight.push(run);
+                        }
+                    }
+                }
+                Some((message_id, status)) = in_flight.next(), if !in_flight.is_empty() => {
+                    info!("{}", status.summary());
+                    notify::notify(&config.notifications, &status, aws_config).await;
+                    let mut body = status.to_json();
+                    body["message_id"] = json!(message_id);
+                    self.send_status(&sqs_client, body).await;
+                }
+                _ = shutdown::interrupted(), if receiving.is_some() => {
+                    // stop receiving and let the runs clean up their hosts
+                    receiving = None;
+                }
+            }
+        }
+    }
+
+    /// Delete the message from the queue and start its run, or reject it if
+    /// the run spec is invalid.
+    async fn accept<'a>(
+        &'a self,
+        sqs_client: &aws_sdk_sqs::Client,
+        message: Message,
+        args: &'a Args,
+        config: &'a OrchestratorConfig,
+        aws_config: &'a aws_types::SdkConfig,
+    ) -> Option<Run<'a>> {
+        if let Err(err) = sqs_client
+            .delete_message()
+            .queue_url(&self.queue)
+            .receipt_handle(&message.receipt_handle)
+            .send()
+            .await
+        {
+            // the message is received again once its visibility timeout expires
+            warn!("Failed to delete message {}: {}", message.message_id, err);
+            return None;
+        }
+
+        let source = format!("message {}", message.message_id);
+        let run_spec = match RunSpec::parse(&message.body, &source) {
+            Ok(run_spec) => run_spec,
+            Err(err) => {
+                warn!("Rejected {}: {}", source, err);
+                self.send_status(
+                    sqs_client,
+                    json!({
+                        "message_id": message.message_id,
+                        "status": "rejected",
+                        "error": err.to_string(),
+                    }),
+                )
+                .await;
+                return None;
+            }
+        };
+        let unique_id = run_unique_id(&message.message_id);
+        info!("Accepted {} as run {}", source, unique_id);
+        self.send_status(
+            sqs_client,
+            json!({
+                "message_id": message.message_id,
+                "unique_id": unique_id,
+                "status": "started",
+            }),
+        )
+        .await;
+
+        Some(Box::pin(async move {
+            let start = Instant::now();
+            let result = async {
+                let groups = check_requirements(args, Some(&run_spec), config, aws_config).await?;
+                execute(&unique_id, args, true, config, &groups, aws_config).await
+            }
+            .await;
+            let status = run_status(&unique_id, true, &result, start.elapsed());
+            (message.message_id, status)
+        }))
+    }
+
+    async fn send_status(&self, sqs_client: &aws_sdk_sqs::Client, body: Value) {
+        let Some(status_queue) = &self.status_queue else {
+            return;
+        };
+        if let Err(err) = sqs_client
+            .send_message()
+            .queue_url(status_queue)
+            .message_body(body.to_string())
+            .send()
+            .await
+        {
+            warn!("Failed to send the status to {}: {}", status_queue, err);
+        }
+    }
+}
+
+/// Runs accepted in the same second are told apart by their message id.
+fn run_unique_id(message_id: &str) -> String {
+    format!(
+        "{}-{}-{}",
+        humantime::format_rfc3339_seconds(SystemTime::now()),
+        STATE.version,
+        message_id.chars().take(8).collect::<String>()
+    )
+}
+
+async fn receive(
+    sqs_client: &aws_sdk_sqs::Client,
+    queue: &str,
+    max_messages: usize,
+) -> OrchResult<Vec<Message>> {
+    let output = sqs_client
+        .receive_message()
+        .queue_url(queue)
+        .max_number_of_messages(max_messages.min(10) as i32)
+        .wait_time_seconds(WAIT_TIME_SECONDS)
+        .send()
+        .await
+        .map_err(|err| OrchError::Init {
+            dbg: format!("Failed to receive from {}: {}", queue, err),
+        })?;
+    Ok(output
+        .messages()
+        .unwrap_or_default()
+        .iter()
+        .filter_map(to_message)
+        .collect())
+}
+
+/// A message missing any of its fields is skipped.
+fn to_message(message: &aws_sdk_sqs::types::Message) -> Option<Message> {
+    Some(Message {
+        message_id: message.message_id()?.to_string(),
+        receipt_handle: message.receipt_handle()?.to_string(),
+        body: message.body()?.to_string(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn receive_messages() {
+        let message = aws_sdk_sqs::types::Message::builder()
+            .message_id("0a1b2c3d-4e5f")
+            .receipt_handle("AQEB")
+            .body(r#"{"drivers": ["tcp"], "scenarios": ["scripts/request_response.json"]}"#)
+            .build();
+        let message = to_message(&message).unwrap();
+        assert_eq!(message.message_id, "0a1b2c3d-4e5f");
+        let run_spec = RunSpec::parse(&message.body, "message").unwrap();
+        assert_eq!(run_spec.drivers, vec!["tcp".to_string()]);
+        assert!(RunSpec::parse(r#"{"drivers": [], "scenarios": []}"#, "message").is_err());
+
+        let missing_body = aws_sdk_sqs::types::Message::builder()
+            .message_id("missing the body")
+            .receipt_handle("AQEC")
+            .build();
+        assert_eq!(to_message(&missing_body), None);
+
+        assert!(run_unique_id("0a1b2c3d-4e5f").ends_with("-0a1b2c3d"));
+    }
+}
@@ -0,0 +1,209 @@
+// Copyright Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::{
+    error::{OrchError, OrchResult},
+    s3_utils::{download_object, list_objects, upload_object},
+    state::STATE,
+};
+use aws_sdk_s3::primitives::ByteStream;
+use bytes::Bytes;
+use clap::Args;
+use core::{future::Future, time::Duration};
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    OnceLock,
+};
+use tokio::sync::Notify;
+use tracing::warn;
+
+/// The exit code of an interrupted run (128 + SIGINT).
+pub const EXIT_CODE: i32 = 130;
+
+// How long each stage of the shutdown is given before moving on to the next
+const STAGE_TIMEOUT: Duration = Duration::from_secs(60);
+// How often a run checks whether it was cancelled with `cancel`
+const CANCEL_POLL_INTERVAL: Duration = Duration::from_secs(10);
+
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+// Wakes the tasks waiting for the run to be interrupted
+fn interrupted_notify() -> &'static Notify {
+    static NOTIFY: OnceLock<Notify> = OnceLock::new();
+    NOTIFY.get_or_init(Notify::new)
+}
+
+/// Handle Ctrl-C by flagging the run as interrupted so that it can stop the
+/// workers, upload the partial results and delete the hosts.
+///
+/// A second Ctrl-C exits immediately, leaving the hosts running. Must be
+/// called from within the tokio runtime.
+pub fn install() {
+    static INSTALLED: AtomicBool = AtomicBool::new(false);
+    if INSTALLED.swap(true, Ordering::SeqCst) {
+        return;
+    }
+    tokio::spawn(async {
+        while tokio::signal::ctrl_c().await.is_ok() {
+            if is_interrupted() {
+                std::process::exit(EXIT_CODE);
+            }
+            interrupt();
+        }
+    });
+}
+
+fn interrupt() {
+    INTERRUPTED.store(true, Ordering::SeqCst);
+    interrupted_notify().notify_waiters();
+}
+
+pub fn is_interrupted() -> bool {
+    INTERRUPTED.load(Ordering::SeqCst)
+}
+
+/// Complete once the run is interrupted.
+pub async fn interrupted() {
+    // registered before checking the flag so that an interrupt isn't missed
+    let notified = interrupted_notify().notified();
+    if is_interrupted() {
+        return;
+    }
+    notified.await;
+}
+
+/// The marker written by `cancel`, which holds the reason for the cancellation.
+fn cancel_key(unique_id: &str) -> String {
+    format!("{unique_id}/cancel")
+}
+
+/// Flag the run as interrupted, as if by Ctrl-C, once `cancel` writes the
+/// cancellation marker of any of `unique_ids`. Completes once the run is
+/// interrupted.
+pub async fn watch_cancel(s3_client: aws_sdk_s3::Client, unique_ids: Vec<String>) {
+    while !is_interrupted() {
+        tokio::time::sleep(CANCEL_POLL_INTERVAL).await;
+        for unique_id in unique_ids.iter() {
+            let Ok(marker) =
+                download_object(&s3_client, STATE.s3_log_bucket, &cancel_key(unique_id)).await
+            else {
+                continue;
+            };
+            let reason = match marker.body.collect().await {
+                Ok(reason) => String::from_utf8_lossy(&reason.into_bytes()).to_string(),
+                Err(_) => String::new(),
+            };
+            warn!("Run {} was cancelled: {}", unique_id, reason.trim());
+            interrupt();
+            return;
+        }
+    }
+}
+
+#[derive(Args, Debug)]
+pub struct CancelArgs {
+    /// The unique_id of the run, or of a group of a run spec. ex: 2023-10-11T17:05:09Z-v2.0.0
+    #[arg(long)]
+    unique_id: String,
+    /// Recorded in the run's log
+    #[arg(long, default_value = "cancelled with the cancel command")]
+    reason: String,
+}
+
+impl CancelArgs {
+    /// Request a running orchestrator to stop its run as if interrupted with
+    /// Ctrl-C: the workers are stopped, the partial results uploaded and the
+    /// hosts deleted.
+    pub async fn run(&self, aws_config: &aws_types::SdkConfig) -> OrchResult<()> {
+        let s3_client = aws_sdk_s3::Client::new(aws_config);
+        if list_objects(
+            &s3_client,
+            STATE.s3_log_bucket,
+            &format!("{}/", self.unique_id),
+        )
+        .await?
+        .is_empty()
+        {
+            return Err(OrchError::Init {
+                dbg: format!(
+                    "Run {} not found in {}",
+                    self.unique_id, STATE.s3_log_bucket
+                ),
+            });
+        }
+        let key = cancel_key(&self.unique_id);
+        upload_object(
+            &s3_client,
+            STATE.s3_log_bucket,
+            ByteStream::from(Bytes::from(self.reason.clone())),
+            &key,
+        )
+        .await
+        .map_err(|err| OrchError::S3 {
+            dbg: format!("Failed to write {}: {}", key, err),
+        })?;
+        println!(
+            "Requested the cancellation of run {}. The run stops within {}s",
+            self.unique_id,
+            CANCEL_POLL_INTERVAL.as_secs()
+        );
+        Ok(())
+    }
+}
+
+/// Run a stage of the shutdown, logging rather than returning its failure so
+/// that the remaining stages still run.
+pub async fn stage<T, E: std::fmt::Display>(
+    name: &str,
+    stage: impl Future<Output = Result<T, E>>,
+) -> Option<T> {
+    run_stage("Interrupted", name, stage).await
+}
+
+/// Run a stage of the abort of a scenario, like a stage of the shutdown.
+pub async fn abort_stage<T, E: std::fmt::Display>(
+    name: &str,
+    stage: impl Future<Output = Result<T, E>>,
+) -> Option<T> {
+    run_stage("Aborted", name, stage).await
+}
+
+async fn run_stage<T, E: std::fmt::Display>(
+    reason: &str,
+    name: &str,
+    stage: impl Future<Output = Result<T, E>>,
+) -> Option<T> {
+    warn!("{}: {}", reason, name);
+    match tokio::time::timeout(STAGE_TIMEOUT, stage).await {
+        Ok(Ok(output)) => Some(output),
+        Ok(Err(err)) => {
+            warn!("{}: failed to {}. {}", reason, name, err);
+            None
+        }
+        Err(_elapsed) => {
+            warn!("{}: timed out trying to {}", reason, name);
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test(start_paused = true)]
+    async fn shutdown_stage() {
+        let ok = stage("succeed", async { Ok::<u8, String>(1) }).await;
+        assert_eq!(ok, Some(1));
+
+        let failed = stage("fail", async { Err::<u8, String>("err".to_string()) }).await;
+        assert_eq!(failed, None);
+
+        let hung = stage("hang", async {
+            std::future::pending::<()>().await;
+            Ok::<u8, String>(1)
+        })
+        .await;
+        assert_eq!(hung, None);
+    }
+}
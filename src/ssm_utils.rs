@@ -2,52 +2,278 @@
 // SPDX-License-Identifier: Apache-2.0
 
 use crate::{
+    config::{Collector, RussulaTimeouts},
+    dashboard::timeline,
+    ec2_utils::{EndpointType, HostOs},
     error::{OrchError, OrchResult},
+    poll::Backoff,
+    russula::{netbench::Profiler, FailurePolicy, Transport},
     state::STATE,
 };
 use aws_sdk_ssm::{
     operation::send_command::SendCommandOutput,
-    types::{CloudWatchOutputConfig, CommandInvocationStatus},
+    types::{CloudWatchOutputConfig, Command, CommandInvocationStatus},
 };
-use core::{task::Poll, time::Duration};
+use core::{str::FromStr, task::Poll, time::Duration};
+use std::{collections::HashMap, net::Ipv4Addr};
 use tracing::{error, trace};
 
+pub mod build_info;
+pub mod calibration;
 pub mod client;
+pub mod clock_sync;
+pub mod cloud_watch;
 pub mod common;
+pub mod document;
+pub mod iperf3;
 mod netbench_driver;
+pub mod network_check;
+pub mod port_forward;
+pub mod router;
+pub mod secrets;
 pub mod server;
+pub mod smoke;
+pub mod sysctl;
+pub mod tuning;
+pub mod windows;
 
 pub use netbench_driver::*;
 
+/// Options passed to the russula worker on each host.
+#[derive(Clone, Debug)]
+pub struct WorkerOptions {
+    pub transport: Transport,
+    pub sys_metrics_interval: Duration,
+    pub profile: Option<Profiler>,
+    pub capture_pcap: Option<PcapHosts>,
+    pub collector: Collector,
+    // Shipped to the Workers with the run parameters
+    pub driver_instances: u16,
+    // Shipped to the Workers with the run parameters. The Workers discard the
+    // results of a warm-up run
+    pub warmup: bool,
+    // Shipped to the client Workers, which stop their drivers once it elapses
+    pub duration: Option<Duration>,
+    // Shipped to the Linux Workers, which upload their output in chunks at this
+    // interval
+    pub stream_interval: Option<Duration>,
+    // The job's folder under streams/, set for each job which is streamed
+    pub stream_to: Option<String>,
+    // How the Coordinators handle failed Workers
+    pub failure_policy: FailurePolicy,
+    // The client Workers are run with PowerShell on Windows clients
+    pub client_os: HostOs,
+    // The Workers dial the Coordinators at this ip and register, rather than
+    // listening on their russula port
+    pub coordinator_ip: Option<Ipv4Addr>,
+    // How long the Coordinators wait for each phase before aborting
+    pub russula_timeouts: RussulaTimeouts,
+}
+
+impl WorkerOptions {
+    /// The russula_cli args with which a Linux Worker listens on its russula
+    /// port, or registers with its Coordinator as the host's private ip.
+    fn russula_addr_args(&self, endpoint_type: &EndpointType) -> String {
+        let port = endpoint_type.russula_port();
+        match self.coordinator_ip {
+            Some(coordinator_ip) => format!(
+                "--russula-addr $(hostname -I | awk '{{print $1}}'):{port} --register {coordinator_ip}:{port}"
+            ),
+            None => format!("--russula-addr 0.0.0.0:{port}"),
+        }
+    }
+
+    /// Args for the `netbench-*-worker` russula_cli subcommands.
+    fn netbench_worker_args(&self, host_group: &str) -> String {
+        let mut args = format!(
+            "--sys-metrics-interval {}",
+            humantime::format_duration(self.sys_metrics_interval)
+        );
+        if let Some(profile) = self.profile {
+            args.push_str(&format!(" --profile {profile}"));
+        }
+        if self
+            .capture_pcap
+            .is_some_and(|pcap_hosts| pcap_hosts.includes(host_group))
+        {
+            args.push_str(" --capture-pcap");
+        }
+        if let Some(interval) = self.collector.interval {
+            args.push_str(&format!(
+                " --collector-interval {}",
+                humantime::format_duration(interval)
+            ));
+        }
+        if self.collector.disable_bpf {
+            args.push_str(" --collector-disable-bpf");
+        }
+        args
+    }
+}
+
+/// The host groups on which to capture pcaps.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PcapHosts {
+    All,
+    Server,
+    Client,
+}
+
+impl PcapHosts {
+    fn includes(&self, host_group: &str) -> bool {
+        match self {
+            PcapHosts::All => true,
+            PcapHosts::Server => host_group == "server",
+            PcapHosts::Client => host_group == "client",
+        }
+    }
+}
+
+impl FromStr for PcapHosts {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "all" => Ok(PcapHosts::All),
+            "server" => Ok(PcapHosts::Server),
+            "client" => Ok(PcapHosts::Client),
+            _ => Err(format!(
+                "unsupported pcap hosts: {}. expected all, server or client",
+                s
+            )),
+        }
+    }
+}
+
+/// Commands which upload the output of the worker sidecars (system metrics and
+/// profiles) for `host_group`.
+///
+/// The output is kept separate from the results so that it's not parsed by
+/// `s2n-netbench report-tree`.
+fn upload_sidecar_cmds(
+    host_group: &str,
+    unique_id: &str,
+    result_key: &str,
+    driver_name: &str,
+) -> Vec<String> {
+    let s3_path = STATE.s3_path(unique_id);
+    let scenario = result_key;
+    let flamegraph_path = STATE.host_flamegraph_path();
+    vec![
+        format!("for sys_metrics in {host_group}-*.sysmetrics.csv; do [ -e $sys_metrics ] || continue; aws s3 mv $sys_metrics {s3_path}/sysmetrics/{scenario}/{driver_name}/; done"),
+        // convert the perf profiles to flamegraphs on the host
+        format!("for profile in {host_group}-*.perf.data; do [ -e $profile ] || continue; perf script -i $profile | {flamegraph_path}/stackcollapse-perf.pl | {flamegraph_path}/flamegraph.pl > ${{profile%.perf.data}}.svg; rm $profile; done"),
+        format!("for flamegraph in {host_group}-*.svg; do [ -e $flamegraph ] || continue; aws s3 mv $flamegraph {s3_path}/flamegraph/{scenario}/{driver_name}/; done"),
+        // tcpdump appends the rotation index to the pcap file name
+        format!("for pcap in {host_group}-*.pcap[0-9]*; do [ -e $pcap ] || continue; gzip $pcap; aws s3 mv $pcap.gz {s3_path}/pcap/{scenario}/{driver_name}/; done"),
+    ]
+}
+
 pub enum Step {
+    HealthCheck,
+    ClockSync,
+    NetworkCheck,
+    Tuning,
+    Sysctl,
+    RevertSysctl,
+    Calibration,
+    Iperf3,
+    ConfigureLogs,
     Configure,
     BuildDriver(String),
     BuildRussula,
+    Smoke,
     RunRussula,
     RunNetbench,
     UploadNetbenchRawData,
+    CheckpointNetbenchData,
+    KillWorkers,
+    ExtendLease,
+    CopyScenarios,
+    ApplyImpairment,
+    RemoveImpairment,
+    ConfigureRoutes,
+    RemoveRoutes,
+    DeliverSecrets,
+    ShredSecrets,
+    InstallCerts,
 }
 
 impl Step {
-    fn as_str(&self) -> &str {
+    pub(crate) fn as_str(&self) -> &str {
         match self {
+            Step::HealthCheck => "health_check",
+            Step::ClockSync => "clock_sync",
+            Step::NetworkCheck => "network_check",
+            Step::Tuning => "tuning",
+            Step::Sysctl => "sysctl",
+            Step::RevertSysctl => "revert_sysctl",
+            Step::Calibration => "calibration",
+            Step::Iperf3 => "iperf3",
+            Step::ConfigureLogs => "configure_logs",
             Step::Configure => "configure",
             Step::BuildDriver(_driver_name) => "build_driver",
             Step::BuildRussula => "build_russula",
+            Step::Smoke => "smoke",
             Step::RunRussula => "run_russula",
             Step::RunNetbench => "run_netbench",
             Step::UploadNetbenchRawData => "upload_netbench_raw_data",
+            Step::CheckpointNetbenchData => "checkpoint_netbench_data",
+            Step::KillWorkers => "kill_workers",
+            Step::ExtendLease => "extend_lease",
+            Step::CopyScenarios => "copy_scenarios",
+            Step::ApplyImpairment => "apply_impairment",
+            Step::RemoveImpairment => "remove_impairment",
+            Step::ConfigureRoutes => "configure_routes",
+            Step::RemoveRoutes => "remove_routes",
+            Step::DeliverSecrets => "deliver_secrets",
+            Step::ShredSecrets => "shred_secrets",
+            Step::InstallCerts => "install_certs",
+        }
+    }
+
+    /// The marker left on a host once it completed the step. Steps with a
+    /// marker are skipped on hosts which already completed them.
+    ///
+    /// A driver's build is identified by its task detail so that a changed
+    /// driver is rebuilt.
+    fn completion_marker(&self) -> Option<String> {
+        match self {
+            Step::Configure => Some(format!("done_{}___", self.as_str())),
+            Step::BuildDriver(detail) => Some(format!("done_{}_{}___", self.as_str(), detail)),
+            _ => None,
         }
     }
 
     fn task_detail(&self) -> Option<&str> {
         match self {
+            Step::HealthCheck => None,
+            Step::ClockSync => None,
+            Step::NetworkCheck => None,
+            Step::Tuning => None,
+            Step::Sysctl => None,
+            Step::RevertSysctl => None,
+            Step::Calibration => None,
+            Step::Iperf3 => None,
+            Step::ConfigureLogs => None,
             Step::Configure => None,
             Step::BuildDriver(driver_name) => Some(driver_name),
             Step::BuildRussula => None,
+            Step::Smoke => None,
             Step::RunRussula => None,
             Step::RunNetbench => None,
             Step::UploadNetbenchRawData => None,
+            Step::CheckpointNetbenchData => None,
+            Step::KillWorkers => None,
+            Step::ExtendLease => None,
+            Step::CopyScenarios => None,
+            Step::ApplyImpairment => None,
+            Step::RemoveImpairment => None,
+            Step::ConfigureRoutes => None,
+            Step::RemoveRoutes => None,
+            Step::DeliverSecrets => None,
+            Step::ShredSecrets => None,
+            Step::InstallCerts => None,
         }
     }
 }
@@ -60,75 +286,102 @@ pub async fn send_command(
     ssm_client: &aws_sdk_ssm::Client,
     ids: Vec<String>,
     commands: Vec<String>,
-) -> Option<SendCommandOutput> {
-    let command = {
-        // SSM doesnt have a concept of order. However, we would still
-        // like to execute commands in parallel. To achieve this we
-        // create files based on the [`Step`] name and poll till the
-        // previous steps has finished.
-        //
-        // For example, the Step::RunRussula step waits for the
-        // Step::BuildRussula and Step::BuildDriver steps to finish.
-        let mut assemble_command = Vec::new();
-
-        // Insert at beginning of user provided commands
-        //
-        // FIXME: use `for entry in ./start_build_driver*; do echo "$entry"; done`
-        // this doesnt work if more than one task share the same step. Multiple BuildDriver
-        // for example. Instead wait for ALL sub-tasks to finish: `for {}_*_start; wait `.
-        // This is not an issue now since the driver build and russula run are not run in
-        // parallel.
-        for step in wait_steps {
-            // wait for previous steps
-            assemble_command.push(format!(
-                "cd /home/ec2-user; until [ -f fin_{}___ ]; do sleep 5; done",
-                step.as_str()
-            ));
-        }
-        // indicate that this step has started
-        assemble_command.push(format!(
-            "cd /home/ec2-user; touch start_{}___",
-            step.as_str()
-        ));
-        if let Some(detail) = step.task_detail() {
-            assemble_command.push(format!(
-                "cd /home/ec2-user; touch start_{}_{}___",
-                step.as_str(),
-                detail
-            ));
-        }
-        assemble_command.extend(commands);
-
-        // Insert at end of user provided commands
-        // indicate that this step has finished.
-        assemble_command.extend(vec![
-            "cd /home/ec2-user".to_string(),
-            format!("mv start_{}___ fin_{}___", step.as_str(), step.as_str()),
-        ]);
-        if let Some(detail) = step.task_detail() {
-            assemble_command.push(format!(
-                "cd /home/ec2-user; mv start_{}_{}___ fin_{}_{}___",
-                step.as_str(),
-                detail,
-                step.as_str(),
-                detail
-            ));
-        }
+) -> OrchResult<SendCommandOutput> {
+    let command = assemble_command(wait_steps, &step, commands);
+    trace!("{} {:?}", endpoint, command);
 
-        trace!("{} {:?}", endpoint, assemble_command);
-        assemble_command
+    let invocation = Invocation {
+        document_name: "AWS-RunShellScript",
+        document_version: "$LATEST".to_string(),
+        parameters: HashMap::from([("commands".to_string(), command)]),
     };
+    send_invocation(
+        step.as_str(),
+        endpoint,
+        comment,
+        ssm_client,
+        ids,
+        invocation,
+    )
+    .await
+}
+
+// The maximum number of instances a single SSM command can target
+pub(crate) const MAX_COMMAND_INSTANCES: usize = 50;
+// Separates the ids of the commands sent to each batch of instances
+const COMMAND_ID_SEPARATOR: char = ',';
+
+/// The SSM document, and its parameters, run by a command.
+pub(crate) struct Invocation {
+    pub document_name: &'static str,
+    pub document_version: String,
+    pub parameters: HashMap<String, Vec<String>>,
+}
+
+/// Send the invocation to `ids`, in batches of [`MAX_COMMAND_INSTANCES`].
+///
+/// The commands sent to each batch are returned as a single command, whose
+/// command_id joins the ids of the batches. The functions taking a command_id
+/// handle either.
+pub(crate) async fn send_invocation(
+    step: &str,
+    endpoint: &str,
+    comment: &str,
+    ssm_client: &aws_sdk_ssm::Client,
+    ids: Vec<String>,
+    invocation: Invocation,
+) -> OrchResult<SendCommandOutput> {
+    let sent_command = if ids.len() <= MAX_COMMAND_INSTANCES {
+        send_batch(step, endpoint, comment, ssm_client, &ids, &invocation).await?
+    } else {
+        let mut command_ids = Vec::new();
+        for batch in ids.chunks(MAX_COMMAND_INSTANCES) {
+            let sent_command =
+                send_batch(step, endpoint, comment, ssm_client, batch, &invocation).await?;
+            command_ids.push(command_id(&sent_command)?.to_string());
+        }
+        SendCommandOutput::builder()
+            .command(
+                Command::builder()
+                    .command_id(command_ids.join(&COMMAND_ID_SEPARATOR.to_string()))
+                    .comment(comment)
+                    .document_name(invocation.document_name)
+                    .set_instance_ids(Some(ids))
+                    .build(),
+            )
+            .build()
+    };
+    timeline::record(timeline::Event::StepStarted {
+        host_group: endpoint.to_string(),
+        step: comment.to_string(),
+    });
+    Ok(sent_command)
+}
 
+/// The ids of the commands sent to each batch of instances, see
+/// [`send_invocation`].
+pub(crate) fn batch_command_ids(command_id: &str) -> impl Iterator<Item = &str> {
+    command_id.split(COMMAND_ID_SEPARATOR)
+}
+
+async fn send_batch(
+    step: &str,
+    endpoint: &str,
+    comment: &str,
+    ssm_client: &aws_sdk_ssm::Client,
+    ids: &[String],
+    invocation: &Invocation,
+) -> OrchResult<SendCommandOutput> {
     let mut remaining_try_count: u32 = 10;
     loop {
         match ssm_client
             .send_command()
             .comment(comment)
             // .instance_ids(ids)
-            .set_instance_ids(Some(ids.clone()))
-            .document_name("AWS-RunShellScript")
-            .document_version("$LATEST")
-            .parameters("commands", command.clone())
+            .set_instance_ids(Some(ids.to_vec()))
+            .document_name(invocation.document_name)
+            .document_version(&invocation.document_version)
+            .set_parameters(Some(invocation.parameters.clone()))
             .cloud_watch_output_config(
                 CloudWatchOutputConfig::builder()
                     .cloud_watch_log_group_name(STATE.cloud_watch_group)
@@ -139,9 +392,7 @@ pub async fn send_command(
             .await
             .map_err(|x| format!("{:#?}", x))
         {
-            Ok(sent_command) => {
-                break Some(sent_command);
-            }
+            Ok(sent_command) => break Ok(sent_command),
             Err(err) => {
                 if remaining_try_count > 0 {
                     trace!("Send command failed: remaining: {remaining_try_count} err: {err}",);
@@ -150,28 +401,216 @@ pub async fn send_command(
                     continue;
                 } else {
                     error!("Send command failed: err: {err}",);
-                    return None;
+                    return Err(OrchError::Ssm {
+                        dbg: format!(
+                            "Failed to send command. step: {} endpoint: {} instances: {:?} err: {}",
+                            step, endpoint, ids, err
+                        ),
+                    });
                 }
             }
         };
     }
 }
 
+/// Wrap the commands of a step with the markers used to order the steps on a
+/// host, and with the guard which skips a step the host already completed.
+fn assemble_command(wait_steps: Vec<Step>, step: &Step, commands: Vec<String>) -> Vec<String> {
+    // SSM doesnt have a concept of order. However, we would still
+    // like to execute commands in parallel. To achieve this we
+    // create files based on the [`Step`] name and poll till the
+    // previous steps has finished.
+    //
+    // For example, the Step::RunRussula step waits for the
+    // Step::BuildRussula and Step::BuildDriver steps to finish.
+    let mut assemble_command = Vec::new();
+
+    // Insert at beginning of user provided commands
+    //
+    // FIXME: use `for entry in ./start_build_driver*; do echo "$entry"; done`
+    // this doesnt work if more than one task share the same step. Multiple BuildDriver
+    // for example. Instead wait for ALL sub-tasks to finish: `for {}_*_start; wait `.
+    // This is not an issue now since the driver build and russula run are not run in
+    // parallel.
+    for step in wait_steps {
+        // wait for previous steps
+        assemble_command.push(format!(
+            "cd /home/ec2-user; until [ -f fin_{}___ ]; do sleep 5; done",
+            step.as_str()
+        ));
+    }
+    // skip a step the host already completed, ex: when retrying a failed run on
+    // the same hosts. The step is still marked finished for the steps waiting on it
+    if let Some(marker) = step.completion_marker() {
+        let mut fin = format!("touch fin_{}___", step.as_str());
+        if let Some(detail) = step.task_detail() {
+            fin.push_str(&format!("; touch fin_{}_{}___", step.as_str(), detail));
+        }
+        assemble_command.push(format!(
+            "cd /home/ec2-user; if [ -f {marker} ]; then echo skip {}: already completed; {fin}; exit 0; fi",
+            step.as_str()
+        ));
+    }
+    // indicate that this step has started
+    //
+    // A step can run multiple times (RunRussula runs once per scenario) so
+    // remove the marker left behind by a previous run of the step.
+    assemble_command.push(format!(
+        "cd /home/ec2-user; rm -f fin_{}___; touch start_{}___",
+        step.as_str(),
+        step.as_str()
+    ));
+    if let Some(detail) = step.task_detail() {
+        assemble_command.push(format!(
+            "cd /home/ec2-user; touch start_{}_{}___",
+            step.as_str(),
+            detail
+        ));
+    }
+    // an idempotent step stops at its first failed command, so that it isn't
+    // marked completed and is retried on the host
+    if step.completion_marker().is_some() {
+        assemble_command.push("set -e".to_string());
+    }
+    assemble_command.extend(commands);
+
+    // Insert at end of user provided commands
+    // indicate that this step has finished.
+    assemble_command.extend(vec![
+        "cd /home/ec2-user".to_string(),
+        format!("mv start_{}___ fin_{}___", step.as_str(), step.as_str()),
+    ]);
+    if let Some(detail) = step.task_detail() {
+        assemble_command.push(format!(
+            "cd /home/ec2-user; mv start_{}_{}___ fin_{}_{}___",
+            step.as_str(),
+            detail,
+            step.as_str(),
+            detail
+        ));
+    }
+    if let Some(marker) = step.completion_marker() {
+        assemble_command.push(format!("cd /home/ec2-user; touch {marker}"));
+    }
+
+    assemble_command
+}
+
 pub(crate) async fn wait_for_ssm_results(
     endpoint: &str,
     ssm_client: &aws_sdk_ssm::Client,
     command_id: &str,
-) -> bool {
-    loop {
-        match poll_ssm_results(endpoint, ssm_client, command_id).await {
-            Ok(Poll::Ready(_)) => break true,
-            Ok(Poll::Pending) => {
-                tokio::time::sleep(STATE.poll_delay_ssm).await;
-                continue;
+) -> OrchResult<()> {
+    let mut backoff = Backoff::ssm();
+    while poll_ssm_results(endpoint, ssm_client, command_id)
+        .await?
+        .is_pending()
+    {
+        backoff.wait().await;
+    }
+    Ok(())
+}
+
+/// The command_id of a SSM command sent via [`send_command`].
+pub fn command_id(cmd: &SendCommandOutput) -> OrchResult<&str> {
+    cmd.command()
+        .and_then(|command| command.command_id())
+        .ok_or(OrchError::Ssm {
+            dbg: format!("Missing command_id in SSM response: {:?}", cmd),
+        })
+}
+
+/// The stdout of a SSM command on a single instance.
+pub(crate) async fn command_output(
+    ssm_client: &aws_sdk_ssm::Client,
+    step: &str,
+    command_id: &str,
+    instance_id: &str,
+) -> OrchResult<String> {
+    let mut errors = Vec::new();
+    // the instance is targeted by a single batch
+    for batch_id in batch_command_ids(command_id) {
+        match ssm_client
+            .get_command_invocation()
+            .command_id(batch_id)
+            .instance_id(instance_id)
+            .send()
+            .await
+        {
+            Ok(output) => {
+                return Ok(output
+                    .standard_output_content()
+                    .unwrap_or_default()
+                    .to_string())
             }
-            Err(_err) => break false,
+            Err(err) => errors.push(err.to_string()),
         }
     }
+    Err(OrchError::SsmCommand {
+        step: step.to_string(),
+        command_id: command_id.to_string(),
+        dbg: format!("Failed to get output for {}: {:?}", instance_id, errors),
+    })
+}
+
+/// The value of the first `key=value` line of a command's output.
+pub(crate) fn output_value<'a>(output: &'a str, key: &str) -> Option<&'a str> {
+    output
+        .lines()
+        .filter_map(|line| line.trim().split_once('='))
+        .find(|(k, _)| *k == key)
+        .map(|(_, value)| value)
+}
+
+/// The status of a SSM command on a single instance.
+#[derive(Clone, Debug)]
+pub struct InvocationStatus {
+    pub instance_id: String,
+    pub comment: String,
+    pub status: CommandInvocationStatus,
+}
+
+pub(crate) async fn list_invocation_status(
+    endpoint: &str,
+    ssm_client: &aws_sdk_ssm::Client,
+    command_id: &str,
+) -> OrchResult<Vec<InvocationStatus>> {
+    trace!("endpoint: {}  command_id {}", endpoint, command_id);
+    let mut invocations = Vec::new();
+    for batch_id in batch_command_ids(command_id) {
+        let mut next_token = None;
+        loop {
+            let output = ssm_client
+                .list_command_invocations()
+                .command_id(batch_id)
+                .set_next_token(next_token)
+                .send()
+                .await
+                .map_err(|err| OrchError::SsmCommand {
+                    step: endpoint.to_string(),
+                    command_id: batch_id.to_string(),
+                    dbg: format!("Failed to list command invocations: {}", err),
+                })?;
+            invocations.extend(
+                output
+                    .command_invocations()
+                    .unwrap_or_default()
+                    .iter()
+                    .filter_map(|invocation| {
+                        Some(InvocationStatus {
+                            instance_id: invocation.instance_id().unwrap_or("unknown").to_string(),
+                            comment: invocation.comment().unwrap_or_default().to_string(),
+                            status: invocation.status()?.clone(),
+                        })
+                    }),
+            );
+            next_token = output.next_token().map(String::from);
+            if next_token.is_none() {
+                break;
+            }
+        }
+    }
+    Ok(invocations)
 }
 
 pub(crate) async fn poll_ssm_results(
@@ -179,53 +618,208 @@ pub(crate) async fn poll_ssm_results(
     ssm_client: &aws_sdk_ssm::Client,
     command_id: &str,
 ) -> OrchResult<Poll<()>> {
-    let status_comment = ssm_client
-        .list_command_invocations()
-        .command_id(command_id)
-        .send()
-        .await
-        .unwrap()
-        .command_invocations()
-        .unwrap()
-        .iter()
-        .find_map(|command| {
-            let status = command.status().cloned();
-            let comment = command.comment().map(|s| s.to_string());
-            status.zip(comment)
-        });
-    let status = match status_comment {
-        Some((status, _comment)) => {
-            // debug!(
-            //     "endpoint: {} status: {:?}  comment {}",
-            //     endpoint, status, comment
-            // );
-
-            status
-        }
-        None => {
-            return Ok(Poll::Ready(()));
-        }
-    };
-    trace!("endpoint: {}  command_id {}", endpoint, command_id);
+    let invocations = list_invocation_status(endpoint, ssm_client, command_id).await?;
+    poll_invocations(command_id, &invocations)
+}
 
-    let status = match status {
-        CommandInvocationStatus::Cancelled
-        | CommandInvocationStatus::Cancelling
-        | CommandInvocationStatus::Failed
-        | CommandInvocationStatus::TimedOut => {
-            return Err(OrchError::Ssm {
-                dbg: "timeout".to_string(),
-            })
-        }
-        CommandInvocationStatus::Delayed
-        | CommandInvocationStatus::InProgress
-        | CommandInvocationStatus::Pending => Poll::Pending,
-        CommandInvocationStatus::Success => Poll::Ready(()),
-        _ => {
-            return Err(OrchError::Ssm {
-                dbg: "unhandled status".to_string(),
-            })
+/// Ready once the command has succeeded on all instances and an error if it
+/// failed on any instance.
+///
+/// Pending while the command has no invocations, since SSM lists the
+/// invocations of a command shortly after it's sent.
+pub(crate) fn poll_invocations(
+    command_id: &str,
+    invocations: &[InvocationStatus],
+) -> OrchResult<Poll<()>> {
+    if invocations.is_empty() {
+        return Ok(Poll::Pending);
+    }
+    let mut poll = Poll::Ready(());
+    for invocation in invocations {
+        let InvocationStatus {
+            instance_id,
+            comment,
+            status,
+        } = invocation;
+
+        match status {
+            CommandInvocationStatus::Cancelled
+            | CommandInvocationStatus::Cancelling
+            | CommandInvocationStatus::Failed
+            | CommandInvocationStatus::TimedOut => {
+                return Err(OrchError::SsmCommand {
+                    step: comment.to_string(),
+                    command_id: command_id.to_string(),
+                    dbg: format!("instance: {} status: {:?}", instance_id, status),
+                })
+            }
+            CommandInvocationStatus::Delayed
+            | CommandInvocationStatus::InProgress
+            | CommandInvocationStatus::Pending => poll = Poll::Pending,
+            CommandInvocationStatus::Success => (),
+            _ => {
+                return Err(OrchError::SsmCommand {
+                    step: comment.to_string(),
+                    command_id: command_id.to_string(),
+                    dbg: format!("instance: {} unhandled status: {:?}", instance_id, status),
+                })
+            }
+        };
+    }
+    Ok(poll)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn invocation(instance_id: &str, status: CommandInvocationStatus) -> InvocationStatus {
+        InvocationStatus {
+            instance_id: instance_id.to_string(),
+            comment: "configure_host_server".to_string(),
+            status,
         }
-    };
-    Ok(status)
+    }
+
+    #[test]
+    fn poll_invocation_status() {
+        let ready = [
+            invocation("i-1", CommandInvocationStatus::Success),
+            invocation("i-2", CommandInvocationStatus::Success),
+        ];
+        assert!(poll_invocations("cmd", &ready).unwrap().is_ready());
+
+        let pending = [
+            invocation("i-1", CommandInvocationStatus::Success),
+            invocation("i-2", CommandInvocationStatus::InProgress),
+        ];
+        assert!(poll_invocations("cmd", &pending).unwrap().is_pending());
+        // the invocations aren't listed yet
+        assert!(poll_invocations("cmd", &[]).unwrap().is_pending());
+
+        let failed = [
+            invocation("i-1", CommandInvocationStatus::InProgress),
+            invocation("i-2", CommandInvocationStatus::Failed),
+        ];
+        let err = poll_invocations("cmd", &failed).unwrap_err().to_string();
+        assert!(err.contains("i-2"), "{}", err);
+        assert!(err.contains("configure_host_server"), "{}", err);
+    }
+
+    #[test]
+    fn batched_command() {
+        let ids: Vec<String> = (0..120).map(|i| format!("i-{i}")).collect();
+        let batches: Vec<_> = ids.chunks(MAX_COMMAND_INSTANCES).collect();
+        assert_eq!(batches.len(), 3);
+        assert_eq!(batches[2].len(), 20);
+
+        assert_eq!(batch_command_ids("cmd-1").collect::<Vec<_>>(), ["cmd-1"]);
+        assert_eq!(
+            batch_command_ids("cmd-1,cmd-2,cmd-3").collect::<Vec<_>>(),
+            ["cmd-1", "cmd-2", "cmd-3"]
+        );
+        // a failed invocation in any batch fails the command
+        let invocations = [
+            invocation("i-1", CommandInvocationStatus::Success),
+            invocation("i-51", CommandInvocationStatus::Failed),
+        ];
+        assert!(poll_invocations("cmd-1,cmd-2", &invocations).is_err());
+    }
+
+    #[test]
+    fn completion_marker() {
+        let cmds = assemble_command(vec![], &Step::Configure, vec!["yum upgrade -y".to_string()]);
+        assert_eq!(
+            cmds[0],
+            "cd /home/ec2-user; if [ -f done_configure___ ]; then echo skip configure: already completed; touch fin_configure___; exit 0; fi"
+        );
+        assert_eq!(
+            cmds.last().unwrap(),
+            "cd /home/ec2-user; touch done_configure___"
+        );
+
+        let cmds = assemble_command(
+            vec![Step::Configure],
+            &Step::BuildDriver("tcp_0123".to_string()),
+            vec![],
+        );
+        assert!(cmds[1].contains("if [ -f done_build_driver_tcp_0123___ ]"));
+        assert!(cmds[1].contains("touch fin_build_driver___; touch fin_build_driver_tcp_0123___"));
+
+        // steps which aren't idempotent always run
+        let cmds = assemble_command(vec![], &Step::RunRussula, vec![]);
+        assert!(!cmds.iter().any(|cmd| cmd.contains("done_")));
+        assert!(!cmds.contains(&"set -e".to_string()));
+    }
+
+    #[test]
+    fn completion_marker_on_failure() {
+        // SSM runs the commands as a single script
+        let run = |home: &std::path::Path, commands: Vec<&str>| {
+            let commands = commands.into_iter().map(String::from).collect();
+            let script = assemble_command(vec![], &Step::Configure, commands)
+                .join("\n")
+                .replace("/home/ec2-user", home.to_str().unwrap());
+            std::process::Command::new("bash")
+                .arg("-c")
+                .arg(script)
+                .status()
+                .unwrap()
+                .success()
+        };
+        let home = tempdir::TempDir::new("marker").unwrap();
+
+        assert!(!run(home.path(), vec!["true", "false", "true"]));
+        assert!(!home.path().join("done_configure___").exists());
+        assert!(!home.path().join("fin_configure___").exists());
+
+        assert!(run(home.path(), vec!["true"]));
+        assert!(home.path().join("done_configure___").exists());
+        assert!(home.path().join("fin_configure___").exists());
+    }
+
+    #[test]
+    fn netbench_worker_args() {
+        let mut worker_opts = WorkerOptions {
+            transport: Transport::Tcp,
+            sys_metrics_interval: Duration::from_secs(1),
+            profile: None,
+            capture_pcap: None,
+            collector: Collector::default(),
+            driver_instances: 1,
+            warmup: false,
+            duration: None,
+            stream_interval: None,
+            stream_to: None,
+            failure_policy: FailurePolicy::FailFast,
+            client_os: HostOs::Linux,
+            coordinator_ip: None,
+            russula_timeouts: RussulaTimeouts::default(),
+        };
+        assert_eq!(
+            worker_opts.netbench_worker_args("server"),
+            "--sys-metrics-interval 1s"
+        );
+
+        worker_opts.profile = Some(Profiler::Perf);
+        worker_opts.capture_pcap = Some(PcapHosts::Client);
+        assert_eq!(
+            worker_opts.netbench_worker_args("server"),
+            "--sys-metrics-interval 1s --profile perf"
+        );
+        assert_eq!(
+            worker_opts.netbench_worker_args("client"),
+            "--sys-metrics-interval 1s --profile perf --capture-pcap"
+        );
+
+        worker_opts.profile = None;
+        worker_opts.collector = Collector {
+            interval: Some(Duration::from_millis(100)),
+            disable_bpf: true,
+        };
+        assert_eq!(
+            worker_opts.netbench_worker_args("server"),
+            "--sys-metrics-interval 1s --collector-interval 100ms --collector-disable-bpf"
+        );
+    }
 }
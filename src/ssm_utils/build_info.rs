@@ -0,0 +1,175 @@
+// Copyright Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+use super::NetbenchDriver;
+use crate::{
+    error::{OrchError, OrchResult},
+    s3_utils::{download_object, list_objects},
+    STATE,
+};
+use base64::{engine::general_purpose, Engine};
+use serde::{Deserialize, Serialize};
+
+// The run's folder holding a `<host_group>/<driver_name>.txt` file per driver build
+pub const BUILD_INFO_DIR: &str = "build_info";
+
+/// The code and toolchain a netbench driver was built with, recorded on the
+/// host after the build.
+///
+/// Recorded in the run's manifest and report so that the results can be traced
+/// to the exact version of the driver.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct BuildInfo {
+    pub host_group: String,
+    pub driver: String,
+    // The repository the driver was cloned from, or `local` for drivers built
+    // from a local source
+    pub git_remote: String,
+    // `unknown` for drivers built from a local source, which is uploaded
+    // without its .git folder
+    pub git_sha: String,
+    pub cargo: String,
+    pub rustc: String,
+    // The cargo build command, including any RUSTFLAGS
+    pub build_cmd: String,
+}
+
+impl BuildInfo {
+    /// The command which records the build info of a driver and uploads it to
+    /// the run's folder. Run after the driver's build commands.
+    pub fn record_cmd(host_group: &str, driver: &NetbenchDriver, unique_id: &str) -> String {
+        let build_cmd = driver
+            .ssm_build_cmd
+            .iter()
+            .find(|cmd| cmd.contains("cargo build"))
+            .map(String::as_str)
+            .unwrap_or_default();
+        // avoid quoting the build command, which can contain quotes
+        let build_cmd = general_purpose::STANDARD.encode(build_cmd);
+        let proj_path = format!("{}/{}", STATE.host_home_path, driver.proj_name);
+        let info_file = format!("{}/build_info_{}", STATE.host_home_path, driver.driver_name);
+        format!(
+            "(echo git_remote=$(git -C {proj_path} remote get-url origin 2>/dev/null || echo local); \
+             echo git_sha=$(git -C {proj_path} rev-parse HEAD 2>/dev/null || echo unknown); \
+             echo cargo=$({bin}/cargo --version); \
+             echo rustc=$({home}/.cargo/bin/rustc --version); \
+             echo build_cmd=$(echo {build_cmd} | base64 -d)) > {info_file} \
+             && aws s3 cp {info_file} {s3_path}/{BUILD_INFO_DIR}/{host_group}/{driver_name}.txt",
+            bin = STATE.host_bin_path(),
+            home = STATE.host_home_path,
+            s3_path = STATE.s3_path(unique_id),
+            driver_name = driver.driver_name,
+        )
+    }
+
+    /// Parse the `key=value` lines written by [`BuildInfo::record_cmd`].
+    pub fn parse(host_group: &str, driver: &str, info: &str) -> Self {
+        let mut build_info = BuildInfo {
+            host_group: host_group.to_string(),
+            driver: driver.to_string(),
+            ..Default::default()
+        };
+        for line in info.lines() {
+            let Some((key, value)) = line.split_once('=') else {
+                continue;
+            };
+            let value = value.trim().to_string();
+            match key {
+                "git_remote" => build_info.git_remote = value,
+                "git_sha" => build_info.git_sha = value,
+                "cargo" => build_info.cargo = value,
+                "rustc" => build_info.rustc = value,
+                "build_cmd" => build_info.build_cmd = value,
+                _ => (),
+            }
+        }
+        build_info
+    }
+}
+
+impl std::fmt::Display for BuildInfo {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} {}: {} {} ({}, {}) `{}`",
+            self.host_group,
+            self.driver,
+            self.git_remote,
+            self.git_sha,
+            self.cargo,
+            self.rustc,
+            self.build_cmd
+        )
+    }
+}
+
+/// Download the build info recorded by the hosts of a run.
+pub async fn download_build_info(
+    s3_client: &aws_sdk_s3::Client,
+    unique_id: &str,
+) -> OrchResult<Vec<BuildInfo>> {
+    let prefix = format!("{unique_id}/{BUILD_INFO_DIR}/");
+    let mut build_info = Vec::new();
+    for key in list_objects(s3_client, STATE.s3_log_bucket, &prefix)
+        .await?
+        .into_keys()
+    {
+        let Some((host_group, driver)) = key
+            .strip_prefix(&prefix)
+            .and_then(|name| name.strip_suffix(".txt"))
+            .and_then(|name| name.split_once('/'))
+        else {
+            continue;
+        };
+        let info = download_object(s3_client, STATE.s3_log_bucket, &key)
+            .await
+            .map_err(|err| OrchError::S3 {
+                dbg: format!("Failed to download build info {}: {}", key, err),
+            })?
+            .body
+            .collect()
+            .await
+            .map_err(|err| OrchError::S3 {
+                dbg: format!("Failed to download build info {}: {}", key, err),
+            })?
+            .into_bytes();
+        build_info.push(BuildInfo::parse(
+            host_group,
+            driver,
+            &String::from_utf8_lossy(&info),
+        ));
+    }
+    Ok(build_info)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_build_info() {
+        let info = "git_remote=https://github.com/aws/s2n-netbench.git\ngit_sha=0123abcd\ncargo=cargo 1.75.0 (1d8b05cdd 2023-11-20)\nrustc=rustc 1.75.0 (82e1608df 2023-12-21)\nbuild_cmd=env RUSTFLAGS='--cfg s2n_quic_unstable' /home/ec2-user/bin/cargo build\n";
+        let build_info = BuildInfo::parse("server", "s2n-netbench-driver-server-s2n-quic", info);
+        assert_eq!(
+            build_info.git_remote,
+            "https://github.com/aws/s2n-netbench.git"
+        );
+        assert_eq!(build_info.git_sha, "0123abcd");
+        assert_eq!(build_info.cargo, "cargo 1.75.0 (1d8b05cdd 2023-11-20)");
+        assert_eq!(build_info.rustc, "rustc 1.75.0 (82e1608df 2023-12-21)");
+        assert_eq!(
+            build_info.build_cmd,
+            "env RUSTFLAGS='--cfg s2n_quic_unstable' /home/ec2-user/bin/cargo build"
+        );
+
+        let driver = crate::ssm_utils::dc_quic_server_driver("id");
+        let cmd = BuildInfo::record_cmd("server", &driver, "id");
+        // the build command is shipped base64 encoded
+        assert!(!cmd.contains("RUSTFLAGS"), "{}", cmd);
+        assert!(
+            cmd.ends_with("/build_info/server/s2n-netbench-driver-server-s2n-quic-dc.txt"),
+            "{}",
+            cmd
+        );
+    }
+}
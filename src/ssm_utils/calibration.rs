@@ -0,0 +1,444 @@
+// Copyright Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+use super::{
+    command_id, command_output, common::wait_complete, iperf3_pairs, iperf3_server_cmds,
+    output_value, send_command, wait_for_ssm_results, Step,
+};
+use crate::{
+    config::Calibration,
+    error::{OrchError, OrchResult},
+    s3_utils::{download_object, upload_object},
+    state::STATE,
+    InfraDetail,
+};
+use aws_sdk_s3::primitives::ByteStream;
+use bytes::Bytes;
+use indicatif::MultiProgress;
+use serde::{Deserialize, Serialize};
+use tracing::{info, warn};
+
+// The loopback probe's server, which doesn't clash with the cross-host servers
+const LOOPBACK_PORT: u16 = 5200;
+// Fewer runs aren't enough to establish a norm
+const MIN_HISTORY: usize = 3;
+
+/// The throughput of a host measured by the calibration probes, in bits per
+/// second.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct HostCalibration {
+    pub instance_id: String,
+    pub host_group: String,
+    // iperf3 against a server on the same host, which is sensitive to the cpu
+    // and memory bandwidth left by the other tenants
+    pub loopback_bps: f64,
+    // iperf3 against the client's server. clients only
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cross_host_bps: Option<f64>,
+}
+
+/// The calibration of a run, recorded in the run's manifest.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct CalibrationReport {
+    pub hosts: Vec<HostCalibration>,
+    // The norm the hosts were compared to, if enough runs were recorded
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub norm: Option<CalibrationRun>,
+    // Hosts which deviate from the norm
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub warnings: Vec<String>,
+}
+
+/// The median throughput of the hosts of a run.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct CalibrationRun {
+    pub unique_id: String,
+    pub loopback_bps: f64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cross_host_bps: Option<f64>,
+}
+
+/// The runs within the norms on an instance type, oldest first.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct CalibrationHistory {
+    pub runs: Vec<CalibrationRun>,
+}
+
+fn median(mut values: Vec<f64>) -> Option<f64> {
+    if values.is_empty() {
+        return None;
+    }
+    values.sort_by(f64::total_cmp);
+    let mid = values.len() / 2;
+    Some(match values.len() % 2 {
+        0 => (values[mid - 1] + values[mid]) / 2.0,
+        _ => values[mid],
+    })
+}
+
+impl CalibrationRun {
+    fn from_hosts(unique_id: &str, hosts: &[HostCalibration]) -> Option<Self> {
+        Some(CalibrationRun {
+            unique_id: unique_id.to_string(),
+            loopback_bps: median(hosts.iter().map(|host| host.loopback_bps).collect())?,
+            cross_host_bps: median(
+                hosts
+                    .iter()
+                    .filter_map(|host| host.cross_host_bps)
+                    .collect(),
+            ),
+        })
+    }
+}
+
+impl CalibrationHistory {
+    fn key(instance_type: &str) -> String {
+        format!("calibration/{instance_type}.json")
+    }
+
+    /// The medians of the recorded runs, if there are enough of them.
+    pub fn norm(&self) -> Option<CalibrationRun> {
+        if self.runs.len() < MIN_HISTORY {
+            return None;
+        }
+        Some(CalibrationRun {
+            unique_id: format!("median of {} runs", self.runs.len()),
+            loopback_bps: median(self.runs.iter().map(|run| run.loopback_bps).collect())?,
+            cross_host_bps: median(
+                self.runs
+                    .iter()
+                    .filter_map(|run| run.cross_host_bps)
+                    .collect(),
+            ),
+        })
+    }
+
+    /// Record a run, keeping the last `history` runs.
+    fn push(&mut self, run: CalibrationRun, history: usize) {
+        self.runs.push(run);
+        let excess = self.runs.len().saturating_sub(history);
+        self.runs.drain(..excess);
+    }
+
+    async fn download(s3_client: &aws_sdk_s3::Client, instance_type: &str) -> OrchResult<Self> {
+        let key = Self::key(instance_type);
+        // the first calibration on the instance type
+        let Ok(object) = download_object(s3_client, STATE.s3_log_bucket, &key).await else {
+            return Ok(Self::default());
+        };
+        let body = object.body.collect().await.map_err(|err| OrchError::S3 {
+            dbg: format!("Failed to read {}: {}", key, err),
+        })?;
+        serde_json::from_slice(&body.into_bytes()).map_err(|err| OrchError::Init {
+            dbg: format!("Failed to parse {}: {}", key, err),
+        })
+    }
+
+    async fn upload(&self, s3_client: &aws_sdk_s3::Client, instance_type: &str) -> OrchResult<()> {
+        let key = Self::key(instance_type);
+        let body = serde_json::to_vec_pretty(self).expect("history serializes");
+        upload_object(
+            s3_client,
+            STATE.s3_log_bucket,
+            ByteStream::from(Bytes::from(body)),
+            &key,
+        )
+        .await
+        .map_err(|err| OrchError::S3 {
+            dbg: format!("Failed to upload {}: {}", key, err),
+        })?;
+        Ok(())
+    }
+}
+
+/// Flag the hosts whose throughput deviates more than `max_deviation_pct` from
+/// the norm.
+fn check(hosts: &[HostCalibration], norm: &CalibrationRun, max_deviation_pct: f64) -> Vec<String> {
+    let gbps = |bps: f64| bps / 1e9;
+    let mut warnings = Vec::new();
+    for host in hosts {
+        let probes = [
+            ("loopback", Some(host.loopback_bps), Some(norm.loopback_bps)),
+            ("cross-host", host.cross_host_bps, norm.cross_host_bps),
+        ];
+        for (probe, value, norm) in probes {
+            let (Some(value), Some(norm)) = (value, norm) else {
+                continue;
+            };
+            if norm <= 0.0 {
+                continue;
+            }
+            let deviation_pct = (value - norm) / norm * 100.0;
+            if deviation_pct.abs() > max_deviation_pct {
+                warnings.push(format!(
+                    "{} {}: {} throughput {:.2} Gbit/s is {:.0}% {} the norm {:.2} Gbit/s",
+                    host.host_group,
+                    host.instance_id,
+                    probe,
+                    gbps(value),
+                    deviation_pct.abs(),
+                    if deviation_pct < 0.0 {
+                        "below"
+                    } else {
+                        "above"
+                    },
+                    gbps(norm),
+                ));
+            }
+        }
+    }
+    warnings
+}
+
+/// Print the throughput received by an iperf3 client as `<key>=<bps>`.
+fn probe_cmd(key: &str, server: &str, port: u16, calibration: &Calibration) -> String {
+    let mut cmd = format!("iperf3 --client {server} --port {port} --json");
+    if let Some(duration) = calibration.duration {
+        cmd.push_str(&format!(" --time {}", duration.as_secs()));
+    }
+    format!(
+        "echo {key}=$({cmd} | python3 -c \"import json, sys; print(json.load(sys.stdin)['end']['sum_received']['bits_per_second'])\")"
+    )
+}
+
+/// Probe the loopback throughput of the servers and clients, then the
+/// throughput from each client to its own server.
+async fn probe(
+    ssm_client: &aws_sdk_ssm::Client,
+    infra: &InfraDetail,
+    calibration: &Calibration,
+) -> OrchResult<Vec<HostCalibration>> {
+    let hosts: Vec<_> = infra.servers.iter().chain(infra.clients.iter()).collect();
+    let server_ids: Vec<String> = infra
+        .servers
+        .iter()
+        .map(|host| host.instance_id.clone())
+        .collect();
+    let parse = |stdout: &str, key: &str, instance_id: &str| {
+        output_value(stdout, key)
+            .and_then(|bps| bps.parse::<f64>().ok())
+            .ok_or(OrchError::Ec2Instance {
+                instance_id: instance_id.to_string(),
+                dbg: format!("Failed to parse the {} calibration: {}", key, stdout),
+            })
+    };
+
+    // the servers are started for the cross-host probe once their loopback
+    // probe completes
+    let loopback = send_command(
+        vec![],
+        Step::Calibration,
+        "all",
+        "calibration_loopback",
+        ssm_client,
+        hosts.iter().map(|host| host.instance_id.clone()).collect(),
+        vec![
+            "pkill -x iperf3 || true".to_string(),
+            format!("iperf3 --server --daemon --one-off --port {LOOPBACK_PORT}"),
+            "sleep 1".to_string(),
+            probe_cmd("loopback_bps", "127.0.0.1", LOOPBACK_PORT, calibration),
+        ],
+    )
+    .await?;
+    let loopback_id = command_id(&loopback)?;
+    wait_for_ssm_results("all", ssm_client, loopback_id).await?;
+
+    let mut calibrations = Vec::new();
+    for host in hosts.iter() {
+        let stdout = command_output(
+            ssm_client,
+            "calibration_loopback",
+            loopback_id,
+            &host.instance_id,
+        )
+        .await?;
+        calibrations.push(HostCalibration {
+            instance_id: host.instance_id.clone(),
+            host_group: host.endpoint_type.as_str().to_lowercase(),
+            loopback_bps: parse(&stdout, "loopback_bps", &host.instance_id)?,
+            cross_host_bps: None,
+        });
+    }
+    if infra.clients.is_empty() || infra.servers.is_empty() {
+        return Ok(calibrations);
+    }
+
+    let ports = infra.clients.len().div_ceil(infra.servers.len()) as u16;
+    let servers = send_command(
+        vec![],
+        Step::Calibration,
+        "server",
+        "calibration_server",
+        ssm_client,
+        server_ids.clone(),
+        iperf3_server_cmds(ports),
+    )
+    .await?;
+    wait_for_ssm_results("server", ssm_client, command_id(&servers)?).await?;
+
+    let mut clients = Vec::new();
+    let pairs = iperf3_pairs(infra.clients.len(), infra.servers.len());
+    for (client, (server, port)) in infra.clients.iter().zip(pairs) {
+        let cmd = send_command(
+            vec![],
+            Step::Calibration,
+            "client",
+            "calibration_cross_host",
+            ssm_client,
+            vec![client.instance_id.clone()],
+            vec![probe_cmd(
+                "cross_host_bps",
+                &infra.servers[server].private_ip,
+                port,
+                calibration,
+            )],
+        )
+        .await?;
+        clients.push(cmd);
+    }
+    let cross_host =
+        wait_complete("client", ssm_client, clients.clone(), &MultiProgress::new()).await;
+
+    let stop_servers = send_command(
+        vec![],
+        Step::Calibration,
+        "server",
+        "calibration_server_stop",
+        ssm_client,
+        server_ids,
+        vec!["pkill -x iperf3 || true".to_string()],
+    )
+    .await?;
+    wait_for_ssm_results("server", ssm_client, command_id(&stop_servers)?).await?;
+    cross_host?;
+
+    for (client, cmd) in infra.clients.iter().zip(clients.iter()) {
+        let stdout = command_output(
+            ssm_client,
+            "calibration_cross_host",
+            command_id(cmd)?,
+            &client.instance_id,
+        )
+        .await?;
+        let cross_host_bps = parse(&stdout, "cross_host_bps", &client.instance_id)?;
+        if let Some(calibration) = calibrations
+            .iter_mut()
+            .find(|calibration| calibration.instance_id == client.instance_id)
+        {
+            calibration.cross_host_bps = Some(cross_host_bps);
+        }
+    }
+    Ok(calibrations)
+}
+
+/// Probe the throughput of the hosts and compare it to the norm of the
+/// previous runs on `instance_type`.
+///
+/// Only runs within the norm are added to the history so that a noisy run
+/// doesn't shift it. A failure to read or update the history is logged without
+/// failing the run.
+pub async fn calibrate(
+    ssm_client: &aws_sdk_ssm::Client,
+    s3_client: &aws_sdk_s3::Client,
+    infra: &InfraDetail,
+    unique_id: &str,
+    instance_type: &str,
+    calibration: &Calibration,
+) -> OrchResult<CalibrationReport> {
+    let hosts = probe(ssm_client, infra, calibration).await?;
+    for host in hosts.iter() {
+        info!(
+            instance_id = %host.instance_id,
+            loopback_bps = host.loopback_bps,
+            cross_host_bps = host.cross_host_bps,
+            "calibration"
+        );
+    }
+
+    let mut history = match CalibrationHistory::download(s3_client, instance_type).await {
+        Ok(history) => history,
+        Err(err) => {
+            warn!("Failed to download the calibration history: {}", err);
+            CalibrationHistory::default()
+        }
+    };
+    let norm = history.norm();
+    let warnings = norm
+        .as_ref()
+        .map(|norm| check(&hosts, norm, calibration.max_deviation_pct))
+        .unwrap_or_default();
+    for warning in warnings.iter() {
+        warn!("calibration: {}", warning);
+    }
+
+    if let (true, Some(run)) = (
+        warnings.is_empty(),
+        CalibrationRun::from_hosts(unique_id, &hosts),
+    ) {
+        history.push(run, calibration.history);
+        if let Err(err) = history.upload(s3_client, instance_type).await {
+            warn!("Failed to update the calibration history: {}", err);
+        }
+    }
+    Ok(CalibrationReport {
+        hosts,
+        norm,
+        warnings,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn host(
+        instance_id: &str,
+        loopback_gbps: f64,
+        cross_host_gbps: Option<f64>,
+    ) -> HostCalibration {
+        HostCalibration {
+            instance_id: instance_id.to_string(),
+            host_group: "client".to_string(),
+            loopback_bps: loopback_gbps * 1e9,
+            cross_host_bps: cross_host_gbps.map(|gbps| gbps * 1e9),
+        }
+    }
+
+    #[test]
+    fn calibration_norms() {
+        let mut history = CalibrationHistory::default();
+        for (i, loopback_gbps) in [40.0, 42.0, 38.0, 41.0].into_iter().enumerate() {
+            assert_eq!(history.norm().is_some(), i >= MIN_HISTORY);
+            let run = CalibrationRun::from_hosts(
+                &format!("run-{i}"),
+                &[host("i-1", loopback_gbps, Some(10.0))],
+            )
+            .unwrap();
+            history.push(run, 3);
+        }
+        // only the last 3 runs are kept
+        assert_eq!(history.runs[0].unique_id, "run-1");
+        let norm = history.norm().unwrap();
+        assert_eq!(norm.loopback_bps, 41e9);
+        assert_eq!(norm.cross_host_bps, Some(10e9));
+
+        let hosts = [
+            host("i-1", 40.0, Some(9.5)),
+            host("i-2", 20.0, Some(10.0)),
+            host("i-3", 41.0, Some(5.0)),
+            host("i-4", 40.0, None),
+        ];
+        let warnings = check(&hosts, &norm, 20.0);
+        assert_eq!(warnings.len(), 2);
+        assert_eq!(
+            warnings[0],
+            "client i-2: loopback throughput 20.00 Gbit/s is 51% below the norm 41.00 Gbit/s"
+        );
+        assert!(
+            warnings[1].starts_with("client i-3: cross-host throughput 5.00 Gbit/s is 50% below")
+        );
+
+        assert_eq!(median(vec![3.0, 1.0, 2.0, 4.0]), Some(2.5));
+        assert_eq!(median(vec![]), None);
+    }
+}
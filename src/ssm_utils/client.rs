@@ -1,24 +1,23 @@
 // Copyright Amazon.com, Inc. or its affiliates. All Rights Reserved.
 // SPDX-License-Identifier: Apache-2.0
 
-use super::{send_command, Step};
-use crate::{state::STATE, NetbenchDriver, Scenario};
+use super::{send_command, upload_sidecar_cmds, windows, Step, WorkerOptions};
+use crate::{
+    ec2_utils::EndpointType, error::OrchResult, russula::netbench::driver_short_name, state::STATE,
+    NetbenchDriver,
+};
 use aws_sdk_ssm::operation::send_command::SendCommandOutput;
-use std::net::{IpAddr, SocketAddr};
 use tracing::debug;
 
 pub async fn upload_netbench_data(
     ssm_client: &aws_sdk_ssm::Client,
     instance_ids: Vec<String>,
     unique_id: &str,
-    scenario: &Scenario,
+    // the job's folder under results/. see run_spec::Job::result_key
+    result_key: &str,
     driver: &NetbenchDriver,
-) -> SendCommandOutput {
-    let driver_name = driver
-        .driver_name
-        .trim_start_matches("s2n-netbench-driver-")
-        .trim_start_matches("netbench-driver-")
-        .trim_end_matches(".json");
+) -> OrchResult<SendCommandOutput> {
+    let driver_name = driver_short_name(&driver.driver_name);
 
     send_command(
         vec![Step::RunRussula],
@@ -29,41 +28,35 @@ pub async fn upload_netbench_data(
         instance_ids,
         vec![
             "cd netbench_orchestrator",
+            // move rather than copy so the results are not uploaded again as
+            // part of the next scenario
+            // results are named per host: client-<instance-id>-<driver>.json
             format!(
-                "aws s3 cp client* {}/results/{}/{driver_name}/",
+                "for result in client-*.json; do aws s3 mv $result {}/results/{}/{driver_name}/; done",
                 STATE.s3_path(unique_id),
-                scenario.file_stem()
+                result_key
             )
             .as_str(),
         ]
         .into_iter()
         .map(String::from)
+        .chain(upload_sidecar_cmds("client", unique_id, result_key, driver_name))
         .collect(),
     )
     .await
-    .expect("Timed out")
 }
 
 pub async fn run_russula_worker(
     ssm_client: &aws_sdk_ssm::Client,
     instance_ids: Vec<String>,
-    server_ips: &Vec<IpAddr>,
-    driver: &NetbenchDriver,
-    scenario: &Scenario,
-) -> SendCommandOutput {
-    let netbench_server_addr = server_ips
-        .iter()
-        .map(|ip| SocketAddr::new(*ip, STATE.netbench_port).to_string())
-        .reduce(|mut accum, item| {
-            accum.push(' ');
-            accum.push_str(&item);
-            accum
-        })
-        .unwrap();
-
+    worker_opts: &WorkerOptions,
+) -> OrchResult<SendCommandOutput> {
+    if worker_opts.client_os.is_windows() {
+        return windows::run_russula_worker(ssm_client, instance_ids, worker_opts).await;
+    }
     let netbench_cmd =
-        format!("env RUST_LOG=debug ./target/debug/russula_cli netbench-client-worker --russula-port {} --driver {} --scenario {} --netbench-servers {netbench_server_addr} --testing",
-            STATE.russula_port, driver.driver_name, scenario.name);
+        format!("env RUST_LOG=debug ./target/debug/russula_cli --transport {} netbench-client-worker --instance-id $(cat /var/lib/cloud/data/instance-id) {} {} --testing",
+            worker_opts.transport, worker_opts.netbench_worker_args("client"), worker_opts.russula_addr_args(&EndpointType::Client));
     debug!("{}", netbench_cmd);
 
     send_command(
@@ -79,5 +72,4 @@ pub async fn run_russula_worker(
             .collect(),
     )
     .await
-    .expect("Timed out")
 }
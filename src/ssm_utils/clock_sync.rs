@@ -0,0 +1,116 @@
+// Copyright Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+use super::{command_id, command_output, send_command, wait_for_ssm_results, Step};
+use crate::{
+    ec2_utils::host_os::ensure_package_cmd,
+    error::{OrchError, OrchResult},
+    InfraDetail,
+};
+use core::time::Duration;
+use serde::{Deserialize, Serialize};
+use tracing::info;
+
+/// The clock offset and jitter of a host relative to NTP time, as reported by
+/// chrony.
+///
+/// Recorded in the run's manifest so that latencies measured across hosts can
+/// be corrected.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ClockSync {
+    pub instance_id: String,
+    pub host_group: String,
+    // System clock offset from NTP time. Positive if the clock is fast
+    pub offset_us: f64,
+    // RMS of the recent offsets
+    pub jitter_us: f64,
+}
+
+/// Ensure chrony is running on the hosts and measure their clock offsets.
+///
+/// Errors if the offset of any host exceeds `max_offset`.
+pub async fn verify_clock_sync(
+    ssm_client: &aws_sdk_ssm::Client,
+    infra: &InfraDetail,
+    max_offset: Duration,
+) -> OrchResult<Vec<ClockSync>> {
+    let cmd = send_command(
+        vec![],
+        Step::ClockSync,
+        "all",
+        "clock_sync",
+        ssm_client,
+        infra.instance_ids(),
+        vec![
+            ensure_package_cmd("chrony"),
+            // chrony is only aliased as chronyd on Ubuntu, which can't be enabled
+            "systemctl enable --now chronyd || systemctl enable --now chrony".to_string(),
+            // wait up to 2 minutes for chrony to sync, but record the offset either way
+            "chronyc waitsync 12 0.001 > /dev/null || true".to_string(),
+            "chronyc -c tracking".to_string(),
+        ],
+    )
+    .await?;
+    let command_id = command_id(&cmd)?;
+    wait_for_ssm_results("all", ssm_client, command_id).await?;
+
+    let mut clock_sync = Vec::new();
+    for host in infra.hosts() {
+        let tracking =
+            command_output(ssm_client, "clock_sync", command_id, &host.instance_id).await?;
+        let (offset_us, jitter_us) =
+            parse_chrony_tracking(&tracking).ok_or(OrchError::Ec2Instance {
+                instance_id: host.instance_id.clone(),
+                dbg: format!("Failed to parse chrony tracking: {}", tracking),
+            })?;
+        info!(
+            instance_id = %host.instance_id,
+            offset_us, jitter_us, "clock sync"
+        );
+        if offset_us.abs() > max_offset.as_micros() as f64 {
+            return Err(OrchError::Ec2Instance {
+                instance_id: host.instance_id.clone(),
+                dbg: format!(
+                    "Clock offset {}us exceeds the max offset {}us",
+                    offset_us,
+                    max_offset.as_micros()
+                ),
+            });
+        }
+        clock_sync.push(ClockSync {
+            instance_id: host.instance_id.clone(),
+            host_group: host.endpoint_type.as_str().to_lowercase(),
+            offset_us,
+            jitter_us,
+        });
+    }
+    Ok(clock_sync)
+}
+
+/// Parse the (offset, jitter) in microseconds from the csv output of
+/// `chronyc -c tracking`.
+///
+/// ex: A9FEA97B,169.254.169.123,4,1697040000.123456,-0.000001234,-0.000000567,0.000012345,...
+fn parse_chrony_tracking(output: &str) -> Option<(f64, f64)> {
+    // the last line, after any output of the preceding commands
+    let fields: Vec<&str> = output.trim().lines().last()?.split(',').collect();
+    // chrony reports how far the clock is behind NTP time
+    let system_time: f64 = fields.get(4)?.parse().ok()?;
+    let rms_offset: f64 = fields.get(6)?.parse().ok()?;
+    Some((-system_time * 1e6, rms_offset * 1e6))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn chrony_tracking() {
+        let output = "chrony-4.3-1.amzn2023.0.4.x86_64\nA9FEA97B,169.254.169.123,4,1697040000.123456,-0.000001250,-0.000000567,0.000012500,-12.345,0.001,0.012,0.000345,0.000012,64.2,Normal\n";
+        let (offset_us, jitter_us) = parse_chrony_tracking(output).unwrap();
+        assert!((offset_us - 1.25).abs() < 1e-6, "{}", offset_us);
+        assert!((jitter_us - 12.5).abs() < 1e-6, "{}", jitter_us);
+
+        assert_eq!(parse_chrony_tracking("506 Cannot talk to daemon"), None);
+    }
+}
@@ -0,0 +1,165 @@
+// Copyright Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+use super::{send_command, Step};
+use crate::{
+    ec2_utils::LinuxDistro,
+    error::{OrchError, OrchResult},
+    state::STATE,
+};
+use aws_sdk_cloudwatchlogs::types::FilteredLogEvent;
+use aws_sdk_ssm::operation::send_command::SendCommandOutput;
+use base64::{engine::general_purpose, Engine};
+use core::time::Duration;
+use serde_json::{json, Value};
+use std::time::SystemTime;
+
+const AGENT_CONFIG_PATH: &str = "/opt/aws/amazon-cloudwatch-agent/etc/netbench.json";
+
+// russula_cli and the netbench drivers are run from the russula checkout
+fn host_target_path() -> String {
+    format!("{}/netbench_orchestrator/target", STATE.host_home_path)
+}
+
+/// Install the CloudWatch agent and ship the russula and netbench driver logs to
+/// `STATE.cloud_watch_group`, in a log stream per instance and log.
+///
+/// ex: i-0123/russula, i-0123/driver
+pub async fn configure_log_shipping(
+    host_group: &str,
+    ssm_client: &aws_sdk_ssm::Client,
+    instance_ids: Vec<String>,
+    distro: LinuxDistro,
+) -> OrchResult<SendCommandOutput> {
+    let config = general_purpose::STANDARD.encode(agent_config().to_string());
+    send_command(
+        vec![Step::Configure],
+        Step::ConfigureLogs,
+        host_group,
+        &format!("configure_logs_{}", host_group),
+        ssm_client,
+        instance_ids,
+        vec![
+            distro.cloud_watch_agent_install_cmd(),
+            format!("echo {config} | base64 -d > {AGENT_CONFIG_PATH}"),
+            format!(
+                "amazon-cloudwatch-agent-ctl -a fetch-config -m ec2 -s -c file:{AGENT_CONFIG_PATH}"
+            ),
+        ],
+    )
+    .await
+}
+
+fn agent_config() -> Value {
+    let file = |file_path: String, log: &str| {
+        json!({
+            "file_path": file_path,
+            "log_group_name": STATE.cloud_watch_group,
+            "log_stream_name": format!("{{instance_id}}/{log}"),
+        })
+    };
+    json!({
+        "logs": {
+            "logs_collected": {
+                "files": {
+                    "collect_list": [
+                        // the daily russula_cli log. ex: russula.log.2023-10-11
+                        file(format!("{}/russula.log*", host_target_path()), "russula"),
+                        // see russula::netbench::stderr_log
+                        file(format!("{}/*.stderr", host_target_path()), "driver"),
+                    ]
+                }
+            }
+        }
+    })
+}
+
+/// The log events shipped from `instance_id` during the last `since`, oldest
+/// first, capped at the latest `limit` events.
+pub async fn recent_log_events(
+    logs_client: &aws_sdk_cloudwatchlogs::Client,
+    instance_id: &str,
+    since: Duration,
+    limit: usize,
+) -> OrchResult<Vec<String>> {
+    let start_time = SystemTime::now()
+        .checked_sub(since)
+        .and_then(|start| start.duration_since(SystemTime::UNIX_EPOCH).ok())
+        .unwrap_or_default();
+    let mut messages = Vec::new();
+    let mut next_token = None;
+    loop {
+        let output = logs_client
+            .filter_log_events()
+            .log_group_name(STATE.cloud_watch_group)
+            .log_stream_name_prefix(format!("{instance_id}/"))
+            .start_time(start_time.as_millis() as i64)
+            .set_next_token(next_token)
+            .send()
+            .await
+            .map_err(|err| OrchError::Ec2Instance {
+                instance_id: instance_id.to_string(),
+                dbg: format!("Failed to query log events: {}", err),
+            })?;
+        messages.extend(
+            output
+                .events()
+                .unwrap_or_default()
+                .iter()
+                .filter_map(event_message),
+        );
+        next_token = output.next_token().map(String::from);
+        if next_token.is_none() {
+            break;
+        }
+    }
+    Ok(latest(messages, limit))
+}
+
+fn event_message(event: &FilteredLogEvent) -> Option<String> {
+    Some(format!(
+        "{}: {}",
+        event.log_stream_name()?,
+        event.message()?
+    ))
+}
+
+fn latest(messages: Vec<String>, limit: usize) -> Vec<String> {
+    let skip = messages.len().saturating_sub(limit);
+    messages.into_iter().skip(skip).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn log_events() {
+        let event = |stream: &str, message: &str| {
+            FilteredLogEvent::builder()
+                .log_stream_name(stream)
+                .message(message)
+                .build()
+        };
+        let messages = [
+            event("i-0123/russula", "state transition"),
+            event("i-0123/driver", "connection refused"),
+            event("i-0123/russula", "Stopped"),
+        ]
+        .iter()
+        .filter_map(event_message)
+        .collect();
+        assert_eq!(
+            latest(messages, 2),
+            vec![
+                "i-0123/driver: connection refused",
+                "i-0123/russula: Stopped"
+            ]
+        );
+        assert_eq!(
+            event_message(&FilteredLogEvent::builder().message("no stream").build()),
+            None
+        );
+        assert!(latest(Vec::new(), 2).is_empty());
+    }
+}
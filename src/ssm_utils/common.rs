@@ -1,60 +1,324 @@
 // Copyright Amazon.com, Inc. or its affiliates. All Rights Reserved.
 // SPDX-License-Identifier: Apache-2.0
 
-use super::{send_command, Step};
-use crate::{poll_ssm_results, state::STATE, NetbenchDriver};
+use super::{
+    build_info::BuildInfo, cloud_watch, command_id, document, list_invocation_status,
+    poll_invocations, send_command, Step,
+};
+use crate::{
+    config::{BuildCache, Impairment},
+    dashboard::{progress::StepProgress, timeline},
+    ec2_utils::LinuxDistro,
+    error::OrchResult,
+    poll::Backoff,
+    scenario::certs::TLS_DIR,
+    state::STATE,
+    NetbenchDriver,
+};
 use aws_sdk_ssm::operation::send_command::SendCommandOutput;
-use core::time::Duration;
-use indicatif::{ProgressBar, ProgressStyle};
-
-fn get_progress_bar(cmds: &Vec<SendCommandOutput>) -> ProgressBar {
-    // TODO use multi-progress bar https://github.com/console-rs/indicatif/blob/main/examples/multi.rs
-    let total_tasks = cmds.len() as u64;
-    let bar = ProgressBar::new(total_tasks);
-    let style = ProgressStyle::with_template(
-        "{spinner} [{elapsed_precise}] {bar:40.cyan/blue} {pos:>7}/{len:7} {msg}",
-    )
-    .unwrap()
-    .tick_chars("⠁⠂⠄⡀⢀⠠⠐⠈ ");
-    bar.set_style(style);
-    bar.enable_steady_tick(Duration::from_secs(1));
-    bar
-}
+use indicatif::MultiProgress;
+use std::collections::BTreeMap;
 
+/// Wait for the SSM commands sent to a host group to complete.
+///
+/// Each command is displayed as a separate bar in `multi_progress` along with the
+/// status of the command on each instance.
 pub async fn wait_complete(
     host_group: &str,
     ssm_client: &aws_sdk_ssm::Client,
     cmds: Vec<SendCommandOutput>,
-) {
-    let total_tasks = cmds.len() as u64;
-    let bar = get_progress_bar(&cmds);
-    loop {
-        let mut completed_tasks = 0;
-        for cmd in cmds.iter() {
-            let _comment = cmd
-                .command()
-                .unwrap()
-                .comment()
-                .map(|s| s.to_string())
-                .unwrap();
-            let cmd_id = cmd.command().unwrap().command_id().unwrap();
-            let poll_cmd = poll_ssm_results(host_group, ssm_client, cmd_id)
-                .await
-                .unwrap();
-            if poll_cmd.is_ready() {
-                completed_tasks += 1;
+    multi_progress: &MultiProgress,
+) -> OrchResult<()> {
+    let mut pending = Vec::new();
+    for cmd in cmds.iter() {
+        let command = cmd.command();
+        let step = command
+            .and_then(|command| command.comment())
+            .unwrap_or_default();
+        let instance_ids = command
+            .and_then(|command| command.instance_ids())
+            .unwrap_or_default();
+        let progress = StepProgress::new(multi_progress, host_group, step, instance_ids);
+        pending.push((command_id(cmd)?, step, progress));
+    }
+
+    let finished = |step: &str, success| {
+        timeline::record(timeline::Event::StepFinished {
+            host_group: host_group.to_string(),
+            step: step.to_string(),
+            success,
+        })
+    };
+    let mut backoff = Backoff::ssm();
+    while !pending.is_empty() {
+        let mut still_pending = Vec::new();
+        let polled = pending.len();
+        for (cmd_id, step, progress) in pending {
+            let invocations = list_invocation_status(host_group, ssm_client, cmd_id).await?;
+            progress.update(&invocations);
+            match poll_invocations(cmd_id, &invocations) {
+                Ok(poll) if poll.is_ready() => {
+                    finished(step, true);
+                    progress.finish()
+                }
+                Ok(_) => still_pending.push((cmd_id, step, progress)),
+                Err(err) => {
+                    finished(step, false);
+                    progress.abandon();
+                    return Err(err);
+                }
             }
         }
+        if still_pending.len() < polled {
+            backoff.reset();
+        }
+        pending = still_pending;
+
+        if !pending.is_empty() {
+            backoff.wait().await;
+        }
+    }
+    Ok(())
+}
+
+// The interface used by the default route
+const DEFAULT_INTERFACE: &str = "$(ip route show default | awk '{print $5; exit}')";
+
+/// Apply the network impairment to the hosts with `tc netem`, or remove any
+/// impairment if `None`.
+pub async fn configure_impairment(
+    ssm_client: &aws_sdk_ssm::Client,
+    instance_ids: Vec<String>,
+    impairment: Option<&Impairment>,
+) -> OrchResult<()> {
+    let (step, command) = match impairment {
+        Some(impairment) => (
+            Step::ApplyImpairment,
+            format!(
+                "tc qdisc replace dev {DEFAULT_INTERFACE} root netem {}",
+                impairment.netem_args()
+            ),
+        ),
+        None => (
+            Step::RemoveImpairment,
+            format!("tc qdisc del dev {DEFAULT_INTERFACE} root || true"),
+        ),
+    };
+    let comment = step.as_str().to_string();
+    let cmd = send_command(
+        vec![],
+        step,
+        "all",
+        &comment,
+        ssm_client,
+        instance_ids,
+        vec![command],
+    )
+    .await?;
+    wait_complete("all", ssm_client, vec![cmd], &MultiProgress::new()).await
+}
+
+/// Copy, rather than move, the partial netbench results of a running job to
+/// `checkpoints/<result_key>/` so that they survive a failure of a long run.
+pub async fn checkpoint_netbench_data(
+    ssm_client: &aws_sdk_ssm::Client,
+    instance_ids: Vec<String>,
+    unique_id: &str,
+    // the job's folder. see run_spec::Job::result_key
+    result_key: &str,
+) -> OrchResult<SendCommandOutput> {
+    let step = Step::CheckpointNetbenchData;
+    let comment = step.as_str().to_string();
+    send_command(
+        vec![],
+        step,
+        "all",
+        &comment,
+        ssm_client,
+        instance_ids,
+        vec![
+            "cd netbench_orchestrator".to_string(),
+            format!(
+                "for result in server-*.json client-*.json; do if [ -f $result ]; then aws s3 cp $result {}/checkpoints/{}/; fi; done",
+                STATE.s3_path(unique_id),
+                result_key
+            ),
+        ],
+    )
+    .await
+}
+
+/// Kill the russula Workers and the collectors and drivers they launched, for a
+/// scenario which is aborted. The collectors are terminated rather than killed
+/// so that they flush their output.
+pub async fn kill_workers(
+    ssm_client: &aws_sdk_ssm::Client,
+    instance_ids: Vec<String>,
+) -> OrchResult<SendCommandOutput> {
+    let step = Step::KillWorkers;
+    let comment = step.as_str().to_string();
+    send_command(
+        vec![],
+        step,
+        "all",
+        &comment,
+        ssm_client,
+        instance_ids,
+        vec![
+            "pkill -f russula_cli || true".to_string(),
+            "pkill -f s2n-netbench || true".to_string(),
+        ],
+    )
+    .await
+}
 
-        bar.set_position(completed_tasks);
-        bar.set_message(host_group.to_string());
+/// Download the scenario files uploaded to the run's folder to the hosts and
+/// verify their checksums, so that every host runs the same bytes.
+pub async fn distribute_scenarios(
+    ssm_client: &aws_sdk_ssm::Client,
+    instance_ids: Vec<String>,
+    unique_id: &str,
+    // scenario name -> sha256
+    scenario_checksums: &BTreeMap<String, String>,
+) -> OrchResult<()> {
+    let step = Step::CopyScenarios;
+    let comment = step.as_str().to_string();
+    let commands = scenario_checksums
+        .iter()
+        .flat_map(|(name, sha256)| {
+            let path = format!("{}/{}", STATE.host_bin_path(), name);
+            [
+                format!("aws s3 cp {}/{} {}", STATE.s3_path(unique_id), name, path),
+                format!("echo '{}  {}' | sha256sum -c", sha256, path),
+            ]
+        })
+        .collect();
+    let cmd = send_command(
+        vec![],
+        step,
+        "all",
+        &comment,
+        ssm_client,
+        instance_ids,
+        commands,
+    )
+    .await?;
+    wait_complete("all", ssm_client, vec![cmd], &MultiProgress::new()).await
+}
 
-        if total_tasks == completed_tasks {
-            bar.finish();
-            break;
+/// Install the run's certificates in `/home/ec2-user/tls`: the CA, server
+/// certificate and key on the servers, and the CA on the clients.
+pub async fn install_certs(
+    ssm_client: &aws_sdk_ssm::Client,
+    server_ids: Vec<String>,
+    client_ids: Vec<String>,
+    unique_id: &str,
+) -> OrchResult<()> {
+    let tls_path = format!("{}/{TLS_DIR}", STATE.host_home_path);
+    let copy = |bucket_path: String, name: &str| {
+        format!("aws s3 cp --only-show-errors {bucket_path}/{TLS_DIR}/{name} {tls_path}/{name}")
+    };
+    let mut cmds = Vec::new();
+    for (host_group, instance_ids, mut commands) in [
+        (
+            "server",
+            server_ids,
+            vec![
+                copy(STATE.s3_path(unique_id), "ca.pem"),
+                copy(STATE.s3_path(unique_id), "server.pem"),
+                copy(STATE.s3_private_path(unique_id), "server.key"),
+                format!("chmod 600 {tls_path}/server.key"),
+            ],
+        ),
+        (
+            "client",
+            client_ids,
+            vec![copy(STATE.s3_path(unique_id), "ca.pem")],
+        ),
+    ] {
+        if instance_ids.is_empty() {
+            continue;
         }
-        tokio::time::sleep(STATE.poll_delay_ssm).await;
+        commands.insert(0, format!("mkdir -p {tls_path}"));
+        cmds.push(
+            send_command(
+                vec![],
+                Step::InstallCerts,
+                host_group,
+                &format!("install_certs_{}", host_group),
+                ssm_client,
+                instance_ids,
+                commands,
+            )
+            .await?,
+        );
     }
+    wait_complete("all", ssm_client, cmds, &MultiProgress::new()).await
+}
+
+/// Reschedule the hosts' shutdown to `shutdown_min` from now.
+pub async fn extend_lease(
+    ssm_client: &aws_sdk_ssm::Client,
+    instance_ids: Vec<String>,
+    shutdown_min: u16,
+) -> OrchResult<()> {
+    let step = Step::ExtendLease;
+    let comment = step.as_str().to_string();
+    let cmd = send_command(
+        vec![],
+        step,
+        "all",
+        &comment,
+        ssm_client,
+        instance_ids,
+        // replaces the scheduled shutdown
+        vec![format!("shutdown -P +{}", shutdown_min)],
+    )
+    .await?;
+    wait_complete("all", ssm_client, vec![cmd], &MultiProgress::new()).await
+}
+
+/// How a host group is set up before running the scenarios.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum HostSetup<'a> {
+    /// Install the dependencies with the package manager of the `distro` and
+    /// schedule the hosts to shutdown after `shutdown_min`, then build, with
+    /// sccache if there is a `build_cache` and on the instance store if
+    /// `instance_store`
+    Configure {
+        distro: LinuxDistro,
+        shutdown_min: u16,
+        build_cache: Option<&'a BuildCache>,
+        instance_store: bool,
+    },
+    /// Only rebuild the drivers and russula, on hosts which are already
+    /// configured. ex: hosts reused from an infra pool
+    Build,
+}
+
+/// Configure a host group and build the drivers and russula on it.
+///
+/// Host groups are independent so this can be run concurrently for the server
+/// and client hosts.
+pub async fn configure_host_group(
+    host_group: &str,
+    ssm_client: &aws_sdk_ssm::Client,
+    instance_ids: Vec<String>,
+    netbench_drivers: &[&NetbenchDriver],
+    unique_id: &str,
+    setup: HostSetup<'_>,
+    multi_progress: &MultiProgress,
+) -> OrchResult<()> {
+    let cmds = collect_config_cmds(
+        host_group,
+        ssm_client,
+        instance_ids,
+        netbench_drivers,
+        unique_id,
+        setup,
+    )
+    .await?;
+    wait_complete(host_group, ssm_client, cmds, multi_progress).await
 }
 
 pub async fn collect_config_cmds(
@@ -63,11 +327,51 @@ pub async fn collect_config_cmds(
     instance_ids: Vec<String>,
     netbench_drivers: &[&NetbenchDriver],
     unique_id: &str,
-) -> Vec<SendCommandOutput> {
+    setup: HostSetup<'_>,
+) -> OrchResult<Vec<SendCommandOutput>> {
+    let mut cmds = Vec::new();
     // configure and build
-    let install_deps =
-        install_deps_cmd(host_group, ssm_client, instance_ids.clone(), unique_id).await;
+    cmds.extend(
+        install_deps_cmd(
+            host_group,
+            ssm_client,
+            instance_ids.clone(),
+            unique_id,
+            setup,
+        )
+        .await?,
+    );
+    if let HostSetup::Configure { distro, .. } = setup {
+        cmds.push(
+            cloud_watch::configure_log_shipping(
+                host_group,
+                ssm_client,
+                instance_ids.clone(),
+                distro,
+            )
+            .await?,
+        );
+    }
+    cmds.extend(
+        collect_build_cmds(
+            host_group,
+            ssm_client,
+            instance_ids,
+            netbench_drivers,
+            unique_id,
+        )
+        .await?,
+    );
+    Ok(cmds)
+}
 
+async fn collect_build_cmds(
+    host_group: &str,
+    ssm_client: &aws_sdk_ssm::Client,
+    instance_ids: Vec<String>,
+    netbench_drivers: &[&NetbenchDriver],
+    unique_id: &str,
+) -> OrchResult<Vec<SendCommandOutput>> {
     let mut build_drivers = Vec::new();
     for driver in netbench_drivers {
         let build_driver_cmd = build_netbench_driver_cmd(
@@ -77,15 +381,14 @@ pub async fn collect_config_cmds(
             instance_ids.clone(),
             unique_id,
         )
-        .await;
+        .await?;
         build_drivers.push(build_driver_cmd);
     }
-    let build_russula = build_russula_cmd(host_group, ssm_client, instance_ids.clone()).await;
+    let build_russula = build_russula_cmd(host_group, ssm_client, instance_ids).await?;
 
-    vec![install_deps, build_russula]
-        .into_iter()
+    Ok(std::iter::once(build_russula)
         .chain(build_drivers)
-        .collect()
+        .collect())
 }
 
 async fn install_deps_cmd(
@@ -93,34 +396,44 @@ async fn install_deps_cmd(
     ssm_client: &aws_sdk_ssm::Client,
     instance_ids: Vec<String>,
     unique_id: &str,
-) -> SendCommandOutput {
-    send_command(vec![], Step::Configure, host_group, &format!("configure_host_{}", host_group) ,ssm_client, instance_ids, vec![
-        // set instances to shutdown after 1 hour
-        format!("shutdown -P +{}", STATE.shutdown_min),
-        "mkdir -p /home/ec2-user/bin".to_string(),
-
-        format!("echo ec2 up > /home/ec2-user/index.html && aws s3 cp /home/ec2-user/index.html {}/{}-step-1", STATE.s3_path(unique_id), host_group),
-        "yum upgrade -y".to_string(),
-        format!("echo yum upgrade finished > /home/ec2-user/index.html && aws s3 cp /home/ec2-user/index.html {}/{}-step-2", STATE.s3_path(unique_id), host_group),
-        format!("timeout 5m bash -c 'until yum install cargo cmake git perl openssl-devel bpftrace perf tree -y; do sleep 10; done' || (echo yum failed > /home/ec2-user/index.html; aws s3 cp /home/ec2-user/index.html {}/{}-step-3; exit 1)", STATE.s3_path(unique_id), host_group),
-        format!("echo yum finished > /home/ec2-user/index.html && aws s3 cp /home/ec2-user/index.html {}/{}-step-3", STATE.s3_path(unique_id), host_group),
-        // rust
-        "runuser -u ec2-user -- curl --proto '=https' --tlsv1.2 -sSf https://sh.rustup.rs > rustup.rs".to_string(),
-
-        "chmod +x rustup.rs".to_string(),
-        "chgrp ec2-user rustup.rs".to_string(),
-        "chown ec2-user rustup.rs".to_string(),
-
-        "sh ./rustup.rs -y".to_string(),
-        "runuser -u ec2-user -- sh ./rustup.rs -y".to_string(),
-
-        "./root/.cargo/bin/rustup update".to_string(),
-        "runuser -u ec2-user -- ./.cargo/bin/rustup update".to_string(),
-        // TODO sim link rustc from home/ec2-user/bin
-        format!("ln -s /home/ec2-user/.cargo/bin/cargo {}/cargo", STATE.host_bin_path())
-
-
-    ]).await.expect("Timed out")
+    setup: HostSetup<'_>,
+) -> OrchResult<Option<SendCommandOutput>> {
+    // hosts which are only rebuilt already have their dependencies
+    let HostSetup::Configure {
+        distro,
+        shutdown_min,
+        build_cache,
+        instance_store,
+    } = setup
+    else {
+        return Ok(None);
+    };
+    let mut parameters = vec![
+        ("shutdownMin", shutdown_min.to_string()),
+        ("instanceStore", instance_store.to_string()),
+        (
+            "statusPrefix",
+            format!("{}/{}", STATE.s3_path(unique_id), host_group),
+        ),
+    ];
+    if let Some(build_cache) = build_cache {
+        parameters.extend([
+            ("sccacheBucket", build_cache.bucket.clone()),
+            ("sccachePrefix", build_cache.prefix.clone()),
+            ("sccacheRegion", build_cache.region.clone()),
+            ("sccacheVersion", build_cache.version.clone()),
+        ]);
+    }
+    document::configure_host(distro)
+        .send(
+            host_group,
+            &format!("configure_host_{}", host_group),
+            ssm_client,
+            instance_ids,
+            parameters,
+        )
+        .await
+        .map(Some)
 }
 
 async fn build_netbench_driver_cmd(
@@ -129,10 +442,10 @@ async fn build_netbench_driver_cmd(
     ssm_client: &aws_sdk_ssm::Client,
     instance_ids: Vec<String>,
     unique_id: &str,
-) -> SendCommandOutput {
+) -> OrchResult<SendCommandOutput> {
     send_command(
         vec![Step::Configure],
-        Step::BuildDriver(driver.driver_name.clone()),
+        Step::BuildDriver(driver.build_id(unique_id)),
         host_group,
         &format!("build_driver_{}", driver.proj_name),
         ssm_client,
@@ -149,39 +462,29 @@ async fn build_netbench_driver_cmd(
             ),
         ]
         .into_iter()
-        .chain(driver.ssm_build_cmd.clone().into_iter())
-        .map(String::from)
+        .chain(driver.ssm_build_cmd.clone())
+        // trace the results to the exact version of the driver
+        .chain([BuildInfo::record_cmd(host_group, driver, unique_id)])
         .collect(),
     )
     .await
-    .expect("Timed out")
 }
 
 async fn build_russula_cmd(
     host_group: &str,
     ssm_client: &aws_sdk_ssm::Client,
     instance_ids: Vec<String>,
-) -> SendCommandOutput {
-    send_command(
-        vec![Step::Configure],
-        Step::BuildRussula,
-        host_group,
-        &format!("build_russula_{}", host_group),
-        ssm_client,
-        instance_ids,
-        vec![
-            format!(
-                "git clone --branch {} {}",
-                STATE.russula_branch, STATE.russula_repo
-            )
-            .as_str(),
-            "cd netbench_orchestrator",
-            format!("{}/cargo build", STATE.host_bin_path()).as_str(),
-        ]
-        .into_iter()
-        .map(String::from)
-        .collect(),
-    )
-    .await
-    .expect("Timed out")
+) -> OrchResult<SendCommandOutput> {
+    document::build_russula()
+        .send(
+            host_group,
+            &format!("build_russula_{}", host_group),
+            ssm_client,
+            instance_ids,
+            vec![
+                ("repo", STATE.russula_repo.to_string()),
+                ("branch", STATE.russula_branch.to_string()),
+            ],
+        )
+        .await
 }
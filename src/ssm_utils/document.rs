@@ -0,0 +1,461 @@
+// Copyright Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+use super::{assemble_command, send_invocation, Invocation, Step};
+use crate::{
+    ec2_utils::LinuxDistro,
+    error::{OrchError, OrchResult},
+    state::STATE,
+};
+use aws_sdk_ssm::{
+    operation::send_command::SendCommandOutput,
+    types::{DocumentFormat, DocumentType},
+};
+use serde_json::{json, Map, Value};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use tracing::{info, warn};
+
+/// A parameter of a [`Document`], interpolated in its commands as
+/// `{{ name }}`.
+pub struct Parameter {
+    pub name: &'static str,
+    pub description: &'static str,
+    pub default: Option<String>,
+    // Parameters are interpolated in shell commands so they are restricted to
+    // a pattern
+    pub allowed_pattern: &'static str,
+}
+
+/// A host-side script stored as a parameterized SSM document, rather than as
+/// commands inlined in each `send_command`.
+///
+/// The orchestrator creates the document and a new version whenever its
+/// content changes, so the script can be reviewed, and run from the console,
+/// as it was run by the orchestrator.
+pub struct Document {
+    pub name: &'static str,
+    description: &'static str,
+    parameters: Vec<Parameter>,
+    step: Step,
+    // Including the markers used to order the steps on a host
+    commands: Vec<String>,
+}
+
+/// The `{{ name }}` placeholder of a parameter.
+fn param(name: &str) -> String {
+    format!("{{{{ {name} }}}}")
+}
+
+impl Document {
+    fn new(
+        name: &'static str,
+        description: &'static str,
+        parameters: Vec<Parameter>,
+        wait_steps: Vec<Step>,
+        step: Step,
+        commands: Vec<String>,
+    ) -> Self {
+        let commands = assemble_command(wait_steps, &step, commands);
+        Document {
+            name,
+            description,
+            parameters,
+            step,
+            commands,
+        }
+    }
+
+    pub fn content(&self) -> String {
+        let parameters: Map<String, Value> = self
+            .parameters
+            .iter()
+            .map(|parameter| {
+                let mut spec = json!({
+                    "type": "String",
+                    "description": parameter.description,
+                    "allowedPattern": parameter.allowed_pattern,
+                });
+                if let Some(default) = &parameter.default {
+                    spec["default"] = json!(default);
+                }
+                (parameter.name.to_string(), spec)
+            })
+            .collect();
+        let content = json!({
+            "schemaVersion": "2.2",
+            "description": self.description,
+            "parameters": parameters,
+            "mainSteps": [{
+                "action": "aws:runShellScript",
+                "name": self.step.as_str(),
+                "inputs": {
+                    "runCommand": self.commands,
+                },
+            }],
+        });
+        serde_json::to_string_pretty(&content).unwrap()
+    }
+
+    /// Identifies the content of the document, so that an unchanged document
+    /// isn't updated.
+    pub fn version_name(&self) -> String {
+        let digest = format!("{:x}", Sha256::digest(self.content()));
+        digest[..16].to_string()
+    }
+
+    /// Create the document, or a new version of it if its content changed.
+    ///
+    /// Returns the version matching the content.
+    async fn ensure(&self, ssm_client: &aws_sdk_ssm::Client) -> OrchResult<String> {
+        let version_name = self.version_name();
+        if let Some(version) = self.describe_version(ssm_client, &version_name).await {
+            return Ok(version);
+        }
+
+        let content = self.content();
+        let created = ssm_client
+            .create_document()
+            .name(self.name)
+            .content(&content)
+            .document_type(DocumentType::Command)
+            .document_format(DocumentFormat::Json)
+            .version_name(&version_name)
+            .send()
+            .await;
+        let description = match created {
+            Ok(created) => created.document_description().cloned(),
+            Err(err) => {
+                let err = err.into_service_error();
+                if !err.is_document_already_exists() {
+                    return Err(self.err(err));
+                }
+                let updated = ssm_client
+                    .update_document()
+                    .name(self.name)
+                    .content(&content)
+                    .document_format(DocumentFormat::Json)
+                    .document_version("$LATEST")
+                    .version_name(&version_name)
+                    .send()
+                    .await;
+                match updated {
+                    Ok(updated) => updated.document_description().cloned(),
+                    Err(err) => {
+                        let err = err.into_service_error();
+                        // created concurrently, ex: by the other host group
+                        if err.is_duplicate_document_content()
+                            || err.is_duplicate_document_version_name()
+                        {
+                            return self
+                                .describe_version(ssm_client, &version_name)
+                                .await
+                                .ok_or_else(|| self.err(err));
+                        }
+                        return Err(self.err(err));
+                    }
+                }
+            }
+        };
+        let version = description
+            .and_then(|description| description.document_version().map(String::from))
+            .ok_or_else(|| self.err("missing document version"))?;
+        info!(
+            document = self.name,
+            version, "Created ssm document version"
+        );
+
+        // so that the console runs the latest version by default
+        if let Err(err) = ssm_client
+            .update_document_default_version()
+            .name(self.name)
+            .document_version(&version)
+            .send()
+            .await
+        {
+            warn!(
+                "Failed to set the default version of ssm document {}: {}",
+                self.name, err
+            );
+        }
+        Ok(version)
+    }
+
+    async fn describe_version(
+        &self,
+        ssm_client: &aws_sdk_ssm::Client,
+        version_name: &str,
+    ) -> Option<String> {
+        ssm_client
+            .describe_document()
+            .name(self.name)
+            .version_name(version_name)
+            .send()
+            .await
+            .ok()?
+            .document()?
+            .document_version()
+            .map(String::from)
+    }
+
+    fn err(&self, err: impl std::fmt::Display) -> OrchError {
+        OrchError::Ssm {
+            dbg: format!("Failed to create ssm document {}: {}", self.name, err),
+        }
+    }
+
+    /// Run the document on the hosts.
+    pub async fn send(
+        &self,
+        endpoint: &str,
+        comment: &str,
+        ssm_client: &aws_sdk_ssm::Client,
+        ids: Vec<String>,
+        parameters: Vec<(&str, String)>,
+    ) -> OrchResult<SendCommandOutput> {
+        let document_version = self.ensure(ssm_client).await?;
+        let invocation = Invocation {
+            document_name: self.name,
+            document_version,
+            parameters: parameters
+                .into_iter()
+                .map(|(name, value)| (name.to_string(), vec![value]))
+                .collect::<HashMap<_, _>>(),
+        };
+        send_invocation(
+            self.step.as_str(),
+            endpoint,
+            comment,
+            ssm_client,
+            ids,
+            invocation,
+        )
+        .await
+    }
+}
+
+/// Install the dependencies of the drivers and russula with the package manager
+/// of the `distro`, and schedule the host to shutdown after its lifetime.
+pub fn configure_host(distro: LinuxDistro) -> Document {
+    let status = |msg: &str, step: u8| {
+        format!(
+            "echo {msg} > /home/ec2-user/index.html && aws s3 cp /home/ec2-user/index.html {}-step-{step}",
+            param("statusPrefix")
+        )
+    };
+    let sccache_release = format!(
+        "sccache-v{}-$(uname -m)-unknown-linux-musl",
+        param("sccacheVersion")
+    );
+    let install_sccache = format!(
+        "curl --proto '=https' -sSfL https://github.com/mozilla/sccache/releases/download/v{}/{sccache_release}.tar.gz | tar -xz --strip-components=1 -C {} {sccache_release}/sccache",
+        param("sccacheVersion"),
+        STATE.host_bin_path()
+    );
+    // fall back to compiling locally if the cache is unavailable
+    let sccache_config = [
+        "[build]".to_string(),
+        format!("rustc-wrapper = \"{}/sccache\"", STATE.host_bin_path()),
+        "[env]".to_string(),
+        format!("SCCACHE_BUCKET = \"{}\"", param("sccacheBucket")),
+        format!("SCCACHE_S3_KEY_PREFIX = \"{}\"", param("sccachePrefix")),
+        format!("SCCACHE_REGION = \"{}\"", param("sccacheRegion")),
+        "SCCACHE_IGNORE_SERVER_IO_ERROR = \"1\"\\n".to_string(),
+    ]
+    .join("\\n");
+    // the russula checkout, where the workers write the results and captures.
+    // xfs doesn't create a lost+found so it can be cloned into
+    let checkout = format!("{}/netbench_orchestrator", STATE.host_home_path);
+    let mount_instance_store = format!(
+        "[ \"{}\" != true ] || {{ disks=$(lsblk -dnpo NAME,MODEL | awk '/Instance Storage/ {{print $1}}'); count=$(echo $disks | wc -w); if [ $count -gt 1 ]; then {install_mdadm} && mdadm --create /dev/md0 --run --level=0 --raid-devices=$count $disks && disk=/dev/md0; else disk=$disks; fi; [ -z \"$disk\" ] || {{ mkfs.xfs -f $disk && mkdir -p {checkout} && mount $disk {checkout}; }}; }}",
+        param("instanceStore"),
+        install_mdadm = distro.install_cmd(&["mdadm"]),
+    );
+    // the status commands rely on the tools installed by the bootstrap commands
+    let mut commands = distro.bootstrap_cmds();
+    commands.extend([
+        // set instances to shutdown after their lifetime
+        format!("shutdown -P +{}", param("shutdownMin")),
+        "mkdir -p /home/ec2-user/bin".to_string(),
+        status("ec2 up", 1),
+        distro.upgrade_cmd(),
+        status(&format!("{distro} upgrade finished"), 2),
+        format!(
+            "timeout 5m bash -c 'until {}; do sleep 10; done' || {{ echo {distro} install failed > /home/ec2-user/index.html; aws s3 cp /home/ec2-user/index.html {}-step-3; exit 1; }}",
+            distro.install_cmd(distro.packages()),
+            param("statusPrefix")
+        ),
+        status(&format!("{distro} install finished"), 3),
+        mount_instance_store,
+        // rust
+        "runuser -u ec2-user -- curl --proto '=https' --tlsv1.2 -sSf https://sh.rustup.rs > rustup.rs".to_string(),
+        "chmod +x rustup.rs".to_string(),
+        "chgrp ec2-user rustup.rs".to_string(),
+        "chown ec2-user rustup.rs".to_string(),
+        "sh ./rustup.rs -y".to_string(),
+        "runuser -u ec2-user -- sh ./rustup.rs -y".to_string(),
+        "/root/.cargo/bin/rustup update".to_string(),
+        "runuser -u ec2-user -- ./.cargo/bin/rustup update".to_string(),
+        // TODO sim link rustc from home/ec2-user/bin
+        format!(
+            "ln -sf /home/ec2-user/.cargo/bin/cargo {}/cargo",
+            STATE.host_bin_path()
+        ),
+        // used to convert perf profiles to flamegraphs
+        format!(
+            "git clone --depth 1 https://github.com/brendangregg/FlameGraph.git {} || true",
+            STATE.host_flamegraph_path()
+        ),
+        // the builds under /home/ec2-user pick up its cargo config, which is
+        // only written once sccache is installed
+        format!(
+            "[ -z \"{}\" ] || {{ {install_sccache} && printf '{sccache_config}' > /home/ec2-user/.cargo/config.toml; }}",
+            param("sccacheBucket")
+        ),
+    ]);
+    Document::new(
+        distro.configure_document_name(),
+        "Install the dependencies of the netbench drivers and russula",
+        vec![
+            Parameter {
+                name: "shutdownMin",
+                description: "Shutdown the host after this many minutes",
+                default: None,
+                allowed_pattern: "^[0-9]+$",
+            },
+            Parameter {
+                name: "instanceStore",
+                description: "Mount the NVMe instance store disks at the russula checkout",
+                default: Some("false".to_string()),
+                allowed_pattern: "^(true|false)$",
+            },
+            Parameter {
+                name: "statusPrefix",
+                description: "The s3 prefix of the host group's status on the dashboard. ex: s3://bucket/<unique_id>/server",
+                default: None,
+                allowed_pattern: "^s3://[a-zA-Z0-9_./:-]+$",
+            },
+            Parameter {
+                name: "sccacheBucket",
+                description: "The S3 bucket of the sccache build cache, or empty to build without sccache",
+                default: Some(String::new()),
+                allowed_pattern: "^[a-zA-Z0-9.-]*$",
+            },
+            Parameter {
+                name: "sccachePrefix",
+                description: "The key prefix of the sccache build cache",
+                default: Some("sccache".to_string()),
+                allowed_pattern: "^[a-zA-Z0-9_./-]*$",
+            },
+            Parameter {
+                name: "sccacheRegion",
+                description: "The region of the sccache bucket",
+                default: Some(STATE.region.to_string()),
+                allowed_pattern: "^[a-zA-Z0-9-]*$",
+            },
+            Parameter {
+                name: "sccacheVersion",
+                description: "The sccache release installed on the host",
+                default: Some("0.8.1".to_string()),
+                allowed_pattern: "^[a-zA-Z0-9.]*$",
+            },
+        ],
+        vec![],
+        Step::Configure,
+        commands,
+    )
+}
+
+/// Build russula from a branch of the orchestrator's repository.
+pub fn build_russula() -> Document {
+    Document::new(
+        "netbench-build-russula",
+        "Build russula, which coordinates the netbench drivers",
+        vec![
+            Parameter {
+                name: "repo",
+                description: "The git repository of russula",
+                default: Some(STATE.russula_repo.to_string()),
+                allowed_pattern: "^[a-zA-Z0-9_./:@-]+$",
+            },
+            Parameter {
+                name: "branch",
+                description: "The branch of russula",
+                default: Some(STATE.russula_branch.to_string()),
+                allowed_pattern: "^[a-zA-Z0-9_./-]+$",
+            },
+        ],
+        vec![Step::Configure],
+        Step::BuildRussula,
+        vec![
+            // hosts reused from an infra pool already have a checkout
+            format!(
+                "git clone --branch {} {} || git -C netbench_orchestrator pull",
+                param("branch"),
+                param("repo")
+            ),
+            "cd netbench_orchestrator".to_string(),
+            format!("{}/cargo build", STATE.host_bin_path()),
+        ],
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ssm_document() {
+        let document = configure_host(LinuxDistro::Al2023);
+        let content: Value = serde_json::from_str(&document.content()).unwrap();
+        assert_eq!(content["schemaVersion"], "2.2");
+        assert_eq!(
+            content["parameters"]["shutdownMin"]["allowedPattern"],
+            "^[0-9]+$"
+        );
+        let commands = content["mainSteps"][0]["inputs"]["runCommand"]
+            .as_array()
+            .unwrap();
+        // wrapped with the step markers
+        assert!(commands[0].as_str().unwrap().contains("done_configure___"));
+        assert!(commands
+            .iter()
+            .any(|cmd| cmd == "shutdown -P +{{ shutdownMin }}"));
+        // sccache is only installed with a bucket
+        let sccache = commands
+            .iter()
+            .filter_map(|cmd| cmd.as_str())
+            .find(|cmd| cmd.contains("sccache"))
+            .unwrap();
+        assert!(sccache.starts_with("[ -z \"{{ sccacheBucket }}\" ] || { curl "));
+        assert!(sccache.contains("SCCACHE_BUCKET = \"{{ sccacheBucket }}\"\\n"));
+        // the instance store is only mounted if enabled
+        assert!(commands.iter().any(|cmd| cmd
+            .as_str()
+            .unwrap()
+            .starts_with("[ \"{{ instanceStore }}\" != true ] || { disks=")));
+
+        // versioned by content
+        assert_eq!(
+            document.version_name(),
+            configure_host(LinuxDistro::Al2023).version_name()
+        );
+        assert_ne!(document.version_name(), build_russula().version_name());
+        assert_eq!(document.version_name().len(), 16);
+
+        // each distribution installs with its own package manager
+        let ubuntu = configure_host(LinuxDistro::Ubuntu);
+        assert_eq!(ubuntu.name, "netbench-configure-host-ubuntu");
+        assert!(ubuntu
+            .content()
+            .contains("apt-get install -y build-essential"));
+        assert!(!ubuntu.content().contains("yum"));
+        assert!(document.content().contains("yum install -y cargo"));
+
+        let russula: Value = serde_json::from_str(&build_russula().content()).unwrap();
+        assert_eq!(
+            russula["parameters"]["branch"]["default"],
+            STATE.russula_branch
+        );
+    }
+}
@@ -0,0 +1,107 @@
+// Copyright Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+use super::{
+    command_id, common::wait_complete, iperf3_client_cmd, iperf3_pairs, iperf3_server_cmds,
+    send_command, wait_for_ssm_results, Step,
+};
+use crate::{config::Iperf3, error::OrchResult, state::STATE, InfraDetail};
+use indicatif::MultiProgress;
+use tracing::{info, warn};
+
+// The run's folder holding a `client-<instance_id>-<protocol>.json` iperf3
+// result per client and protocol
+pub const IPERF3_DIR: &str = "iperf3";
+
+/// Run an iperf3 baseline from each client to its own server, for each
+/// protocol in turn, and upload the json results to the run's `iperf3/` folder.
+///
+/// The clients run concurrently so that the baseline measures the same
+/// aggregate load as the scenarios.
+pub async fn run_baseline(
+    ssm_client: &aws_sdk_ssm::Client,
+    infra: &InfraDetail,
+    unique_id: &str,
+    iperf3: &Iperf3,
+) -> OrchResult<()> {
+    if infra.clients.is_empty() || infra.servers.is_empty() {
+        warn!("Skipping the iperf3 baseline, which needs Linux clients and servers");
+        return Ok(());
+    }
+    let pairs = iperf3_pairs(infra.clients.len(), infra.servers.len());
+    // the clients of each server
+    let ports = infra.clients.len().div_ceil(infra.servers.len()) as u16;
+    let server_ids: Vec<String> = infra
+        .servers
+        .iter()
+        .map(|host| host.instance_id.clone())
+        .collect();
+
+    let start_servers = send_command(
+        vec![],
+        Step::Iperf3,
+        "server",
+        "iperf3_server",
+        ssm_client,
+        server_ids.clone(),
+        std::iter::once(format!("cd {}", STATE.host_home_path))
+            .chain(iperf3_server_cmds(ports))
+            .collect(),
+    )
+    .await?;
+    wait_for_ssm_results("server", ssm_client, command_id(&start_servers)?).await?;
+
+    // each client targets a different server and port
+    let mut clients = Vec::new();
+    for (client, (server, port)) in infra.clients.iter().zip(pairs) {
+        let server = &infra.servers[server];
+        let mut cmds = vec![format!("cd {}", STATE.host_home_path)];
+        for protocol in iperf3.protocols.iter() {
+            let output = format!("client-{}-{}.json", client.instance_id, protocol.as_str());
+            cmds.push(iperf3_client_cmd(
+                iperf3,
+                *protocol,
+                &server.private_ip,
+                port,
+                &output,
+            ));
+            cmds.push(format!(
+                "aws s3 mv {output} {}/{IPERF3_DIR}/{output}",
+                STATE.s3_path(unique_id)
+            ));
+        }
+        clients.push(
+            send_command(
+                vec![],
+                Step::Iperf3,
+                "client",
+                "iperf3_client",
+                ssm_client,
+                vec![client.instance_id.clone()],
+                cmds,
+            )
+            .await?,
+        );
+    }
+    let baseline = wait_complete("client", ssm_client, clients, &MultiProgress::new()).await;
+
+    // stop the servers even if a client failed so they don't skew the scenarios
+    let stop_servers = send_command(
+        vec![],
+        Step::Iperf3,
+        "server",
+        "iperf3_server_stop",
+        ssm_client,
+        server_ids,
+        vec!["pkill -x iperf3 || true".to_string()],
+    )
+    .await?;
+    wait_for_ssm_results("server", ssm_client, command_id(&stop_servers)?).await?;
+    baseline?;
+
+    info!(
+        "iperf3 baseline of {} clients: Successful",
+        infra.clients.len()
+    );
+    Ok(())
+}
@@ -1,17 +1,22 @@
 // Copyright Amazon.com, Inc. or its affiliates. All Rights Reserved.
 // SPDX-License-Identifier: Apache-2.0
 
-use crate::STATE;
-use std::{
-    path::PathBuf,
-    process::{Command, Stdio},
+use crate::{
+    error::{OrchError, OrchResult},
+    s3_utils::delta::SourceManifest,
+    STATE,
 };
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::{path::PathBuf, sync::OnceLock};
 use tracing::debug;
 
+mod iperf3_driver;
 mod s2n_quic_dc_driver;
 mod s2n_quic_driver;
 mod tcp_driver;
 
+pub use iperf3_driver::*;
 pub use s2n_quic_dc_driver::*;
 pub use s2n_quic_driver::*;
 pub use tcp_driver::*;
@@ -25,27 +30,259 @@ pub struct NetbenchDriver {
     //
     // upload to s3 locally and download form s3 in ssm_build_cmd
     local_path_to_proj: Option<PathBuf>,
+    // The digest of the local source, once uploaded
+    source_digest: OnceLock<String>,
 }
 
-// This local command runs twice; once for server and once for client.
-// For this reason `aws sync` is preferred over `aws cp` since sync avoids
-// object copy if the same copy already exists.
-fn local_upload_source_to_s3(local_path_to_proj: &PathBuf, proj_name: &str, unique_id: &str) {
-    let mut local_to_s3_cmd = Command::new("aws");
-    local_to_s3_cmd.args(["s3", "sync"]).stdout(Stdio::null());
-    local_to_s3_cmd
-        .arg(format!(
-            "{}/{}",
-            local_path_to_proj.to_str().unwrap(),
-            proj_name
-        ))
-        .arg(format!(
-            "{}/{}/",
-            STATE.s3_private_path(unique_id),
-            proj_name
-        ));
-    local_to_s3_cmd.args(["--exclude", "target/*", "--exclude", ".git/*"]);
-    debug!("{:?}", local_to_s3_cmd);
-    let status = local_to_s3_cmd.status().unwrap();
-    assert!(status.success(), "aws sync command failed");
+// The private bucket's folder holding the files of the local sources by sha256
+const SOURCE_OBJECTS: &str = "sources";
+// The manifest of a host's copy of a local source
+const SOURCE_MANIFEST: &str = ".netbench-source.sha256";
+
+impl NetbenchDriver {
+    /// Upload the local driver source so that it can be built on the hosts.
+    ///
+    /// The files are stored by sha256, so only the files which changed since a
+    /// previous run are uploaded, along with a manifest of the source for the
+    /// run which the hosts sync against with [`sync_local_source_cmds`].
+    ///
+    /// This is a no-op for drivers which are built from a public repository.
+    pub async fn upload_local_source(
+        &self,
+        s3_client: &aws_sdk_s3::Client,
+        unique_id: &str,
+    ) -> OrchResult<()> {
+        let local_path_to_proj = match &self.local_path_to_proj {
+            Some(local_path_to_proj) => local_path_to_proj,
+            None => return Ok(()),
+        };
+
+        let local_dir = local_path_to_proj.join(&self.proj_name);
+        let manifest = SourceManifest::from_dir(&local_dir, &["target/", ".git/"])?;
+        let uploaded = manifest
+            .upload(
+                s3_client,
+                &local_dir,
+                STATE.s3_private_log_bucket,
+                &format!("{}/{}", SOURCE_OBJECTS, self.proj_name),
+            )
+            .await?;
+        manifest
+            .upload_manifest(
+                s3_client,
+                STATE.s3_private_log_bucket,
+                &format!("{}/{}.sha256", unique_id, self.proj_name),
+            )
+            .await?;
+        debug!("uploaded {} changed files for {}", uploaded, self.proj_name);
+        let _ = self.source_digest.set(manifest.digest());
+        Ok(())
+    }
+
+    /// Identifies a build of the driver on a host, so that a host which already
+    /// built the same driver skips the build.
+    ///
+    /// The build of a local source is identified by the source's digest once it's
+    /// uploaded, and is otherwise specific to the run.
+    pub fn build_id(&self, unique_id: &str) -> String {
+        let mut hasher = Sha256::new();
+        for cmd in &self.ssm_build_cmd {
+            hasher.update(cmd);
+            hasher.update("\n");
+        }
+        if self.local_path_to_proj.is_some() {
+            hasher.update(self.source_digest.get().map_or(unique_id, String::as_str));
+        }
+        let digest = format!("{:x}", hasher.finalize());
+        format!("{}_{}", self.driver_name, &digest[..12])
+    }
+}
+
+/// Sync the local source uploaded by [`NetbenchDriver::upload_local_source`]
+/// to `<host_home>/<proj_name>`.
+///
+/// Only the files which differ from the host's copy are downloaded, and the
+/// files removed from the source are deleted, so unchanged files keep their
+/// mtime and cargo rebuilds the source incrementally.
+pub(crate) fn sync_local_source_cmds(proj_name: &str, unique_id: &str) -> Vec<String> {
+    let dir = format!("{}/{}", STATE.host_home_path, proj_name);
+    let objects = format!(
+        "s3://{}/{}/{}",
+        STATE.s3_private_log_bucket, SOURCE_OBJECTS, proj_name
+    );
+    vec![
+        format!("mkdir -p {dir}"),
+        format!(
+            "aws s3 cp --only-show-errors {}/{proj_name}.sha256 {dir}/{SOURCE_MANIFEST}.new || exit 1",
+            STATE.s3_private_path(unique_id)
+        ),
+        // the paths follow the 64 character sha256 and 2 spaces
+        format!(
+            "(cd {dir} && if [ -f {SOURCE_MANIFEST} ]; then \
+             cut -c67- {SOURCE_MANIFEST} | sort > {SOURCE_MANIFEST}.old; \
+             cut -c67- {SOURCE_MANIFEST}.new | sort | comm -23 {SOURCE_MANIFEST}.old - \
+             | while IFS= read -r path; do rm -f -- \"$path\"; done; fi)"
+        ),
+        format!(
+            "(cd {dir} && while read -r hash path; do \
+             [ \"$(sha256sum -- \"$path\" 2>/dev/null | cut -c1-64)\" = \"$hash\" ] \
+             || aws s3 cp --only-show-errors {objects}/$hash \"$path\" || exit 1; \
+             done < {SOURCE_MANIFEST}.new) || exit 1"
+        ),
+        format!("mv {dir}/{SOURCE_MANIFEST}.new {dir}/{SOURCE_MANIFEST}"),
+    ]
+}
+
+/// The git source of the drivers which are built from a repository.
+///
+/// ex: a fork and feature branch, or a PR with `--driver-rev pull/123/head`
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DriverSource {
+    pub repo: String,
+    pub branch: String,
+    // A commit or ref which is fetched and checked out after cloning the branch
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rev: Option<String>,
+}
+
+impl Default for DriverSource {
+    fn default() -> Self {
+        DriverSource {
+            repo: STATE.netbench_repo.to_string(),
+            branch: STATE.netbench_branch.to_string(),
+            rev: None,
+        }
+    }
+}
+
+impl DriverSource {
+    /// The values are interpolated into the build commands so only allow
+    /// characters found in urls and git refs.
+    pub fn validate(&self) -> OrchResult<()> {
+        for value in [&self.repo, &self.branch].into_iter().chain(&self.rev) {
+            let valid = !value.is_empty()
+                && value
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || "-_./:@+~^".contains(c));
+            if !valid {
+                return Err(OrchError::Init {
+                    dbg: format!("Invalid driver repo, branch or rev: {:?}", value),
+                });
+            }
+        }
+        Ok(())
+    }
+
+    /// Clone the source into `proj_name`.
+    pub(crate) fn clone_cmds(&self, proj_name: &str) -> Vec<String> {
+        let mut cmds = vec![format!(
+            "git clone --branch {} {} {proj_name}",
+            self.branch, self.repo
+        )];
+        if let Some(rev) = &self.rev {
+            cmds.push(format!("git -C {proj_name} fetch origin {rev}"));
+            cmds.push(format!("git -C {proj_name} checkout FETCH_HEAD"));
+        }
+        cmds
+    }
+
+    /// The [`DriverSource::clone_cmds`] of a Linux host, where the drivers of
+    /// a project share its checkout and a driver can be built once it's cloned.
+    pub(crate) fn linux_clone_cmds(&self, proj_name: &str) -> Vec<String> {
+        let mut cmds = self.clone_cmds(proj_name);
+        cmds[0] = format!("{} || [ -d {proj_name}/.git ]", cmds[0]);
+        cmds
+    }
+}
+
+impl std::fmt::Display for DriverSource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} {}", self.repo, self.branch)?;
+        if let Some(rev) = &self.rev {
+            write!(f, " {}", rev)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn driver_source() {
+        let source = DriverSource::default();
+        source.validate().unwrap();
+        assert_eq!(
+            source.clone_cmds("s2n-netbench"),
+            vec![format!(
+                "git clone --branch main {} s2n-netbench",
+                STATE.netbench_repo
+            )]
+        );
+
+        assert_eq!(
+            source.linux_clone_cmds("s2n-netbench")[0],
+            format!(
+                "git clone --branch main {} s2n-netbench || [ -d s2n-netbench/.git ]",
+                STATE.netbench_repo
+            )
+        );
+
+        let pr = DriverSource {
+            repo: "https://github.com/me/s2n-netbench.git".to_string(),
+            branch: "main".to_string(),
+            rev: Some("pull/123/head".to_string()),
+        };
+        pr.validate().unwrap();
+        let cmds = pr.clone_cmds("s2n-netbench");
+        assert_eq!(cmds[1], "git -C s2n-netbench fetch origin pull/123/head");
+        assert_eq!(cmds[2], "git -C s2n-netbench checkout FETCH_HEAD");
+
+        let injected = DriverSource {
+            branch: "main; rm -rf /".to_string(),
+            ..Default::default()
+        };
+        assert!(injected.validate().is_err());
+    }
+
+    #[test]
+    fn driver_build_id() {
+        let driver = quic_server_driver(&DriverSource::default());
+        let build_id = driver.build_id("run-1");
+        assert!(build_id.starts_with("s2n-netbench-driver-server-s2n-quic_"));
+        // a driver built from a repository is the same build across runs
+        assert_eq!(build_id, driver.build_id("run-2"));
+
+        let pr = quic_server_driver(&DriverSource {
+            rev: Some("pull/123/head".to_string()),
+            ..Default::default()
+        });
+        assert_ne!(build_id, pr.build_id("run-1"));
+
+        let local = dc_quic_server_driver("run-1");
+        assert_ne!(local.build_id("run-1"), local.build_id("run-2"));
+        // once uploaded, the build of a local source is identified by its contents
+        local.source_digest.set("digest".to_string()).unwrap();
+        assert_eq!(local.build_id("run-1"), local.build_id("run-2"));
+    }
+
+    #[test]
+    fn local_source_sync() {
+        let cmds = sync_local_source_cmds("SaltyLib-Rust", "run-1");
+        assert_eq!(
+            cmds[1],
+            format!(
+                "aws s3 cp --only-show-errors s3://{}/run-1/SaltyLib-Rust.sha256 {}/SaltyLib-Rust/.netbench-source.sha256.new || exit 1",
+                STATE.s3_private_log_bucket, STATE.host_home_path
+            )
+        );
+        // only the files which differ are downloaded
+        assert!(cmds[3].contains(&format!(
+            "|| aws s3 cp --only-show-errors s3://{}/sources/SaltyLib-Rust/$hash \"$path\" || exit 1;",
+            STATE.s3_private_log_bucket
+        )));
+        // a failed download fails the build rather than only the subshell
+        assert!(cmds[3].ends_with("done < .netbench-source.sha256.new) || exit 1"));
+    }
 }
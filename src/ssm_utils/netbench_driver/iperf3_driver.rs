@@ -0,0 +1,112 @@
+// Copyright Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+use super::NetbenchDriver;
+use crate::{
+    config::{Iperf3, Iperf3Protocol},
+    ec2_utils::host_os::ensure_package_cmd,
+};
+use std::sync::OnceLock;
+
+// The first port of the iperf3 servers. A server only runs one test at a time
+// so each of its clients connects to its own port: 5201, 5202, ...
+pub const IPERF3_BASE_PORT: u16 = 5201;
+
+/// iperf3 is installed from the distribution's packages rather than built,
+/// and is run by the baseline step rather than by the russula Workers.
+fn iperf3_driver(host_group: &str) -> NetbenchDriver {
+    NetbenchDriver {
+        driver_name: format!("iperf3-{host_group}"),
+        ssm_build_cmd: vec![ensure_package_cmd("iperf3")],
+        proj_name: "iperf3".to_string(),
+        local_path_to_proj: None,
+        source_digest: OnceLock::new(),
+    }
+}
+
+pub fn iperf3_server_driver() -> NetbenchDriver {
+    iperf3_driver("server")
+}
+
+pub fn iperf3_client_driver() -> NetbenchDriver {
+    iperf3_driver("client")
+}
+
+/// The server and port of each client: clients are spread across the servers
+/// round robin, and the clients of a server use consecutive ports.
+pub fn iperf3_pairs(clients: usize, servers: usize) -> Vec<(usize, u16)> {
+    (0..clients)
+        .map(|i| (i % servers, IPERF3_BASE_PORT + (i / servers) as u16))
+        .collect()
+}
+
+/// Start a daemonized iperf3 server on each of the `ports` ports of the host.
+pub fn iperf3_server_cmds(ports: u16) -> Vec<String> {
+    vec![
+        // a server left behind by a previous run of the baseline
+        "pkill -x iperf3 || true".to_string(),
+        format!(
+            "for port in $(seq {IPERF3_BASE_PORT} {}); do iperf3 --server --daemon --port $port; done",
+            IPERF3_BASE_PORT + ports.max(1) - 1
+        ),
+    ]
+}
+
+/// Run a test against `server` and write its json output to `output`.
+///
+/// iperf3 writes its error to the json output if the test fails, so the
+/// command doesn't fail the step and the error is reported with the results.
+pub fn iperf3_client_cmd(
+    iperf3: &Iperf3,
+    protocol: Iperf3Protocol,
+    server: &str,
+    port: u16,
+    output: &str,
+) -> String {
+    let mut cmd = format!(
+        "iperf3 --client {server} --port {port} --parallel {} --json",
+        iperf3.parallel
+    );
+    if let Some(duration) = iperf3.duration {
+        cmd.push_str(&format!(" --time {}", duration.as_secs()));
+    }
+    if protocol == Iperf3Protocol::Udp {
+        cmd.push_str(&format!(" --udp --bitrate {}", iperf3.udp_bitrate));
+    }
+    format!("{cmd} > {output} || true")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::time::Duration;
+
+    #[test]
+    fn iperf3_commands() {
+        assert_eq!(
+            iperf3_pairs(5, 2),
+            vec![(0, 5201), (1, 5201), (0, 5202), (1, 5202), (0, 5203)]
+        );
+        assert_eq!(
+            iperf3_server_cmds(3)[1],
+            "for port in $(seq 5201 5203); do iperf3 --server --daemon --port $port; done"
+        );
+
+        let iperf3 = Iperf3 {
+            duration: Some(Duration::from_secs(30)),
+            parallel: 4,
+            ..Default::default()
+        };
+        assert_eq!(
+            iperf3_client_cmd(&iperf3, Iperf3Protocol::Tcp, "10.0.0.1", 5202, "out.json"),
+            "iperf3 --client 10.0.0.1 --port 5202 --parallel 4 --json --time 30 > out.json || true"
+        );
+        assert!(
+            iperf3_client_cmd(&iperf3, Iperf3Protocol::Udp, "10.0.0.1", 5202, "out.json")
+                .contains(" --udp --bitrate 10G ")
+        );
+        assert!(iperf3_server_driver()
+            .build_id("run-1")
+            .starts_with("iperf3-server_"));
+    }
+}
@@ -0,0 +1,157 @@
+// Copyright Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+use super::{command_id, command_output, output_value, send_command, wait_for_ssm_results, Step};
+use crate::{
+    ec2_utils::host_os::ensure_package_cmd,
+    error::{OrchError, OrchResult},
+    InfraDetail,
+};
+use serde::{Deserialize, Serialize};
+use tracing::info;
+
+// The drivers of the ENA and of the older Intel 82599 VF (SR-IOV) enhanced
+// networking
+const ENHANCED_NETWORKING_DRIVERS: [&str; 2] = ["ena", "ixgbevf"];
+
+/// The primary network interface of a host.
+///
+/// Recorded in the run's manifest since results aren't comparable across
+/// hosts with different network capabilities.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct NetworkInterface {
+    pub instance_id: String,
+    pub host_group: String,
+    pub interface: String,
+    pub driver: String,
+    pub driver_version: String,
+    pub mtu: u16,
+    pub rx_queues: u16,
+}
+
+/// Set the MTU of the hosts' primary interface if `mtu` is set, then verify
+/// that enhanced networking is active and that the MTU is the expected one.
+pub async fn verify_network(
+    ssm_client: &aws_sdk_ssm::Client,
+    infra: &InfraDetail,
+    mtu: Option<u16>,
+) -> OrchResult<Vec<NetworkInterface>> {
+    let mut cmds = vec![
+        ensure_package_cmd("ethtool"),
+        "iface=$(ip route show default | awk '{print $5; exit}')".to_string(),
+        "echo interface=$iface".to_string(),
+    ];
+    if let Some(mtu) = mtu {
+        cmds.push(format!("ip link set dev $iface mtu {mtu}"));
+    }
+    cmds.extend([
+        "ethtool -i $iface | awk '/^driver:/ {print \"driver=\"$2} /^version:/ {print \"driver_version=\"$2}'".to_string(),
+        "echo mtu=$(cat /sys/class/net/$iface/mtu)".to_string(),
+        "echo rx_queues=$(ls -d /sys/class/net/$iface/queues/rx-* | wc -l)".to_string(),
+    ]);
+    let cmd = send_command(
+        vec![],
+        Step::NetworkCheck,
+        "all",
+        "network_check",
+        ssm_client,
+        infra.instance_ids(),
+        cmds,
+    )
+    .await?;
+    let command_id = command_id(&cmd)?;
+    wait_for_ssm_results("all", ssm_client, command_id).await?;
+
+    let mut interfaces = Vec::new();
+    for host in infra.hosts() {
+        let stdout =
+            command_output(ssm_client, "network_check", command_id, &host.instance_id).await?;
+        let interface = parse_interface(&host.instance_id, &host.group(), &stdout).ok_or(
+            OrchError::Ec2Instance {
+                instance_id: host.instance_id.clone(),
+                dbg: format!("Failed to parse the network interface: {}", stdout),
+            },
+        )?;
+        check_interface(&interface, mtu).map_err(|dbg| OrchError::Ec2Instance {
+            instance_id: host.instance_id.clone(),
+            dbg,
+        })?;
+        info!(
+            instance_id = %host.instance_id,
+            driver = %interface.driver,
+            mtu = interface.mtu,
+            rx_queues = interface.rx_queues,
+            "network interface"
+        );
+        interfaces.push(interface);
+    }
+    Ok(interfaces)
+}
+
+/// Parse the `key=value` lines printed by the network check.
+fn parse_interface(instance_id: &str, host_group: &str, output: &str) -> Option<NetworkInterface> {
+    let value = |key: &str| output_value(output, key).map(String::from);
+    Some(NetworkInterface {
+        instance_id: instance_id.to_string(),
+        host_group: host_group.to_string(),
+        interface: value("interface").filter(|iface| !iface.is_empty())?,
+        driver: value("driver")?,
+        driver_version: value("driver_version").unwrap_or_default(),
+        mtu: value("mtu")?.parse().ok()?,
+        rx_queues: value("rx_queues")?.parse().ok()?,
+    })
+}
+
+fn check_interface(interface: &NetworkInterface, mtu: Option<u16>) -> Result<(), String> {
+    if !ENHANCED_NETWORKING_DRIVERS.contains(&interface.driver.as_str()) {
+        return Err(format!(
+            "Enhanced networking isn't active on {}: driver {}",
+            interface.interface, interface.driver
+        ));
+    }
+    match mtu {
+        Some(mtu) if mtu != interface.mtu => Err(format!(
+            "MTU of {} is {} rather than {}",
+            interface.interface, interface.mtu, mtu
+        )),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn network_interface() {
+        let output = "interface=ens5\ndriver=ena\ndriver_version=2.8.6g\nmtu=9001\nrx_queues=8\n";
+        let interface = parse_interface("i-1", "server", output).unwrap();
+        assert_eq!(
+            interface,
+            NetworkInterface {
+                instance_id: "i-1".to_string(),
+                host_group: "server".to_string(),
+                interface: "ens5".to_string(),
+                driver: "ena".to_string(),
+                driver_version: "2.8.6g".to_string(),
+                mtu: 9001,
+                rx_queues: 8,
+            }
+        );
+        assert!(check_interface(&interface, None).is_ok());
+        assert!(check_interface(&interface, Some(9001)).is_ok());
+        assert!(check_interface(&interface, Some(1500)).is_err());
+
+        let netfront = NetworkInterface {
+            driver: "vif".to_string(),
+            ..interface
+        };
+        assert!(check_interface(&netfront, None).is_err());
+
+        // the default route wasn't found
+        assert_eq!(
+            parse_interface("i-1", "server", "interface=\nmtu=1500\n"),
+            None
+        );
+    }
+}
@@ -0,0 +1,109 @@
+// Copyright Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::{
+    error::{OrchError, OrchResult},
+    state::STATE,
+};
+use core::time::Duration;
+use std::{
+    io::{BufRead, BufReader},
+    net::{Ipv4Addr, SocketAddr, TcpListener},
+    process::{Child, Command, Stdio},
+    sync::mpsc,
+    time::Instant,
+};
+use tracing::{debug, info};
+
+// Printed by the session-manager-plugin once the local port is open
+const SESSION_READY: &str = "Waiting for connections";
+const SESSION_START_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// A local port relayed to a port on an instance by an SSM port forwarding
+/// session. Requires the `session-manager-plugin` to be installed locally.
+///
+/// The session is terminated when dropped.
+#[derive(Debug)]
+pub struct PortForward {
+    child: Child,
+    pub local_addr: SocketAddr,
+}
+
+impl PortForward {
+    pub async fn start(instance_id: &str, remote_port: u16) -> OrchResult<Self> {
+        let local_addr = free_local_addr()?;
+        let mut cmd = Command::new("aws");
+        cmd.args([
+            "ssm",
+            "start-session",
+            "--region",
+            STATE.vpc_region,
+            "--target",
+            instance_id,
+            "--document-name",
+            "AWS-StartPortForwardingSession",
+            "--parameters",
+            &format!(
+                r#"{{"portNumber":["{}"],"localPortNumber":["{}"]}}"#,
+                remote_port,
+                local_addr.port()
+            ),
+        ])
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped());
+        let mut child = cmd.spawn().map_err(|err| OrchError::Ssm {
+            dbg: format!(
+                "Failed to start port forwarding session. Is the session-manager-plugin installed? {}",
+                err
+            ),
+        })?;
+
+        // Drain the session output so that the plugin doesn't block on a full pipe
+        let stdout = child.stdout.take().expect("stdout is piped");
+        let (ready_tx, ready_rx) = mpsc::channel();
+        let target = instance_id.to_string();
+        std::thread::spawn(move || {
+            for line in BufReader::new(stdout).lines().map_while(Result::ok) {
+                debug!("port forward {}: {}", target, line);
+                if line.contains(SESSION_READY) {
+                    let _ = ready_tx.send(());
+                }
+            }
+        });
+
+        let port_forward = PortForward { child, local_addr };
+        let start = Instant::now();
+        while ready_rx.try_recv().is_err() {
+            if start.elapsed() > SESSION_START_TIMEOUT {
+                return Err(OrchError::Ssm {
+                    dbg: format!(
+                        "Port forwarding session to {}:{} didn't start within {:?}",
+                        instance_id, remote_port, SESSION_START_TIMEOUT
+                    ),
+                });
+            }
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+        info!(
+            "forwarding {} to {}:{}",
+            local_addr, instance_id, remote_port
+        );
+        Ok(port_forward)
+    }
+}
+
+impl Drop for PortForward {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+// Let the OS pick an unused local port
+fn free_local_addr() -> OrchResult<SocketAddr> {
+    TcpListener::bind((Ipv4Addr::LOCALHOST, 0))
+        .and_then(|listener| listener.local_addr())
+        .map_err(|err| OrchError::Init {
+            dbg: format!("Failed to find a free local port: {}", err),
+        })
+}
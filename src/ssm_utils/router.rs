@@ -0,0 +1,85 @@
+// Copyright Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+use super::{common::wait_complete, send_command, Step, WorkerOptions};
+use crate::{
+    ec2_utils::{EndpointType, InfraDetail},
+    error::OrchResult,
+};
+use aws_sdk_ssm::operation::send_command::SendCommandOutput;
+use indicatif::MultiProgress;
+use tracing::debug;
+
+pub async fn run_russula_worker(
+    ssm_client: &aws_sdk_ssm::Client,
+    instance_ids: Vec<String>,
+    worker_opts: &WorkerOptions,
+) -> OrchResult<SendCommandOutput> {
+    let router_cmd = format!(
+        "env RUST_LOG=debug ./target/debug/russula_cli --transport {} netbench-router-worker {}",
+        worker_opts.transport,
+        worker_opts.russula_addr_args(&EndpointType::Router)
+    );
+    debug!("{}", router_cmd);
+
+    send_command(
+        // routers don't build any drivers
+        vec![Step::BuildRussula],
+        Step::RunRussula,
+        "router",
+        "run_router_russula",
+        ssm_client,
+        instance_ids,
+        vec!["cd netbench_orchestrator", router_cmd.as_str()]
+            .into_iter()
+            .map(String::from)
+            .collect(),
+    )
+    .await
+}
+
+/// Route the client traffic to the servers through the routers, or remove the
+/// routes if `enable` is false.
+///
+/// The clients are spread across the routers.
+pub async fn configure_client_routes(
+    ssm_client: &aws_sdk_ssm::Client,
+    infra: &InfraDetail,
+    enable: bool,
+) -> OrchResult<()> {
+    let mut cmds = Vec::new();
+    for (client, router) in infra.clients.iter().zip(infra.routers.iter().cycle()) {
+        let (step, routes) = route_cmds(infra, &router.private_ip, enable);
+        let comment = step.as_str().to_string();
+        cmds.push(
+            send_command(
+                vec![],
+                step,
+                "client",
+                &comment,
+                ssm_client,
+                vec![client.instance_id.clone()],
+                routes,
+            )
+            .await?,
+        );
+    }
+    wait_complete("client", ssm_client, cmds, &MultiProgress::new()).await
+}
+
+fn route_cmds(infra: &InfraDetail, router_ip: &str, enable: bool) -> (Step, Vec<String>) {
+    let step = if enable {
+        Step::ConfigureRoutes
+    } else {
+        Step::RemoveRoutes
+    };
+    let routes = infra
+        .servers
+        .iter()
+        .map(|server| match enable {
+            true => format!("ip route replace {}/32 via {}", server.ip, router_ip),
+            false => format!("ip route del {}/32 || true", server.ip),
+        })
+        .collect();
+    (step, routes)
+}
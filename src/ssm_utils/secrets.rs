@@ -0,0 +1,370 @@
+// Copyright Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+use super::{command_id, send_command, wait_for_ssm_results, Step};
+use crate::{
+    config::Secret,
+    error::{OrchError, OrchResult},
+    s3_utils::delete_object,
+    state::STATE,
+};
+use aws_sdk_kms::{
+    primitives::Blob,
+    types::{GrantOperation, Tag},
+};
+use aws_sdk_s3::primitives::ByteStream;
+use aws_types::region::Region;
+use tracing::{info, warn};
+
+// The tmpfs folder holding the secrets on the hosts, so they are never
+// written to disk
+pub const HOST_SECRETS_PATH: &str = "/run/netbench/secrets";
+// KMS encrypts at most 4KiB
+const MAX_SECRET_LEN: usize = 4096;
+
+/// The secrets of a run, encrypted with a KMS key created for the run and
+/// uploaded to the private bucket as `<unique_id>/secrets/<name>.kms`.
+///
+/// The instance role of the hosts is granted the use of the key to decrypt
+/// the secrets. The secrets are shredded from the hosts, and the key scheduled
+/// for deletion, once the run is cleaned up.
+#[derive(Debug)]
+pub struct RunSecrets {
+    kms_client: aws_sdk_kms::Client,
+    unique_id: String,
+    names: Vec<String>,
+    kms_key_id: Option<String>,
+    // The hosts the secrets were delivered to
+    instance_ids: Vec<String>,
+}
+
+impl RunSecrets {
+    /// Read the secrets, encrypt them with a new KMS key and upload them.
+    ///
+    /// The key is created in the region of `aws_config`, the region of the
+    /// hosts.
+    pub async fn prepare(
+        aws_config: &aws_types::SdkConfig,
+        s3_client: &aws_sdk_s3::Client,
+        iam_client: &aws_sdk_iam::Client,
+        unique_id: &str,
+        secrets: &[Secret],
+    ) -> OrchResult<Self> {
+        let mut run_secrets = RunSecrets {
+            kms_client: aws_sdk_kms::Client::new(aws_config),
+            unique_id: unique_id.to_string(),
+            names: Vec::new(),
+            kms_key_id: None,
+            instance_ids: Vec::new(),
+        };
+        if secrets.is_empty() {
+            return Ok(run_secrets);
+        }
+
+        // fail before creating the key if a secret can't be read
+        let mut values = Vec::new();
+        for secret in secrets {
+            let value = read_secret(aws_config, secret).await?;
+            if value.len() > MAX_SECRET_LEN {
+                return Err(OrchError::Init {
+                    dbg: format!(
+                        "secret {} is {} bytes, more than the {} bytes KMS encrypts",
+                        secret.name,
+                        value.len(),
+                        MAX_SECRET_LEN
+                    ),
+                });
+            }
+            values.push((secret.name.clone(), value));
+        }
+
+        let role_arn = instance_role_arn(iam_client).await?;
+        let key = run_secrets
+            .kms_client
+            .create_key()
+            .description(format!("netbench secrets of {}", unique_id))
+            .tags(
+                Tag::builder()
+                    .tag_key("netbench_run")
+                    .tag_value(unique_id)
+                    .build(),
+            )
+            .send()
+            .await
+            .map_err(|err| OrchError::Init {
+                dbg: format!("Failed to create the key of the secrets: {}", err),
+            })?;
+        let kms_key_id = key
+            .key_metadata()
+            .and_then(|metadata| metadata.key_id())
+            .map(String::from)
+            .ok_or(OrchError::Init {
+                dbg: "Missing id of the key of the secrets".to_string(),
+            })?;
+        run_secrets.kms_key_id = Some(kms_key_id.clone());
+        // don't leave the key behind if a secret can't be encrypted
+        if let Err(err) = run_secrets
+            .encrypt(s3_client, &role_arn, &kms_key_id, values)
+            .await
+        {
+            run_secrets.discard(s3_client).await;
+            return Err(err);
+        }
+        info!("Encrypted {} secrets", run_secrets.names.len());
+        Ok(run_secrets)
+    }
+
+    // Grant the hosts the use of the key, then encrypt and upload the secrets.
+    async fn encrypt(
+        &mut self,
+        s3_client: &aws_sdk_s3::Client,
+        role_arn: &str,
+        kms_key_id: &str,
+        values: Vec<(String, Vec<u8>)>,
+    ) -> OrchResult<()> {
+        self.kms_client
+            .create_grant()
+            .key_id(kms_key_id)
+            .grantee_principal(role_arn)
+            .operations(GrantOperation::Decrypt)
+            .send()
+            .await
+            .map_err(|err| OrchError::Init {
+                dbg: format!("Failed to grant the hosts the use of the key: {}", err),
+            })?;
+
+        for (name, value) in values {
+            let encrypted = self
+                .kms_client
+                .encrypt()
+                .key_id(kms_key_id)
+                .plaintext(Blob::new(value))
+                .send()
+                .await
+                .map_err(|err| OrchError::Init {
+                    dbg: format!("Failed to encrypt secret {}: {}", name, err),
+                })?;
+            let ciphertext = encrypted
+                .ciphertext_blob()
+                .map(|blob| blob.as_ref().to_vec())
+                .ok_or(OrchError::Init {
+                    dbg: format!("Missing ciphertext of secret {}", name),
+                })?;
+            let key = secret_key(&self.unique_id, &name);
+            s3_client
+                .put_object()
+                .bucket(STATE.s3_private_log_bucket)
+                .key(&key)
+                .body(ByteStream::from(ciphertext))
+                .send()
+                .await
+                .map_err(|err| OrchError::S3 {
+                    dbg: format!("Failed to upload {}: {}", key, err),
+                })?;
+            self.names.push(name);
+        }
+        Ok(())
+    }
+
+    /// Decrypt the secrets to [`HOST_SECRETS_PATH`] on the hosts.
+    pub async fn deliver(
+        &mut self,
+        ssm_client: &aws_sdk_ssm::Client,
+        instance_ids: Vec<String>,
+    ) -> OrchResult<()> {
+        if self.names.is_empty() {
+            return Ok(());
+        }
+        self.instance_ids.clone_from(&instance_ids);
+        let cmd = send_command(
+            vec![],
+            Step::DeliverSecrets,
+            "all",
+            "deliver_secrets",
+            ssm_client,
+            instance_ids,
+            deliver_cmds(&self.unique_id, &self.names),
+        )
+        .await?;
+        wait_for_ssm_results("all", ssm_client, command_id(&cmd)?).await?;
+        info!("Delivered {} secrets to the hosts", self.names.len());
+        Ok(())
+    }
+
+    /// Shred the secrets from the hosts, delete the encrypted secrets and
+    /// schedule the deletion of the key.
+    ///
+    /// The encrypted secrets and the key are discarded even if the secrets
+    /// couldn't be shredded from the hosts, in which case the error is returned.
+    pub async fn cleanup(
+        &self,
+        ssm_client: &aws_sdk_ssm::Client,
+        s3_client: &aws_sdk_s3::Client,
+    ) -> OrchResult<()> {
+        let shredded = async {
+            if self.instance_ids.is_empty() {
+                return Ok(());
+            }
+            let cmd = send_command(
+                vec![],
+                Step::ShredSecrets,
+                "all",
+                "shred_secrets",
+                ssm_client,
+                self.instance_ids.clone(),
+                shred_cmds(),
+            )
+            .await?;
+            wait_for_ssm_results("all", ssm_client, command_id(&cmd)?).await?;
+            info!("Shredded the secrets from the hosts");
+            Ok(())
+        }
+        .await;
+        self.discard(s3_client).await;
+        shredded
+    }
+
+    // Delete the encrypted secrets and schedule the deletion of the key.
+    async fn discard(&self, s3_client: &aws_sdk_s3::Client) {
+        for name in self.names.iter() {
+            let key = secret_key(&self.unique_id, name);
+            if let Err(err) = delete_object(s3_client, STATE.s3_private_log_bucket, &key).await {
+                warn!("{}", err);
+            }
+        }
+        if let Some(kms_key_id) = &self.kms_key_id {
+            let scheduled = self
+                .kms_client
+                .schedule_key_deletion()
+                .key_id(kms_key_id)
+                // the shortest window KMS allows
+                .pending_window_in_days(7)
+                .send()
+                .await;
+            if let Err(err) = scheduled {
+                warn!(
+                    "Failed to schedule the deletion of key {}: {}",
+                    kms_key_id, err
+                );
+            }
+        }
+    }
+}
+
+fn secret_key(unique_id: &str, name: &str) -> String {
+    format!("{}/secrets/{}.kms", unique_id, name)
+}
+
+async fn read_secret(aws_config: &aws_types::SdkConfig, secret: &Secret) -> OrchResult<Vec<u8>> {
+    if let Some(path) = &secret.file {
+        return std::fs::read(path).map_err(|err| OrchError::Init {
+            dbg: format!("Failed to read secret {} {:?}: {}", secret.name, path, err),
+        });
+    }
+    let secret_id = secret.secret_id.as_deref().unwrap_or_default();
+    // the secret of an arn is fetched from the arn's region, and otherwise
+    // from the region of the hosts
+    let mut config = aws_sdk_secretsmanager::config::Builder::from(aws_config);
+    if let Some(region) = arn_region(secret_id) {
+        config = config.region(Region::new(region.to_string()));
+    }
+    let value = aws_sdk_secretsmanager::Client::from_conf(config.build())
+        .get_secret_value()
+        .secret_id(secret_id)
+        .send()
+        .await
+        .map_err(|err| OrchError::Init {
+            dbg: format!("Failed to fetch secret {}: {}", secret.name, err),
+        })?;
+    match (value.secret_string(), value.secret_binary()) {
+        (Some(string), _) => Ok(string.as_bytes().to_vec()),
+        (None, Some(binary)) => Ok(binary.as_ref().to_vec()),
+        (None, None) => Err(OrchError::Init {
+            dbg: format!("Secret {} has no value", secret.name),
+        }),
+    }
+}
+
+/// The region of an arn. ex: arn:aws:secretsmanager:<region>:<account>:secret:<name>
+fn arn_region(arn: &str) -> Option<&str> {
+    arn.strip_prefix("arn:")?
+        .split(':')
+        .nth(2)
+        .filter(|region| !region.is_empty())
+}
+
+/// The role of the hosts' instance profile, which is granted the use of the key.
+async fn instance_role_arn(iam_client: &aws_sdk_iam::Client) -> OrchResult<String> {
+    let profile = iam_client
+        .get_instance_profile()
+        .instance_profile_name(STATE.instance_profile)
+        .send()
+        .await
+        .map_err(|err| OrchError::Iam {
+            dbg: err.to_string(),
+        })?;
+    profile
+        .instance_profile()
+        .and_then(|profile| profile.roles()?.first())
+        .and_then(|role| role.arn())
+        .map(String::from)
+        .ok_or(OrchError::Iam {
+            dbg: format!(
+                "Missing role for instance profile {}",
+                STATE.instance_profile
+            ),
+        })
+}
+
+fn deliver_cmds(unique_id: &str, names: &[String]) -> Vec<String> {
+    let mut cmds = vec![format!("mkdir -p -m 700 {HOST_SECRETS_PATH}")];
+    for name in names {
+        let secret = format!("{HOST_SECRETS_PATH}/{name}");
+        let ciphertext = format!("{HOST_SECRETS_PATH}/.{name}.kms");
+        // the plaintext only goes through a variable and the tmpfs
+        cmds.push(format!(
+            "(umask 077 && aws s3 cp --only-show-errors s3://{}/{} {ciphertext} \
+             && plaintext=$(aws kms decrypt --region {} --ciphertext-blob fileb://{ciphertext} --query Plaintext --output text) \
+             && echo \"$plaintext\" | base64 -d > {secret}; status=$?; rm -f {ciphertext}; exit $status) || exit 1",
+            STATE.s3_private_log_bucket,
+            secret_key(unique_id, name),
+            STATE.vpc_region,
+        ));
+    }
+    cmds
+}
+
+fn shred_cmds() -> Vec<String> {
+    vec![
+        format!("[ ! -d {HOST_SECRETS_PATH} ] || find {HOST_SECRETS_PATH} -type f -exec shred -u {{}} +"),
+        format!("rm -rf {HOST_SECRETS_PATH}"),
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn secret_cmds() {
+        let cmds = deliver_cmds("run-1", &["token".to_string()]);
+        assert_eq!(cmds[0], "mkdir -p -m 700 /run/netbench/secrets");
+        assert!(cmds[1].starts_with(&format!(
+            "(umask 077 && aws s3 cp --only-show-errors s3://{}/run-1/secrets/token.kms /run/netbench/secrets/.token.kms && ",
+            STATE.s3_private_log_bucket
+        )));
+        assert!(cmds[1].ends_with(
+            "base64 -d > /run/netbench/secrets/token; status=$?; rm -f /run/netbench/secrets/.token.kms; exit $status) || exit 1"
+        ));
+        assert_eq!(
+            shred_cmds()[0],
+            "[ ! -d /run/netbench/secrets ] || find /run/netbench/secrets -type f -exec shred -u {} +"
+        );
+
+        assert_eq!(
+            arn_region("arn:aws:secretsmanager:us-west-2:123456789012:secret:tls-key-AbCdEf"),
+            Some("us-west-2")
+        );
+        assert_eq!(arn_region("netbench/tls-key"), None);
+    }
+}
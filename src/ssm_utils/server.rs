@@ -1,8 +1,11 @@
 // Copyright Amazon.com, Inc. or its affiliates. All Rights Reserved.
 // SPDX-License-Identifier: Apache-2.0
 
-use super::{send_command, Step};
-use crate::{state::STATE, NetbenchDriver, Scenario};
+use super::{send_command, upload_sidecar_cmds, Step, WorkerOptions};
+use crate::{
+    ec2_utils::EndpointType, error::OrchResult, russula::netbench::driver_short_name, state::STATE,
+    NetbenchDriver,
+};
 use aws_sdk_ssm::operation::send_command::SendCommandOutput;
 use tracing::debug;
 
@@ -10,14 +13,11 @@ pub async fn upload_netbench_data(
     ssm_client: &aws_sdk_ssm::Client,
     instance_ids: Vec<String>,
     unique_id: &str,
-    scenario: &Scenario,
+    // the job's folder under results/. see run_spec::Job::result_key
+    result_key: &str,
     driver: &NetbenchDriver,
-) -> SendCommandOutput {
-    let driver_name = driver
-        .driver_name
-        .trim_start_matches("s2n-netbench-driver-")
-        .trim_start_matches("netbench-driver-")
-        .trim_end_matches(".json");
+) -> OrchResult<SendCommandOutput> {
+    let driver_name = driver_short_name(&driver.driver_name);
 
     send_command(
         vec![Step::RunRussula],
@@ -28,30 +28,32 @@ pub async fn upload_netbench_data(
         instance_ids,
         vec![
             "cd netbench_orchestrator",
+            // move rather than copy so the results are not uploaded again as
+            // part of the next scenario
+            // results are named per host: server-<instance-id>-<driver>.json
             format!(
-                "aws s3 cp server* {}/results/{}/{driver_name}/",
+                "for result in server-*.json; do aws s3 mv $result {}/results/{}/{driver_name}/; done",
                 STATE.s3_path(unique_id),
-                scenario.file_stem()
+                result_key
             )
             .as_str(),
         ]
         .into_iter()
         .map(String::from)
+        .chain(upload_sidecar_cmds("server", unique_id, result_key, driver_name))
         .collect(),
     )
     .await
-    .expect("Timed out")
 }
 
 pub async fn run_russula_worker(
     ssm_client: &aws_sdk_ssm::Client,
     instance_ids: Vec<String>,
-    driver: &NetbenchDriver,
-    scenario: &Scenario,
-) -> SendCommandOutput {
+    worker_opts: &WorkerOptions,
+) -> OrchResult<SendCommandOutput> {
     let netbench_cmd =
-        format!("env RUST_LOG=debug ./target/debug/russula_cli netbench-server-worker --russula-port {} --driver {} --scenario {} --netbench-port {} --testing",
-            STATE.russula_port, driver.driver_name, scenario.name, STATE.netbench_port);
+        format!("env RUST_LOG=debug ./target/debug/russula_cli --transport {} netbench-server-worker --instance-id $(cat /var/lib/cloud/data/instance-id) {} {} --testing",
+            worker_opts.transport, worker_opts.netbench_worker_args("server"), worker_opts.russula_addr_args(&EndpointType::Server));
     debug!("{}", netbench_cmd);
 
     send_command(
@@ -67,5 +69,4 @@ pub async fn run_russula_worker(
             .collect(),
     )
     .await
-    .expect("Timed out")
 }
@@ -0,0 +1,278 @@
+// Copyright Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+use super::{
+    command_id, command_output, output_value, send_command, wait_for_ssm_results, NetbenchDriver,
+    Step,
+};
+use crate::{
+    error::{OrchError, OrchResult},
+    state::STATE,
+    InfraDetail,
+};
+use base64::{engine::general_purpose, Engine};
+use tracing::{info, warn};
+
+// A single request/response connection, which is enough to exercise a driver
+const SMOKE_SCENARIO: &str = include_str!("../../scripts/request_response.json");
+const SMOKE_SCENARIO_FILE: &str = "smoke-request_response.json";
+// The clients stop their driver after this many seconds
+const CLIENT_SECS: u16 = 5;
+// Give the servers time to listen before the clients connect
+const CLIENT_DELAY_SECS: u16 = 3;
+// The servers run until the clients are done
+const SERVER_SECS: u16 = CLIENT_DELAY_SECS + CLIENT_SECS + 7;
+// The exit status of `timeout` once it stopped the driver
+const TIMED_OUT: i32 = 124;
+// The lines of a driver's stderr included in the diagnostics
+const STDERR_LINES: u16 = 20;
+
+/// The outcome of a driver's smoke run on a host.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SmokeResult {
+    pub instance_id: String,
+    pub host_group: String,
+    pub driver: String,
+    // The exit status of the collector, or -1 if the command didn't report it
+    pub exit: i32,
+    // The size of the netbench output
+    pub bytes: u64,
+    pub stderr: Vec<String>,
+}
+
+impl SmokeResult {
+    fn parse(instance_id: &str, host_group: &str, driver: &str, output: &str) -> Self {
+        SmokeResult {
+            instance_id: instance_id.to_string(),
+            host_group: host_group.to_string(),
+            driver: driver.to_string(),
+            exit: output_value(output, "smoke_exit")
+                .and_then(|exit| exit.parse().ok())
+                .unwrap_or(-1),
+            bytes: output_value(output, "smoke_bytes")
+                .and_then(|bytes| bytes.parse().ok())
+                .unwrap_or_default(),
+            stderr: output
+                .lines()
+                .filter_map(|line| line.strip_prefix("stderr: "))
+                .map(String::from)
+                .collect(),
+        }
+    }
+
+    /// Why the driver is considered broken on the host, if it is. A driver
+    /// which is still running when the smoke run ends is fine.
+    fn problem(&self) -> Option<String> {
+        match self.exit {
+            0 | TIMED_OUT => (),
+            -1 => return Some("didn't report its exit status".to_string()),
+            // the shell reports a process killed by a signal as 128 + signal
+            exit if exit > 128 => {
+                return Some(format!(
+                    "was killed by signal {} (exit {})",
+                    exit - 128,
+                    exit
+                ))
+            }
+            exit => return Some(format!("exited with {}", exit)),
+        }
+        if self.bytes == 0 {
+            return Some("wrote no results".to_string());
+        }
+        None
+    }
+}
+
+/// Run each pair of (server, client) drivers for a few seconds before the
+/// measured runs, and fail the run if a driver is broken on any host.
+///
+/// Each server host runs the server driver and each client host connects to
+/// a server round robin, so that every host runs its driver. A driver is
+/// broken if it crashes or writes no results, ex: a client which segfaults.
+pub async fn smoke_test(
+    ssm_client: &aws_sdk_ssm::Client,
+    infra: &InfraDetail,
+    drivers: &[(&NetbenchDriver, &NetbenchDriver)],
+) -> OrchResult<()> {
+    if infra.clients.is_empty() || infra.servers.is_empty() {
+        warn!("Skipping the smoke test, which needs Linux clients and servers");
+        return Ok(());
+    }
+    let mut broken = Vec::new();
+    for (server_driver, client_driver) in drivers {
+        let results = smoke_run(ssm_client, infra, server_driver, client_driver).await?;
+        broken.extend(results.into_iter().filter_map(|result| {
+            let problem = result.problem()?;
+            Some((result, problem))
+        }));
+    }
+    if !broken.is_empty() {
+        return Err(OrchError::Init {
+            dbg: diagnostics(&broken),
+        });
+    }
+    info!("Smoke test of {} drivers: Successful", drivers.len() * 2);
+    Ok(())
+}
+
+async fn smoke_run(
+    ssm_client: &aws_sdk_ssm::Client,
+    infra: &InfraDetail,
+    server_driver: &NetbenchDriver,
+    client_driver: &NetbenchDriver,
+) -> OrchResult<Vec<SmokeResult>> {
+    let servers = send_command(
+        vec![],
+        Step::Smoke,
+        "server",
+        "smoke_server",
+        ssm_client,
+        infra.server_ids(),
+        smoke_cmds(
+            "server",
+            &format!("PORT={}", STATE.netbench_port),
+            &server_driver.driver_name,
+            SERVER_SECS,
+        ),
+    )
+    .await?;
+
+    // each client targets a server round robin
+    let mut clients = Vec::new();
+    for (i, client) in infra.clients.iter().enumerate() {
+        let server = &infra.servers[i % infra.servers.len()];
+        let mut cmds = vec![format!("sleep {CLIENT_DELAY_SECS}")];
+        cmds.extend(smoke_cmds(
+            "client",
+            &format!("SERVER_0={}:{}", server.private_ip, STATE.netbench_port),
+            &client_driver.driver_name,
+            CLIENT_SECS,
+        ));
+        let cmd = send_command(
+            vec![],
+            Step::Smoke,
+            "client",
+            "smoke_client",
+            ssm_client,
+            vec![client.instance_id.clone()],
+            cmds,
+        )
+        .await?;
+        clients.push((client, cmd));
+    }
+
+    let mut results = Vec::new();
+    let server_command_id = command_id(&servers)?;
+    wait_for_ssm_results("server", ssm_client, server_command_id).await?;
+    for server in infra.servers.iter() {
+        let output =
+            command_output(ssm_client, "smoke", server_command_id, &server.instance_id).await?;
+        results.push(SmokeResult::parse(
+            &server.instance_id,
+            &server.group(),
+            &server_driver.driver_name,
+            &output,
+        ));
+    }
+    for (client, cmd) in clients {
+        let client_command_id = command_id(&cmd)?;
+        wait_for_ssm_results("client", ssm_client, client_command_id).await?;
+        let output =
+            command_output(ssm_client, "smoke", client_command_id, &client.instance_id).await?;
+        results.push(SmokeResult::parse(
+            &client.instance_id,
+            &client.group(),
+            &client_driver.driver_name,
+            &output,
+        ));
+    }
+    Ok(results)
+}
+
+/// Run the driver with the collector for at most `secs` seconds and print its
+/// exit status, the size of its output and the tail of its stderr.
+///
+/// The commands don't fail so that the outcome is reported for every host.
+fn smoke_cmds(endpoint: &str, env: &str, driver: &str, secs: u16) -> Vec<String> {
+    let output = format!("smoke-{endpoint}.json");
+    let stderr = format!("smoke-{endpoint}.stderr");
+    vec![
+        format!("cd {}", STATE.host_bin_path()),
+        format!(
+            "echo {} | base64 -d > {SMOKE_SCENARIO_FILE}",
+            general_purpose::STANDARD.encode(SMOKE_SCENARIO)
+        ),
+        format!(
+            "env {env} timeout {secs} ./s2n-netbench-collector ./{driver} --scenario ./{SMOKE_SCENARIO_FILE} > {output} 2> {stderr}; echo smoke_exit=$?"
+        ),
+        format!("echo smoke_bytes=$(stat -c %s {output})"),
+        format!("tail -n {STDERR_LINES} {stderr} | sed 's/^/stderr: /'"),
+        format!("rm -f {output} {stderr} {SMOKE_SCENARIO_FILE}"),
+    ]
+}
+
+fn diagnostics(broken: &[(SmokeResult, String)]) -> String {
+    let mut dbg = format!(
+        "The smoke test found broken drivers on {} hosts:",
+        broken.len()
+    );
+    for (result, problem) in broken {
+        dbg.push_str(&format!(
+            "\n  {} {}: {} {}",
+            result.host_group, result.instance_id, result.driver, problem
+        ));
+        for line in result.stderr.iter() {
+            dbg.push_str(&format!("\n    {line}"));
+        }
+    }
+    dbg
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn smoke_results() {
+        let driver = "s2n-netbench-driver-client-s2n-quic";
+        let ok = SmokeResult::parse(
+            "i-1",
+            "client",
+            driver,
+            "smoke_exit=124\nsmoke_bytes=2048\n",
+        );
+        assert_eq!(ok.problem(), None);
+        assert!(ok.stderr.is_empty());
+
+        let segfault = SmokeResult::parse(
+            "i-2",
+            "client",
+            driver,
+            "smoke_exit=139\nsmoke_bytes=0\nstderr: starting\nstderr: Segmentation fault\n",
+        );
+        assert_eq!(
+            segfault.problem().unwrap(),
+            "was killed by signal 11 (exit 139)"
+        );
+        let empty = SmokeResult::parse("i-3", "server", driver, "smoke_exit=0\nsmoke_bytes=0\n");
+        assert_eq!(empty.problem().unwrap(), "wrote no results");
+        let failed = SmokeResult::parse("i-4", "server", driver, "smoke_exit=1\n");
+        assert_eq!(failed.problem().unwrap(), "exited with 1");
+        let missing = SmokeResult::parse("i-5", "server", driver, "");
+        assert_eq!(missing.problem().unwrap(), "didn't report its exit status");
+
+        let broken = vec![(segfault.clone(), segfault.problem().unwrap())];
+        assert_eq!(
+            diagnostics(&broken),
+            "The smoke test found broken drivers on 1 hosts:\n  \
+            client i-2: s2n-netbench-driver-client-s2n-quic was killed by signal 11 (exit 139)\n    \
+            starting\n    \
+            Segmentation fault"
+        );
+
+        let cmds = smoke_cmds("client", "SERVER_0=10.0.0.1:4433", driver, CLIENT_SECS);
+        assert!(cmds.contains(&format!(
+            "env SERVER_0=10.0.0.1:4433 timeout 5 ./s2n-netbench-collector ./{driver} --scenario ./{SMOKE_SCENARIO_FILE} > smoke-client.json 2> smoke-client.stderr; echo smoke_exit=$?"
+        )));
+    }
+}
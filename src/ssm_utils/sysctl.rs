@@ -0,0 +1,172 @@
+// Copyright Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+use super::{command_id, command_output, output_value, send_command, wait_for_ssm_results, Step};
+use crate::{
+    error::{OrchError, OrchResult},
+    state::STATE,
+    InfraDetail,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use tracing::info;
+
+/// The kernel tunables of a host once the run's `sysctl` settings are applied,
+/// as read back from the host.
+///
+/// Recorded in the run's manifest since the default buffer sizes differ
+/// between images and can dominate the results of a benchmark.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct HostSysctl {
+    pub instance_id: String,
+    pub host_group: String,
+    // Tunable -> value read back after applying it
+    pub values: BTreeMap<String, String>,
+    // Tunable -> value before the run, which is restored after the run
+    pub defaults: BTreeMap<String, String>,
+}
+
+// The values of the tunables before the first run which changed them, in the
+// format read by `sysctl -p`. Kept until they're restored so that a host of an
+// infra pool left tuned by an interrupted run is still restored to its defaults
+fn defaults_path() -> String {
+    format!("{}/sysctl_defaults.conf", STATE.host_home_path)
+}
+
+fn sysctl_cmds(sysctl: &BTreeMap<String, String>) -> Vec<String> {
+    let defaults = defaults_path();
+    let mut cmds = Vec::new();
+    for (key, value) in sysctl {
+        cmds.extend([
+            format!(
+                "grep -q '^{key} = ' {defaults} 2>/dev/null || echo \"{key} = $(sysctl -n {key})\" >> {defaults}"
+            ),
+            format!("echo default.{key}=$(sed -n 's/^{key} = //p' {defaults} | xargs)"),
+            // an unknown tunable or an invalid value fails the run
+            format!("sysctl -w {key}='{value}' || exit 1"),
+            // multi-valued tunables are tab separated. ex: net.ipv4.tcp_rmem
+            format!("echo value.{key}=$(sysctl -n {key} | xargs)"),
+        ]);
+    }
+    cmds
+}
+
+/// Apply the kernel tunables to the hosts, ex: the socket buffer sizes or the
+/// tcp congestion control, and read back their values.
+pub async fn apply_sysctl(
+    ssm_client: &aws_sdk_ssm::Client,
+    infra: &InfraDetail,
+    sysctl: &BTreeMap<String, String>,
+) -> OrchResult<Vec<HostSysctl>> {
+    let cmd = send_command(
+        vec![],
+        Step::Sysctl,
+        "all",
+        "sysctl",
+        ssm_client,
+        infra.instance_ids(),
+        sysctl_cmds(sysctl),
+    )
+    .await?;
+    let command_id = command_id(&cmd)?;
+    wait_for_ssm_results("all", ssm_client, command_id).await?;
+
+    let mut hosts = Vec::new();
+    for host in infra.hosts() {
+        let stdout = command_output(ssm_client, "sysctl", command_id, &host.instance_id).await?;
+        let host_sysctl = parse_sysctl(&host.instance_id, &host.group(), sysctl, &stdout).ok_or(
+            OrchError::Ec2Instance {
+                instance_id: host.instance_id.clone(),
+                dbg: format!("Failed to parse the kernel tunables: {}", stdout),
+            },
+        )?;
+        info!(
+            instance_id = %host.instance_id,
+            values = ?host_sysctl.values,
+            "applied sysctl"
+        );
+        hosts.push(host_sysctl);
+    }
+    Ok(hosts)
+}
+
+/// Restore the values the tunables had before the run.
+pub async fn revert_sysctl(
+    ssm_client: &aws_sdk_ssm::Client,
+    infra: &InfraDetail,
+) -> OrchResult<()> {
+    let defaults = defaults_path();
+    let cmd = send_command(
+        vec![],
+        Step::RevertSysctl,
+        "all",
+        "revert_sysctl",
+        ssm_client,
+        infra.instance_ids(),
+        vec![format!(
+            "[ ! -f {defaults} ] || {{ sysctl -q -p {defaults} && rm {defaults}; }}"
+        )],
+    )
+    .await?;
+    wait_for_ssm_results("all", ssm_client, command_id(&cmd)?).await?;
+    info!("Reverted the kernel tunables");
+    Ok(())
+}
+
+fn parse_sysctl(
+    instance_id: &str,
+    host_group: &str,
+    sysctl: &BTreeMap<String, String>,
+    output: &str,
+) -> Option<HostSysctl> {
+    let mut values = BTreeMap::new();
+    let mut defaults = BTreeMap::new();
+    for key in sysctl.keys() {
+        let value = output_value(output, &format!("value.{key}"))?;
+        values.insert(key.clone(), value.to_string());
+        let default = output_value(output, &format!("default.{key}"))?;
+        defaults.insert(key.clone(), default.to_string());
+    }
+    Some(HostSysctl {
+        instance_id: instance_id.to_string(),
+        host_group: host_group.to_string(),
+        values,
+        defaults,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn host_sysctl() {
+        let sysctl = BTreeMap::from([
+            ("net.core.rmem_max".to_string(), "26214400".to_string()),
+            (
+                "net.ipv4.tcp_rmem".to_string(),
+                "4096 131072 26214400".to_string(),
+            ),
+        ]);
+        let cmds = sysctl_cmds(&sysctl);
+        assert_eq!(cmds.len(), 8);
+        assert!(cmds[0].starts_with("grep -q '^net.core.rmem_max = ' "));
+        assert!(cmds[0].ends_with(
+            "|| echo \"net.core.rmem_max = $(sysctl -n net.core.rmem_max)\" >> /home/ec2-user/sysctl_defaults.conf"
+        ));
+        assert_eq!(
+            cmds[6],
+            "sysctl -w net.ipv4.tcp_rmem='4096 131072 26214400' || exit 1"
+        );
+
+        let output = "default.net.core.rmem_max=212992\nnet.core.rmem_max = 26214400\nvalue.net.core.rmem_max=26214400\ndefault.net.ipv4.tcp_rmem=4096 131072 6291456\nnet.ipv4.tcp_rmem = 4096 131072 26214400\nvalue.net.ipv4.tcp_rmem=4096 131072 26214400\n";
+        let host = parse_sysctl("i-1", "server", &sysctl, output).unwrap();
+        assert_eq!(host.values, sysctl);
+        assert_eq!(host.defaults["net.core.rmem_max"], "212992");
+        assert_eq!(host.defaults["net.ipv4.tcp_rmem"], "4096 131072 6291456");
+        assert_eq!(
+            parse_sysctl("i-1", "server", &sysctl, "value.net.core.rmem_max=1\n"),
+            None
+        );
+    }
+}
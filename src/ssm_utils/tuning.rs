@@ -0,0 +1,139 @@
+// Copyright Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+use super::{command_id, command_output, output_value, send_command, wait_for_ssm_results, Step};
+use crate::{
+    config::Tuning,
+    error::{OrchError, OrchResult},
+    InfraDetail,
+};
+use serde::{Deserialize, Serialize};
+use tracing::info;
+
+/// The tuning profile applied to a host, as reported by the host.
+///
+/// Recorded in the run's manifest and listed on the report's Hosts page.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct HostTuning {
+    pub instance_id: String,
+    pub host_group: String,
+    // The cpus handling the NIC's interrupts
+    pub irq_cpus: String,
+    // The cpus left online, once the hyperthread siblings are disabled
+    pub online_cpus: String,
+    pub governor: String,
+    pub irqbalance: String,
+}
+
+fn tuning_cmds(tuning: &Tuning) -> Vec<String> {
+    let irq_cpus = format!("0-{}", tuning.irq_cpus - 1);
+    let mut cmds = vec![
+        "systemctl disable --now irqbalance || true".to_string(),
+        "iface=$(ip route show default | awk '{print $5; exit}')".to_string(),
+        format!("for irq in $(grep \"$iface\" /proc/interrupts | cut -d: -f1); do echo {irq_cpus} > /proc/irq/$irq/smp_affinity_list || true; done"),
+        // the cpufreq driver isn't exposed on every instance type
+        format!("for governor in /sys/devices/system/cpu/cpu*/cpufreq/scaling_governor; do [ -e $governor ] && echo {} > $governor || true; done", tuning.governor),
+    ];
+    if tuning.disable_smt_siblings {
+        // keep a single thread of each core used by the drivers
+        cmds.push(format!("for cpu in /sys/devices/system/cpu/cpu[0-9]*; do n=${{cpu##*cpu}}; [ $n -lt {} ] && continue; first=$(cut -d, -f1 $cpu/topology/thread_siblings_list | cut -d- -f1); [ \"$first\" != \"$n\" ] && echo 0 > $cpu/online || true; done", tuning.irq_cpus));
+    }
+    cmds.extend([
+        format!("echo irq_cpus={irq_cpus}"),
+        "echo online_cpus=$(cat /sys/devices/system/cpu/online)".to_string(),
+        "echo governor=$(cat /sys/devices/system/cpu/cpu0/cpufreq/scaling_governor 2>/dev/null || echo unsupported)".to_string(),
+        "echo irqbalance=$(systemctl is-active irqbalance)".to_string(),
+    ]);
+    cmds
+}
+
+/// Apply the tuning profile to the hosts to reduce run to run variance:
+/// irqbalance is disabled, the NIC's interrupts are pinned to the first
+/// `irq_cpus`, the cpu governor is set and the hyperthread siblings of the
+/// remaining cpus are disabled.
+pub async fn apply_tuning(
+    ssm_client: &aws_sdk_ssm::Client,
+    infra: &InfraDetail,
+    tuning: &Tuning,
+) -> OrchResult<Vec<HostTuning>> {
+    let cmd = send_command(
+        vec![],
+        Step::Tuning,
+        "all",
+        "tuning",
+        ssm_client,
+        infra.instance_ids(),
+        tuning_cmds(tuning),
+    )
+    .await?;
+    let command_id = command_id(&cmd)?;
+    wait_for_ssm_results("all", ssm_client, command_id).await?;
+
+    let mut tunings = Vec::new();
+    for host in infra.hosts() {
+        let stdout = command_output(ssm_client, "tuning", command_id, &host.instance_id).await?;
+        let host_tuning = parse_tuning(&host.instance_id, &host.group(), &stdout).ok_or(
+            OrchError::Ec2Instance {
+                instance_id: host.instance_id.clone(),
+                dbg: format!("Failed to parse the tuning profile: {}", stdout),
+            },
+        )?;
+        info!(
+            instance_id = %host.instance_id,
+            online_cpus = %host_tuning.online_cpus,
+            governor = %host_tuning.governor,
+            "tuned"
+        );
+        tunings.push(host_tuning);
+    }
+    Ok(tunings)
+}
+
+fn parse_tuning(instance_id: &str, host_group: &str, output: &str) -> Option<HostTuning> {
+    let value = |key: &str| output_value(output, key).map(String::from);
+    Some(HostTuning {
+        instance_id: instance_id.to_string(),
+        host_group: host_group.to_string(),
+        irq_cpus: value("irq_cpus")?,
+        online_cpus: value("online_cpus")?,
+        governor: value("governor")?,
+        irqbalance: value("irqbalance")?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn host_tuning() {
+        let tuning = Tuning {
+            irq_cpus: 2,
+            ..Default::default()
+        };
+        let cmds = tuning_cmds(&tuning);
+        assert!(cmds[2].contains("echo 0-1 > /proc/irq/$irq/smp_affinity_list"));
+        assert!(cmds[3].contains("echo performance > $governor"));
+        assert!(cmds[4].contains("[ $n -lt 2 ] && continue"));
+
+        let cmds = tuning_cmds(&Tuning {
+            disable_smt_siblings: false,
+            ..tuning
+        });
+        assert!(!cmds.iter().any(|cmd| cmd.contains("thread_siblings_list")));
+
+        let output = "Removed /etc/systemd/system/multi-user.target.wants/irqbalance.service.\nirq_cpus=0-1\nonline_cpus=0-9\ngovernor=unsupported\nirqbalance=inactive\n";
+        assert_eq!(
+            parse_tuning("i-1", "client", output),
+            Some(HostTuning {
+                instance_id: "i-1".to_string(),
+                host_group: "client".to_string(),
+                irq_cpus: "0-1".to_string(),
+                online_cpus: "0-9".to_string(),
+                governor: "unsupported".to_string(),
+                irqbalance: "inactive".to_string(),
+            })
+        );
+        assert_eq!(parse_tuning("i-1", "client", "irq_cpus=0-1\n"), None);
+    }
+}
@@ -0,0 +1,440 @@
+// Copyright Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+//! The steps run on Windows Server client hosts, as PowerShell scripts.
+//!
+//! Windows clients mirror the Linux steps which are needed to run the client
+//! drivers: the hosts are configured with the MSVC toolchain, the drivers and
+//! russula are built from source and the results are uploaded to the run's
+//! folder. The host checks and tuning which rely on Linux tools are skipped.
+
+use super::{
+    build_info::BUILD_INFO_DIR,
+    common::{wait_complete, HostSetup},
+    send_invocation, DriverSource, Invocation, NetbenchDriver, Step, WorkerOptions,
+};
+use crate::{
+    ec2_utils::{EndpointType, HostOs},
+    error::OrchResult,
+    russula::netbench::driver_short_name,
+    state::STATE,
+};
+use aws_sdk_ssm::operation::send_command::SendCommandOutput;
+use indicatif::MultiProgress;
+use std::collections::{BTreeMap, HashMap};
+use tracing::{debug, trace};
+
+// The equivalent of /home/ec2-user on Linux hosts
+const HOME: &str = r"C:\netbench";
+
+fn bin_path() -> String {
+    format!(r"{HOME}\bin")
+}
+
+/// Fail the script if the last native command failed, which PowerShell
+/// doesn't do on its own.
+fn checked(cmd: &str) -> String {
+    format!("{cmd}; if ($LASTEXITCODE -ne 0) {{ exit $LASTEXITCODE }}")
+}
+
+/// The PowerShell equivalent of [`super::assemble_command`]: the commands are
+/// wrapped with the markers used to order the steps on a host.
+///
+/// Each SSM command runs in a new PowerShell session so the toolchain is
+/// added to the session's path.
+fn assemble_command(wait_steps: Vec<Step>, step: &Step, commands: Vec<String>) -> Vec<String> {
+    let step_name = step.as_str();
+    let mut assemble_command = vec![
+        "$ProgressPreference = 'SilentlyContinue'".to_string(),
+        format!("$env:CARGO_HOME = '{HOME}\\.cargo'; $env:RUSTUP_HOME = '{HOME}\\.rustup'"),
+        format!(
+            "$env:Path = [Environment]::GetEnvironmentVariable('Path', 'Machine') + ';{HOME}\\.cargo\\bin;{}'",
+            bin_path()
+        ),
+        format!("New-Item -ItemType Directory -Force {} | Out-Null", bin_path()),
+    ];
+    for step in wait_steps {
+        assemble_command.push(format!(
+            "Set-Location {HOME}; while (-not (Test-Path fin_{}___)) {{ Start-Sleep 5 }}",
+            step.as_str()
+        ));
+    }
+    let touch = |marker: String| format!("New-Item -Force {marker} | Out-Null");
+    if let Some(marker) = step.completion_marker() {
+        let mut fin = touch(format!("fin_{step_name}___"));
+        if let Some(detail) = step.task_detail() {
+            fin.push_str(&format!(
+                "; {}",
+                touch(format!("fin_{step_name}_{detail}___"))
+            ));
+        }
+        assemble_command.push(format!(
+            "Set-Location {HOME}; if (Test-Path {marker}) {{ echo 'skip {step_name}: already completed'; {fin}; exit 0 }}"
+        ));
+    }
+    assemble_command.push(format!(
+        "Set-Location {HOME}; Remove-Item -Force -ErrorAction Ignore fin_{step_name}___; {}",
+        touch(format!("start_{step_name}___"))
+    ));
+    if let Some(detail) = step.task_detail() {
+        assemble_command.push(format!(
+            "Set-Location {HOME}; {}",
+            touch(format!("start_{step_name}_{detail}___"))
+        ));
+    }
+    // like `set -e` on Linux: an idempotent step stops at its first failed
+    // cmdlet, and at a failed native command with `checked`, so that it isn't
+    // marked completed and is retried on the host
+    if step.completion_marker().is_some() {
+        assemble_command.push("$ErrorActionPreference = 'Stop'".to_string());
+    }
+    assemble_command.extend(commands);
+
+    assemble_command.push(format!(
+        "Set-Location {HOME}; Move-Item -Force start_{step_name}___ fin_{step_name}___"
+    ));
+    if let Some(detail) = step.task_detail() {
+        assemble_command.push(format!(
+            "Set-Location {HOME}; Move-Item -Force start_{step_name}_{detail}___ fin_{step_name}_{detail}___"
+        ));
+    }
+    if let Some(marker) = step.completion_marker() {
+        assemble_command.push(format!("Set-Location {HOME}; {}", touch(marker)));
+    }
+    assemble_command
+}
+
+async fn send_command(
+    wait_steps: Vec<Step>,
+    step: Step,
+    comment: &str,
+    ssm_client: &aws_sdk_ssm::Client,
+    ids: Vec<String>,
+    commands: Vec<String>,
+) -> OrchResult<SendCommandOutput> {
+    let command = assemble_command(wait_steps, &step, commands);
+    trace!("client {:?}", command);
+
+    let invocation = Invocation {
+        document_name: HostOs::Windows.run_script_document(),
+        document_version: "$LATEST".to_string(),
+        parameters: HashMap::from([("commands".to_string(), command)]),
+    };
+    send_invocation(
+        step.as_str(),
+        "client",
+        comment,
+        ssm_client,
+        ids,
+        invocation,
+    )
+    .await
+}
+
+/// Reschedule the hosts' shutdown to `shutdown_min` from now.
+fn shutdown_cmd(shutdown_min: u16) -> String {
+    // abort the shutdown scheduled by the user data, if any. cmd discards the
+    // error when there is none, which would stop a step run with
+    // `$ErrorActionPreference = 'Stop'`
+    format!(
+        "cmd /c 'shutdown /a 2>nul'; {}",
+        checked(&format!("shutdown /s /t {}", shutdown_min as u32 * 60))
+    )
+}
+
+/// Install the dependencies of the drivers and russula: git, cmake, nasm (for
+/// aws-lc), the AWS CLI, the Visual Studio build tools and rust with the MSVC
+/// toolchain.
+fn configure_cmds(shutdown_min: u16) -> Vec<String> {
+    vec![
+        shutdown_cmd(shutdown_min),
+        // the drivers and russula listen for the coordinators and the servers.
+        // the security group restricts which hosts can reach them
+        "New-NetFirewallRule -DisplayName netbench -Direction Inbound -Action Allow -Protocol TCP | Out-Null".to_string(),
+        "New-NetFirewallRule -DisplayName netbench-udp -Direction Inbound -Action Allow -Protocol UDP | Out-Null".to_string(),
+        "[Net.ServicePointManager]::SecurityProtocol = [Net.SecurityProtocolType]::Tls12".to_string(),
+        "if (-not (Get-Command choco -ErrorAction Ignore)) { Set-ExecutionPolicy Bypass -Scope Process -Force; Invoke-Expression ((New-Object Net.WebClient).DownloadString('https://community.chocolatey.org/install.ps1')) }".to_string(),
+        "$env:Path += ';C:\\ProgramData\\chocolatey\\bin'".to_string(),
+        checked("choco install -y --no-progress git cmake nasm awscli"),
+        checked("choco install -y --no-progress visualstudio2022buildtools --package-parameters '--add Microsoft.VisualStudio.Workload.VCTools --includeRecommended --passive'"),
+        "$env:Path = [Environment]::GetEnvironmentVariable('Path', 'Machine') + ';' + $env:Path".to_string(),
+        format!("Invoke-WebRequest https://win.rustup.rs/x86_64 -OutFile {HOME}\\rustup-init.exe"),
+        checked(&format!(
+            "{HOME}\\rustup-init.exe -y --no-modify-path --profile minimal --default-host x86_64-pc-windows-msvc"
+        )),
+        checked("rustup update"),
+    ]
+}
+
+/// Build a client driver from its repository and copy the executables to the
+/// bin folder.
+///
+/// Each driver is cloned to its own folder since the builds run concurrently.
+fn build_driver_cmds(
+    driver: &NetbenchDriver,
+    source: &DriverSource,
+    unique_id: &str,
+) -> Vec<String> {
+    let proj_name = &driver.driver_name;
+    let mut cmds = vec![format!(
+        "Remove-Item -Recurse -Force -ErrorAction Ignore {proj_name}"
+    )];
+    cmds.extend(source.clone_cmds(proj_name).iter().map(|cmd| checked(cmd)));
+    cmds.extend([
+        format!("Set-Location {proj_name}"),
+        checked("cargo build --release"),
+        format!(
+            "Copy-Item -Force target\\release\\*.exe {}",
+            bin_path()
+        ),
+        // trace the results to the exact version of the driver
+        format!(
+            "@(\"git_remote=$(git remote get-url origin)\", \"git_sha=$(git rev-parse HEAD)\", \"cargo=$(cargo --version)\", \"rustc=$(rustc --version)\", 'build_cmd=cargo build --release') | Set-Content -Encoding ascii {HOME}\\build_info_{driver_name}",
+            driver_name = driver.driver_name
+        ),
+        checked(&format!(
+            "aws s3 cp {HOME}\\build_info_{driver_name} {s3_path}/{BUILD_INFO_DIR}/client/{driver_name}.txt",
+            driver_name = driver.driver_name,
+            s3_path = STATE.s3_path(unique_id),
+        )),
+    ]);
+    cmds
+}
+
+fn build_russula_cmds() -> Vec<String> {
+    vec![
+        checked(&format!(
+            "if (Test-Path netbench_orchestrator) {{ git -C netbench_orchestrator pull }} else {{ git clone --branch {} {} }}",
+            STATE.russula_branch, STATE.russula_repo
+        )),
+        "Set-Location netbench_orchestrator".to_string(),
+        // the orchestrator itself only runs on Linux and macOS
+        checked("cargo build --bin russula_cli"),
+    ]
+}
+
+/// Configure the Windows client hosts and build the client drivers and russula
+/// on them. The drivers are built from their repository since local sources
+/// are built with Linux commands.
+pub async fn configure_host_group(
+    ssm_client: &aws_sdk_ssm::Client,
+    instance_ids: Vec<String>,
+    netbench_drivers: &[&NetbenchDriver],
+    source: &DriverSource,
+    unique_id: &str,
+    setup: HostSetup<'_>,
+    multi_progress: &MultiProgress,
+) -> OrchResult<()> {
+    let mut cmds = Vec::new();
+    // sccache isn't installed on Windows hosts
+    if let HostSetup::Configure { shutdown_min, .. } = setup {
+        cmds.push(
+            send_command(
+                vec![],
+                Step::Configure,
+                "configure_host_client",
+                ssm_client,
+                instance_ids.clone(),
+                configure_cmds(shutdown_min),
+            )
+            .await?,
+        );
+    }
+    cmds.push(
+        send_command(
+            vec![Step::Configure],
+            Step::BuildRussula,
+            "build_russula_client",
+            ssm_client,
+            instance_ids.clone(),
+            build_russula_cmds(),
+        )
+        .await?,
+    );
+    for driver in netbench_drivers {
+        cmds.push(
+            send_command(
+                vec![Step::Configure],
+                Step::BuildDriver(driver.build_id(unique_id)),
+                &format!("build_driver_{}", driver.proj_name),
+                ssm_client,
+                instance_ids.clone(),
+                build_driver_cmds(driver, source, unique_id),
+            )
+            .await?,
+        );
+    }
+    wait_complete("client", ssm_client, cmds, multi_progress).await
+}
+
+/// Download the scenario files to the bin folder, where the client Worker
+/// looks for them, and verify their checksums.
+pub async fn distribute_scenarios(
+    ssm_client: &aws_sdk_ssm::Client,
+    instance_ids: Vec<String>,
+    unique_id: &str,
+    // scenario name -> sha256
+    scenario_checksums: &BTreeMap<String, String>,
+) -> OrchResult<()> {
+    let commands = scenario_checksums
+        .iter()
+        .flat_map(|(name, sha256)| {
+            let path = format!(r"{}\{}", bin_path(), name);
+            [
+                checked(&format!(
+                    "aws s3 cp {}/{} {}",
+                    STATE.s3_path(unique_id),
+                    name,
+                    path
+                )),
+                format!(
+                    "if ((Get-FileHash -Algorithm SHA256 {path}).Hash -ne '{sha256}') {{ echo 'checksum mismatch: {name}'; exit 1 }}"
+                ),
+            ]
+        })
+        .collect();
+    let step = Step::CopyScenarios;
+    let comment = step.as_str().to_string();
+    let cmd = send_command(vec![], step, &comment, ssm_client, instance_ids, commands).await?;
+    wait_complete("client", ssm_client, vec![cmd], &MultiProgress::new()).await
+}
+
+/// Reschedule the hosts' shutdown to `shutdown_min` from now.
+pub async fn extend_lease(
+    ssm_client: &aws_sdk_ssm::Client,
+    instance_ids: Vec<String>,
+    shutdown_min: u16,
+) -> OrchResult<()> {
+    let step = Step::ExtendLease;
+    let comment = step.as_str().to_string();
+    let cmd = send_command(
+        vec![],
+        step,
+        &comment,
+        ssm_client,
+        instance_ids,
+        vec![shutdown_cmd(shutdown_min)],
+    )
+    .await?;
+    wait_complete("client", ssm_client, vec![cmd], &MultiProgress::new()).await
+}
+
+/// Args for the `netbench-client-worker` russula_cli subcommand. The system
+/// metrics, profilers, pcaps and bpf probes rely on Linux tools so they are
+/// disabled.
+fn worker_args(worker_opts: &WorkerOptions) -> String {
+    let mut args = format!("--netbench-path {} --collector-disable-bpf", bin_path());
+    if let Some(interval) = worker_opts.collector.interval {
+        args.push_str(&format!(
+            " --collector-interval {}",
+            humantime::format_duration(interval)
+        ));
+    }
+    args
+}
+
+pub async fn run_russula_worker(
+    ssm_client: &aws_sdk_ssm::Client,
+    instance_ids: Vec<String>,
+    worker_opts: &WorkerOptions,
+) -> OrchResult<SendCommandOutput> {
+    let netbench_cmd = format!(
+        ".\\target\\debug\\russula_cli.exe --transport {} netbench-client-worker --instance-id $instance_id {} --russula-addr 0.0.0.0:{} --testing",
+        worker_opts.transport,
+        worker_args(worker_opts),
+        EndpointType::Client.russula_port()
+    );
+    debug!("{}", netbench_cmd);
+
+    send_command(
+        vec![Step::BuildDriver("".to_string()), Step::BuildRussula],
+        Step::RunRussula,
+        "run_client_russula",
+        ssm_client,
+        instance_ids,
+        vec![
+            "Set-Location netbench_orchestrator".to_string(),
+            "$token = Invoke-RestMethod -Method Put -Headers @{'X-aws-ec2-metadata-token-ttl-seconds' = '60'} http://169.254.169.254/latest/api/token".to_string(),
+            "$instance_id = Invoke-RestMethod -Headers @{'X-aws-ec2-metadata-token' = $token} http://169.254.169.254/latest/meta-data/instance-id".to_string(),
+            "$env:RUST_LOG = 'debug'".to_string(),
+            checked(&netbench_cmd),
+        ],
+    )
+    .await
+}
+
+pub async fn upload_netbench_data(
+    ssm_client: &aws_sdk_ssm::Client,
+    instance_ids: Vec<String>,
+    unique_id: &str,
+    // the job's folder under results/. see run_spec::Job::result_key
+    result_key: &str,
+    driver: &NetbenchDriver,
+) -> OrchResult<SendCommandOutput> {
+    let driver_name = driver_short_name(&driver.driver_name);
+    send_command(
+        vec![Step::RunRussula],
+        Step::UploadNetbenchRawData,
+        "upload_netbench_raw_data",
+        ssm_client,
+        instance_ids,
+        vec![
+            "Set-Location netbench_orchestrator".to_string(),
+            // move rather than copy so the results are not uploaded again as
+            // part of the next scenario
+            format!(
+                "Get-ChildItem client-*.json | ForEach-Object {{ aws s3 mv $_.Name {}/results/{}/{driver_name}/ }}",
+                STATE.s3_path(unique_id),
+                result_key
+            ),
+        ],
+    )
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn windows_commands() {
+        let cmds = assemble_command(
+            vec![Step::Configure],
+            &Step::BuildDriver("tcp_0123".to_string()),
+            vec!["cargo build --release".to_string()],
+        );
+        assert!(cmds[2].ends_with(r";C:\netbench\.cargo\bin;C:\netbench\bin'"));
+        assert_eq!(
+            cmds[4],
+            r"Set-Location C:\netbench; while (-not (Test-Path fin_configure___)) { Start-Sleep 5 }"
+        );
+        assert_eq!(
+            cmds[5],
+            r"Set-Location C:\netbench; if (Test-Path done_build_driver_tcp_0123___) { echo 'skip build_driver: already completed'; New-Item -Force fin_build_driver___ | Out-Null; New-Item -Force fin_build_driver_tcp_0123___ | Out-Null; exit 0 }"
+        );
+        let stop = cmds
+            .iter()
+            .position(|cmd| cmd == "$ErrorActionPreference = 'Stop'")
+            .unwrap();
+        assert_eq!(cmds[stop + 1], "cargo build --release");
+        assert_eq!(
+            cmds.last().unwrap(),
+            r"Set-Location C:\netbench; New-Item -Force done_build_driver_tcp_0123___ | Out-Null"
+        );
+
+        // steps which aren't idempotent keep going after an error
+        let cmds = assemble_command(vec![], &Step::RunRussula, vec![]);
+        assert!(!cmds
+            .iter()
+            .any(|cmd| cmd.contains("$ErrorActionPreference")));
+
+        assert_eq!(
+            checked("cargo build"),
+            "cargo build; if ($LASTEXITCODE -ne 0) { exit $LASTEXITCODE }"
+        );
+        assert_eq!(
+            shutdown_cmd(2),
+            "cmd /c 'shutdown /a 2>nul'; shutdown /s /t 120; if ($LASTEXITCODE -ne 0) { exit $LASTEXITCODE }"
+        );
+    }
+}
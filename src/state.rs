@@ -1,9 +1,6 @@
 // Copyright Amazon.com, Inc. or its affiliates. All Rights Reserved.
 // SPDX-License-Identifier: Apache-2.0
 
-use crate::ec2_utils::EndpointType;
-use core::time::Duration;
-
 pub const STATE: State = State {
     version: "v2.1.3",
 
@@ -22,13 +19,12 @@ pub const STATE: State = State {
     host_home_path: "/home/ec2-user",
     workspace_dir: "./target/netbench",
     shutdown_min: 120, // 1 hour
-    poll_delay_ssm: Duration::from_secs(10),
 
     // russula
     russula_repo: "https://github.com/toidiu/netbench_orchestrator.git",
     russula_branch: "ak-main",
+    // base port. see EndpointType::russula_port
     russula_port: 9000,
-    poll_delay_russula: Duration::from_secs(5),
 
     // aws
     s3_private_log_bucket: "netbenchrunnerlogs-source",
@@ -67,13 +63,11 @@ pub struct State {
     pub host_home_path: &'static str,
     pub workspace_dir: &'static str,
     pub shutdown_min: u16,
-    pub poll_delay_ssm: Duration,
 
     // russula
     pub russula_repo: &'static str,
     pub russula_branch: &'static str,
     pub russula_port: u16,
-    pub poll_delay_russula: Duration,
 
     // aws
     pub s3_private_log_bucket: &'static str,
@@ -103,13 +97,18 @@ impl State {
         format!("{}/bin", self.host_home_path)
     }
 
+    // https://github.com/brendangregg/FlameGraph
+    pub fn host_flamegraph_path(&self) -> String {
+        format!("{}/FlameGraph", self.host_home_path)
+    }
+
     // Create a security group with the following name prefix. Use with `sg_name_with_id`
     // security_group_name_prefix: "netbench_runner",
     pub fn security_group_name(&self, unique_id: &str) -> String {
         format!("netbench_{}", unique_id)
     }
 
-    pub fn instance_name(&self, unique_id: &str, endpoint_type: EndpointType) -> String {
-        format!("{}_{}", endpoint_type.as_str().to_lowercase(), unique_id)
+    pub fn instance_name(&self, unique_id: &str, host_group: &str) -> String {
+        format!("{}_{}", host_group, unique_id)
     }
 }